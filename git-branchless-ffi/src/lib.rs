@@ -0,0 +1,400 @@
+//! C bindings for `git-branchless`, for tools written in languages like C,
+//! C++ or Swift which would otherwise parse the output of the
+//! `git branchless` commands.
+//!
+//! The header is generated with `cbindgen` (see `cbindgen.toml`). All
+//! functions are prefixed with `branchless_`. Objects returned by a
+//! `branchless_*_new` or `branchless_repo_open` function are owned by the
+//! caller, and must be freed with the corresponding `branchless_*_free`
+//! function. On failure, functions return `NULL`, and
+//! [`branchless_last_error`] describes what went wrong.
+//!
+//! The same queries are also available to other Rust crates, such as the
+//! Python bindings, as [`get_graph_commits`] and [`get_successor`].
+
+#![warn(missing_docs)]
+#![warn(
+    clippy::all,
+    clippy::as_conversions,
+    clippy::clone_on_ref_ptr,
+    clippy::dbg_macro
+)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_smartlog as smartlog;
+pub use git_branchless_smartlog::{GraphCommit, Successor};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run the given function, recording its error (or panic) for
+/// [`branchless_last_error`] and returning `default` if it fails. Panics must
+/// not unwind into the caller, since it isn't Rust code.
+fn ffi_try<T>(default: T, f: impl FnOnce() -> eyre::Result<T>) -> T {
+    let error = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => {
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+            return value;
+        }
+        Ok(Err(err)) => format!("{err:#}"),
+        Err(_) => "git-branchless panicked".to_string(),
+    };
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(to_c_string(error)));
+    default
+}
+
+fn to_c_string(s: impl Into<Vec<u8>>) -> CString {
+    let mut bytes = s.into();
+    bytes.retain(|b| *b != 0);
+    CString::new(bytes).expect("NUL bytes should have been removed")
+}
+
+fn into_raw_c_string(s: impl Into<Vec<u8>>) -> *mut c_char {
+    to_c_string(s).into_raw()
+}
+
+/// # Safety
+///
+/// `s` must be `NULL` or have been returned by [`into_raw_c_string`].
+unsafe fn free_raw_c_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// # Safety
+///
+/// `s` must be `NULL` or a valid NUL-terminated string which outlives the
+/// returned value.
+unsafe fn str_from_ptr<'a>(s: *const c_char, name: &str) -> eyre::Result<&'a str> {
+    if s.is_null() {
+        eyre::bail!("`{name}` was NULL");
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// Convert the given items into an array which can be passed to C, and its
+/// length.
+fn into_raw_array<T>(items: Vec<T>) -> (*mut T, usize) {
+    let len = items.len();
+    (Box::into_raw(items.into_boxed_slice()).cast(), len)
+}
+
+/// # Safety
+///
+/// `items` and `len` must have been returned by [`into_raw_array`].
+unsafe fn from_raw_array<T>(items: *mut T, len: usize) -> Vec<T> {
+    Box::from_raw(ptr::slice_from_raw_parts_mut(items, len)).into_vec()
+}
+
+fn make_effects() -> Effects {
+    Effects::new(Glyphs::text()).suppress()
+}
+
+/// Get the commits in the smartlog graph for the given revset, in topological
+/// order. If the revset couldn't be resolved, returns a description of the
+/// problem in the inner `Result`.
+pub fn get_graph_commits(
+    repo: &Repo,
+    revset: Revset,
+) -> eyre::Result<Result<Vec<GraphCommit>, String>> {
+    let effects = make_effects();
+    let graph_commits =
+        smartlog::get_graph_commits(&effects, repo, revset, &ResolveRevsetOptions::default())?;
+    Ok(graph_commits.map_err(|err| err.to_string()))
+}
+
+/// Get whether the given commit is obsolete, and what it was rewritten into.
+pub fn get_successor(repo: &Repo, oid: NonZeroOid) -> eyre::Result<Successor> {
+    let effects = make_effects();
+    let conn = repo.get_db_conn_read_only()?;
+    let event_log_db = EventLogDb::new_read_only(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    Ok(smartlog::get_successor(&event_replayer, event_cursor, oid))
+}
+
+/// A repository managed by `git-branchless`.
+pub struct BranchlessRepo {
+    repo: Repo,
+}
+
+/// A commit in a [`BranchlessGraph`]. All strings are NUL-terminated and
+/// UTF-8.
+#[repr(C)]
+pub struct BranchlessGraphCommit {
+    /// The hex OID of the commit.
+    pub oid: *mut c_char,
+
+    /// The hex OIDs of the parents of the commit in the graph, which may be
+    /// ancestors rather than parents of the underlying commit, since
+    /// uninteresting commits are omitted.
+    pub parents: *mut *mut c_char,
+
+    /// The number of elements in `parents`.
+    pub num_parents: usize,
+
+    /// The first line of the commit message.
+    pub summary: *mut c_char,
+
+    /// The names of the local branches pointing to the commit, in sorted
+    /// order.
+    pub branches: *mut *mut c_char,
+
+    /// The number of elements in `branches`.
+    pub num_branches: usize,
+
+    /// Whether the commit is checked out.
+    pub is_head: bool,
+
+    /// Whether the commit is on the main branch.
+    pub is_main: bool,
+
+    /// Whether the commit is obsolete, such as because it was rewritten or
+    /// hidden.
+    pub is_obsolete: bool,
+}
+
+/// The commit graph shown in the smartlog.
+#[repr(C)]
+pub struct BranchlessGraph {
+    /// The commits in the graph, in topological order.
+    pub commits: *mut BranchlessGraphCommit,
+
+    /// The number of elements in `commits`.
+    pub num_commits: usize,
+}
+
+/// Whether a commit is obsolete, and what it was rewritten into.
+#[repr(C)]
+pub struct BranchlessSuccessor {
+    /// Whether the commit is obsolete, such as because it was rewritten or
+    /// hidden.
+    pub is_obsolete: bool,
+
+    /// The hex OID of the latest commit that the commit was rewritten into,
+    /// or `NULL` if it wasn't rewritten (for example, if it was only hidden).
+    pub successor: *mut c_char,
+
+    /// Whether the commit was deleted while rewriting other commits, such as
+    /// when its changes were already applied upstream.
+    pub is_deleted: bool,
+}
+
+/// Get a description of the last error which occurred on this thread, or
+/// `NULL` if the last call succeeded. The string is owned by the library, and
+/// is only valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn branchless_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match last_error.borrow().as_ref() {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Open the repository containing the given path. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_repo_open(path: *const c_char) -> *mut BranchlessRepo {
+    ffi_try(ptr::null_mut(), || {
+        let path = str_from_ptr(path, "path")?;
+        let repo = Repo::from_dir(Path::new(path))?;
+        Ok(Box::into_raw(Box::new(BranchlessRepo { repo })))
+    })
+}
+
+/// Free a repository returned by [`branchless_repo_open`]. Does nothing if
+/// `repo` is `NULL`.
+///
+/// # Safety
+///
+/// `repo` must be `NULL` or have been returned by [`branchless_repo_open`],
+/// and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_repo_free(repo: *mut BranchlessRepo) {
+    if !repo.is_null() {
+        drop(Box::from_raw(repo));
+    }
+}
+
+/// Get the commit graph shown in the smartlog for the given revset, or for the
+/// same commits as `git smartlog` if `revset` is `NULL`. Returns `NULL` on
+/// failure.
+///
+/// # Safety
+///
+/// `repo` must have been returned by [`branchless_repo_open`], and `revset`
+/// must be `NULL` or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_graph_new(
+    repo: *const BranchlessRepo,
+    revset: *const c_char,
+) -> *mut BranchlessGraph {
+    ffi_try(ptr::null_mut(), || {
+        let repo = match repo.as_ref() {
+            Some(BranchlessRepo { repo }) => repo,
+            None => eyre::bail!("`repo` was NULL"),
+        };
+        let revset = if revset.is_null() {
+            Revset::default_smartlog_revset()
+        } else {
+            Revset(str_from_ptr(revset, "revset")?.to_owned())
+        };
+        let commits = make_graph_commits(repo, revset)?;
+        let (commits, num_commits) = into_raw_array(commits);
+        Ok(Box::into_raw(Box::new(BranchlessGraph {
+            commits,
+            num_commits,
+        })))
+    })
+}
+
+fn make_graph_commits(repo: &Repo, revset: Revset) -> eyre::Result<Vec<BranchlessGraphCommit>> {
+    let graph_commits = get_graph_commits(repo, revset)?.map_err(|err| eyre::eyre!(err))?;
+    let graph_commits = graph_commits
+        .into_iter()
+        .map(|graph_commit| {
+            let GraphCommit {
+                oid,
+                parents,
+                summary,
+                branches,
+                is_head,
+                is_main,
+                is_obsolete,
+                num_omitted_descendants: _,
+            } = graph_commit;
+            let parents = parents
+                .into_iter()
+                .map(|oid| into_raw_c_string(oid.to_string()))
+                .collect();
+            let branches = branches.into_iter().map(into_raw_c_string).collect();
+            let (parents, num_parents) = into_raw_array(parents);
+            let (branches, num_branches) = into_raw_array(branches);
+            BranchlessGraphCommit {
+                oid: into_raw_c_string(oid.to_string()),
+                parents,
+                num_parents,
+                summary: into_raw_c_string(summary),
+                branches,
+                num_branches,
+                is_head,
+                is_main,
+                is_obsolete,
+            }
+        })
+        .collect();
+    Ok(graph_commits)
+}
+
+/// Free a graph returned by [`branchless_graph_new`]. Does nothing if `graph`
+/// is `NULL`.
+///
+/// # Safety
+///
+/// `graph` must be `NULL` or have been returned by [`branchless_graph_new`],
+/// and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_graph_free(graph: *mut BranchlessGraph) {
+    if graph.is_null() {
+        return;
+    }
+    let BranchlessGraph {
+        commits,
+        num_commits,
+    } = *Box::from_raw(graph);
+    for commit in from_raw_array(commits, num_commits) {
+        let BranchlessGraphCommit {
+            oid,
+            parents,
+            num_parents,
+            summary,
+            branches,
+            num_branches,
+            is_head: _,
+            is_main: _,
+            is_obsolete: _,
+        } = commit;
+        free_raw_c_string(oid);
+        free_raw_c_string(summary);
+        for s in from_raw_array(parents, num_parents)
+            .into_iter()
+            .chain(from_raw_array(branches, num_branches))
+        {
+            free_raw_c_string(s);
+        }
+    }
+}
+
+/// Get whether the commit with the given hex OID is obsolete, and what it was
+/// rewritten into. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `repo` must have been returned by [`branchless_repo_open`], and `oid` must
+/// be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_successor_new(
+    repo: *const BranchlessRepo,
+    oid: *const c_char,
+) -> *mut BranchlessSuccessor {
+    ffi_try(ptr::null_mut(), || {
+        let repo = match repo.as_ref() {
+            Some(BranchlessRepo { repo }) => repo,
+            None => eyre::bail!("`repo` was NULL"),
+        };
+        let oid: NonZeroOid = str_from_ptr(oid, "oid")?.parse()?;
+
+        let Successor {
+            is_obsolete,
+            rewrite_target,
+        } = get_successor(repo, oid)?;
+        let (successor, is_deleted) = match rewrite_target {
+            Some(MaybeZeroOid::NonZero(successor_oid)) => {
+                (into_raw_c_string(successor_oid.to_string()), false)
+            }
+            Some(MaybeZeroOid::Zero) => (ptr::null_mut(), true),
+            None => (ptr::null_mut(), false),
+        };
+        Ok(Box::into_raw(Box::new(BranchlessSuccessor {
+            is_obsolete,
+            successor,
+            is_deleted,
+        })))
+    })
+}
+
+/// Free a value returned by [`branchless_successor_new`]. Does nothing if
+/// `successor` is `NULL`.
+///
+/// # Safety
+///
+/// `successor` must be `NULL` or have been returned by
+/// [`branchless_successor_new`], and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn branchless_successor_free(successor: *mut BranchlessSuccessor) {
+    if successor.is_null() {
+        return;
+    }
+    let BranchlessSuccessor {
+        is_obsolete: _,
+        successor,
+        is_deleted: _,
+    } = *Box::from_raw(successor);
+    free_raw_c_string(successor);
+}
@@ -0,0 +1,121 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use branchless_ffi::{
+    branchless_graph_free, branchless_graph_new, branchless_last_error, branchless_repo_free,
+    branchless_repo_open, branchless_successor_free, branchless_successor_new,
+};
+use lib::testing::make_git;
+
+unsafe fn to_string(s: *const c_char) -> String {
+    CStr::from_ptr(s).to_str().unwrap().to_owned()
+}
+
+unsafe fn to_strings(items: *mut *mut c_char, len: usize) -> Vec<String> {
+    (0..len).map(|i| to_string(*items.add(i))).collect()
+}
+
+#[test]
+fn test_ffi_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+
+    let path = CString::new(git.repo_path.to_str().unwrap())?;
+    unsafe {
+        let repo = branchless_repo_open(path.as_ptr());
+        assert!(!repo.is_null());
+
+        let graph = branchless_graph_new(repo, ptr::null());
+        assert!(!graph.is_null());
+        let graph_ref = &*graph;
+        assert_eq!(graph_ref.num_commits, 3);
+        let commits = std::slice::from_raw_parts(graph_ref.commits, graph_ref.num_commits);
+
+        assert!(commits[0].is_main);
+        assert_eq!(to_string(commits[1].oid), test1_oid.to_string());
+        assert_eq!(to_string(commits[1].summary), "create test1.txt");
+        assert_eq!(
+            to_strings(commits[1].parents, commits[1].num_parents),
+            vec![to_string(commits[0].oid)]
+        );
+        assert!(!commits[1].is_head);
+
+        assert_eq!(to_string(commits[2].oid), test2_oid.to_string());
+        assert_eq!(
+            to_strings(commits[2].branches, commits[2].num_branches),
+            vec!["foo".to_string()]
+        );
+        assert!(commits[2].is_head);
+        assert!(!commits[2].is_obsolete);
+        branchless_graph_free(graph);
+
+        let revset = CString::new("foo^")?;
+        let graph = branchless_graph_new(repo, revset.as_ptr());
+        assert!(!graph.is_null());
+        let commits = std::slice::from_raw_parts((*graph).commits, (*graph).num_commits);
+        let oids: Vec<String> = commits.iter().map(|commit| to_string(commit.oid)).collect();
+        assert!(oids.contains(&test1_oid.to_string()));
+        branchless_graph_free(graph);
+
+        let revset = CString::new("foo(")?;
+        let graph = branchless_graph_new(repo, revset.as_ptr());
+        assert!(graph.is_null());
+        assert!(to_string(branchless_last_error()).contains("parse error"));
+
+        branchless_repo_free(repo);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ffi_successor() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    let amended_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+
+    let path = CString::new(git.repo_path.to_str().unwrap())?;
+    unsafe {
+        let repo = branchless_repo_open(path.as_ptr());
+        assert!(!repo.is_null());
+
+        let oid = CString::new(test1_oid.to_string())?;
+        let successor = branchless_successor_new(repo, oid.as_ptr());
+        assert!(!successor.is_null());
+        assert!((*successor).is_obsolete);
+        assert!(!(*successor).is_deleted);
+        assert_eq!(to_string((*successor).successor), amended_oid.to_string());
+        branchless_successor_free(successor);
+
+        let oid = CString::new(amended_oid.to_string())?;
+        let successor = branchless_successor_new(repo, oid.as_ptr());
+        assert!(!successor.is_null());
+        assert!(!(*successor).is_obsolete);
+        assert!((*successor).successor.is_null());
+        branchless_successor_free(successor);
+
+        let oid = CString::new("not an oid")?;
+        let successor = branchless_successor_new(repo, oid.as_ptr());
+        assert!(successor.is_null());
+        assert!(!branchless_last_error().is_null());
+
+        branchless_repo_free(repo);
+    }
+
+    let path = CString::new(git.repo_path.join("nonexistent").to_str().unwrap())?;
+    unsafe {
+        let repo = branchless_repo_open(path.as_ptr());
+        assert!(repo.is_null());
+        assert!(!branchless_last_error().is_null());
+    }
+
+    Ok(())
+}
@@ -16,10 +16,12 @@
 )]
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::io::{stdin, BufRead};
 use std::time::SystemTime;
 
+use eden_dag::DagAlgorithm;
 use eyre::Context;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::{HookArgs, HookSubcommand};
@@ -27,10 +29,23 @@ use itertools::Itertools;
 use lib::util::ExitCode;
 use tracing::{error, instrument, warn};
 
-use lib::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb};
+use lib::core::config::{
+    get_hint_enabled, get_hint_string, get_ignored_ref_patterns,
+    get_pre_push_obsolete_commits_action, get_reference_transaction_hook_enabled,
+    print_hint_suppression_notice, Hint, PrePushObsoleteCommitsAction,
+    PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY,
+};
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::eventlog::{
+    ref_pattern_matches, should_ignore_ref_updates, spool_ref_update_events, CommitActivityStatus,
+    CopyKind, Event, EventLogDb, EventReplayer, EventTransactionId, SpooledRefUpdate,
+    BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
 use lib::core::formatting::{Glyphs, Pluralize};
 use lib::core::gc::{gc, mark_commit_reachable};
-use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::find_rewrite_target;
+use lib::git::{CategorizedReferenceName, Commit, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
 use lib::core::effects::Effects;
 pub use lib::core::rewrite::rewrite_hooks::{
@@ -38,6 +53,42 @@ pub use lib::core::rewrite::rewrite_hooks::{
     hook_skip_upstream_applied_commit,
 };
 
+/// The file in the `git am` state directory which stores the ID of the
+/// transaction for the current `git am` session.
+const APPLY_MAILBOX_TRANSACTION_ID_FILE_NAME: &str = "branchless_transaction_id";
+
+/// Make a transaction ID for the events recorded by a hook.
+///
+/// While `git am` is applying a series of patches, all of the hooks which it
+/// invokes share one transaction, so that the whole series can be undone at
+/// once, like the commit created by a single `git commit` invocation.
+#[instrument]
+fn make_hook_transaction_id(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    hook_name: &str,
+) -> eyre::Result<EventTransactionId> {
+    if !repo.is_apply_mailbox_underway() {
+        return event_log_db.make_transaction_id(now, hook_name);
+    }
+
+    let event_tx_id_path = repo
+        .get_apply_mailbox_state_dir_path()
+        .join(APPLY_MAILBOX_TRANSACTION_ID_FILE_NAME);
+    if let Ok(contents) = std::fs::read_to_string(&event_tx_id_path) {
+        match contents.trim().parse() {
+            Ok(event_tx_id) => return Ok(event_tx_id),
+            Err(err) => warn!(?err, ?contents, "Could not parse `git am` transaction ID"),
+        }
+    }
+    let event_tx_id = event_log_db.make_transaction_id(now, "am")?;
+    // `git am` deletes its state directory when it's done, along with this file.
+    std::fs::write(&event_tx_id_path, event_tx_id.to_string())
+        .wrap_err("Writing `git am` transaction ID")?;
+    Ok(event_tx_id)
+}
+
 /// Handle Git's `post-checkout` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -71,7 +122,7 @@ fn hook_post_checkout(
             let oid: MaybeZeroOid = current_head_oid.parse()?;
             oid
         },
-        ref_name: ReferenceName::from("HEAD"),
+        ref_name: repo.get_head_event_reference_name(),
         message: None,
     }])?;
     Ok(())
@@ -113,21 +164,147 @@ fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<(
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs_f64();
 
-    let event_tx_id = event_log_db.make_transaction_id(now, hook_name)?;
-    event_log_db.add_events(vec![Event::CommitEvent {
+    let event_tx_id = make_hook_transaction_id(&repo, &event_log_db, now, hook_name)?;
+    let copied_commit = find_copied_commit(&repo, &commit)?;
+    let mut events = vec![Event::CommitEvent {
         timestamp,
         event_tx_id,
         commit_oid: commit.get_oid(),
-    }])?;
+    }];
+    if let Some((original_commit_oid, kind)) = copied_commit {
+        events.push(Event::CopyEvent {
+            timestamp,
+            event_tx_id,
+            original_commit_oid,
+            new_commit_oid: commit.get_oid(),
+            kind,
+        });
+    }
+    event_log_db.add_events(events)?;
     writeln!(
         effects.get_output_stream(),
         "branchless: processed commit: {}",
         glyphs.render(commit.friendly_describe(&glyphs)?)?,
     )?;
 
+    if let Some((original_commit_oid, CopyKind::CherryPick)) = copied_commit {
+        if get_hint_enabled(effects, &repo, Hint::HideCherryPickedCommit)?
+            && is_visible_draft_commit(effects, &repo, &event_log_db, original_commit_oid)?
+        {
+            let original_commit = repo.find_commit_or_fail(original_commit_oid)?;
+            writeln!(
+                effects.get_output_stream(),
+                "{}: the original commit {} is still visible; to hide it, run: git hide {}",
+                effects.get_glyphs().render(get_hint_string(effects))?,
+                glyphs.render(original_commit.friendly_describe(&glyphs)?)?,
+                original_commit.get_short_oid()?,
+            )?;
+            print_hint_suppression_notice(effects, Hint::HideCherryPickedCommit)?;
+        }
+    }
+
     Ok(())
 }
 
+/// Determine whether the commit which was just made was created by `git
+/// cherry-pick` or `git revert`, and if so, which commit it was created from.
+///
+/// Git doesn't record this directly, so it's inferred from the state of the
+/// operation which is underway, or failing that, from the lines which `git
+/// cherry-pick -x` and `git revert` add to the commit message.
+fn find_copied_commit(
+    repo: &Repo,
+    commit: &Commit,
+) -> eyre::Result<Option<(NonZeroOid, CopyKind)>> {
+    // Commits made during a rebase are recorded as rewrites by the
+    // `post-rewrite` hook instead.
+    if repo.is_rebase_underway()? {
+        return Ok(None);
+    }
+
+    let original = if let Some(original_commit) = repo.revparse_single_commit("CHERRY_PICK_HEAD")? {
+        Some((original_commit.get_oid(), CopyKind::CherryPick))
+    } else if let Some(original) = read_sequencer_current_commit(repo)? {
+        Some(original)
+    } else {
+        let message = commit.get_message_raw()?;
+        let message = String::from_utf8_lossy(&message);
+        // Use the last such line, since it's the one which was most recently
+        // added.
+        message
+            .lines()
+            .rev()
+            .find_map(|line| {
+                let line = line.trim();
+                if let Some(oid) = line
+                    .strip_prefix("(cherry picked from commit ")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    Some((oid, CopyKind::CherryPick))
+                } else {
+                    line.strip_prefix("This reverts commit ")
+                        .and_then(|rest| rest.strip_suffix('.'))
+                        .map(|oid| (oid, CopyKind::Revert))
+                }
+            })
+            .and_then(|(oid, kind)| Some((oid.parse::<NonZeroOid>().ok()?, kind)))
+    };
+
+    match original {
+        Some((original_commit_oid, kind))
+            if original_commit_oid != commit.get_oid()
+                && repo.find_commit(original_commit_oid)?.is_some() =>
+        {
+            Ok(Some((original_commit_oid, kind)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// When `git cherry-pick` or `git revert` is applying several commits, the
+/// first line of the sequencer's todo list is the commit being applied.
+fn read_sequencer_current_commit(repo: &Repo) -> eyre::Result<Option<(NonZeroOid, CopyKind)>> {
+    let todo_path = repo.get_path().join("sequencer").join("todo");
+    let contents = match std::fs::read_to_string(&todo_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err("Reading sequencer todo list"),
+    };
+    let mut words = match contents.lines().next() {
+        Some(line) => line.split_whitespace(),
+        None => return Ok(None),
+    };
+    let kind = match words.next() {
+        Some("pick" | "p") => CopyKind::CherryPick,
+        Some("revert" | "r") => CopyKind::Revert,
+        _ => return Ok(None),
+    };
+    match words.next() {
+        Some(spec) => Ok(repo
+            .revparse_single_commit(spec)?
+            .map(|commit| (commit.get_oid(), kind))),
+        None => Ok(None),
+    }
+}
+
+/// Determine whether the given commit is visible in the smartlog and hasn't
+/// been merged into the main branch.
+fn is_visible_draft_commit(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    commit_oid: NonZeroOid,
+) -> eyre::Result<bool> {
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    if repo.find_merge_base(commit_oid, main_branch_oid)? == Some(commit_oid) {
+        return Ok(false);
+    }
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let status = event_replayer
+        .get_cursor_commit_activity_status(event_replayer.make_default_cursor(), commit_oid);
+    Ok(matches!(status, CommitActivityStatus::Active))
+}
+
 /// Handle Git's `post-commit` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -154,6 +331,125 @@ fn hook_post_applypatch(effects: &Effects) -> eyre::Result<()> {
     hook_post_commit_common(effects, "post-applypatch")
 }
 
+/// Handle Git's `pre-push` hook. Warns about commits being pushed which have
+/// been rewritten or hidden locally, since pushing them usually means
+/// publishing an out-of-date version of a stack. Depending on the
+/// `branchless.prePush.obsoleteCommits` setting, the push is also aborted.
+///
+/// See the man-page for `githooks(5)`.
+#[instrument]
+fn hook_pre_push(effects: &Effects, remote_name: &str) -> eyre::Result<ExitCode> {
+    let glyphs = Glyphs::detect();
+    let repo = Repo::from_current_dir()?;
+
+    let mut local_oids = Vec::new();
+    let mut remote_oids = Vec::new();
+    for line in stdin().lock().lines() {
+        let line = line.wrap_err("Reading pre-push line")?;
+        // Each line is of the form `<local ref> <local oid> <remote ref> <remote oid>`.
+        let (local_oid, remote_oid) = match line.split(' ').collect_vec().as_slice() {
+            [_local_ref, local_oid, _remote_ref, remote_oid] => {
+                let local_oid: MaybeZeroOid = local_oid.parse()?;
+                let remote_oid: MaybeZeroOid = remote_oid.parse()?;
+                (local_oid, remote_oid)
+            }
+            _ => {
+                warn!(?line, "Could not parse pre-push line");
+                continue;
+            }
+        };
+        // A zero local OID means that the remote reference is being deleted.
+        if let MaybeZeroOid::NonZero(local_oid) = local_oid {
+            local_oids.push(local_oid);
+        }
+        // The remote commit may not be available locally, such as if it was
+        // pushed from another clone and not fetched since.
+        if let MaybeZeroOid::NonZero(remote_oid) = remote_oid {
+            if repo.find_commit(remote_oid)?.is_some() {
+                remote_oids.push(remote_oid);
+            }
+        }
+    }
+    if local_oids.is_empty() {
+        return Ok(ExitCode(0));
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let local_commits: CommitSet = local_oids.into_iter().collect();
+    let remote_commits: CommitSet = remote_oids.into_iter().collect();
+    let dag = Dag::open_and_sync_heads(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+        local_commits.union(&remote_commits),
+    )?;
+
+    let pushed_commits = dag
+        .query()
+        .only(local_commits, remote_commits.union(&dag.main_branch_commit))?;
+    let obsolete_commits = pushed_commits.intersection(&dag.query_obsolete_commits());
+    let obsolete_commits = sorted_commit_set(&repo, &dag, &obsolete_commits)?;
+    if obsolete_commits.is_empty() {
+        return Ok(ExitCode(0));
+    }
+
+    let action = get_pre_push_obsolete_commits_action(&repo)?;
+    writeln!(
+        effects.get_error_stream(),
+        "branchless: {}: pushing {} to {remote_name} which {} been rewritten or hidden locally:",
+        match action {
+            PrePushObsoleteCommitsAction::Warn => "warning",
+            PrePushObsoleteCommitsAction::Block => "error",
+        },
+        Pluralize {
+            determiner: None,
+            amount: obsolete_commits.len(),
+            unit: ("commit", "commits"),
+        },
+        if obsolete_commits.len() == 1 {
+            "has"
+        } else {
+            "have"
+        },
+    )?;
+    for commit in obsolete_commits {
+        let description = glyphs.render(commit.friendly_describe(&glyphs)?)?;
+        match find_rewrite_target(&event_replayer, event_cursor, commit.get_oid()) {
+            Some(MaybeZeroOid::NonZero(rewritten_oid)) => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "branchless:   {description} (rewritten as {})",
+                    glyphs
+                        .render(repo.friendly_describe_commit_from_oid(&glyphs, rewritten_oid)?)?,
+                )?;
+            }
+            Some(MaybeZeroOid::Zero) | None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "branchless:   {description} (hidden)"
+                )?;
+            }
+        }
+    }
+
+    match action {
+        PrePushObsoleteCommitsAction::Warn => Ok(ExitCode(0)),
+        PrePushObsoleteCommitsAction::Block => {
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: aborting push ({PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY} is set to block); to push anyways, run: git push --no-verify"
+            )?;
+            Ok(ExitCode(1))
+        }
+    }
+}
+
 mod reference_transaction {
     use std::collections::HashMap;
     use std::fs::File;
@@ -376,6 +672,30 @@ mod reference_transaction {
     }
 }
 
+/// Print a summary of the reference updates which are about to be recorded.
+fn write_processing_ref_updates<'a>(
+    effects: &Effects,
+    ref_names: impl ExactSizeIterator<Item = &'a ReferenceName>,
+) -> eyre::Result<()> {
+    let num_reference_updates = Pluralize {
+        determiner: None,
+        amount: ref_names.len(),
+        unit: ("update", "updates"),
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: processing {}: {}",
+        num_reference_updates,
+        ref_names
+            .map(|ref_name| CategorizedReferenceName::new(ref_name).friendly_describe())
+            .map(|description| format!("{}", console::style(description).green()))
+            .sorted()
+            .collect::<Vec<_>>()
+            .join(", ")
+    )?;
+    Ok(())
+}
+
 /// Handle Git's `reference-transaction` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -392,12 +712,7 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
     let now = SystemTime::now();
 
     let repo = Repo::from_current_dir()?;
-    let conn = repo.get_db_conn()?;
-    let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = event_log_db.make_transaction_id(now, "reference-transaction")?;
-
-    let packed_references = read_packed_refs_file(&repo)?;
-
+    let ignored_ref_patterns = get_ignored_ref_patterns(&repo)?;
     let parsed_lines: Vec<ParsedReferenceTransactionLine> = stdin()
         .lock()
         .split(b'\n')
@@ -426,66 +741,151 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
                  ref_name,
                  old_oid: _,
                  new_oid: _,
-             }| !should_ignore_ref_updates(ref_name),
+             }| {
+                !should_ignore_ref_updates(ref_name)
+                    && !ignored_ref_patterns
+                        .iter()
+                        .any(|pattern| ref_pattern_matches(pattern, ref_name))
+            },
         )
-        .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
         .collect();
     if parsed_lines.is_empty() {
         return Ok(());
     }
 
-    let num_reference_updates = Pluralize {
-        determiner: None,
-        amount: parsed_lines.len(),
-        unit: ("update", "updates"),
-    };
-    writeln!(
-        effects.get_output_stream(),
-        "branchless: processing {}: {}",
-        num_reference_updates,
-        parsed_lines
-            .iter()
-            .map(
-                |ParsedReferenceTransactionLine {
-                     ref_name,
-                     old_oid: _,
-                     new_oid: _,
-                 }| { CategorizedReferenceName::new(ref_name).friendly_describe() }
-            )
-            .map(|description| format!("{}", console::style(description).green()))
-            .sorted()
-            .collect::<Vec<_>>()
-            .join(", ")
-    )?;
-
-    let timestamp = now
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .wrap_err("Calculating timestamp")?
-        .as_secs_f64();
-    let events = parsed_lines
+    let packed_references = read_packed_refs_file(&repo)?;
+    let ref_updates: Vec<SpooledRefUpdate> = parsed_lines
         .into_iter()
+        .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
         .map(
             |ParsedReferenceTransactionLine {
                  ref_name,
                  old_oid,
                  new_oid,
              }| {
-                Event::RefUpdateEvent {
-                    timestamp,
-                    event_tx_id,
-                    ref_name,
-                    old_oid,
-                    new_oid,
-                    message: None,
+                // `HEAD` is per-worktree, but the event log is shared between
+                // worktrees.
+                if ref_name.as_str() == "HEAD" {
+                    (repo.get_head_event_reference_name(), old_oid, new_oid)
+                } else {
+                    (ref_name, old_oid, new_oid)
                 }
             },
         )
+        .collect();
+
+    write_processing_ref_updates(effects, ref_updates.iter().map(|(ref_name, _, _)| ref_name))?;
+
+    // Unless these updates belong to an enclosing transaction, spool them
+    // rather than opening the database, since operations which touch many
+    // references run this hook many times in quick succession.
+    let is_enclosing_transaction_underway =
+        std::env::var_os(BRANCHLESS_TRANSACTION_ID_ENV_VAR).is_some();
+    if !is_enclosing_transaction_underway && !repo.is_apply_mailbox_underway() {
+        spool_ref_update_events(&repo.get_db_path(), now, &ref_updates)?;
+        return Ok(());
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = make_hook_transaction_id(&repo, &event_log_db, now, "reference-transaction")?;
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating timestamp")?
+        .as_secs_f64();
+    let events = ref_updates
+        .into_iter()
+        .map(|(ref_name, old_oid, new_oid)| Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid,
+            message: None,
+        })
         .collect::<Vec<Event>>();
     event_log_db.add_events(events)?;
 
     Ok(())
 }
 
+/// If the `reference-transaction` hook has been disabled, record the updates
+/// to `HEAD` and local branches which happened since the last recorded event
+/// instead.
+///
+/// This is coarser than using the `reference-transaction` hook: intermediate
+/// locations of references aren't observed, and updates made by operations
+/// which don't invoke any of our other hooks aren't recorded until the next
+/// hook runs.
+#[instrument]
+fn record_ref_updates_without_reference_transaction(
+    effects: &Effects,
+    hook_name: &str,
+) -> eyre::Result<()> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    if get_reference_transaction_hook_enabled(&repo)? {
+        return Ok(());
+    }
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let latest_ref_locations = event_replayer.get_latest_ref_locations();
+
+    let head_reference_name = repo.get_head_event_reference_name();
+    let mut current_ref_locations: HashMap<ReferenceName, NonZeroOid> = HashMap::new();
+    if let Some(head_oid) = repo.get_head_info()?.oid {
+        current_ref_locations.insert(head_reference_name, head_oid);
+    }
+    for branch in repo.get_all_local_branches()? {
+        if let Some(branch_oid) = branch.get_oid()? {
+            current_ref_locations.insert(branch.get_reference_name()?, branch_oid);
+        }
+    }
+
+    let mut updates: Vec<(ReferenceName, MaybeZeroOid, MaybeZeroOid)> = Vec::new();
+    for (ref_name, new_oid) in current_ref_locations.iter() {
+        let old_oid = latest_ref_locations.get(ref_name).copied();
+        if old_oid != Some(*new_oid) {
+            updates.push((ref_name.clone(), old_oid.into(), (*new_oid).into()));
+        }
+    }
+    for (ref_name, old_oid) in latest_ref_locations.iter() {
+        let is_local_branch = matches!(
+            CategorizedReferenceName::new(ref_name),
+            CategorizedReferenceName::LocalBranch { .. }
+        );
+        if is_local_branch && !current_ref_locations.contains_key(ref_name) {
+            updates.push((ref_name.clone(), (*old_oid).into(), MaybeZeroOid::Zero));
+        }
+    }
+    if updates.is_empty() {
+        return Ok(());
+    }
+    updates.sort();
+
+    write_processing_ref_updates(effects, updates.iter().map(|(ref_name, _, _)| ref_name))?;
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating timestamp")?
+        .as_secs_f64();
+    let event_tx_id = make_hook_transaction_id(&repo, &event_log_db, now, hook_name)?;
+    event_log_db.add_events(
+        updates
+            .into_iter()
+            .map(|(ref_name, old_oid, new_oid)| Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name,
+                old_oid,
+                new_oid,
+                message: None,
+            })
+            .collect(),
+    )?;
+    Ok(())
+}
+
 /// `hook` subcommand.
 #[instrument]
 pub fn command_main(ctx: CommandContext, args: HookArgs) -> eyre::Result<ExitCode> {
@@ -502,11 +902,22 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> eyre::Result<ExitCod
         }
 
         HookSubcommand::PreAutoGc => {
-            gc(&effects)?;
+            // If another garbage collection is already running, there's no
+            // need to hold up Git's own.
+            let _exit_code = gc(&effects, &git_run_info, false)?;
+        }
+
+        HookSubcommand::PrePush {
+            remote_name,
+            remote_url: _,
+        } => {
+            let exit_code = hook_pre_push(&effects, &remote_name)?;
+            return Ok(exit_code);
         }
 
         HookSubcommand::PostApplypatch => {
             hook_post_applypatch(&effects)?;
+            record_ref_updates_without_reference_transaction(&effects, "post-applypatch")?;
         }
 
         HookSubcommand::PostCheckout {
@@ -520,18 +931,22 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> eyre::Result<ExitCod
                 &current_commit,
                 is_branch_checkout,
             )?;
+            record_ref_updates_without_reference_transaction(&effects, "post-checkout")?;
         }
 
         HookSubcommand::PostCommit => {
             hook_post_commit(&effects)?;
+            record_ref_updates_without_reference_transaction(&effects, "post-commit")?;
         }
 
         HookSubcommand::PostMerge { is_squash_merge } => {
             hook_post_merge(&effects, is_squash_merge)?;
+            record_ref_updates_without_reference_transaction(&effects, "post-merge")?;
         }
 
         HookSubcommand::PostRewrite { rewrite_type } => {
             hook_post_rewrite(&effects, &git_run_info, &rewrite_type)?;
+            record_ref_updates_without_reference_transaction(&effects, "post-rewrite")?;
         }
 
         HookSubcommand::ReferenceTransaction { transaction_state } => {
@@ -12,6 +12,7 @@
 use std::fmt::Write;
 use std::io::{stdin, stdout, BufRead, BufReader, Write as WriteIo};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use console::style;
 use eyre::Context;
@@ -23,12 +24,24 @@ use path_slash::PathExt;
 use tracing::{instrument, warn};
 
 use git_branchless_opts::{write_man_pages, InitArgs};
-use lib::core::config::{get_default_branch_name, get_default_hooks_dir, get_hooks_dir};
+use lib::core::config::{
+    get_default_branch_name, get_default_hooks_dir, get_hint_enabled, get_hint_string,
+    get_hooks_dir, get_init_aliases, get_init_sapling_aliases, get_pre_push_hook_enabled,
+    get_reference_transaction_hook_enabled, get_subcommand_aliases, Hint, PRE_PUSH_HOOK_CONFIG_KEY,
+    REFERENCE_TRANSACTION_HOOK_CONFIG_KEY, SAPLING_ALIASES_CONFIG_KEY,
+};
 use lib::core::dag::Dag;
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{
+    get_ref_update_spool_dir_path, is_gc_ref, Event, EventLogDb, EventReplayer, KEEP_REFS_PREFIX,
+};
+use lib::core::formatting::Pluralize;
+use lib::core::gc::{mark_commit_reachable, migrate_legacy_keep_references};
 use lib::core::repo_ext::RepoExt;
-use lib::git::{BranchType, Config, ConfigRead, ConfigWrite, GitRunInfo, GitVersion, Repo};
+use lib::git::{
+    BranchType, Config, ConfigRead, ConfigWrite, GitRunInfo, GitRunOpts, GitRunResult, GitVersion,
+    NonZeroOid, ReferenceName, Repo,
+};
 
 /// The contents of all Git hooks to install.
 pub const ALL_HOOKS: &[(&str, &str)] = &[
@@ -66,6 +79,12 @@ git branchless hook post-rewrite "$@"
         "pre-auto-gc",
         r#"
 git branchless hook pre-auto-gc "$@"
+"#,
+    ),
+    (
+        "pre-push",
+        r#"
+git branchless hook pre-push "$@" || exit $?
 "#,
     ),
     (
@@ -82,6 +101,44 @@ echo 'branchless: This is a bug. Please report it.'
     ),
 ];
 
+/// The hooks in `ALL_HOOKS` which Git only invokes for operations on a working
+/// copy. These are not installed in bare repositories.
+const WORKING_COPY_HOOKS: &[&str] = &[
+    "post-applypatch",
+    "post-checkout",
+    "post-commit",
+    "post-merge",
+    "post-rewrite",
+];
+
+/// The hook which can be disabled with the
+/// `branchless.core.referenceTransactionHook` config setting.
+const REFERENCE_TRANSACTION_HOOK: &str = "reference-transaction";
+
+/// The hook which is only installed if the `branchless.core.prePushHook`
+/// config setting is enabled.
+const PRE_PUSH_HOOK: &str = "pre-push";
+
+/// Get the hooks from `ALL_HOOKS` which apply to the given repository. `repo`
+/// should be the repository for the current working copy, rather than the
+/// parent repository of a worktree.
+fn get_applicable_hooks(
+    repo: &Repo,
+    reference_transaction_hook_enabled: bool,
+    pre_push_hook_enabled: bool,
+) -> Vec<(&'static str, &'static str)> {
+    let is_bare = repo.get_working_copy_path().is_none();
+    ALL_HOOKS
+        .iter()
+        .filter(|(hook_type, _hook_script)| !(is_bare && WORKING_COPY_HOOKS.contains(hook_type)))
+        .filter(|(hook_type, _hook_script)| {
+            reference_transaction_hook_enabled || *hook_type != REFERENCE_TRANSACTION_HOOK
+        })
+        .filter(|(hook_type, _hook_script)| pre_push_hook_enabled || *hook_type != PRE_PUSH_HOOK)
+        .copied()
+        .collect()
+}
+
 const ALL_ALIASES: &[(&str, &str)] = &[
     ("amend", "amend"),
     ("hide", "hide"),
@@ -102,6 +159,16 @@ const ALL_ALIASES: &[(&str, &str)] = &[
     ("unhide", "unhide"),
 ];
 
+/// Aliases named after Sapling commands, installed if
+/// `branchless.init.saplingAliases` is set. Unlike `ALL_ALIASES`, the targets
+/// may include arguments.
+const SAPLING_ALIASES: &[(&str, &str)] = &[
+    ("fold", "fold"),
+    ("goto", "switch"),
+    ("histedit", "wrap -- rebase --interactive"),
+    ("metaedit", "reword"),
+];
+
 /// A specification for installing a Git hook on disk.
 #[derive(Debug)]
 pub enum Hook {
@@ -121,7 +188,7 @@ pub enum Hook {
 /// Determine the path where all hooks are installed.
 #[instrument]
 pub fn determine_hook_path(repo: &Repo, hooks_dir: &Path, hook_type: &str) -> eyre::Result<Hook> {
-    let multi_hooks_path = repo.get_path().join("hooks_multi");
+    let multi_hooks_path = repo.get_common_path().join("hooks_multi");
     let hook = if multi_hooks_path.exists() {
         let path = multi_hooks_path
             .join(format!("{hook_type}.d"))
@@ -201,12 +268,108 @@ fn write_script(path: &Path, contents: &str) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Hooks which receive input on stdin. When chaining to a pre-existing hook,
+/// the input has to be saved so that it can be passed to both hooks.
+const HOOKS_WITH_STDIN: &[&str] = &["post-rewrite", "pre-push", "reference-transaction"];
+
+/// Get the path that a pre-existing, non-branchless hook is moved to when
+/// installing our hook in its place. Our hook calls the original hook, and
+/// `git branchless init --uninstall` moves it back.
+fn get_original_hook_path(hook_path: &Path) -> PathBuf {
+    let mut file_name = hook_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".pre-branchless");
+    hook_path.with_file_name(file_name)
+}
+
+/// Wrap the given hook contents so that the pre-existing hook at
+/// `original_hook_path` is run first. The exit code of the original hook is
+/// preserved.
+fn make_chained_hook_contents(
+    hook_type: &str,
+    original_hook_path: &Path,
+    hook_contents: &str,
+) -> String {
+    let original_hook_name = original_hook_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let original_hook = format!(r#""$(dirname "$0")/{original_hook_name}""#);
+    if HOOKS_WITH_STDIN.contains(&hook_type) {
+        format!(
+            r#"
+# Run the hook which was installed before git-branchless. It will be restored
+# by `git branchless init --uninstall`. Like Git, skip it if it's not
+# executable.
+branchless_exit_code=0
+if [ -x {original_hook} ]; then
+    branchless_stdin=$(mktemp)
+    cat >"$branchless_stdin"
+    {original_hook} "$@" <"$branchless_stdin"
+    branchless_exit_code=$?
+    exec <"$branchless_stdin"
+    rm -f "$branchless_stdin"
+fi
+{hook_contents}
+exit $branchless_exit_code
+"#
+        )
+    } else {
+        format!(
+            r#"
+# Run the hook which was installed before git-branchless. It will be restored
+# by `git branchless init --uninstall`. Like Git, skip it if it's not
+# executable.
+branchless_exit_code=0
+if [ -x {original_hook} ]; then
+    {original_hook} "$@"
+    branchless_exit_code=$?
+fi
+{hook_contents}
+exit $branchless_exit_code
+"#
+        )
+    }
+}
+
 #[instrument]
-fn update_hook_contents(hook: &Hook, hook_contents: &str) -> eyre::Result<()> {
+fn update_hook_contents(
+    effects: &Effects,
+    hook_type: &str,
+    hook: &Hook,
+    hook_contents: &str,
+) -> eyre::Result<()> {
     let (hook_path, hook_contents) = match hook {
         Hook::RegularHook { path } => match std::fs::read_to_string(path) {
+            Ok(lines) if !lines.lines().any(|line| line == UPDATE_MARKER_START) => {
+                // The hook was installed by someone else (such as a hook
+                // manager). Rather than editing it, move it out of the way and
+                // chain to it, since it might not be a shell script, or it
+                // might exit before reaching our hook contents.
+                let original_hook_path = get_original_hook_path(path);
+                std::fs::rename(path, &original_hook_path).wrap_err_with(|| {
+                    format!("Moving existing hook {path:?} to {original_hook_path:?}")
+                })?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Moved existing {hook_type} hook to: {}",
+                    original_hook_path.to_string_lossy()
+                )?;
+                let hook_contents =
+                    make_chained_hook_contents(hook_type, &original_hook_path, hook_contents);
+                let hook_contents = format!(
+                    "{SHEBANG}\n{UPDATE_MARKER_START}\n{hook_contents}\n{UPDATE_MARKER_END}\n"
+                );
+                (path, hook_contents)
+            }
             Ok(lines) => {
-                let lines = update_between_lines(&lines, hook_contents);
+                let original_hook_path = get_original_hook_path(path);
+                let lines = if original_hook_path.exists() {
+                    let hook_contents =
+                        make_chained_hook_contents(hook_type, &original_hook_path, hook_contents);
+                    update_between_lines(&lines, &hook_contents)
+                } else {
+                    update_between_lines(&lines, hook_contents)
+                };
                 (path, lines)
             }
             Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -229,29 +392,60 @@ fn update_hook_contents(hook: &Hook, hook_contents: &str) -> eyre::Result<()> {
 
 #[instrument]
 fn install_hook(
+    effects: &Effects,
     repo: &Repo,
     hooks_dir: &Path,
     hook_type: &str,
     hook_script: &str,
 ) -> eyre::Result<()> {
     let hook = determine_hook_path(repo, hooks_dir, hook_type)?;
-    update_hook_contents(&hook, hook_script)?;
+    update_hook_contents(effects, hook_type, &hook, hook_script)?;
     Ok(())
 }
 
 #[instrument]
-fn install_hooks(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<()> {
+fn install_hooks(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    hooks: &[(&str, &str)],
+) -> eyre::Result<()> {
     writeln!(
         effects.get_output_stream(),
         "Installing hooks: {}",
-        ALL_HOOKS
+        hooks
             .iter()
             .map(|(hook_type, _hook_script)| hook_type)
             .join(", ")
     )?;
+    let is_installing = |hook_type: &str| {
+        hooks
+            .iter()
+            .any(|(installed_hook_type, _hook_script)| *installed_hook_type == hook_type)
+    };
+    if !WORKING_COPY_HOOKS
+        .iter()
+        .all(|hook_type| is_installing(hook_type))
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "Skipping hooks which require a working copy (this repository is bare): {}",
+            WORKING_COPY_HOOKS.join(", ")
+        )?;
+    }
     let hooks_dir = get_hooks_dir(git_run_info, repo, None)?;
-    for (hook_type, hook_script) in ALL_HOOKS {
-        install_hook(repo, &hooks_dir, hook_type, hook_script)?;
+    for (hook_type, hook_script) in hooks {
+        install_hook(effects, repo, &hooks_dir, hook_type, hook_script)?;
+    }
+    if !is_installing(REFERENCE_TRANSACTION_HOOK) {
+        writeln!(
+            effects.get_output_stream(),
+            "Skipping hook {REFERENCE_TRANSACTION_HOOK} ({REFERENCE_TRANSACTION_HOOK_CONFIG_KEY} is false); branch updates will be recorded by the other hooks instead"
+        )?;
+        remove_hook(effects, repo, &hooks_dir, REFERENCE_TRANSACTION_HOOK)?;
+    }
+    if !is_installing(PRE_PUSH_HOOK) {
+        remove_hook(effects, repo, &hooks_dir, PRE_PUSH_HOOK)?;
     }
 
     let default_hooks_dir = get_default_hooks_dir(repo);
@@ -261,7 +455,8 @@ fn install_hooks(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) -> e
             "\
 {}: the configuration value core.hooksPath was set to: {},
 which is not the expected default value of: {}
-The Git hooks above may have been installed to an unexpected global location.",
+The Git hooks above may have been installed to an unexpected global location.
+If core.hooksPath is managed by another tool, configure it to run: git branchless hook <hook-type> \"$@\"",
             style("Warning").yellow().bold(),
             hooks_dir.to_string_lossy(),
             default_hooks_dir.to_string_lossy()
@@ -271,8 +466,109 @@ The Git hooks above may have been installed to an unexpected global location.",
     Ok(())
 }
 
+/// The result of calling `remove_hook`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RemoveHookResult {
+    /// There was no hook installed by us.
+    NotInstalled,
+
+    /// Our hook was deleted.
+    Removed,
+
+    /// The hook contained other contents besides ours, so only our section of
+    /// it was removed.
+    Stripped,
+
+    /// The hook which existed before we installed ours was restored.
+    Restored,
+}
+
+/// Remove the lines between (and including) the update markers from the hook
+/// contents.
+fn remove_between_lines(lines: &str) -> String {
+    let mut new_lines = String::new();
+    let mut is_ignoring_lines = false;
+    for line in lines.lines() {
+        if line == UPDATE_MARKER_START {
+            is_ignoring_lines = true;
+        } else if line == UPDATE_MARKER_END {
+            is_ignoring_lines = false;
+        } else if !is_ignoring_lines {
+            new_lines.push_str(line);
+            new_lines.push('\n');
+        }
+    }
+    new_lines
+}
+
+/// Remove a hook that a previous `git branchless init` may have installed,
+/// restoring the pre-existing hook if there was one. Hooks which weren't
+/// installed by us are left alone.
 #[instrument]
-fn uninstall_hooks(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<()> {
+fn remove_hook(
+    effects: &Effects,
+    repo: &Repo,
+    hooks_dir: &Path,
+    hook_type: &str,
+) -> eyre::Result<RemoveHookResult> {
+    match determine_hook_path(repo, hooks_dir, hook_type)? {
+        Hook::RegularHook { path } => {
+            let original_hook_path = get_original_hook_path(&path);
+            if original_hook_path.exists() {
+                std::fs::rename(&original_hook_path, &path).wrap_err_with(|| {
+                    format!("Restoring original hook {original_hook_path:?} to {path:?}")
+                })?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Restored original {hook_type} hook"
+                )?;
+                return Ok(RemoveHookResult::Restored);
+            }
+
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(_) => return Ok(RemoveHookResult::NotInstalled),
+            };
+            if !contents.lines().any(|line| line == UPDATE_MARKER_START) {
+                return Ok(RemoveHookResult::NotInstalled);
+            }
+
+            let remaining_contents = remove_between_lines(&contents);
+            let has_other_contents = remaining_contents
+                .lines()
+                .any(|line| !line.trim().is_empty() && line != SHEBANG);
+            if has_other_contents {
+                // Write the file in place so that its permissions are
+                // preserved.
+                std::fs::write(&path, remaining_contents)
+                    .wrap_err_with(|| format!("Updating hook {path:?}"))?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Removed git-branchless section from existing {hook_type} hook"
+                )?;
+                Ok(RemoveHookResult::Stripped)
+            } else {
+                std::fs::remove_file(&path).wrap_err_with(|| format!("Removing hook {path:?}"))?;
+                Ok(RemoveHookResult::Removed)
+            }
+        }
+        Hook::MultiHook { path } => {
+            if path.exists() {
+                std::fs::remove_file(&path).wrap_err_with(|| format!("Removing hook {path:?}"))?;
+                Ok(RemoveHookResult::Removed)
+            } else {
+                Ok(RemoveHookResult::NotInstalled)
+            }
+        }
+    }
+}
+
+#[instrument]
+fn uninstall_hooks(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<Vec<RemoveHookResult>> {
     writeln!(
         effects.get_output_stream(),
         "Uninstalling hooks: {}",
@@ -282,17 +578,102 @@ fn uninstall_hooks(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) ->
             .join(", ")
     )?;
     let hooks_dir = get_hooks_dir(git_run_info, repo, None)?;
+    let mut results = Vec::new();
     for (hook_type, _hook_script) in ALL_HOOKS {
-        install_hook(
-            repo,
-            &hooks_dir,
-            hook_type,
-            r#"
-# This hook has been uninstalled.
-# Run `git branchless init` to reinstall.
-"#,
+        results.push(remove_hook(effects, repo, &hooks_dir, hook_type)?);
+    }
+    Ok(results)
+}
+
+/// Check whether our hooks are installed in the hooks directory that Git will
+/// actually use, and warn the user if not.
+///
+/// This commonly happens when `core.hooksPath` is set (such as by a hook
+/// manager) after `git branchless init` was run, in which case the hooks
+/// installed into the old location never run, and commits, checkouts, and
+/// branch updates silently stop being recorded in the event log.
+#[instrument]
+pub fn check_hooks_installed(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<()> {
+    let hooks = get_applicable_hooks(
+        repo,
+        get_reference_transaction_hook_enabled(repo)?,
+        get_pre_push_hook_enabled(repo)?,
+    );
+    let parent_repo = repo.open_worktree_parent_repo()?;
+    let repo = parent_repo.as_ref().unwrap_or(repo);
+    if !repo.get_config_path().exists()
+        || !get_hint_enabled(effects, repo, Hint::HooksNotInstalled)?
+    {
+        return Ok(());
+    }
+
+    // Only shell out to resolve `core.hooksPath` when it's set at all, since
+    // this check runs on every command invocation.
+    let default_hooks_dir = get_default_hooks_dir(repo);
+    let hooks_dir = match repo
+        .get_readonly_config()?
+        .get::<String, _>("core.hooksPath")?
+    {
+        Some(_) => get_hooks_dir(git_run_info, repo, None)?,
+        None => default_hooks_dir.clone(),
+    };
+    let hooks_dir = if hooks_dir.is_relative() {
+        match repo.get_working_copy_path() {
+            Some(working_copy_path) => working_copy_path.join(hooks_dir),
+            None => repo.get_path().join(hooks_dir),
+        }
+    } else {
+        hooks_dir
+    };
+
+    let mut missing_hook_types = Vec::new();
+    for (hook_type, _hook_script) in hooks {
+        let is_installed = match determine_hook_path(repo, &hooks_dir, hook_type)? {
+            Hook::RegularHook { path } => match std::fs::read_to_string(&path) {
+                Ok(contents) => contents.contains(UPDATE_MARKER_START),
+                Err(_) => false,
+            },
+            Hook::MultiHook { path } => path.exists(),
+        };
+        if !is_installed {
+            missing_hook_types.push(hook_type);
+        }
+    }
+    if missing_hook_types.is_empty() {
+        return Ok(());
+    }
+
+    let hint = effects.get_glyphs().render(get_hint_string(effects))?;
+    let mut message = String::new();
+    writeln!(
+        message,
+        "{}: the following git-branchless hooks are not installed in {}: {}",
+        style("Warning").yellow().bold(),
+        hooks_dir.to_string_lossy(),
+        missing_hook_types.join(", "),
+    )?;
+    writeln!(
+        message,
+        "Commits, checkouts, and branch updates will not be recorded until they are installed."
+    )?;
+    writeln!(message, "{hint}: to install them, run: git branchless init")?;
+    if hooks_dir != default_hooks_dir {
+        writeln!(
+            message,
+            "{hint}: core.hooksPath is set to {}; if it is managed by a hook manager, configure it to run `git branchless hook <hook-type> \"$@\"` instead",
+            hooks_dir.to_string_lossy(),
         )?;
     }
+    writeln!(
+        message,
+        "{hint}: disable this hint by running: git config --global {} false",
+        Hint::HooksNotInstalled.get_config_key(),
+    )?;
+    effects.get_error_stream().write_str(&message)?;
     Ok(())
 }
 
@@ -319,7 +700,7 @@ fn install_alias(
     config: &mut Config,
     default_config: &Config,
     from: &str,
-    to: &str,
+    alias: String,
 ) -> eyre::Result<()> {
     let alias_key = format!("alias.{from}");
 
@@ -329,25 +710,35 @@ fn install_alias(
     }
 
     let default_alias: Option<String> = default_config.get(&alias_key)?;
-    if default_alias.is_some() {
+    if let Some(default_alias) = default_alias {
         writeln!(
             effects.get_output_stream(),
-            "Alias {from} already installed, skipping"
+            "Alias {from} already installed, skipping (it is set to: {default_alias})"
         )?;
         return Ok(());
     }
 
-    let alias = if should_use_wrapped_command_alias() {
-        format!("branchless-{to}")
-    } else {
-        format!("branchless {to}")
-    };
     config.set(&alias_key, alias)?;
     Ok(())
 }
 
 #[instrument]
 fn detect_main_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
+    // If the repository was cloned, then the remote's `HEAD` is the most
+    // reliable indicator of the main branch.
+    if let Some(reference) = repo.find_reference(&"refs/remotes/origin/HEAD".into())? {
+        let reference_info = repo.resolve_reference(&reference)?;
+        if let Some(branch_name) = reference_info
+            .reference_name
+            .as_ref()
+            .and_then(|name| name.as_str().strip_prefix("refs/remotes/origin/"))
+        {
+            if repo.find_branch(branch_name, BranchType::Local)?.is_some() {
+                return Ok(Some(branch_name.to_string()));
+            }
+        }
+    }
+
     if let Some(default_branch_name) = get_default_branch_name(repo)? {
         if repo
             .find_branch(&default_branch_name, BranchType::Local)?
@@ -373,6 +764,40 @@ fn detect_main_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
     Ok(None)
 }
 
+/// Determine which of `ALL_ALIASES` to install, based on the command-line
+/// arguments and configuration. Returns `Err` with the unknown alias name if
+/// one of the requested aliases doesn't exist.
+#[instrument]
+fn select_aliases(
+    repo: &Repo,
+    aliases: &[String],
+    no_aliases: bool,
+) -> eyre::Result<Result<Vec<&'static str>, String>> {
+    let requested_aliases = if no_aliases {
+        Some(Vec::new())
+    } else if !aliases.is_empty() {
+        Some(aliases.to_vec())
+    } else {
+        get_init_aliases(repo)?
+    };
+    let requested_aliases = match requested_aliases {
+        Some(requested_aliases) => requested_aliases,
+        None => return Ok(Ok(ALL_ALIASES.iter().map(|(from, _to)| *from).collect())),
+    };
+
+    let mut result = Vec::new();
+    for requested_alias in requested_aliases {
+        match ALL_ALIASES
+            .iter()
+            .find(|(from, _to)| *from == requested_alias)
+        {
+            Some((from, _to)) => result.push(*from),
+            None => return Ok(Err(requested_alias)),
+        }
+    }
+    Ok(Ok(result))
+}
+
 #[instrument]
 fn install_aliases(
     effects: &Effects,
@@ -380,9 +805,55 @@ fn install_aliases(
     config: &mut Config,
     default_config: &Config,
     git_run_info: &GitRunInfo,
+    selected_aliases: &[&str],
+    sapling_aliases: bool,
 ) -> eyre::Result<()> {
+    // User-defined subcommand aliases take precedence over our own aliases
+    // with the same name.
+    let subcommand_aliases = get_subcommand_aliases(repo)?;
     for (from, to) in ALL_ALIASES {
-        install_alias(effects, repo, config, default_config, from, to)?;
+        if subcommand_aliases.iter().any(|(name, _)| name == from) {
+            continue;
+        }
+        if !selected_aliases.contains(from) {
+            // Remove the alias in case it was installed by a previous
+            // invocation with a different set of aliases.
+            let alias_key = format!("alias.{from}");
+            let existing_alias: Option<String> = config.get(&alias_key)?;
+            if existing_alias.is_some() {
+                config.remove(&alias_key)?;
+            }
+            continue;
+        }
+        let alias = if should_use_wrapped_command_alias() {
+            format!("branchless-{to}")
+        } else {
+            format!("branchless {to}")
+        };
+        install_alias(effects, repo, config, default_config, from, alias)?;
+    }
+    for (from, to) in SAPLING_ALIASES {
+        if subcommand_aliases.iter().any(|(name, _)| name == from) {
+            continue;
+        }
+        // The targets may include arguments, so always use the `branchless
+        // <subcommand>` form.
+        let alias = format!("branchless {to}");
+        if !sapling_aliases {
+            // Only remove the alias if we installed it, so that the user's
+            // own aliases with these names are kept.
+            let alias_key = format!("alias.{from}");
+            let existing_alias: Option<String> = config.get(&alias_key)?;
+            if existing_alias.as_ref() == Some(&alias) {
+                config.remove(&alias_key)?;
+            }
+            continue;
+        }
+        install_alias(effects, repo, config, default_config, from, alias)?;
+    }
+    for (name, _) in subcommand_aliases {
+        let alias = format!("branchless {name}");
+        install_alias(effects, repo, config, default_config, &name, alias)?;
     }
 
     let version_str = git_run_info
@@ -517,7 +988,10 @@ fn set_configs(
 
     config.set("branchless.core.mainBranch", main_branch_name)?;
     config.set("advice.detachedHead", false)?;
-    config.set("log.excludeDecoration", "refs/branchless/*")?;
+    config.set("log.excludeDecoration", format!("{KEEP_REFS_PREFIX}*"))?;
+    // Don't advertise the references which keep commits alive to clients
+    // fetching from or pushing to this repository.
+    config.set("transfer.hideRefs", KEEP_REFS_PREFIX)?;
 
     Ok(())
 }
@@ -572,6 +1046,7 @@ fn create_isolated_config(
 #[instrument]
 fn delete_isolated_config(
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     repo: &Repo,
     mut parent_config: Config,
 ) -> eyre::Result<()> {
@@ -581,6 +1056,26 @@ fn delete_isolated_config(
         repo.get_config_path().to_string_lossy()
     )?;
     parent_config.remove_multivar("include.path", INCLUDE_PATH_REGEX)?;
+
+    // Removing the last value leaves behind an empty `[include]` section
+    // header, so remove the section too unless it has other values.
+    let run_config = |args: &[&str]| {
+        git_run_info.run_silent(
+            repo,
+            None,
+            args,
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )
+    };
+    let remaining_includes = run_config(&["config", "--local", "--get-regexp", r"^include\."])?;
+    if !remaining_includes.exit_code.is_success() {
+        let _ignored: GitRunResult =
+            run_config(&["config", "--local", "--remove-section", "include"])?;
+    }
+
     let result = match std::fs::remove_file(repo.get_config_path()) {
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
             writeln!(
@@ -595,31 +1090,165 @@ fn delete_isolated_config(
     Ok(())
 }
 
+/// Record events for the commits of the existing local branches and `HEAD`,
+/// so that the first smartlog shows the work that was in progress before
+/// `git-branchless` was installed, and so that it stays visible if those
+/// branches are deleted later. Public commits, and commits and references
+/// which were already recorded (such as by a previous `init`), are skipped.
+#[instrument]
+fn record_existing_commits(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    dag: &Dag,
+) -> eyre::Result<()> {
+    let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+    let mut references: Vec<(ReferenceName, NonZeroOid)> = Vec::new();
+    for branch in repo.get_all_local_branches()? {
+        let reference_name = branch.get_reference_name()?;
+        if reference_name == main_branch_reference_name {
+            continue;
+        }
+        if let Some(branch_oid) = branch.get_oid()? {
+            references.push((reference_name, branch_oid));
+        }
+    }
+    if let Some(head_oid) = repo.get_head_info()?.oid {
+        references.push((repo.get_head_event_reference_name(), head_oid));
+    }
+
+    let observed_oids = event_replayer.get_cursor_oids(event_replayer.make_default_cursor());
+    let latest_ref_locations = event_replayer.get_latest_ref_locations();
+    let mut commit_oids: Vec<NonZeroOid> = Vec::new();
+    let mut ref_updates: Vec<(ReferenceName, NonZeroOid)> = Vec::new();
+    for (reference_name, oid) in references {
+        if dag.is_public_commit(oid)? {
+            continue;
+        }
+        if !observed_oids.contains(&oid) && !commit_oids.contains(&oid) {
+            commit_oids.push(oid);
+        }
+        if latest_ref_locations.get(&reference_name) != Some(&oid) {
+            ref_updates.push((reference_name, oid));
+        }
+    }
+    if commit_oids.is_empty() && ref_updates.is_empty() {
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "init")?;
+    let mut events = Vec::new();
+    for commit_oid in commit_oids.iter().copied() {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        mark_commit_reachable(repo, commit_oid)
+            .wrap_err("Marking commit as reachable for GC purposes")?;
+        // As in the `post-commit` hook, use the commit time, rather than the
+        // current time, for the commit event.
+        let timestamp = commit
+            .get_time()
+            .to_system_time()?
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs_f64();
+        events.push(Event::CommitEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        });
+    }
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    for (ref_name, oid) in ref_updates {
+        let old_oid = latest_ref_locations.get(&ref_name).copied().into();
+        events.push(Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid: oid.into(),
+            message: None,
+        });
+    }
+    event_log_db.add_events(events)?;
+
+    if !commit_oids.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Recorded {} from existing branches as visible",
+            Pluralize {
+                determiner: None,
+                amount: commit_oids.len(),
+                unit: ("commit", "commits"),
+            }
+        )?;
+    }
+    Ok(())
+}
+
 /// Initialize `git-branchless` in the current repo.
 #[instrument]
 fn command_init(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     main_branch_name: Option<&str>,
+    aliases: &[String],
+    no_aliases: bool,
+    no_reference_transaction_hook: bool,
+    pre_push_hook: bool,
+    sapling_aliases: bool,
 ) -> eyre::Result<ExitCode> {
     let mut in_ = BufReader::new(stdin());
     let repo = Repo::from_current_dir()?;
+    let reference_transaction_hook_enabled =
+        !no_reference_transaction_hook && get_reference_transaction_hook_enabled(&repo)?;
+    let pre_push_hook_enabled = pre_push_hook || get_pre_push_hook_enabled(&repo)?;
+    let sapling_aliases_enabled =
+        !no_aliases && (sapling_aliases || get_init_sapling_aliases(&repo)?);
+    let hooks = get_applicable_hooks(
+        &repo,
+        reference_transaction_hook_enabled,
+        pre_push_hook_enabled,
+    );
     let mut repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
 
+    let selected_aliases = match select_aliases(&repo, aliases, no_aliases)? {
+        Ok(selected_aliases) => selected_aliases,
+        Err(unknown_alias) => {
+            writeln!(
+                effects.get_output_stream(),
+                "Unknown alias: {unknown_alias}\nThe available aliases are: {}",
+                ALL_ALIASES.iter().map(|(from, _to)| from).join(", ")
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
     let default_config = Config::open_default()?;
     let readonly_config = repo.get_readonly_config()?;
     let mut config = create_isolated_config(effects, &repo, readonly_config.into_config())?;
 
     set_configs(&mut in_, effects, &repo, &mut config, main_branch_name)?;
-    install_hooks(effects, git_run_info, &repo)?;
+    if no_reference_transaction_hook {
+        config.set(REFERENCE_TRANSACTION_HOOK_CONFIG_KEY, false)?;
+    }
+    if pre_push_hook {
+        config.set(PRE_PUSH_HOOK_CONFIG_KEY, true)?;
+    }
+    if sapling_aliases {
+        config.set(SAPLING_ALIASES_CONFIG_KEY, true)?;
+    }
+    install_hooks(effects, git_run_info, &repo, &hooks)?;
     install_aliases(
         effects,
         &mut repo,
         &mut config,
         &default_config,
         git_run_info,
+        &selected_aliases,
+        sapling_aliases_enabled,
     )?;
     install_man_pages(effects, &repo, &mut config)?;
+    migrate_legacy_keep_references(&repo)?;
 
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
@@ -629,13 +1258,14 @@ fn command_init(
     if let Ok(references_snapshot) = repo.get_references_snapshot() {
         let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
         let event_cursor = event_replayer.make_default_cursor();
-        Dag::open_and_sync(
+        let dag = Dag::open_and_sync(
             effects,
             &repo,
             &event_replayer,
             event_cursor,
             &references_snapshot,
         )?;
+        record_existing_commits(effects, &repo, &event_log_db, &event_replayer, &dag)?;
     }
 
     writeln!(
@@ -654,13 +1284,127 @@ fn command_init(
     Ok(ExitCode(0))
 }
 
+/// Remove a file or directory if it exists, returning whether it existed.
+fn remove_path_if_exists(path: &Path) -> eyre::Result<bool> {
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err).wrap_err_with(|| format!("Removing {path:?}")),
+    }
+}
+
+/// Delete the event log, the cached commit graph, and the references which
+/// keep commits alive. The user-written TOML config is left alone.
+#[instrument]
+fn purge_repo_data(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+    let mut num_references_deleted = 0;
+    for mut reference in repo.get_all_references()? {
+        if is_gc_ref(&reference.get_name()?) {
+            reference.delete()?;
+            num_references_deleted += 1;
+        }
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Deleted {} which kept commits alive",
+        Pluralize {
+            determiner: None,
+            amount: num_references_deleted,
+            unit: ("reference", "references"),
+        }
+    )?;
+
+    let db_path = repo.get_db_path();
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let mut path = db_path.clone().into_os_string();
+        path.push(suffix);
+        remove_path_if_exists(Path::new(&path))?;
+    }
+    remove_path_if_exists(&get_ref_update_spool_dir_path(&db_path))?;
+    writeln!(
+        effects.get_output_stream(),
+        "Deleted event log: {}",
+        db_path.to_string_lossy()
+    )?;
+
+    remove_path_if_exists(&repo.get_dag_dir())?;
+    remove_path_if_exists(&repo.get_dag_shallow_commits_path())?;
+    remove_path_if_exists(&repo.get_tempfile_dir())?;
+
+    // Only succeeds if nothing else (such as the TOML config) is left.
+    let _ignored: std::io::Result<()> = std::fs::remove_dir(repo.get_branchless_dir());
+    Ok(())
+}
+
 /// Uninstall `git-branchless` in the current repo.
 #[instrument]
-fn command_uninstall(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
+fn command_uninstall(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    purge: bool,
+) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
     let readonly_config = repo.get_readonly_config().wrap_err("Getting repo config")?;
-    delete_isolated_config(effects, &repo, readonly_config.into_config())?;
-    uninstall_hooks(effects, git_run_info, &repo)?;
+    delete_isolated_config(effects, git_run_info, &repo, readonly_config.into_config())?;
+    let hook_results = uninstall_hooks(effects, git_run_info, &repo)?;
+    let hook_summary = [
+        (RemoveHookResult::Removed, "removed"),
+        (RemoveHookResult::Restored, "restored"),
+        (RemoveHookResult::Stripped, "updated"),
+    ]
+    .into_iter()
+    .filter_map(|(result, verb)| {
+        let amount = hook_results
+            .iter()
+            .filter(|hook_result| **hook_result == result)
+            .count();
+        if amount == 0 {
+            None
+        } else {
+            let hooks = Pluralize {
+                determiner: None,
+                amount,
+                unit: ("hook", "hooks"),
+            };
+            Some(format!("{verb} {hooks}"))
+        }
+    })
+    .join(", ");
+    if hook_summary.is_empty() {
+        writeln!(effects.get_output_stream(), "No hooks were installed")?;
+    } else {
+        writeln!(effects.get_output_stream(), "Hooks: {hook_summary}")?;
+    }
+
+    let man_dir = repo.get_man_dir();
+    if remove_path_if_exists(&man_dir)? {
+        writeln!(
+            effects.get_output_stream(),
+            "Removed man pages: {}",
+            man_dir.to_string_lossy()
+        )?;
+    }
+
+    if purge {
+        purge_repo_data(effects, &repo)?;
+    }
+
+    if !purge {
+        writeln!(
+            effects.get_output_stream(),
+            "Kept event log: {}\nTo delete it as well, run: git branchless init --uninstall --purge",
+            repo.get_db_path().to_string_lossy()
+        )?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Successfully uninstalled git-branchless."
+    )?;
     Ok(ExitCode(0))
 }
 
@@ -674,13 +1418,34 @@ pub fn command_main(ctx: CommandContext, args: InitArgs) -> eyre::Result<ExitCod
     match args {
         InitArgs {
             uninstall: false,
+            purge: _,
             main_branch_name,
-        } => command_init(&effects, &git_run_info, main_branch_name.as_deref()),
+            aliases,
+            no_aliases,
+            no_reference_transaction_hook,
+            pre_push_hook,
+            sapling_aliases,
+        } => command_init(
+            &effects,
+            &git_run_info,
+            main_branch_name.as_deref(),
+            &aliases,
+            no_aliases,
+            no_reference_transaction_hook,
+            pre_push_hook,
+            sapling_aliases,
+        ),
 
         InitArgs {
             uninstall: true,
+            purge,
             main_branch_name: _,
-        } => command_uninstall(&effects, &git_run_info),
+            aliases: _,
+            no_aliases: _,
+            no_reference_transaction_hook: _,
+            pre_push_hook: _,
+            sapling_aliases: _,
+        } => command_uninstall(&effects, &git_run_info, purge),
     }
 }
 
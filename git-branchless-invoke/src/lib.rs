@@ -13,21 +13,32 @@
 )]
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
+mod trace_summary;
+
 use std::any::Any;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::OsString;
 use std::fmt::Write;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
-use clap::{CommandFactory, FromArgMatches, Parser};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use cursive_core::theme::BaseColor;
 use cursive_core::utils::markup::StyledString;
 use eyre::Context;
 use git_branchless_opts::{ColorSetting, GlobalArgs};
-use lib::core::config::env_vars::get_path_to_git;
+use lib::core::config::env_vars::{
+    get_path_to_git, GIT_BRANCHLESS_ASSUME_YES, GIT_BRANCHLESS_COLOR, GIT_BRANCHLESS_DETERMINISTIC,
+    GIT_BRANCHLESS_PAGINATE, GIT_BRANCHLESS_PLAIN, GIT_BRANCHLESS_TRACE, GIT_BRANCHLESS_VERBOSITY,
+};
+use lib::core::config::{
+    get_deterministic, get_inherited_verbosity, get_locale_dir, get_plain_output,
+    get_subcommand_aliases, get_theme, Verbosity,
+};
 use lib::core::effects::Effects;
-use lib::core::formatting::Glyphs;
+use lib::core::formatting::{ColorDepth, Glyphs};
+use lib::core::i18n::{detect_locale, Messages};
 use lib::git::GitRunInfo;
 use lib::git::{Repo, RepoError};
 use lib::util::ExitCode;
@@ -39,6 +50,8 @@ use tracing_subscriber::fmt as tracing_fmt;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
+use crate::trace_summary::make_trace_summary_layer;
+
 /// Shared context for all commands.
 #[derive(Clone, Debug)]
 pub struct CommandContext {
@@ -51,11 +64,18 @@ pub struct CommandContext {
 
 #[must_use = "This function returns a guard object to flush traces. Dropping it immediately is probably incorrect. Make sure that the returned value lives until tracing has finished."]
 #[instrument]
-fn install_tracing(effects: Effects) -> eyre::Result<impl Drop> {
+fn install_tracing(
+    effects: Effects,
+    trace: bool,
+    profile: Option<PathBuf>,
+) -> eyre::Result<impl Drop> {
     let env_filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::WARN.into())
         .from_env_lossy();
-    let fmt_layer = tracing_fmt::layer().with_writer(move || effects.clone().get_error_stream());
+    let fmt_layer = {
+        let effects = effects.clone();
+        tracing_fmt::layer().with_writer(move || effects.clone().get_error_stream())
+    };
 
     let (profile_layer, flush_guard): (_, Box<dyn Any>) = {
         // We may invoke a hook that calls back into `git-branchless`. In that case,
@@ -72,8 +92,9 @@ fn install_tracing(effects: Effects) -> eyre::Result<impl Drop> {
             Ok(_) | Err(_) => false,
         };
 
-        let filename = match std::env::var("RUST_PROFILE") {
-            Ok(value) if value == "1" || value == "true" => {
+        let filename = match (profile, std::env::var("RUST_PROFILE")) {
+            (Some(profile), _) => Some(profile),
+            (None, Ok(value)) if value == "1" || value == "true" => {
                 let filename = format!(
                     "trace-{}.json-{}",
                     SystemTime::now()
@@ -81,10 +102,12 @@ fn install_tracing(effects: Effects) -> eyre::Result<impl Drop> {
                         .as_secs(),
                     nesting_level,
                 );
-                Some(filename)
+                Some(PathBuf::from(filename))
             }
-            Ok(value) if !value.is_empty() => Some(format!("{value}-{nesting_level}")),
-            Ok(_) | Err(_) => None,
+            (None, Ok(value)) if !value.is_empty() => {
+                Some(PathBuf::from(format!("{value}-{nesting_level}")))
+            }
+            (None, Ok(_) | Err(_)) => None,
         };
 
         match filename {
@@ -102,13 +125,27 @@ fn install_tracing(effects: Effects) -> eyre::Result<impl Drop> {
         }
     };
 
+    let trace = trace
+        || match std::env::var(GIT_BRANCHLESS_TRACE) {
+            Ok(value) => !(value.is_empty() || value == "0" || value == "false"),
+            Err(_) => false,
+        };
+    let (summary_layer, summary_guard) = if trace {
+        let (layer, guard) = make_trace_summary_layer(effects);
+        (Some(layer), Some(guard))
+    } else {
+        (None, None)
+    };
+
     tracing_subscriber::registry()
         .with(ErrorLayer::default())
         .with(fmt_layer.with_filter(env_filter))
         .with(profile_layer)
+        .with(summary_layer)
         .try_init()?;
 
-    Ok(flush_guard)
+    let guard: Box<dyn Any> = Box::new((flush_guard, summary_guard));
+    Ok(guard)
 }
 
 #[instrument]
@@ -122,6 +159,77 @@ fn install_libgit2_tracing() {
     }
 }
 
+/// Style output with the theme configured for the current repository, if any.
+/// An invalid theme is reported, but doesn't prevent the command from running.
+#[instrument]
+fn apply_configured_theme(effects: Effects) -> eyre::Result<Effects> {
+    let repo = match Repo::from_current_dir() {
+        Ok(repo) => repo,
+        Err(_) => return Ok(effects),
+    };
+    match get_theme(&repo) {
+        Ok(theme) => Ok(effects.with_theme(theme)),
+        Err(err) => {
+            writeln!(
+                effects.get_error_stream(),
+                "{}: {err}",
+                effects
+                    .get_glyphs()
+                    .render(StyledString::styled("Warning", BaseColor::Yellow.light()))?,
+            )?;
+            Ok(effects)
+        }
+    }
+}
+
+/// Load translations of user-facing messages for the user's locale, if a
+/// directory of translations is configured.
+#[instrument]
+fn apply_configured_locale(effects: Effects) -> eyre::Result<Effects> {
+    let locale = match detect_locale() {
+        Some(locale) => locale,
+        None => return Ok(effects),
+    };
+    let locale_dir = match Repo::from_current_dir() {
+        Ok(repo) => match get_locale_dir(&repo)? {
+            Some(locale_dir) => locale_dir,
+            None => return Ok(effects),
+        },
+        Err(_) => return Ok(effects),
+    };
+    match Messages::load(&locale_dir, &locale) {
+        Ok(messages) => Ok(effects.with_messages(messages)),
+        Err(err) => {
+            writeln!(
+                effects.get_error_stream(),
+                "{}: {err}",
+                effects
+                    .get_glyphs()
+                    .render(StyledString::styled("Warning", BaseColor::Yellow.light()))?,
+            )?;
+            Ok(effects)
+        }
+    }
+}
+
+/// Switch to plain output if requested with `--plain` (and thus
+/// `$GIT_BRANCHLESS_PLAIN`) or the `branchless.core.plain` config setting.
+#[instrument]
+fn apply_plain_mode(effects: Effects) -> eyre::Result<Effects> {
+    let plain = match std::env::var(GIT_BRANCHLESS_PLAIN) {
+        Ok(value) if !value.is_empty() => !(value == "0" || value == "false"),
+        _ => match Repo::from_current_dir() {
+            Ok(repo) => get_plain_output(&repo)?,
+            Err(_) => false,
+        },
+    };
+    if plain {
+        Ok(effects.plain())
+    } else {
+        Ok(effects)
+    }
+}
+
 #[instrument]
 fn check_unsupported_config_options(effects: &Effects) -> eyre::Result<Option<ExitCode>> {
     let _repo = match Repo::from_current_dir() {
@@ -165,14 +273,34 @@ pub fn do_main_and_drop_locals<T: Parser>(
     f: impl Fn(CommandContext, T) -> eyre::Result<ExitCode>,
     args: Vec<OsString>,
 ) -> eyre::Result<i32> {
-    let command = GlobalArgs::command();
+    // Collect the subcommand and its arguments into a catch-all positional
+    // argument, so that global arguments are still found when they're passed
+    // after the subcommand.
+    let command = GlobalArgs::command().arg(
+        clap::Arg::new("rest")
+            .num_args(0..)
+            .action(clap::ArgAction::Append)
+            .value_parser(clap::value_parser!(OsString)),
+    );
     let matches = command.ignore_errors(true).get_matches_from(&args);
     let GlobalArgs {
         working_directory,
+        git_dir,
+        work_tree,
         color,
+        output: _,
+        paginate,
+        no_pager,
+        plain,
+        yes,
+        // Read from the full parse below.
+        quiet: _,
+        verbose: _,
+        deterministic: _,
+        trace,
+        profile,
     } = GlobalArgs::from_arg_matches(&matches)
         .map_err(|err| eyre::eyre!("Could not parse global arguments: {err}"))?;
-    let command_args = T::parse_from(args);
 
     if let Some(working_directory) = working_directory {
         std::env::set_current_dir(&working_directory).wrap_err_with(|| {
@@ -183,29 +311,87 @@ pub fn do_main_and_drop_locals<T: Parser>(
         })?;
     }
 
-    let path_to_git = get_path_to_git().unwrap_or_else(|_| PathBuf::from("git"));
-    let path_to_git = PathBuf::from(&path_to_git);
-    let git_run_info = GitRunInfo {
-        path_to_git,
-        working_directory: std::env::current_dir()?,
-        env: std::env::vars_os().collect(),
-    };
+    // Resolve the paths relative to the working directory set above, as Git
+    // does, and pass them via the environment so that both repository
+    // discovery and any `git` subprocesses (and thus hooks) use them.
+    for (path, env_var) in [(git_dir, "GIT_DIR"), (work_tree, "GIT_WORK_TREE")] {
+        if let Some(path) = path {
+            std::env::set_var(env_var, std::env::current_dir()?.join(path));
+        }
+    }
+    if paginate == Some(true) {
+        std::env::set_var(GIT_BRANCHLESS_PAGINATE, "1");
+    }
+    if no_pager == Some(true) {
+        std::env::set_var("GIT_PAGER", "cat");
+    }
+    if plain == Some(true) {
+        std::env::set_var(GIT_BRANCHLESS_PLAIN, "1");
+    }
+    if yes == Some(true) {
+        std::env::set_var(GIT_BRANCHLESS_ASSUME_YES, "1");
+    }
 
     let color = match color {
-        Some(ColorSetting::Always) => Glyphs::pretty(),
+        Some(color) => Some(color),
+        None => match std::env::var(GIT_BRANCHLESS_COLOR) {
+            Ok(value) if !value.is_empty() => {
+                Some(ColorSetting::from_str(&value, true).map_err(|err| {
+                    eyre::eyre!("Invalid value for {GIT_BRANCHLESS_COLOR}: {err}")
+                })?)
+            }
+            _ => None,
+        },
+    };
+    let color = match color {
+        Some(ColorSetting::Always) => Glyphs::pretty().with_color_depth(ColorDepth::detect()),
         Some(ColorSetting::Never) => Glyphs::text(),
         Some(ColorSetting::Auto) | None => Glyphs::detect(),
     };
     let effects = Effects::new(color);
 
-    let _tracing_guard = install_tracing(effects.clone());
+    // Install tracing before calling any instrumented functions. Otherwise,
+    // this thread caches the lack of a subscriber and never reports any spans.
+    let _tracing_guard = install_tracing(effects.clone(), trace.unwrap_or_default(), profile);
     install_libgit2_tracing();
+    let effects = apply_configured_theme(effects)?;
+    let effects = apply_plain_mode(effects)?;
+    let effects = apply_configured_locale(effects)?;
 
     if let Some(ExitCode(exit_code)) = check_unsupported_config_options(&effects)? {
         let exit_code: i32 = exit_code.try_into()?;
         return Ok(exit_code);
     }
 
+    let args = match expand_subcommand_aliases::<T>(&effects, args)? {
+        Ok(args) => args,
+        Err(ExitCode(exit_code)) => {
+            let exit_code: i32 = exit_code.try_into()?;
+            return Ok(exit_code);
+        }
+    };
+    let mut matches = T::command().get_matches_from(&args);
+    let effects = apply_verbosity(&matches, effects);
+    let effects = apply_deterministic_mode(&matches, effects);
+    let command_args = T::from_arg_matches_mut(&mut matches)
+        .map_err(|err| err.format(&mut T::command()))
+        .unwrap_or_else(|err| err.exit());
+
+    let path_to_git = get_path_to_git().unwrap_or_else(|_| PathBuf::from("git"));
+    let path_to_git = PathBuf::from(&path_to_git);
+    let mut env: HashMap<OsString, OsString> = std::env::vars_os().collect();
+    // Pass the verbosity to the `git-branchless` processes which are invoked
+    // by Git, such as for hooks.
+    env.insert(
+        GIT_BRANCHLESS_VERBOSITY.into(),
+        effects.get_verbosity().get_level().to_string().into(),
+    );
+    let git_run_info = GitRunInfo {
+        path_to_git,
+        working_directory: std::env::current_dir()?,
+        env,
+    };
+
     let ctx = CommandContext {
         effects,
         git_run_info,
@@ -215,6 +401,126 @@ pub fn do_main_and_drop_locals<T: Parser>(
     Ok(exit_code)
 }
 
+/// Set the verbosity of the returned `Effects` according to the `--quiet`
+/// and `--verbose` options, or else the verbosity of the parent
+/// `git-branchless` process, if any.
+///
+/// Unlike the other global arguments, these are read from the full parse of
+/// the arguments, since they're commonly passed after the arguments of the
+/// subcommand (such as `git test run -x 'cargo test' -v`), which stop the
+/// parse of only the global arguments.
+fn apply_verbosity(matches: &ArgMatches, effects: Effects) -> Effects {
+    let quiet = matches.try_get_one::<bool>("quiet").ok().flatten();
+    let verbose = matches.try_get_one::<u8>("verbose").ok().flatten();
+    let verbosity = if quiet == Some(&true) {
+        Verbosity::Quiet
+    } else if let Some(verbose @ 1..) = verbose.copied() {
+        Verbosity::from_level(verbose.into())
+    } else {
+        get_inherited_verbosity().unwrap_or(Verbosity::Normal)
+    };
+    effects.with_verbosity(verbosity)
+}
+
+/// Set [`GIT_BRANCHLESS_DETERMINISTIC`] for this process and its subprocesses
+/// if the `--deterministic` option was passed, which is read from the full
+/// parse of the arguments for the same reason as in [`apply_verbosity`]. If
+/// deterministic mode is enabled, fix the terminal width of `git`
+/// subprocesses and disable progress indicators on the returned `Effects`.
+fn apply_deterministic_mode(matches: &ArgMatches, effects: Effects) -> Effects {
+    let deterministic = matches.try_get_one::<bool>("deterministic").ok().flatten();
+    if deterministic == Some(&true) {
+        std::env::set_var(GIT_BRANCHLESS_DETERMINISTIC, "1");
+    }
+    if !get_deterministic() {
+        return effects;
+    }
+    std::env::set_var("COLUMNS", "80");
+    effects.without_progress()
+}
+
+/// Find the index of the subcommand in the given arguments, skipping over the
+/// global arguments which may precede it.
+fn find_subcommand_index(args: &[OsString]) -> Option<usize> {
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        let arg = arg.to_str()?;
+        match arg {
+            "-C" | "--color" | "--git-dir" | "--work-tree" | "--output" | "--profile" => i += 2,
+            arg if arg.starts_with('-') => i += 1,
+            _ => return Some(i),
+        }
+    }
+    None
+}
+
+/// Expand any user-defined subcommand aliases (see
+/// `lib::core::config::get_subcommand_aliases`) in the arguments. Built-in
+/// subcommands always take precedence over aliases with the same name.
+#[instrument]
+fn expand_subcommand_aliases<T: CommandFactory>(
+    effects: &Effects,
+    mut args: Vec<OsString>,
+) -> eyre::Result<Result<Vec<OsString>, ExitCode>> {
+    let command = T::command();
+    let is_builtin_subcommand = |args: &[OsString]| match find_subcommand_index(args) {
+        Some(index) => match args[index].to_str() {
+            Some(name) => command.find_subcommand(name).is_some(),
+            None => true,
+        },
+        None => true,
+    };
+    // Avoid opening the repository in the common case.
+    if !command.has_subcommands() || is_builtin_subcommand(&args) {
+        return Ok(Ok(args));
+    }
+    let aliases = match Repo::from_current_dir() {
+        Ok(repo) => get_subcommand_aliases(&repo)?,
+        Err(_) => return Ok(Ok(args)),
+    };
+    if aliases.is_empty() {
+        return Ok(Ok(args));
+    }
+
+    let mut expanded_names = Vec::new();
+    while !is_builtin_subcommand(&args) {
+        let index = match find_subcommand_index(&args) {
+            Some(index) => index,
+            None => break,
+        };
+        let name = match args[index].to_str() {
+            Some(name) => name.to_owned(),
+            None => break,
+        };
+        let expansion = match aliases.iter().find(|(alias_name, _)| alias_name == &name) {
+            Some((_, expansion)) => expansion,
+            None => break,
+        };
+        if expanded_names.contains(&name) {
+            writeln!(
+                effects.get_error_stream(),
+                "The alias {} expands to itself: {} -> {name}",
+                expanded_names[0],
+                expanded_names.join(" -> ")
+            )?;
+            return Ok(Err(ExitCode::USAGE));
+        }
+        let expansion = match shell_words::split(expansion) {
+            Ok(expansion) => expansion,
+            Err(err) => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Could not parse the alias branchless.alias.{name} ({expansion:?}): {err}"
+                )?;
+                return Ok(Err(ExitCode::USAGE));
+            }
+        };
+        args.splice(index..=index, expansion.into_iter().map(OsString::from));
+        expanded_names.push(name);
+    }
+    Ok(Ok(args))
+}
+
 /// Invoke the provided subcommand main function. This should be used in the
 /// `main.rs` file for the subcommand executable. For example:
 ///
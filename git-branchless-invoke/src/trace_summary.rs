@@ -0,0 +1,134 @@
+//! Summarize how much time was spent in each tracing span, for `--trace`.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lib::core::effects::Effects;
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// The timing information tracked for an open span.
+struct SpanTiming {
+    entered_at: Option<Instant>,
+    busy: Duration,
+}
+
+/// The accumulated timing information for all spans with the same name.
+#[derive(Clone, Debug, Default)]
+struct SpanStats {
+    count: usize,
+    total: Duration,
+}
+
+type Stats = Arc<Mutex<HashMap<String, SpanStats>>>;
+
+/// Layer which records how long each span was entered for, aggregated by span
+/// name. Time spent in nested spans is also counted towards their parents.
+pub struct TraceSummaryLayer {
+    stats: Stats,
+}
+
+impl<S> Layer<S> for TraceSummaryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanTiming {
+                entered_at: None,
+                busy: Duration::ZERO,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                timing.entered_at = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                if let Some(entered_at) = timing.entered_at.take() {
+                    timing.busy += entered_at.elapsed();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let busy = match span.extensions().get::<SpanTiming>() {
+            Some(timing) => timing.busy,
+            None => return,
+        };
+        let name = format!("{}::{}", span.metadata().target(), span.metadata().name());
+        if let Ok(mut stats) = self.stats.lock() {
+            let stats = stats.entry(name).or_default();
+            stats.count += 1;
+            stats.total += busy;
+        }
+    }
+}
+
+/// Prints the summary of the recorded spans when dropped, which should happen
+/// once the command has finished.
+pub struct TraceSummaryGuard {
+    effects: Effects,
+    stats: Stats,
+}
+
+impl Drop for TraceSummaryGuard {
+    fn drop(&mut self) {
+        let stats = match self.stats.lock() {
+            Ok(stats) => stats.clone(),
+            Err(_) => return,
+        };
+        let mut stats: Vec<(String, SpanStats)> = stats.into_iter().collect();
+        stats.sort_by(|(lhs_name, lhs), (rhs_name, rhs)| {
+            rhs.total
+                .cmp(&lhs.total)
+                .then_with(|| lhs_name.cmp(rhs_name))
+        });
+
+        let mut stream = self.effects.get_error_stream();
+        let _ignored: std::io::Result<()> = (|| {
+            writeln!(
+                stream,
+                "branchless: time spent in each span (including nested spans):"
+            )?;
+            for (name, SpanStats { count, total }) in stats {
+                writeln!(
+                    stream,
+                    "{:>10.1}ms {:>6}x  {}",
+                    total.as_secs_f64() * 1000.0,
+                    count,
+                    name
+                )?;
+            }
+            stream.flush()
+        })();
+    }
+}
+
+/// Create a layer recording span timings, along with a guard which prints a
+/// summary of them to `effects`'s error stream when dropped.
+pub fn make_trace_summary_layer(effects: Effects) -> (TraceSummaryLayer, TraceSummaryGuard) {
+    let stats: Stats = Default::default();
+    let layer = TraceSummaryLayer {
+        stats: Arc::clone(&stats),
+    };
+    let guard = TraceSummaryGuard { effects, stats };
+    (layer, guard)
+}
@@ -1,16 +1,21 @@
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use branchless::core::dag::{CommitSet, Dag};
 use branchless::core::effects::Effects;
-use branchless::core::eventlog::{EventLogDb, EventReplayer};
+use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
 use branchless::core::repo_ext::RepoExt;
 use branchless::core::rewrite::{
     BuildRebasePlanOptions, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
-use branchless::git::{CherryPickFastOptions, Commit, Diff, Repo};
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use branchless::git::{CherryPickFastOptions, Commit, Diff, GitRunInfo, Repo};
+use branchless::testing::{
+    make_git, GitInitOptions, GitWrapper, SyntheticHistory, SyntheticHistoryOptions,
+    SYNTHETIC_HISTORIES,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 use rayon::ThreadPoolBuilder;
 
 fn get_repo() -> Repo {
@@ -19,6 +24,36 @@ fn get_repo() -> Repo {
     Repo::from_dir(&PathBuf::from(repo_dir)).unwrap()
 }
 
+/// Create a repository with a synthetic history. This requires the `TEST_GIT`
+/// and `TEST_GIT_EXEC_PATH` environment variables to be set, as for the
+/// integration tests.
+fn make_synthetic_repo(options: &SyntheticHistoryOptions) -> (GitWrapper, SyntheticHistory) {
+    let git = make_git().unwrap();
+    git.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        run_branchless_init: false,
+    })
+    .unwrap();
+    let history = git.generate_synthetic_history(options).unwrap();
+    (git, history)
+}
+
+fn open_dag(effects: &Effects, repo: &Repo) -> Dag {
+    let references_snapshot = repo.get_references_snapshot().unwrap();
+    let conn = repo.get_db_conn().unwrap();
+    let event_log_db = EventLogDb::new(&conn).unwrap();
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db).unwrap();
+    let event_cursor = event_replayer.make_default_cursor();
+    Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )
+    .unwrap()
+}
+
 fn nth_parent(commit: Commit, n: usize) -> Commit {
     let mut commit = commit.clone();
     for _i in 0..n {
@@ -55,6 +90,11 @@ fn bench_rebase_plan(c: &mut Criterion) {
         .unwrap();
         let pool = ThreadPoolBuilder::new().build().unwrap();
         let repo_pool = RepoResource::new_pool(&repo).unwrap();
+        let git_run_info = GitRunInfo {
+            path_to_git: PathBuf::from("git"),
+            working_directory: repo.get_working_copy_path().unwrap().to_path_buf(),
+            env: std::env::vars_os().collect(),
+        };
 
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits: true,
@@ -77,7 +117,7 @@ fn bench_rebase_plan(c: &mut Criterion) {
             || builder.clone(),
             |builder| {
                 builder
-                    .build(&effects, &pool, &repo_pool)
+                    .build(&effects, &git_run_info, &pool, &repo_pool)
                     .unwrap()
                     .unwrap()
                     .unwrap()
@@ -163,6 +203,90 @@ fn bench_get_paths_touched_by_commits(c: &mut Criterion) {
     });
 }
 
+fn bench_synthetic_rebase_plan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic-rebase-plan");
+    for (name, options) in SYNTHETIC_HISTORIES {
+        group.bench_function(BenchmarkId::new("RebasePlanBuilder::build", name), |b| {
+            let (git, history) = make_synthetic_repo(options);
+            let repo = git.get_repo().unwrap();
+            let effects = Effects::new_suppress_for_test(Glyphs::text());
+            let dag = open_dag(&effects, &repo);
+            let pool = ThreadPoolBuilder::new().build().unwrap();
+            let repo_pool = RepoResource::new_pool(&repo).unwrap();
+            let git_run_info = git.get_git_run_info();
+
+            // Move every stack onto the tip of the main branch, as `git sync`
+            // would.
+            let main_oid = *history.main_oids.last().unwrap();
+            let stack_root_oids = history
+                .stack_oids
+                .iter()
+                .filter_map(|stack_oids| stack_oids.first().copied())
+                .collect::<Vec<_>>();
+            let build_options = BuildRebasePlanOptions {
+                force_rewrite_public_commits: false,
+                dump_rebase_constraints: false,
+                dump_rebase_plan: false,
+                detect_duplicate_commits_via_patch_id: false,
+            };
+            let permissions = RebasePlanPermissions::verify_rewrite_set(
+                &dag,
+                build_options,
+                &stack_root_oids.iter().copied().collect::<CommitSet>(),
+            )
+            .unwrap()
+            .unwrap();
+            let mut builder = RebasePlanBuilder::new(&dag, permissions);
+            for stack_root_oid in stack_root_oids {
+                builder
+                    .move_subtree(stack_root_oid, vec![main_oid])
+                    .unwrap();
+            }
+            b.iter_batched(
+                || builder.clone(),
+                |builder| {
+                    builder
+                        .build(&effects, &git_run_info, &pool, &repo_pool)
+                        .unwrap()
+                        .unwrap()
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+}
+
+fn bench_synthetic_event_log_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic-event-log");
+    for (name, options) in SYNTHETIC_HISTORIES {
+        group.bench_function(BenchmarkId::new("EventLogDb::add_events", name), |b| {
+            let (git, history) = make_synthetic_repo(options);
+            let repo = git.get_repo().unwrap();
+            let conn = repo.get_db_conn().unwrap();
+            let event_log_db = EventLogDb::new(&conn).unwrap();
+
+            // Record a commit event for each commit in the history, as the
+            // `post-commit` hook would have.
+            b.iter(|| {
+                let event_tx_id = event_log_db
+                    .make_transaction_id(SystemTime::now(), "bench")
+                    .unwrap();
+                let events = history
+                    .main_oids
+                    .iter()
+                    .chain(history.stack_oids.iter().flatten())
+                    .map(|commit_oid| Event::CommitEvent {
+                        timestamp: 0.0,
+                        event_tx_id,
+                        commit_oid: *commit_oid,
+                    })
+                    .collect();
+                event_log_db.add_events(events).unwrap();
+            });
+        });
+    }
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default().sample_size(10);
@@ -171,5 +295,7 @@ criterion_group!(
         bench_diff_fast,
         bench_get_paths_touched_by_commits,
         bench_rebase_plan,
+        bench_synthetic_event_log_writes,
+        bench_synthetic_rebase_plan,
 );
 criterion_main!(benches);
@@ -0,0 +1,241 @@
+//! Persistent storage for named archives of commits.
+//!
+//! An archive is a group of commits (typically one or more stacks) which were
+//! hidden together under a user-provided name, along with the branches which
+//! pointed to them at the time. This lets the user park an abandoned project
+//! and restore it later without having to know the OIDs involved.
+//!
+//! Hiding and unhiding the commits themselves is done via the event log; this
+//! module only records which commits and branches belong to which archive.
+
+use std::collections::HashMap;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::{NonZeroOid, ReferenceName};
+
+/// A named group of commits which were hidden together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Archive {
+    /// The name provided by the user for this archive.
+    pub name: String,
+
+    /// The time at which the archive was created, in seconds since the Unix
+    /// epoch.
+    pub timestamp: f64,
+
+    /// The commits which were hidden as part of this archive.
+    pub commit_oids: Vec<NonZeroOid>,
+
+    /// The branches which were deleted as part of this archive, and the
+    /// commits they pointed to.
+    pub branches: Vec<(ReferenceName, NonZeroOid)>,
+}
+
+/// Stores `Archive`s on disk.
+pub struct ArchiveDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for ArchiveDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<ArchiveDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS archives (
+    name TEXT NOT NULL PRIMARY KEY,
+    timestamp REAL NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `archives` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS archive_commits (
+    name TEXT NOT NULL,
+    commit_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `archive_commits` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS archive_branches (
+    name TEXT NOT NULL,
+    reference_name TEXT NOT NULL,
+    commit_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `archive_branches` table")?;
+
+    Ok(())
+}
+
+impl<'conn> ArchiveDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(ArchiveDb { conn })
+    }
+
+    /// Add the given archive to the database, in a transaction. Fails if an
+    /// archive with the same name already exists.
+    #[instrument]
+    pub fn add_archive(&self, archive: &Archive) -> eyre::Result<()> {
+        let Archive {
+            name,
+            timestamp,
+            commit_oids,
+            branches,
+        } = archive;
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "
+INSERT INTO archives VALUES (:name, :timestamp)
+",
+            rusqlite::named_params! {
+                ":name": name,
+                ":timestamp": timestamp,
+            },
+        )
+        .wrap_err_with(|| format!("Adding archive: {name}"))?;
+        for commit_oid in commit_oids {
+            tx.execute(
+                "
+INSERT INTO archive_commits VALUES (:name, :commit_oid)
+",
+                rusqlite::named_params! {
+                    ":name": name,
+                    ":commit_oid": commit_oid.to_string(),
+                },
+            )?;
+        }
+        for (reference_name, commit_oid) in branches {
+            tx.execute(
+                "
+INSERT INTO archive_branches VALUES (:name, :reference_name, :commit_oid)
+",
+                rusqlite::named_params! {
+                    ":name": name,
+                    ":reference_name": reference_name.as_str(),
+                    ":commit_oid": commit_oid.to_string(),
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the archive with the given name, if any.
+    #[instrument]
+    pub fn get_archive(&self, name: &str) -> eyre::Result<Option<Archive>> {
+        let archives = self.get_archives()?;
+        Ok(archives.into_iter().find(|archive| archive.name == name))
+    }
+
+    /// Get all archives, ordered from oldest to newest.
+    #[instrument]
+    pub fn get_archives(&self) -> eyre::Result<Vec<Archive>> {
+        let mut commit_oids: HashMap<String, Vec<NonZeroOid>> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT name, commit_oid
+FROM archive_commits
+ORDER BY rowid ASC
+",
+            )?;
+            let rows: rusqlite::Result<Vec<(String, String)>> = stmt
+                .query_map(rusqlite::params![], |row| {
+                    Ok((row.get("name")?, row.get("commit_oid")?))
+                })?
+                .collect();
+            for (name, commit_oid) in rows? {
+                commit_oids
+                    .entry(name)
+                    .or_default()
+                    .push(commit_oid.parse()?);
+            }
+        }
+
+        let mut branches: HashMap<String, Vec<(ReferenceName, NonZeroOid)>> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT name, reference_name, commit_oid
+FROM archive_branches
+ORDER BY rowid ASC
+",
+            )?;
+            let rows: rusqlite::Result<Vec<(String, String, String)>> = stmt
+                .query_map(rusqlite::params![], |row| {
+                    Ok((
+                        row.get("name")?,
+                        row.get("reference_name")?,
+                        row.get("commit_oid")?,
+                    ))
+                })?
+                .collect();
+            for (name, reference_name, commit_oid) in rows? {
+                branches
+                    .entry(name)
+                    .or_default()
+                    .push((ReferenceName::from(reference_name), commit_oid.parse()?));
+            }
+        }
+
+        let mut stmt = self.conn.prepare(
+            "
+SELECT name, timestamp
+FROM archives
+ORDER BY timestamp ASC, rowid ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(String, f64)>> = stmt
+            .query_map(rusqlite::params![], |row| {
+                Ok((row.get("name")?, row.get("timestamp")?))
+            })?
+            .collect();
+        let archives = rows?
+            .into_iter()
+            .map(|(name, timestamp)| Archive {
+                commit_oids: commit_oids.remove(&name).unwrap_or_default(),
+                branches: branches.remove(&name).unwrap_or_default(),
+                name,
+                timestamp,
+            })
+            .collect();
+        Ok(archives)
+    }
+
+    /// Remove the archive with the given name from the database, if it
+    /// exists.
+    #[instrument]
+    pub fn remove_archive(&self, name: &str) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for table in ["archives", "archive_commits", "archive_branches"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE name = :name"),
+                rusqlite::named_params! {
+                    ":name": name,
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -8,20 +8,42 @@ use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use eyre::Context;
 use itertools::Itertools;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
-use crate::core::config::get_auto_switch_branches;
+use crate::core::config::{get_auto_switch_branches, get_checkout_in_process, Verbosity};
 use crate::git::{
-    update_index, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
-    Repo, Stage, UpdateIndexCommand, WorkingCopySnapshot,
+    update_index, BranchType, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid,
+    ReferenceName, Repo, Stage, UpdateIndexCommand, WorkingCopySnapshot,
 };
 use crate::util::ExitCode;
 
 use super::config::get_undo_create_snapshots;
-use super::effects::Effects;
+use super::effects::{Effects, OperationType};
 use super::eventlog::{Event, EventLogDb, EventTransactionId};
 use super::repo_ext::{RepoExt, RepoReferencesSnapshot};
 
+/// Check that the repository has a working copy, since the current operation
+/// requires one. If it doesn't (because the repository is bare), print an
+/// error message and return the exit code to exit with.
+#[instrument]
+pub fn check_has_working_copy(
+    effects: &Effects,
+    repo: &Repo,
+) -> eyre::Result<Result<(), ExitCode>> {
+    if repo.get_working_copy_path().is_some() {
+        return Ok(Ok(()));
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        effects.get_glyphs().render(StyledString::styled(
+            "This operation requires a working copy, but this repository is bare.".to_string(),
+            BaseColor::Red.light()
+        ))?
+    )?;
+    Ok(Err(ExitCode(1)))
+}
+
 /// An entity to check out.
 #[derive(Clone, Debug)]
 pub enum CheckoutTarget {
@@ -48,6 +70,7 @@ pub struct CheckOutCommitOptions {
     pub reset: bool,
 
     /// Whether or not to render the smartlog after the checkout has completed.
+    /// The smartlog is never rendered with `--quiet`.
     pub render_smartlog: bool,
 }
 
@@ -119,6 +142,10 @@ pub fn check_out_commit(
         render_smartlog,
     } = options;
 
+    if let Err(exit_code) = check_has_working_copy(effects, repo)? {
+        return Ok(exit_code);
+    }
+
     let (target, oid) = match target {
         None => (None, None),
         Some(CheckoutTarget::Reference(reference_name)) => {
@@ -148,29 +175,40 @@ pub fn check_out_commit(
         }
     }
 
-    let checkout_args = {
-        let mut args = vec![OsStr::new("checkout")];
-        if let Some(target) = &target {
-            args.push(OsStr::new(target.as_str()));
+    let is_checked_out_in_process = match &target {
+        Some(target) if !*reset && additional_args.is_empty() && get_checkout_in_process(repo)? => {
+            try_check_out_in_process(effects, git_run_info, repo, event_tx_id, target)?
         }
-        args.extend(additional_args.iter().map(OsStr::new));
-        args
+        _ => false,
+    };
+    let exit_code = if is_checked_out_in_process {
+        ExitCode(0)
+    } else {
+        let checkout_args = {
+            let mut args = vec![OsStr::new("checkout")];
+            if let Some(target) = &target {
+                args.push(OsStr::new(target.as_str()));
+            }
+            args.extend(additional_args.iter().map(OsStr::new));
+            args
+        };
+        let exit_code = git_run_info.run(effects, Some(event_tx_id), checkout_args.as_slice())?;
+        if !exit_code.is_success() {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                effects.get_glyphs().render(StyledString::styled(
+                    match target {
+                        Some(target) => format!("Failed to check out commit: {target}"),
+                        None => "Failed to check out commit".to_string(),
+                    },
+                    BaseColor::Red.light()
+                ))?
+            )?;
+            return Ok(exit_code);
+        }
+        exit_code
     };
-    let exit_code = git_run_info.run(effects, Some(event_tx_id), checkout_args.as_slice())?;
-    if !exit_code.is_success() {
-        writeln!(
-            effects.get_output_stream(),
-            "{}",
-            effects.get_glyphs().render(StyledString::styled(
-                match target {
-                    Some(target) => format!("Failed to check out commit: {target}"),
-                    None => "Failed to check out commit".to_string(),
-                },
-                BaseColor::Red.light()
-            ))?
-        )?;
-        return Ok(exit_code);
-    }
 
     // Determine if we currently have a snapshot checked out, and, if so,
     // attempt to restore it.
@@ -188,7 +226,7 @@ pub fn check_out_commit(
         }
     }
 
-    if *render_smartlog {
+    if *render_smartlog && effects.get_verbosity() > Verbosity::Quiet {
         let exit_code =
             git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?;
         Ok(exit_code)
@@ -197,6 +235,56 @@ pub fn check_out_commit(
     }
 }
 
+/// Try to check out `target` (a local branch name or a commit) in-process,
+/// rather than by running `git checkout`, as configured by
+/// `branchless.checkout.inProcess`. The `post-checkout` hook is still run, so
+/// that the checkout is recorded in the event log.
+///
+/// Returns `false` without changing anything if the checkout should be done by
+/// `git checkout` instead, such as if `target` is something that only Git knows
+/// how to interpret, or if checking it out would overwrite uncommitted changes
+/// (in which case `git checkout` produces the appropriate error message).
+#[instrument]
+fn try_check_out_in_process(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    target: &str,
+) -> eyre::Result<bool> {
+    let (oid, branch_name) = match repo.find_branch(target, BranchType::Local)? {
+        Some(branch) => match branch.get_oid()? {
+            Some(oid) => (oid, Some(branch.get_reference_name()?)),
+            None => return Ok(false),
+        },
+        None => match repo.revparse_single_commit(target)? {
+            Some(commit) => (commit.get_oid(), None),
+            None => return Ok(false),
+        },
+    };
+
+    let old_head_oid = MaybeZeroOid::from(repo.get_head_info()?.oid);
+    {
+        let (_effects, progress) = effects.start_operation(OperationType::CheckOutCommit);
+        if let Err(err) = repo.check_out_commit(oid, branch_name.as_ref(), &mut |current, total| {
+            progress.notify_progress(current, total)
+        }) {
+            warn!(?err, "Could not check out commit in-process");
+            return Ok(false);
+        }
+    }
+
+    git_run_info.run_hook(
+        effects,
+        repo,
+        "post-checkout",
+        event_tx_id,
+        &[old_head_oid.to_string(), oid.to_string(), "1".to_string()],
+        None,
+    )?;
+    Ok(true)
+}
+
 /// Create a working copy snapshot containing the working copy's current contents.
 ///
 /// The working copy contents are not changed by this operation. That is, the
@@ -0,0 +1,305 @@
+//! Persistent index from commits to their children.
+//!
+//! The DAG can answer "what are the children of this commit?", but doing so
+//! requires scanning the part of the graph which comes after the commit, which
+//! is slow for commits far from the heads in large repositories. Operations
+//! like `git next`, `git hide --recursive`, and `git restack` ask this question
+//! repeatedly, so the parent-to-children mapping is stored in the database
+//! instead. It's updated incrementally whenever new commits are added to the
+//! DAG (see [`Dag::sync_from_oids`]), and rebuilt from the DAG if it's found
+//! to be out of date, such as when it's first created.
+
+use eden_dag::DagAlgorithm;
+use eyre::Context;
+use rusqlite::OptionalExtension;
+use tracing::{instrument, warn};
+
+use crate::core::dag::{CommitSet, CommitVertex, Dag};
+use crate::git::NonZeroOid;
+
+/// Stores the children of each commit in the DAG on disk.
+pub struct ChildIndexDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for ChildIndexDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<ChildIndexDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS commit_children (
+    parent_oid TEXT NOT NULL,
+    child_oid TEXT NOT NULL,
+    PRIMARY KEY (parent_oid, child_oid)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `commit_children` table")?;
+
+    // The number of commits in the DAG which have been indexed, used to detect
+    // whether the index is missing any commits.
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS commit_children_metadata (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    num_indexed_commits INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `commit_children_metadata` table")?;
+
+    Ok(())
+}
+
+fn count_commits(dag: &Dag) -> eyre::Result<usize> {
+    let num_commits = dag.query().all()?.count()?;
+    Ok(num_commits)
+}
+
+impl<'conn> ChildIndexDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(ChildIndexDb { conn })
+    }
+
+    fn get_num_indexed_commits(&self) -> eyre::Result<Option<usize>> {
+        let num_indexed_commits: Option<usize> = self
+            .conn
+            .query_row(
+                "SELECT num_indexed_commits FROM commit_children_metadata WHERE id = 0",
+                rusqlite::params![],
+                |row| row.get("num_indexed_commits"),
+            )
+            .optional()
+            .wrap_err("Querying number of indexed commits")?;
+        Ok(num_indexed_commits)
+    }
+
+    fn set_num_indexed_commits(
+        tx: &rusqlite::Transaction,
+        num_indexed_commits: usize,
+    ) -> eyre::Result<()> {
+        tx.execute(
+            "INSERT OR REPLACE INTO commit_children_metadata VALUES (0, :num_indexed_commits)",
+            rusqlite::named_params! {
+                ":num_indexed_commits": num_indexed_commits,
+            },
+        )
+        .wrap_err("Updating number of indexed commits")?;
+        Ok(())
+    }
+
+    fn insert_edges(
+        tx: &rusqlite::Transaction,
+        edges: impl IntoIterator<Item = (NonZeroOid, NonZeroOid)>,
+    ) -> eyre::Result<()> {
+        let mut statement = tx
+            .prepare("INSERT OR IGNORE INTO commit_children VALUES (:parent_oid, :child_oid)")
+            .wrap_err("Preparing statement")?;
+        for (parent_oid, child_oid) in edges {
+            statement
+                .execute(rusqlite::named_params! {
+                    ":parent_oid": parent_oid.to_string(),
+                    ":child_oid": child_oid.to_string(),
+                })
+                .wrap_err("Inserting into `commit_children`")?;
+        }
+        Ok(())
+    }
+
+    /// Record the parent-child edges for commits which were just added to the
+    /// DAG. `num_new_commits` is the number of commits which were added
+    /// (including those without parents), which is used to check that the
+    /// index was up-to-date beforehand. If it wasn't, the index is rebuilt on
+    /// its next use.
+    #[instrument(skip(edges))]
+    pub fn add_new_commits(
+        &self,
+        dag: &Dag,
+        num_new_commits: usize,
+        edges: &[(NonZeroOid, NonZeroOid)],
+    ) -> eyre::Result<()> {
+        let num_commits = count_commits(dag)?;
+        let tx = self.conn.unchecked_transaction()?;
+        Self::insert_edges(&tx, edges.iter().copied())?;
+        let num_indexed_commits = self.get_num_indexed_commits()?.unwrap_or_default();
+        if num_indexed_commits + num_new_commits == num_commits {
+            Self::set_num_indexed_commits(&tx, num_commits)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Rebuild the index from scratch if it doesn't cover all the commits in
+    /// the DAG.
+    #[instrument]
+    fn ensure_up_to_date(&self, dag: &Dag) -> eyre::Result<()> {
+        let num_commits = count_commits(dag)?;
+        if self.get_num_indexed_commits()? == Some(num_commits) {
+            return Ok(());
+        }
+
+        let mut edges = Vec::new();
+        for child in dag.query().all()?.iter()? {
+            let child: CommitVertex = child?;
+            let child_oid = NonZeroOid::try_from(child.clone())?;
+            for parent in dag.query().parent_names(child)? {
+                edges.push((NonZeroOid::try_from(parent)?, child_oid));
+            }
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM commit_children", rusqlite::params![])
+            .wrap_err("Clearing `commit_children`")?;
+        Self::insert_edges(&tx, edges)?;
+        Self::set_num_indexed_commits(&tx, num_commits)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Remove all entries from the index, so that it's rebuilt on its next
+    /// use.
+    #[instrument]
+    pub fn clear(&self) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM commit_children", rusqlite::params![])
+            .wrap_err("Clearing `commit_children`")?;
+        tx.execute("DELETE FROM commit_children_metadata", rusqlite::params![])
+            .wrap_err("Clearing `commit_children_metadata`")?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the children of the given commits. This is equivalent to
+    /// `dag.query().children(commits)`, including in that the result may
+    /// contain commits which aren't visible.
+    #[instrument]
+    pub fn query_children(&self, dag: &Dag, commits: &CommitSet) -> eyre::Result<CommitSet> {
+        if let Err(err) = self.ensure_up_to_date(dag) {
+            warn!(
+                ?err,
+                "Could not update child index; querying the DAG instead"
+            );
+            return Ok(dag.query().children(commits.clone())?);
+        }
+
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT child_oid FROM commit_children WHERE parent_oid = :parent_oid")
+            .wrap_err("Preparing statement")?;
+        let mut children = Vec::new();
+        for parent in commits.iter()? {
+            let parent_oid = NonZeroOid::try_from(parent?)?;
+            let child_oids = statement
+                .query_map(
+                    rusqlite::named_params! {
+                        ":parent_oid": parent_oid.to_string(),
+                    },
+                    |row| row.get::<_, String>("child_oid"),
+                )
+                .wrap_err("Querying `commit_children`")?;
+            for child_oid in child_oids {
+                let child_oid: NonZeroOid = child_oid?.parse()?;
+                children.push(child_oid);
+            }
+        }
+        Ok(children.into_iter().collect())
+    }
+
+    /// Get the visible children of the given commits.
+    #[instrument]
+    pub fn query_visible_children(
+        &self,
+        dag: &Dag,
+        commits: &CommitSet,
+    ) -> eyre::Result<CommitSet> {
+        let children = self.query_children(dag, commits)?;
+        dag.filter_visible_commits(children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::dag::commit_set_to_vec;
+    use crate::core::effects::Effects;
+    use crate::core::eventlog::{EventLogDb, EventReplayer};
+    use crate::core::formatting::Glyphs;
+    use crate::core::repo_ext::RepoExt;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_child_index() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["checkout", "HEAD^"])?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let child_index_db = ChildIndexDb::new(&conn)?;
+        let open_dag = || -> eyre::Result<Dag> {
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+            let event_cursor = event_replayer.make_default_cursor();
+            let references_snapshot = repo.get_references_snapshot()?;
+            Dag::open_and_sync(
+                &effects,
+                &repo,
+                &event_replayer,
+                event_cursor,
+                &references_snapshot,
+            )
+        };
+
+        let dag = open_dag()?;
+        let expected: CommitSet = [test2_oid, test3_oid].into_iter().collect();
+        assert_eq!(
+            child_index_db
+                .query_children(&dag, &CommitSet::from(test1_oid))?
+                .count()?,
+            2
+        );
+        assert!(child_index_db
+            .query_children(&dag, &CommitSet::from(test1_oid))?
+            .difference(&expected)
+            .is_empty()?);
+
+        // New commits are added to the index incrementally when the DAG is
+        // synced.
+        let test4_oid = git.commit_file("test4", 4)?;
+        let dag = open_dag()?;
+        let num_indexed_commits = child_index_db.get_num_indexed_commits()?;
+        assert_eq!(num_indexed_commits, Some(count_commits(&dag)?));
+        assert_eq!(
+            commit_set_to_vec(&child_index_db.query_children(&dag, &CommitSet::from(test3_oid))?)?,
+            vec![test4_oid]
+        );
+
+        // An out-of-date index is rebuilt from the DAG.
+        conn.execute("DELETE FROM commit_children", [])?;
+        conn.execute(
+            "UPDATE commit_children_metadata SET num_indexed_commits = 0",
+            [],
+        )?;
+        assert_eq!(
+            commit_set_to_vec(&child_index_db.query_children(&dag, &CommitSet::from(test3_oid))?)?,
+            vec![test4_oid]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Query the continuous integration (CI) status of pushed commits.
+//!
+//! The status of each commit is fetched from a [`CiStatusProvider`], such as
+//! GitHub, and cached in the database with [`CiStatusDb`]. Cached statuses are
+//! reused until they're older than `branchless.ci.refreshInterval`, so that
+//! rendering the smartlog repeatedly doesn't exhaust the provider's rate
+//! limits. Failures to reach the provider are cached in the same way, so that
+//! the smartlog doesn't block on the network each time it's rendered while
+//! offline. The cache is cleared by `git branchless gc`.
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use eyre::Context;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::core::config::{get_ci_provider, CI_PROVIDER_CONFIG_KEY};
+use crate::core::forge::CiStatus;
+use crate::git::{NonZeroOid, Repo};
+
+/// How long to wait for a single `gh api` call before giving up on it.
+const GH_API_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A service which reports the CI status of commits.
+pub trait CiStatusProvider: std::fmt::Debug {
+    /// A short, unique name for the provider (such as `github`), used to
+    /// identify its statuses in the database.
+    fn get_name(&self) -> &'static str;
+
+    /// Look up the combined status of the CI checks for the given commit.
+    fn query_status(&mut self, commit_oid: NonZeroOid) -> eyre::Result<CiStatus>;
+}
+
+/// Construct the CI status provider configured with `branchless.ci.provider`,
+/// if any.
+#[instrument]
+pub fn make_ci_status_provider(repo: &Repo) -> eyre::Result<Option<Box<dyn CiStatusProvider>>> {
+    let provider = match get_ci_provider(repo)? {
+        Some(provider) => provider,
+        None => return Ok(None),
+    };
+    let working_directory = repo
+        .get_working_copy_path()
+        .unwrap_or_else(|| repo.get_path())
+        .to_path_buf();
+    match provider.to_lowercase().as_str() {
+        "github" => Ok(Some(Box::new(GithubCiStatusProvider { working_directory }))),
+        other => eyre::bail!(
+            "Invalid value for {CI_PROVIDER_CONFIG_KEY}: expected one of: none, github, but got: {other}"
+        ),
+    }
+}
+
+/// A check run, as reported by the GitHub Checks API.
+#[derive(Debug, Deserialize)]
+struct CheckRun {
+    status: String,
+    conclusion: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRuns {
+    check_runs: Vec<CheckRun>,
+}
+
+/// The combined commit status, as reported by the GitHub Statuses API.
+#[derive(Debug, Deserialize)]
+struct CombinedStatus {
+    state: String,
+    total_count: usize,
+}
+
+/// Reports the results of the GitHub check runs and commit statuses for each
+/// commit. GitHub is accessed via the [GitHub CLI](https://cli.github.com/)
+/// (`gh`), which must be installed and authenticated.
+#[derive(Debug)]
+pub struct GithubCiStatusProvider {
+    working_directory: PathBuf,
+}
+
+impl GithubCiStatusProvider {
+    fn run_gh_api(&self, endpoint: &str) -> eyre::Result<String> {
+        let mut child = Command::new("gh")
+            .args(["api", endpoint])
+            .current_dir(&self.working_directory)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Running gh api {endpoint}"))?;
+
+        // Read the output on separate threads, so that the child doesn't block
+        // on a full pipe while we wait for it to exit.
+        let read_to_end = |mut pipe: Box<dyn Read + Send>| {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                let _ = pipe.read_to_end(&mut buf);
+                buf
+            })
+        };
+        let stdout = read_to_end(Box::new(child.stdout.take().expect("stdout is piped")));
+        let stderr = read_to_end(Box::new(child.stderr.take().expect("stderr is piped")));
+
+        let deadline = Instant::now() + GH_API_TIMEOUT;
+        let status = loop {
+            if let Some(status) = child
+                .try_wait()
+                .wrap_err_with(|| format!("Waiting for gh api {endpoint}"))?
+            {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                eyre::bail!(
+                    "gh api {endpoint} timed out after {} seconds",
+                    GH_API_TIMEOUT.as_secs()
+                );
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            eyre::bail!(
+                "gh api {endpoint} failed: {}",
+                String::from_utf8_lossy(&stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+}
+
+impl CiStatusProvider for GithubCiStatusProvider {
+    fn get_name(&self) -> &'static str {
+        "github"
+    }
+
+    fn query_status(&mut self, commit_oid: NonZeroOid) -> eyre::Result<CiStatus> {
+        let mut statuses = Vec::new();
+
+        let output = self.run_gh_api(&format!(
+            "repos/{{owner}}/{{repo}}/commits/{commit_oid}/check-runs"
+        ))?;
+        let check_runs: CheckRuns = serde_json::from_str(&output)
+            .wrap_err_with(|| format!("Parsing check runs: {output}"))?;
+        for CheckRun { status, conclusion } in check_runs.check_runs {
+            statuses.push(match (status.as_str(), conclusion.as_deref()) {
+                ("completed", Some("success" | "neutral" | "skipped")) => CiStatus::Passed,
+                ("completed", Some(_)) => CiStatus::Failed,
+                _ => CiStatus::Pending,
+            });
+        }
+
+        let output = self.run_gh_api(&format!(
+            "repos/{{owner}}/{{repo}}/commits/{commit_oid}/status"
+        ))?;
+        let combined_status: CombinedStatus = serde_json::from_str(&output)
+            .wrap_err_with(|| format!("Parsing commit status: {output}"))?;
+        // The combined state is `pending` if there are no statuses at all.
+        if combined_status.total_count > 0 {
+            statuses.push(match combined_status.state.as_str() {
+                "success" => CiStatus::Passed,
+                "pending" => CiStatus::Pending,
+                _ => CiStatus::Failed,
+            });
+        }
+
+        let result = if statuses.is_empty() {
+            CiStatus::Unknown
+        } else if statuses.contains(&CiStatus::Failed) {
+            CiStatus::Failed
+        } else if statuses.contains(&CiStatus::Pending) {
+            CiStatus::Pending
+        } else {
+            CiStatus::Passed
+        };
+        Ok(result)
+    }
+}
+
+/// Caches the CI status of each commit, as most recently reported by each
+/// provider.
+pub struct CiStatusDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for CiStatusDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CiStatusDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS ci_statuses (
+    provider TEXT NOT NULL,
+    commit_oid TEXT NOT NULL,
+    status TEXT NOT NULL,
+    timestamp REAL NOT NULL,
+    PRIMARY KEY (provider, commit_oid)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `ci_statuses` table")?;
+    Ok(())
+}
+
+impl<'conn> CiStatusDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(CiStatusDb { conn })
+    }
+
+    /// Get the cached status of the given commit, along with the time at which
+    /// it was fetched.
+    #[instrument]
+    pub fn get_status(
+        &self,
+        provider: &str,
+        commit_oid: NonZeroOid,
+    ) -> eyre::Result<Option<(CiStatus, SystemTime)>> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "
+SELECT status, timestamp
+FROM ci_statuses
+WHERE provider = :provider AND commit_oid = :commit_oid
+",
+            )
+            .wrap_err("Preparing statement")?;
+        let mut rows = statement
+            .query_map(
+                rusqlite::named_params! {
+                    ":provider": provider,
+                    ":commit_oid": commit_oid.to_string(),
+                },
+                |row| {
+                    let status: String = row.get("status")?;
+                    let timestamp: f64 = row.get("timestamp")?;
+                    Ok((status, timestamp))
+                },
+            )
+            .wrap_err("Querying CI status cache")?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let (status, timestamp) = row.wrap_err("Reading CI status cache")?;
+                let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs_f64(timestamp);
+                Ok(Some((status.parse()?, timestamp)))
+            }
+        }
+    }
+
+    /// Record the status of the given commit, as fetched at the given time.
+    #[instrument]
+    pub fn set_status(
+        &self,
+        provider: &str,
+        commit_oid: NonZeroOid,
+        status: CiStatus,
+        now: SystemTime,
+    ) -> eyre::Result<()> {
+        let timestamp = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err("Calculating timestamp")?
+            .as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO ci_statuses
+VALUES (:provider, :commit_oid, :status, :timestamp)
+",
+                rusqlite::named_params! {
+                    ":provider": provider,
+                    ":commit_oid": commit_oid.to_string(),
+                    ":status": status.to_string(),
+                    ":timestamp": timestamp,
+                },
+            )
+            .wrap_err("Updating CI status cache")?;
+        Ok(())
+    }
+
+    /// Remove all cached statuses. Returns the number of entries removed.
+    #[instrument]
+    pub fn clear(&self) -> eyre::Result<usize> {
+        let num_removed = self
+            .conn
+            .execute("DELETE FROM ci_statuses", rusqlite::params![])
+            .wrap_err("Clearing CI status cache")?;
+        Ok(num_removed)
+    }
+}
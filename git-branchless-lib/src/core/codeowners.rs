@@ -0,0 +1,139 @@
+//! Parse `CODEOWNERS` files, which assign owners (such as users or teams) to
+//! the paths in a repository, for forges like GitHub and GitLab to request
+//! reviews from.
+//!
+//! Each line of the file is a pattern followed by the owners of the paths
+//! matching it. Patterns use the same syntax as `.gitignore`, and when several
+//! patterns match a path, the last one takes precedence.
+
+use std::path::Path;
+
+use regex::Regex;
+use tracing::instrument;
+
+use crate::git::{Repo, Tree};
+
+/// The paths at which a `CODEOWNERS` file is looked for, in order of
+/// precedence.
+pub const CODEOWNERS_PATHS: &[&str] = &[
+    ".github/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+];
+
+/// A parsed `CODEOWNERS` file.
+#[derive(Clone, Debug)]
+pub struct CodeOwners {
+    rules: Vec<(Regex, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// Parse the contents of a `CODEOWNERS` file.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use branchless::core::codeowners::CodeOwners;
+    /// let code_owners = CodeOwners::parse("\
+    /// # Comment
+    /// *           @everyone
+    /// /docs/      @docs-team
+    /// *.rs        @rust-team @alice
+    /// /docs/*.rs
+    /// ").unwrap();
+    /// assert_eq!(code_owners.get_owners(Path::new("README.md")), ["@everyone"]);
+    /// assert_eq!(code_owners.get_owners(Path::new("docs/guide/intro.md")), ["@docs-team"]);
+    /// assert_eq!(code_owners.get_owners(Path::new("src/lib.rs")), ["@rust-team", "@alice"]);
+    /// assert!(code_owners.get_owners(Path::new("docs/example.rs")).is_empty());
+    /// ```
+    pub fn parse(contents: &str) -> eyre::Result<Self> {
+        let mut rules = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            // GitLab groups rules into sections with headers like `[Docs]`.
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let pattern = match words.next() {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let owners = words
+                .take_while(|word| !word.starts_with('#'))
+                .map(|word| word.to_owned())
+                .collect();
+            rules.push((pattern_to_regex(pattern)?, owners));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Load the `CODEOWNERS` file from the given tree, if there is one.
+    #[instrument]
+    pub fn from_tree(repo: &Repo, tree: &Tree) -> eyre::Result<Option<Self>> {
+        for path in CODEOWNERS_PATHS {
+            let entry = match tree.get_path(Path::new(path))? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let blob = match repo.find_blob(entry.get_oid())? {
+                Some(blob) => blob,
+                None => continue,
+            };
+            let contents = String::from_utf8_lossy(blob.get_content());
+            return Ok(Some(Self::parse(&contents)?));
+        }
+        Ok(None)
+    }
+
+    /// Get the owners of the given path, which is relative to the root of the
+    /// repository. Returns an empty list if the path isn't owned by anyone.
+    pub fn get_owners(&self, path: &Path) -> &[String] {
+        let path = path.to_string_lossy();
+        self.rules
+            .iter()
+            .rev()
+            .find(|(regex, _owners)| regex.is_match(&path))
+            .map(|(_regex, owners)| owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Convert a `.gitignore`-style pattern into a regex which matches the paths
+/// it applies to, including the paths inside a matching directory.
+fn pattern_to_regex(pattern: &str) -> eyre::Result<Regex> {
+    let (pattern, is_directory) = match pattern.strip_suffix('/') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    // A pattern with a slash at the beginning or in the middle is relative to
+    // the root of the repository. Otherwise, it can match at any depth.
+    let (pattern, is_anchored) = match pattern.strip_prefix('/') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, pattern.contains('/')),
+    };
+
+    let mut regex = String::from(if is_anchored { "^" } else { "^(?:.*/)?" });
+    let mut rest = pattern;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('*') {
+            regex.push_str("[^/]*");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('?') {
+            regex.push_str("[^/]");
+            rest = after;
+        } else {
+            let c = rest.chars().next().unwrap();
+            regex.push_str(&regex::escape(&c.to_string()));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    regex.push_str(if is_directory { "/.*$" } else { "(?:/.*)?$" });
+    Ok(Regex::new(&regex)?)
+}
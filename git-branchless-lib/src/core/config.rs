@@ -3,14 +3,15 @@
 use std::ffi::OsString;
 use std::fmt::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use cursive::theme::{BaseColor, Effect, Style};
+use cursive::theme::{BaseColor, ColorType, Effect, PaletteColor, Style};
 use cursive::utils::markup::StyledString;
 use eyre::Context;
 use tracing::{instrument, warn};
 
-use crate::core::formatting::StyledStringBuilder;
-use crate::git::{ConfigRead, GitRunInfo, GitRunOpts, Repo};
+use crate::core::formatting::{parse_style, GlyphSet, StyledStringBuilder, Theme};
+use crate::git::{BranchType, ConfigRead, GitRunInfo, GitRunOpts, Repo};
 
 use super::effects::Effects;
 use super::eventlog::EventTransactionId;
@@ -19,7 +20,7 @@ use super::eventlog::EventTransactionId;
 /// overridden it.
 #[instrument]
 pub fn get_default_hooks_dir(repo: &Repo) -> PathBuf {
-    repo.get_path().join("hooks")
+    repo.get_common_path().join("hooks")
 }
 
 /// Get the path where Git hooks are stored on disk.
@@ -50,6 +51,142 @@ pub fn get_hooks_dir(
     Ok(hooks_path)
 }
 
+/// Config key for [`get_reference_transaction_hook_enabled`].
+pub const REFERENCE_TRANSACTION_HOOK_CONFIG_KEY: &str = "branchless.core.referenceTransactionHook";
+
+/// If `false`, don't install the `reference-transaction` hook. Instead, branch
+/// updates are detected less precisely by the other hooks, which avoids
+/// invoking `git-branchless` on every reference update in repositories with
+/// many of them.
+#[instrument]
+pub fn get_reference_transaction_hook_enabled(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or(REFERENCE_TRANSACTION_HOOK_CONFIG_KEY, true)
+}
+
+/// Config key for [`get_pre_push_hook_enabled`].
+pub const PRE_PUSH_HOOK_CONFIG_KEY: &str = "branchless.core.prePushHook";
+
+/// If `true`, install the `pre-push` hook, which checks whether any of the
+/// commits being pushed have been rewritten or hidden locally. Off by default,
+/// since it runs on every push.
+#[instrument]
+pub fn get_pre_push_hook_enabled(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or(PRE_PUSH_HOOK_CONFIG_KEY, false)
+}
+
+/// Config key for [`get_init_sapling_aliases`].
+pub const SAPLING_ALIASES_CONFIG_KEY: &str = "branchless.init.saplingAliases";
+
+/// If `true`, `git branchless init` also installs aliases named after Sapling
+/// commands (such as `git goto`), for users who are used to Sapling.
+#[instrument]
+pub fn get_init_sapling_aliases(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or(SAPLING_ALIASES_CONFIG_KEY, false)
+}
+
+/// Config key for [`get_ignored_ref_patterns`].
+pub const IGNORED_REFS_CONFIG_KEY: &str = "branchless.core.ignoredRefs";
+
+/// Get the patterns of references whose updates shouldn't be recorded in the
+/// event log, such as `refs/notes/*` or references updated by automation. The
+/// value is a list separated by spaces, and the key may also be set multiple
+/// times. See [`crate::core::eventlog::ref_pattern_matches`] for the syntax.
+#[instrument]
+pub fn get_ignored_ref_patterns(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let patterns = repo
+        .get_readonly_config()?
+        .list(format!(
+            "^{}$",
+            regex::escape(&IGNORED_REFS_CONFIG_KEY.to_lowercase())
+        ))?
+        .into_iter()
+        .flat_map(|(_key, value)| {
+            value
+                .split_whitespace()
+                .map(|pattern| pattern.to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    Ok(patterns)
+}
+
+/// Config key for [`get_protected_branch_patterns`].
+pub const PROTECTED_BRANCHES_CONFIG_KEY: &str = "branchless.core.protectedBranches";
+
+/// Get the patterns of branch names which shouldn't be moved or force-pushed,
+/// in addition to the main branch. The value is a list separated by spaces,
+/// and the key may also be set multiple times. See
+/// [`crate::core::eventlog::ref_pattern_matches`] for the syntax.
+#[instrument]
+pub fn get_protected_branch_patterns(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let patterns = repo
+        .get_readonly_config()?
+        .list(format!(
+            "^{}$",
+            regex::escape(&PROTECTED_BRANCHES_CONFIG_KEY.to_lowercase())
+        ))?
+        .into_iter()
+        .flat_map(|(_key, value)| {
+            value
+                .split_whitespace()
+                .map(|pattern| pattern.to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    Ok(patterns)
+}
+
+/// Config key for the script to run after commits are rewritten. See
+/// [`crate::core::user_hooks`].
+pub const POST_REWRITE_USER_HOOK_CONFIG_KEY: &str = "branchless.hooks.postRewrite";
+
+/// Config key for the script to run after commits are hidden or unhidden. See
+/// [`crate::core::user_hooks`].
+pub const POST_HIDE_USER_HOOK_CONFIG_KEY: &str = "branchless.hooks.postHide";
+
+/// Config key for the script to run after `git undo`. See
+/// [`crate::core::user_hooks`].
+pub const POST_UNDO_USER_HOOK_CONFIG_KEY: &str = "branchless.hooks.postUndo";
+
+/// Config key for [`get_pre_push_obsolete_commits_action`].
+pub const PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY: &str = "branchless.prePush.obsoleteCommits";
+
+/// What the `pre-push` hook should do when a commit being pushed is obsolete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrePushObsoleteCommitsAction {
+    /// Print a warning, but allow the push.
+    Warn,
+
+    /// Print a warning and abort the push.
+    Block,
+}
+
+/// Get the configured [`PrePushObsoleteCommitsAction`]. Unrecognized values
+/// are treated as `warn`, so that a typo doesn't prevent pushing altogether.
+#[instrument]
+pub fn get_pre_push_obsolete_commits_action(
+    repo: &Repo,
+) -> eyre::Result<PrePushObsoleteCommitsAction> {
+    let action: Option<String> = repo
+        .get_readonly_config()?
+        .get(PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY)?;
+    let action = match action.as_deref() {
+        Some("block") => PrePushObsoleteCommitsAction::Block,
+        Some("warn") | None => PrePushObsoleteCommitsAction::Warn,
+        Some(action) => {
+            warn!(
+                ?action,
+                "Unrecognized value for {PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY}"
+            );
+            PrePushObsoleteCommitsAction::Warn
+        }
+    };
+    Ok(action)
+}
+
 /// Get the configured name of the main branch.
 ///
 /// The following config values are resolved, in order. The first valid value is returned.
@@ -86,6 +223,16 @@ pub fn get_auto_switch_branches(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.navigation.autoSwitchBranches", true)
 }
 
+/// If `true`, check out commits in-process using libgit2, rather than by
+/// running `git checkout`, when no extra arguments need to be passed to it.
+/// Checkouts which libgit2 can't carry out (such as those which would overwrite
+/// uncommitted changes) still fall back to `git checkout`.
+#[instrument]
+pub fn get_checkout_in_process(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.checkout.inProcess", false)
+}
+
 /// Get the default comment character.
 #[instrument]
 pub fn get_comment_char(repo: &Repo) -> eyre::Result<char> {
@@ -98,6 +245,29 @@ pub fn get_comment_char(repo: &Repo) -> eyre::Result<char> {
     Ok(comment_char)
 }
 
+/// Get the directory where Git records resolutions of merge conflicts for
+/// `git rerere`.
+pub fn get_rerere_cache_dir(repo: &Repo) -> PathBuf {
+    repo.get_common_path().join("rr-cache")
+}
+
+/// Whether to record and reuse resolutions of merge conflicts, as with `git
+/// rerere`. As in Git, if `rerere.enabled` isn't set, this is enabled if the
+/// resolution cache directory already exists.
+#[instrument]
+pub fn get_rerere_enabled(repo: &Repo) -> eyre::Result<bool> {
+    let enabled: Option<bool> = repo.get_readonly_config()?.get("rerere.enabled")?;
+    Ok(enabled.unwrap_or_else(|| get_rerere_cache_dir(repo).is_dir()))
+}
+
+/// Whether resolutions reused from `git rerere` can be committed without
+/// asking the user to review them first, as with Git's `rerere.autoUpdate`.
+#[instrument]
+pub fn get_rerere_autoupdate(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("rerere.autoUpdate", false)
+}
+
 /// Get the commit template message, if any.
 #[instrument]
 pub fn get_commit_template(repo: &Repo) -> eyre::Result<Option<String>> {
@@ -190,6 +360,24 @@ pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.restack.preserveTimestamps", false)
 }
 
+/// If `true`, when `git commit --amend` abandons descendant commits, offer to
+/// restack them right away (when running in a terminal), rather than only
+/// suggesting to run `git restack`.
+#[instrument]
+pub fn get_restack_prompt_after_amend(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.promptAfterAmend", false)
+}
+
+/// If `true`, when an in-memory rebase run with `--merge` encounters merge
+/// conflicts, offer to resolve them in a terminal user interface before
+/// falling back to an on-disk rebase.
+#[instrument]
+pub fn get_restack_resolve_conflicts_interactively(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.resolveConflictsInteractively", true)
+}
+
 /// If `true`, when advancing to a "next" commit, prompt interactively to
 /// if there is ambiguity in which commit to advance to.
 #[instrument]
@@ -198,6 +386,70 @@ pub fn get_next_interactive(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.next.interactive", false)
 }
 
+/// If `true`, when advancing to a "next" commit, select the newest child if
+/// there is ambiguity in which commit to advance to, as if `--newest` were
+/// passed.
+#[instrument]
+pub fn get_next_default_newest(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.next.defaultNewest", false)
+}
+
+/// If `true`, only attempt in-memory rebases when moving commits, as if
+/// `--in-memory` were passed. Passing `--on-disk` or `--merge` overrides this.
+#[instrument]
+pub fn get_move_default_in_memory(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.move.defaultInMemory", false)
+}
+
+/// If `true`, also hide the descendants of the commits passed to `git hide`,
+/// as if `--recursive` were passed. Passing `--no-recursive` overrides this.
+#[instrument]
+pub fn get_hide_default_recursive(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.hide.defaultRecursive", false)
+}
+
+/// Get the names of the `git` aliases which `git branchless init` should
+/// install, as configured with `branchless.init.aliases` (a list separated by
+/// spaces or commas). Returns `None` if all aliases should be installed.
+#[instrument]
+pub fn get_init_aliases(repo: &Repo) -> eyre::Result<Option<Vec<String>>> {
+    let aliases: Option<String> = repo.get_readonly_config()?.get("branchless.init.aliases")?;
+    let aliases = aliases.map(|aliases| {
+        aliases
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|alias| !alias.is_empty())
+            .map(|alias| alias.to_owned())
+            .collect()
+    });
+    Ok(aliases)
+}
+
+/// Get the user-defined subcommand aliases, as configured with
+/// `branchless.alias.<name>`. For example, `branchless.alias.up = next
+/// --newest` lets the user run `git branchless up`.
+///
+/// Returns: the pairs of alias name and the arguments it expands to.
+#[instrument]
+pub fn get_subcommand_aliases(repo: &Repo) -> eyre::Result<Vec<(String, String)>> {
+    let aliases = repo
+        .get_readonly_config()?
+        .list(r"^branchless\.alias\.")?
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let name = key.get("branchless.alias.".len()..)?;
+            if name.is_empty() || name.contains('.') {
+                None
+            } else {
+                Some((name.to_owned(), value))
+            }
+        })
+        .collect();
+    Ok(aliases)
+}
+
 /// If `true`, show branches pointing to each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_branches(repo: &Repo) -> eyre::Result<bool> {
@@ -205,6 +457,14 @@ pub fn get_commit_descriptors_branches(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.branches", true)
 }
 
+/// If `true`, show which other worktrees have each commit checked out in the
+/// smartlog.
+#[instrument]
+pub fn get_commit_descriptors_worktrees(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.worktrees", true)
+}
+
 /// If `true`, show associated Phabricator commits in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result<bool> {
@@ -212,13 +472,353 @@ pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result
         .get_or("branchless.commitDescriptors.differentialRevision", true)
 }
 
-/// If `true`, show the age of each commit in the smartlog.
+/// If `true`, show the age of each commit in the smartlog. Always `false` in
+/// deterministic mode (see [`get_deterministic`]).
 #[instrument]
 pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
+    if get_deterministic() {
+        return Ok(false);
+    }
     repo.get_readonly_config()?
         .get_or("branchless.commitDescriptors.relativeTime", true)
 }
 
+/// If `true`, highlight the commits which were created, rewritten, or hidden,
+/// or which had branches moved to them, by the command which rendered the
+/// smartlog.
+#[instrument]
+pub fn get_smartlog_highlight_changes(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.highlightChanges", true)
+}
+
+/// If `true`, `git amend`, `git hide`, and `git unhide` print the smartlog once
+/// they've finished, as `git move` and `git restack` do.
+#[instrument]
+pub fn get_smartlog_show_after_command(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.showAfterCommand", false)
+}
+
+/// Get the directory containing translations of user-facing messages (see
+/// `crate::core::i18n`), if configured. A relative path is interpreted
+/// relative to the root of the working copy, so that translations can be
+/// checked into the repository.
+#[instrument]
+pub fn get_locale_dir(repo: &Repo) -> eyre::Result<Option<PathBuf>> {
+    let locale_dir: Option<String> = repo
+        .get_readonly_config()?
+        .get("branchless.core.localeDir")?;
+    let locale_dir = match locale_dir {
+        Some(locale_dir) => PathBuf::from(locale_dir),
+        None => return Ok(None),
+    };
+    match repo.get_working_copy_path() {
+        Some(working_copy_path) if locale_dir.is_relative() => {
+            Ok(Some(working_copy_path.join(locale_dir)))
+        }
+        _ => Ok(Some(locale_dir)),
+    }
+}
+
+/// If `true`, produce plain output (see `Effects::plain`), as with the
+/// `--plain` option.
+#[instrument]
+pub fn get_plain_output(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.plain", false)
+}
+
+/// Get the theme to use for output, as configured with `branchless.theme.*`.
+/// Styles which aren't configured keep their default values.
+#[instrument]
+pub fn get_theme(repo: &Repo) -> eyre::Result<Theme> {
+    let config = repo.get_readonly_config()?;
+    let mut theme = Theme::default();
+
+    let glyph_set: String = config.get_or("branchless.theme.glyphs", "auto".to_string())?;
+    theme.glyph_set = match glyph_set.to_lowercase().as_str() {
+        "auto" => None,
+        "unicode" => Some(GlyphSet::Unicode),
+        "ascii" => Some(GlyphSet::Ascii),
+        other => eyre::bail!(
+            "Invalid value for branchless.theme.glyphs: expected one of: auto, unicode, ascii, but got: {other}"
+        ),
+    };
+
+    let Theme {
+        glyph_set: _,
+        commit_oid,
+        branch,
+        annotation,
+        worktree,
+        differential_revision,
+        relative_time,
+        merge,
+        change_created,
+        change_rewritten,
+        change_branch_moved,
+        change_hidden,
+        ci_passed,
+        ci_failed,
+        ci_pending,
+        issue,
+        tui_palette,
+    } = &mut theme;
+    for (key, style) in [
+        ("branchless.theme.commitOid", commit_oid),
+        ("branchless.theme.branch", branch),
+        ("branchless.theme.annotation", annotation),
+        ("branchless.theme.worktree", worktree),
+        (
+            "branchless.theme.differentialRevision",
+            differential_revision,
+        ),
+        ("branchless.theme.relativeTime", relative_time),
+        ("branchless.theme.merge", merge),
+        ("branchless.theme.changeCreated", change_created),
+        ("branchless.theme.changeRewritten", change_rewritten),
+        ("branchless.theme.changeBranchMoved", change_branch_moved),
+        ("branchless.theme.changeHidden", change_hidden),
+        ("branchless.theme.ciPassed", ci_passed),
+        ("branchless.theme.ciFailed", ci_failed),
+        ("branchless.theme.ciPending", ci_pending),
+        ("branchless.theme.issue", issue),
+    ] {
+        if let Some(value) = config.get::<String, _>(key)? {
+            *style =
+                parse_style(&value).map_err(|err| eyre::eyre!("Invalid value for {key}: {err}"))?;
+        }
+    }
+
+    for (key, value) in config.list(r"^branchless\.theme\.tui\.")? {
+        let name = match key.get("branchless.theme.tui.".len()..) {
+            Some(name) => name.to_lowercase(),
+            None => continue,
+        };
+        let palette_color = match name.as_str() {
+            "background" => PaletteColor::Background,
+            "shadow" => PaletteColor::Shadow,
+            "view" => PaletteColor::View,
+            "primary" => PaletteColor::Primary,
+            "secondary" => PaletteColor::Secondary,
+            "tertiary" => PaletteColor::Tertiary,
+            "titleprimary" => PaletteColor::TitlePrimary,
+            "titlesecondary" => PaletteColor::TitleSecondary,
+            "highlight" => PaletteColor::Highlight,
+            "highlightinactive" => PaletteColor::HighlightInactive,
+            "highlighttext" => PaletteColor::HighlightText,
+            _ => {
+                warn!(?key, "Unknown palette color in theme");
+                continue;
+            }
+        };
+        let style =
+            parse_style(&value).map_err(|err| eyre::eyre!("Invalid value for {key}: {err}"))?;
+        match style.color.front {
+            ColorType::Color(color) => tui_palette.push((palette_color, color)),
+            ColorType::Palette(_) | ColorType::InheritParent => {
+                eyre::bail!("Invalid value for {key}: expected a color, but got: {value}")
+            }
+        }
+    }
+
+    Ok(theme)
+}
+
+/// Config key for `get_auto_hide_after`.
+pub const AUTO_HIDE_AFTER_CONFIG_KEY: &str = "branchless.autoHide.after";
+
+/// If set, `gc` hides draft stacks which haven't been touched for this long.
+/// The value is a number followed by one of `s`, `m`, `h`, `d`, or `w` (such
+/// as `90d`); a bare number is interpreted as a number of days.
+///
+/// Returns the raw config value along with the parsed duration, so that the
+/// former can be shown to the user.
+#[instrument]
+pub fn get_auto_hide_after(repo: &Repo) -> eyre::Result<Option<(String, Duration)>> {
+    get_duration_config(repo, AUTO_HIDE_AFTER_CONFIG_KEY)
+}
+
+/// Config key for `get_gc_retention`.
+pub const GC_RETENTION_CONFIG_KEY: &str = "branchless.gc.retention";
+
+/// If set, `gc` keeps the references which anchor hidden commits until the
+/// commits have been hidden for this long, so that they can still be restored
+/// with `git undo` in the meantime. After that, they're left to Git's normal
+/// pruning. The value has the same format as `branchless.autoHide.after`.
+///
+/// Returns the raw config value along with the parsed duration, so that the
+/// former can be shown to the user.
+#[instrument]
+pub fn get_gc_retention(repo: &Repo) -> eyre::Result<Option<(String, Duration)>> {
+    get_duration_config(repo, GC_RETENTION_CONFIG_KEY)
+}
+
+/// Config key for `get_gc_stale_state_expiry`.
+pub const GC_STALE_STATE_EXPIRY_CONFIG_KEY: &str = "branchless.gc.staleStateExpiry";
+
+/// `gc` removes temporary files, test worktrees, and rebase state left behind
+/// by interrupted operations once they haven't been modified for this long.
+/// The value has the same format as `branchless.autoHide.after`, and defaults
+/// to one day.
+///
+/// Returns the raw config value along with the parsed duration, so that the
+/// former can be shown to the user.
+#[instrument]
+pub fn get_gc_stale_state_expiry(repo: &Repo) -> eyre::Result<(String, Duration)> {
+    let result = get_duration_config(repo, GC_STALE_STATE_EXPIRY_CONFIG_KEY)?
+        .unwrap_or_else(|| ("1d".to_string(), Duration::from_secs(24 * 60 * 60)));
+    Ok(result)
+}
+
+/// Config key for `get_ci_provider`.
+pub const CI_PROVIDER_CONFIG_KEY: &str = "branchless.ci.provider";
+
+/// The service to query for the CI status of pushed commits, which is shown in
+/// the smartlog (such as `github`). If unset, CI statuses aren't shown.
+#[instrument]
+pub fn get_ci_provider(repo: &Repo) -> eyre::Result<Option<String>> {
+    let provider: Option<String> = repo.get_readonly_config()?.get(CI_PROVIDER_CONFIG_KEY)?;
+    Ok(provider.filter(|provider| provider != "none"))
+}
+
+/// Config key for `get_ci_refresh_interval`.
+pub const CI_REFRESH_INTERVAL_CONFIG_KEY: &str = "branchless.ci.refreshInterval";
+
+/// The CI status of each commit is cached for this long before it's queried
+/// again, to limit the number of requests made to the CI provider. The value
+/// has the same format as `branchless.autoHide.after`, and defaults to five
+/// minutes.
+#[instrument]
+pub fn get_ci_refresh_interval(repo: &Repo) -> eyre::Result<Duration> {
+    let result = get_duration_config(repo, CI_REFRESH_INTERVAL_CONFIG_KEY)?
+        .map(|(_value, duration)| duration)
+        .unwrap_or_else(|| Duration::from_secs(5 * 60));
+    Ok(result)
+}
+
+/// Config key for `get_issues_trailers`.
+pub const ISSUES_TRAILERS_CONFIG_KEY: &str = "branchless.issues.trailers";
+
+/// Get the keys of the commit message trailers which reference issues in an
+/// external issue tracker (such as `Fixes` or `Jira`), which are shown in the
+/// smartlog. The value is a list separated by spaces, and the key may also be
+/// set multiple times.
+#[instrument]
+pub fn get_issues_trailers(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let trailers = repo
+        .get_readonly_config()?
+        .list(format!(
+            "^{}$",
+            regex::escape(&ISSUES_TRAILERS_CONFIG_KEY.to_lowercase())
+        ))?
+        .into_iter()
+        .flat_map(|(_key, value)| {
+            value
+                .split_whitespace()
+                .map(|trailer| trailer.trim_end_matches(':').to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    Ok(trailers)
+}
+
+/// Config key for `get_issues_status_command`.
+pub const ISSUES_STATUS_COMMAND_CONFIG_KEY: &str = "branchless.issues.statusCommand";
+
+/// The shell command to run to look up the status of an issue referenced by a
+/// commit message trailer, which is shown in the smartlog. See
+/// [`crate::core::issues::CommandIssueStatusProvider`]. If unset, statuses
+/// aren't shown.
+#[instrument]
+pub fn get_issues_status_command(repo: &Repo) -> eyre::Result<Option<String>> {
+    let command: Option<String> = repo
+        .get_readonly_config()?
+        .get(ISSUES_STATUS_COMMAND_CONFIG_KEY)?;
+    Ok(command.filter(|command| !command.trim().is_empty()))
+}
+
+/// `git branchless gc` runs automatically at the end of other commands once
+/// this many events have been recorded since it last ran. It is disabled if
+/// set to 0.
+#[instrument]
+pub fn get_gc_auto_events(repo: &Repo) -> eyre::Result<usize> {
+    let value: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.autoEvents", 1000)?;
+    Ok(value.try_into().unwrap_or(0))
+}
+
+/// `git branchless gc` runs automatically at the end of other commands once
+/// this many commits have been hidden (including by rewriting them) since it
+/// last ran. It is disabled if set to 0.
+#[instrument]
+pub fn get_gc_auto_hidden_commits(repo: &Repo) -> eyre::Result<usize> {
+    let value: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.autoHiddenCommits", 100)?;
+    Ok(value.try_into().unwrap_or(0))
+}
+
+/// Config key for `get_gc_write_commit_graph`.
+pub const GC_WRITE_COMMIT_GRAPH_CONFIG_KEY: &str = "branchless.gc.writeCommitGraph";
+
+/// If set, `gc` writes Git's commit-graph file for all reachable commits, which
+/// speeds up ancestry queries for both Git and git-branchless.
+#[instrument]
+pub fn get_gc_write_commit_graph(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or(GC_WRITE_COMMIT_GRAPH_CONFIG_KEY, false)
+}
+
+fn get_duration_config(repo: &Repo, key: &str) -> eyre::Result<Option<(String, Duration)>> {
+    let value: Option<String> = repo.get_readonly_config()?.get(key)?;
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let value = value.trim().to_owned();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let duration = parse_duration(&value).map_err(|err| eyre::eyre!("Parsing {key}: {err}"))?;
+    Ok(Some((value, duration)))
+}
+
+/// Parse a duration config value, such as `90d`. The value is a number
+/// followed by one of `s`, `m`, `h`, `d`, or `w`; a bare number is interpreted
+/// as a number of days.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (amount, unit_secs) = match value.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => {
+            let unit_secs = match unit {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                'w' => 7 * 24 * 60 * 60,
+                _ => {
+                    return Err(format!(
+                        "unknown unit {unit:?} in {value:?} (expected one of s, m, h, d, or w)"
+                    ))
+                }
+            };
+            (&value[..index], unit_secs)
+        }
+        _ => (value, 24 * 60 * 60),
+    };
+    let amount: u64 = amount
+        .trim()
+        .parse()
+        .map_err(|err| format!("invalid duration {value:?}: {err}"))?;
+    let secs = amount
+        .checked_mul(unit_secs)
+        .ok_or_else(|| format!("duration {value:?} is too large"))?;
+    Ok(Duration::from_secs(secs))
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -228,42 +828,143 @@ pub enum Hint {
     /// Suggest running `git test clean` in order to clean cached test results.
     CleanCachedTestResults,
 
+    /// Note that `HEAD` is no longer attached to a branch after moving away
+    /// from it with `git next`, `git prev`, or `git switch`, so new commits
+    /// won't update the branch. (`git branchless init` disables Git's own
+    /// `advice.detachedHead`, since git-branchless workflows usually detach
+    /// `HEAD` on purpose.)
+    DetachedHead,
+
+    /// Suggest hiding a draft commit after it's been cherry-picked elsewhere.
+    HideCherryPickedCommit,
+
+    /// Suggest `git hide --orphans` when hiding commits leaves their visible
+    /// descendants dangling.
+    HideOrphans,
+
+    /// Suggest running `git undo` after hiding or unhiding commits.
+    HideUndo,
+
+    /// Warn that the hooks aren't installed in the hooks directory that Git
+    /// actually uses, so events aren't being recorded.
+    HooksNotInstalled,
+
     /// Suggest omitting arguments when they would default to `HEAD`.
     MoveImplicitHeadArgument,
 
     /// Suggest running `git restack` when a commit is abandoned as part of a `rewrite` event.
     RestackWarnAbandoned,
 
+    /// Suggest running `git undo` after rewording several commits.
+    RewordUndo,
+
     /// Suggest running `git restack` when the smartlog prints an abandoned commit.
     SmartlogFixAbandoned,
 
+    /// Suggest `git sync --pull` when the main branch is behind its upstream
+    /// branch, since commits are otherwise synced onto the outdated main
+    /// branch.
+    SyncMainBranchBehind,
+
     /// Suggest showing more output with `git test show` using `--verbose`.
     TestShowVerbose,
 }
 
 impl Hint {
-    fn get_config_key(&self) -> &'static str {
+    /// Get the config key which controls whether this hint is shown.
+    pub fn get_config_key(&self) -> &'static str {
         match self {
             Hint::CleanCachedTestResults => "branchless.hint.cleanCachedTestResults",
+            Hint::DetachedHead => "branchless.hint.detachedHead",
+            Hint::HideCherryPickedCommit => "branchless.hint.hideCherryPickedCommit",
+            Hint::HideOrphans => "branchless.hint.hideOrphans",
+            Hint::HideUndo => "branchless.hint.hideUndo",
+            Hint::HooksNotInstalled => "branchless.hint.hooksNotInstalled",
             Hint::MoveImplicitHeadArgument => "branchless.hint.moveImplicitHeadArgument",
             Hint::RestackWarnAbandoned => "branchless.hint.restackWarnAbandoned",
+            Hint::RewordUndo => "branchless.hint.rewordUndo",
             Hint::SmartlogFixAbandoned => "branchless.hint.smartlogFixAbandoned",
+            Hint::SyncMainBranchBehind => "branchless.hint.syncMainBranchBehind",
             Hint::TestShowVerbose => "branchless.hint.testShowVerbose",
         }
     }
 }
 
-/// Determine if a given hint is enabled.
-pub fn get_hint_enabled(repo: &Repo, hint: Hint) -> eyre::Result<bool> {
+/// How much output commands should produce, as set with the global `--quiet`
+/// and `--verbose` options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Don't print the smartlog after a command or any hints (`-q`).
+    Quiet,
+
+    /// The default output.
+    Normal,
+
+    /// Also print the rebase plan before executing it (`-v`).
+    Verbose,
+
+    /// Also print the constraints used to build the rebase plan (`-vv`).
+    VeryVerbose,
+}
+
+impl Verbosity {
+    /// Get the verbosity for the given level, which is the number of times
+    /// `--verbose` was passed, or negative if `--quiet` was passed.
+    pub fn from_level(level: i64) -> Self {
+        match level {
+            i64::MIN..=-1 => Verbosity::Quiet,
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            2..=i64::MAX => Verbosity::VeryVerbose,
+        }
+    }
+
+    /// Get the level for this verbosity. The inverse of
+    /// [`Verbosity::from_level`].
+    pub fn get_level(self) -> i64 {
+        match self {
+            Verbosity::Quiet => -1,
+            Verbosity::Normal => 0,
+            Verbosity::Verbose => 1,
+            Verbosity::VeryVerbose => 2,
+        }
+    }
+}
+
+/// Get the verbosity which was passed down from a parent `git-branchless`
+/// process (such as the one which ran the `git` command invoking a hook) with
+/// [`env_vars::GIT_BRANCHLESS_VERBOSITY`], if any.
+pub fn get_inherited_verbosity() -> Option<Verbosity> {
+    let value = std::env::var(env_vars::GIT_BRANCHLESS_VERBOSITY).ok()?;
+    let level: i64 = value.trim().parse().ok()?;
+    Some(Verbosity::from_level(level))
+}
+
+/// Determine whether output should be reproducible across runs and machines,
+/// as set with the global `--deterministic` option (and thus
+/// [`env_vars::GIT_BRANCHLESS_DETERMINISTIC`]), such as for snapshot tests.
+pub fn get_deterministic() -> bool {
+    match std::env::var(env_vars::GIT_BRANCHLESS_DETERMINISTIC) {
+        Ok(value) => !matches!(value.to_lowercase().as_str(), "" | "0" | "false"),
+        Err(_) => false,
+    }
+}
+
+/// Determine if a given hint is enabled. Hints are never shown with
+/// [`Verbosity::Quiet`].
+pub fn get_hint_enabled(effects: &Effects, repo: &Repo, hint: Hint) -> eyre::Result<bool> {
+    if effects.get_verbosity() == Verbosity::Quiet {
+        return Ok(false);
+    }
     repo.get_readonly_config()?
         .get_or(hint.get_config_key(), true)
 }
 
 /// Render the leading colored "hint" text for use in messaging.
-pub fn get_hint_string() -> StyledString {
+pub fn get_hint_string(effects: &Effects) -> StyledString {
     StyledStringBuilder::new()
         .append_styled(
-            "hint",
+            effects.get_messages().format("hint", &[]),
             Style::merge(&[BaseColor::Blue.dark().into(), Effect::Bold.into()]),
         )
         .build()
@@ -273,13 +974,477 @@ pub fn get_hint_string() -> StyledString {
 pub fn print_hint_suppression_notice(effects: &Effects, hint: Hint) -> eyre::Result<()> {
     writeln!(
         effects.get_output_stream(),
-        "{}: disable this hint by running: git config --global {} false",
-        effects.get_glyphs().render(get_hint_string())?,
-        hint.get_config_key(),
+        "{}: {}",
+        effects.get_glyphs().render(get_hint_string(effects))?,
+        effects.get_messages().format(
+            "hint-suppression-notice",
+            &[("config-key", &hint.get_config_key())]
+        ),
     )?;
     Ok(())
 }
 
+/// The type of value expected for a config key. Used to validate values set
+/// with `git branchless config set`.
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigValueType {
+    /// A boolean, spelled any way that Git accepts.
+    Bool,
+
+    /// An integer.
+    Integer,
+
+    /// A duration, as accepted by [`parse_duration`].
+    Duration,
+
+    /// One of the given values.
+    OneOf(&'static [&'static str]),
+
+    /// The name of a local branch.
+    Branch,
+
+    /// A style, as accepted by [`parse_style`].
+    Style,
+
+    /// Any string.
+    String,
+}
+
+impl ConfigValueType {
+    /// Check whether the given value is valid for this type. Returns a
+    /// description of the problem if not.
+    #[instrument]
+    pub fn validate(&self, repo: &Repo, value: &str) -> eyre::Result<Result<(), String>> {
+        let result = match self {
+            ConfigValueType::Bool => match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" | "false" | "no" | "off" | "0" => Ok(()),
+                _ => Err(format!(
+                    "expected a boolean (true or false), but got: {value}"
+                )),
+            },
+            ConfigValueType::Integer => match value.parse::<i32>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err(format!("expected an integer, but got: {value}")),
+            },
+            ConfigValueType::Duration => parse_duration(value).map(|_| ()),
+            ConfigValueType::OneOf(values) => {
+                if values.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "expected one of: {}, but got: {value}",
+                        values.join(", ")
+                    ))
+                }
+            }
+            ConfigValueType::Branch => {
+                if repo.find_branch(value, BranchType::Local)?.is_some() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "the branch {value} does not exist (expected the name of a local branch)"
+                    ))
+                }
+            }
+            ConfigValueType::Style => parse_style(value).map(|_| ()),
+            ConfigValueType::String => Ok(()),
+        };
+        Ok(result)
+    }
+}
+
+/// A config key which `git-branchless` recognizes. If the name ends with
+/// `.<name>`, then it stands for any key with that prefix (such as
+/// `branchless.alias.up`).
+#[derive(Clone, Copy, Debug)]
+pub struct KnownConfigKey {
+    /// The full name of the key, as passed to `git config`.
+    pub name: &'static str,
+
+    /// The type of value expected for the key.
+    pub value_type: ConfigValueType,
+}
+
+/// All config keys which `git-branchless` recognizes.
+pub const KNOWN_CONFIG_KEYS: &[KnownConfigKey] = &[
+    KnownConfigKey {
+        name: "branchless.alias.<name>",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: AUTO_HIDE_AFTER_CONFIG_KEY,
+        value_type: ConfigValueType::Duration,
+    },
+    KnownConfigKey {
+        name: "branchless.checkout.inProcess",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: CI_PROVIDER_CONFIG_KEY,
+        value_type: ConfigValueType::OneOf(&["none", "github"]),
+    },
+    KnownConfigKey {
+        name: CI_REFRESH_INTERVAL_CONFIG_KEY,
+        value_type: ConfigValueType::Duration,
+    },
+    KnownConfigKey {
+        name: "branchless.commitDescriptors.branches",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.commitDescriptors.differentialRevision",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.commitDescriptors.relativeTime",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.commitDescriptors.worktrees",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: IGNORED_REFS_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.core.localeDir",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.core.mainBranch",
+        value_type: ConfigValueType::Branch,
+    },
+    KnownConfigKey {
+        name: "branchless.core.plain",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: PRE_PUSH_HOOK_CONFIG_KEY,
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: PROTECTED_BRANCHES_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: REFERENCE_TRANSACTION_HOOK_CONFIG_KEY,
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.gc.autoEvents",
+        value_type: ConfigValueType::Integer,
+    },
+    KnownConfigKey {
+        name: "branchless.gc.autoHiddenCommits",
+        value_type: ConfigValueType::Integer,
+    },
+    KnownConfigKey {
+        name: GC_RETENTION_CONFIG_KEY,
+        value_type: ConfigValueType::Duration,
+    },
+    KnownConfigKey {
+        name: GC_STALE_STATE_EXPIRY_CONFIG_KEY,
+        value_type: ConfigValueType::Duration,
+    },
+    KnownConfigKey {
+        name: GC_WRITE_COMMIT_GRAPH_CONFIG_KEY,
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hide.defaultRecursive",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.cleanCachedTestResults",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.detachedHead",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.hideCherryPickedCommit",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.hideOrphans",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.hideUndo",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.hooksNotInstalled",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.moveImplicitHeadArgument",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.restackWarnAbandoned",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.rewordUndo",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.smartlogFixAbandoned",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.syncMainBranchBehind",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.hint.testShowVerbose",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: POST_HIDE_USER_HOOK_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: POST_REWRITE_USER_HOOK_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: POST_UNDO_USER_HOOK_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.init.aliases",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: SAPLING_ALIASES_CONFIG_KEY,
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: ISSUES_STATUS_COMMAND_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: ISSUES_TRAILERS_CONFIG_KEY,
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.mainBranch",
+        value_type: ConfigValueType::Branch,
+    },
+    KnownConfigKey {
+        name: "branchless.move.defaultInMemory",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.navigation.autoSwitchBranches",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.next.defaultNewest",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.next.interactive",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: PRE_PUSH_OBSOLETE_COMMITS_CONFIG_KEY,
+        value_type: ConfigValueType::OneOf(&["warn", "block"]),
+    },
+    KnownConfigKey {
+        name: "branchless.restack.preserveTimestamps",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.restack.promptAfterAmend",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.restack.resolveConflictsInteractively",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: RESTACK_WARN_ABANDONED_CONFIG_KEY,
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.revsets.alias.<name>",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.smartlog.highlightChanges",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.smartlog.showAfterCommand",
+        value_type: ConfigValueType::Bool,
+    },
+    KnownConfigKey {
+        name: "branchless.submit.forge",
+        value_type: ConfigValueType::OneOf(&[
+            "branch",
+            "github",
+            "gitlab",
+            "gerrit",
+            "phabricator",
+        ]),
+    },
+    KnownConfigKey {
+        name: "branchless.submit.gitlab.project",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.submit.gitlab.url",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.test.alias.<name>",
+        value_type: ConfigValueType::String,
+    },
+    KnownConfigKey {
+        name: "branchless.test.jobs",
+        value_type: ConfigValueType::Integer,
+    },
+    KnownConfigKey {
+        name: "branchless.test.strategy",
+        value_type: ConfigValueType::OneOf(&["working-copy", "worktree"]),
+    },
+    KnownConfigKey {
+        name: "branchless.theme.annotation",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.branch",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.changeBranchMoved",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.changeCreated",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.changeHidden",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.changeRewritten",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.ciFailed",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.ciPassed",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.ciPending",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.commitOid",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.differentialRevision",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.glyphs",
+        value_type: ConfigValueType::OneOf(&["auto", "unicode", "ascii"]),
+    },
+    KnownConfigKey {
+        name: "branchless.theme.issue",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.merge",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.relativeTime",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.tui.<name>",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.theme.worktree",
+        value_type: ConfigValueType::Style,
+    },
+    KnownConfigKey {
+        name: "branchless.undo.createSnapshots",
+        value_type: ConfigValueType::Bool,
+    },
+];
+
+/// Shorter names which can be used for some config keys with `git branchless
+/// config`.
+const CONFIG_KEY_SHORTHANDS: &[(&str, &str)] = &[("main-branch", "branchless.core.mainBranch")];
+
+/// Look up the given key in [`KNOWN_CONFIG_KEYS`]. The `branchless.` prefix
+/// may be omitted, and section and key names are matched case-insensitively, as
+/// in Git. Returns the canonical spelling of the key along with its entry, if
+/// found.
+pub fn find_known_config_key(key: &str) -> Option<(String, &'static KnownConfigKey)> {
+    let key = CONFIG_KEY_SHORTHANDS
+        .iter()
+        .find(|(shorthand, _)| *shorthand == key)
+        .map(|(_, key)| *key)
+        .unwrap_or(key);
+    let key = if key.to_lowercase().starts_with("branchless.") {
+        key.to_owned()
+    } else {
+        format!("branchless.{key}")
+    };
+    KNOWN_CONFIG_KEYS
+        .iter()
+        .find_map(|known_key| match known_key.name.strip_suffix("<name>") {
+            Some(prefix) => {
+                let key_prefix = key.get(..prefix.len())?;
+                let suffix = key.get(prefix.len()..)?;
+                if key_prefix.eq_ignore_ascii_case(prefix) && !suffix.is_empty() {
+                    Some((format!("{prefix}{suffix}"), known_key))
+                } else {
+                    None
+                }
+            }
+            None => {
+                if key.eq_ignore_ascii_case(known_key.name) {
+                    Some((known_key.name.to_owned(), known_key))
+                } else {
+                    None
+                }
+            }
+        })
+}
+
+/// Find the known config key which the given (unknown) key is most likely a
+/// misspelling of, if any.
+pub fn suggest_known_config_key(key: &str) -> Option<&'static str> {
+    let key = key.to_lowercase();
+    let key = key.strip_prefix("branchless.").unwrap_or(&key);
+    KNOWN_CONFIG_KEYS
+        .iter()
+        .map(|known_key| {
+            let name = known_key.name.to_lowercase();
+            let name = name.strip_prefix("branchless.").unwrap_or(&name);
+            (strsim::jaro(key, name), known_key.name)
+        })
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .max_by(|(lhs, _), (rhs, _)| lhs.partial_cmp(rhs).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, name)| name)
+}
+
 /// Environment variables which affect the functioning of `git-branchless`.
 pub mod env_vars {
     use std::path::PathBuf;
@@ -327,6 +1492,53 @@ pub mod env_vars {
     /// manually.
     pub const TEST_SEPARATE_COMMAND_BINARIES: &str = "TEST_SEPARATE_COMMAND_BINARIES";
 
+    /// Overrides the `branchless.core.mainBranch` config setting.
+    pub const GIT_BRANCHLESS_MAIN_BRANCH: &str = "GIT_BRANCHLESS_MAIN_BRANCH";
+
+    /// Overrides whether to display terminal colors, as with the `--color`
+    /// option. One of `auto`, `always`, or `never`. The `--color` option takes
+    /// precedence, if passed.
+    pub const GIT_BRANCHLESS_COLOR: &str = "GIT_BRANCHLESS_COLOR";
+
+    /// If set to a non-empty value other than `0` or `false`, print a summary
+    /// of the time spent in each phase of the command when it exits, as with
+    /// the `--trace` option.
+    pub const GIT_BRANCHLESS_TRACE: &str = "GIT_BRANCHLESS_TRACE";
+
+    /// If set to a non-empty value other than `0` or `false`, skip
+    /// confirmation prompts before destructive operations, as with the `--yes`
+    /// option.
+    pub const GIT_BRANCHLESS_ASSUME_YES: &str = "GIT_BRANCHLESS_ASSUME_YES";
+
+    /// If set to a non-empty value other than `0` or `false`, produce plain
+    /// output without Unicode glyphs, colors, or progress indicators, as with
+    /// the `--plain` option. If set to `0` or `false`, overrides the
+    /// `branchless.core.plain` config setting.
+    pub const GIT_BRANCHLESS_PLAIN: &str = "GIT_BRANCHLESS_PLAIN";
+
+    /// How much output to produce, as with the `--quiet` and `--verbose`
+    /// options: `-1` for quiet output, or the number of times `--verbose` was
+    /// passed. Set for the subprocesses of `git-branchless` so that its hooks
+    /// use the same verbosity. See
+    /// [`get_inherited_verbosity`](super::get_inherited_verbosity).
+    pub const GIT_BRANCHLESS_VERBOSITY: &str = "GIT_BRANCHLESS_VERBOSITY";
+
+    /// If set to a non-empty value other than `0` or `false`, produce output
+    /// which is the same across runs and machines, as with the
+    /// `--deterministic` option. See
+    /// [`get_deterministic`](super::get_deterministic).
+    pub const GIT_BRANCHLESS_DETERMINISTIC: &str = "GIT_BRANCHLESS_DETERMINISTIC";
+
+    /// If set to a non-empty value other than `0` or `false`, send the output
+    /// of commands which use a pager to it even if `pager.<command>` is set to
+    /// `false`, as with the `--paginate` option.
+    pub const GIT_BRANCHLESS_PAGINATE: &str = "GIT_BRANCHLESS_PAGINATE";
+
+    /// Overrides the path to the SQLite database storing the event log and
+    /// other `git-branchless` state, which is usually stored in the
+    /// repository's `branchless` directory.
+    pub const GIT_BRANCHLESS_DB_PATH: &str = "GIT_BRANCHLESS_DB_PATH";
+
     /// Get the path to the Git executable for testing.
     #[instrument]
     pub fn get_path_to_git() -> eyre::Result<PathBuf> {
@@ -373,3 +1585,35 @@ or set `env.{0}` in your `config.toml` \
             .any(|value| value.strip_prefix("git-branchless-").unwrap_or(value) == program)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_config_keys_complete() {
+        let source = include_str!("config.rs");
+        let regex = regex::Regex::new(
+            r#"\.get(?:_or|_or_else)?(?:::<[^>]*>)?\(\s*(?:&?format!\(\s*)?"(branchless\.[^"{]+)(\{[^"]*)?"\s*[,)]"#,
+        )
+        .unwrap();
+        let keys: Vec<String> = regex
+            .captures_iter(source)
+            .map(|captures| {
+                // Keys built with `format!` end in a placeholder, which
+                // corresponds to a `<name>` entry in the table.
+                match captures.get(2) {
+                    Some(_) => format!("{}name", &captures[1]),
+                    None => captures[1].to_owned(),
+                }
+            })
+            .collect();
+        assert!(keys.contains(&"branchless.undo.createSnapshots".to_owned()));
+        for key in keys {
+            assert!(
+                find_known_config_key(&key).is_some(),
+                "{key} is missing from KNOWN_CONFIG_KEYS"
+            );
+        }
+    }
+}
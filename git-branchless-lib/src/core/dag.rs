@@ -2,10 +2,12 @@
 //! allows for efficient graph queries.
 
 use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
+use std::path::Path;
 
 use eden_dag::ops::DagPersistent;
 use eden_dag::DagAlgorithm;
@@ -14,9 +16,11 @@ use itertools::Itertools;
 use once_cell::sync::OnceCell;
 use tracing::{instrument, trace, warn};
 
+use crate::core::child_index::ChildIndexDb;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventReplayer};
-use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
+use crate::core::merge_base::MergeBaseDb;
+use crate::git::{Commit, CommitGraph, MaybeZeroOid, NonZeroOid, Repo, Time};
 
 use super::repo_ext::RepoReferencesSnapshot;
 
@@ -92,6 +96,10 @@ pub fn union_all(commits: &[CommitSet]) -> CommitSet {
 
 /// Interface to access the directed acyclic graph (DAG) representing Git's
 /// commit graph. Based on the Eden SCM DAG.
+///
+/// The DAG is stored in the directory returned by [`Repo::get_dag_dir`]. When
+/// Git's commit-graph file is present, commits' parents are read from it
+/// while syncing.
 pub struct Dag {
     inner: eden_dag::Dag,
 
@@ -141,6 +149,37 @@ impl Dag {
         Ok(dag)
     }
 
+    /// Initialize the DAG for the given repository, and update it with only
+    /// the commits pointed to by `HEAD`, the main branch, and local branches,
+    /// as well as the given `commits`.
+    ///
+    /// This is cheaper than [`Dag::open_and_sync`] for commands which only
+    /// need to look at a few commits, since it doesn't visit every commit
+    /// observed by the `EventReplayer`. However, observed commits which
+    /// aren't otherwise referenced might not be in the DAG, so the resulting
+    /// DAG shouldn't be used to query the visible or draft commits.
+    #[instrument]
+    pub fn open_and_sync_heads(
+        effects: &Effects,
+        repo: &Repo,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        references_snapshot: &RepoReferencesSnapshot,
+        commits: CommitSet,
+    ) -> eyre::Result<Self> {
+        let mut dag = Self::open_without_syncing(
+            effects,
+            repo,
+            event_replayer,
+            event_cursor,
+            references_snapshot,
+        )?;
+        let master_heads = dag.main_branch_commit.clone();
+        let non_master_heads = commits.union(&dag.head_commit).union(&dag.branch_commits);
+        dag.sync_from_oids(effects, repo, master_heads, non_master_heads)?;
+        Ok(dag)
+    }
+
     /// Initialize a DAG for the given repository, without updating it with new
     /// commits that may have appeared.
     ///
@@ -200,12 +239,61 @@ impl Dag {
     #[instrument]
     fn open_inner_dag(repo: &Repo) -> eyre::Result<eden_dag::Dag> {
         let dag_dir = repo.get_dag_dir();
+        Self::reset_if_shallow_boundary_changed(repo, &dag_dir)?;
         std::fs::create_dir_all(&dag_dir).wrap_err("Creating .git/branchless/dag dir")?;
         let dag = eden_dag::Dag::open(&dag_dir)
             .wrap_err_with(|| format!("Opening DAG directory at: {:?}", &dag_dir))?;
         Ok(dag)
     }
 
+    /// In a shallow clone, the commits at the shallow boundary are indexed as
+    /// root commits. If the boundary has changed since the DAG was built (such
+    /// as after `git fetch --deepen` or `--unshallow`), then those commits'
+    /// parents would be missing, and since commits can't be removed from the
+    /// DAG or have their parents changed, the DAG has to be rebuilt from
+    /// scratch instead.
+    #[instrument]
+    fn reset_if_shallow_boundary_changed(repo: &Repo, dag_dir: &Path) -> eyre::Result<()> {
+        let shallow_commits_path = repo.get_dag_shallow_commits_path();
+        let shallow_commits = repo
+            .get_shallow_commit_oids()?
+            .into_iter()
+            .map(|oid| oid.to_string())
+            .sorted()
+            .join("\n");
+        let indexed_shallow_commits = match std::fs::read_to_string(&shallow_commits_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| {
+                    format!("Reading shallow commits file at: {shallow_commits_path:?}")
+                })
+            }
+        };
+        if shallow_commits == indexed_shallow_commits {
+            return Ok(());
+        }
+
+        if dag_dir.exists() {
+            warn!(
+                ?dag_dir,
+                "Shallow clone boundary changed; rebuilding commit graph"
+            );
+            std::fs::remove_dir_all(dag_dir)
+                .wrap_err_with(|| format!("Removing DAG directory at: {dag_dir:?}"))?;
+            // Cached information derived from the old parents is no longer
+            // valid either.
+            let conn = repo.get_db_conn()?;
+            ChildIndexDb::new(&conn)?.clear()?;
+            MergeBaseDb::new(&conn)?.clear()?;
+        }
+        std::fs::create_dir_all(dag_dir).wrap_err("Creating .git/branchless/dag dir")?;
+        std::fs::write(&shallow_commits_path, shallow_commits).wrap_err_with(|| {
+            format!("Writing shallow commits file at: {shallow_commits_path:?}")
+        })?;
+        Ok(())
+    }
+
     /// This function's code adapted from `GitDag`, licensed under GPL-2.
     #[instrument]
     fn sync(&mut self, effects: &Effects, repo: &Repo) -> eyre::Result<()> {
@@ -229,7 +317,17 @@ impl Dag {
         let (effects, _progress) = effects.start_operation(OperationType::UpdateCommitGraph);
         let _effects = effects;
 
-        let parent_func = |v: CommitVertex| -> eden_dag::Result<Vec<CommitVertex>> {
+        // Only read the commit-graph file if we actually need to visit
+        // commits, since the DAG is usually already up-to-date.
+        let commit_graph: OnceCell<Option<CommitGraph>> = OnceCell::new();
+        // Commits at the boundary of a shallow clone are treated as roots, as
+        // Git does, since their parents aren't available.
+        let shallow_commit_oids: OnceCell<HashSet<NonZeroOid>> = OnceCell::new();
+        // The commits visited while syncing, and the edges to their parents,
+        // for updating the child index afterwards.
+        let new_commits: RefCell<HashSet<CommitVertex>> = Default::default();
+        let new_edges: RefCell<Vec<(NonZeroOid, NonZeroOid)>> = Default::default();
+        let get_parents = |v: CommitVertex| -> eden_dag::Result<Vec<CommitVertex>> {
             use eden_dag::errors::BackendError;
             trace!(?v, "visiting Git commit");
 
@@ -241,6 +339,32 @@ impl Dag {
                 MaybeZeroOid::Zero => return Ok(Vec::new()),
             };
 
+            let shallow_commit_oids =
+                shallow_commit_oids.get_or_init(|| match repo.get_shallow_commit_oids() {
+                    Ok(shallow_commit_oids) => shallow_commit_oids.into_iter().collect(),
+                    Err(err) => {
+                        warn!(?err, "Could not read shallow commits");
+                        Default::default()
+                    }
+                });
+            if shallow_commit_oids.contains(&oid) {
+                return Ok(Vec::new());
+            }
+
+            let commit_graph = commit_graph.get_or_init(|| match CommitGraph::open(repo) {
+                Ok(commit_graph) => commit_graph,
+                Err(err) => {
+                    warn!(?err, "Could not open commit-graph file");
+                    None
+                }
+            });
+            if let Some(entry) = commit_graph
+                .as_ref()
+                .and_then(|commit_graph| commit_graph.lookup(oid))
+            {
+                return Ok(entry.parents.into_iter().map(CommitVertex::from).collect());
+            }
+
             let commit = repo
                 .find_commit(oid)
                 .map_err(|_e| anyhow::anyhow!("Could not resolve to Git commit: {:?}", &v))
@@ -260,6 +384,19 @@ impl Dag {
                 .map(CommitVertex::from)
                 .collect())
         };
+        let parent_func = |v: CommitVertex| -> eden_dag::Result<Vec<CommitVertex>> {
+            let parents = get_parents(v.clone())?;
+            if let Ok(child_oid) = NonZeroOid::try_from(v.clone()) {
+                let mut new_edges = new_edges.borrow_mut();
+                for parent in parents.iter() {
+                    if let Ok(parent_oid) = NonZeroOid::try_from(parent.clone()) {
+                        new_edges.push((parent_oid, child_oid));
+                    }
+                }
+            }
+            new_commits.borrow_mut().insert(v);
+            Ok(parents)
+        };
 
         let commit_set_to_vec = |commit_set: CommitSet| -> Vec<CommitVertex> {
             let mut result = Vec::new();
@@ -279,6 +416,24 @@ impl Dag {
             commit_set_to_vec(master_heads).as_slice(),
             commit_set_to_vec(non_master_heads).as_slice(),
         )?;
+
+        let num_new_commits = new_commits.into_inner().len();
+        if num_new_commits > 0 {
+            let result = repo
+                .get_db_conn()
+                .map_err(eyre::Error::from)
+                .and_then(|conn| {
+                    ChildIndexDb::new(&conn)?.add_new_commits(
+                        self,
+                        num_new_commits,
+                        &new_edges.into_inner(),
+                    )
+                });
+            if let Err(err) = result {
+                // The index will be rebuilt the next time it's used.
+                warn!(?err, "Could not update child index");
+            }
+        }
         Ok(())
     }
 
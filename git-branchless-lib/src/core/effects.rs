@@ -5,6 +5,7 @@ use std::convert::TryInto;
 use std::fmt::{Debug, Write};
 use std::io::{stderr, stdout, Stderr, Stdout, Write as WriteIo};
 use std::mem::take;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 use std::{io, thread};
@@ -14,7 +15,12 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use tracing::warn;
 
-use crate::core::formatting::Glyphs;
+use crate::core::config::Verbosity;
+use crate::core::formatting::{Glyphs, Theme};
+use crate::core::i18n::Messages;
+use crate::core::user_error::UserError;
+use crate::git::{MaybeZeroOid, NonZeroOid};
+use crate::util::ExitCode;
 
 #[allow(missing_docs)]
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -23,9 +29,11 @@ pub enum OperationType {
     CalculateDiff,
     CalculatePatchId,
     CheckForCycles,
+    CheckOutCommit,
     ConstrainCommits,
     DetectDuplicateCommits,
     EvaluateRevset(Arc<String>),
+    FetchMissingObjects,
     FilterByTouchedPaths,
     FilterCommits,
     FindPathToMergeBase,
@@ -34,6 +42,7 @@ pub enum OperationType {
     GetUpstreamPatchIds,
     InitializeRebase,
     MakeGraph,
+    MoveCommits,
     ProcessEvents,
     PushBranches,
     QueryWorkingCopy,
@@ -41,11 +50,13 @@ pub enum OperationType {
     RebaseCommits,
     RepairBranches,
     RepairCommits,
+    RestackCommits,
     RunGitCommand(Arc<String>),
     RunTests(Arc<String>),
     RunTestOnCommit(Arc<String>),
     SortCommits,
     SyncCommits,
+    SyncCodeReviews,
     UpdateCommitGraph,
     WalkCommits,
 }
@@ -57,11 +68,13 @@ impl ToString for OperationType {
             OperationType::CalculateDiff => "Computing diffs",
             OperationType::CalculatePatchId => "Hashing commit contents",
             OperationType::CheckForCycles => "Checking for cycles",
+            OperationType::CheckOutCommit => "Checking out commit",
             OperationType::ConstrainCommits => "Creating commit constraints",
             OperationType::DetectDuplicateCommits => "Checking for duplicate commits",
             OperationType::EvaluateRevset(revset) => {
                 return format!("Evaluating revset: {revset}");
             }
+            OperationType::FetchMissingObjects => "Fetching missing objects",
             OperationType::FilterByTouchedPaths => "Filtering upstream commits by touched paths",
             OperationType::FilterCommits => "Filtering commits",
             OperationType::FindPathToMergeBase => "Finding path to merge-base",
@@ -70,6 +83,7 @@ impl ToString for OperationType {
             OperationType::GetUpstreamPatchIds => "Enumerating patch IDs",
             OperationType::InitializeRebase => "Initializing rebase",
             OperationType::MakeGraph => "Examining local history",
+            OperationType::MoveCommits => "Moving commits",
             OperationType::PushBranches => "Pushing branches",
             OperationType::ProcessEvents => "Processing events",
             OperationType::QueryWorkingCopy => "Querying the working copy",
@@ -77,6 +91,7 @@ impl ToString for OperationType {
             OperationType::RebaseCommits => "Rebasing commits",
             OperationType::RepairBranches => "Checking for broken branches",
             OperationType::RepairCommits => "Checking for broken commits",
+            OperationType::RestackCommits => "Restacking commits",
             OperationType::RunGitCommand(command) => {
                 return format!("Running Git command: {}", &command)
             }
@@ -84,6 +99,7 @@ impl ToString for OperationType {
             OperationType::RunTestOnCommit(commit) => return format!("Waiting to test {commit}"),
             OperationType::SortCommits => "Sorting commits",
             OperationType::SyncCommits => "Syncing commit stacks",
+            OperationType::SyncCodeReviews => "Syncing code reviews",
             OperationType::UpdateCommitGraph => "Updating commit graph",
             OperationType::WalkCommits => "Walking commits",
         };
@@ -111,6 +127,10 @@ type OperationKey = [OperationType];
 struct RootOperation {
     multi_progress: MultiProgress,
     children: Vec<OperationState>,
+
+    /// If set, the progress indicators are never shown (see
+    /// [`Effects::plain`]).
+    is_progress_disabled: bool,
 }
 
 impl RootOperation {
@@ -120,10 +140,18 @@ impl RootOperation {
     }
 
     pub fn show_multi_progress(&mut self) {
+        if self.is_progress_disabled {
+            return;
+        }
         self.multi_progress
             .set_draw_target(ProgressDrawTarget::stderr());
     }
 
+    pub fn disable_multi_progress(&mut self) {
+        self.is_progress_disabled = true;
+        self.hide_multi_progress();
+    }
+
     /// If all operations are no longer in progress, clear the multi-progress bar.
     pub fn clear_operations_if_finished(&mut self) {
         if self
@@ -254,7 +282,7 @@ pub mod icons {
 }
 
 /// An icon denoting the status of an operation.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OperationIcon {
     /// A suitable waiting icon should be rendered.
     InProgress,
@@ -423,24 +451,165 @@ impl OperationState {
             },
         );
         self.progress_bar.set_prefix("  ".repeat(nesting_level));
-        self.progress_bar.set_message(format!(
-            "{} ({:.1}s)",
-            self.progress_message,
-            elapsed_duration.as_secs_f64(),
-        ));
+        let remaining_duration = if self.has_meter {
+            estimate_remaining_duration(
+                elapsed_duration,
+                self.progress_bar.position(),
+                self.progress_bar.length(),
+            )
+        } else {
+            None
+        };
+        self.progress_bar.set_message(match remaining_duration {
+            Some(remaining_duration) => format!(
+                "{} ({:.1}s, ETA {})",
+                self.progress_message,
+                elapsed_duration.as_secs_f64(),
+                render_eta(remaining_duration),
+            ),
+            None => format!(
+                "{} ({:.1}s)",
+                self.progress_message,
+                elapsed_duration.as_secs_f64(),
+            ),
+        });
         self.progress_bar.tick();
     }
 }
 
+/// Estimate how much longer an operation will take, assuming that the
+/// remaining steps take as long on average as the steps completed so far.
+/// Returns `None` if there isn't enough information to make an estimate.
+fn estimate_remaining_duration(
+    elapsed_duration: Duration,
+    current: u64,
+    total: Option<u64>,
+) -> Option<Duration> {
+    let total = total?;
+    if current == 0 || current >= total {
+        return None;
+    }
+    let remaining = u32::try_from(total - current).ok()?;
+    let current = u32::try_from(current).ok()?;
+    Some(elapsed_duration / current * remaining)
+}
+
+/// Render a duration compactly, such as `45s` or `2m05s`.
+fn render_eta(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{secs}s")
+    } else {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// A structured event reported to the observers registered with
+/// [`Effects::with_observer`], as an alternative to parsing the text output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OperationEvent {
+    /// An operation started. The key is the path of nested operations, ending
+    /// with the operation which started.
+    Started {
+        /// The key of the operation.
+        operation_key: Vec<OperationType>,
+    },
+
+    /// The operation has `total` units of work, and has completed `current`
+    /// of them.
+    Progress {
+        /// The key of the operation.
+        operation_key: Vec<OperationType>,
+
+        /// The number of units of work completed.
+        current: usize,
+
+        /// The total number of units of work.
+        total: usize,
+    },
+
+    /// The operation completed another `increment` units of work.
+    ProgressIncremented {
+        /// The key of the operation.
+        operation_key: Vec<OperationType>,
+
+        /// The number of units of work completed since the last event.
+        increment: usize,
+    },
+
+    /// The status message of the operation changed.
+    Status {
+        /// The key of the operation.
+        operation_key: Vec<OperationType>,
+
+        /// The icon of the new status, which indicates whether the operation
+        /// succeeded.
+        icon: OperationIcon,
+
+        /// The new status message.
+        message: String,
+    },
+
+    /// An operation finished.
+    Finished {
+        /// The key of the operation.
+        operation_key: Vec<OperationType>,
+    },
+
+    /// A commit was rewritten by a rebase. Only reported for in-memory rebases,
+    /// since on-disk rebases are carried out by Git itself.
+    CommitRewritten {
+        /// The OID of the original commit.
+        old_commit_oid: NonZeroOid,
+
+        /// The OID of the rewritten commit, or zero if the commit was skipped.
+        new_commit_oid: MaybeZeroOid,
+    },
+
+    /// A rebase succeeded.
+    RebaseSucceeded,
+
+    /// A rebase stopped because a commit couldn't be merged, and it wasn't
+    /// requested to resolve merge conflicts.
+    RebaseDeclinedToMerge {
+        /// The commit which couldn't be merged.
+        commit_oid: NonZeroOid,
+
+        /// The paths which were in conflict, if any, in sorted order.
+        conflicting_paths: Vec<PathBuf>,
+    },
+
+    /// A rebase failed.
+    RebaseFailed {
+        /// The exit code which the rebase failed with.
+        exit_code: ExitCode,
+    },
+
+    /// A failure was reported to the user with [`UserError::report`].
+    UserError(UserError),
+}
+
+/// Receives the [`OperationEvent`]s of an [`Effects`] and its descendants, such
+/// as to report the progress of a `move`, `restack` or `sync` to another
+/// program. Events are reported regardless of where the text output is sent.
+pub trait OperationObserver: Send + Sync {
+    /// Handle the given event. This is called synchronously on the thread
+    /// which caused the event, so it should return quickly.
+    fn on_event(&self, event: &OperationEvent);
+}
+
 /// Wrapper around side-effectful operations, such as output and progress
 /// indicators.
 #[derive(Clone)]
 pub struct Effects {
     glyphs: Glyphs,
+    messages: Arc<Messages>,
     dest: OutputDest,
     updater_thread_handle: Arc<RwLock<UpdaterThreadHandle>>,
     operation_key: Vec<OperationType>,
     root_operation: Arc<Mutex<RootOperation>>,
+    observers: Vec<Arc<dyn OperationObserver>>,
+    verbosity: Verbosity,
 }
 
 impl std::fmt::Debug for Effects {
@@ -512,10 +681,13 @@ impl Effects {
         let updater_thread_handle = spawn_progress_updater_thread(&root_operation);
         Effects {
             glyphs,
+            messages: Default::default(),
             dest: OutputDest::Stdout,
             updater_thread_handle,
             operation_key: Default::default(),
             root_operation,
+            observers: Default::default(),
+            verbosity: Verbosity::Normal,
         }
     }
 
@@ -523,10 +695,13 @@ impl Effects {
     pub fn new_suppress_for_test(glyphs: Glyphs) -> Self {
         Effects {
             glyphs,
+            messages: Default::default(),
             dest: OutputDest::Suppress,
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            observers: Default::default(),
+            verbosity: Verbosity::Normal,
         }
     }
 
@@ -538,6 +713,7 @@ impl Effects {
     ) -> Self {
         Effects {
             glyphs,
+            messages: Default::default(),
             dest: OutputDest::BufferForTest {
                 stdout: Arc::clone(stdout),
                 stderr: Arc::clone(stderr),
@@ -545,6 +721,8 @@ impl Effects {
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            observers: Default::default(),
+            verbosity: Verbosity::Normal,
         }
     }
 
@@ -559,6 +737,29 @@ impl Effects {
         }
     }
 
+    /// Produce plain output with the returned `Effects`, for the sake of screen
+    /// readers, dumb terminals, and logs: graphs are drawn with ASCII
+    /// characters, colors are disabled, and progress indicators are never
+    /// shown, so that output is only ever written a whole line at a time.
+    pub fn plain(&self) -> Self {
+        Self {
+            glyphs: Glyphs {
+                theme: self.glyphs.theme.clone(),
+                ..Glyphs::text()
+            },
+            ..self.without_progress()
+        }
+    }
+
+    /// Never show progress indicators with the returned `Effects`, since they
+    /// include the elapsed time of each operation. Used for deterministic
+    /// output.
+    pub fn without_progress(&self) -> Self {
+        let mut root_operation = self.root_operation.lock().unwrap();
+        root_operation.disable_multi_progress();
+        self.clone()
+    }
+
     /// Suppress output sent to the returned `Effects`.
     pub fn suppress(&self) -> Self {
         Self {
@@ -576,6 +777,51 @@ impl Effects {
         }
     }
 
+    /// Style the output of the returned `Effects` using the given theme.
+    pub fn with_theme(&self, theme: Theme) -> Self {
+        Self {
+            glyphs: self.glyphs.clone().with_theme(theme),
+            ..self.clone()
+        }
+    }
+
+    /// Translate the user-facing messages of the returned `Effects` using the
+    /// given catalog.
+    pub fn with_messages(&self, messages: Messages) -> Self {
+        Self {
+            messages: Arc::new(messages),
+            ..self.clone()
+        }
+    }
+
+    /// Set the verbosity of the returned `Effects`, as with the global
+    /// `--quiet` and `--verbose` options.
+    pub fn with_verbosity(&self, verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            ..self.clone()
+        }
+    }
+
+    /// Report the [`OperationEvent`]s of the returned `Effects`, and of the
+    /// `Effects` derived from it, to the given observer in addition to any
+    /// observers which were already registered.
+    pub fn with_observer(&self, observer: Arc<dyn OperationObserver>) -> Self {
+        let mut observers = self.observers.clone();
+        observers.push(observer);
+        Self {
+            observers,
+            ..self.clone()
+        }
+    }
+
+    /// Report the given event to the registered observers.
+    pub fn notify_observers(&self, event: OperationEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
     /// Start reporting progress for the specified operation type.
     ///
     /// A progress spinner is shown until the returned `ProgressHandle` is
@@ -605,11 +851,18 @@ impl Effects {
             effects: self,
             operation_key: operation_key.clone(),
         };
+        self.notify_observers(OperationEvent::Started {
+            operation_key: operation_key.clone(),
+        });
+        // Nest the operations of the returned `Effects` even when progress
+        // isn't displayed, so that observers see the hierarchy.
+        let effects = Self {
+            operation_key: operation_key.clone(),
+            ..self.clone()
+        };
         match self.dest {
             OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => {
-                return (self.clone(), progress)
-            }
+            OutputDest::Suppress | OutputDest::BufferForTest { .. } => return (effects, progress),
         }
 
         let now = Instant::now();
@@ -617,15 +870,15 @@ impl Effects {
         let operation_state = root_operation.get_or_create_child(&operation_key);
         operation_state.start_times.push(now);
         root_operation.refresh_multi_progress();
-
-        let effects = Self {
-            operation_key,
-            ..self.clone()
-        };
         (effects, progress)
     }
 
     fn on_notify_progress(&self, operation_key: &OperationKey, current: usize, total: usize) {
+        self.notify_observers(OperationEvent::Progress {
+            operation_key: operation_key.to_vec(),
+            current,
+            total,
+        });
         match self.dest {
             OutputDest::Stdout => {}
             OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
@@ -637,6 +890,10 @@ impl Effects {
     }
 
     fn on_notify_progress_inc(&self, operation_key: &OperationKey, increment: usize) {
+        self.notify_observers(OperationEvent::ProgressIncremented {
+            operation_key: operation_key.to_vec(),
+            increment,
+        });
         match self.dest {
             OutputDest::Stdout => {}
             OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
@@ -652,6 +909,11 @@ impl Effects {
     }
 
     fn on_set_message(&self, operation_key: &OperationKey, icon: OperationIcon, message: String) {
+        self.notify_observers(OperationEvent::Status {
+            operation_key: operation_key.to_vec(),
+            icon,
+            message: message.clone(),
+        });
         match self.dest {
             OutputDest::Stdout => {}
             OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
@@ -668,6 +930,9 @@ impl Effects {
     }
 
     fn on_drop_progress_handle(&self, operation_key: &OperationKey) {
+        self.notify_observers(OperationEvent::Finished {
+            operation_key: operation_key.to_vec(),
+        });
         match self.dest {
             OutputDest::Stdout => {}
             OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
@@ -721,6 +986,17 @@ impl Effects {
         &self.glyphs
     }
 
+    /// Get the catalog used to translate user-facing messages.
+    pub fn get_messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    /// Get the verbosity of the output, as set with the global `--quiet` and
+    /// `--verbose` options.
+    pub fn get_verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
     /// Create a stream that can be written to. The output might go to stdout or
     /// be rendered specially in the terminal.
     pub fn get_output_stream(&self) -> OutputStream {
@@ -1034,6 +1310,20 @@ impl ProgressHandle<'_> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_estimate_remaining_duration() {
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(estimate_remaining_duration(elapsed, 0, Some(4)), None);
+        assert_eq!(estimate_remaining_duration(elapsed, 4, Some(4)), None);
+        assert_eq!(estimate_remaining_duration(elapsed, 1, None), None);
+        assert_eq!(
+            estimate_remaining_duration(elapsed, 2, Some(5)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(render_eta(Duration::from_secs(15)), "15s");
+        assert_eq!(render_eta(Duration::from_secs(125)), "2m05s");
+    }
+
     #[test]
     fn test_effects_progress() -> eyre::Result<()> {
         let effects = Effects::new(Glyphs::text());
@@ -1096,6 +1386,58 @@ mod tests {
         Ok(())
     }
 
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<OperationEvent>>,
+    }
+
+    impl OperationObserver for RecordingObserver {
+        fn on_event(&self, event: &OperationEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_effects_observer() -> eyre::Result<()> {
+        let observer = Arc::new(RecordingObserver::default());
+        let effects =
+            Effects::new_suppress_for_test(Glyphs::text())
+                .with_observer(Arc::<RecordingObserver>::clone(&observer));
+        {
+            let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
+            progress.notify_progress(1, 2);
+            let (_effects, _progress) = effects.start_operation(OperationType::CheckOutCommit);
+        }
+        effects.notify_observers(OperationEvent::RebaseSucceeded);
+
+        let rebase_commits = vec![OperationType::RebaseCommits];
+        let check_out_commit = vec![OperationType::RebaseCommits, OperationType::CheckOutCommit];
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            vec![
+                OperationEvent::Started {
+                    operation_key: rebase_commits.clone()
+                },
+                OperationEvent::Progress {
+                    operation_key: rebase_commits.clone(),
+                    current: 1,
+                    total: 2
+                },
+                OperationEvent::Started {
+                    operation_key: check_out_commit.clone()
+                },
+                OperationEvent::Finished {
+                    operation_key: check_out_commit
+                },
+                OperationEvent::Finished {
+                    operation_key: rebase_commits
+                },
+                OperationEvent::RebaseSucceeded,
+            ]
+        );
+        Ok(())
+    }
+
     /// Test for the issue fixed by <https://github.com/console-rs/indicatif/pull/403>.
     #[test]
     fn test_effects_progress_rewind_panic() -> eyre::Result<()> {
@@ -4,16 +4,27 @@
 //! them in persistent storage. Later, we play back the actions in order to
 //! determine what actions the user took on the repository, and which commits
 //! they're still working on.
+//!
+//! Other tools can read the event log without modifying it by opening the
+//! database with [`Repo::get_db_conn_read_only`] and
+//! [`EventLogDb::new_read_only`]. [`EventLogDb::get_events`] returns the
+//! recorded events, and an [`EventReplayer`] answers whether each commit is
+//! visible or obsolete as of a given [`EventCursor`]. To find the commit which
+//! an obsolete commit was rewritten into, see
+//! [`find_rewrite_target`](crate::core::rewrite::find_rewrite_target).
 
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
-
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
-use tracing::{error, instrument};
+use itertools::Itertools;
+use tracing::{error, instrument, warn};
 
 use crate::core::effects::{Effects, OperationType};
 use crate::core::repo_ext::RepoExt;
@@ -25,6 +36,12 @@ use super::repo_ext::RepoReferencesSnapshot;
 /// which the caller has already started.
 pub const BRANCHLESS_TRANSACTION_ID_ENV_VAR: &str = "BRANCHLESS_TRANSACTION_ID";
 
+/// Set alongside [`BRANCHLESS_TRANSACTION_ID_ENV_VAR`] by `git branchless wrap`
+/// to the path of the database which the transaction belongs to. A nested
+/// command only joins the transaction if it uses the same database, since the
+/// wrapped program may also run commands in other repositories.
+pub const BRANCHLESS_TRANSACTION_DB_ENV_VAR: &str = "BRANCHLESS_TRANSACTION_DB";
+
 // Wrapper around the row stored directly in the database.
 #[derive(Clone, Debug)]
 struct Row {
@@ -35,6 +52,7 @@ struct Row {
     ref2: Option<ReferenceName>,
     ref_name: Option<ReferenceName>,
     message: Option<ReferenceName>,
+    reason: Option<String>,
 }
 
 /// The ID associated with the transactions that created an event.
@@ -53,9 +71,17 @@ struct Row {
 ///
 /// Unlike in a database, there is no specific guarantee that an event
 /// transaction is an atomic unit of work.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct EventTransactionId(isize);
 
+impl EventTransactionId {
+    /// Get the numeric ID of the transaction, as stored in the event log.
+    pub fn get_id(&self) -> isize {
+        let EventTransactionId(event_id) = self;
+        *event_id
+    }
+}
+
 impl ToString for EventTransactionId {
     fn to_string(&self) -> String {
         let EventTransactionId(event_id) = self;
@@ -72,6 +98,17 @@ impl FromStr for EventTransactionId {
     }
 }
 
+/// The operation which created a commit based on another commit, as recorded in
+/// an [`Event::CopyEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyKind {
+    /// The commit was created by `git cherry-pick`.
+    CherryPick,
+
+    /// The commit was created by `git revert`.
+    Revert,
+}
+
 /// An event that occurred to one of the commits in the repository.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Event {
@@ -149,6 +186,9 @@ pub enum Event {
 
         /// The OID of the commit that was obsoleted.
         commit_oid: NonZeroOid,
+
+        /// The reason provided by the user for obsoleting the commit, if any.
+        message: Option<String>,
     },
 
     /// Indicates that a commit was explicitly un-obsoleted by the user.
@@ -166,6 +206,28 @@ pub enum Event {
         commit_oid: NonZeroOid,
     },
 
+    /// Indicates that a commit was copied by `git cherry-pick` or reverted by
+    /// `git revert`.
+    ///
+    /// Unlike a rewrite, this doesn't make the original commit obsolete, since
+    /// the user may still want to keep working on it.
+    CopyEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the commit which was cherry-picked or reverted.
+        original_commit_oid: NonZeroOid,
+
+        /// The OID of the commit which was created by the operation.
+        new_commit_oid: NonZeroOid,
+
+        /// The operation which created the new commit.
+        kind: CopyKind,
+    },
+
     /// Represents a snapshot of the working copy made at a certain time,
     /// typically before a potentially-destructive operation.
     WorkingCopySnapshot {
@@ -197,6 +259,7 @@ impl Event {
             Event::CommitEvent { timestamp, .. } => timestamp,
             Event::ObsoleteEvent { timestamp, .. } => timestamp,
             Event::UnobsoleteEvent { timestamp, .. } => timestamp,
+            Event::CopyEvent { timestamp, .. } => timestamp,
             Event::WorkingCopySnapshot { timestamp, .. } => timestamp,
         };
         SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp)
@@ -210,6 +273,7 @@ impl Event {
             Event::CommitEvent { event_tx_id, .. } => *event_tx_id,
             Event::ObsoleteEvent { event_tx_id, .. } => *event_tx_id,
             Event::UnobsoleteEvent { event_tx_id, .. } => *event_tx_id,
+            Event::CopyEvent { event_tx_id, .. } => *event_tx_id,
             Event::WorkingCopySnapshot { event_tx_id, .. } => *event_tx_id,
         }
     }
@@ -231,6 +295,7 @@ impl From<Event> for Row {
                 ref2: Some(new_commit_oid.into()),
                 ref_name: None,
                 message: None,
+                reason: None,
             },
 
             Event::RefUpdateEvent {
@@ -248,6 +313,7 @@ impl From<Event> for Row {
                 ref2: Some(new_oid.into()),
                 ref_name: Some(ref_name),
                 message,
+                reason: None,
             },
 
             Event::CommitEvent {
@@ -262,12 +328,14 @@ impl From<Event> for Row {
                 ref2: None,
                 ref_name: None,
                 message: None,
+                reason: None,
             },
 
             Event::ObsoleteEvent {
                 timestamp,
                 event_tx_id: EventTransactionId(event_tx_id),
                 commit_oid,
+                message,
             } => Row {
                 timestamp,
                 event_tx_id,
@@ -276,6 +344,7 @@ impl From<Event> for Row {
                 ref2: None,
                 ref_name: None,
                 message: None,
+                reason: message,
             },
 
             Event::UnobsoleteEvent {
@@ -290,6 +359,27 @@ impl From<Event> for Row {
                 ref2: None,
                 ref_name: None,
                 message: None,
+                reason: None,
+            },
+
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                original_commit_oid,
+                new_commit_oid,
+                kind,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from(match kind {
+                    CopyKind::CherryPick => "cherry-pick",
+                    CopyKind::Revert => "revert",
+                }),
+                ref1: Some(original_commit_oid.into()),
+                ref2: Some(new_commit_oid.into()),
+                ref_name: None,
+                message: None,
+                reason: None,
             },
 
             Event::WorkingCopySnapshot {
@@ -306,6 +396,7 @@ impl From<Event> for Row {
                 ref2: Some(commit_oid.into()),
                 ref_name,
                 message: None,
+                reason: None,
             },
         }
     }
@@ -321,6 +412,7 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
         ref1,
         ref2,
         message,
+        reason,
     } = row;
     let event_tx_id = EventTransactionId(event_tx_id);
 
@@ -381,6 +473,8 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
                 timestamp,
                 event_tx_id,
                 commit_oid,
+                // Older versions stored the reason in the `message` column.
+                message: reason.or_else(|| message.map(|message| message.as_str().to_owned())),
             }
         }
 
@@ -393,6 +487,23 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
             }
         }
 
+        "cherry-pick" | "revert" => {
+            let original_commit_oid: NonZeroOid =
+                get_oid(&ref1, "original commit OID")?.try_into()?;
+            let new_commit_oid: NonZeroOid = get_oid(&ref2, "new commit OID")?.try_into()?;
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id,
+                original_commit_oid,
+                new_commit_oid,
+                kind: if type_ == "revert" {
+                    CopyKind::Revert
+                } else {
+                    CopyKind::CherryPick
+                },
+            }
+        }
+
         "snapshot" => {
             let head_oid: MaybeZeroOid = get_oid(&ref1, "head OID")?;
             let commit_oid: NonZeroOid = get_oid(&ref2, "commit OID")?.try_into()?;
@@ -424,6 +535,23 @@ impl TryFrom<Row> for Event {
     }
 }
 
+/// How many times to attempt a write to the event log before giving up, if
+/// the database is busy.
+const DB_WRITE_ATTEMPTS: u32 = 5;
+
+/// Determine whether `err` was caused by another connection holding a lock on
+/// the database.
+fn is_database_busy(err: &eyre::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause
+                .downcast_ref::<rusqlite::Error>()
+                .and_then(|err| err.sqlite_error_code()),
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
 /// Stores `Event`s on disk.
 pub struct EventLogDb<'conn> {
     conn: &'conn rusqlite::Connection,
@@ -453,6 +581,16 @@ CREATE TABLE IF NOT EXISTS event_log (
     )
     .wrap_err("Creating `event_log` table")?;
 
+    // The `reason` column was added after the table was created, so add it to
+    // existing databases.
+    if !has_reason_column(conn)? {
+        conn.execute(
+            "ALTER TABLE event_log ADD COLUMN reason TEXT",
+            rusqlite::params![],
+        )
+        .wrap_err("Adding `reason` column to `event_log` table")?;
+    }
+
     conn.execute(
         "
 CREATE TABLE IF NOT EXISTS event_transactions (
@@ -474,61 +612,278 @@ CREATE TABLE IF NOT EXISTS event_transactions (
     Ok(())
 }
 
+/// Whether the `event_log` table has the `reason` column yet (see
+/// `init_tables`). Databases opened read-only might not.
+fn has_reason_column(conn: &rusqlite::Connection) -> eyre::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('event_log') WHERE name = 'reason'",
+        rusqlite::params![],
+        |row| row.get(0),
+    )
+    .wrap_err("Checking for `reason` column in `event_log` table")
+}
+
+/// The message for the transactions which record spooled reference updates.
+const REF_UPDATE_SPOOL_TRANSACTION_MESSAGE: &str = "reference-transaction";
+
+/// A reference update to be spooled: the name of the reference, and its old and
+/// new OIDs.
+pub type SpooledRefUpdate = (ReferenceName, MaybeZeroOid, MaybeZeroOid);
+
+/// Get the directory where reference updates are spooled for the database at
+/// `db_path` (see [`spool_ref_update_events`]).
+pub fn get_ref_update_spool_dir_path(db_path: &Path) -> PathBuf {
+    let mut file_name = db_path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push("-spool");
+    db_path.with_file_name(file_name)
+}
+
+/// Save reference updates to be recorded in the database at `db_path` the next
+/// time that it's opened, rather than recording them immediately.
+///
+/// The `reference-transaction` hook runs once for every reference transaction,
+/// so operations like fetching many branches or deleting branches in a loop
+/// would otherwise open and write to the database hundreds of times. Each call
+/// writes one file, which becomes one event transaction when it's recorded.
+#[instrument(skip(ref_updates))]
+pub fn spool_ref_update_events(
+    db_path: &Path,
+    now: SystemTime,
+    ref_updates: &[SpooledRefUpdate],
+) -> eyre::Result<()> {
+    let spool_dir = get_ref_update_spool_dir_path(db_path);
+    std::fs::create_dir_all(&spool_dir).wrap_err("Creating reference update spool directory")?;
+
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating timestamp")?;
+    let mut contents = format!("{}\n", timestamp.as_secs_f64());
+    for (ref_name, old_oid, new_oid) in ref_updates {
+        writeln!(contents, "{} {} {}", old_oid, new_oid, ref_name.as_str())?;
+    }
+
+    // Spooled files are recorded in order of their names. Write to a hidden
+    // file first so that a partially-written file is never recorded.
+    let file_name = format!("{:020}-{}", timestamp.as_nanos(), std::process::id());
+    let temp_path = spool_dir.join(format!(".{}.tmp", file_name));
+    std::fs::write(&temp_path, contents).wrap_err("Writing spooled reference updates")?;
+    std::fs::rename(&temp_path, spool_dir.join(file_name))
+        .wrap_err("Moving spooled reference updates into place")?;
+    Ok(())
+}
+
+fn parse_spooled_ref_updates(contents: &str) -> Option<(f64, Vec<SpooledRefUpdate>)> {
+    let mut lines = contents.lines();
+    let timestamp = lines.next()?.parse().ok()?;
+    let ref_updates = lines
+        .map(
+            |line| match line.splitn(3, ' ').collect::<Vec<_>>().as_slice() {
+                [old_oid, new_oid, ref_name] => Some((
+                    ReferenceName::from(*ref_name),
+                    old_oid.parse().ok()?,
+                    new_oid.parse().ok()?,
+                )),
+                _ => None,
+            },
+        )
+        .collect::<Option<Vec<_>>>()?;
+    Some((timestamp, ref_updates))
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[instrument]
     pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
         init_tables(conn)?;
+        let event_log_db = EventLogDb { conn };
+        event_log_db.flush_ref_update_spool()?;
+        Ok(event_log_db)
+    }
+
+    /// Constructor for a connection opened with
+    /// [`Repo::get_db_conn_read_only`]. The database isn't modified, so
+    /// reference updates which are still waiting in the spool (see
+    /// [`spool_ref_update_events`]) aren't included in the events until
+    /// another process records them.
+    #[instrument]
+    pub fn new_read_only(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
         Ok(EventLogDb { conn })
     }
 
+    /// Record the reference updates saved by [`spool_ref_update_events`], so
+    /// that they're visible to anyone reading the event log.
+    #[instrument]
+    fn flush_ref_update_spool(&self) -> eyre::Result<()> {
+        let spool_dir = match self.conn.path() {
+            Some(db_path) if !db_path.as_os_str().is_empty() => {
+                get_ref_update_spool_dir_path(db_path)
+            }
+            _ => return Ok(()),
+        };
+        let entries = match std::fs::read_dir(&spool_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err).wrap_err("Reading reference update spool directory"),
+        };
+        let file_names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|file_name| !file_name.starts_with('.'))
+            .sorted()
+            .collect();
+        if file_names.is_empty() {
+            return Ok(());
+        }
+
+        // Claim each file by renaming it, so that another process flushing the
+        // spool at the same time doesn't record the same updates again.
+        let mut claimed_paths = Vec::new();
+        for file_name in file_names {
+            let path = spool_dir.join(&file_name);
+            let claimed_path =
+                spool_dir.join(format!(".{}.flushing-{}", file_name, std::process::id()));
+            if std::fs::rename(&path, &claimed_path).is_ok() {
+                claimed_paths.push((path, claimed_path));
+            }
+        }
+
+        let result = self.with_write_transaction(|| {
+            claimed_paths
+                .iter()
+                .try_for_each(|(_, claimed_path)| self.record_spooled_ref_updates(claimed_path))
+        });
+        for (path, claimed_path) in claimed_paths {
+            let cleanup_result = match result {
+                Ok(()) => std::fs::remove_file(&claimed_path),
+                // Put the file back so that the updates can be recorded later.
+                Err(_) => std::fs::rename(&claimed_path, &path),
+            };
+            if let Err(err) = cleanup_result {
+                warn!(
+                    ?err,
+                    ?claimed_path,
+                    "Could not clean up spooled reference updates"
+                );
+            }
+        }
+        result
+    }
+
+    fn record_spooled_ref_updates(&self, path: &Path) -> eyre::Result<()> {
+        let contents =
+            std::fs::read_to_string(path).wrap_err("Reading spooled reference updates")?;
+        let (timestamp, ref_updates) = match parse_spooled_ref_updates(&contents) {
+            Some(parsed) => parsed,
+            None => {
+                error!(
+                    ?path,
+                    ?contents,
+                    "Could not parse spooled reference updates"
+                );
+                return Ok(());
+            }
+        };
+        let event_tx_id =
+            self.insert_transaction(timestamp, REF_UPDATE_SPOOL_TRANSACTION_MESSAGE)?;
+        for (ref_name, old_oid, new_oid) in ref_updates {
+            self.insert_event(Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name,
+                old_oid,
+                new_oid,
+                message: None,
+            })?;
+        }
+        Ok(())
+    }
+
     /// Add events in the given order to the database, in a transaction.
     ///
     /// Args:
     /// * events: The events to add.
     #[instrument]
     pub fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
-        let tx = self.conn.unchecked_transaction()?;
-        for event in events {
-            let Row {
-                timestamp,
-                type_,
-                event_tx_id,
-                ref1,
-                ref2,
-                ref_name,
-                message,
-            } = Row::from(event);
+        self.with_write_transaction(|| {
+            for event in events.iter().cloned() {
+                self.insert_event(event)?;
+            }
+            Ok(())
+        })
+    }
 
-            let ref1 = ref1.as_ref().map(|x| x.as_str());
-            let ref2 = ref2.as_ref().map(|x| x.as_str());
-            let ref_name = ref_name.as_ref().map(|x| x.as_str());
-            let message = message.as_ref().map(|x| x.as_str());
+    /// Run `f` in a transaction which is committed if it succeeds.
+    ///
+    /// The transaction takes the write lock up-front, so that it waits for
+    /// other writers according to the connection's busy timeout. Even so,
+    /// SQLite can report that the database is busy without waiting (such as
+    /// when a concurrent writer is committing), so the whole transaction is
+    /// retried a few times with a backoff in that case.
+    fn with_write_transaction<T>(&self, mut f: impl FnMut() -> eyre::Result<T>) -> eyre::Result<T> {
+        let mut attempt = 1;
+        loop {
+            let result = (|| -> eyre::Result<T> {
+                let tx = rusqlite::Transaction::new_unchecked(
+                    self.conn,
+                    rusqlite::TransactionBehavior::Immediate,
+                )?;
+                let value = f()?;
+                tx.commit()?;
+                Ok(value)
+            })();
+            match result {
+                Err(err) if attempt < DB_WRITE_ATTEMPTS && is_database_busy(&err) => {
+                    warn!(?err, attempt, "Database is busy; retrying write");
+                    std::thread::sleep(Duration::from_millis(50 << attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
 
-            tx.execute(
-                "
-INSERT INTO event_log VALUES (
+    fn insert_event(&self, event: Event) -> eyre::Result<()> {
+        let Row {
+            timestamp,
+            type_,
+            event_tx_id,
+            ref1,
+            ref2,
+            ref_name,
+            message,
+            reason,
+        } = Row::from(event);
+
+        let ref1 = ref1.as_ref().map(|x| x.as_str());
+        let ref2 = ref2.as_ref().map(|x| x.as_str());
+        let ref_name = ref_name.as_ref().map(|x| x.as_str());
+        let message = message.as_ref().map(|x| x.as_str());
+
+        self.conn.execute(
+            "
+INSERT INTO event_log (
+    timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message, reason
+) VALUES (
     :timestamp,
     :type,
     :event_tx_id,
     :old_ref,
     :new_ref,
     :ref_name,
-    :message
+    :message,
+    :reason
 )
             ",
-                rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":type": &type_,
-                    ":event_tx_id": event_tx_id,
-                    ":old_ref": &ref1,
-                    ":new_ref": &ref2,
-                    ":ref_name": &ref_name,
-                    ":message": &message,
-                },
-            )?;
-        }
-        tx.commit()?;
+            rusqlite::named_params! {
+                ":timestamp": timestamp,
+                ":type": &type_,
+                ":event_tx_id": event_tx_id,
+                ":old_ref": &ref1,
+                ":new_ref": &ref2,
+                ":ref_name": &ref_name,
+                ":message": &message,
+                ":reason": &reason,
+            },
+        )?;
         Ok(())
     }
 
@@ -538,13 +893,18 @@ INSERT INTO event_log VALUES (
     #[instrument]
 
     pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
-        let mut stmt = self.conn.prepare(
+        let reason_column = if has_reason_column(self.conn)? {
+            "reason"
+        } else {
+            "NULL AS reason"
+        };
+        let mut stmt = self.conn.prepare(&format!(
             "
-SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message, {reason_column}
 FROM event_log
 ORDER BY rowid ASC
-",
-        )?;
+"
+        ))?;
         let rows: rusqlite::Result<Vec<Row>> = stmt
             .query_map(rusqlite::params![], |row| {
                 let timestamp: f64 = row.get("timestamp")?;
@@ -554,6 +914,7 @@ ORDER BY rowid ASC
                 let old_ref: Option<String> = row.get("old_ref")?;
                 let new_ref: Option<String> = row.get("new_ref")?;
                 let message: Option<String> = row.get("message")?;
+                let reason: Option<String> = row.get("reason")?;
 
                 Ok(Row {
                     timestamp,
@@ -563,6 +924,7 @@ ORDER BY rowid ASC
                     ref1: old_ref.map(ReferenceName::from),
                     ref2: new_ref.map(ReferenceName::from),
                     message: message.map(ReferenceName::from),
+                    reason,
                 })
             })?
             .collect();
@@ -576,18 +938,22 @@ ORDER BY rowid ASC
         now: SystemTime,
         message: &str,
     ) -> eyre::Result<EventTransactionId> {
-        if let Ok(transaction_id) = std::env::var(BRANCHLESS_TRANSACTION_ID_ENV_VAR) {
-            if let Ok(transaction_id) = transaction_id.parse::<EventTransactionId>() {
-                return Ok(transaction_id);
-            }
+        if let Some(transaction_id) = self.get_enclosing_transaction_id() {
+            return Ok(transaction_id);
         }
 
-        let tx = self.conn.unchecked_transaction()?;
-
         let timestamp = now
             .duration_since(SystemTime::UNIX_EPOCH)
             .wrap_err("Calculating event transaction timestamp")?
             .as_secs_f64();
+        self.with_write_transaction(|| self.insert_transaction(timestamp, message))
+    }
+
+    fn insert_transaction(
+        &self,
+        timestamp: f64,
+        message: &str,
+    ) -> eyre::Result<EventTransactionId> {
         self.conn
             .execute(
                 "
@@ -603,14 +969,39 @@ ORDER BY rowid ASC
             )
             .wrap_err("Creating event transaction")?;
 
-        // Ensure that we query `last_insert_rowid` in a transaction, in case
-        // there's another thread in this process making queries with the same
-        // SQLite connection.
+        // Callers query `last_insert_rowid` in a transaction, in case there's
+        // another thread in this process making queries with the same SQLite
+        // connection.
         let event_tx_id: isize = self.conn.last_insert_rowid().try_into()?;
-        tx.commit()?;
         Ok(EventTransactionId(event_tx_id))
     }
 
+    /// Get the canonical path of the database file, if it's known.
+    pub fn get_db_path(&self) -> Option<PathBuf> {
+        let path = self.conn.path()?;
+        Some(std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()))
+    }
+
+    /// Get the ID of the transaction which the calling process has already
+    /// started, such as when running under `git branchless wrap`, if it belongs
+    /// to this database.
+    pub fn get_enclosing_transaction_id(&self) -> Option<EventTransactionId> {
+        let transaction_id = std::env::var(BRANCHLESS_TRANSACTION_ID_ENV_VAR).ok()?;
+        let transaction_id = transaction_id.parse::<EventTransactionId>().ok()?;
+        match std::env::var_os(BRANCHLESS_TRANSACTION_DB_ENV_VAR) {
+            None => Some(transaction_id),
+            Some(db_path) => {
+                let db_path = PathBuf::from(db_path);
+                let db_path = std::fs::canonicalize(&db_path).unwrap_or(db_path);
+                if self.get_db_path() == Some(db_path) {
+                    Some(transaction_id)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     /// Create a new event transaction ID to be used to insert subsequent
     /// `Event`s into the database.
     pub fn make_transaction_id(
@@ -621,6 +1012,43 @@ ORDER BY rowid ASC
         self.make_transaction_id_inner(now, message.as_ref())
     }
 
+    /// Count the events recorded since the most recent transaction with the
+    /// given message, or all events if there's no such transaction.
+    ///
+    /// Returns: A tuple of the number of events and the number of those events
+    /// which hid a commit (including by rewriting it).
+    pub fn count_events_since_transaction(&self, message: &str) -> eyre::Result<(usize, usize)> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT
+    COUNT(*) AS num_events,
+    COALESCE(SUM(type IN ('hide', 'rewrite')), 0) AS num_hide_events
+FROM event_log
+WHERE event_tx_id > COALESCE(
+    (SELECT MAX(event_tx_id) FROM event_transactions WHERE message = :message),
+    0
+)
+",
+        )?;
+        let (num_events, num_hide_events): (i64, i64) = stmt
+            .query_row(rusqlite::named_params![":message": message], |row| {
+                Ok((row.get("num_events")?, row.get("num_hide_events")?))
+            })?;
+        Ok((num_events.try_into()?, num_hide_events.try_into()?))
+    }
+
+    /// Count the events in the database. This is the ID of the event cursor
+    /// which points to immediately after the last event (see
+    /// [`EventReplayer::make_default_cursor`]).
+    pub fn count_events(&self) -> eyre::Result<usize> {
+        let num_events: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM event_log",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(num_events.try_into()?)
+    }
+
     /// Get the message associated with the given transaction.
     pub fn get_transaction_message(&self, event_tx_id: EventTransactionId) -> eyre::Result<String> {
         let EventTransactionId(event_tx_id) = event_tx_id;
@@ -640,15 +1068,39 @@ WHERE event_tx_id = :event_tx_id
         )?;
         Ok(result)
     }
+
+    /// Check the consistency of the database's tables and indexes, as with
+    /// SQLite's `PRAGMA integrity_check`. Returns the problems found, if any.
+    pub fn check_integrity(&self) -> eyre::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let messages: Vec<String> = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(messages
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect())
+    }
 }
 
+/// The prefix of the references which keep commits alive. They're kept in
+/// their own Git namespace, which `git branchless init` excludes from
+/// decorations and from the references advertised to other repositories.
+pub const KEEP_REFS_PREFIX: &str = "refs/namespaces/branchless/refs/keep/";
+
+/// The prefix under which older versions kept the references which keep
+/// commits alive. `git branchless gc` moves them under [`KEEP_REFS_PREFIX`].
+pub const LEGACY_KEEP_REFS_PREFIX: &str = "refs/branchless/";
+
 /// Determine whether a given reference is used to keep a commit alive.
 ///
 /// Returns: Whether or not the given reference is used internally to keep the
 /// commit alive, so that it's not collected by Git's garbage collection
 /// mechanism.
 pub fn is_gc_ref(reference_name: &ReferenceName) -> bool {
-    reference_name.as_str().starts_with("refs/branchless/")
+    let reference_name = reference_name.as_str();
+    reference_name.starts_with(KEEP_REFS_PREFIX)
+        || reference_name.starts_with(LEGACY_KEEP_REFS_PREFIX)
 }
 
 /// Determines whether or not updates to the given reference should be ignored.
@@ -665,6 +1117,39 @@ pub fn should_ignore_ref_updates(reference_name: &ReferenceName) -> bool {
     )
 }
 
+/// Determine whether the given reference name matches a pattern, as configured
+/// with `branchless.core.ignoredRefs`.
+///
+/// In the pattern, `*` matches any sequence of characters (including `/`). A
+/// pattern without any `*` matches the reference with that exact name, as
+/// well as all references under it, so `refs/notes` is the same as
+/// `refs/notes/*`.
+pub fn ref_pattern_matches(pattern: &str, reference_name: &ReferenceName) -> bool {
+    let reference_name = reference_name.as_str();
+    if !pattern.contains('*') {
+        return match reference_name.strip_prefix(pattern) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || pattern.ends_with('/'),
+            None => false,
+        };
+    }
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match reference_name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts = parts.collect_vec();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 #[derive(Debug)]
 enum EventClassification {
     Show,
@@ -724,9 +1209,17 @@ pub struct EventReplayer {
     /// The name of the reference representing the main branch.
     main_branch_reference_name: ReferenceName,
 
+    /// The name under which `HEAD` updates for the current worktree are
+    /// recorded. See `Repo::get_head_event_reference_name`.
+    head_reference_name: ReferenceName,
+
     /// The events that have affected each commit.
     commit_history: HashMap<NonZeroOid, Vec<EventInfo>>,
 
+    /// The commits which were cherry-picked or reverted from each commit,
+    /// along with the ID of the event which recorded them.
+    commit_copies: HashMap<NonZeroOid, Vec<(isize, NonZeroOid, CopyKind)>>,
+
     /// Map from ref names to ref locations (an OID or another ref name). Works
     /// around <https://github.com/arxanas/git-branchless/issues/7>.
     ///
@@ -752,7 +1245,9 @@ impl EventReplayer {
             id_counter: 0,
             events: vec![],
             main_branch_reference_name,
+            head_reference_name: ReferenceName::from("HEAD"),
             commit_history: HashMap::new(),
+            commit_copies: HashMap::new(),
             ref_locations: HashMap::new(),
         }
     }
@@ -773,6 +1268,7 @@ impl EventReplayer {
 
         let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
         let mut result = EventReplayer::new(main_branch_reference_name);
+        result.head_reference_name = repo.get_head_event_reference_name();
         for event in event_log_db.get_events()? {
             result.process_event(&event);
         }
@@ -869,6 +1365,7 @@ impl EventReplayer {
                 timestamp: _,
                 event_tx_id: _,
                 commit_oid,
+                message: _,
             } => self
                 .commit_history
                 .entry(*commit_oid)
@@ -893,6 +1390,21 @@ impl EventReplayer {
                     event_classification: EventClassification::Show,
                 }),
 
+            Event::CopyEvent {
+                timestamp: _,
+                event_tx_id: _,
+                original_commit_oid,
+                new_commit_oid,
+                kind,
+            } => {
+                // The new commit is made active by its own `CommitEvent`, and
+                // the original commit isn't affected.
+                self.commit_copies
+                    .entry(*original_commit_oid)
+                    .or_default()
+                    .push((id, *new_commit_oid, *kind));
+            }
+
             Event::WorkingCopySnapshot { .. } => {
                 // Do nothing. A working copy snapshot doesn't imply that the
                 // commit has become active or inactive.
@@ -1011,6 +1523,30 @@ impl EventReplayer {
         Some(&event_info.event)
     }
 
+    /// Get the commits which were created from the given commit by `git
+    /// cherry-pick` or `git revert`, as of the cursor's point in time, from
+    /// oldest to newest.
+    pub fn get_cursor_commit_copies(
+        &self,
+        cursor: EventCursor,
+        oid: NonZeroOid,
+    ) -> Vec<(NonZeroOid, CopyKind)> {
+        match self.commit_copies.get(&oid) {
+            None => vec![],
+            Some(copies) => copies
+                .iter()
+                .filter(|(id, _, _)| *id < cursor.event_id)
+                .map(|(_, new_commit_oid, kind)| (*new_commit_oid, *kind))
+                .collect(),
+        }
+    }
+
+    /// Get the most recently observed location of each reference, as of the
+    /// latest event. References which were deleted aren't included.
+    pub fn get_latest_ref_locations(&self) -> &HashMap<ReferenceName, NonZeroOid> {
+        &self.ref_locations
+    }
+
     /// Get all OIDs which have been observed so far. This should be the set of
     /// non-inactive commits.
     pub fn get_cursor_oids(&self, cursor: EventCursor) -> HashSet<NonZeroOid> {
@@ -1149,7 +1685,7 @@ impl EventReplayer {
                         ref_name,
                         new_oid: MaybeZeroOid::NonZero(new_oid),
                         ..
-                    } if ref_name.as_str() == "HEAD" => Some(*new_oid),
+                    } if ref_name == &self.head_reference_name => Some(*new_oid),
                     Event::RefUpdateEvent { .. } => None,
 
                     // Not strictly necessary, but helps to compensate in case
@@ -1168,7 +1704,8 @@ impl EventReplayer {
 
                     Event::RewriteEvent { .. }
                     | Event::ObsoleteEvent { .. }
-                    | Event::UnobsoleteEvent { .. } => None,
+                    | Event::UnobsoleteEvent { .. }
+                    | Event::CopyEvent { .. } => None,
                 }
             })
     }
@@ -1331,6 +1868,19 @@ impl EventReplayer {
         }
     }
 
+    /// Get the events before the event cursor which were recorded as part of
+    /// the given transaction.
+    pub fn get_tx_events(
+        &self,
+        event_tx_id: EventTransactionId,
+        cursor: EventCursor,
+    ) -> impl Iterator<Item = &Event> {
+        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
+        self.events[..cursor_event_id]
+            .iter()
+            .filter(move |event| event.get_event_tx_id() == event_tx_id)
+    }
+
     /// Get all the events that have happened since the event cursor.
     ///
     /// Returns: An ordered list of events that have happened since the event
@@ -1368,6 +1918,9 @@ pub mod testing {
             | Event::UnobsoleteEvent {
                 ref mut timestamp, ..
             }
+            | Event::CopyEvent {
+                ref mut timestamp, ..
+            }
             | Event::WorkingCopySnapshot {
                 ref mut timestamp, ..
             } => *timestamp = 0.0,
@@ -1429,7 +1982,7 @@ mod tests {
 
         git.init_repo()?;
         git.commit_file("test1", 1)?;
-        git.branchless("hide", &["HEAD"])?;
+        git.branchless("hide", &["--force", "HEAD"])?;
 
         let repo = git.get_repo()?;
         let conn = repo.get_db_conn()?;
@@ -1541,4 +2094,142 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_ref_pattern_matches() {
+        let matches = |pattern: &str, reference_name: &str| {
+            ref_pattern_matches(pattern, &ReferenceName::from(reference_name))
+        };
+        assert!(matches("refs/notes/*", "refs/notes/commits"));
+        assert!(matches("refs/notes/*", "refs/notes/foo/bar"));
+        assert!(!matches("refs/notes/*", "refs/heads/notes"));
+        assert!(matches("refs/notes", "refs/notes/commits"));
+        assert!(matches("refs/notes/", "refs/notes/commits"));
+        assert!(!matches("refs/notes", "refs/notesfoo"));
+        assert!(matches("refs/heads/main", "refs/heads/main"));
+        assert!(matches(
+            "refs/remotes/*/bot-*",
+            "refs/remotes/origin/bot-123"
+        ));
+        assert!(!matches(
+            "refs/remotes/*/bot-*",
+            "refs/remotes/origin/feature"
+        ));
+        assert!(matches("refs/*-tmp", "refs/heads/foo-tmp"));
+        assert!(!matches("refs/*-tmp", "refs/heads/foo-tmp2"));
+    }
+
+    #[test]
+    fn test_concurrent_writes() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let commit_oid = git.commit_file("test1", 1)?;
+
+        let count_commit_events = |event_log_db: &EventLogDb| -> eyre::Result<usize> {
+            Ok(event_log_db
+                .get_events()?
+                .into_iter()
+                .filter(|event| matches!(event, Event::CommitEvent { .. }))
+                .count())
+        };
+        let num_initial_commit_events = {
+            let repo = git.get_repo()?;
+            let conn = repo.get_db_conn()?;
+            count_commit_events(&EventLogDb::new(&conn)?)?
+        };
+
+        let num_threads = 8;
+        let num_writes = 20;
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let repo_path = git.repo_path.clone();
+                std::thread::spawn(move || -> eyre::Result<()> {
+                    let repo = Repo::from_dir(&repo_path)?;
+                    let conn = repo.get_db_conn()?;
+                    let event_log_db = EventLogDb::new(&conn)?;
+                    for _ in 0..num_writes {
+                        let event_tx_id =
+                            event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+                        event_log_db.add_events(vec![Event::CommitEvent {
+                            timestamp: 0.0,
+                            event_tx_id,
+                            commit_oid,
+                        }])?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("Writer thread panicked")?;
+        }
+
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let journal_mode: String =
+            conn.query_row("PRAGMA journal_mode", rusqlite::params![], |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        assert_eq!(
+            count_commit_events(&EventLogDb::new(&conn)?)?,
+            num_initial_commit_events + num_threads * num_writes
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_obsolete_event_reason() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        // Create the table as older versions did, before the `reason` column
+        // was added, with a reason stored in the `message` column.
+        conn.execute(
+            "
+CREATE TABLE event_log (
+    timestamp REAL NOT NULL,
+    type TEXT NOT NULL,
+    event_tx_id INTEGER NOT NULL,
+    old_ref TEXT,
+    new_ref TEXT,
+    ref_name TEXT,
+    message TEXT
+)
+",
+            rusqlite::params![],
+        )?;
+        conn.execute(
+            "INSERT INTO event_log VALUES (0.0, 'hide', 1, 'abc', NULL, NULL, 'old reason')",
+            rusqlite::params![],
+        )?;
+        assert_eq!(
+            EventLogDb::new_read_only(&conn)?.get_events()?,
+            vec![Event::ObsoleteEvent {
+                timestamp: 0.0,
+                event_tx_id: make_dummy_transaction_id(1),
+                commit_oid: NonZeroOid::from_str("abc")?,
+                message: Some("old reason".to_string()),
+            }]
+        );
+
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.add_events(vec![Event::ObsoleteEvent {
+            timestamp: 1.0,
+            event_tx_id: make_dummy_transaction_id(2),
+            commit_oid: NonZeroOid::from_str("def")?,
+            message: Some("superseded by a new approach".to_string()),
+        }])?;
+        let reasons: Vec<(Option<String>, Option<String>)> = conn
+            .prepare("SELECT message, reason FROM event_log ORDER BY rowid ASC")?
+            .query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            reasons,
+            vec![
+                (Some("old reason".to_string()), None),
+                (None, Some("superseded by a new approach".to_string())),
+            ]
+        );
+        assert_eq!(event_log_db.get_events()?.len(), 2);
+
+        Ok(())
+    }
 }
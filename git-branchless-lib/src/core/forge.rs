@@ -0,0 +1,313 @@
+//! Code review services ("forges"), such as GitHub.
+//!
+//! Each forge is accessed through the [`Forge`] trait, so that commands like
+//! `git submit` don't need to know which forge is in use. The code reviews
+//! returned by a forge are stored in the database with [`CodeReviewDb`], so
+//! that commands like `git smartlog` can show them without querying the forge
+//! over the network.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::effects::Effects;
+use crate::git::NonZeroOid;
+use crate::util::ExitCode;
+
+/// The combined status of the continuous integration checks for a code
+/// review.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiStatus {
+    /// There are no checks, or their status isn't known.
+    Unknown,
+
+    /// Some checks haven't finished yet, and none have failed.
+    Pending,
+
+    /// All checks passed.
+    Passed,
+
+    /// At least one check failed.
+    Failed,
+}
+
+impl Display for CiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CiStatus::Unknown => "unknown",
+            CiStatus::Pending => "pending",
+            CiStatus::Passed => "passed",
+            CiStatus::Failed => "failed",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for CiStatus {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unknown" => Ok(CiStatus::Unknown),
+            "pending" => Ok(CiStatus::Pending),
+            "passed" => Ok(CiStatus::Passed),
+            "failed" => Ok(CiStatus::Failed),
+            other => eyre::bail!("Unknown CI status: {other:?}"),
+        }
+    }
+}
+
+/// A code review (such as a GitHub pull request) for a branch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeReview {
+    /// The forge-specific identifier of the review, as shown to the user (such
+    /// as `#123`).
+    pub id: String,
+
+    /// The URL at which the review can be viewed.
+    pub url: String,
+
+    /// The name of the branch under review.
+    pub head: String,
+
+    /// The name of the branch which the review would be merged into.
+    pub base: String,
+
+    /// The title of the review.
+    pub title: String,
+
+    /// The description of the review.
+    pub body: String,
+
+    /// The status of the review's continuous integration checks.
+    pub ci_status: CiStatus,
+
+    /// The state of the review itself (such as `accepted` or `needs review`),
+    /// if the forge reports one.
+    pub state: Option<String>,
+}
+
+/// The desired state of the code review for a branch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeReviewSpec {
+    /// The commit which the branch points to.
+    pub commit_oid: NonZeroOid,
+
+    /// The name of the branch under review.
+    pub head: String,
+
+    /// The name of the branch which the review should be merged into.
+    pub base: String,
+
+    /// The title of the review.
+    pub title: String,
+
+    /// The description of the review.
+    pub body: String,
+}
+
+impl CodeReviewSpec {
+    /// Whether the given existing review already matches this spec.
+    pub fn is_up_to_date(&self, review: &CodeReview) -> bool {
+        review.head == self.head
+            && review.base == self.base
+            && review.title == self.title
+            && review.body.trim() == self.body
+    }
+}
+
+/// The name under which Phabricator revisions are recorded in the database.
+/// They're identified by the `Differential Revision` trailer in the commit
+/// message, so they're keyed by revision (such as `D123`) rather than branch.
+pub const PHABRICATOR_FORGE_NAME: &str = "phabricator";
+
+/// A code review service. Each method returns `Err(exit_code)` (after printing
+/// an explanation) if the forge couldn't be accessed.
+pub trait Forge: std::fmt::Debug {
+    /// A short, unique name for the forge (such as `github`), used to identify
+    /// its code reviews in the database.
+    fn get_name(&self) -> &'static str;
+
+    /// The singular and plural names of a code review on this forge (such as
+    /// `pull request`), for display to the user.
+    fn get_review_unit(&self) -> (&'static str, &'static str);
+
+    /// Look up the open code reviews for the given branches, including the
+    /// status of their continuous integration checks. Branches without an open
+    /// review are omitted from the result.
+    fn query_reviews(
+        &mut self,
+        effects: &Effects,
+        heads: &[&str],
+    ) -> eyre::Result<Result<HashMap<String, CodeReview>, ExitCode>>;
+
+    /// Look up the branches which are protected on the forge (such as against
+    /// force-pushes). Each may be a pattern, such as `release/*`.
+    fn query_protected_branches(
+        &mut self,
+        effects: &Effects,
+    ) -> eyre::Result<Result<Vec<String>, ExitCode>>;
+
+    /// Create a new code review.
+    fn create_review(
+        &mut self,
+        effects: &Effects,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>>;
+
+    /// Update an existing code review to match the given spec.
+    fn update_review(
+        &mut self,
+        effects: &Effects,
+        review: &CodeReview,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>>;
+}
+
+/// Stores the code reviews most recently seen on each forge.
+pub struct CodeReviewDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for CodeReviewDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CodeReviewDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS code_reviews (
+    forge TEXT NOT NULL,
+    head TEXT NOT NULL,
+    commit_oid TEXT NOT NULL,
+    id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    base TEXT NOT NULL,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    ci_status TEXT NOT NULL,
+    state TEXT,
+    PRIMARY KEY (forge, head)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `code_reviews` table")?;
+    Ok(())
+}
+
+impl<'conn> CodeReviewDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(CodeReviewDb { conn })
+    }
+
+    /// Record the code review for the given commit on the given forge,
+    /// replacing any previous review for the same branch.
+    #[instrument]
+    pub fn set_review(
+        &self,
+        forge_name: &str,
+        commit_oid: NonZeroOid,
+        review: &CodeReview,
+    ) -> eyre::Result<()> {
+        let CodeReview {
+            id,
+            url,
+            head,
+            base,
+            title,
+            body,
+            ci_status,
+            state,
+        } = review;
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO code_reviews
+    (forge, head, commit_oid, id, url, base, title, body, ci_status, state)
+VALUES (:forge, :head, :commit_oid, :id, :url, :base, :title, :body, :ci_status, :state)
+",
+                rusqlite::named_params! {
+                    ":forge": forge_name,
+                    ":head": head,
+                    ":commit_oid": commit_oid.to_string(),
+                    ":id": id,
+                    ":url": url,
+                    ":base": base,
+                    ":title": title,
+                    ":body": body,
+                    ":ci_status": ci_status.to_string(),
+                    ":state": state,
+                },
+            )
+            .wrap_err("Updating code review cache")?;
+        Ok(())
+    }
+
+    /// Get the most recently recorded code reviews on any forge, keyed by the
+    /// commit which was submitted for review, along with the name of the
+    /// forge.
+    #[instrument]
+    pub fn get_reviews_by_commit(
+        &self,
+    ) -> eyre::Result<HashMap<NonZeroOid, Vec<(String, CodeReview)>>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT * FROM code_reviews ORDER BY forge, head")
+            .wrap_err("Preparing statement")?;
+        let rows = statement
+            .query_map(rusqlite::params![], |row| {
+                let forge: String = row.get("forge")?;
+                let commit_oid: String = row.get("commit_oid")?;
+                let ci_status: String = row.get("ci_status")?;
+                let review = CodeReview {
+                    id: row.get("id")?,
+                    url: row.get("url")?,
+                    head: row.get("head")?,
+                    base: row.get("base")?,
+                    title: row.get("title")?,
+                    body: row.get("body")?,
+                    ci_status: CiStatus::Unknown,
+                    state: row.get("state")?,
+                };
+                Ok((forge, commit_oid, ci_status, review))
+            })
+            .wrap_err("Querying code review cache")?;
+
+        let mut result: HashMap<NonZeroOid, Vec<(String, CodeReview)>> = HashMap::new();
+        for row in rows {
+            let (forge, commit_oid, ci_status, review) =
+                row.wrap_err("Reading code review cache")?;
+            let commit_oid: NonZeroOid = commit_oid.parse()?;
+            let review = CodeReview {
+                ci_status: ci_status.parse()?,
+                ..review
+            };
+            result.entry(commit_oid).or_default().push((forge, review));
+        }
+        Ok(result)
+    }
+
+    /// Get the most recently recorded code reviews on the given forge, keyed
+    /// by the branch or other identifier under review.
+    #[instrument]
+    pub fn get_reviews(&self, forge_name: &str) -> eyre::Result<HashMap<String, CodeReview>> {
+        let reviews = self
+            .get_reviews_by_commit()?
+            .into_values()
+            .flatten()
+            .filter(|(forge, _review)| forge == forge_name)
+            .map(|(_forge, review)| (review.head.clone(), review))
+            .collect();
+        Ok(reviews)
+    }
+}
@@ -6,7 +6,7 @@
 
 use std::fmt::Display;
 
-use cursive::theme::{Effect, Style};
+use cursive::theme::{BaseColor, Color, ColorStyle, ColorType, Effect, PaletteColor, Style};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
 
@@ -76,6 +76,13 @@ pub struct Glyphs {
     /// color).
     pub should_write_ansi_escape_codes: bool,
 
+    /// The range of colors which can be rendered. Colors outside of this range
+    /// are approximated.
+    pub color_depth: ColorDepth,
+
+    /// The styles to use for the different kinds of output.
+    pub theme: Theme,
+
     /// Line connecting a parent commit to its single child commit.
     pub line: &'static str,
 
@@ -147,6 +154,15 @@ pub struct Glyphs {
 
     /// Corner at the lower left of the arrow used when printing a commit cycle.
     pub cycle_lower_left_corner: &'static str,
+
+    /// Marker for a commit whose CI checks passed.
+    pub ci_passed: &'static str,
+
+    /// Marker for a commit whose CI checks failed.
+    pub ci_failed: &'static str,
+
+    /// Marker for a commit whose CI checks are still running.
+    pub ci_pending: &'static str,
 }
 
 impl Glyphs {
@@ -154,7 +170,7 @@ impl Glyphs {
     pub fn detect() -> Self {
         let color_support = concolor::get(concolor::Stream::Stdout);
         if color_support.color() {
-            Glyphs::pretty()
+            Glyphs::pretty().with_color_depth(ColorDepth::detect())
         } else {
             Glyphs::text()
         }
@@ -164,6 +180,8 @@ impl Glyphs {
     pub fn text() -> Self {
         Glyphs {
             should_write_ansi_escape_codes: false,
+            color_depth: ColorDepth::Ansi16,
+            theme: Theme::default(),
             line: "|",
             line_with_offshoot: "|",
             vertical_ellipsis: ":",
@@ -186,6 +204,9 @@ impl Glyphs {
             cycle_vertical_line: "|",
             cycle_upper_left_corner: ",",
             cycle_lower_left_corner: "`",
+            ci_passed: "+",
+            ci_failed: "x",
+            ci_pending: "~",
         }
     }
 
@@ -193,6 +214,8 @@ impl Glyphs {
     pub fn pretty() -> Self {
         Glyphs {
             should_write_ansi_escape_codes: true,
+            color_depth: ColorDepth::Ansi16,
+            theme: Theme::default(),
             line: "┃",
             line_with_offshoot: "┣",
             vertical_ellipsis: "⋮",
@@ -215,9 +238,39 @@ impl Glyphs {
             cycle_vertical_line: "│",
             cycle_upper_left_corner: "┌",
             cycle_lower_left_corner: "└",
+            ci_passed: "✔",
+            ci_failed: "✘",
+            ci_pending: "◷",
         }
     }
 
+    /// Render colors using the given color depth.
+    pub fn with_color_depth(self, color_depth: ColorDepth) -> Self {
+        Self {
+            color_depth,
+            ..self
+        }
+    }
+
+    /// Use the given theme to style output. If the theme specifies a glyph
+    /// set, then use it regardless of whether color is enabled.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        let base = match theme.glyph_set {
+            None => self,
+            Some(GlyphSet::Unicode) => Glyphs {
+                should_write_ansi_escape_codes: self.should_write_ansi_escape_codes,
+                color_depth: self.color_depth,
+                ..Glyphs::pretty()
+            },
+            Some(GlyphSet::Ascii) => Glyphs {
+                should_write_ansi_escape_codes: self.should_write_ansi_escape_codes,
+                color_depth: self.color_depth,
+                ..Glyphs::text()
+            },
+        };
+        Self { theme, ..base }
+    }
+
     /// Return a `Glyphs` object suitable for rendering graphs in the reverse of
     /// their usual order.
     pub fn reverse_order(mut self, reverse: bool) -> Self {
@@ -241,7 +294,7 @@ impl Glyphs {
                     width: _,
                 } = span;
                 if self.should_write_ansi_escape_codes {
-                    Ok(render_style_as_ansi(content, *attr)?)
+                    Ok(render_style_as_ansi(content, *attr, self.color_depth)?)
                 } else {
                     Ok(content.to_string())
                 }
@@ -261,6 +314,280 @@ impl std::fmt::Debug for Glyphs {
     }
 }
 
+/// The range of colors supported by the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// The 16 standard ANSI colors.
+    Ansi16,
+
+    /// The 256 colors of the `xterm` palette.
+    Ansi256,
+
+    /// 24-bit RGB colors.
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Detect the color depth of the terminal from the `$COLORTERM` and `$TERM`
+    /// environment variables.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.ends_with("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+/// The set of characters to use when drawing graphs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlyphSet {
+    /// Box-drawing and other non-ASCII characters, as in [`Glyphs::pretty`].
+    Unicode,
+
+    /// ASCII characters only, as in [`Glyphs::text`].
+    Ascii,
+}
+
+/// The styles to use for the different kinds of output. The defaults can be
+/// overridden with the `branchless.theme.*` config keys.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// The glyph set to use regardless of whether color is enabled, if any.
+    pub glyph_set: Option<GlyphSet>,
+
+    /// Commit hashes.
+    pub commit_oid: Style,
+
+    /// Branch names pointing to a commit.
+    pub branch: Style,
+
+    /// Annotations explaining the status of a commit, such as `(rewritten as
+    /// ...)` or `(manually hidden)`.
+    pub annotation: Style,
+
+    /// Names of the worktrees which have a commit checked out.
+    pub worktree: Style,
+
+    /// Phabricator revision numbers.
+    pub differential_revision: Style,
+
+    /// How long ago a commit was made.
+    pub relative_time: Style,
+
+    /// Merge markers in the smartlog.
+    pub merge: Style,
+
+    /// Cursors of the commits created by the last command.
+    pub change_created: Style,
+
+    /// Cursors of the commits rewritten by the last command.
+    pub change_rewritten: Style,
+
+    /// Cursors of the commits whose branches were moved by the last command.
+    pub change_branch_moved: Style,
+
+    /// Cursors of the commits hidden by the last command.
+    pub change_hidden: Style,
+
+    /// Markers for commits whose CI checks passed.
+    pub ci_passed: Style,
+
+    /// Markers for commits whose CI checks failed.
+    pub ci_failed: Style,
+
+    /// Markers for commits whose CI checks are still running.
+    pub ci_pending: Style,
+
+    /// Issues referenced by commit message trailers.
+    pub issue: Style,
+
+    /// Overrides for the palette of interactive interfaces, such as `git undo`.
+    pub tui_palette: Vec<(PaletteColor, Color)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            glyph_set: None,
+            commit_oid: BaseColor::Yellow.dark().into(),
+            branch: BaseColor::Green.light().into(),
+            annotation: BaseColor::Black.light().into(),
+            worktree: BaseColor::Cyan.light().into(),
+            differential_revision: BaseColor::Green.dark().into(),
+            relative_time: BaseColor::Green.dark().into(),
+            merge: BaseColor::Blue.dark().into(),
+            change_created: BaseColor::Green.light().into(),
+            change_rewritten: BaseColor::Yellow.light().into(),
+            change_branch_moved: BaseColor::Blue.light().into(),
+            change_hidden: BaseColor::Red.light().into(),
+            ci_passed: BaseColor::Green.light().into(),
+            ci_failed: BaseColor::Red.light().into(),
+            ci_pending: BaseColor::Yellow.light().into(),
+            issue: BaseColor::Magenta.dark().into(),
+            tui_palette: Vec::new(),
+        }
+    }
+}
+
+/// Parse a style, written in the same way as Git's color settings: any number
+/// of attributes (`bold`, `dim`, `italic`, `ul`, `blink`, `reverse`) and at
+/// most one color, which can be a name (such as `red` or `brightred`), a number
+/// in the 256-color palette, or an RGB value (such as `#ff8000`).
+///
+/// ```
+/// # use branchless::core::formatting::parse_style;
+/// # use cursive::theme::{BaseColor, Effect, Style};
+/// assert_eq!(
+///     parse_style("bold brightgreen"),
+///     Ok(Style::merge(&[BaseColor::Green.light().into(), Effect::Bold.into()])),
+/// );
+/// assert!(parse_style("red blue").is_err());
+/// ```
+pub fn parse_style(value: &str) -> Result<Style, String> {
+    let mut style = Style::none();
+    let mut color = None;
+    for word in value.split_whitespace() {
+        let word = word.to_lowercase();
+        let effect = match word.as_str() {
+            "bold" => Some(Effect::Bold),
+            "dim" => Some(Effect::Dim),
+            "italic" => Some(Effect::Italic),
+            "ul" | "underline" => Some(Effect::Underline),
+            "blink" => Some(Effect::Blink),
+            "reverse" => Some(Effect::Reverse),
+            _ => None,
+        };
+        if let Some(effect) = effect {
+            style.effects.insert(effect);
+            continue;
+        }
+
+        let word_color = match parse_color(&word) {
+            Some(word_color) => word_color,
+            None => return Err(format!("unknown color or attribute: {word}")),
+        };
+        if color.replace(word_color).is_some() {
+            return Err(format!("expected at most one color, but got: {value}"));
+        }
+    }
+    if let Some(color) = color {
+        style.color = ColorStyle::front(color);
+    }
+    Ok(style)
+}
+
+fn parse_color(word: &str) -> Option<Color> {
+    let parse_base_color = |name: &str| match name {
+        "black" => Some(BaseColor::Black),
+        "red" => Some(BaseColor::Red),
+        "green" => Some(BaseColor::Green),
+        "yellow" => Some(BaseColor::Yellow),
+        "blue" => Some(BaseColor::Blue),
+        "magenta" => Some(BaseColor::Magenta),
+        "cyan" => Some(BaseColor::Cyan),
+        "white" => Some(BaseColor::White),
+        _ => None,
+    };
+
+    if word == "normal" || word == "default" {
+        Some(Color::TerminalDefault)
+    } else if let Some(name) = word.strip_prefix("bright") {
+        parse_base_color(name).map(Color::Light)
+    } else if let Some(hex) = word.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        let [_, r, g, b] = rgb.to_be_bytes();
+        Some(Color::Rgb(r, g, b))
+    } else if let Ok(index) = word.parse::<u8>() {
+        Some(ansi256_to_color(index))
+    } else {
+        parse_base_color(word).map(Color::Dark)
+    }
+}
+
+const BASE_COLORS: [BaseColor; 8] = [
+    BaseColor::Black,
+    BaseColor::Red,
+    BaseColor::Green,
+    BaseColor::Yellow,
+    BaseColor::Blue,
+    BaseColor::Magenta,
+    BaseColor::Cyan,
+    BaseColor::White,
+];
+
+/// The RGB values of the 16 standard colors in the default `xterm` palette,
+/// indexed by their color number.
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+fn ansi256_to_color(index: u8) -> Color {
+    match index {
+        0..=7 => Color::Dark(BASE_COLORS[usize::from(index)]),
+        8..=15 => Color::Light(BASE_COLORS[usize::from(index - 8)]),
+        16..=231 => {
+            let index = index - 16;
+            Color::RgbLowRes(index / 36, (index / 6) % 6, index % 6)
+        }
+        232..=255 => {
+            let level = 8 + 10 * (index - 232);
+            Color::Rgb(level, level, level)
+        }
+    }
+}
+
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            level => 232 + (level - 8) / 10,
+        }
+    } else {
+        let scale = |value: u8| (u16::from(value) * 5 + 127) / 255;
+        let index = 16 + 36 * scale(r) + 6 * scale(g) + scale(b);
+        u8::try_from(index).unwrap_or(231)
+    }
+}
+
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |lhs: u8, rhs: u8| (i32::from(lhs) - i32::from(rhs)).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    let index = (0..ANSI16_RGB.len())
+        .min_by_key(|index| distance(ANSI16_RGB[*index]))
+        .unwrap_or_default();
+    if index < 8 {
+        Color::Dark(BASE_COLORS[index])
+    } else {
+        Color::Light(BASE_COLORS[index - 8])
+    }
+}
+
 /// Helper to build `StyledString`s by combining multiple strings (both regular
 /// `String`s and `StyledString`s).
 pub struct StyledStringBuilder {
@@ -365,18 +692,42 @@ impl From<StyledStringBuilder> for StyledString {
     }
 }
 
-fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
+fn render_style_as_ansi(
+    content: &str,
+    style: Style,
+    color_depth: ColorDepth,
+) -> eyre::Result<String> {
     let Style { effects, color } = style;
+    let mut true_color = None;
     let output = {
         use console::style;
-        use cursive::theme::{BaseColor, Color, ColorType};
         let output = content.to_string();
-        match color.front {
+        let front = match (color.front, color_depth) {
+            (ColorType::Color(Color::Rgb(r, g, b)), ColorDepth::Ansi16) => {
+                ColorType::Color(rgb_to_ansi16(r, g, b))
+            }
+            (ColorType::Color(Color::RgbLowRes(r, g, b)), ColorDepth::Ansi16) => {
+                let scale = |value: u8| if value == 0 { 0 } else { 55 + 40 * value };
+                ColorType::Color(rgb_to_ansi16(scale(r), scale(g), scale(b)))
+            }
+            (front, _) => front,
+        };
+        match front {
             ColorType::Palette(_) => {
                 eyre::bail!("Not implemented: using cursive palette colors")
             }
-            ColorType::Color(Color::Rgb(..)) | ColorType::Color(Color::RgbLowRes(..)) => {
-                eyre::bail!("Not implemented: using raw RGB colors")
+            ColorType::Color(Color::Rgb(r, g, b)) => {
+                if color_depth == ColorDepth::TrueColor {
+                    // `console` doesn't support 24-bit colors, so we write the
+                    // escape code ourselves below.
+                    true_color = Some((r, g, b));
+                    style(output)
+                } else {
+                    style(output).color256(rgb_to_ansi256(r, g, b))
+                }
+            }
+            ColorType::Color(Color::RgbLowRes(r, g, b)) => {
+                style(output).color256(16 + 36 * r + 6 * g + b)
             }
             ColorType::InheritParent | ColorType::Color(Color::TerminalDefault) => style(output),
             ColorType::Color(Color::Light(color)) => match color {
@@ -426,5 +777,8 @@ fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
     // See https://github.com/arxanas/git-branchless/issues/506
     let output = output.force_styling(true);
 
-    Ok(output.to_string())
+    match true_color {
+        Some((r, g, b)) => Ok(format!("\u{1b}[38;2;{r};{g};{b}m{output}\u{1b}[0m")),
+        None => Ok(output.to_string()),
+    }
 }
@@ -9,19 +9,39 @@
 //! active.
 
 use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use eden_dag::DagAlgorithm;
 use eyre::Context;
-use tracing::instrument;
+use fslock::LockFile;
+use tracing::{instrument, warn};
 
+use crate::core::ci_status::CiStatusDb;
+use crate::core::config::{
+    get_auto_hide_after, get_gc_auto_events, get_gc_auto_hidden_commits, get_gc_retention,
+    get_gc_stale_state_expiry, get_gc_write_commit_graph,
+};
+use crate::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
 use crate::core::effects::Effects;
 use crate::core::eventlog::{
-    is_gc_ref, CommitActivityStatus, EventCursor, EventLogDb, EventReplayer,
+    is_gc_ref, CommitActivityStatus, Event, EventCursor, EventLogDb, EventReplayer,
+    KEEP_REFS_PREFIX, LEGACY_KEEP_REFS_PREFIX,
+};
+use crate::core::formatting::{Glyphs, Pluralize};
+use crate::core::merge_base::MergeBaseDb;
+use crate::core::patch_id::PatchIdDb;
+use crate::core::prompt::confirm;
+use crate::core::repo_ext::RepoExt;
+use crate::core::rewrite::rewrite_hooks::is_unstarted_branchless_rebase;
+use crate::git::{
+    get_test_locks_dir, get_test_worktrees_dir, GitRunInfo, GitRunOpts, NonZeroOid, Reference,
+    Repo, RepoError,
 };
-use crate::core::formatting::Pluralize;
-use crate::git::{NonZeroOid, Reference, Repo};
+use crate::util::ExitCode;
 
-/// Find references under `refs/branchless/` which point to commits which are no
-/// longer active. These are safe to remove.
+/// Find the references which keep commits alive (see [`is_gc_ref`]) but point
+/// to commits which are no longer active. These are safe to remove.
 pub fn find_dangling_references<'repo>(
     repo: &'repo Repo,
     event_replayer: &EventReplayer,
@@ -59,6 +79,33 @@ pub fn find_dangling_references<'repo>(
     Ok(result)
 }
 
+/// Split the given references to hidden commits into those whose commits were
+/// hidden before `cutoff`, and those which were hidden more recently and should
+/// be retained for now.
+fn partition_by_hidden_time<'repo>(
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    references: Vec<Reference<'repo>>,
+    cutoff: SystemTime,
+) -> eyre::Result<(Vec<Reference<'repo>>, Vec<Reference<'repo>>)> {
+    let mut expired_references = Vec::new();
+    let mut retained_references = Vec::new();
+    for reference in references {
+        let commit_oid = match reference.peel_to_commit()? {
+            Some(commit) => commit.get_oid(),
+            None => continue,
+        };
+        let hidden_time = event_replayer
+            .get_cursor_commit_latest_event(event_cursor, commit_oid)
+            .map(|event| event.get_timestamp());
+        match hidden_time {
+            Some(hidden_time) if hidden_time > cutoff => retained_references.push(reference),
+            _ => expired_references.push(reference),
+        }
+    }
+    Ok((expired_references, retained_references))
+}
+
 /// Mark a commit as reachable.
 ///
 /// Once marked as reachable, the commit won't be collected by Git's garbage
@@ -72,7 +119,7 @@ pub fn find_dangling_references<'repo>(
 /// * `commit_oid`: The commit OID to mark as reachable.
 #[instrument]
 pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<()> {
-    let ref_name = format!("refs/branchless/{commit_oid}");
+    let ref_name = format!("{KEEP_REFS_PREFIX}{commit_oid}");
     eyre::ensure!(
         Reference::is_valid_name(&ref_name),
         format!("Invalid ref name to mark commit as reachable: {ref_name}")
@@ -94,12 +141,465 @@ pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Resul
     Ok(())
 }
 
+/// Move the references which keep commits alive from under
+/// [`LEGACY_KEEP_REFS_PREFIX`], where older versions created them, to
+/// [`KEEP_REFS_PREFIX`]. Returns the number of references which were moved.
+#[instrument]
+pub fn migrate_legacy_keep_references(repo: &Repo) -> eyre::Result<usize> {
+    let mut num_migrated = 0;
+    for mut reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        let is_legacy_keep_reference = reference_name
+            .as_str()
+            .strip_prefix(LEGACY_KEEP_REFS_PREFIX)
+            .map_or(false, |suffix| suffix.parse::<NonZeroOid>().is_ok());
+        if !is_legacy_keep_reference {
+            continue;
+        }
+        if let Some(commit) = reference.peel_to_commit()? {
+            mark_commit_reachable(repo, commit.get_oid())?;
+        }
+        reference.delete()?;
+        num_migrated += 1;
+    }
+    Ok(num_migrated)
+}
+
+/// Find draft stacks which haven't been touched since `cutoff`. A stack is
+/// considered touched if any of its commits were committed or had an event
+/// recorded after the cutoff. Stacks containing `HEAD` or a branch are never
+/// considered stale.
+fn find_stale_commits(
+    repo: &Repo,
+    dag: &Dag,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    cutoff: SystemTime,
+) -> eyre::Result<CommitSet> {
+    let references_snapshot = repo.get_references_snapshot()?;
+    let draft_commits = dag.query_draft_commits()?;
+    let mut stale_commits = CommitSet::empty();
+    for root_oid in commit_set_to_vec(&dag.query().roots(draft_commits.clone())?)? {
+        let stack = dag
+            .query()
+            .descendants(CommitSet::from(root_oid))?
+            .intersection(draft_commits);
+
+        let mut is_stale = true;
+        for commit_oid in commit_set_to_vec(&stack)? {
+            if references_snapshot.head_oid == Some(commit_oid)
+                || references_snapshot
+                    .branch_oid_to_names
+                    .contains_key(&commit_oid)
+            {
+                is_stale = false;
+                break;
+            }
+
+            let commit_time = match repo.find_commit(commit_oid)? {
+                Some(commit) => commit.get_committer().get_time().to_system_time()?,
+                None => continue,
+            };
+            let event_time = event_replayer
+                .get_cursor_commit_latest_event(event_cursor, commit_oid)
+                .map(|event| event.get_timestamp());
+            let last_touched = match event_time {
+                Some(event_time) => commit_time.max(event_time),
+                None => commit_time,
+            };
+            if last_touched > cutoff {
+                is_stale = false;
+                break;
+            }
+        }
+
+        if is_stale {
+            stale_commits = stale_commits.union(&stack);
+        }
+    }
+    Ok(stale_commits)
+}
+
+/// Hide draft stacks which haven't been touched within the duration given by
+/// the `branchless.autoHide.after` config setting, if it's set. The commits
+/// are hidden in their own transaction, so they can be restored with
+/// `git undo`. If `dry_run` is set, the commits are only listed.
+#[instrument]
+fn auto_hide_stale_commits(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let (auto_hide_after, duration) = match get_auto_hide_after(repo)? {
+        Some(auto_hide_after) => auto_hide_after,
+        None => return Ok(()),
+    };
+
+    let now = SystemTime::now();
+    let cutoff = now.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH);
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        effects,
+        repo,
+        event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    let stale_commits = find_stale_commits(repo, &dag, event_replayer, event_cursor, cutoff)?;
+    let stale_commits = sorted_commit_set(repo, &dag, &stale_commits)?;
+    if stale_commits.is_empty() {
+        return Ok(());
+    }
+
+    let glyphs = Glyphs::detect();
+    let num_stale_commits = Pluralize {
+        determiner: None,
+        amount: stale_commits.len(),
+        unit: ("stale commit", "stale commits"),
+    };
+    if dry_run {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: would hide {num_stale_commits} not touched in {auto_hide_after}:",
+        )?;
+        for commit in stale_commits.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "- {}",
+                glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            )?;
+        }
+        return Ok(());
+    }
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = event_log_db.make_transaction_id(now, "auto-hide")?;
+    let message = format!("not touched in {auto_hide_after}");
+    let events = stale_commits
+        .iter()
+        .map(|commit| Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: commit.get_oid(),
+            message: Some(message.clone()),
+        })
+        .collect();
+    event_log_db.add_events(events)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: hid {num_stale_commits} {message}:",
+    )?;
+    for commit in stale_commits.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "- {}",
+            glyphs.render(commit.friendly_describe(&glyphs)?)?,
+        )?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: to unhide {}, run: git undo",
+        match stale_commits.len() {
+            1 => "it",
+            _ => "them",
+        }
+    )?;
+    Ok(())
+}
+
+/// How long a `gc` lock file can be held before it's assumed to have been left
+/// behind by a process which crashed. This matches the expiry of Git's own
+/// `gc.pid` file.
+const GC_LOCK_EXPIRY: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// A lock held for the duration of a garbage collection, so that concurrent
+/// invocations (such as one run on a schedule and one triggered by a hook)
+/// don't race with each other. The lock is released when this is dropped.
+#[derive(Debug)]
+struct GcLock {
+    path: PathBuf,
+}
+
+impl GcLock {
+    /// Try to acquire the lock. Returns `None` if another garbage collection
+    /// currently holds it.
+    #[instrument]
+    fn try_acquire(repo: &Repo) -> eyre::Result<Option<Self>> {
+        let path = repo.get_gc_lock_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Creating directory for gc lock: {dir:?}"))?;
+        }
+
+        for _ in 0..2 {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    std::io::Write::write_all(
+                        &mut file,
+                        format!("{}\n", std::process::id()).as_bytes(),
+                    )
+                    .wrap_err_with(|| format!("Writing gc lock: {path:?}"))?;
+                    return Ok(Some(Self { path }));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let is_expired = std::fs::metadata(&path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map(|elapsed| elapsed > GC_LOCK_EXPIRY)
+                        .unwrap_or(false);
+                    if !is_expired {
+                        return Ok(None);
+                    }
+                    match std::fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(err) => {
+                            return Err(err)
+                                .wrap_err_with(|| format!("Removing expired gc lock: {path:?}"))
+                        }
+                    }
+                }
+                Err(err) => {
+                    return Err(err).wrap_err_with(|| format!("Creating gc lock: {path:?}"));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for GcLock {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(?err, path = ?self.path, "Could not remove gc lock");
+        }
+    }
+}
+
+/// Delete the given reference to a hidden commit, unless it was changed by
+/// another process since it was read, or the commit it points to has been
+/// unhidden since then.
+fn delete_dangling_reference(
+    event_replayer: &EventReplayer,
+    reference: Reference,
+) -> eyre::Result<()> {
+    let commit_oid = match reference.peel_to_commit()? {
+        Some(commit) => commit.get_oid(),
+        None => return Ok(()),
+    };
+    match event_replayer
+        .get_cursor_commit_activity_status(event_replayer.make_default_cursor(), commit_oid)
+    {
+        CommitActivityStatus::Obsolete => {}
+        CommitActivityStatus::Active | CommitActivityStatus::Inactive => return Ok(()),
+    }
+
+    delete_reference_if_unchanged(reference)
+}
+
+/// Delete the given reference, unless it was changed by another process since
+/// it was read, so that a concurrent update isn't lost.
+fn delete_reference_if_unchanged(mut reference: Reference) -> eyre::Result<()> {
+    match reference.delete() {
+        Ok(()) => Ok(()),
+        Err(RepoError::DeleteReference(err))
+            if matches!(
+                err.code(),
+                git2::ErrorCode::Modified | git2::ErrorCode::NotFound
+            ) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Determine whether the file or directory at `path` hasn't been modified
+/// within `expiry`.
+fn is_stale(path: &Path, expiry: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|elapsed| elapsed >= expiry)
+        .unwrap_or(false)
+}
+
+/// Find state left behind by operations which were interrupted, such as by a
+/// crash, and which hasn't been modified within the duration given by
+/// `branchless.gc.staleStateExpiry`:
+///
+/// - Temporary files under `.git/branchless/tmp`.
+/// - Worktrees created by `git test`, if no test is currently using them.
+/// - The rebase state of on-disk rebases which git-branchless set up, but Git
+///   never started carrying out. Such a rebase would otherwise block future
+///   operations.
+///
+/// These are removed, unless `dry_run` is set, in which case they're only
+/// listed.
+#[instrument]
+fn clean_up_stale_state(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let (stale_state_expiry, expiry) = get_gc_stale_state_expiry(repo)?;
+    let mut stale_paths = Vec::new();
+
+    let tempfile_dir = repo.get_tempfile_dir();
+    if let Ok(entries) = std::fs::read_dir(&tempfile_dir) {
+        for entry in entries {
+            let path = entry
+                .wrap_err_with(|| format!("Reading temporary file dir: {tempfile_dir:?}"))?
+                .path();
+            if is_stale(&path, expiry) {
+                if !dry_run {
+                    let result = if path.is_dir() {
+                        std::fs::remove_dir_all(&path)
+                    } else {
+                        std::fs::remove_file(&path)
+                    };
+                    result.wrap_err_with(|| format!("Removing temporary file: {path:?}"))?;
+                }
+                stale_paths.push(path);
+            }
+        }
+    }
+
+    let worktrees_dir = get_test_worktrees_dir(repo);
+    if let Ok(entries) = std::fs::read_dir(&worktrees_dir) {
+        let mut worktree_paths = Vec::new();
+        for entry in entries {
+            let path = entry
+                .wrap_err_with(|| format!("Reading test worktrees dir: {worktrees_dir:?}"))?
+                .path();
+            worktree_paths.push(path);
+        }
+        worktree_paths.sort();
+
+        for path in worktree_paths {
+            let worker_id = match path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .and_then(|file_name| file_name.strip_prefix("testing-worktree-"))
+            {
+                Some(worker_id) => worker_id,
+                None => continue,
+            };
+
+            // The lock file is rewritten each time a test uses the worktree,
+            // so it's a better indication of when it was last used than the
+            // worktree itself.
+            let lock_path = get_test_locks_dir(repo).join(format!("worktree-{worker_id}.lock"));
+            let last_used_path = if lock_path.exists() {
+                &lock_path
+            } else {
+                &path
+            };
+            if !is_stale(last_used_path, expiry) {
+                continue;
+            }
+            let mut lock_file = LockFile::open(&lock_path)
+                .wrap_err_with(|| format!("Opening test worktree lock at {lock_path:?}"))?;
+            if !lock_file
+                .try_lock()
+                .wrap_err_with(|| format!("Locking test worktree with {lock_path:?}"))?
+            {
+                continue;
+            }
+
+            if !dry_run {
+                let path_str = path.to_string_lossy().into_owned();
+                let result = git_run_info.run_silent(
+                    repo,
+                    None,
+                    &["worktree", "remove", "--force", &path_str],
+                    GitRunOpts {
+                        treat_git_failure_as_error: false,
+                        ..Default::default()
+                    },
+                )?;
+                // The worktree might not be registered with Git anymore, such
+                // as if it was interrupted while being created.
+                if !result.exit_code.is_success() && path.exists() {
+                    std::fs::remove_dir_all(&path)
+                        .wrap_err_with(|| format!("Removing test worktree: {path:?}"))?;
+                }
+            }
+            stale_paths.push(path);
+        }
+    }
+
+    let rebase_state_dir = repo.get_rebase_state_dir_path();
+    if is_unstarted_branchless_rebase(repo) && is_stale(&rebase_state_dir, expiry) {
+        if !dry_run {
+            std::fs::remove_dir_all(&rebase_state_dir)
+                .wrap_err_with(|| format!("Removing rebase state: {rebase_state_dir:?}"))?;
+        }
+        stale_paths.push(rebase_state_dir);
+    }
+
+    if stale_paths.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: {} {} from interrupted operations (not modified in {stale_state_expiry}):",
+        if dry_run { "would remove" } else { "removed" },
+        Pluralize {
+            determiner: None,
+            amount: stale_paths.len(),
+            unit: ("stale path", "stale paths"),
+        },
+    )?;
+    for path in stale_paths {
+        writeln!(effects.get_output_stream(), "- {}", path.display())?;
+    }
+    Ok(())
+}
+
 /// Run branchless's garbage collection.
 ///
 /// Frees any references to commits which are no longer visible in the smartlog.
+/// If `branchless.autoHide.after` is set, stale draft stacks are hidden first.
+/// If `branchless.gc.retention` is set, references to commits which were
+/// hidden more recently than that are kept. State left behind by interrupted
+/// operations is also cleaned up (see `clean_up_stale_state`).
+///
+/// If `dry_run` is set, nothing is changed. Instead, the commits which would
+/// be hidden, and those whose references would be deleted, are listed along
+/// with an estimate of the disk space which Git could then reclaim.
+///
+/// Only one garbage collection runs at a time. If another one is already
+/// running, this returns a failing exit code without doing anything.
 #[instrument]
-pub fn gc(effects: &Effects) -> eyre::Result<()> {
+pub fn gc(effects: &Effects, git_run_info: &GitRunInfo, dry_run: bool) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
+    let _lock = if dry_run {
+        None
+    } else {
+        match GcLock::try_acquire(&repo)? {
+            Some(lock) => Some(lock),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "branchless: another garbage collection is already running; skipping (if not, remove: {})",
+                    repo.get_gc_lock_path().display()
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    };
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
@@ -107,22 +607,503 @@ pub fn gc(effects: &Effects) -> eyre::Result<()> {
 
     writeln!(
         effects.get_output_stream(),
-        "branchless: collecting garbage"
+        "branchless: collecting garbage{}",
+        if dry_run { " (dry run)" } else { "" }
+    )?;
+
+    if !dry_run {
+        migrate_legacy_keep_references(&repo)?;
+    }
+
+    // Note that dangling references are still computed as of `event_cursor`,
+    // so the references for any commits hidden here are only deleted by the
+    // next garbage collection. This keeps them recoverable with `git undo` in
+    // the meantime.
+    auto_hide_stale_commits(
+        effects,
+        &repo,
+        &event_log_db,
+        &event_replayer,
+        event_cursor,
+        dry_run,
     )?;
     let dangling_references = find_dangling_references(&repo, &event_replayer, event_cursor)?;
+    let (dangling_references, retained_references) = match get_gc_retention(&repo)? {
+        Some((retention, duration)) => {
+            let cutoff = SystemTime::now()
+                .checked_sub(duration)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let (dangling_references, retained_references) = partition_by_hidden_time(
+                &event_replayer,
+                event_cursor,
+                dangling_references,
+                cutoff,
+            )?;
+            (dangling_references, Some((retention, retained_references)))
+        }
+        None => (dangling_references, None),
+    };
+
     let num_dangling_references = Pluralize {
         determiner: None,
         amount: dangling_references.len(),
         unit: ("dangling reference", "dangling references"),
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: {num_dangling_references} {}{}",
+        if dry_run {
+            "would be deleted"
+        } else {
+            "deleted"
+        },
+        if dangling_references.is_empty() {
+            ""
+        } else {
+            ":"
+        }
+    )?;
+    let glyphs = Glyphs::detect();
+    for reference in dangling_references.iter() {
+        if let Some(commit) = reference.peel_to_commit()? {
+            writeln!(
+                effects.get_output_stream(),
+                "- {}",
+                glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            )?;
+        }
     }
-    .to_string();
-    for mut reference in dangling_references.into_iter() {
-        reference.delete()?;
+    if let Some((retention, retained_references)) = retained_references {
+        if !retained_references.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: {} kept (hidden less than {retention} ago)",
+                Pluralize {
+                    determiner: None,
+                    amount: retained_references.len(),
+                    unit: (
+                        "reference to a recently-hidden commit",
+                        "references to recently-hidden commits"
+                    ),
+                },
+            )?;
+        }
     }
 
+    clean_up_stale_state(effects, git_run_info, &repo, dry_run)?;
+
+    if dry_run {
+        if !dangling_references.is_empty() {
+            if let Some(size_bytes) =
+                estimate_reclaimable_size(git_run_info, &repo, &dangling_references)?
+            {
+                writeln!(
+                    effects.get_output_stream(),
+                    "branchless: up to {} could then be reclaimed by Git's garbage collection",
+                    format_size(size_bytes)
+                )?;
+            }
+        }
+    } else {
+        // Hooks may have recorded new events (such as unhiding a commit) while
+        // the above ran, so check against the latest state before deleting.
+        let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+        for reference in dangling_references.into_iter() {
+            delete_dangling_reference(&event_replayer, reference)?;
+        }
+        // Cached merge-bases and patch IDs stay valid for as long as their
+        // commits exist, so only drop the ones for commits which Git has
+        // already deleted.
+        MergeBaseDb::new(&conn)?.prune(&repo)?;
+        PatchIdDb::new(&conn)?.prune(&repo)?;
+        // Cached CI statuses may refer to commits which Git is about to
+        // garbage-collect.
+        CiStatusDb::new(&conn)?.clear()?;
+        if get_gc_write_commit_graph(&repo)? {
+            write_commit_graph(effects, git_run_info, &repo)?;
+        }
+        // Record when garbage was last collected, for `gc_if_needed`.
+        event_log_db.make_transaction_id(SystemTime::now(), GC_TRANSACTION_MESSAGE)?;
+    }
+    Ok(ExitCode(0))
+}
+
+/// Write Git's commit-graph file for all reachable commits, as configured by
+/// `branchless.gc.writeCommitGraph`. Failing to write it only produces a
+/// warning, since it's just a cache.
+#[instrument]
+fn write_commit_graph(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<()> {
+    let result = git_run_info.run_silent(
+        repo,
+        None,
+        &["commit-graph", "write", "--reachable"],
+        GitRunOpts {
+            treat_git_failure_as_error: false,
+            ..Default::default()
+        },
+    )?;
+    if result.exit_code.is_success() {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: wrote commit-graph"
+        )?;
+    } else {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: could not write commit-graph (exit code {}): {}",
+            result.exit_code.0,
+            String::from_utf8_lossy(&result.stderr).trim(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Verify the consistency of branchless's own data:
+///
+/// - The event log database's tables and indexes are intact.
+/// - Every commit which is visible according to the event log exists in the
+///   object database.
+/// - Every reference which keeps a commit alive points to an existing commit.
+///
+/// If `repair` is set, visible commits which are missing are marked as hidden
+/// (in their own transaction, so that this can be undone with `git undo`), and
+/// broken references are deleted. Problems with the database itself can't be
+/// repaired.
+///
+/// Returns a failing exit code if any problems remain.
+#[instrument]
+pub fn check(effects: &Effects, repair: bool) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
     writeln!(
         effects.get_output_stream(),
-        "branchless: {num_dangling_references} deleted",
+        "branchless: checking consistency"
     )?;
+
+    let database_problems = event_log_db.check_integrity()?;
+
+    // Commits which are no longer visible may have been legitimately pruned by
+    // Git's garbage collection, so only visible ones are checked.
+    let missing_commit_oids: Vec<NonZeroOid> = {
+        let mut result = Vec::new();
+        for commit_oid in event_replayer.get_cursor_oids(event_cursor) {
+            match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
+                CommitActivityStatus::Active => {}
+                CommitActivityStatus::Inactive | CommitActivityStatus::Obsolete => continue,
+            }
+            if repo.find_commit(commit_oid)?.is_none() {
+                result.push(commit_oid);
+            }
+        }
+        result.sort();
+        result
+    };
+
+    let broken_references = {
+        let mut result = Vec::new();
+        for reference in repo.get_all_references()? {
+            if is_gc_ref(&reference.get_name()?) && reference.peel_to_commit()?.is_none() {
+                result.push(reference);
+            }
+        }
+        result
+    };
+
+    let num_database_problems = database_problems.len();
+    if num_database_problems > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: {} in the event log database:",
+            Pluralize {
+                determiner: None,
+                amount: num_database_problems,
+                unit: ("problem", "problems"),
+            },
+        )?;
+        for problem in database_problems {
+            writeln!(effects.get_output_stream(), "- {problem}")?;
+        }
+    }
+
+    let num_missing_commits = missing_commit_oids.len();
+    if num_missing_commits > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: {} missing from the object database{}:",
+            Pluralize {
+                determiner: None,
+                amount: num_missing_commits,
+                unit: ("visible commit", "visible commits"),
+            },
+            if repair { " (now hidden)" } else { "" },
+        )?;
+        for commit_oid in missing_commit_oids.iter() {
+            writeln!(effects.get_output_stream(), "- {commit_oid}")?;
+        }
+    }
+
+    let num_broken_references = broken_references.len();
+    if num_broken_references > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: {} not pointing to a commit{}:",
+            Pluralize {
+                determiner: None,
+                amount: num_broken_references,
+                unit: ("reference", "references"),
+            },
+            if repair { " (now deleted)" } else { "" },
+        )?;
+        for reference in broken_references.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "- {}",
+                reference.get_name()?.as_str()
+            )?;
+        }
+    }
+
+    if repair {
+        if !missing_commit_oids.is_empty() {
+            let now = SystemTime::now();
+            let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+            let event_tx_id = event_log_db.make_transaction_id(now, "gc-check")?;
+            let events = missing_commit_oids
+                .into_iter()
+                .map(|commit_oid| Event::ObsoleteEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                    message: Some("missing from the object database".to_string()),
+                })
+                .collect();
+            event_log_db.add_events(events)?;
+        }
+        for mut reference in broken_references.into_iter() {
+            reference.delete()?;
+        }
+    }
+
+    let num_remaining_problems = if repair {
+        num_database_problems
+    } else {
+        num_database_problems + num_missing_commits + num_broken_references
+    };
+    if num_database_problems + num_missing_commits + num_broken_references == 0 {
+        writeln!(effects.get_output_stream(), "branchless: no problems found")?;
+    } else if num_remaining_problems > 0 {
+        if !repair && num_missing_commits + num_broken_references > 0 {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: to repair, run: git branchless gc --check --repair"
+            )?;
+        }
+        return Ok(ExitCode(1));
+    }
+    Ok(ExitCode(0))
+}
+
+/// The message of the (empty) event transaction which `gc` records when it
+/// runs.
+const GC_TRANSACTION_MESSAGE: &str = "gc";
+
+/// Run `gc` if enough events have been recorded, or enough commits hidden,
+/// since it last ran, as configured by `branchless.gc.autoEvents` and
+/// `branchless.gc.autoHiddenCommits`. This is analogous to `git gc --auto`.
+#[instrument]
+pub fn gc_if_needed(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<()> {
+    let auto_events = get_gc_auto_events(repo)?;
+    let auto_hidden_commits = get_gc_auto_hidden_commits(repo)?;
+    if auto_events == 0 && auto_hidden_commits == 0 {
+        return Ok(());
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let (num_events, num_hidden_commits) =
+        event_log_db.count_events_since_transaction(GC_TRANSACTION_MESSAGE)?;
+    let reason = if auto_events > 0 && num_events >= auto_events {
+        Pluralize {
+            determiner: None,
+            amount: num_events,
+            unit: ("event", "events"),
+        }
+        .to_string()
+    } else if auto_hidden_commits > 0 && num_hidden_commits >= auto_hidden_commits {
+        Pluralize {
+            determiner: None,
+            amount: num_hidden_commits,
+            unit: ("hidden commit", "hidden commits"),
+        }
+        .to_string()
+    } else {
+        return Ok(());
+    };
+
+    // Report on standard error, since the output of the command which
+    // triggered this may be consumed by a script.
+    writeln!(
+        effects.get_error_stream(),
+        "branchless: collecting garbage automatically ({reason} since the last collection)"
+    )?;
+    // If another garbage collection is already running, there's nothing to do.
+    let _exit_code = gc(&effects.suppress(), git_run_info, false)?;
     Ok(())
 }
+
+/// Estimate the on-disk size of the objects which would become unreachable
+/// once the given references are deleted. Objects which are still referenced
+/// by reflogs aren't accounted for, so this is an upper bound. Returns `None`
+/// if the estimate couldn't be computed, such as with versions of Git which
+/// don't support `git rev-list --disk-usage`.
+#[instrument]
+fn estimate_reclaimable_size(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    references: &[Reference],
+) -> eyre::Result<Option<u64>> {
+    let mut args: Vec<String> = ["rev-list", "--objects", "--disk-usage"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let mut reference_names = Vec::new();
+    for reference in references {
+        if let Some(commit) = reference.peel_to_commit()? {
+            args.push(commit.get_oid().to_string());
+        }
+        reference_names.push(reference.get_name()?);
+    }
+    args.extend(
+        ["--not", "--branches", "--tags", "--remotes", "HEAD"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    );
+    args.extend(
+        reference_names
+            .iter()
+            .map(|reference_name| format!("--exclude={}", reference_name.as_str())),
+    );
+    args.push(format!("--glob={KEEP_REFS_PREFIX}*"));
+
+    let result = git_run_info.run_silent(
+        repo,
+        None,
+        &args,
+        GitRunOpts {
+            treat_git_failure_as_error: false,
+            ..Default::default()
+        },
+    )?;
+    if !result.exit_code.is_success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8(result.stdout).wrap_err("Decoding git rev-list output")?;
+    Ok(stdout.trim().parse().ok())
+}
+
+/// The number and on-disk size of the objects in a repository, as reported by
+/// `git count-objects`.
+#[derive(Clone, Copy, Debug, Default)]
+struct ObjectStats {
+    num_objects: u64,
+    size_kib: u64,
+}
+
+#[instrument]
+fn count_objects(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<ObjectStats> {
+    let result = git_run_info
+        .run_silent(repo, None, &["count-objects", "-v"], GitRunOpts::default())
+        .wrap_err("Counting objects")?;
+    let stdout = String::from_utf8(result.stdout).wrap_err("Decoding git count-objects output")?;
+
+    let mut stats = ObjectStats::default();
+    for line in stdout.lines() {
+        let (key, value) = match line.split_once(": ") {
+            Some((key, value)) => (key, value),
+            None => continue,
+        };
+        let value: u64 = match value.trim().parse() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        match key {
+            "count" | "in-pack" => stats.num_objects += value,
+            "size" | "size-pack" | "size-garbage" => stats.size_kib += value,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+fn format_size(size_bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut unit_index = 0;
+    let mut unit_size: u64 = 1;
+    while unit_index + 1 < UNITS.len() && size_bytes >= unit_size * 1024 {
+        unit_index += 1;
+        unit_size *= 1024;
+    }
+    if unit_index == 0 {
+        format!("{size_bytes} {}", UNITS[unit_index])
+    } else {
+        let tenths = size_bytes * 10 / unit_size;
+        format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit_index])
+    }
+}
+
+/// Repack the repository's objects and prune unreachable ones, such as for
+/// commits whose references were just deleted by `gc`, and report how much
+/// was reclaimed.
+///
+/// Pruning respects Git's usual expiry settings (such as `gc.pruneExpire`,
+/// which defaults to two weeks), and commits which are still referenced by
+/// reflogs are kept, so recently-hidden commits remain recoverable.
+#[instrument]
+pub fn repack_and_prune(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<ExitCode> {
+    if !confirm(
+        effects,
+        "Repack the repository and permanently delete unreachable objects?",
+    )? {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            effects.get_messages().format("confirm-aborted", &[])
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let stats_before = count_objects(git_run_info, repo)?;
+    let exit_code = git_run_info.run(effects, None, &["gc", "--quiet"])?;
+    if !exit_code.is_success() {
+        return Ok(exit_code);
+    }
+    let stats_after = count_objects(git_run_info, repo)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: reclaimed {} ({})",
+        Pluralize {
+            determiner: None,
+            amount: stats_before
+                .num_objects
+                .saturating_sub(stats_after.num_objects)
+                .try_into()
+                .unwrap_or(usize::MAX),
+            unit: ("object", "objects"),
+        },
+        format_size(stats_before.size_kib.saturating_sub(stats_after.size_kib) * 1024),
+    )?;
+    Ok(ExitCode(0))
+}
@@ -0,0 +1,128 @@
+//! Handle the `Change-Id` trailers which Gerrit uses to identify changes.
+//!
+//! Gerrit tracks each change by the `Change-Id` trailer in its commit message
+//! rather than by commit hash, so that a rewritten commit becomes a new patch
+//! set of the same change. The trailer therefore has to be kept when a commit
+//! is rewritten, such as with `git reword`.
+
+use bstr::ByteSlice;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::git::{Commit, Repo};
+
+lazy_static! {
+    static ref CHANGE_ID_RE: Regex = Regex::new(r"^Change-Id:\s*(I[0-9a-f]{40})\s*$")
+        .expect("Failed to compile `CHANGE_ID_RE` regex");
+    static ref TRAILER_RE: Regex =
+        Regex::new(r"^[A-Za-z0-9-]+:\s").expect("Failed to compile `TRAILER_RE` regex");
+}
+
+/// Get the last paragraph of the given message, if it's a paragraph of
+/// trailers (and not the only paragraph, which would be the summary).
+fn get_trailers_paragraph(message: &str) -> Option<&str> {
+    let message = message.trim_end();
+    let (_body, last_paragraph) = message.rsplit_once("\n\n")?;
+    if last_paragraph.lines().all(|line| TRAILER_RE.is_match(line)) {
+        Some(last_paragraph)
+    } else {
+        None
+    }
+}
+
+/// Get the `Change-Id` trailer of the given commit message, if any.
+pub fn get_change_id(message: &str) -> Option<&str> {
+    let trailers = get_trailers_paragraph(message)?;
+    trailers.lines().rev().find_map(|line| {
+        CHANGE_ID_RE
+            .captures(line)
+            .and_then(|captures| captures.get(1))
+            .map(|change_id| change_id.as_str())
+    })
+}
+
+/// Add a `Change-Id` trailer to the given commit message, after any existing
+/// trailers.
+pub fn add_change_id(message: &str, change_id: &str) -> String {
+    let message = message.trim_end();
+    if get_trailers_paragraph(message).is_some() {
+        format!("{message}\nChange-Id: {change_id}\n")
+    } else {
+        format!("{message}\n\nChange-Id: {change_id}\n")
+    }
+}
+
+/// Generate a new `Change-Id` for the given commit. Like the one generated by
+/// Gerrit's `commit-msg` hook, it's derived from the commit's contents, so
+/// it's unique to the commit but deterministic.
+pub fn make_change_id(repo: &Repo, commit: &Commit) -> eyre::Result<String> {
+    let contents = format!(
+        "commit {}\n\n{}",
+        commit.get_oid(),
+        commit.get_message_raw()?.to_str_lossy()
+    );
+    let oid = repo.hash_blob_contents(contents.as_bytes())?;
+    Ok(format!("I{oid}"))
+}
+
+/// Copy the `Change-Id` trailer of `old_message` to `new_message` if it was
+/// removed, so that the rewritten commit still belongs to the same change.
+pub fn preserve_change_id(old_message: &str, new_message: &str) -> String {
+    match (get_change_id(old_message), get_change_id(new_message)) {
+        (Some(change_id), None) => add_change_id(new_message, change_id),
+        (None, _) | (Some(_), Some(_)) => new_message.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGE_ID: &str = "I0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn test_get_change_id() {
+        assert_eq!(get_change_id("foo"), None);
+        assert_eq!(get_change_id(&format!("Change-Id: {CHANGE_ID}")), None);
+        assert_eq!(
+            get_change_id(&format!("foo\n\nbar\n\nChange-Id: {CHANGE_ID}\n")),
+            Some(CHANGE_ID)
+        );
+        assert_eq!(
+            get_change_id(&format!(
+                "foo\n\nChange-Id: {CHANGE_ID}\nSigned-off-by: Foo <foo@example.com>\n"
+            )),
+            Some(CHANGE_ID)
+        );
+        assert_eq!(
+            get_change_id(&format!("foo\n\nChange-Id: {CHANGE_ID}\n\nbar\n")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_add_change_id() {
+        insta::assert_snapshot!(add_change_id("foo\n", CHANGE_ID), @r###"
+        foo
+
+        Change-Id: I0123456789abcdef0123456789abcdef01234567
+        "###);
+        insta::assert_snapshot!(
+            add_change_id("foo\n\nSigned-off-by: Foo <foo@example.com>\n", CHANGE_ID),
+            @r###"
+        foo
+
+        Signed-off-by: Foo <foo@example.com>
+        Change-Id: I0123456789abcdef0123456789abcdef01234567
+        "###);
+        insta::assert_snapshot!(
+            preserve_change_id(&add_change_id("foo\n", CHANGE_ID), "bar\n\nbaz\n"),
+            @r###"
+        bar
+
+        baz
+
+        Change-Id: I0123456789abcdef0123456789abcdef01234567
+        "###);
+    }
+}
@@ -0,0 +1,275 @@
+//! Translate user-facing messages.
+//!
+//! Messages are identified by an ID, and may contain placeables of the form
+//! `{ $name }`, which are filled in with [`Messages::format`]. The English
+//! versions of the messages are built in. Translations are loaded from files
+//! named after the user's locale (such as `de_DE.ftl` or `de.ftl`) in the
+//! directory given by the `branchless.core.localeDir` config setting. Any
+//! message which isn't translated falls back to English.
+//!
+//! Translation files use a subset of the [Fluent](https://projectfluent.org)
+//! syntax:
+//!
+//! ```text
+//! # Comments start with `#`.
+//! confirm-aborted = Abgebrochen.
+//! hint-suppression-notice =
+//!     Um diesen Hinweis zu deaktivieren, führe aus: git config --global { $config-key } false
+//! ```
+//!
+//! A message may span multiple lines by indenting its continuation lines. The
+//! common indentation of the continuation lines is removed.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use tracing::instrument;
+
+/// The built-in English messages, which are used when no translation is
+/// available.
+const ENGLISH_MESSAGES: &[(&str, &str)] = &[
+    ("hint", "hint"),
+    (
+        "hint-suppression-notice",
+        "disable this hint by running: git config --global { $config-key } false",
+    ),
+    ("confirm-aborted", "Aborted."),
+    ("abandoned-warning", "This operation abandoned { $items }!"),
+    ("abandoned-commits-list", "The abandoned commits are:"),
+    (
+        "abandoned-suggestions",
+        "\
+Consider running one of the following:
+  - { $git-restack }: re-apply the abandoned commits/branches
+    (this is most likely what you want to do)
+  - { $git-smartlog }: assess the situation
+  - { $git-hide } [<commit>...]: hide the commits from the smartlog
+  - { $git-undo }: undo the operation",
+    ),
+];
+
+/// A catalog of user-facing messages for a particular locale.
+#[derive(Clone, Debug, Default)]
+pub struct Messages {
+    locale: Option<String>,
+    translations: HashMap<String, String>,
+}
+
+impl Messages {
+    /// Load the translations for the given locale (such as `de_DE`) from the
+    /// given directory. The file for the full locale is preferred, followed by
+    /// the file for just its language (such as `de`). If neither exists, only
+    /// the English messages are used.
+    #[instrument]
+    pub fn load(locale_dir: &Path, locale: &str) -> eyre::Result<Self> {
+        let language = locale.split('_').next().unwrap_or(locale);
+        for name in [locale, language] {
+            let path = locale_dir.join(format!("{name}.ftl"));
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let translations = parse_messages(&contents)
+                .map_err(|err| eyre::eyre!("Could not parse {path:?}: {err}"))?;
+            return Ok(Self {
+                locale: Some(name.to_owned()),
+                translations,
+            });
+        }
+        Ok(Self::default())
+    }
+
+    /// The locale of the loaded translations, if any.
+    pub fn get_locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Get the message with the given ID, with its placeables replaced by the
+    /// corresponding values in `args`.
+    ///
+    /// Panics if there is no built-in English message with the given ID.
+    pub fn format(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        let template = match self.translations.get(id) {
+            Some(template) => template.as_str(),
+            None => ENGLISH_MESSAGES
+                .iter()
+                .find(|(english_id, _)| *english_id == id)
+                .map(|(_, template)| *template)
+                .unwrap_or_else(|| panic!("Unknown message ID: {id}")),
+        };
+        interpolate(template, args)
+    }
+}
+
+/// Determine the user's locale for messages from the environment, in the
+/// same way as `gettext`: the first non-empty value of `$LC_ALL`,
+/// `$LC_MESSAGES`, and `$LANG`, with any encoding (such as `.UTF-8`) or
+/// modifier removed. Returns `None` for the `C` and `POSIX` locales.
+pub fn detect_locale() -> Option<String> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    let locale = value.split(['.', '@']).next().unwrap_or_default();
+    match locale {
+        "" | "C" | "POSIX" => None,
+        locale => Some(locale.to_owned()),
+    }
+}
+
+fn parse_messages(contents: &str) -> Result<HashMap<String, String>, String> {
+    let mut result = HashMap::new();
+    let mut current: Option<(String, String, Vec<&str>)> = None;
+    let mut finish = |current: Option<(String, String, Vec<&str>)>| {
+        if let Some((id, first_line, continuation_lines)) = current {
+            let indent = continuation_lines
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or_default();
+            let mut lines = Vec::new();
+            if !first_line.is_empty() {
+                lines.push(first_line.as_str());
+            }
+            lines.extend(
+                continuation_lines
+                    .iter()
+                    .map(|line| line.get(indent..).unwrap_or_default().trim_end()),
+            );
+            while lines.last() == Some(&"") {
+                lines.pop();
+            }
+            result.insert(id, lines.join("\n"));
+        }
+    };
+
+    for (line_num, line) in contents.lines().enumerate() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            match current.as_mut() {
+                Some((_, _, continuation_lines)) => continuation_lines.push(line),
+                None if line.trim().is_empty() => {}
+                None => {
+                    return Err(format!(
+                        "line {}: indented line doesn't belong to a message",
+                        line_num + 1
+                    ))
+                }
+            }
+            continue;
+        }
+
+        finish(current.take());
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (id, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `<message-id> = <text>`", line_num + 1))?;
+        let id = id.trim();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("line {}: invalid message ID: {id:?}", line_num + 1));
+        }
+        current = Some((id.to_owned(), value.trim().to_owned(), Vec::new()));
+    }
+    finish(current.take());
+    Ok(result)
+}
+
+fn interpolate(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let (before, placeable) = rest.split_at(start);
+        result.push_str(before);
+        let end = match placeable.find('}') {
+            Some(end) => end,
+            None => {
+                rest = placeable;
+                break;
+            }
+        };
+        let name = placeable[1..end].trim();
+        match name
+            .strip_prefix('$')
+            .and_then(|name| args.iter().find(|(arg_name, _)| *arg_name == name))
+        {
+            Some((_, value)) => result.push_str(&value.to_string()),
+            None => result.push_str(&placeable[..=end]),
+        }
+        rest = &placeable[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_messages() -> Result<(), String> {
+        let messages = parse_messages(
+            "\
+# A comment.
+confirm-aborted = Abgebrochen.
+
+abandoned-suggestions =
+    Erwäge einen der folgenden Befehle:
+      - { $git-restack }: die Commits wiederherstellen
+hint = Hinweis
+",
+        )?;
+        let mut messages: Vec<_> = messages.into_iter().collect();
+        messages.sort();
+        insta::assert_debug_snapshot!(messages, @r###"
+        [
+            (
+                "abandoned-suggestions",
+                "Erwäge einen der folgenden Befehle:\n  - { $git-restack }: die Commits wiederherstellen",
+            ),
+            (
+                "confirm-aborted",
+                "Abgebrochen.",
+            ),
+            (
+                "hint",
+                "Hinweis",
+            ),
+        ]
+        "###);
+
+        insta::assert_debug_snapshot!(parse_messages("not a message"), @r###"
+        Err(
+            "line 1: expected `<message-id> = <text>`",
+        )
+        "###);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_message() {
+        let mut messages = Messages::default();
+        assert_eq!(
+            messages.format("hint-suppression-notice", &[("config-key", &"foo.bar")]),
+            "disable this hint by running: git config --global foo.bar false"
+        );
+
+        messages.translations.insert(
+            "hint-suppression-notice".to_owned(),
+            "{ $config-key } auf false setzen, um { $missing } zu deaktivieren".to_owned(),
+        );
+        assert_eq!(
+            messages.format("hint-suppression-notice", &[("config-key", &"foo.bar")]),
+            "foo.bar auf false setzen, um { $missing } zu deaktivieren"
+        );
+        assert_eq!(messages.format("confirm-aborted", &[]), "Aborted.");
+    }
+}
@@ -0,0 +1,168 @@
+//! References to issues in an external issue tracker, such as GitHub issues or
+//! Jira tickets.
+//!
+//! Issues are referenced by trailers in commit messages (such as `Fixes: #123`
+//! or `Jira: ABC-1`). Which trailers are recognized is configured with
+//! `branchless.issues.trailers`. The status of each issue can optionally be
+//! looked up with an [`IssueStatusProvider`], so that it can be shown in the
+//! smartlog.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use eyre::{eyre, Context};
+use tracing::instrument;
+
+use crate::core::config::get_issues_status_command;
+use crate::git::Repo;
+use crate::util::get_sh;
+
+/// The environment variable which contains the trailer key of the issue whose
+/// status is being queried by `branchless.issues.statusCommand`.
+pub const BRANCHLESS_ISSUE_TRAILER_ENV_VAR: &str = "BRANCHLESS_ISSUE_TRAILER";
+
+/// The environment variable which contains the ID of the issue whose status is
+/// being queried by `branchless.issues.statusCommand`.
+pub const BRANCHLESS_ISSUE_ID_ENV_VAR: &str = "BRANCHLESS_ISSUE_ID";
+
+/// A reference to an issue in a commit message trailer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IssueReference {
+    /// The key of the trailer which referenced the issue, as written in the
+    /// commit message (such as `Fixes`).
+    pub trailer: String,
+
+    /// The tracker-specific identifier of the issue (such as `#123` or
+    /// `ABC-1`).
+    pub id: String,
+}
+
+/// Find the references to issues in the trailers (the last paragraph) of the
+/// given commit message. Only trailers whose keys are in `trailers` are considered, compared
+/// case-insensitively. A trailer may reference several issues, separated by
+/// commas.
+///
+/// ```
+/// # use branchless::core::issues::{parse_issue_references, IssueReference};
+/// let message = "Fixes: the frobnicator\n\nFixes: #123, #456\njira: ABC-1\nSee: #789\n";
+/// let trailers = vec!["Fixes".to_string(), "Jira".to_string()];
+/// assert_eq!(
+///     parse_issue_references(message, &trailers),
+///     vec![
+///         IssueReference { trailer: "Fixes".to_string(), id: "#123".to_string() },
+///         IssueReference { trailer: "Fixes".to_string(), id: "#456".to_string() },
+///         IssueReference { trailer: "jira".to_string(), id: "ABC-1".to_string() },
+///     ],
+/// );
+/// ```
+pub fn parse_issue_references(message: &str, trailers: &[String]) -> Vec<IssueReference> {
+    let mut result = Vec::new();
+    if trailers.is_empty() {
+        return result;
+    }
+    // Trailers are in the last paragraph of the message, which can't also be
+    // the summary line.
+    let trailer_paragraph = match message.trim_end().rsplit_once("\n\n") {
+        Some((_, trailer_paragraph)) => trailer_paragraph,
+        None => return result,
+    };
+    for line in trailer_paragraph.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value),
+            None => continue,
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            continue;
+        }
+        if !trailers
+            .iter()
+            .any(|trailer| trailer.eq_ignore_ascii_case(key))
+        {
+            continue;
+        }
+        for id in value.split(',') {
+            let id = id.trim();
+            if !id.is_empty() {
+                result.push(IssueReference {
+                    trailer: key.to_owned(),
+                    id: id.to_owned(),
+                });
+            }
+        }
+    }
+    result
+}
+
+/// A service which reports the status of issues.
+pub trait IssueStatusProvider: std::fmt::Debug {
+    /// Look up the status of the given issue (such as `open` or `closed`), if
+    /// known.
+    fn query_status(&mut self, issue: &IssueReference) -> eyre::Result<Option<String>>;
+}
+
+/// Construct the issue status provider configured with
+/// `branchless.issues.statusCommand`, if any.
+#[instrument]
+pub fn make_issue_status_provider(
+    repo: &Repo,
+) -> eyre::Result<Option<Box<dyn IssueStatusProvider>>> {
+    let command = match get_issues_status_command(repo)? {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    let working_directory = repo
+        .get_working_copy_path()
+        .unwrap_or_else(|| repo.get_path())
+        .to_path_buf();
+    Ok(Some(Box::new(CommandIssueStatusProvider {
+        command,
+        working_directory,
+        statuses: Default::default(),
+    })))
+}
+
+/// Reports the status of issues by running a user-provided shell command. The
+/// trailer key and issue ID are passed in the [`BRANCHLESS_ISSUE_TRAILER_ENV_VAR`]
+/// and [`BRANCHLESS_ISSUE_ID_ENV_VAR`] environment variables, and the first
+/// line of the command's output is used as the status. Each issue is only
+/// queried once.
+#[derive(Debug)]
+pub struct CommandIssueStatusProvider {
+    command: String,
+    working_directory: PathBuf,
+    statuses: HashMap<IssueReference, Option<String>>,
+}
+
+impl IssueStatusProvider for CommandIssueStatusProvider {
+    fn query_status(&mut self, issue: &IssueReference) -> eyre::Result<Option<String>> {
+        if let Some(status) = self.statuses.get(issue) {
+            return Ok(status.clone());
+        }
+
+        let output = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
+            .current_dir(&self.working_directory)
+            .arg("-c")
+            .arg(&self.command)
+            .env(BRANCHLESS_ISSUE_TRAILER_ENV_VAR, &issue.trailer)
+            .env(BRANCHLESS_ISSUE_ID_ENV_VAR, &issue.id)
+            .stdin(Stdio::null())
+            .output()
+            .wrap_err_with(|| format!("Running issue status command {:?}", self.command))?;
+        if !output.status.success() {
+            eyre::bail!(
+                "Issue status command {:?} failed for {}: {}",
+                self.command,
+                issue.id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let status = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty());
+        self.statuses.insert(issue.clone(), status.clone());
+        Ok(status)
+    }
+}
@@ -0,0 +1,277 @@
+//! Persistent cache of merge-base computations.
+//!
+//! `git smartlog` and `git restack` look up the merge-base of each draft
+//! commit with the main branch every time they run, and walking the commit
+//! graph to find it can dominate their run time in large repositories. The
+//! merge-bases of a pair of commits are fixed once both commits exist, so the
+//! results are kept in the database, keyed by the (unordered) pair.
+//!
+//! An entry only becomes stale once one of its commits has been deleted by
+//! Git's garbage collection, so `git branchless gc` prunes just those entries.
+//! The whole cache is discarded if the commit graph index is rebuilt because
+//! the shallow clone boundary moved, since that changes commits' ancestry.
+
+use eden_dag::DagAlgorithm;
+use eyre::Context;
+use rusqlite::OptionalExtension;
+use tracing::instrument;
+
+use crate::core::dag::{commit_set_to_vec, CommitSet, Dag};
+use crate::git::{NonZeroOid, Repo};
+
+/// Stores the merge-bases of pairs of commits on disk.
+pub struct MergeBaseDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for MergeBaseDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<MergeBaseDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS merge_bases (
+    lhs_oid TEXT NOT NULL,
+    rhs_oid TEXT NOT NULL,
+    merge_base_oids TEXT NOT NULL,
+    PRIMARY KEY (lhs_oid, rhs_oid)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `merge_bases` table")?;
+    Ok(())
+}
+
+impl<'conn> MergeBaseDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(MergeBaseDb { conn })
+    }
+
+    /// Get all the merge-bases of the two given commits (of which there may be
+    /// more than one in the case of criss-cross merges), computing them with
+    /// the DAG if they aren't already cached. The result is empty if the
+    /// commits have no common ancestor.
+    #[instrument]
+    pub fn get_merge_bases(
+        &self,
+        dag: &Dag,
+        lhs_oid: NonZeroOid,
+        rhs_oid: NonZeroOid,
+    ) -> eyre::Result<Vec<NonZeroOid>> {
+        // Merge-bases are symmetric, so only store each pair once.
+        let (lhs_oid, rhs_oid) = if lhs_oid <= rhs_oid {
+            (lhs_oid, rhs_oid)
+        } else {
+            (rhs_oid, lhs_oid)
+        };
+
+        let cached: Option<String> = self
+            .conn
+            .query_row(
+                "
+SELECT merge_base_oids
+FROM merge_bases
+WHERE lhs_oid = :lhs_oid AND rhs_oid = :rhs_oid
+",
+                rusqlite::named_params! {
+                    ":lhs_oid": lhs_oid.to_string(),
+                    ":rhs_oid": rhs_oid.to_string(),
+                },
+                |row| row.get("merge_base_oids"),
+            )
+            .optional()
+            .wrap_err("Querying merge-base cache")?;
+        if let Some(cached) = cached {
+            return cached.split_whitespace().map(|oid| oid.parse()).collect();
+        }
+
+        let commit_set: CommitSet = [lhs_oid, rhs_oid].into_iter().collect();
+        let merge_base_oids = commit_set_to_vec(&dag.query().gca_all(commit_set)?)?;
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO merge_bases VALUES (:lhs_oid, :rhs_oid, :merge_base_oids)
+",
+                rusqlite::named_params! {
+                    ":lhs_oid": lhs_oid.to_string(),
+                    ":rhs_oid": rhs_oid.to_string(),
+                    ":merge_base_oids": merge_base_oids
+                        .iter()
+                        .map(|oid| oid.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                },
+            )
+            .wrap_err("Updating merge-base cache")?;
+        Ok(merge_base_oids)
+    }
+
+    /// Get a single merge-base of the two given commits, if they have a common
+    /// ancestor. This agrees with `dag.query().gca_one(...)`.
+    #[instrument]
+    pub fn get_merge_base(
+        &self,
+        dag: &Dag,
+        lhs_oid: NonZeroOid,
+        rhs_oid: NonZeroOid,
+    ) -> eyre::Result<Option<NonZeroOid>> {
+        match self.get_merge_bases(dag, lhs_oid, rhs_oid)?.as_slice() {
+            [] => Ok(None),
+            [merge_base_oid] => Ok(Some(*merge_base_oid)),
+            _ => {
+                // Which of several merge-bases is chosen is up to the DAG, so
+                // defer to it in this (rare) case.
+                let commit_set: CommitSet = [lhs_oid, rhs_oid].into_iter().collect();
+                match dag.query().gca_one(commit_set)? {
+                    Some(merge_base_oid) => Ok(Some(NonZeroOid::try_from(merge_base_oid)?)),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Remove the cached merge-bases which refer to a commit that no longer
+    /// exists in the repository, either as one of the pair or as one of its
+    /// merge-bases. Returns the number of entries removed.
+    #[instrument]
+    pub fn prune(&self, repo: &Repo) -> eyre::Result<usize> {
+        let entries: Vec<(String, String, String)> = {
+            let mut statement = self
+                .conn
+                .prepare("SELECT lhs_oid, rhs_oid, merge_base_oids FROM merge_bases")
+                .wrap_err("Preparing statement")?;
+            let rows = statement
+                .query_map(rusqlite::params![], |row| {
+                    Ok((
+                        row.get("lhs_oid")?,
+                        row.get("rhs_oid")?,
+                        row.get("merge_base_oids")?,
+                    ))
+                })
+                .wrap_err("Querying merge-base cache")?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let commit_exists = |oid: &str| -> eyre::Result<bool> {
+            match oid.parse::<NonZeroOid>() {
+                Ok(oid) => Ok(repo.find_commit(oid)?.is_some()),
+                Err(_) => Ok(false),
+            }
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        let mut num_removed = 0;
+        for (lhs_oid, rhs_oid, merge_base_oids) in entries {
+            let mut is_stale = false;
+            for oid in [lhs_oid.as_str(), rhs_oid.as_str()]
+                .into_iter()
+                .chain(merge_base_oids.split_whitespace())
+            {
+                if !commit_exists(oid)? {
+                    is_stale = true;
+                    break;
+                }
+            }
+            if !is_stale {
+                continue;
+            }
+            num_removed += tx
+                .execute(
+                    "DELETE FROM merge_bases WHERE lhs_oid = :lhs_oid AND rhs_oid = :rhs_oid",
+                    rusqlite::named_params! {
+                        ":lhs_oid": lhs_oid,
+                        ":rhs_oid": rhs_oid,
+                    },
+                )
+                .wrap_err("Pruning merge-base cache")?;
+        }
+        tx.commit()?;
+        Ok(num_removed)
+    }
+
+    /// Remove all cached merge-bases. Returns the number of entries removed.
+    #[instrument]
+    pub fn clear(&self) -> eyre::Result<usize> {
+        let num_removed = self
+            .conn
+            .execute("DELETE FROM merge_bases", rusqlite::params![])
+            .wrap_err("Clearing merge-base cache")?;
+        Ok(num_removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::effects::Effects;
+    use crate::core::eventlog::{EventLogDb, EventReplayer};
+    use crate::core::formatting::Glyphs;
+    use crate::core::repo_ext::RepoExt;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_merge_base_cache() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["checkout", "HEAD^"])?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let references_snapshot = repo.get_references_snapshot()?;
+        let dag = Dag::open_and_sync(
+            &effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+        assert_eq!(
+            merge_base_db.get_merge_base(&dag, test2_oid, test3_oid)?,
+            Some(test1_oid)
+        );
+        assert_eq!(
+            merge_base_db.get_merge_bases(&dag, test3_oid, test2_oid)?,
+            vec![test1_oid]
+        );
+        let num_cached: usize =
+            conn.query_row("SELECT COUNT(*) FROM merge_bases", [], |row| row.get(0))?;
+        assert_eq!(num_cached, 1);
+
+        // Only the entry which refers to a missing commit is pruned.
+        conn.execute(
+            "INSERT INTO merge_bases VALUES (:lhs_oid, :rhs_oid, :merge_base_oids)",
+            rusqlite::named_params! {
+                ":lhs_oid": test1_oid.to_string(),
+                ":rhs_oid": "1234567890123456789012345678901234567890",
+                ":merge_base_oids": test1_oid.to_string(),
+            },
+        )?;
+        assert_eq!(merge_base_db.prune(&repo)?, 1);
+        assert_eq!(merge_base_db.prune(&repo)?, 0);
+
+        assert_eq!(merge_base_db.clear()?, 1);
+        assert_eq!(
+            merge_base_db.get_merge_base(&dag, test3_oid, test2_oid)?,
+            Some(test1_oid)
+        );
+
+        Ok(())
+    }
+}
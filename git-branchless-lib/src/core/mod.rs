@@ -1,13 +1,28 @@
 //! Core algorithms and data structures.
 
+pub mod archive;
 pub mod check_out;
+pub mod child_index;
+pub mod ci_status;
+pub mod codeowners;
 pub mod config;
 pub mod dag;
 pub mod effects;
 pub mod eventlog;
+pub mod forge;
 pub mod formatting;
 pub mod gc;
+pub mod gerrit;
+pub mod i18n;
+pub mod issues;
+pub mod merge_base;
 pub mod node_descriptors;
+pub mod pager;
+pub mod patch_id;
+pub mod prompt;
+pub mod protected_branches;
 pub mod repo_ext;
 pub mod rewrite;
 pub mod task;
+pub mod user_error;
+pub mod user_hooks;
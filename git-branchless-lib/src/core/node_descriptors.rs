@@ -6,25 +6,29 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use bstr::{ByteSlice, ByteVec};
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use lazy_static::lazy_static;
 use regex::Regex;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::core::config::{
-    get_commit_descriptors_branches, get_commit_descriptors_differential_revision,
-    get_commit_descriptors_relative_time,
+    get_ci_refresh_interval, get_commit_descriptors_branches,
+    get_commit_descriptors_differential_revision, get_commit_descriptors_relative_time,
+    get_commit_descriptors_worktrees, get_issues_trailers,
 };
 use crate::git::{
-    CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    get_test_worktrees_dir, CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo,
+    ResolvedReferenceInfo,
 };
 
-use super::eventlog::{Event, EventCursor, EventReplayer};
+use super::ci_status::{make_ci_status_provider, CiStatusDb, CiStatusProvider};
+use super::eventlog::{CommitActivityStatus, CopyKind, Event, EventCursor, EventReplayer};
+use super::forge::{CiStatus, CodeReview, CodeReviewDb, PHABRICATOR_FORGE_NAME};
 use super::formatting::{Glyphs, StyledStringBuilder};
+use super::issues::{make_issue_status_provider, parse_issue_references, IssueStatusProvider};
 use super::repo_ext::RepoReferencesSnapshot;
 use super::rewrite::find_rewrite_target;
 
@@ -189,12 +193,12 @@ impl NodeDescriptor for CommitOidDescriptor {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         let oid = object.get_short_oid()?;
         let oid = if self.use_color {
-            StyledString::styled(oid, BaseColor::Yellow.dark())
+            StyledString::styled(oid, glyphs.theme.commit_oid)
         } else {
             StyledString::plain(oid)
         };
@@ -253,7 +257,7 @@ impl<'a> ObsolescenceExplanationDescriptor<'a> {
 impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         let event = self
@@ -272,25 +276,87 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
                 rewrite_target.map(|rewritten_oid| {
                     StyledString::styled(
                         format!("(rewritten as {})", &rewritten_oid.to_string()[..8]),
-                        BaseColor::Black.light(),
+                        glyphs.theme.annotation,
                     )
                 })
             }
 
-            Event::ObsoleteEvent { .. } => Some(StyledString::styled(
+            Event::ObsoleteEvent {
+                message: Some(message),
+                ..
+            } => Some(StyledString::styled(
+                format!("(manually hidden: {message})"),
+                glyphs.theme.annotation,
+            )),
+
+            Event::ObsoleteEvent { message: None, .. } => Some(StyledString::styled(
                 "(manually hidden)",
-                BaseColor::Black.light(),
+                glyphs.theme.annotation,
             )),
 
             Event::RefUpdateEvent { .. }
             | Event::CommitEvent { .. }
             | Event::UnobsoleteEvent { .. }
+            | Event::CopyEvent { .. }
             | Event::WorkingCopySnapshot { .. } => None,
         };
         Ok(result)
     }
 }
 
+/// For commits which were cherry-picked or reverted, show the commits which
+/// were created by doing so.
+pub struct CopiedCommitDescriptor<'a> {
+    event_replayer: &'a EventReplayer,
+    event_cursor: EventCursor,
+}
+
+impl<'a> CopiedCommitDescriptor<'a> {
+    /// Constructor.
+    pub fn new(event_replayer: &'a EventReplayer, event_cursor: EventCursor) -> eyre::Result<Self> {
+        Ok(CopiedCommitDescriptor {
+            event_replayer,
+            event_cursor,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for CopiedCommitDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let descriptions = self
+            .event_replayer
+            .get_cursor_commit_copies(self.event_cursor, object.get_oid())
+            .into_iter()
+            // Don't mention copies which have since been hidden or undone.
+            .filter(|(new_commit_oid, _)| {
+                !matches!(
+                    self.event_replayer
+                        .get_cursor_commit_activity_status(self.event_cursor, *new_commit_oid),
+                    CommitActivityStatus::Obsolete
+                )
+            })
+            .map(|(new_commit_oid, kind)| {
+                let short_oid = &new_commit_oid.to_string()[..8];
+                match kind {
+                    CopyKind::CherryPick => format!("cherry-picked as {short_oid}"),
+                    CopyKind::Revert => format!("reverted by {short_oid}"),
+                }
+            })
+            .collect::<Vec<_>>();
+        if descriptions.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(StyledString::styled(
+            format!("({})", descriptions.join(", ")),
+            glyphs.theme.annotation,
+        )))
+    }
+}
+
 /// Display branches that point to a given commit.
 #[derive(Debug)]
 pub struct BranchesDescriptor<'a> {
@@ -371,32 +437,91 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
             branch_names.sort_unstable();
             let result = StyledString::styled(
                 format!("({})", branch_names.join(", ")),
-                BaseColor::Green.light(),
+                glyphs.theme.branch,
             );
             Ok(Some(result))
         }
     }
 }
 
+/// Display the other worktrees which have a given commit checked out.
+#[derive(Debug)]
+pub struct WorktreesDescriptor {
+    worktree_names: HashMap<NonZeroOid, Vec<String>>,
+}
+
+impl WorktreesDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo) -> eyre::Result<Self> {
+        let mut worktree_names: HashMap<NonZeroOid, Vec<String>> = HashMap::new();
+        if get_commit_descriptors_worktrees(repo)? {
+            // The worktrees used by `git test` are an implementation detail.
+            let test_worktrees_dir = get_test_worktrees_dir(repo);
+            for worktree in repo.get_other_worktrees()? {
+                if worktree.path.starts_with(&test_worktrees_dir) {
+                    continue;
+                }
+                if let Some(head_oid) = worktree.head_oid {
+                    worktree_names
+                        .entry(head_oid)
+                        .or_default()
+                        .push(worktree.name);
+                }
+            }
+        }
+        for names in worktree_names.values_mut() {
+            names.sort_unstable();
+        }
+        Ok(WorktreesDescriptor { worktree_names })
+    }
+}
+
+impl NodeDescriptor for WorktreesDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let result = self.worktree_names.get(&object.get_oid()).map(|names| {
+            StyledString::styled(
+                format!("[worktree: {}]", names.join(", ")),
+                glyphs.theme.worktree,
+            )
+        });
+        Ok(result)
+    }
+}
+
 /// Display the associated Phabricator revision for a given commit.
 #[derive(Debug)]
 pub struct DifferentialRevisionDescriptor<'a> {
     is_enabled: bool,
     redactor: &'a Redactor,
+    revisions: HashMap<String, CodeReview>,
 }
 
 impl<'a> DifferentialRevisionDescriptor<'a> {
     /// Constructor.
     pub fn new(repo: &Repo, redactor: &'a Redactor) -> eyre::Result<Self> {
         let is_enabled = get_commit_descriptors_differential_revision(repo)?;
+        let revisions = if is_enabled {
+            let conn = repo.get_db_conn()?;
+            CodeReviewDb::new(&conn)?.get_reviews(PHABRICATOR_FORGE_NAME)?
+        } else {
+            Default::default()
+        };
         Ok(DifferentialRevisionDescriptor {
             is_enabled,
             redactor,
+            revisions,
         })
     }
 }
 
-fn extract_diff_number(message: &str) -> Option<String> {
+/// Get the Phabricator revision (such as `D123`) from the `Differential
+/// Revision` trailer of the given commit message, if any.
+pub fn extract_diff_number(message: &str) -> Option<String> {
     lazy_static! {
         static ref RE: Regex = Regex::new(
             r"(?mx)
@@ -417,7 +542,7 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         match self.redactor {
@@ -436,7 +561,245 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
             Some(diff_number) => diff_number,
             None => return Ok(None),
         };
-        let result = StyledString::styled(diff_number, BaseColor::Green.dark());
+        // Show the state of the revision as of the last `git submit`, if
+        // known.
+        let description = match self
+            .revisions
+            .get(&diff_number)
+            .and_then(|revision| revision.state.as_ref())
+        {
+            Some(state) => format!("{diff_number} [{state}]"),
+            None => diff_number,
+        };
+        let result = StyledString::styled(description, glyphs.theme.differential_revision);
+        Ok(Some(result))
+    }
+}
+
+/// Display the code reviews (such as GitHub pull requests) which a given
+/// commit was last submitted as, along with the status of their continuous
+/// integration checks, as recorded by `git submit`.
+#[derive(Debug)]
+pub struct CodeReviewDescriptor<'a> {
+    reviews: HashMap<NonZeroOid, Vec<(String, CodeReview)>>,
+    redactor: &'a Redactor,
+}
+
+impl<'a> CodeReviewDescriptor<'a> {
+    /// Constructor.
+    pub fn new(repo: &Repo, redactor: &'a Redactor) -> eyre::Result<Self> {
+        let conn = repo.get_db_conn()?;
+        let reviews = CodeReviewDb::new(&conn)?.get_reviews_by_commit()?;
+        Ok(CodeReviewDescriptor { reviews, redactor })
+    }
+}
+
+impl<'a> NodeDescriptor for CodeReviewDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        match self.redactor {
+            Redactor::Enabled { .. } => return Ok(None),
+            Redactor::Disabled => {}
+        }
+        let reviews = match self.reviews.get(&object.get_oid()) {
+            Some(reviews) => reviews,
+            None => return Ok(None),
+        };
+        let description: Vec<String> = reviews
+            .iter()
+            // Phabricator revisions are shown by `DifferentialRevisionDescriptor`
+            // instead, since they're tracked through rewrites by the commit
+            // message.
+            .filter(|(forge, _review)| forge != PHABRICATOR_FORGE_NAME)
+            .map(|(_forge, review)| match review.ci_status {
+                CiStatus::Unknown => review.id.clone(),
+                ci_status => format!("{} [CI {ci_status}]", review.id),
+            })
+            .collect();
+        if description.is_empty() {
+            return Ok(None);
+        }
+        let result =
+            StyledString::styled(description.join(", "), glyphs.theme.differential_revision);
+        Ok(Some(result))
+    }
+}
+
+/// Display the CI status of each pushed commit, as reported by the provider
+/// configured with `branchless.ci.provider`. Only commits which a
+/// remote-tracking branch points to are queried, since the provider won't
+/// have run any checks on other commits.
+#[derive(Debug)]
+pub struct CiStatusDescriptor<'a> {
+    provider: Option<Box<dyn CiStatusProvider>>,
+    pushed_oids: HashSet<NonZeroOid>,
+    conn: rusqlite::Connection,
+    refresh_interval: Duration,
+    now: SystemTime,
+    redactor: &'a Redactor,
+}
+
+impl<'a> CiStatusDescriptor<'a> {
+    /// Constructor.
+    pub fn new(repo: &Repo, now: SystemTime, redactor: &'a Redactor) -> eyre::Result<Self> {
+        let provider = make_ci_status_provider(repo)?;
+        let pushed_oids = match provider {
+            Some(_) => repo
+                .get_all_remote_branches()?
+                .into_iter()
+                .map(|branch| branch.get_oid())
+                .filter_map(|oid| oid.transpose())
+                .collect::<Result<_, _>>()?,
+            None => Default::default(),
+        };
+        Ok(CiStatusDescriptor {
+            provider,
+            pushed_oids,
+            conn: repo.get_db_conn()?,
+            refresh_interval: get_ci_refresh_interval(repo)?,
+            now,
+            redactor,
+        })
+    }
+
+    fn get_status(&mut self, commit_oid: NonZeroOid) -> eyre::Result<CiStatus> {
+        let provider = match &mut self.provider {
+            Some(provider) => provider,
+            None => return Ok(CiStatus::Unknown),
+        };
+        let ci_status_db = CiStatusDb::new(&self.conn)?;
+        let cached_status = ci_status_db.get_status(provider.get_name(), commit_oid)?;
+        if let Some((status, timestamp)) = cached_status {
+            let age = self.now.duration_since(timestamp).unwrap_or_default();
+            if age < self.refresh_interval {
+                return Ok(status);
+            }
+        }
+
+        match provider.query_status(commit_oid) {
+            Ok(status) => {
+                ci_status_db.set_status(provider.get_name(), commit_oid, status, self.now)?;
+                Ok(status)
+            }
+            Err(err) => {
+                // Don't fail to render the smartlog just because the provider
+                // couldn't be reached. Record the failure as well, so that the
+                // provider isn't queried again until the refresh interval has
+                // passed.
+                warn!(?err, ?commit_oid, "Could not query CI status");
+                let status = cached_status
+                    .map(|(status, _timestamp)| status)
+                    .unwrap_or(CiStatus::Unknown);
+                ci_status_db.set_status(provider.get_name(), commit_oid, status, self.now)?;
+                Ok(status)
+            }
+        }
+    }
+}
+
+impl<'a> NodeDescriptor for CiStatusDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        match self.redactor {
+            Redactor::Enabled { .. } => return Ok(None),
+            Redactor::Disabled => {}
+        }
+        let commit_oid = object.get_oid();
+        if !self.pushed_oids.contains(&commit_oid) {
+            return Ok(None);
+        }
+        let result = match self.get_status(commit_oid)? {
+            CiStatus::Unknown => return Ok(None),
+            CiStatus::Passed => StyledString::styled(glyphs.ci_passed, glyphs.theme.ci_passed),
+            CiStatus::Failed => StyledString::styled(glyphs.ci_failed, glyphs.theme.ci_failed),
+            CiStatus::Pending => StyledString::styled(glyphs.ci_pending, glyphs.theme.ci_pending),
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Display the issues referenced by the trailers of each commit message which
+/// are configured with `branchless.issues.trailers`, along with their status,
+/// if a provider is configured with `branchless.issues.statusCommand`.
+#[derive(Debug)]
+pub struct IssueTrailersDescriptor<'a> {
+    trailers: Vec<String>,
+    provider: Option<Box<dyn IssueStatusProvider>>,
+    redactor: &'a Redactor,
+}
+
+impl<'a> IssueTrailersDescriptor<'a> {
+    /// Constructor.
+    pub fn new(repo: &Repo, redactor: &'a Redactor) -> eyre::Result<Self> {
+        let trailers = get_issues_trailers(repo)?;
+        let provider = if trailers.is_empty() {
+            None
+        } else {
+            make_issue_status_provider(repo)?
+        };
+        Ok(IssueTrailersDescriptor {
+            trailers,
+            provider,
+            redactor,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for IssueTrailersDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        match self.redactor {
+            Redactor::Enabled { .. } => return Ok(None),
+            Redactor::Disabled => {}
+        }
+        if self.trailers.is_empty() {
+            return Ok(None);
+        }
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+
+        let mut issues =
+            parse_issue_references(&commit.get_message_raw()?.to_str_lossy(), &self.trailers);
+        let mut seen_ids = HashSet::new();
+        issues.retain(|issue| seen_ids.insert(issue.id.clone()));
+        if issues.is_empty() {
+            return Ok(None);
+        }
+
+        let mut description = Vec::new();
+        for issue in issues {
+            let status = match &mut self.provider {
+                None => None,
+                Some(provider) => match provider.query_status(&issue) {
+                    Ok(status) => status,
+                    Err(err) => {
+                        // Don't fail to render the smartlog just because the
+                        // issue tracker couldn't be reached.
+                        warn!(?err, ?issue, "Could not query issue status");
+                        None
+                    }
+                },
+            };
+            description.push(match status {
+                Some(status) => format!("{} [{status}]", issue.id),
+                None => issue.id,
+            });
+        }
+        let result = StyledString::styled(description.join(", "), glyphs.theme.issue);
         Ok(Some(result))
     }
 }
@@ -500,7 +863,7 @@ impl NodeDescriptor for RelativeTimeDescriptor {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         if !self.is_enabled {
@@ -512,7 +875,7 @@ impl NodeDescriptor for RelativeTimeDescriptor {
         };
 
         let description = Self::describe_time_delta(self.now, commit.get_time().to_system_time()?)?;
-        let result = StyledString::styled(description, BaseColor::Green.dark());
+        let result = StyledString::styled(description, glyphs.theme.relative_time);
         Ok(Some(result))
     }
 }
@@ -0,0 +1,242 @@
+//! Send long output through the user's pager, in the same way as Git.
+//!
+//! The pager is started as a subprocess, and this process's stdout (and
+//! stderr, if it's a terminal) are redirected to it until the returned
+//! [`Pager`] is dropped.
+
+use std::ffi::OsStr;
+use std::io::Write;
+
+use tracing::instrument;
+
+use crate::core::config::env_vars::GIT_BRANCHLESS_PAGINATE;
+use crate::git::{ConfigRead, GitRunInfo, GitRunOpts, Repo};
+
+/// Environment variable set by Git (and by us) when output is already being
+/// sent to a pager, so that subprocesses don't start another one.
+pub const GIT_PAGER_IN_USE: &str = "GIT_PAGER_IN_USE";
+
+/// Determine the pager command to use for the given subcommand, following
+/// Git's rules:
+///
+/// - `$GIT_PAGER`, if set.
+/// - `pager.<command_name>`, if set to a command.
+/// - `core.pager`, `$PAGER`, or `less`, in that order.
+///
+/// Returns `None` if output shouldn't be paged, such as if the pager is `cat`,
+/// if a pager is already in use, or if `pager.<command_name>` is set to `false`
+/// (unless [`GIT_BRANCHLESS_PAGINATE`] is set by the `--paginate` option).
+/// This doesn't check whether stdout is a terminal.
+#[instrument]
+pub fn get_pager_command(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    command_name: &str,
+) -> eyre::Result<Option<String>> {
+    let get_env = |name: &str| {
+        git_run_info
+            .env
+            .get(OsStr::new(name))
+            .map(|value| value.to_string_lossy().into_owned())
+    };
+    if let Some(value) = get_env(GIT_PAGER_IN_USE) {
+        if is_config_true(&value) == Some(true) {
+            return Ok(None);
+        }
+    }
+
+    let paginate = match get_env(GIT_BRANCHLESS_PAGINATE) {
+        Some(value) => is_config_true(&value) == Some(true),
+        None => false,
+    };
+    let command_pager: Option<String> = repo
+        .get_readonly_config()?
+        .get(format!("pager.{command_name}"))?;
+    let command_pager = match command_pager {
+        Some(value) => match is_config_true(&value) {
+            Some(false) if !paginate => return Ok(None),
+            Some(_) => None,
+            None => Some(value),
+        },
+        None => None,
+    };
+
+    let pager = match get_env("GIT_PAGER") {
+        Some(pager) => pager,
+        None => match command_pager {
+            Some(command_pager) => command_pager,
+            None => {
+                let result = git_run_info.run_silent(
+                    repo,
+                    None,
+                    &["var", "GIT_PAGER"],
+                    GitRunOpts {
+                        treat_git_failure_as_error: false,
+                        stdin: None,
+                    },
+                )?;
+                if !result.exit_code.is_success() {
+                    return Ok(None);
+                }
+                String::from_utf8_lossy(&result.stdout).trim().to_owned()
+            }
+        },
+    };
+
+    if pager.is_empty() || pager == "cat" {
+        Ok(None)
+    } else {
+        Ok(Some(pager))
+    }
+}
+
+/// Interpret a config value as a boolean in the way that Git does, if it is
+/// one.
+fn is_config_true(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// A running pager process. Output written to stdout is sent to the pager
+/// until this object is dropped, at which point stdout is restored and we wait
+/// for the user to exit the pager.
+pub struct Pager {
+    child: std::process::Child,
+
+    /// The file descriptors which were redirected to the pager, along with
+    /// copies of their original targets.
+    #[cfg(unix)]
+    redirected_fds: Vec<(libc::c_int, libc::c_int)>,
+}
+
+impl std::fmt::Debug for Pager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Pager pid={}>", self.child.id())
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+
+        // Restoring the original file descriptors closes our end of the pipe,
+        // so that the pager sees the end of its input.
+        #[cfg(unix)]
+        for (fd, saved_fd) in self.redirected_fds.drain(..) {
+            // SAFETY: both file descriptors are owned by this object.
+            unsafe {
+                libc::dup2(saved_fd, fd);
+                libc::close(saved_fd);
+            }
+        }
+
+        let _ = self.child.wait();
+    }
+}
+
+/// Start the pager for the given subcommand, if stdout is a terminal and a
+/// pager is configured (see [`get_pager_command`]). Output is paged until the
+/// returned object is dropped.
+///
+/// Paging is only supported on Unix. On other platforms, this always returns
+/// `None`.
+#[instrument]
+pub fn start_pager(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    command_name: &str,
+) -> eyre::Result<Option<Pager>> {
+    if !console::user_attended() {
+        return Ok(None);
+    }
+    let pager_command = match get_pager_command(git_run_info, repo, command_name)? {
+        Some(pager_command) => pager_command,
+        None => return Ok(None),
+    };
+    start_pager_inner(git_run_info, &pager_command)
+}
+
+#[cfg(unix)]
+fn start_pager_inner(
+    git_run_info: &GitRunInfo,
+    pager_command: &str,
+) -> eyre::Result<Option<Pager>> {
+    use std::os::unix::io::AsRawFd;
+    use std::process::{Command, Stdio};
+
+    use eyre::Context;
+
+    use crate::util::get_sh;
+
+    let sh = match get_sh() {
+        Some(sh) => sh,
+        None => return Ok(None),
+    };
+    let mut command = Command::new(sh);
+    command
+        .arg("-c")
+        .arg(pager_command)
+        .current_dir(&git_run_info.working_directory)
+        .env_clear()
+        .envs(git_run_info.env.iter())
+        .stdin(Stdio::piped());
+    // Same defaults as Git: exit if the output fits on one screen, pass
+    // through color escape codes, and don't clear the screen on exit.
+    for (name, value) in [("LESS", "FRX"), ("LV", "-c")] {
+        if !git_run_info.env.contains_key(OsStr::new(name)) {
+            command.env(name, value);
+        }
+    }
+    let mut child = command
+        .spawn()
+        .wrap_err_with(|| format!("Spawning pager: {pager_command}"))?;
+    let stdin = match child.stdin.take() {
+        Some(stdin) => stdin,
+        None => eyre::bail!("Could not open stdin for pager: {pager_command}"),
+    };
+
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+    let mut redirected_fds = Vec::new();
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: only standard file descriptors and the pipe owned by `stdin`
+        // are used.
+        unsafe {
+            if fd == libc::STDERR_FILENO && libc::isatty(fd) == 0 {
+                continue;
+            }
+            let saved_fd = libc::dup(fd);
+            if saved_fd < 0 {
+                continue;
+            }
+            libc::dup2(stdin.as_raw_fd(), fd);
+            redirected_fds.push((fd, saved_fd));
+        }
+    }
+    drop(stdin);
+
+    // If the user quits the pager before all the output has been written,
+    // exit quietly (as Git does) rather than failing to write to stdout.
+    // SAFETY: resetting a signal to its default disposition.
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    std::env::set_var(GIT_PAGER_IN_USE, "true");
+
+    Ok(Some(Pager {
+        child,
+        redirected_fds,
+    }))
+}
+
+#[cfg(not(unix))]
+fn start_pager_inner(
+    _git_run_info: &GitRunInfo,
+    _pager_command: &str,
+) -> eyre::Result<Option<Pager>> {
+    Ok(None)
+}
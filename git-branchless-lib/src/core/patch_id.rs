@@ -0,0 +1,233 @@
+//! Persistent cache of patch IDs.
+//!
+//! Detecting commits which have already been applied upstream (such as when
+//! running `git sync` or `git restack`) requires the patch ID of every commit
+//! between the merge-base and the upstream commit, which involves computing a
+//! diff for each of them. A patch ID is derived only from the commit's own
+//! contents, so it's stored in the database keyed by commit OID, and later
+//! runs only need to diff upstream commits which haven't been seen before.
+//!
+//! Upstream commits are rarely deleted, so `git branchless gc` keeps the
+//! cache and only removes the entries for commits which Git has
+//! garbage-collected.
+
+use std::collections::HashMap;
+
+use eyre::Context;
+use rusqlite::OptionalExtension;
+use tracing::instrument;
+
+use crate::core::effects::Effects;
+use crate::git::{Commit, NonZeroOid, PatchId, Repo};
+
+/// Stores the patch IDs of commits on disk.
+pub struct PatchIdDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for PatchIdDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<PatchIdDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    // `patch_id` is `NULL` for commits which don't have a patch ID, such as
+    // merge commits.
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS patch_ids (
+    commit_oid TEXT PRIMARY KEY,
+    patch_id TEXT
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `patch_ids` table")?;
+    Ok(())
+}
+
+impl<'conn> PatchIdDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(PatchIdDb { conn })
+    }
+
+    /// Look up the cached patch IDs of the given commits. Commits which
+    /// aren't in the cache are omitted from the result. Commits which are in
+    /// the cache but don't have a patch ID map to `None`.
+    #[instrument(skip(commit_oids))]
+    pub fn get_cached_patch_ids(
+        &self,
+        commit_oids: &[NonZeroOid],
+    ) -> eyre::Result<HashMap<NonZeroOid, Option<PatchId>>> {
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT patch_id FROM patch_ids WHERE commit_oid = :commit_oid")
+            .wrap_err("Preparing statement")?;
+        let mut result = HashMap::new();
+        for commit_oid in commit_oids {
+            let patch_id: Option<Option<String>> = statement
+                .query_row(
+                    rusqlite::named_params! {
+                        ":commit_oid": commit_oid.to_string(),
+                    },
+                    |row| row.get("patch_id"),
+                )
+                .optional()
+                .wrap_err("Querying patch ID cache")?;
+            if let Some(patch_id) = patch_id {
+                let patch_id = patch_id.map(|patch_id| patch_id.parse()).transpose()?;
+                result.insert(*commit_oid, patch_id);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Add the given patch IDs to the cache.
+    #[instrument(skip(patch_ids))]
+    pub fn insert_patch_ids(
+        &self,
+        patch_ids: impl IntoIterator<Item = (NonZeroOid, Option<PatchId>)>,
+    ) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut statement = tx
+                .prepare("INSERT OR REPLACE INTO patch_ids VALUES (:commit_oid, :patch_id)")
+                .wrap_err("Preparing statement")?;
+            for (commit_oid, patch_id) in patch_ids {
+                statement
+                    .execute(rusqlite::named_params! {
+                        ":commit_oid": commit_oid.to_string(),
+                        ":patch_id": patch_id.map(|patch_id| patch_id.to_string()),
+                    })
+                    .wrap_err("Updating patch ID cache")?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the patch ID of the given commit, computing it with
+    /// [`Repo::get_patch_id`] if it isn't already cached.
+    #[instrument]
+    pub fn get_patch_id(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        commit: &Commit,
+    ) -> eyre::Result<Option<PatchId>> {
+        let commit_oid = commit.get_oid();
+        if let Some(patch_id) = self
+            .get_cached_patch_ids(&[commit_oid])?
+            .remove(&commit_oid)
+        {
+            return Ok(patch_id);
+        }
+        let patch_id = repo.get_patch_id(effects, commit)?;
+        self.insert_patch_ids([(commit_oid, patch_id)])?;
+        Ok(patch_id)
+    }
+
+    /// Remove the cached patch IDs of commits which no longer exist in the
+    /// repository. Returns the number of entries removed.
+    #[instrument]
+    pub fn prune(&self, repo: &Repo) -> eyre::Result<usize> {
+        let commit_oids: Vec<String> = {
+            let mut statement = self
+                .conn
+                .prepare("SELECT commit_oid FROM patch_ids")
+                .wrap_err("Preparing statement")?;
+            let rows = statement
+                .query_map(rusqlite::params![], |row| row.get("commit_oid"))
+                .wrap_err("Querying patch ID cache")?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut num_removed = 0;
+        for commit_oid in commit_oids {
+            let exists = match commit_oid.parse::<NonZeroOid>() {
+                Ok(oid) => repo.find_commit(oid)?.is_some(),
+                Err(_) => false,
+            };
+            if exists {
+                continue;
+            }
+            num_removed += tx
+                .execute(
+                    "DELETE FROM patch_ids WHERE commit_oid = :commit_oid",
+                    rusqlite::named_params! {
+                        ":commit_oid": commit_oid,
+                    },
+                )
+                .wrap_err("Pruning patch ID cache")?;
+        }
+        tx.commit()?;
+        Ok(num_removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::formatting::Glyphs;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_patch_id_cache() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "other", "HEAD^"])?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["merge", "--no-ff", "other", "-m", "merge"])?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let patch_id_db = PatchIdDb::new(&conn)?;
+        assert!(patch_id_db
+            .get_cached_patch_ids(&[test1_oid, test2_oid])?
+            .is_empty());
+
+        let test1 = repo.find_commit_or_fail(test1_oid)?;
+        let test1_patch_id = repo.get_patch_id(&effects, &test1)?;
+        assert!(test1_patch_id.is_some());
+        assert_eq!(
+            patch_id_db.get_patch_id(&effects, &repo, &test1)?,
+            test1_patch_id
+        );
+
+        let merge_oid = repo.get_head_info()?.oid.unwrap();
+        let merge_commit = repo.find_commit_or_fail(merge_oid)?;
+        assert_eq!(
+            patch_id_db.get_patch_id(&effects, &repo, &merge_commit)?,
+            None
+        );
+
+        let cached = patch_id_db.get_cached_patch_ids(&[test1_oid, test2_oid, merge_oid])?;
+        assert_eq!(
+            cached,
+            [(test1_oid, test1_patch_id), (merge_oid, None)]
+                .into_iter()
+                .collect()
+        );
+
+        let missing_oid: NonZeroOid = "1234567890123456789012345678901234567890".parse()?;
+        patch_id_db.insert_patch_ids([(missing_oid, test1_patch_id)])?;
+        assert_eq!(patch_id_db.prune(&repo)?, 1);
+        assert!(patch_id_db.get_cached_patch_ids(&[missing_oid])?.is_empty());
+        assert_eq!(
+            patch_id_db.get_cached_patch_ids(&[test1_oid, test2_oid, merge_oid])?,
+            cached
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! Ask the user to confirm destructive operations.
+//!
+//! Confirmation is skipped when the global `--yes` option was passed (see
+//! [`GIT_BRANCHLESS_ASSUME_YES`]) or when the command isn't being run from a
+//! terminal, so that the same commands can be used unchanged in scripts.
+
+use std::fmt::Write;
+use std::io::BufRead;
+
+use tracing::instrument;
+
+use crate::core::config::env_vars::GIT_BRANCHLESS_ASSUME_YES;
+use crate::core::effects::Effects;
+
+/// Determine whether the user asked to skip confirmation prompts, such as with
+/// the global `--yes` option.
+pub fn should_assume_yes() -> bool {
+    match std::env::var(GIT_BRANCHLESS_ASSUME_YES) {
+        Ok(value) => !matches!(value.to_lowercase().as_str(), "" | "0" | "false"),
+        Err(_) => false,
+    }
+}
+
+/// Determine whether a confirmation prompt can be shown, i.e. whether both the
+/// input and output are attached to a terminal.
+fn is_interactive() -> bool {
+    console::user_attended() && {
+        #[cfg(unix)]
+        {
+            // SAFETY: only queries the standard input file descriptor.
+            unsafe { libc::isatty(libc::STDIN_FILENO) != 0 }
+        }
+        #[cfg(not(unix))]
+        {
+            true
+        }
+    }
+}
+
+/// Ask the user to confirm the operation described by `message`, which should
+/// be phrased as a question. Returns `true` if the user answered `y`.
+///
+/// If confirmation was skipped with `--yes`, or if the command isn't being run
+/// interactively, returns `true` without prompting.
+#[instrument]
+pub fn confirm(effects: &Effects, message: &str) -> eyre::Result<bool> {
+    if should_assume_yes() || !is_interactive() {
+        return Ok(true);
+    }
+
+    write!(effects.get_output_stream(), "{message} [yN] ")?;
+    let mut user_input = String::new();
+    let confirmed = match std::io::stdin().lock().read_line(&mut user_input) {
+        Ok(_size) => {
+            let user_input = user_input.trim();
+            user_input == "y" || user_input == "Y"
+        }
+        Err(_) => false,
+    };
+    Ok(confirmed)
+}
@@ -0,0 +1,85 @@
+//! Branches which shouldn't be moved or force-pushed, such as the main branch
+//! or release branches.
+//!
+//! Besides the main branch, branches are protected by setting
+//! `branchless.core.protectedBranches`, and forges may report additional
+//! protected branches when submitting.
+
+use eyre::Context;
+use itertools::Itertools;
+use tracing::instrument;
+
+use crate::core::config::{get_main_branch_name, get_protected_branch_patterns};
+use crate::core::dag::{commit_set_to_vec, CommitSet};
+use crate::core::eventlog::ref_pattern_matches;
+use crate::core::repo_ext::RepoReferencesSnapshot;
+use crate::git::{CategorizedReferenceName, ReferenceName, Repo};
+
+/// The set of protected branches.
+#[derive(Clone, Debug)]
+pub struct ProtectedBranches {
+    main_branch_name: String,
+    patterns: Vec<String>,
+}
+
+impl ProtectedBranches {
+    /// Load the protected branches configured for the repository.
+    #[instrument]
+    pub fn new(repo: &Repo) -> eyre::Result<Self> {
+        let main_branch_name = get_main_branch_name(repo)?;
+        let patterns =
+            get_protected_branch_patterns(repo).wrap_err("Reading protected branch patterns")?;
+        Ok(Self {
+            main_branch_name,
+            patterns,
+        })
+    }
+
+    /// Also protect the branches matching the given patterns, such as those
+    /// reported by a forge.
+    pub fn add_patterns(&mut self, patterns: impl IntoIterator<Item = String>) {
+        self.patterns.extend(patterns);
+    }
+
+    /// Whether the local branch with the given name (without the `refs/heads/`
+    /// prefix) is protected.
+    pub fn is_protected(&self, branch_name: &str) -> bool {
+        if branch_name == self.main_branch_name {
+            return true;
+        }
+        let branch_name = ReferenceName::from(branch_name);
+        self.patterns
+            .iter()
+            .any(|pattern| ref_pattern_matches(pattern, &branch_name))
+    }
+
+    /// Get the names of the protected local branches which point to any of the
+    /// given commits, in sorted order.
+    #[instrument]
+    pub fn find_protected_branches(
+        &self,
+        references_snapshot: &RepoReferencesSnapshot,
+        commits: &CommitSet,
+    ) -> eyre::Result<Vec<String>> {
+        let mut result = Vec::new();
+        for commit_oid in commit_set_to_vec(commits)? {
+            let reference_names = match references_snapshot.branch_oid_to_names.get(&commit_oid) {
+                Some(reference_names) => reference_names,
+                None => continue,
+            };
+            for reference_name in reference_names {
+                match CategorizedReferenceName::new(reference_name) {
+                    name @ CategorizedReferenceName::LocalBranch { .. } => {
+                        let branch_name = name.render_suffix();
+                        if self.is_protected(&branch_name) {
+                            result.push(branch_name);
+                        }
+                    }
+                    CategorizedReferenceName::RemoteBranch { .. }
+                    | CategorizedReferenceName::OtherRef { .. } => {}
+                }
+            }
+        }
+        Ok(result.into_iter().sorted().dedup().collect())
+    }
+}
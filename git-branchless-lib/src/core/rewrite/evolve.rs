@@ -1,9 +1,9 @@
 use std::convert::TryFrom;
 
-use eden_dag::DagAlgorithm;
 use itertools::Itertools;
 use tracing::instrument;
 
+use crate::core::child_index::ChildIndexDb;
 use crate::core::dag::{CommitSet, Dag};
 use crate::core::eventlog::{Event, EventCursor, EventReplayer};
 use crate::git::{MaybeZeroOid, NonZeroOid};
@@ -61,6 +61,7 @@ pub fn find_rewrite_target(
         | Event::CommitEvent { .. }
         | Event::ObsoleteEvent { .. }
         | Event::UnobsoleteEvent { .. }
+        | Event::CopyEvent { .. }
         | Event::WorkingCopySnapshot { .. } => None,
     }
 }
@@ -72,6 +73,7 @@ pub fn find_rewrite_target(
 #[instrument]
 pub fn find_abandoned_children(
     dag: &Dag,
+    child_index_db: &ChildIndexDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
     oid: NonZeroOid,
@@ -81,8 +83,7 @@ pub fn find_abandoned_children(
         Some(MaybeZeroOid::Zero) => oid,
         None => return Ok(None),
     };
-    let children = dag.query().children(CommitSet::from(oid))?;
-    let children = dag.filter_visible_commits(children)?;
+    let children = child_index_db.query_visible_children(dag, &CommitSet::from(oid))?;
     let non_obsolete_children = children.difference(&dag.query_obsolete_commits());
     let non_obsolete_children_oids: Vec<NonZeroOid> = non_obsolete_children
         .iter()?
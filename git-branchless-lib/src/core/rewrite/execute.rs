@@ -6,13 +6,15 @@ use std::time::SystemTime;
 
 use bstr::BString;
 use eyre::Context;
+use itertools::Itertools;
 use tracing::warn;
 
 use crate::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
-use crate::core::effects::Effects;
+use crate::core::effects::{Effects, OperationEvent};
 use crate::core::eventlog::{EventLogDb, EventTransactionId};
-use crate::core::formatting::Pluralize;
+use crate::core::formatting::{Pluralize, StyledStringBuilder};
 use crate::core::repo_ext::RepoExt;
+use crate::core::user_error::{UserError, UserErrorCode};
 use crate::git::{
     BranchType, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
     Repo, ResolvedReferenceInfo,
@@ -356,86 +358,87 @@ impl FailedMergeInfo {
         repo: &Repo,
         remediation: MergeConflictRemediation,
     ) -> eyre::Result<()> {
-        match self {
+        // The callers decide whether the conflict fails the whole command.
+        let _exit_code = self
+            .to_user_error(effects, repo, remediation)?
+            .report(effects)?;
+        Ok(())
+    }
+
+    /// Get the [`UserError`] describing the merge conflict, with the given
+    /// remediation.
+    pub fn to_user_error(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        remediation: MergeConflictRemediation,
+    ) -> eyre::Result<UserError> {
+        let glyphs = effects.get_glyphs();
+        let error = match self {
             FailedMergeInfo::Conflict {
                 commit_oid,
                 conflicting_paths,
-            } => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "This operation would cause a merge conflict:"
-                )?;
-                writeln!(
-                    effects.get_output_stream(),
-                    "{} ({}) {}",
-                    effects.get_glyphs().bullet_point,
-                    Pluralize {
-                        determiner: None,
-                        amount: conflicting_paths.len(),
-                        unit: ("conflicting file", "conflicting files"),
-                    },
-                    effects.get_glyphs().render(
-                        repo.friendly_describe_commit_from_oid(effects.get_glyphs(), *commit_oid)?
-                    )?
-                )?;
-            }
-
-            FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid } => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "Merge commits currently can't be rebased in-memory."
-                )?;
-                writeln!(
-                    effects.get_output_stream(),
-                    "The merge commit was: {}",
-                    effects.get_glyphs().render(
-                        repo.friendly_describe_commit_from_oid(effects.get_glyphs(), *commit_oid)?
-                    )?,
-                )?;
-            }
-        }
+            } => UserError::new(
+                UserErrorCode::MergeConflict,
+                StyledStringBuilder::new()
+                    .append_plain("This operation would cause a merge conflict:\n")
+                    .append_plain(format!(
+                        "{} ({}) ",
+                        glyphs.bullet_point,
+                        Pluralize {
+                            determiner: None,
+                            amount: conflicting_paths.len(),
+                            unit: ("conflicting file", "conflicting files"),
+                        },
+                    ))
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+            ),
+
+            FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid } => UserError::new(
+                UserErrorCode::CannotRebaseMergeInMemory,
+                StyledStringBuilder::new()
+                    .append_plain("Merge commits currently can't be rebased in-memory.\n")
+                    .append_plain("The merge commit was: ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+            ),
+        };
 
-        match remediation {
-            MergeConflictRemediation::Retry => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "To resolve merge conflicts, retry this operation with the --merge option."
-                )?;
-            }
-            MergeConflictRemediation::Restack => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "To resolve merge conflicts, run: git restack --merge"
-                )?;
-            }
+        let purpose = "To resolve merge conflicts";
+        let error = match remediation {
+            MergeConflictRemediation::Retry => error.with_retry_option(purpose, "--merge"),
+            MergeConflictRemediation::Restack => error.with_command(purpose, "git restack --merge"),
             MergeConflictRemediation::Insert => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "To resolve merge conflicts, run: git move -m -s 'siblings(.)'"
-                )?;
+                error.with_command(purpose, "git move -m -s 'siblings(.)'")
             }
-        }
-
-        Ok(())
+        };
+        Ok(error)
     }
 }
 
 mod in_memory {
-    use std::collections::HashMap;
+    use std::collections::{BTreeSet, HashMap};
     use std::fmt::Write;
+    use std::path::{Path, PathBuf};
 
     use bstr::{BString, ByteSlice};
     use eyre::Context;
+    use itertools::Itertools;
     use tracing::{instrument, warn};
 
+    use crate::core::config::get_rerere_autoupdate;
     use crate::core::effects::{Effects, OperationIcon, OperationType};
     use crate::core::eventlog::EventLogDb;
     use crate::core::gc::mark_commit_reachable;
     use crate::core::rewrite::execute::check_out_updated_head;
     use crate::core::rewrite::move_branches;
     use crate::core::rewrite::plan::{OidOrLabel, RebaseCommand, RebasePlan};
+    use crate::core::rewrite::rerere::resolve_conflicts_with_rerere;
+    use crate::core::rewrite::resolve::resolve_conflicts_in_memory;
     use crate::git::{
-        CherryPickFastError, CherryPickFastOptions, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo,
+        CherryPickFastError, CherryPickFastOptions, Commit, FileMode, GitRunInfo, MaybeZeroOid,
+        NonZeroOid, Repo, Tree,
     };
     use crate::util::ExitCode;
 
@@ -458,6 +461,7 @@ mod in_memory {
     #[instrument]
     pub fn rebase_in_memory(
         effects: &Effects,
+        git_run_info: &GitRunInfo,
         repo: &Repo,
         rebase_plan: &RebasePlan,
         options: &ExecuteRebasePlanOptions,
@@ -478,7 +482,8 @@ mod in_memory {
                     | RebaseCommand::Break
                     | RebaseCommand::RegisterExtraPostRewriteHook
                     | RebaseCommand::DetectEmptyCommit { .. }
-                    | RebaseCommand::SkipUpstreamAppliedCommit { .. } => None,
+                    | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                    | RebaseCommand::SkipFixedUpCommit { .. } => None,
                 })
         {
             return Ok(RebaseInMemoryResult::MergeFailed(
@@ -496,10 +501,53 @@ mod in_memory {
             preserve_timestamps,
             force_in_memory: _,
             force_on_disk: _,
-            resolve_merge_conflicts: _, // May be needed once we can resolve merge conflicts in memory.
+            resolve_merge_conflicts,
             check_out_commit_options: _, // Caller is responsible for checking out to new HEAD.
         } = options;
 
+        // In a partial clone, the blobs needed to merge the commits may not have
+        // been fetched yet. Git would fetch them on demand, but libgit2 won't,
+        // so fetch them all up front rather than failing partway through the
+        // rebase.
+        {
+            let mut commits_to_apply = Vec::new();
+            let mut target_commits = vec![repo.find_commit_or_fail(rebase_plan.first_dest_oid)?];
+            for command in rebase_plan.commands.iter() {
+                match command {
+                    RebaseCommand::Pick {
+                        original_commit_oid: _,
+                        commit_to_apply_oid,
+                    } => {
+                        commits_to_apply.push(repo.find_commit_or_fail(*commit_to_apply_oid)?);
+                    }
+                    RebaseCommand::Reset {
+                        target: OidOrLabel::Oid(commit_oid),
+                    } => {
+                        target_commits.push(repo.find_commit_or_fail(*commit_oid)?);
+                    }
+                    RebaseCommand::CreateLabel { .. }
+                    | RebaseCommand::Reset {
+                        target: OidOrLabel::Label(_),
+                    }
+                    | RebaseCommand::Merge { .. }
+                    | RebaseCommand::Replace { .. }
+                    | RebaseCommand::Break
+                    | RebaseCommand::RegisterExtraPostRewriteHook
+                    | RebaseCommand::DetectEmptyCommit { .. }
+                    | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                    | RebaseCommand::SkipFixedUpCommit { .. } => {}
+                }
+            }
+            if let Err(err) = repo.fetch_missing_blobs_for_patches(
+                effects,
+                git_run_info,
+                &commits_to_apply,
+                &target_commits,
+            ) {
+                warn!(?err, "Could not fetch missing objects for rebase");
+            }
+        }
+
         let mut current_oid = rebase_plan.first_dest_oid;
         let mut labels: HashMap<String, NonZeroOid> = HashMap::new();
         let mut rewritten_oids: Vec<(NonZeroOid, MaybeZeroOid)> = Vec::new();
@@ -530,7 +578,8 @@ mod in_memory {
                 RebaseCommand::Pick { .. }
                 | RebaseCommand::Merge { .. }
                 | RebaseCommand::Replace { .. }
-                | RebaseCommand::SkipUpstreamAppliedCommit { .. } => true,
+                | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                | RebaseCommand::SkipFixedUpCommit { .. } => true,
             })
             .count();
         let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
@@ -599,12 +648,47 @@ mod in_memory {
                     ) {
                         Ok(rebased_commit) => rebased_commit,
                         Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
-                            return Ok(RebaseInMemoryResult::MergeFailed(
-                                FailedMergeInfo::Conflict {
-                                    commit_oid: *commit_to_apply_oid,
-                                    conflicting_paths,
-                                },
-                            ))
+                            let resolved_tree = match resolve_conflicts_with_rerere(
+                                repo,
+                                &commit_to_apply,
+                                &current_commit,
+                            )? {
+                                // As with Git, only commit the reused
+                                // resolutions without the user's review if
+                                // `rerere.autoUpdate` is set, or if they've
+                                // asked to resolve conflicts with `--merge`.
+                                Some((resolved_tree, resolved_paths))
+                                    if *resolve_merge_conflicts || get_rerere_autoupdate(repo)? =>
+                                {
+                                    writeln!(
+                                        effects.get_output_stream(),
+                                        "{commit_num} Reused recorded conflict resolution for: {}",
+                                        resolved_paths
+                                            .iter()
+                                            .map(|path| path.display().to_string())
+                                            .join(", ")
+                                    )?;
+                                    Some(resolved_tree)
+                                }
+                                None if *resolve_merge_conflicts => resolve_conflicts_in_memory(
+                                    &effects,
+                                    repo,
+                                    &commit_to_apply,
+                                    &current_commit,
+                                )?,
+                                _ => None,
+                            };
+                            match resolved_tree {
+                                Some(resolved_tree) => resolved_tree,
+                                None => {
+                                    return Ok(RebaseInMemoryResult::MergeFailed(
+                                        FailedMergeInfo::Conflict {
+                                            commit_oid: *commit_to_apply_oid,
+                                            conflicting_paths,
+                                        },
+                                    ))
+                                }
+                            }
                         }
                         Err(other) => eyre::bail!(other),
                     };
@@ -666,6 +750,17 @@ mod in_memory {
                             effects.get_output_stream(),
                             "{commit_num} Committed as: {commit_description}"
                         )?;
+                        for path in get_newly_out_of_sync_submodules(
+                            repo,
+                            &commit_to_apply,
+                            &rebased_commit,
+                        )? {
+                            writeln!(
+                                effects.get_output_stream(),
+                                "{commit_num} Warning: the submodule at {} is now out of sync with .gitmodules",
+                                path.display()
+                            )?;
+                        }
                     }
                 }
 
@@ -793,6 +888,25 @@ mod in_memory {
                     )?;
                 }
 
+                RebaseCommand::SkipFixedUpCommit {
+                    commit_oid,
+                    dest_oid,
+                } => {
+                    i += 1;
+                    let commit_num = format!("[{i}/{num_picks}]");
+
+                    let commit = repo.find_commit_or_fail(*commit_oid)?;
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
+                    maybe_set_skipped_head_new_oid(*commit_oid, current_oid);
+
+                    let commit_description = commit.friendly_describe(effects.get_glyphs())?;
+                    let commit_description = effects.get_glyphs().render(commit_description)?;
+                    writeln!(
+                        effects.get_output_stream(),
+                        "{commit_num} Skipped commit (was fixed up into {dest_oid}): {commit_description}"
+                    )?;
+                }
+
                 RebaseCommand::RegisterExtraPostRewriteHook
                 | RebaseCommand::DetectEmptyCommit { .. } => {
                     // Do nothing. We'll carry out post-rebase operations after the
@@ -847,6 +961,62 @@ mod in_memory {
         })
     }
 
+    /// Get the paths of the submodules which are out of sync with
+    /// `.gitmodules` in `rewritten_commit` (i.e. which have an entry in one but
+    /// not the other), but which weren't out of sync in the original `commit`.
+    /// Only the paths touched by the commit are checked, so that descendants
+    /// of a commit which went out of sync aren't reported again.
+    fn get_newly_out_of_sync_submodules(
+        repo: &Repo,
+        commit: &Commit,
+        rewritten_commit: &Commit,
+    ) -> eyre::Result<Vec<PathBuf>> {
+        let changed_paths = match repo.get_paths_touched_by_commit(commit)? {
+            Some(changed_paths) => changed_paths,
+            None => return Ok(Vec::new()),
+        };
+        let tree = commit.get_tree()?;
+        let rewritten_tree = rewritten_commit.get_tree()?;
+        let is_submodule = |tree: &Tree, path: &Path| -> eyre::Result<bool> {
+            let entry = tree.get_path(path)?;
+            Ok(matches!(entry, Some(entry) if entry.get_filemode() == FileMode::Commit))
+        };
+
+        let touches_gitmodules = changed_paths.contains(Path::new(".gitmodules"));
+        if !touches_gitmodules {
+            let mut touches_submodule = false;
+            for path in changed_paths.iter() {
+                if is_submodule(&tree, path)? || is_submodule(&rewritten_tree, path)? {
+                    touches_submodule = true;
+                    break;
+                }
+            }
+            if !touches_submodule {
+                return Ok(Vec::new());
+            }
+        }
+
+        let gitmodules_paths = repo.get_gitmodules_paths(&tree)?;
+        let rewritten_gitmodules_paths = repo.get_gitmodules_paths(&rewritten_tree)?;
+        let mut paths_to_check: BTreeSet<&Path> =
+            changed_paths.iter().map(PathBuf::as_path).collect();
+        if touches_gitmodules {
+            paths_to_check.extend(gitmodules_paths.iter().map(PathBuf::as_path));
+            paths_to_check.extend(rewritten_gitmodules_paths.iter().map(PathBuf::as_path));
+        }
+
+        let mut result = Vec::new();
+        for path in paths_to_check {
+            let was_in_sync = is_submodule(&tree, path)? == gitmodules_paths.contains(path);
+            let is_in_sync =
+                is_submodule(&rewritten_tree, path)? == rewritten_gitmodules_paths.contains(path);
+            if was_in_sync && !is_in_sync {
+                result.push(path.to_owned());
+            }
+        }
+        Ok(result)
+    }
+
     pub fn post_rebase_in_memory(
         effects: &Effects,
         git_run_info: &GitRunInfo,
@@ -1200,8 +1370,25 @@ pub enum ExecuteRebasePlanResult {
     },
 }
 
+impl ExecuteRebasePlanResult {
+    /// The exit code which a command should exit with for this result.
+    /// Declining to merge is reported as [`ExitCode::MERGE_CONFLICT`].
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => ExitCode::SUCCESS,
+            ExecuteRebasePlanResult::DeclinedToMerge {
+                failed_merge_info: _,
+            } => ExitCode::MERGE_CONFLICT,
+            ExecuteRebasePlanResult::Failed { exit_code } => *exit_code,
+        }
+    }
+}
+
 /// Execute the provided rebase plan. Returns the exit status (zero indicates
 /// success).
+///
+/// The rewritten commits and the result of the rebase are also reported to the
+/// observers of `effects` (see [`Effects::with_observer`]).
 pub fn execute_rebase_plan(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -1209,6 +1396,60 @@ pub fn execute_rebase_plan(
     event_log_db: &EventLogDb,
     rebase_plan: &RebasePlan,
     options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<ExecuteRebasePlanResult> {
+    let result = execute_rebase_plan_inner(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        rebase_plan,
+        options,
+    )?;
+    notify_rebase_result(effects, &result);
+    Ok(result)
+}
+
+fn notify_rebase_result(effects: &Effects, result: &ExecuteRebasePlanResult) {
+    let event = match result {
+        ExecuteRebasePlanResult::Succeeded { rewritten_oids } => {
+            let rewritten_oids = rewritten_oids.iter().flatten().sorted();
+            for (old_commit_oid, new_commit_oid) in rewritten_oids {
+                effects.notify_observers(OperationEvent::CommitRewritten {
+                    old_commit_oid: *old_commit_oid,
+                    new_commit_oid: *new_commit_oid,
+                });
+            }
+            OperationEvent::RebaseSucceeded
+        }
+        ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => match failed_merge_info {
+            FailedMergeInfo::Conflict {
+                commit_oid,
+                conflicting_paths,
+            } => OperationEvent::RebaseDeclinedToMerge {
+                commit_oid: *commit_oid,
+                conflicting_paths: conflicting_paths.iter().cloned().sorted().collect(),
+            },
+            FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid } => {
+                OperationEvent::RebaseDeclinedToMerge {
+                    commit_oid: *commit_oid,
+                    conflicting_paths: Vec::new(),
+                }
+            }
+        },
+        ExecuteRebasePlanResult::Failed { exit_code } => OperationEvent::RebaseFailed {
+            exit_code: *exit_code,
+        },
+    };
+    effects.notify_observers(event);
+}
+
+fn execute_rebase_plan_inner(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plan: &RebasePlan,
+    options: &ExecuteRebasePlanOptions,
 ) -> eyre::Result<ExecuteRebasePlanResult> {
     let ExecuteRebasePlanOptions {
         now: _,
@@ -1227,38 +1468,39 @@ pub fn execute_rebase_plan(
             "Attempting rebase in-memory..."
         )?;
 
-        let failed_merge_info = match rebase_in_memory(effects, repo, rebase_plan, options)? {
-            RebaseInMemoryResult::MergeFailed(failed_merge_info) => failed_merge_info,
-
-            RebaseInMemoryResult::Succeeded {
-                rewritten_oids,
-                new_head_oid,
-            } => {
-                // Ignore the return code, as it probably indicates that the
-                // checkout failed (which might happen if the user has changes
-                // which don't merge cleanly). The user can resolve that
-                // themselves.
-                //
-                // FIXME: we may still want to propagate the exit code to the
-                // caller.
-                let ExitCode(_exit_code) = post_rebase_in_memory(
-                    effects,
-                    git_run_info,
-                    repo,
-                    event_log_db,
-                    &rewritten_oids,
+        let failed_merge_info =
+            match rebase_in_memory(effects, git_run_info, repo, rebase_plan, options)? {
+                RebaseInMemoryResult::MergeFailed(failed_merge_info) => failed_merge_info,
+
+                RebaseInMemoryResult::Succeeded {
+                    rewritten_oids,
                     new_head_oid,
-                    options,
-                )?;
+                } => {
+                    // Ignore the return code, as it probably indicates that the
+                    // checkout failed (which might happen if the user has changes
+                    // which don't merge cleanly). The user can resolve that
+                    // themselves.
+                    //
+                    // FIXME: we may still want to propagate the exit code to the
+                    // caller.
+                    let ExitCode(_exit_code) = post_rebase_in_memory(
+                        effects,
+                        git_run_info,
+                        repo,
+                        event_log_db,
+                        &rewritten_oids,
+                        new_head_oid,
+                        options,
+                    )?;
 
-                let rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> =
-                    rewritten_oids.into_iter().collect();
-                writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
-                return Ok(ExecuteRebasePlanResult::Succeeded {
-                    rewritten_oids: Some(rewritten_oids),
-                });
-            }
-        };
+                    let rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> =
+                        rewritten_oids.into_iter().collect();
+                    writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
+                    return Ok(ExecuteRebasePlanResult::Succeeded {
+                        rewritten_oids: Some(rewritten_oids),
+                    });
+                }
+            };
 
         if !resolve_merge_conflicts {
             return Ok(ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info });
@@ -3,6 +3,8 @@
 mod evolve;
 mod execute;
 mod plan;
+mod rerere;
+mod resolve;
 pub mod rewrite_hooks;
 
 use std::sync::Mutex;
@@ -4,6 +4,7 @@ use std::ops::Sub;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use bstr::ByteSlice;
 use chashmap::CHashMap;
 use eden_dag::DagAlgorithm;
 use eyre::Context;
@@ -11,12 +12,17 @@ use itertools::Itertools;
 use rayon::{prelude::*, ThreadPool};
 use tracing::{instrument, warn};
 
+use crate::core::config::Verbosity;
 use crate::core::dag::{commit_set_to_vec, union_all, CommitSet, Dag};
 use crate::core::effects::{Effects, OperationType};
 use crate::core::formatting::Pluralize;
+use crate::core::merge_base::MergeBaseDb;
+use crate::core::patch_id::PatchIdDb;
 use crate::core::rewrite::{RepoPool, RepoResource};
 use crate::core::task::ResourcePool;
-use crate::git::{Commit, NonZeroOid, PatchId, Repo};
+use crate::git::{
+    CherryPickFastError, CherryPickFastOptions, Commit, GitRunInfo, NonZeroOid, PatchId, Repo,
+};
 
 /// Represents the target for certain [`RebaseCommand`]s.
 #[derive(Clone, Debug)]
@@ -116,6 +122,16 @@ pub enum RebaseCommand {
         /// The original commit, which will be recorded as skipped.
         commit_oid: NonZeroOid,
     },
+
+    /// The commit was fixed up into another commit, so its changes are already
+    /// part of that commit. Skip it and record it in the `rewritten-list`.
+    SkipFixedUpCommit {
+        /// The original commit, which will be recorded as skipped.
+        commit_oid: NonZeroOid,
+
+        /// The commit that `commit_oid` was fixed up into.
+        dest_oid: NonZeroOid,
+    },
 }
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
@@ -177,7 +193,11 @@ impl ToString for RebaseCommand {
             RebaseCommand::DetectEmptyCommit { commit_oid } => {
                 format!("exec git branchless hook-detect-empty-commit {commit_oid}")
             }
-            RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => {
+            RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+            | RebaseCommand::SkipFixedUpCommit {
+                commit_oid,
+                dest_oid: _,
+            } => {
                 format!("exec git branchless hook-skip-upstream-applied-commit {commit_oid}")
             }
         }
@@ -521,6 +541,11 @@ struct BuildState<'repo> {
 
 /// Builder for a rebase plan. Unlike regular Git rebases, a `git-branchless`
 /// rebase plan can move multiple unrelated subtrees to unrelated destinations.
+///
+/// Add constraints with methods like [`RebasePlanBuilder::move_subtree`] and
+/// [`RebasePlanBuilder::fixup_commit`], then call [`RebasePlanBuilder::build`]
+/// to produce a [`RebasePlan`], which can be carried out with
+/// [`execute_rebase_plan`](super::execute_rebase_plan).
 #[derive(Clone, Debug)]
 pub struct RebasePlanBuilder<'a> {
     dag: &'a Dag,
@@ -533,6 +558,10 @@ pub struct RebasePlanBuilder<'a> {
     /// with.
     replacement_commits: HashMap<NonZeroOid, NonZeroOid>,
 
+    /// Mapping of commits that were fixed up to the commits that they were
+    /// fixed up into. These commits are skipped when rebasing.
+    fixup_commits: HashMap<NonZeroOid, NonZeroOid>,
+
     /// Cache mapping from commit OID to the paths changed in the diff for that
     /// commit. The value is `None` if the commit doesn't have an associated
     /// diff (i.e. is a merge commit).
@@ -688,6 +717,7 @@ impl<'a> RebasePlanBuilder<'a> {
             permissions,
             initial_constraints: Default::default(),
             replacement_commits: Default::default(),
+            fixup_commits: Default::default(),
             touched_paths_cache: Default::default(),
         }
     }
@@ -711,6 +741,7 @@ impl<'a> RebasePlanBuilder<'a> {
         &self,
         effects: &Effects,
         repo: &Repo,
+        patch_id_db: &PatchIdDb,
         state: &mut BuildState,
         current_commit: Commit,
         upstream_patch_ids: &HashSet<PatchId>,
@@ -723,7 +754,7 @@ impl<'a> RebasePlanBuilder<'a> {
                 // to calculate the diff for the patch ID.
                 false
             } else {
-                match repo.get_patch_id(effects, &current_commit)? {
+                match patch_id_db.get_patch_id(effects, repo, &current_commit)? {
                     Some(current_patch_id) => upstream_patch_ids.contains(&current_patch_id),
                     None => false,
                 }
@@ -735,6 +766,11 @@ impl<'a> RebasePlanBuilder<'a> {
                 acc.push(RebaseCommand::SkipUpstreamAppliedCommit {
                     commit_oid: current_commit.get_oid(),
                 });
+            } else if let Some(dest_oid) = self.fixup_commits.get(&current_commit.get_oid()) {
+                acc.push(RebaseCommand::SkipFixedUpCommit {
+                    commit_oid: current_commit.get_oid(),
+                    dest_oid: *dest_oid,
+                });
             } else if current_commit.get_parent_count() > 1 {
                 // This is a merge commit. We need to make sure that all parent
                 // commits have been applied, and only then proceed with
@@ -911,6 +947,7 @@ impl<'a> RebasePlanBuilder<'a> {
             let acc = self.make_rebase_plan_for_current_commit(
                 effects,
                 repo,
+                patch_id_db,
                 state,
                 only_child_commit,
                 upstream_patch_ids,
@@ -928,6 +965,7 @@ impl<'a> RebasePlanBuilder<'a> {
                 acc = self.make_rebase_plan_for_current_commit(
                     effects,
                     repo,
+                    patch_id_db,
                     state,
                     child_commit,
                     upstream_patch_ids,
@@ -1006,10 +1044,84 @@ impl<'a> RebasePlanBuilder<'a> {
         Ok(())
     }
 
+    /// Instruct the rebase planner to fix up the commit at `source_oid` into
+    /// the commit at `dest_oid`, as with `git rebase --autosquash`. The changes
+    /// from `source_oid` are applied to `dest_oid`, which keeps its own
+    /// metadata, and `source_oid` is removed from the history, with its
+    /// descendants moved onto its parent.
+    ///
+    /// Fails if the changes from `source_oid` can't be applied to `dest_oid`
+    /// without a merge conflict.
+    pub fn fixup_commit(
+        &mut self,
+        repo: &Repo,
+        source_oid: NonZeroOid,
+        dest_oid: NonZeroOid,
+    ) -> eyre::Result<()> {
+        if source_oid == dest_oid {
+            eyre::bail!("Refusing to fix up commit {} into itself.", source_oid);
+        }
+        let source_commit = repo.find_commit_or_fail(source_oid)?;
+        let dest_commit = repo.find_commit_or_fail(dest_oid)?;
+        let (source_parent_oids, dest_parent_oids) = match (
+            source_commit.get_parent_oids(),
+            dest_commit.get_parent_oids(),
+        ) {
+            (source_parent_oids, dest_parent_oids)
+                if !source_parent_oids.is_empty() && !dest_parent_oids.is_empty() =>
+            {
+                (source_parent_oids, dest_parent_oids)
+            }
+            _ => eyre::bail!(
+                "Cannot fix up commit {} into commit {}, because one of them is a root commit.",
+                source_oid,
+                dest_oid
+            ),
+        };
+
+        let fixed_up_tree = match repo.cherry_pick_fast(
+            &source_commit,
+            &dest_commit,
+            &CherryPickFastOptions {
+                reuse_parent_tree_if_possible: false,
+            },
+        ) {
+            Ok(tree) => tree,
+            Err(CherryPickFastError::MergeConflict { conflicting_paths }) => eyre::bail!(
+                "Cannot fix up commit {} into commit {}, because it would cause a merge conflict in: {}",
+                source_oid,
+                dest_oid,
+                conflicting_paths
+                    .iter()
+                    .sorted()
+                    .map(|path| path.display())
+                    .join(", ")
+            ),
+            Err(err) => return Err(err.into()),
+        };
+        let fixed_up_oid = repo.create_commit(
+            None,
+            &dest_commit.get_author(),
+            &dest_commit.get_committer(),
+            &dest_commit.get_message_raw()?.to_str_lossy(),
+            &fixed_up_tree,
+            dest_commit.get_parents().iter().collect(),
+        )?;
+
+        self.replace_commit(dest_oid, fixed_up_oid)?;
+        // Make sure that both commits are part of the rebase, even if they're
+        // not otherwise being moved.
+        self.move_subtree(dest_oid, dest_parent_oids)?;
+        self.move_subtree(source_oid, source_parent_oids)?;
+        self.fixup_commits.insert(source_oid, dest_oid);
+        Ok(())
+    }
+
     /// Create the rebase plan. Returns `None` if there were no commands in the rebase plan.
     pub fn build(
         &self,
         effects: &Effects,
+        git_run_info: &GitRunInfo,
         pool: &ThreadPool,
         repo_pool: &ResourcePool<RepoResource>,
     ) -> eyre::Result<Result<Option<RebasePlan>, BuildRebasePlanError>> {
@@ -1029,7 +1141,11 @@ impl<'a> RebasePlanBuilder<'a> {
             dump_rebase_plan,
             detect_duplicate_commits_via_patch_id,
         } = &self.permissions.build_options;
-        if *dump_rebase_constraints {
+        let verbosity = effects.get_verbosity();
+        let dump_rebase_constraints =
+            *dump_rebase_constraints || verbosity >= Verbosity::VeryVerbose;
+        let dump_rebase_plan = *dump_rebase_plan || verbosity >= Verbosity::Verbose;
+        if dump_rebase_constraints {
             // For test: don't print to `effects.get_output_stream()`, as it will
             // be suppressed.
             println!(
@@ -1038,7 +1154,7 @@ impl<'a> RebasePlanBuilder<'a> {
             );
         }
         state.constraints.add_descendant_constraints(&effects)?;
-        if *dump_rebase_constraints {
+        if dump_rebase_constraints {
             // For test: don't print to `effects.get_output_stream()`, as it will
             // be suppressed.
             println!(
@@ -1055,6 +1171,9 @@ impl<'a> RebasePlanBuilder<'a> {
         }
 
         let repo = repo_pool.try_create()?;
+        let conn = repo.get_db_conn()?;
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+        let patch_id_db = PatchIdDb::new(&conn)?;
         let roots = state.constraints.find_roots();
         let mut acc = Vec::new();
         let mut first_dest_oid = None;
@@ -1080,9 +1199,12 @@ impl<'a> RebasePlanBuilder<'a> {
                     effects.start_operation(OperationType::DetectDuplicateCommits);
                 self.get_upstream_patch_ids(
                     &effects,
+                    git_run_info,
                     pool,
                     repo_pool,
                     &repo,
+                    &merge_base_db,
+                    &patch_id_db,
                     &mut state,
                     child_oid,
                     &parent_oids,
@@ -1093,6 +1215,7 @@ impl<'a> RebasePlanBuilder<'a> {
             acc = self.make_rebase_plan_for_current_commit(
                 &effects,
                 &repo,
+                &patch_id_db,
                 &mut state,
                 repo.find_commit_or_fail(child_oid)?,
                 &upstream_patch_ids,
@@ -1108,7 +1231,7 @@ impl<'a> RebasePlanBuilder<'a> {
             first_dest_oid,
             commands: acc,
         });
-        if *dump_rebase_plan {
+        if dump_rebase_plan {
             // For test: don't print to `effects.get_output_stream()`, as it will
             // be suppressed.
             println!("Rebase plan: {rebase_plan:#?}");
@@ -1141,7 +1264,11 @@ impl<'a> RebasePlanBuilder<'a> {
                     replacement_commit_oid: _,
                     parents: _,
                 }
-                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => vec![*commit_oid],
+                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+                | RebaseCommand::SkipFixedUpCommit {
+                    commit_oid,
+                    dest_oid: _,
+                } => vec![*commit_oid],
             })
             .collect();
         let missing_commit_oids = state
@@ -1165,18 +1292,22 @@ impl<'a> RebasePlanBuilder<'a> {
     fn get_upstream_patch_ids(
         &self,
         effects: &Effects,
+        git_run_info: &GitRunInfo,
         pool: &ThreadPool,
         repo_pool: &RepoPool,
         repo: &Repo,
+        merge_base_db: &MergeBaseDb,
+        patch_id_db: &PatchIdDb,
         state: &mut BuildState,
         current_oid: NonZeroOid,
         dest_oids: &[NonZeroOid],
     ) -> eyre::Result<HashSet<PatchId>> {
         let merge_base_oids: Vec<CommitSet> = dest_oids
             .iter()
-            .map(|dest_oid| {
-                let commit_set: CommitSet = [current_oid, *dest_oid].into_iter().collect();
-                self.dag.query().gca_all(commit_set)
+            .map(|dest_oid| -> eyre::Result<CommitSet> {
+                let merge_base_oids =
+                    merge_base_db.get_merge_bases(self.dag, current_oid, *dest_oid)?;
+                Ok(merge_base_oids.into_iter().collect())
             })
             .try_collect()?;
         let merge_base_oids = union_all(&merge_base_oids);
@@ -1200,37 +1331,64 @@ impl<'a> RebasePlanBuilder<'a> {
                 repo_pool,
                 repo,
                 path,
-                touched_commit_oids,
+                touched_commit_oids.clone(),
             )?
         };
 
-        // FIXME: we may recalculate common patch IDs many times, should be
-        // cached.
-        let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
-        progress.notify_progress(0, path.len());
-        let result: HashSet<PatchId> = {
-            let path_oids = path
-                .into_iter()
-                .map(|commit| commit.get_oid())
-                .collect_vec();
+        let path_oids = path
+            .into_iter()
+            .map(|commit| commit.get_oid())
+            .collect_vec();
+        let cached_patch_ids = patch_id_db.get_cached_patch_ids(&path_oids)?;
+        let uncached_oids = path_oids
+            .iter()
+            .copied()
+            .filter(|commit_oid| !cached_patch_ids.contains_key(commit_oid))
+            .collect_vec();
+
+        // Computing patch IDs requires the contents of the files which each
+        // commit changed, which may not have been fetched yet in a partial
+        // clone. Fetch them in a single batch now, rather than one at a time
+        // (or not at all) while computing the patch IDs in parallel.
+        let commits_to_hash: Vec<Commit> = uncached_oids
+            .iter()
+            .chain(touched_commit_oids.iter())
+            .map(|commit_oid| repo.find_commit(*commit_oid))
+            .filter_map(|commit| commit.transpose())
+            .try_collect()?;
+        if let Err(err) =
+            repo.fetch_missing_blobs_for_patches(effects, git_run_info, &commits_to_hash, &[])
+        {
+            warn!(?err, "Could not fetch missing objects for patch IDs");
+        }
 
-            pool.install(|| {
-                path_oids
-                    .into_par_iter()
-                    .map(|commit_oid| -> eyre::Result<Option<PatchId>> {
+        let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
+        progress.notify_progress(cached_patch_ids.len(), path_oids.len());
+        let new_patch_ids: Vec<(NonZeroOid, Option<PatchId>)> = pool.install(|| {
+            uncached_oids
+                .into_par_iter()
+                .map(
+                    |commit_oid| -> eyre::Result<Option<(NonZeroOid, Option<PatchId>)>> {
                         let repo = repo_pool.try_create()?;
                         let commit = match repo.find_commit(commit_oid)? {
                             Some(commit) => commit,
                             None => return Ok(None),
                         };
-                        let result = repo.get_patch_id(&effects, &commit)?;
-                        Ok(result)
-                    })
-                    .inspect(|_| progress.notify_progress_inc(1))
-                    .filter_map(|result| result.transpose())
-                    .collect::<eyre::Result<HashSet<PatchId>>>()
-            })?
-        };
+                        let patch_id = repo.get_patch_id(&effects, &commit)?;
+                        Ok(Some((commit_oid, patch_id)))
+                    },
+                )
+                .inspect(|_| progress.notify_progress_inc(1))
+                .filter_map(|result| result.transpose())
+                .collect::<eyre::Result<_>>()
+        })?;
+        patch_id_db.insert_patch_ids(new_patch_ids.iter().copied())?;
+
+        let result: HashSet<PatchId> = cached_patch_ids
+            .into_values()
+            .chain(new_patch_ids.into_iter().map(|(_, patch_id)| patch_id))
+            .flatten()
+            .collect();
         Ok(result)
     }
 
@@ -1403,10 +1561,11 @@ mod tests {
         let repo_pool = RepoPool::new(RepoResource {
             repo: Mutex::new(repo.try_clone()?),
         });
+        let git_run_info = git.get_git_run_info();
         let mut builder = RebasePlanBuilder::new(&dag, permissions);
         let builder2 = builder.clone();
         builder.move_subtree(test3_oid, vec![test1_oid])?;
-        let result = builder.build(&effects, &pool, &repo_pool)?;
+        let result = builder.build(&effects, &git_run_info, &pool, &repo_pool)?;
         let result = result.unwrap();
         let _ignored: Option<RebasePlan> = result;
         assert!(builder.touched_paths_cache.contains_key(&test1_oid));
@@ -2018,6 +2177,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_plan_fixup_commit() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        let test4_oid = git.commit_file("test4", 4)?;
+
+        let repo = git.get_repo()?;
+        create_and_execute_plan(&git, |builder: &mut RebasePlanBuilder| {
+            builder.fixup_commit(&repo, test4_oid, test2_oid)?;
+            Ok(())
+        })?;
+
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 5db6074 create test2.txt
+        |
+        @ 824066b create test3.txt
+        "###);
+
+        let (stdout, _stderr) = git.run(&["show", "--name-only", "--format=%s", "HEAD^"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+
+        test2.txt
+        test4.txt
+        "###);
+
+        Ok(())
+    }
+
     /// Helper function to handle the boilerplate involved in creating, building
     /// and executing the rebase plan.
     fn create_and_execute_plan(
@@ -2043,6 +2240,7 @@ mod tests {
         let repo_pool = RepoPool::new(RepoResource {
             repo: Mutex::new(repo.try_clone()?),
         });
+        let git_run_info = git.get_git_run_info();
 
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits: false,
@@ -2055,7 +2253,7 @@ mod tests {
 
         builder_callback_fn(&mut builder)?;
 
-        let build_result = builder.build(&effects, &pool, &repo_pool)?;
+        let build_result = builder.build(&effects, &git_run_info, &pool, &repo_pool)?;
 
         let rebase_plan = match build_result {
             Ok(None) => return Ok(()),
@@ -0,0 +1,362 @@
+//! Reuse recorded resolutions of merge conflicts, as with `git rerere`.
+//!
+//! When rerere is enabled, Git records how each conflict was resolved in the
+//! `rr-cache` directory. Each entry holds a `preimage`, which is the conflicted
+//! file with its conflict markers normalized, and a `postimage`, which is the
+//! file as it was resolved. Git names each entry after a hash of its conflicted
+//! hunks, and looks entries up by that name.
+//!
+//! In-memory rebases read the same cache, but find entries by comparing the
+//! conflicted hunks of each preimage instead, so that resolutions recorded by
+//! Git during on-disk rebases, merges and cherry-picks are reused. Resolutions
+//! made during in-memory rebases are recorded in the same format, under a name
+//! derived from the conflicted hunks. Git itself won't find them under that
+//! name, so they're only reused by in-memory rebases, but they still expire
+//! with `git rerere gc`.
+//!
+//! As with Git, reused resolutions are only committed without the user's
+//! review if `rerere.autoUpdate` is set, or if conflicts are being resolved
+//! anyways (with `--merge`).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+use git_record::{ConflictFileState, ConflictSection};
+use itertools::Itertools;
+use tracing::instrument;
+
+use crate::core::config::{get_rerere_cache_dir, get_rerere_enabled};
+use crate::git::{merge_for_resolve, Commit, Repo, Tree};
+
+use super::resolve::{read_conflicting_files, ConflictingFile};
+
+/// The file name of the normalized conflicted contents in a cache entry.
+const PREIMAGE_FILE_NAME: &str = "preimage";
+
+/// The file name of the resolved contents in a cache entry.
+const POSTIMAGE_FILE_NAME: &str = "postimage";
+
+/// A conflicted file, normalized in the same way as `git rerere`.
+#[derive(Debug, PartialEq, Eq)]
+struct NormalizedConflict {
+    /// The contents of the file, where each conflicted hunk consists of only
+    /// its two sides in sorted order, between bare conflict markers.
+    preimage: String,
+
+    /// The two sides of each conflicted hunk, in sorted order.
+    hunks: Vec<(String, String)>,
+}
+
+impl NormalizedConflict {
+    fn new(file_state: &ConflictFileState) -> Self {
+        let mut preimage = String::new();
+        let mut hunks = Vec::new();
+        for section in file_state.sections.iter() {
+            match section {
+                ConflictSection::Unchanged { contents } => {
+                    preimage.extend(contents.iter().map(|line| line.as_ref()));
+                }
+                ConflictSection::Conflicted {
+                    base: _,
+                    ours,
+                    theirs,
+                    resolution: _,
+                } => {
+                    // Like Git, leave lines which are the same on both sides
+                    // out of the conflicted hunk.
+                    let prefix_len = ours
+                        .iter()
+                        .zip(theirs.iter())
+                        .take_while(|(ours, theirs)| ours == theirs)
+                        .count();
+                    let suffix_len = ours[prefix_len..]
+                        .iter()
+                        .rev()
+                        .zip(theirs[prefix_len..].iter().rev())
+                        .take_while(|(ours, theirs)| ours == theirs)
+                        .count();
+                    let get_side = |lines: &[_]| -> String {
+                        let lines = &lines[prefix_len..lines.len() - suffix_len];
+                        let mut side: String = lines.concat();
+                        if !side.is_empty() && !side.ends_with('\n') {
+                            side.push('\n');
+                        }
+                        side
+                    };
+                    let (one, two) = {
+                        let ours = get_side(ours);
+                        let theirs = get_side(theirs);
+                        if ours <= theirs {
+                            (ours, theirs)
+                        } else {
+                            (theirs, ours)
+                        }
+                    };
+
+                    preimage.extend(ours[..prefix_len].iter().map(|line| line.as_ref()));
+                    preimage.push_str("<<<<<<<\n");
+                    preimage.push_str(&one);
+                    preimage.push_str("=======\n");
+                    preimage.push_str(&two);
+                    preimage.push_str(">>>>>>>\n");
+                    preimage.extend(
+                        ours[ours.len() - suffix_len..]
+                            .iter()
+                            .map(|line| line.as_ref()),
+                    );
+                    hunks.push((one, two));
+                }
+            }
+        }
+        Self { preimage, hunks }
+    }
+
+    /// Parse a recorded preimage. Returns `None` if its conflict markers are
+    /// malformed.
+    fn from_preimage(preimage: String) -> Option<Self> {
+        #[derive(PartialEq, Eq)]
+        enum State {
+            Context,
+            One,
+            Base,
+            Two,
+        }
+
+        let mut hunks = Vec::new();
+        let mut one = String::new();
+        let mut two = String::new();
+        let mut state = State::Context;
+        for line in preimage.split_inclusive('\n') {
+            state = match state {
+                State::Context if line.starts_with("<<<<<<<") => State::One,
+                State::Context => State::Context,
+                State::One | State::Base if line.starts_with("=======") => State::Two,
+                State::One if line.starts_with("|||||||") => State::Base,
+                State::One => {
+                    one.push_str(line);
+                    State::One
+                }
+                State::Base => State::Base,
+                State::Two if line.starts_with(">>>>>>>") => {
+                    hunks.push((std::mem::take(&mut one), std::mem::take(&mut two)));
+                    State::Context
+                }
+                State::Two => {
+                    two.push_str(line);
+                    State::Two
+                }
+            };
+        }
+        if state == State::Context {
+            Some(Self { preimage, hunks })
+        } else {
+            None
+        }
+    }
+
+    /// The name of the cache entry under which to record resolutions of this
+    /// conflict.
+    fn get_cache_key(&self) -> eyre::Result<String> {
+        let mut key = Vec::new();
+        for (one, two) in self.hunks.iter() {
+            key.extend(one.as_bytes());
+            key.push(0);
+            key.extend(two.as_bytes());
+            key.push(0);
+        }
+        let oid = git2::Oid::hash_object(git2::ObjectType::Blob, &key)?;
+        Ok(oid.to_string())
+    }
+}
+
+/// Find the recorded resolution of the given conflict, if any, and return the
+/// resolved contents of the file.
+fn find_resolution(
+    cache_dir: &Path,
+    conflict: &NormalizedConflict,
+) -> eyre::Result<Option<String>> {
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .wrap_err_with(|| format!("Reading rerere cache: {}", cache_dir.display()))
+        }
+    };
+
+    let mut image_paths = Vec::new();
+    for entry in entries {
+        let entry_path = entry?.path();
+        if !entry_path.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(&entry_path)? {
+            let file_name = file?.file_name();
+            let suffix = match file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_prefix(PREIMAGE_FILE_NAME))
+            {
+                Some(suffix) => suffix,
+                None => continue,
+            };
+            image_paths.push((
+                entry_path.join(&file_name),
+                entry_path.join(format!("{POSTIMAGE_FILE_NAME}{suffix}")),
+            ));
+        }
+    }
+    image_paths.sort();
+
+    let mut candidates = Vec::new();
+    for (preimage_path, postimage_path) in image_paths {
+        // A preimage without a postimage hasn't been resolved yet.
+        let (preimage, postimage) = match (
+            fs::read_to_string(preimage_path),
+            fs::read_to_string(postimage_path),
+        ) {
+            (Ok(preimage), Ok(postimage)) => (preimage, postimage),
+            _ => continue,
+        };
+        let recorded = match NormalizedConflict::from_preimage(preimage) {
+            Some(recorded) => recorded,
+            None => continue,
+        };
+        if recorded.hunks != conflict.hunks {
+            continue;
+        }
+        if recorded.preimage == conflict.preimage {
+            return Ok(Some(postimage));
+        }
+        candidates.push((recorded, postimage));
+    }
+
+    // The lines around the conflicted hunks are different, so apply the
+    // changes from the recorded resolution instead.
+    for (recorded, postimage) in candidates {
+        let merged = merge_for_resolve(&recorded.preimage, &conflict.preimage, &postimage);
+        if let Some(contents) = merged.get_resolved_contents() {
+            return Ok(Some(contents));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolve the merge conflicts from applying `patch_commit` onto
+/// `target_commit` with previously-recorded resolutions, if rerere is enabled.
+///
+/// Returns the resolved tree along with the paths which were resolved, or
+/// `None` if any of the conflicting files doesn't have a recorded resolution.
+#[instrument]
+pub fn resolve_conflicts_with_rerere<'repo>(
+    repo: &'repo Repo,
+    patch_commit: &'repo Commit,
+    target_commit: &'repo Commit,
+) -> eyre::Result<Option<(Tree<'repo>, Vec<PathBuf>)>> {
+    if !get_rerere_enabled(repo)? {
+        return Ok(None);
+    }
+    let files = match read_conflicting_files(repo, patch_commit, target_commit)? {
+        Some(files) => files,
+        None => return Ok(None),
+    };
+
+    let cache_dir = get_rerere_cache_dir(repo);
+    let mut resolutions = HashMap::new();
+    for file in files {
+        let ConflictingFile {
+            path,
+            file_mode,
+            base,
+            ours,
+            theirs,
+        } = file;
+        let conflict = NormalizedConflict::new(&merge_for_resolve(&base, &ours, &theirs));
+        if conflict.hunks.is_empty() {
+            return Ok(None);
+        }
+        let contents = match find_resolution(&cache_dir, &conflict)? {
+            Some(contents) => contents,
+            None => return Ok(None),
+        };
+        let oid = repo.create_blob_from_contents(contents.as_bytes())?;
+        resolutions.insert(path, Some((oid, file_mode)));
+    }
+
+    let tree = repo.cherry_pick_with_resolutions(patch_commit, target_commit, &resolutions)?;
+    let paths = resolutions.into_keys().sorted().collect();
+    Ok(Some((tree, paths)))
+}
+
+/// Record how the conflicts in `file_state` were resolved, so that the
+/// resolution can be reused if the same conflicts come up again.
+#[instrument]
+pub(super) fn record_resolution(
+    repo: &Repo,
+    file_state: &ConflictFileState<'_>,
+    resolved_contents: &str,
+) -> eyre::Result<()> {
+    let conflict = NormalizedConflict::new(file_state);
+    if conflict.hunks.is_empty() {
+        return Ok(());
+    }
+
+    let entry_dir = get_rerere_cache_dir(repo).join(conflict.get_cache_key()?);
+    fs::create_dir_all(&entry_dir)
+        .wrap_err_with(|| format!("Creating rerere cache entry: {}", entry_dir.display()))?;
+    // Different conflicts can have the same conflicted hunks, in which case
+    // each is recorded as its own numbered variant in the same entry.
+    let mut variant = 0;
+    let suffix = loop {
+        let suffix = if variant == 0 {
+            String::new()
+        } else {
+            format!(".{variant}")
+        };
+        match fs::read_to_string(entry_dir.join(format!("{PREIMAGE_FILE_NAME}{suffix}"))) {
+            Ok(preimage) if preimage != conflict.preimage => variant += 1,
+            _ => break suffix,
+        }
+    };
+    fs::write(
+        entry_dir.join(format!("{PREIMAGE_FILE_NAME}{suffix}")),
+        &conflict.preimage,
+    )?;
+    fs::write(
+        entry_dir.join(format!("{POSTIMAGE_FILE_NAME}{suffix}")),
+        resolved_contents,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_conflict() {
+        let file_state =
+            merge_for_resolve("a\nb\nc\n", "a\nours\nsame\nc\n", "a\ntheirs\nsame\nc\n");
+        let conflict = NormalizedConflict::new(&file_state);
+        assert_eq!(
+            conflict.preimage,
+            "a\n<<<<<<<\nours\n=======\ntheirs\n>>>>>>>\nsame\nc\n"
+        );
+        assert_eq!(
+            conflict.hunks,
+            vec![("ours\n".to_string(), "theirs\n".to_string())]
+        );
+
+        // The sides of each hunk are sorted, regardless of which side they
+        // came from.
+        let swapped = merge_for_resolve("a\nb\nc\n", "a\ntheirs\nsame\nc\n", "a\nours\nsame\nc\n");
+        assert_eq!(NormalizedConflict::new(&swapped), conflict);
+
+        assert_eq!(
+            NormalizedConflict::from_preimage(conflict.preimage.clone()),
+            Some(conflict)
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Resolve merge conflicts from in-memory rebases without touching the working
+//! copy.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use cursive::backends::crossterm;
+use cursive::utils::markup::StyledString;
+use cursive::CursiveRunnable;
+use cursive_buffered_backend::BufferedBackend;
+use git_record::{ConflictResolver, RecordError, ResolveState};
+use tracing::instrument;
+
+use crate::core::config::{get_rerere_enabled, get_restack_resolve_conflicts_interactively};
+use crate::core::effects::Effects;
+use crate::core::formatting::Glyphs;
+use crate::git::{merge_for_resolve, Commit, FileMode, MergeConflictEntry, NonZeroOid, Repo, Tree};
+
+use super::rerere::record_resolution;
+
+/// Offer to resolve the merge conflicts from applying `patch_commit` onto
+/// `target_commit` by picking a side for each conflicted section in a terminal
+/// user interface.
+///
+/// Returns `None` if the conflicts can't be resolved this way (such as if one
+/// side deleted the file, or the file isn't text), or if the user declined to
+/// resolve them. The caller should then fall back to resolving them on disk.
+#[instrument]
+pub fn resolve_conflicts_in_memory<'repo>(
+    effects: &Effects,
+    repo: &'repo Repo,
+    patch_commit: &'repo Commit,
+    target_commit: &'repo Commit,
+) -> eyre::Result<Option<Tree<'repo>>> {
+    if !console::user_attended() || !get_restack_resolve_conflicts_interactively(repo)? {
+        return Ok(None);
+    }
+
+    let file_contents = match read_conflicting_files(repo, patch_commit, target_commit)? {
+        Some(file_contents) => file_contents,
+        None => return Ok(None),
+    };
+
+    let resolve_state = ResolveState {
+        file_states: file_contents
+            .iter()
+            .map(|file| {
+                (
+                    file.path.clone(),
+                    merge_for_resolve(&file.base, &file.ours, &file.theirs),
+                )
+            })
+            .collect(),
+    };
+    let header = {
+        let glyphs = Glyphs::text();
+        let mut header = StyledString::plain("Resolving merge conflicts from applying: ");
+        header.append(patch_commit.friendly_describe(&glyphs)?);
+        header.append_plain("\nonto: ");
+        header.append(target_commit.friendly_describe(&glyphs)?);
+        header.append_plain(
+            "\n(\"ours\" is the commit being applied onto, \"theirs\" is the commit being applied)",
+        );
+        header
+    };
+
+    let _effects = effects.enable_tui_mode();
+    let siv = CursiveRunnable::new(|| -> io::Result<_> {
+        // Use crossterm to ensure that we support Windows.
+        let crossterm_backend = crossterm::Backend::init()?;
+        Ok(Box::new(BufferedBackend::new(crossterm_backend)))
+    });
+    let ResolveState { file_states } =
+        match ConflictResolver::new(header, resolve_state).run(siv.into_runner()) {
+            Ok(resolve_state) => resolve_state,
+            Err(RecordError::Cancelled) => return Ok(None),
+        };
+
+    let rerere_enabled = get_rerere_enabled(repo)?;
+    let mut resolutions = HashMap::new();
+    for ((path, file_state), file) in file_states.iter().zip(&file_contents) {
+        let contents = match file_state.get_resolved_contents() {
+            Some(contents) => contents,
+            None => return Ok(None),
+        };
+        if rerere_enabled {
+            record_resolution(repo, file_state, &contents)?;
+        }
+        let oid = repo.create_blob_from_contents(contents.as_bytes())?;
+        resolutions.insert(path.clone(), Some((oid, file.file_mode)));
+    }
+    let tree = repo.cherry_pick_with_resolutions(patch_commit, target_commit, &resolutions)?;
+    Ok(Some(tree))
+}
+
+/// The contents of a file which conflicted when applying a patch.
+#[derive(Debug)]
+pub(super) struct ConflictingFile {
+    pub path: PathBuf,
+    pub file_mode: FileMode,
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// Read the contents of each file which conflicts when applying `patch_commit`
+/// onto `target_commit`, sorted by path.
+///
+/// Returns `None` if any of the conflicts can't be resolved by editing the
+/// contents of the file (such as if one side deleted the file, or the file
+/// isn't text).
+#[instrument]
+pub(super) fn read_conflicting_files(
+    repo: &Repo,
+    patch_commit: &Commit,
+    target_commit: &Commit,
+) -> eyre::Result<Option<Vec<ConflictingFile>>> {
+    let conflicts = repo.get_cherry_pick_conflicts(patch_commit, target_commit)?;
+    if conflicts.is_empty() {
+        return Ok(None);
+    }
+
+    let read_contents = |entry: Option<(NonZeroOid, _)>| -> eyre::Result<Option<String>> {
+        match entry {
+            None => Ok(Some(String::new())),
+            Some((oid, _file_mode)) => {
+                let blob = repo.find_blob_or_fail(oid)?;
+                let content = blob.get_content();
+                if content.contains(&0) {
+                    // Probably a binary file.
+                    return Ok(None);
+                }
+                Ok(String::from_utf8(content.to_vec()).ok())
+            }
+        }
+    };
+    let mut file_contents = Vec::new();
+    for conflict in conflicts {
+        let MergeConflictEntry {
+            path,
+            ancestor,
+            ours,
+            theirs,
+        } = conflict;
+        let (ours, theirs) = match (ours, theirs) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            _ => return Ok(None),
+        };
+        // Keep a file mode change made by the commit being applied.
+        let file_mode = match ancestor {
+            Some((_oid, ancestor_file_mode)) if ancestor_file_mode == ours.1 => theirs.1,
+            _ => ours.1,
+        };
+        match (
+            read_contents(ancestor)?,
+            read_contents(Some(ours))?,
+            read_contents(Some(theirs))?,
+        ) {
+            (Some(base), Some(ours), Some(theirs)) => {
+                file_contents.push(ConflictingFile {
+                    path,
+                    file_mode,
+                    base,
+                    ours,
+                    theirs,
+                });
+            }
+            _ => return Ok(None),
+        }
+    }
+    file_contents.sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+    Ok(Some(file_contents))
+}
@@ -15,12 +15,16 @@ use tempfile::NamedTempFile;
 use tracing::instrument;
 
 use crate::core::check_out::CheckOutCommitOptions;
-use crate::core::config::{get_hint_enabled, print_hint_suppression_notice, Hint};
-use crate::core::dag::Dag;
+use crate::core::child_index::ChildIndexDb;
+use crate::core::config::{
+    get_hint_enabled, get_restack_prompt_after_amend, print_hint_suppression_notice, Hint,
+};
+use crate::core::dag::{sorted_commit_set, CommitSet, Dag};
 use crate::core::effects::Effects;
-use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
+use crate::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use crate::core::formatting::Pluralize;
 use crate::core::repo_ext::RepoExt;
+use crate::core::user_hooks::{run_user_hook, UserHook};
 use crate::git::{
     CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
     ResolvedReferenceInfo,
@@ -145,7 +149,7 @@ pub fn hook_post_rewrite(
         )?;
     }
 
-    event_log_db.add_events(events)?;
+    event_log_db.add_events(events.clone())?;
 
     if repo
         .get_rebase_state_dir_path()
@@ -174,20 +178,37 @@ pub fn hook_post_rewrite(
         }
     }
 
-    let should_check_abandoned_commits = get_hint_enabled(&repo, Hint::RestackWarnAbandoned)?;
+    let should_check_abandoned_commits =
+        get_hint_enabled(effects, &repo, Hint::RestackWarnAbandoned)?;
     if should_check_abandoned_commits && !is_spurious_event {
+        let is_amend = rewrite_type == "amend";
         let printed_hint = warn_abandoned(
             effects,
             &repo,
             &conn,
             &event_log_db,
             rewritten_oids.keys().copied(),
+            is_amend,
         )?;
         if printed_hint {
             print_hint_suppression_notice(effects, Hint::RestackWarnAbandoned)?;
+            if is_amend && get_restack_prompt_after_amend(&repo)? {
+                prompt_restack(effects, git_run_info, event_tx_id)?;
+            }
         }
     }
 
+    if !is_spurious_event {
+        run_user_hook(
+            effects,
+            git_run_info,
+            &repo,
+            UserHook::PostRewrite,
+            event_tx_id,
+            &events,
+        )?;
+    }
+
     Ok(())
 }
 
@@ -198,6 +219,7 @@ fn warn_abandoned(
     conn: &rusqlite::Connection,
     event_log_db: &EventLogDb,
     old_commit_oids: impl IntoIterator<Item = NonZeroOid>,
+    list_abandoned_commits: bool,
 ) -> eyre::Result<bool> {
     // The caller will have added events to the event log database, so make sure
     // to construct a fresh `EventReplayer` here.
@@ -215,9 +237,15 @@ fn warn_abandoned(
     let (all_abandoned_children, all_abandoned_branches) = {
         let mut all_abandoned_children: HashSet<NonZeroOid> = HashSet::new();
         let mut all_abandoned_branches: HashSet<&str> = HashSet::new();
+        let child_index_db = ChildIndexDb::new(conn)?;
         for old_commit_oid in old_commit_oids {
-            let abandoned_result =
-                find_abandoned_children(&dag, &event_replayer, event_cursor, old_commit_oid)?;
+            let abandoned_result = find_abandoned_children(
+                &dag,
+                &child_index_db,
+                &event_replayer,
+                event_cursor,
+                old_commit_oid,
+            )?;
             let (_rewritten_oid, abandoned_children) = match abandoned_result {
                 Some(abandoned_result) => abandoned_result,
                 None => continue,
@@ -272,35 +300,101 @@ fn warn_abandoned(
         };
 
         let warning_message = warning_items.join(" and ");
-        let warning_message = style(format!("This operation abandoned {warning_message}!"))
-            .bold()
-            .yellow();
-
-        print!(
-            "\
-branchless: {warning_message}
-branchless: Consider running one of the following:
-branchless:   - {git_restack}: re-apply the abandoned commits/branches
-branchless:     (this is most likely what you want to do)
-branchless:   - {git_smartlog}: assess the situation
-branchless:   - {git_hide} [<commit>...]: hide the commits from the smartlog
-branchless:   - {git_undo}: undo the operation
-",
-            warning_message = warning_message,
-            git_smartlog = style("git smartlog").bold(),
-            git_restack = style("git restack").bold(),
-            git_hide = style("git hide").bold(),
-            git_undo = style("git undo").bold(),
+        let messages = effects.get_messages();
+        let warning_message =
+            style(messages.format("abandoned-warning", &[("items", &warning_message)]))
+                .bold()
+                .yellow();
+
+        let abandoned_commits_list = if list_abandoned_commits && num_abandoned_children > 0 {
+            let glyphs = effects.get_glyphs();
+            let abandoned_commits: CommitSet = all_abandoned_children.into_iter().collect();
+            let mut lines = vec![format!(
+                "branchless: {}\n",
+                messages.format("abandoned-commits-list", &[])
+            )];
+            for commit in sorted_commit_set(repo, &dag, &abandoned_commits)? {
+                lines.push(format!(
+                    "branchless:   {}\n",
+                    glyphs.render(commit.friendly_describe(glyphs)?)?
+                ));
+            }
+            lines.concat()
+        } else {
+            String::new()
+        };
+
+        let suggestions = messages.format(
+            "abandoned-suggestions",
+            &[
+                ("git-restack", &style("git restack").bold()),
+                ("git-smartlog", &style("git smartlog").bold()),
+                ("git-hide", &style("git hide").bold()),
+                ("git-undo", &style("git undo").bold()),
+            ],
         );
+        print!("branchless: {warning_message}\n{abandoned_commits_list}");
+        for line in suggestions.lines() {
+            println!("branchless: {line}");
+        }
         Ok(true)
     } else {
         Ok(false)
     }
 }
 
+/// Ask whether to restack the commits abandoned by an amend right away, and do
+/// so if the user presses `y`. The prompt is skipped if not running in a
+/// terminal, such as when the amend was carried out by a script or an editor.
+#[instrument]
+fn prompt_restack(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<()> {
+    // The hook's standard input is the list of rewritten commits, so read the
+    // response from the terminal directly.
+    let term = console::Term::stderr();
+    if !term.is_term() {
+        return Ok(());
+    }
+
+    term.write_str(&format!(
+        "branchless: Run {} now? [yN] ",
+        style("git restack").bold()
+    ))?;
+    let key = term.read_char().unwrap_or('n');
+    term.write_line(&key.to_string())?;
+    if key != 'y' && key != 'Y' {
+        return Ok(());
+    }
+
+    let exit_code = git_run_info.run(effects, Some(event_tx_id), &["branchless", "restack"])?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: Restacking failed; run {} to assess the situation",
+            style("git smartlog").bold()
+        )?;
+    }
+    Ok(())
+}
+
 const ORIGINAL_HEAD_OID_FILE_NAME: &str = "branchless_original_head_oid";
 const ORIGINAL_HEAD_FILE_NAME: &str = "branchless_original_head";
 
+/// Determine whether the rebase state directory was set up by an on-disk
+/// rebase initiated by git-branchless, but Git never started carrying it out
+/// (such as if git-branchless was interrupted before invoking `git rebase
+/// --continue`). Once Git has applied a rebase command, it would have written
+/// the `done` file.
+#[instrument]
+pub fn is_unstarted_branchless_rebase(repo: &Repo) -> bool {
+    let rebase_state_dir = repo.get_rebase_state_dir_path();
+    rebase_state_dir.join(ORIGINAL_HEAD_OID_FILE_NAME).exists()
+        && !rebase_state_dir.join("done").exists()
+}
+
 /// Save the name of the currently checked-out branch. This should be called as
 /// part of initializing the rebase.
 #[instrument]
@@ -0,0 +1,174 @@
+//! Failures caused by the user's input or the state of their repository, as
+//! opposed to bugs or I/O errors, which are reported with `eyre`.
+//!
+//! Each failure carries a machine-readable [`UserErrorCode`], a message, and
+//! suggestions of what to do next, so that it can be rendered the same way
+//! by every command, and reported to programs in the `--output=json` and
+//! `--output=jsonl` modes.
+
+use std::fmt::{Display, Write};
+
+use cursive::utils::markup::StyledString;
+
+use crate::core::effects::{Effects, OperationEvent};
+use crate::util::ExitCode;
+
+/// The kind of a [`UserError`]. The string representations returned by
+/// [`UserErrorCode::as_str`] are part of the machine-readable output, and
+/// won't change within a version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UserErrorCode {
+    /// An archive with the requested name already exists.
+    ArchiveAlreadyExists,
+
+    /// There is no archive with the requested name.
+    ArchiveNotFound,
+
+    /// A merge commit would have to be rebased, which can't be done
+    /// in-memory.
+    CannotRebaseMergeInMemory,
+
+    /// A revset expression couldn't be parsed or evaluated.
+    InvalidRevset,
+
+    /// Moving commits would cause a merge conflict, and resolving conflicts
+    /// wasn't requested.
+    MergeConflict,
+
+    /// No command was configured or provided for `git test`.
+    TestCommandNotFound,
+}
+
+impl UserErrorCode {
+    /// Get the machine-readable name of this code, such as `merge-conflict`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserErrorCode::ArchiveAlreadyExists => "archive-already-exists",
+            UserErrorCode::ArchiveNotFound => "archive-not-found",
+            UserErrorCode::CannotRebaseMergeInMemory => "cannot-rebase-merge-in-memory",
+            UserErrorCode::InvalidRevset => "invalid-revset",
+            UserErrorCode::MergeConflict => "merge-conflict",
+            UserErrorCode::TestCommandNotFound => "test-command-not-found",
+        }
+    }
+
+    /// Get the exit code which commands should return when they fail with an
+    /// error of this kind.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            UserErrorCode::CannotRebaseMergeInMemory | UserErrorCode::MergeConflict => {
+                ExitCode::MERGE_CONFLICT
+            }
+            UserErrorCode::ArchiveAlreadyExists
+            | UserErrorCode::ArchiveNotFound
+            | UserErrorCode::InvalidRevset
+            | UserErrorCode::TestCommandNotFound => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl Display for UserErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A suggestion of what to do about a [`UserError`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Remediation {
+    /// Run the given command. Rendered like "To resolve merge conflicts, run:
+    /// git restack --merge".
+    Run {
+        /// What running the command accomplishes, starting with "To".
+        purpose: String,
+
+        /// The command to run.
+        command: String,
+    },
+
+    /// Run the same operation again with the given option.
+    RetryWithOption {
+        /// What retrying the operation accomplishes, starting with "To".
+        purpose: String,
+
+        /// The option to pass, such as `--merge`.
+        option: String,
+    },
+}
+
+impl Display for Remediation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Remediation::Run { purpose, command } => write!(f, "{purpose}, run: {command}"),
+            Remediation::RetryWithOption { purpose, option } => {
+                write!(
+                    f,
+                    "{purpose}, retry this operation with the {option} option."
+                )
+            }
+        }
+    }
+}
+
+/// A failure to report to the user, along with what they can do about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserError {
+    /// The kind of error.
+    pub code: UserErrorCode,
+
+    /// A description of what went wrong, which may span multiple lines.
+    pub message: StyledString,
+
+    /// Suggestions of what to do next, in order of preference.
+    pub remediations: Vec<Remediation>,
+}
+
+impl UserError {
+    /// Constructor.
+    pub fn new(code: UserErrorCode, message: impl Into<StyledString>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            remediations: Vec::new(),
+        }
+    }
+
+    /// Suggest running the given command for the given purpose.
+    pub fn with_command(mut self, purpose: impl Into<String>, command: impl Into<String>) -> Self {
+        self.remediations.push(Remediation::Run {
+            purpose: purpose.into(),
+            command: command.into(),
+        });
+        self
+    }
+
+    /// Suggest retrying the operation with the given option for the given
+    /// purpose.
+    pub fn with_retry_option(
+        mut self,
+        purpose: impl Into<String>,
+        option: impl Into<String>,
+    ) -> Self {
+        self.remediations.push(Remediation::RetryWithOption {
+            purpose: purpose.into(),
+            option: option.into(),
+        });
+        self
+    }
+
+    /// Print the error and its remediations to the error stream, and report
+    /// it to the observers of `effects`. Returns the exit code which the
+    /// command should exit with.
+    pub fn report(&self, effects: &Effects) -> eyre::Result<ExitCode> {
+        effects.notify_observers(OperationEvent::UserError(self.clone()));
+        writeln!(
+            effects.get_error_stream(),
+            "{}",
+            effects.get_glyphs().render(self.message.clone())?
+        )?;
+        for remediation in &self.remediations {
+            writeln!(effects.get_error_stream(), "{remediation}")?;
+        }
+        Ok(self.code.exit_code())
+    }
+}
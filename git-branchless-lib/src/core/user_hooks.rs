@@ -0,0 +1,182 @@
+//! Run user-provided scripts after `git-branchless` operations, so that custom
+//! automation (such as sending notifications or syncing an external system)
+//! can react to them.
+//!
+//! A script is configured with `branchless.hooks.<hook>`, such as
+//! `branchless.hooks.postRewrite = "./scripts/on-rewrite.sh"`. It's run with
+//! `sh -c` in the root of the working copy, after the operation has finished,
+//! and receives a JSON object describing what changed on stdin:
+//!
+//! ```json
+//! {
+//!   "hook": "postRewrite",
+//!   "eventTxId": 12,
+//!   "rewrittenCommits": [{"oldCommit": "abc...", "newCommit": "def..."}],
+//!   "hiddenCommits": [],
+//!   "unhiddenCommits": [],
+//!   "refUpdates": []
+//! }
+//! ```
+//!
+//! A `newCommit`, `oldOid`, or `newOid` value of `null` means that the commit
+//! was deleted, or that the reference didn't exist before or after the update.
+
+use std::fmt::Write;
+
+use bstr::BString;
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::core::config::{
+    POST_HIDE_USER_HOOK_CONFIG_KEY, POST_REWRITE_USER_HOOK_CONFIG_KEY,
+    POST_UNDO_USER_HOOK_CONFIG_KEY,
+};
+use crate::core::effects::Effects;
+use crate::core::eventlog::{Event, EventTransactionId};
+use crate::git::{ConfigRead, GitRunInfo, MaybeZeroOid, Repo};
+
+/// Environment variable which is set while a user hook is running, so that
+/// `git-branchless` operations run by the script don't trigger user hooks
+/// again.
+pub const BRANCHLESS_USER_HOOK_ENV_VAR: &str = "BRANCHLESS_USER_HOOK";
+
+/// A point at which a user-provided script can be run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserHook {
+    /// Commits were rewritten, such as by `git move`, `git restack`, `git
+    /// amend`, `git sync`, or `git commit --amend`.
+    PostRewrite,
+
+    /// Commits were hidden with `git hide` or unhidden with `git unhide`.
+    PostHide,
+
+    /// Events were undone with `git undo`.
+    PostUndo,
+}
+
+impl UserHook {
+    /// The config key which holds the script for this hook.
+    pub fn get_config_key(self) -> &'static str {
+        match self {
+            UserHook::PostRewrite => POST_REWRITE_USER_HOOK_CONFIG_KEY,
+            UserHook::PostHide => POST_HIDE_USER_HOOK_CONFIG_KEY,
+            UserHook::PostUndo => POST_UNDO_USER_HOOK_CONFIG_KEY,
+        }
+    }
+
+    /// The name of this hook, as passed in the payload.
+    pub fn get_name(self) -> &'static str {
+        match self {
+            UserHook::PostRewrite => "postRewrite",
+            UserHook::PostHide => "postHide",
+            UserHook::PostUndo => "postUndo",
+        }
+    }
+}
+
+fn oid_to_json(oid: MaybeZeroOid) -> Value {
+    match oid {
+        MaybeZeroOid::NonZero(oid) => Value::String(oid.to_string()),
+        MaybeZeroOid::Zero => Value::Null,
+    }
+}
+
+/// Build the JSON payload passed to a user hook, summarizing the given events.
+pub fn make_user_hook_payload(
+    hook: UserHook,
+    event_tx_id: EventTransactionId,
+    events: &[Event],
+) -> Value {
+    let mut rewritten_commits = Vec::new();
+    let mut hidden_commits = Vec::new();
+    let mut unhidden_commits = Vec::new();
+    let mut ref_updates = Vec::new();
+    for event in events {
+        match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid,
+                new_commit_oid,
+            } => rewritten_commits.push(json!({
+                "oldCommit": oid_to_json(*old_commit_oid),
+                "newCommit": oid_to_json(*new_commit_oid),
+            })),
+            Event::ObsoleteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+                message: _,
+            } => hidden_commits.push(Value::String(commit_oid.to_string())),
+            Event::UnobsoleteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => unhidden_commits.push(Value::String(commit_oid.to_string())),
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid,
+                new_oid,
+                message: _,
+            } => ref_updates.push(json!({
+                "refName": ref_name.as_str(),
+                "oldOid": oid_to_json(*old_oid),
+                "newOid": oid_to_json(*new_oid),
+            })),
+            Event::CommitEvent { .. }
+            | Event::WorkingCopySnapshot { .. }
+            | Event::CopyEvent { .. } => {}
+        }
+    }
+
+    json!({
+        "hook": hook.get_name(),
+        "eventTxId": event_tx_id.get_id(),
+        "rewrittenCommits": rewritten_commits,
+        "hiddenCommits": hidden_commits,
+        "unhiddenCommits": unhidden_commits,
+        "refUpdates": ref_updates,
+    })
+}
+
+/// Run the user's script for the given hook, if one is configured, passing it
+/// a summary of the given events. A failing script only produces a warning,
+/// since the operation which triggered it has already completed.
+#[instrument]
+pub fn run_user_hook(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    hook: UserHook,
+    event_tx_id: EventTransactionId,
+    events: &[Event],
+) -> eyre::Result<()> {
+    if std::env::var_os(BRANCHLESS_USER_HOOK_ENV_VAR).is_some() {
+        return Ok(());
+    }
+    let command: Option<String> = repo.get_readonly_config()?.get(hook.get_config_key())?;
+    let command = match command {
+        Some(command) if !command.trim().is_empty() => command,
+        _ => return Ok(()),
+    };
+
+    let payload = make_user_hook_payload(hook, event_tx_id, events);
+    let exit_code = git_run_info.run_user_command(
+        effects,
+        repo,
+        &command,
+        &[(BRANCHLESS_USER_HOOK_ENV_VAR, hook.get_name())],
+        BString::from(format!("{payload}\n")),
+    )?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: the {} script exited with code {}",
+            hook.get_config_key(),
+            exit_code.0
+        )?;
+    }
+    Ok(())
+}
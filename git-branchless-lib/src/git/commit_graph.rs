@@ -0,0 +1,288 @@
+//! Read Git's `commit-graph` file, which stores the parents and generation
+//! numbers of commits, so that they can be looked up without inflating the
+//! commit objects themselves.
+//!
+//! See `gitformat-commit-graph(5)` for a description of the file format. Only
+//! a single `objects/info/commit-graph` file using SHA-1 is supported; split
+//! commit-graph chains are ignored, in which case callers should fall back to
+//! reading the commit objects.
+
+use std::convert::TryInto;
+use std::path::PathBuf;
+
+use tracing::{instrument, warn};
+
+use crate::git::{ConfigRead, NonZeroOid, Repo};
+
+const SIGNATURE: &[u8] = b"CGPH";
+const VERSION: u8 = 1;
+const HASH_VERSION_SHA1: u8 = 1;
+const HASH_LEN: usize = 20;
+const HEADER_LEN: usize = 8;
+const CHUNK_TABLE_ENTRY_LEN: usize = 12;
+const FANOUT_LEN: usize = 256 * 4;
+const COMMIT_DATA_ENTRY_LEN: usize = HASH_LEN + 16;
+
+const CHUNK_OID_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_OID_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_COMMIT_DATA: u32 = u32::from_be_bytes(*b"CDAT");
+const CHUNK_EXTRA_EDGES: u32 = u32::from_be_bytes(*b"EDGE");
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA_EDGES_NEEDED: u32 = 0x8000_0000;
+const EDGE_LAST: u32 = 0x8000_0000;
+const EDGE_INDEX_MASK: u32 = 0x7fff_ffff;
+
+/// The information stored about a commit in the commit-graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitGraphEntry {
+    /// The parents of the commit, in order.
+    pub parents: Vec<NonZeroOid>,
+
+    /// The topological level of the commit: `1` for root commits, and one more
+    /// than the maximum of its parents' levels otherwise. If commit `A` is an
+    /// ancestor of a different commit `B`, then `A`'s generation is less than
+    /// `B`'s.
+    pub generation: u32,
+}
+
+/// A parsed `commit-graph` file.
+pub struct CommitGraph {
+    data: Vec<u8>,
+    num_commits: usize,
+    fanout_offset: usize,
+    oid_lookup_offset: usize,
+    commit_data_offset: usize,
+    extra_edges_offset: Option<usize>,
+}
+
+impl std::fmt::Debug for CommitGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CommitGraph num_commits={}>", self.num_commits)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn to_usize(value: impl TryInto<usize>) -> Option<usize> {
+    value.try_into().ok()
+}
+
+impl CommitGraph {
+    fn get_path(repo: &Repo) -> PathBuf {
+        repo.get_common_path()
+            .join("objects")
+            .join("info")
+            .join("commit-graph")
+    }
+
+    /// Open the commit-graph file for the repository. Returns `None` if there
+    /// isn't one, if it's in an unsupported format, or if Git wouldn't use it
+    /// either (such as when `core.commitGraph` is `false`, or when grafts are
+    /// in use or the repository is a shallow clone, since they change the
+    /// parents of commits).
+    #[instrument]
+    pub fn open(repo: &Repo) -> eyre::Result<Option<Self>> {
+        if !repo
+            .get_readonly_config()?
+            .get_or("core.commitGraph", true)?
+        {
+            return Ok(None);
+        }
+        if repo.get_common_path().join("info").join("grafts").exists()
+            || repo.get_common_path().join("shallow").exists()
+        {
+            return Ok(None);
+        }
+
+        let path = Self::get_path(repo);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(eyre::eyre!(err)),
+        };
+        let commit_graph = Self::parse(data);
+        if commit_graph.is_none() {
+            warn!(?path, "Could not parse commit-graph file; ignoring it");
+        }
+        Ok(commit_graph)
+    }
+
+    fn parse(data: Vec<u8>) -> Option<Self> {
+        let header = data.get(..HEADER_LEN)?;
+        if &header[..4] != SIGNATURE || header[4] != VERSION || header[5] != HASH_VERSION_SHA1 {
+            return None;
+        }
+        let num_chunks = usize::from(header[6]);
+        let num_base_graphs = header[7];
+        if num_base_graphs != 0 {
+            return None;
+        }
+
+        let mut fanout_offset = None;
+        let mut oid_lookup_offset = None;
+        let mut commit_data_offset = None;
+        let mut extra_edges_offset = None;
+        for i in 0..num_chunks {
+            let entry_offset = HEADER_LEN + i * CHUNK_TABLE_ENTRY_LEN;
+            let chunk_id = read_u32(&data, entry_offset)?;
+            let chunk_offset = to_usize(read_u64(&data, entry_offset + 4)?)?;
+            match chunk_id {
+                CHUNK_OID_FANOUT => fanout_offset = Some(chunk_offset),
+                CHUNK_OID_LOOKUP => oid_lookup_offset = Some(chunk_offset),
+                CHUNK_COMMIT_DATA => commit_data_offset = Some(chunk_offset),
+                CHUNK_EXTRA_EDGES => extra_edges_offset = Some(chunk_offset),
+                _ => {}
+            }
+        }
+
+        let fanout_offset = fanout_offset?;
+        let oid_lookup_offset = oid_lookup_offset?;
+        let commit_data_offset = commit_data_offset?;
+        let num_commits = to_usize(read_u32(&data, fanout_offset + FANOUT_LEN - 4)?)?;
+        if data.len() < oid_lookup_offset.checked_add(num_commits.checked_mul(HASH_LEN)?)?
+            || data.len()
+                < commit_data_offset.checked_add(num_commits.checked_mul(COMMIT_DATA_ENTRY_LEN)?)?
+        {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            num_commits,
+            fanout_offset,
+            oid_lookup_offset,
+            commit_data_offset,
+            extra_edges_offset,
+        })
+    }
+
+    /// The number of commits in the commit-graph.
+    pub fn num_commits(&self) -> usize {
+        self.num_commits
+    }
+
+    fn get_oid_at(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.num_commits {
+            return None;
+        }
+        let offset = self.oid_lookup_offset + index * HASH_LEN;
+        self.data.get(offset..offset + HASH_LEN)
+    }
+
+    fn find_index(&self, oid: NonZeroOid) -> Option<usize> {
+        let oid = oid.as_bytes();
+        let first_byte = usize::from(*oid.first()?);
+        let lower = match first_byte {
+            0 => 0,
+            _ => to_usize(read_u32(
+                &self.data,
+                self.fanout_offset + (first_byte - 1) * 4,
+            )?)?,
+        };
+        let upper = to_usize(read_u32(&self.data, self.fanout_offset + first_byte * 4)?)?;
+
+        let (mut lower, mut upper) = (lower, upper.min(self.num_commits));
+        while lower < upper {
+            let middle = lower + (upper - lower) / 2;
+            match self.get_oid_at(middle)?.cmp(oid) {
+                std::cmp::Ordering::Equal => return Some(middle),
+                std::cmp::Ordering::Less => lower = middle + 1,
+                std::cmp::Ordering::Greater => upper = middle,
+            }
+        }
+        None
+    }
+
+    fn get_parent_oid(&self, parent_index: u32) -> Option<NonZeroOid> {
+        let oid = self.get_oid_at(to_usize(parent_index)?)?;
+        let oid = git2::Oid::from_bytes(oid).ok()?;
+        Some(NonZeroOid { inner: oid })
+    }
+
+    /// Look up the given commit. Returns `None` if the commit isn't in the
+    /// commit-graph (such as if it was made after the commit-graph was
+    /// written), in which case the caller should read the commit object
+    /// instead.
+    pub fn lookup(&self, oid: NonZeroOid) -> Option<CommitGraphEntry> {
+        let index = self.find_index(oid)?;
+        let entry_offset = self.commit_data_offset + index * COMMIT_DATA_ENTRY_LEN;
+        let parent1 = read_u32(&self.data, entry_offset + HASH_LEN)?;
+        let parent2 = read_u32(&self.data, entry_offset + HASH_LEN + 4)?;
+        let generation = read_u32(&self.data, entry_offset + HASH_LEN + 8)? >> 2;
+
+        let mut parents = Vec::new();
+        if parent1 != PARENT_NONE {
+            parents.push(self.get_parent_oid(parent1)?);
+        }
+        if parent2 & PARENT_EXTRA_EDGES_NEEDED != 0 {
+            // Octopus merge: the remaining parents are stored in the extra
+            // edges list, with the last one marked.
+            let extra_edges_offset = self.extra_edges_offset?;
+            let mut edge_index = to_usize(parent2 & EDGE_INDEX_MASK)?;
+            loop {
+                let edge = read_u32(&self.data, extra_edges_offset.checked_add(edge_index * 4)?)?;
+                parents.push(self.get_parent_oid(edge & EDGE_INDEX_MASK)?);
+                if edge & EDGE_LAST != 0 {
+                    break;
+                }
+                edge_index += 1;
+            }
+        } else if parent2 != PARENT_NONE {
+            parents.push(self.get_parent_oid(parent2)?);
+        }
+
+        Some(CommitGraphEntry {
+            parents,
+            generation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_commit_graph_lookup() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "other", "HEAD^"])?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["merge", "--no-ff", "other", "-m", "merge"])?;
+
+        let repo = git.get_repo()?;
+        assert!(CommitGraph::open(&repo)?.is_none());
+
+        git.run(&["commit-graph", "write", "--reachable"])?;
+        let commit_graph = CommitGraph::open(&repo)?.expect("commit-graph should exist");
+        assert_eq!(commit_graph.num_commits(), 4);
+
+        let merge_commit = repo.get_head_info()?.oid.unwrap();
+        let entry = commit_graph.lookup(merge_commit).unwrap();
+        assert_eq!(entry.parents, vec![test1_oid, test2_oid]);
+        assert_eq!(entry.generation, 3);
+
+        let test1 = repo.find_commit_or_fail(test1_oid)?;
+        let entry = commit_graph.lookup(test1_oid).unwrap();
+        assert_eq!(entry.parents, test1.get_parent_oids());
+        assert_eq!(entry.generation, 2);
+
+        let new_oid = git.commit_file("test3", 3)?;
+        assert_eq!(commit_graph.lookup(new_oid), None);
+
+        Ok(())
+    }
+}
@@ -1,19 +1,127 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use eyre::Context;
 use tracing::instrument;
 
-use super::repo::wrap_git_error;
+use crate::core::config::env_vars::GIT_BRANCHLESS_MAIN_BRANCH;
+
+use super::repo::{wrap_git_error, Error, Result};
 
 /// Wrapper around the config values stored on disk for Git.
+///
+/// In addition to Git's own configuration files, a `Config` may be backed by
+/// one or more of `git-branchless`'s TOML config files (see
+/// [`get_user_config_file_path`]). These are consulted for `branchless.*` keys
+/// which aren't set in Git's configuration, but are never written to.
 pub struct Config {
     inner: git2::Config,
+
+    /// Values set by environment variables (see [`ENV_VAR_CONFIG_OVERRIDES`]),
+    /// which take precedence over all config files.
+    env_layer: Option<toml::Table>,
+
+    /// The TOML config files, in order of decreasing precedence.
+    file_layers: Vec<Arc<toml::Table>>,
 }
 
 impl From<git2::Config> for Config {
     fn from(config: git2::Config) -> Self {
-        Config { inner: config }
+        Config {
+            inner: config,
+            env_layer: None,
+            file_layers: Vec::new(),
+        }
+    }
+}
+
+/// The prefix of Git config keys which can also be set in the TOML config
+/// files. The prefix is dropped when looking up the key in the TOML file, so
+/// `branchless.restack.preserveTimestamps` corresponds to `preserveTimestamps`
+/// in the `[restack]` table.
+const FILE_LAYER_KEY_PREFIX: &str = "branchless.";
+
+/// Environment variables which override the given config keys. These take
+/// precedence over all config files, which allows controlling `git-branchless`
+/// for a single invocation (such as in CI) without modifying any repository
+/// state. Empty values are ignored.
+pub const ENV_VAR_CONFIG_OVERRIDES: &[(&str, &str)] =
+    &[(GIT_BRANCHLESS_MAIN_BRANCH, "branchless.core.mainBranch")];
+
+/// Get the path to the per-user `git-branchless` config file, which is
+/// `$XDG_CONFIG_HOME/git-branchless/config.toml` or
+/// `~/.config/git-branchless/config.toml`.
+pub fn get_user_config_file_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_dir) if !config_dir.is_empty() => PathBuf::from(config_dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("git-branchless").join("config.toml"))
+}
+
+/// Read and parse the given TOML config files, skipping those which don't
+/// exist.
+#[instrument]
+pub fn read_config_files(paths: &[PathBuf]) -> Result<Vec<Arc<toml::Table>>> {
+    let mut result = Vec::new();
+    for path in paths {
+        if let Some(table) = read_config_file(path)? {
+            result.push(Arc::new(table));
+        }
+    }
+    Ok(result)
+}
+
+#[instrument]
+fn read_config_file(path: &Path) -> Result<Option<toml::Table>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let table: toml::Table =
+                toml::from_str(&contents).map_err(|err| Error::ParseConfigFile {
+                    source: err,
+                    path: path.to_owned(),
+                })?;
+            Ok(Some(table))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::ReadConfigFile {
+            source: err,
+            path: path.to_owned(),
+        }),
+    }
+}
+
+/// Look up the given key, with the `branchless.` prefix already removed, in
+/// the given TOML table. Section and key names are matched
+/// case-insensitively, as in Git.
+fn get_table_value<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let components: Vec<&str> = key.split('.').collect();
+    let (last, init) = components.split_last()?;
+    let mut table = table;
+    for component in init {
+        table = table
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(component))
+            .and_then(|(_, value)| value.as_table())?;
+    }
+    table
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(last))
+        .map(|(_, value)| value)
+}
+
+/// Flatten the given TOML table into `key = value` pairs, where nested tables
+/// are joined with `.`, as in Git config keys.
+fn flatten_table(prefix: &str, table: &toml::Table, result: &mut Vec<(String, String)>) {
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        match value {
+            toml::Value::Table(table) => flatten_table(&format!("{key}."), table, result),
+            toml::Value::String(value) => result.push((key, value.clone())),
+            value => result.push((key, value.to_string())),
+        }
     }
 }
 
@@ -84,19 +192,46 @@ pub trait GetConfigValue<V> {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<V>>;
 }
 
+/// Look up `key` in each layer of `config` in order of precedence: the
+/// environment variable overrides, then Git's configuration, then the TOML
+/// config files. `from_toml` converts a value found in a TOML layer, and
+/// `from_git` looks up the value in Git's configuration.
+fn get_layered_value<V>(
+    config: &Config,
+    key: &str,
+    from_toml: impl Fn(&toml::Value) -> eyre::Result<V>,
+    from_git: impl FnOnce(&git2::Config) -> std::result::Result<V, git2::Error>,
+) -> eyre::Result<Option<V>> {
+    if let Some(value) = config.get_env_layer_value(key) {
+        return from_toml(value).map(Some);
+    }
+    match from_git(&config.inner) {
+        Ok(value) => return Ok(Some(value)),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => {}
+        Err(err) => {
+            return Err(wrap_git_error(err))
+                .wrap_err_with(|| format!("Looking up value for config key {key:?}"));
+        }
+    }
+    match config.get_file_layer_value(key) {
+        Some(value) => from_toml(value).map(Some),
+        None => Ok(None),
+    }
+}
+
 impl GetConfigValue<String> for String {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<String>> {
         #[instrument]
         fn inner(config: &Config, key: &str) -> eyre::Result<Option<String>> {
-            let value = match config.inner.get_string(key) {
-                Ok(value) => Some(value),
-                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
-                Err(err) => {
-                    return Err(wrap_git_error(err))
-                        .wrap_err("Looking up string value for config key");
-                }
-            };
-            Ok(value)
+            get_layered_value(
+                config,
+                key,
+                |value| match value {
+                    toml::Value::String(value) => Ok(value.clone()),
+                    value => Ok(value.to_string()),
+                },
+                |git_config| git_config.get_string(key),
+            )
         }
         inner(config, key.as_ref())
     }
@@ -106,15 +241,22 @@ impl GetConfigValue<bool> for bool {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<bool>> {
         #[instrument]
         fn inner(config: &Config, key: &str) -> eyre::Result<Option<bool>> {
-            let value = match config.inner.get_bool(key) {
-                Ok(value) => Some(value),
-                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
-                Err(err) => {
-                    return Err(wrap_git_error(err))
-                        .wrap_err("Looking up bool value for config key")
-                }
-            };
-            Ok(value)
+            get_layered_value(
+                config,
+                key,
+                |value| match value {
+                    toml::Value::Boolean(value) => Ok(*value),
+                    // Accept the same spellings as Git does.
+                    toml::Value::String(value) => match value.to_lowercase().as_str() {
+                        "true" | "yes" | "on" | "1" => Ok(true),
+                        "false" | "no" | "off" | "0" | "" => Ok(false),
+                        _ => eyre::bail!("Invalid bool value for config key {key:?}: {value}"),
+                    },
+                    toml::Value::Integer(value) => Ok(*value != 0),
+                    value => eyre::bail!("Invalid bool value for config key {key:?}: {value}"),
+                },
+                |git_config| git_config.get_bool(key),
+            )
         }
         inner(config, key.as_ref())
     }
@@ -124,15 +266,20 @@ impl GetConfigValue<i32> for i32 {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<i32>> {
         #[instrument]
         fn inner(config: &Config, key: &str) -> eyre::Result<Option<i32>> {
-            let value = match config.inner.get_i32(key) {
-                Ok(value) => Some(value),
-                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
-                Err(err) => {
-                    return Err(wrap_git_error(err))
-                        .wrap_err("Looking up bool value for config key")
-                }
-            };
-            Ok(value)
+            get_layered_value(
+                config,
+                key,
+                |value| match value {
+                    toml::Value::Integer(value) => i32::try_from(*value).wrap_err_with(|| {
+                        format!("Converting integer value for config key {key:?}")
+                    }),
+                    toml::Value::String(value) => value
+                        .parse()
+                        .wrap_err_with(|| format!("Parsing integer value for config key {key:?}")),
+                    value => eyre::bail!("Invalid integer value for config key {key:?}: {value}"),
+                },
+                |git_config| git_config.get_i32(key),
+            )
         }
         inner(config, key.as_ref())
     }
@@ -142,15 +289,20 @@ impl GetConfigValue<PathBuf> for PathBuf {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<PathBuf>> {
         #[instrument]
         fn inner(config: &Config, key: &str) -> eyre::Result<Option<PathBuf>> {
-            let value = match config.inner.get_path(key.as_ref()) {
-                Ok(value) => Some(value),
-                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
-                Err(err) => {
-                    return Err(wrap_git_error(err))
-                        .wrap_err("Looking up path value for config key")
-                }
-            };
-            Ok(value)
+            get_layered_value(
+                config,
+                key,
+                |value| match value {
+                    toml::Value::String(value) => {
+                        match (value.strip_prefix("~/"), std::env::var_os("HOME")) {
+                            (Some(value), Some(home)) => Ok(PathBuf::from(home).join(value)),
+                            _ => Ok(PathBuf::from(value)),
+                        }
+                    }
+                    value => eyre::bail!("Invalid path value for config key {key:?}: {value}"),
+                },
+                |git_config| git_config.get_path(key),
+            )
         }
         inner(config, key.as_ref())
     }
@@ -201,19 +353,57 @@ impl ConfigRead for Config {
 
     fn list<S: AsRef<str>>(&self, glob_pattern: S) -> eyre::Result<Vec<(String, String)>> {
         let glob_pattern = glob_pattern.as_ref();
+
+        // Git config globs are actually regular expressions, so match the
+        // file layers' keys the same way.
+        let regex = regex::RegexBuilder::new(glob_pattern)
+            .case_insensitive(true)
+            .build()
+            .wrap_err_with(|| format!("Compiling config glob pattern {glob_pattern:?}"))?;
+        let table_entries = |table: &toml::Table| {
+            let mut entries = Vec::new();
+            flatten_table(FILE_LAYER_KEY_PREFIX, table, &mut entries);
+            entries.retain(|(name, _value)| regex.is_match(name));
+            entries
+        };
+
+        let mut git_entries = Vec::new();
         let entries = self.inner.entries(Some(glob_pattern)).wrap_err_with(|| {
             format!("Reading config entries for glob pattern {glob_pattern:?}")
         })?;
-        let mut result = Vec::new();
         entries
             .for_each(|entry| {
                 if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
-                    result.push((name.to_owned(), value.to_owned()));
+                    git_entries.push((name.to_owned(), value.to_owned()));
                 }
             })
             .wrap_err_with(|| {
                 format!("Iterating config entries for glob pattern {glob_pattern:?}")
             })?;
+
+        // Layers in order of decreasing precedence. A key is only listed from
+        // the first layer which sets it, but Git's configuration may list a
+        // multivariable key more than once.
+        let layers = self
+            .env_layer
+            .iter()
+            .map(table_entries)
+            .chain(std::iter::once(git_entries))
+            .chain(self.file_layers.iter().map(|table| table_entries(table)));
+        let mut result = Vec::new();
+        let mut seen_keys = HashSet::new();
+        for layer in layers {
+            let layer_keys: HashSet<String> = layer
+                .iter()
+                .map(|(name, _value)| name.to_lowercase())
+                .collect();
+            result.extend(
+                layer
+                    .into_iter()
+                    .filter(|(name, _value)| !seen_keys.contains(&name.to_lowercase())),
+            );
+            seen_keys.extend(layer_keys);
+        }
         Ok(result)
     }
 }
@@ -250,7 +440,7 @@ impl Config {
     #[instrument]
     pub fn open(path: &Path) -> eyre::Result<Self> {
         let inner = git2::Config::open(path).map_err(wrap_git_error)?;
-        Ok(Config { inner })
+        Ok(Config::from(inner))
     }
 
     /// Open a configuration instance derived from the global, XDG and
@@ -258,7 +448,66 @@ impl Config {
     #[instrument]
     pub fn open_default() -> eyre::Result<Self> {
         let inner = git2::Config::open_default().map_err(wrap_git_error)?;
-        Ok(Config { inner })
+        Ok(Config::from(inner))
+    }
+
+    /// Use the given TOML config files (see [`read_config_files`]), in order
+    /// of decreasing precedence, for keys which aren't set in this
+    /// configuration.
+    pub fn with_file_layers(mut self, file_layers: Vec<Arc<toml::Table>>) -> Self {
+        self.file_layers = file_layers;
+        self
+    }
+
+    /// Layer the values from the environment variables in
+    /// `ENV_VAR_CONFIG_OVERRIDES` on top of this configuration, taking
+    /// precedence over all other layers.
+    #[instrument]
+    pub fn with_env_overrides(mut self) -> Self {
+        let mut table = toml::Table::new();
+        for (env_var, key) in ENV_VAR_CONFIG_OVERRIDES {
+            let value = match std::env::var(env_var) {
+                Ok(value) if !value.is_empty() => value,
+                _ => continue,
+            };
+            let key = match key.strip_prefix(FILE_LAYER_KEY_PREFIX) {
+                Some(key) => key,
+                None => continue,
+            };
+            let components: Vec<&str> = key.split('.').collect();
+            if let Some((last, init)) = components.split_last() {
+                let mut current = &mut table;
+                for component in init {
+                    current = match current
+                        .entry(component.to_string())
+                        .or_insert_with(|| toml::Value::Table(Default::default()))
+                    {
+                        toml::Value::Table(table) => table,
+                        _ => unreachable!("only tables are inserted at intermediate keys"),
+                    };
+                }
+                current.insert(last.to_string(), toml::Value::String(value));
+            }
+        }
+        if !table.is_empty() {
+            self.env_layer = Some(table);
+        }
+        self
+    }
+
+    /// Look up the given `branchless.*` key in the environment variable
+    /// overrides, if any.
+    fn get_env_layer_value(&self, key: &str) -> Option<&toml::Value> {
+        let key = key.strip_prefix(FILE_LAYER_KEY_PREFIX)?;
+        get_table_value(self.env_layer.as_ref()?, key)
+    }
+
+    /// Look up the given `branchless.*` key in the TOML config files, if any.
+    fn get_file_layer_value(&self, key: &str) -> Option<&toml::Value> {
+        let key = key.strip_prefix(FILE_LAYER_KEY_PREFIX)?;
+        self.file_layers
+            .iter()
+            .find_map(|table| get_table_value(table, key))
     }
 
     #[instrument]
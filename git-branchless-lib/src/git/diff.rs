@@ -3,9 +3,12 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+use cursive::theme::BaseColor;
+use cursive::utils::markup::StyledString;
 use eyre::Context;
-use git_record::{FileState, Section, SectionChangedLine};
+use git_record::{ConflictFileState, ConflictSection, FileState, Section, SectionChangedLine};
 use itertools::Itertools;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
 
 use super::{MaybeZeroOid, Repo};
 
@@ -14,6 +17,37 @@ pub struct Diff<'repo> {
     pub(super) inner: git2::Diff<'repo>,
 }
 
+impl Diff<'_> {
+    /// Render the diff in the unified patch format, as `git diff` would, with
+    /// one entry per line. Added and removed lines are colored.
+    pub fn render_patch(&self) -> eyre::Result<Vec<StyledString>> {
+        let mut lines = Vec::new();
+        self.inner
+            .print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+                let content = String::from_utf8_lossy(line.content());
+                let origin = line.origin();
+                for content_line in content.lines() {
+                    let line = match origin {
+                        '+' => StyledString::styled(
+                            format!("+{content_line}"),
+                            BaseColor::Green.light(),
+                        ),
+                        '-' => {
+                            StyledString::styled(format!("-{content_line}"), BaseColor::Red.light())
+                        }
+                        ' ' => StyledString::plain(format!(" {content_line}")),
+                        'H' => StyledString::styled(content_line, BaseColor::Cyan.light()),
+                        _ => StyledString::plain(content_line),
+                    };
+                    lines.push(line);
+                }
+                true
+            })
+            .wrap_err("Rendering diff")?;
+        Ok(lines)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct GitHunk {
     old_start: usize,
@@ -287,3 +321,158 @@ pub fn process_diff_for_record(
     }
     Ok(result)
 }
+
+/// Merge the lines of `ours` and `theirs`, which were both derived from
+/// `base`, and produce the sections to display when resolving conflicts.
+/// Changes made on only one side (or identically on both sides) are merged
+/// automatically, and the rest become [`ConflictSection::Conflicted`] sections.
+pub fn merge_for_resolve<'a>(
+    base: &'a str,
+    ours: &'a str,
+    theirs: &'a str,
+) -> ConflictFileState<'a> {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    // For each line in `base`, the index of the matching line in the other
+    // file, if it wasn't changed.
+    let get_matching_lines = |other: &[&str]| -> Vec<Option<usize>> {
+        let mut result = vec![None; base.len()];
+        for op in capture_diff_slices(Algorithm::Myers, &base, other) {
+            if let DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } = op
+            {
+                for i in 0..len {
+                    result[old_index + i] = Some(new_index + i);
+                }
+            }
+        }
+        result
+    };
+    let ours_matches = get_matching_lines(&ours);
+    let theirs_matches = get_matching_lines(&theirs);
+
+    let mut sections = Vec::new();
+    let mut unchanged: Vec<Cow<'a, str>> = Vec::new();
+    let (mut base_index, mut ours_index, mut theirs_index) = (0, 0, 0);
+    loop {
+        if base_index < base.len()
+            && ours_matches[base_index] == Some(ours_index)
+            && theirs_matches[base_index] == Some(theirs_index)
+        {
+            unchanged.push(Cow::Borrowed(base[base_index]));
+            base_index += 1;
+            ours_index += 1;
+            theirs_index += 1;
+            continue;
+        }
+
+        // Find the next line which is unchanged on both sides, and treat
+        // everything up to it as one chunk.
+        let next_match =
+            (base_index..base.len()).find_map(|i| match (ours_matches[i], theirs_matches[i]) {
+                (Some(ours_end), Some(theirs_end))
+                    if ours_end >= ours_index && theirs_end >= theirs_index =>
+                {
+                    Some((i, ours_end, theirs_end))
+                }
+                _ => None,
+            });
+        let (base_end, ours_end, theirs_end) =
+            next_match.unwrap_or((base.len(), ours.len(), theirs.len()));
+        if (base_end, ours_end, theirs_end) == (base_index, ours_index, theirs_index) {
+            break;
+        }
+
+        let base_chunk = &base[base_index..base_end];
+        let ours_chunk = &ours[ours_index..ours_end];
+        let theirs_chunk = &theirs[theirs_index..theirs_end];
+        if ours_chunk == base_chunk {
+            unchanged.extend(theirs_chunk.iter().copied().map(Cow::Borrowed));
+        } else if theirs_chunk == base_chunk || theirs_chunk == ours_chunk {
+            unchanged.extend(ours_chunk.iter().copied().map(Cow::Borrowed));
+        } else {
+            if !unchanged.is_empty() {
+                sections.push(ConflictSection::Unchanged {
+                    contents: std::mem::take(&mut unchanged),
+                });
+            }
+            sections.push(ConflictSection::Conflicted {
+                base: base_chunk.iter().copied().map(Cow::Borrowed).collect(),
+                ours: ours_chunk.iter().copied().map(Cow::Borrowed).collect(),
+                theirs: theirs_chunk.iter().copied().map(Cow::Borrowed).collect(),
+                resolution: None,
+            });
+        }
+        base_index = base_end;
+        ours_index = ours_end;
+        theirs_index = theirs_end;
+    }
+    if !unchanged.is_empty() {
+        sections.push(ConflictSection::Unchanged {
+            contents: unchanged,
+        });
+    }
+
+    ConflictFileState { sections }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_for_resolve() {
+        let base = "a\nb\nc\nd\ne\n";
+
+        // Changes to different lines merge cleanly.
+        let merged = merge_for_resolve(base, "a\nB\nc\nd\ne\n", "a\nb\nc\nD\ne\n");
+        assert_eq!(merged.count_conflicts(), 0);
+        assert_eq!(
+            merged.get_resolved_contents().as_deref(),
+            Some("a\nB\nc\nD\ne\n")
+        );
+
+        // Identical changes merge cleanly.
+        let merged = merge_for_resolve(base, "a\nB\nc\nd\ne\n", "a\nB\nc\nd\ne\n");
+        assert_eq!(merged.count_conflicts(), 0);
+
+        // Different changes to the same line conflict.
+        let merged = merge_for_resolve(base, "a\nb\nX\nd\ne\n", "a\nb\nY\nY\nd\ne\n");
+        insta::assert_debug_snapshot!(merged, @r###"
+        ConflictFileState {
+            sections: [
+                Unchanged {
+                    contents: [
+                        "a\n",
+                        "b\n",
+                    ],
+                },
+                Conflicted {
+                    base: [
+                        "c\n",
+                    ],
+                    ours: [
+                        "X\n",
+                    ],
+                    theirs: [
+                        "Y\n",
+                        "Y\n",
+                    ],
+                    resolution: None,
+                },
+                Unchanged {
+                    contents: [
+                        "d\n",
+                        "e\n",
+                    ],
+                },
+            ],
+        }
+        "###);
+    }
+}
@@ -1,5 +1,6 @@
 //! Tools for interfacing with the Git repository.
 
+mod commit_graph;
 mod config;
 mod diff;
 mod index;
@@ -13,8 +14,9 @@ mod status;
 mod test;
 mod tree;
 
-pub use config::{Config, ConfigRead, ConfigValue, ConfigWrite};
-pub use diff::{process_diff_for_record, Diff};
+pub use commit_graph::{CommitGraph, CommitGraphEntry};
+pub use config::{get_user_config_file_path, Config, ConfigRead, ConfigValue, ConfigWrite};
+pub use diff::{merge_for_resolve, process_diff_for_record, Diff};
 pub use index::{update_index, Index, IndexEntry, Stage, UpdateIndexCommand};
 pub use object::Commit;
 pub use oid::{MaybeZeroOid, NonZeroOid};
@@ -23,8 +25,8 @@ pub use reference::{
 };
 pub use repo::{
     message_prettify, AmendFastOptions, CherryPickFastError, CherryPickFastOptions,
-    Error as RepoError, GitVersion, PatchId, Repo, ResolvedReferenceInfo, Result as RepoResult,
-    Time,
+    Error as RepoError, GitVersion, MergeConflictEntry, PatchId, Repo, ResolvedReferenceInfo,
+    Result as RepoResult, Time, WorktreeInfo,
 };
 pub use run::{GitRunInfo, GitRunOpts, GitRunResult};
 pub use snapshot::{WorkingCopyChangesType, WorkingCopySnapshot};
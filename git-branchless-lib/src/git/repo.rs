@@ -10,12 +10,14 @@
 //! - To collect some different helper Git functions.
 
 use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::num::TryFromIntError;
 use std::ops::Add;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use std::{io, time};
 
@@ -25,17 +27,19 @@ use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use git2::DiffOptions;
 use itertools::Itertools;
+use once_cell::unsync::OnceCell;
 use thiserror::Error;
 use tracing::{instrument, warn};
 
+use crate::core::config::env_vars::GIT_BRANCHLESS_DB_PATH;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::EventTransactionId;
 use crate::core::formatting::Glyphs;
-use crate::git::config::{Config, ConfigRead};
+use crate::git::config::{get_user_config_file_path, read_config_files, Config, ConfigRead};
 use crate::git::object::Blob;
 use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
 use crate::git::reference::ReferenceNameError;
-use crate::git::run::GitRunInfo;
+use crate::git::run::{GitRunInfo, GitRunOpts, GitRunResult};
 use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, Tree};
 use crate::git::{Branch, BranchType, Commit, Reference, ReferenceName};
 
@@ -44,6 +48,10 @@ use super::snapshot::WorkingCopySnapshot;
 use super::status::FileMode;
 use super::{tree, Diff, StatusEntry};
 
+/// How long to wait for another process to release its lock on the database
+/// before giving up.
+const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[allow(missing_docs)]
 #[derive(Debug, Error)]
 pub enum Error {
@@ -56,6 +64,9 @@ pub enum Error {
     #[error("could not open repository: {0}")]
     UnsupportedExtensionWorktreeConfig(#[source] git2::Error),
 
+    #[error("could not read worktrees: {0}")]
+    ReadWorktrees(#[source] git2::Error),
+
     #[error("could not read index: {0}")]
     ReadIndex(#[source] git2::Error),
 
@@ -74,12 +85,39 @@ pub enum Error {
     #[error("could not read config: {0}")]
     ReadConfig(#[source] git2::Error),
 
+    #[error("could not read config file at {path}: {source}")]
+    ReadConfigFile { source: io::Error, path: PathBuf },
+
+    #[error("could not read partial clone config: {0}")]
+    ReadPartialCloneConfig(#[source] eyre::Error),
+
+    #[error("could not open object database: {0}")]
+    ReadObjectDatabase(#[source] git2::Error),
+
+    #[error("could not fetch missing objects from promisor remote: {0}")]
+    FetchMissingObjects(#[source] eyre::Error),
+
+    #[error("could not read shallow file at {path}: {source}")]
+    ReadShallowFile { source: io::Error, path: PathBuf },
+
+    #[error("could not parse config file at {path}: {source}")]
+    ParseConfigFile {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
     #[error("could not set HEAD (detached) to {oid}: {source}")]
     SetHead {
         source: git2::Error,
         oid: NonZeroOid,
     },
 
+    #[error("could not check out commit {oid}: {source}")]
+    CheckOutCommit {
+        source: git2::Error,
+        oid: NonZeroOid,
+    },
+
     #[error("could not find object {oid}")]
     FindObject { oid: NonZeroOid },
 
@@ -99,6 +137,9 @@ pub enum Error {
     #[error("could not create blob: {0}")]
     CreateBlob(#[source] git2::Error),
 
+    #[error("could not hash blob: {0}")]
+    HashBlob(#[source] git2::Error),
+
     #[error("could not create blob from {path}: {source}")]
     CreateBlobFromPath { source: eyre::Error, path: PathBuf },
 
@@ -125,6 +166,9 @@ pub enum Error {
         onto: NonZeroOid,
     },
 
+    #[error("could not merge submodule at {path:?}: {source}")]
+    MergeSubmodule { source: git2::Error, path: PathBuf },
+
     #[error("could not amend the current commit: {0}")]
     Amend(#[source] git2::Error),
 
@@ -379,6 +423,21 @@ impl FromStr for GitVersion {
     }
 }
 
+/// Information about a worktree of the repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorktreeInfo {
+    /// The name of the worktree. For linked worktrees, this is the name that
+    /// Git uses to refer to it (such as in `git worktree lock`). For the main
+    /// worktree, this is the name of its directory.
+    pub name: String,
+
+    /// The path to the worktree's working copy.
+    pub path: PathBuf,
+
+    /// The OID of the commit that the worktree's `HEAD` points to, if any.
+    pub head_oid: Option<NonZeroOid>,
+}
+
 /// Options for `Repo::cherry_pick_fast`.
 #[derive(Clone, Debug)]
 pub struct CherryPickFastOptions {
@@ -424,6 +483,25 @@ pub enum CherryPickFastError {
     Git(git2::Error),
 }
 
+/// A path which couldn't be merged automatically when cherry-picking a commit.
+/// Each side is the blob and file mode of the path on that side of the merge,
+/// or `None` if the path doesn't exist there.
+#[derive(Clone, Debug)]
+pub struct MergeConflictEntry {
+    /// The path which conflicted.
+    pub path: PathBuf,
+
+    /// The entry at the common ancestor, i.e. the parent of the commit being
+    /// applied.
+    pub ancestor: Option<(NonZeroOid, FileMode)>,
+
+    /// The entry in the commit being applied onto.
+    pub ours: Option<(NonZeroOid, FileMode)>,
+
+    /// The entry in the commit being applied.
+    pub theirs: Option<(NonZeroOid, FileMode)>,
+}
+
 /// Options for `Repo::amend_fast`
 #[derive(Debug)]
 pub enum AmendFastOptions {
@@ -449,11 +527,37 @@ impl AmendFastOptions {
     }
 }
 
+fn wrap_open_repo_error(err: git2::Error) -> Error {
+    if err.code() == git2::ErrorCode::GenericError
+        && err
+            .message()
+            .contains("unsupported extension name extensions.worktreeconfig")
+    {
+        Error::UnsupportedExtensionWorktreeConfig(err)
+    } else {
+        Error::OpenRepo(err)
+    }
+}
+
 /// Wrapper around `git2::Repository`.
 pub struct Repo {
     pub(super) inner: git2::Repository,
+    cherry_pick_fast_cache: RefCell<CherryPickFastCache>,
+
+    /// The parsed TOML config files (see `get_readonly_config`). These are
+    /// read at most once per `Repo`, so that long-running processes pick up
+    /// changes by opening the repository again.
+    config_file_layers: OnceCell<Vec<Arc<toml::Table>>>,
 }
 
+/// The results of previous calls to `Repo::cherry_pick_fast`, keyed by the trees
+/// of the patch's parent (if any), the patch, and the target commit.
+type CherryPickFastCache = HashMap<(Option<NonZeroOid>, NonZeroOid, NonZeroOid), NonZeroOid>;
+
+/// The entries of the submodules which could be merged automatically when
+/// cherry-picking a commit, keyed by path.
+type SubmoduleResolutions = HashMap<PathBuf, (NonZeroOid, FileMode)>;
+
 impl std::fmt::Debug for Repo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<Git repository at: {:?}>", self.get_path())
@@ -461,29 +565,46 @@ impl std::fmt::Debug for Repo {
 }
 
 impl Repo {
+    fn from_inner(inner: git2::Repository) -> Self {
+        Repo {
+            inner,
+            cherry_pick_fast_cache: Default::default(),
+            config_file_layers: Default::default(),
+        }
+    }
+
     /// Get the Git repository associated with the given directory.
     #[instrument]
     pub fn from_dir(path: &Path) -> Result<Self> {
-        let repo = match git2::Repository::discover(path) {
-            Ok(repo) => repo,
-            Err(err)
-                if err.code() == git2::ErrorCode::GenericError
-                    && err
-                        .message()
-                        .contains("unsupported extension name extensions.worktreeconfig") =>
-            {
-                return Err(Error::UnsupportedExtensionWorktreeConfig(err))
-            }
-            Err(err) => return Err(Error::OpenRepo(err)),
-        };
-        Ok(Repo { inner: repo })
+        let repo = git2::Repository::discover(path).map_err(wrap_open_repo_error)?;
+        Ok(Repo::from_inner(repo))
     }
 
     /// Get the Git repository associated with the current directory.
+    ///
+    /// As with Git, the `$GIT_DIR` and `$GIT_WORK_TREE` environment variables
+    /// (which are also set by the `--git-dir` and `--work-tree` options) take
+    /// precedence over discovering the repository from the current directory.
     #[instrument]
     pub fn from_current_dir() -> Result<Self> {
         let path = std::env::current_dir().map_err(Error::Io)?;
-        Repo::from_dir(&path)
+        let repo = match std::env::var_os("GIT_DIR") {
+            Some(git_dir) if !git_dir.is_empty() => {
+                let repo =
+                    git2::Repository::open(path.join(git_dir)).map_err(wrap_open_repo_error)?;
+                Repo::from_inner(repo)
+            }
+            _ => Repo::from_dir(&path)?,
+        };
+        match std::env::var_os("GIT_WORK_TREE") {
+            Some(work_tree) if !work_tree.is_empty() => {
+                repo.inner
+                    .set_workdir(&path.join(work_tree), false)
+                    .map_err(Error::OpenRepo)?;
+            }
+            _ => {}
+        }
+        Ok(repo)
     }
 
     /// Open a new copy of the repository.
@@ -491,7 +612,7 @@ impl Repo {
     pub fn try_clone(&self) -> Result<Self> {
         let path = self.get_path();
         let repo = git2::Repository::open(path).map_err(Error::OpenRepo)?;
-        Ok(Repo { inner: repo })
+        Ok(Repo::from_inner(repo))
     }
 
     /// Get the path to the `.git` directory for the repository.
@@ -499,9 +620,29 @@ impl Repo {
         self.inner.path()
     }
 
+    /// Get the path to the `.git` directory shared by all worktrees of the
+    /// repository. For the main worktree, this is the same as `get_path`.
+    pub fn get_common_path(&self) -> PathBuf {
+        let path = self.get_path();
+        if !self.inner.is_worktree() {
+            return path.to_owned();
+        }
+
+        // `git2` doesn't expose the common directory, so read it from the
+        // `commondir` file ourselves. It's typically a relative path like
+        // `../..`.
+        match std::fs::read_to_string(path.join("commondir")) {
+            Ok(common_dir) => {
+                let common_dir = path.join(common_dir.trim_end());
+                common_dir.canonicalize().unwrap_or(common_dir)
+            }
+            Err(_) => path.to_owned(),
+        }
+    }
+
     /// Get the path to the `packed-refs` file for the repository.
     pub fn get_packed_refs_path(&self) -> PathBuf {
-        self.inner.path().join("packed-refs")
+        self.get_common_path().join("packed-refs")
     }
 
     /// Get the path to the directory inside the `.git` directory which contains
@@ -510,6 +651,12 @@ impl Repo {
         self.inner.path().join("rebase-merge")
     }
 
+    /// Get the path to the directory inside the `.git` directory which contains
+    /// state used for the current `git am` session (if any).
+    pub fn get_apply_mailbox_state_dir_path(&self) -> PathBuf {
+        self.inner.path().join("rebase-apply")
+    }
+
     /// Get the path to the working copy for this repository. If the repository
     /// is bare (has no working copy), returns `None`.
     pub fn get_working_copy_path(&self) -> Option<&Path> {
@@ -550,27 +697,156 @@ impl Repo {
         Ok(Some(parent_repo))
     }
 
+    /// If this repository is a linked worktree (as created by `git worktree
+    /// add`), get the name of the worktree.
+    pub fn get_worktree_name(&self) -> Option<String> {
+        if !self.inner.is_worktree() {
+            return None;
+        }
+        self.get_path()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// Get the name of the reference under which updates to this worktree's
+    /// `HEAD` are recorded in the event log.
+    ///
+    /// The event log is shared between all worktrees, so for linked worktrees,
+    /// this is the `worktrees/<name>/HEAD` pseudo-ref rather than `HEAD`,
+    /// which would otherwise make checkouts in one worktree look like they
+    /// moved `HEAD` in the other worktrees.
+    pub fn get_head_event_reference_name(&self) -> ReferenceName {
+        match self.get_worktree_name() {
+            Some(worktree_name) => ReferenceName::from(format!("worktrees/{worktree_name}/HEAD")),
+            None => ReferenceName::from("HEAD"),
+        }
+    }
+
+    /// Get information about the worktrees of this repository, other than the
+    /// current one. This includes the main worktree if the current repository
+    /// is a linked worktree, unless the main repository is bare.
+    #[instrument]
+    pub fn get_other_worktrees(&self) -> Result<Vec<WorktreeInfo>> {
+        let mut result = Vec::new();
+        if let Some(parent_repo) = self.open_worktree_parent_repo()? {
+            if let Some(path) = parent_repo.get_working_copy_path() {
+                result.push(WorktreeInfo {
+                    name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    path: path.to_owned(),
+                    head_oid: parent_repo.get_head_info()?.oid,
+                });
+            }
+        }
+
+        let current_worktree_name = self.get_worktree_name();
+        let worktree_names = self.inner.worktrees().map_err(Error::ReadWorktrees)?;
+        for name in worktree_names.iter().flatten() {
+            if Some(name) == current_worktree_name.as_deref() {
+                continue;
+            }
+            let worktree = self
+                .inner
+                .find_worktree(name)
+                .map_err(Error::ReadWorktrees)?;
+            // Skip worktrees whose directory has been deleted without running
+            // `git worktree prune`.
+            if worktree.validate().is_err() {
+                continue;
+            }
+            let repo = git2::Repository::open_from_worktree(&worktree).map_err(Error::OpenRepo)?;
+            let repo = Repo::from_inner(repo);
+            result.push(WorktreeInfo {
+                name: name.to_owned(),
+                path: worktree.path().to_owned(),
+                head_oid: repo.get_head_info()?.oid,
+            });
+        }
+        Ok(result)
+    }
+
     /// Get the configuration object for the repository.
     ///
+    /// Values for `branchless.*` keys are looked up in the Git configuration
+    /// (including `git -c` overrides), then in the per-repository TOML config
+    /// file (see `get_toml_config_path`), and finally in the per-user TOML
+    /// config file.
+    ///
     /// **Warning**: This object should only be used for read operations. Write
     /// operations should go to the `config` file under the `.git/branchless`
     /// directory.
     #[instrument]
     pub fn get_readonly_config(&self) -> Result<impl ConfigRead> {
         let config = self.inner.config().map_err(Error::ReadConfig)?;
-        Ok(Config::from(config))
+        let file_layers = self.config_file_layers.get_or_try_init(|| {
+            let paths: Vec<PathBuf> = std::iter::once(self.get_toml_config_path())
+                .chain(get_user_config_file_path())
+                .collect();
+            read_config_files(&paths)
+        })?;
+        Ok(Config::from(config)
+            .with_file_layers(file_layers.clone())
+            .with_env_overrides())
+    }
+
+    /// Get the directory where `git-branchless` stores its state. This is
+    /// shared between all worktrees of the repository, so that they share the
+    /// same event log and configuration.
+    #[instrument]
+    pub fn get_branchless_dir(&self) -> PathBuf {
+        self.get_common_path().join("branchless")
+    }
+
+    /// Get the per-repository TOML config file for `git-branchless`. Unlike
+    /// the file returned by `get_config_path`, this file is only written by
+    /// the user.
+    #[instrument]
+    pub fn get_toml_config_path(&self) -> PathBuf {
+        self.get_branchless_dir().join("config.toml")
     }
 
     /// Get the file where git-branchless-specific Git configuration is stored.
     #[instrument]
     pub fn get_config_path(&self) -> PathBuf {
-        self.get_path().join("branchless").join("config")
+        self.get_branchless_dir().join("config")
+    }
+
+    /// Get the commits at the boundary of a shallow clone, whose parents
+    /// aren't available locally. Git treats them as root commits. This is
+    /// empty if the repository isn't a shallow clone.
+    #[instrument]
+    pub fn get_shallow_commit_oids(&self) -> Result<Vec<NonZeroOid>> {
+        let path = self.get_common_path().join("shallow");
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(Error::ReadShallowFile { source, path }),
+        };
+        let mut result = Vec::new();
+        for line in contents.lines() {
+            match line.trim().parse() {
+                Ok(MaybeZeroOid::NonZero(oid)) => result.push(oid),
+                Ok(MaybeZeroOid::Zero) | Err(_) => {
+                    warn!(?line, "Could not parse line in shallow file");
+                }
+            }
+        }
+        Ok(result)
     }
 
     /// Get the directory where the DAG for the repository is stored.
     #[instrument]
     pub fn get_dag_dir(&self) -> PathBuf {
-        self.get_path().join("branchless").join("dag")
+        self.get_branchless_dir().join("dag")
+    }
+
+    /// Get the file recording the shallow clone boundary as of when the DAG
+    /// was built. See [`Repo::get_shallow_commit_oids`].
+    #[instrument]
+    pub fn get_dag_shallow_commits_path(&self) -> PathBuf {
+        self.get_branchless_dir().join("dag-shallow-commits")
     }
 
     /// Get the directory to store man-pages. Note that this is the `man`
@@ -578,7 +854,7 @@ impl Repo {
     /// go into the `man/man1` directory to be found by `man`.
     #[instrument]
     pub fn get_man_dir(&self) -> PathBuf {
-        self.get_path().join("branchless").join("man")
+        self.get_branchless_dir().join("man")
     }
 
     /// Get a directory suitable for storing temporary files.
@@ -589,25 +865,84 @@ impl Repo {
     /// <https://github.com/arxanas/git-branchless/discussions/120>.
     #[instrument]
     pub fn get_tempfile_dir(&self) -> PathBuf {
-        self.get_path().join("branchless").join("tmp")
+        self.get_branchless_dir().join("tmp")
+    }
+
+    /// Get the path to the SQLite database for this repository. This can be
+    /// overridden with the [`GIT_BRANCHLESS_DB_PATH`] environment variable.
+    #[instrument]
+    pub fn get_db_path(&self) -> PathBuf {
+        match std::env::var_os(GIT_BRANCHLESS_DB_PATH) {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => self.get_branchless_dir().join("db.sqlite3"),
+        }
     }
 
     /// Get the connection to the SQLite database for this repository.
     #[instrument]
     pub fn get_db_conn(&self) -> Result<rusqlite::Connection> {
-        let dir = self.get_path().join("branchless");
-        std::fs::create_dir_all(&dir).map_err(|err| Error::CreateBranchlessDir {
+        let path = self.get_db_path();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|err| Error::CreateBranchlessDir {
+                source: err,
+                path: dir.to_owned(),
+            })?;
+        }
+        let conn = rusqlite::Connection::open(&path).map_err(|err| Error::OpenDatabase {
             source: err,
-            path: dir.clone(),
+            path: path.clone(),
         })?;
-        let path = dir.join("db.sqlite3");
-        let conn = rusqlite::Connection::open(&path).map_err(|err| Error::OpenDatabase {
+        // Hooks may fire while another process (such as a long-running `gc`)
+        // is writing to the database, so wait for it rather than failing.
+        conn.busy_timeout(DB_BUSY_TIMEOUT)
+            .map_err(|err| Error::OpenDatabase {
+                source: err,
+                path: path.clone(),
+            })?;
+        // In WAL mode, readers don't block writers (and vice-versa), so
+        // concurrent hook invocations rarely have to wait for each other. The
+        // journal mode is stored in the database file, so this only has an
+        // effect the first time. It can fail on filesystems which don't support
+        // shared memory, in which case the default journal mode still works.
+        if let Err(err) =
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+        {
+            warn!(?err, ?path, "Could not enable WAL mode for database");
+        }
+        Ok(conn)
+    }
+
+    /// Get a read-only connection to the SQLite database for this repository,
+    /// such as for tools which inspect the event log without modifying it.
+    /// Fails if the database doesn't exist yet.
+    #[instrument]
+    pub fn get_db_conn_read_only(&self) -> Result<rusqlite::Connection> {
+        let path = self.get_db_path();
+        let conn = rusqlite::Connection::open_with_flags(
+            &path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY
+                | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | rusqlite::OpenFlags::SQLITE_OPEN_URI,
+        )
+        .map_err(|err| Error::OpenDatabase {
             source: err,
             path: path.clone(),
         })?;
+        conn.busy_timeout(DB_BUSY_TIMEOUT)
+            .map_err(|err| Error::OpenDatabase {
+                source: err,
+                path: path.clone(),
+            })?;
         Ok(conn)
     }
 
+    /// Get the path to the lock file which is held while `gc` runs, so that
+    /// only one garbage collection runs at a time.
+    #[instrument]
+    pub fn get_gc_lock_path(&self) -> PathBuf {
+        self.get_branchless_dir().join("gc.lock")
+    }
+
     /// Get a snapshot of information about a given reference.
     #[instrument]
     pub fn resolve_reference(&self, reference: &Reference) -> Result<ResolvedReferenceInfo> {
@@ -650,6 +985,42 @@ impl Repo {
         Ok(())
     }
 
+    /// Check out the given commit into the index and working copy, and then
+    /// point `HEAD` at `branch_name` if provided, or directly at the commit
+    /// otherwise. `HEAD`'s reflog is updated in the same way as by `git
+    /// checkout`.
+    ///
+    /// Like `git checkout`, this fails without changing anything if it would
+    /// overwrite uncommitted changes. Local changes to paths which don't
+    /// differ between `HEAD` and the commit are carried over.
+    ///
+    /// `progress` is called with the number of paths checked out so far and
+    /// the total number of paths to check out.
+    #[instrument(skip(progress))]
+    pub fn check_out_commit(
+        &self,
+        oid: NonZeroOid,
+        branch_name: Option<&ReferenceName>,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<()> {
+        let commit = self.find_commit_or_fail(oid)?;
+        let mut checkout_builder = git2::build::CheckoutBuilder::new();
+        checkout_builder.safe();
+        checkout_builder.progress(|_path, current, total| progress(current, total));
+        self.inner
+            .checkout_tree(commit.inner.as_object(), Some(&mut checkout_builder))
+            .map_err(|err| Error::CheckOutCommit { source: err, oid })?;
+
+        match branch_name {
+            Some(branch_name) => self
+                .inner
+                .set_head(branch_name.as_str())
+                .map_err(|err| Error::CheckOutCommit { source: err, oid })?,
+            None => self.set_head(oid)?,
+        }
+        Ok(())
+    }
+
     /// Detach `HEAD` by making it point directly to its current OID, rather
     /// than to a branch. If `HEAD` is unborn, logs a warning.
     #[instrument]
@@ -698,6 +1069,12 @@ impl Repo {
         }
     }
 
+    /// Determine if `git am` is currently applying a series of patches.
+    #[instrument]
+    pub fn is_apply_mailbox_underway(&self) -> bool {
+        self.inner.state() == git2::RepositoryState::ApplyMailbox
+    }
+
     /// Get the type current multi-step operation (such as `rebase` or
     /// `cherry-pick`) which is underway. Returns `None` if there is no such
     /// operation.
@@ -842,6 +1219,148 @@ impl Repo {
         Ok(Some(changed_paths))
     }
 
+    /// Get the name of the remote from which missing objects can be fetched,
+    /// if this repository is a partial clone (such as one made with `git clone
+    /// --filter=blob:none`).
+    #[instrument]
+    pub fn get_promisor_remote_name(&self) -> Result<Option<String>> {
+        let config = self.get_readonly_config()?;
+        if let Some(remote_name) = config
+            .get::<String, _>("extensions.partialClone")
+            .map_err(Error::ReadPartialCloneConfig)?
+        {
+            return Ok(Some(remote_name));
+        }
+        for remote_name in self.get_all_remote_names()? {
+            if config
+                .get_or(format!("remote.{remote_name}.promisor"), false)
+                .map_err(Error::ReadPartialCloneConfig)?
+            {
+                return Ok(Some(remote_name));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get the blobs which applying the given commits' patches on top of
+    /// `target_commits` would need to read, i.e. the blobs at the paths
+    /// touched by each commit in the commit itself, its parent, and each
+    /// target commit.
+    #[instrument]
+    fn get_blobs_for_patches(
+        &self,
+        commits: &[Commit],
+        target_commits: &[Commit],
+    ) -> Result<HashSet<NonZeroOid>> {
+        let target_trees: Vec<Tree> = target_commits
+            .iter()
+            .map(|commit| commit.get_tree())
+            .try_collect()?;
+        let mut result = HashSet::new();
+        for commit in commits {
+            let changed_paths = match self.get_paths_touched_by_commit(commit)? {
+                Some(changed_paths) => changed_paths,
+                None => continue,
+            };
+            let parents = commit.get_parents();
+            let mut trees = vec![commit.get_tree()?];
+            for parent in parents.iter() {
+                trees.push(parent.get_tree()?);
+            }
+            for tree in trees.iter().chain(target_trees.iter()) {
+                for path in changed_paths.iter() {
+                    if let Some(entry) = tree.get_path(path).map_err(Error::ReadTreeEntry)? {
+                        match entry.get_filemode() {
+                            FileMode::Blob | FileMode::BlobExecutable | FileMode::Link => {
+                                result.insert(entry.get_oid());
+                            }
+                            FileMode::Unreadable | FileMode::Tree | FileMode::Commit => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// If this repository is a partial clone, fetch whichever of the given
+    /// objects are missing from the promisor remote in a single batch. Git
+    /// fetches missing objects on demand, but libgit2 doesn't, so this should
+    /// be called before operations which read objects that might be missing.
+    /// Returns the number of objects which were fetched.
+    #[instrument(skip(oids))]
+    pub fn fetch_missing_objects(
+        &self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        oids: impl IntoIterator<Item = NonZeroOid>,
+    ) -> Result<usize> {
+        let remote_name = match self.get_promisor_remote_name()? {
+            Some(remote_name) => remote_name,
+            None => return Ok(0),
+        };
+        let odb = self.inner.odb().map_err(Error::ReadObjectDatabase)?;
+        let missing_oids = oids
+            .into_iter()
+            .filter(|oid| !odb.exists(oid.inner))
+            .sorted()
+            .collect_vec();
+        if missing_oids.is_empty() {
+            return Ok(0);
+        }
+
+        let (_effects, progress) = effects.start_operation(OperationType::FetchMissingObjects);
+        progress.notify_progress(0, missing_oids.len());
+        // These are the same arguments that Git itself uses to fetch missing
+        // objects from a promisor remote.
+        let stdin = missing_oids
+            .iter()
+            .map(|oid| format!("{oid}\n"))
+            .collect::<String>();
+        let GitRunResult { .. } = git_run_info
+            .run_silent(
+                self,
+                None,
+                &[
+                    "-c",
+                    "fetch.negotiationAlgorithm=noop",
+                    "fetch",
+                    &remote_name,
+                    "--no-tags",
+                    "--no-write-fetch-head",
+                    "--recurse-submodules=no",
+                    "--filter=blob:none",
+                    "--stdin",
+                ],
+                GitRunOpts {
+                    treat_git_failure_as_error: true,
+                    stdin: Some(stdin.into_bytes()),
+                },
+            )
+            .map_err(Error::FetchMissingObjects)?;
+        progress.notify_progress(missing_oids.len(), missing_oids.len());
+        Ok(missing_oids.len())
+    }
+
+    /// If this repository is a partial clone, fetch any blobs which applying
+    /// the given commits' patches on top of `target_commits` (or computing
+    /// their patch IDs) would need, but which haven't been fetched yet.
+    /// Returns the number of objects which were fetched.
+    #[instrument]
+    pub fn fetch_missing_blobs_for_patches(
+        &self,
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        commits: &[Commit],
+        target_commits: &[Commit],
+    ) -> Result<usize> {
+        if self.get_promisor_remote_name()?.is_none() {
+            return Ok(0);
+        }
+        let blob_oids = self.get_blobs_for_patches(commits, target_commits)?;
+        self.fetch_missing_objects(effects, git_run_info, blob_oids)
+    }
+
     /// Get the patch ID for this commit.
     #[instrument]
     pub fn get_patch_id(&self, effects: &Effects, commit: &Commit) -> Result<Option<PatchId>> {
@@ -856,6 +1375,26 @@ impl Repo {
         Ok(Some(PatchId { patch_id }))
     }
 
+    /// Get the patch ID of the combined changes between two commits, as if the
+    /// commits in between had been squashed into a single commit on top of
+    /// `old_commit`.
+    #[instrument]
+    pub fn get_patch_id_between_commits(
+        &self,
+        effects: &Effects,
+        old_commit: &Commit,
+        new_commit: &Commit,
+    ) -> Result<PatchId> {
+        let old_tree = old_commit.get_tree()?;
+        let new_tree = new_commit.get_tree()?;
+        let diff = self.get_diff_between_trees(effects, Some(&old_tree), &new_tree, 3)?;
+        let patch_id = {
+            let (_effects, _progress) = effects.start_operation(OperationType::CalculatePatchId);
+            diff.inner.patchid(None).map_err(Error::GetPatchId)?
+        };
+        Ok(PatchId { patch_id })
+    }
+
     /// Attempt to parse the user-provided object descriptor.
     pub fn revparse_single_commit(&self, spec: &str) -> Result<Option<Commit>> {
         if spec.ends_with('@') && spec.len() > 1 {
@@ -1038,6 +1577,24 @@ impl Repo {
         Ok(all_branches)
     }
 
+    /// Get all remote-tracking branches in the repository.
+    #[instrument]
+    pub fn get_all_remote_branches(&self) -> Result<Vec<Branch<'_>>> {
+        let mut all_branches = Vec::new();
+        for branch in self
+            .inner
+            .branches(Some(git2::BranchType::Remote))
+            .map_err(Error::GetBranches)?
+        {
+            let (branch, _branch_type) = branch.map_err(Error::ReadBranch)?;
+            all_branches.push(Branch {
+                repo: self,
+                inner: branch,
+            });
+        }
+        Ok(all_branches)
+    }
+
     /// Look up the branch with the given name. Returns `None` if not found.
     #[instrument]
     pub fn find_branch(&self, name: &str, branch_type: BranchType) -> Result<Option<Branch>> {
@@ -1143,6 +1700,73 @@ impl Repo {
         Ok(Some(blob))
     }
 
+    /// Open the repository of the submodule at the provided path in the
+    /// working copy. Returns `None` if the submodule isn't checked out.
+    fn open_submodule(&self, path: &Path) -> Result<Option<git2::Repository>> {
+        let path = match self.get_working_copy_path() {
+            Some(working_copy_path) => working_copy_path.join(path),
+            None => return Ok(None),
+        };
+        match git2::Repository::open(path) {
+            Ok(submodule_repo) => Ok(Some(submodule_repo)),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(Error::OpenRepo(err)),
+        }
+    }
+
+    /// Get the commit checked out in the submodule at the provided path in the
+    /// working copy, which is what Git records for the submodule when
+    /// committing it. Returns `None` if the submodule isn't checked out or
+    /// doesn't have a commit checked out.
+    #[instrument]
+    pub fn get_submodule_head_oid(&self, path: &Path) -> Result<Option<NonZeroOid>> {
+        let submodule_repo = match self.open_submodule(path)? {
+            Some(submodule_repo) => submodule_repo,
+            None => return Ok(None),
+        };
+        let head_oid = match submodule_repo.head() {
+            Ok(head) => head.target(),
+            Err(err)
+                if err.code() == git2::ErrorCode::UnbornBranch
+                    || err.code() == git2::ErrorCode::NotFound =>
+            {
+                None
+            }
+            Err(err) => return Err(Error::ReadReference(err)),
+        };
+        Ok(head_oid.map(make_non_zero_oid))
+    }
+
+    /// Get the paths of the submodules listed in the `.gitmodules` file of the
+    /// provided tree. Returns an empty set if there is no `.gitmodules` file.
+    #[instrument]
+    pub fn get_gitmodules_paths(&self, tree: &Tree) -> Result<HashSet<PathBuf>> {
+        let entry = match tree
+            .get_path(Path::new(".gitmodules"))
+            .map_err(Error::ReadTreeEntry)?
+        {
+            Some(entry) => entry,
+            None => return Ok(Default::default()),
+        };
+        let blob = self.find_blob_or_fail(entry.get_oid())?;
+
+        // `libgit2` can only parse config files from disk.
+        let gitmodules_file = tempfile::NamedTempFile::new().map_err(Error::Io)?;
+        std::fs::write(gitmodules_file.path(), blob.get_content()).map_err(Error::Io)?;
+        let config = git2::Config::open(gitmodules_file.path()).map_err(Error::ReadConfig)?;
+        let mut entries = config
+            .entries(Some(r"submodule\..*\.path"))
+            .map_err(Error::ReadConfig)?;
+        let mut result = HashSet::new();
+        while let Some(entry) = entries.next() {
+            let entry = entry.map_err(Error::ReadConfig)?;
+            if let Some(path) = entry.value() {
+                result.insert(PathBuf::from(path));
+            }
+        }
+        Ok(result)
+    }
+
     /// Create a blob corresponding to the provided byte slice.
     #[instrument]
     pub fn create_blob_from_contents(&self, contents: &[u8]) -> Result<NonZeroOid> {
@@ -1150,6 +1774,15 @@ impl Repo {
         Ok(make_non_zero_oid(oid))
     }
 
+    /// Compute the OID that a blob with the provided contents would have,
+    /// without writing it to the object database.
+    #[instrument]
+    pub fn hash_blob_contents(&self, contents: &[u8]) -> Result<NonZeroOid> {
+        let oid =
+            git2::Oid::hash_object(git2::ObjectType::Blob, contents).map_err(Error::HashBlob)?;
+        Ok(make_non_zero_oid(oid))
+    }
+
     /// Create a new commit.
     #[instrument]
     pub fn create_commit(
@@ -1207,6 +1840,13 @@ impl Repo {
     /// involved indexes by filtering out any unchanged entries from the input
     /// trees, then call into `libgit2`, then add back the unchanged entries to
     /// the output tree.
+    ///
+    /// If none of the paths touched by the patch differ between its parent
+    /// and the target commit, then the patch applies without merging, and its
+    /// entries are copied directly into the target tree. The resulting trees
+    /// are also cached for the lifetime of this `Repo`, so that applying the
+    /// same patch to the same tree again (such as when retrying a rebase
+    /// plan) doesn't repeat the work.
     #[instrument]
     pub fn cherry_pick_fast<'repo>(
         &'repo self,
@@ -1237,6 +1877,49 @@ impl Repo {
             })?
             .into_iter()
             .collect_vec();
+
+        let patch_tree = patch_commit.get_tree()?;
+        let patch_parent = patch_commit.get_only_parent();
+        let patch_parent_tree = match &patch_parent {
+            Some(patch_parent) => Some(patch_parent.get_tree()?),
+            None => None,
+        };
+        let target_tree = target_commit.get_tree()?;
+        let cache_key = (
+            patch_parent_tree.as_ref().map(|tree| tree.get_oid()),
+            patch_tree.get_oid(),
+            target_tree.get_oid(),
+        );
+        let cached_tree_oid = self
+            .cherry_pick_fast_cache
+            .borrow()
+            .get(&cache_key)
+            .copied();
+        if let Some(tree_oid) = cached_tree_oid {
+            return Ok(self.find_tree_or_fail(tree_oid)?);
+        }
+
+        if self.can_apply_without_merging(
+            patch_parent_tree.as_ref(),
+            &target_tree,
+            &changed_pathbufs,
+        )? {
+            let mut entries = HashMap::new();
+            for changed_path in changed_pathbufs {
+                let entry = patch_tree
+                    .get_path(&changed_path)
+                    .map_err(Error::ReadTreeEntry)?
+                    .map(|entry| (entry.get_oid(), entry.get_filemode()));
+                entries.insert(changed_path, entry);
+            }
+            let rebased_tree_oid = hydrate_tree(self, Some(&target_tree), entries)
+                .map_err(CherryPickFastError::HydrateTree)?;
+            self.cherry_pick_fast_cache
+                .borrow_mut()
+                .insert(cache_key, rebased_tree_oid);
+            return Ok(self.find_tree_or_fail(rebased_tree_oid)?);
+        }
+
         let changed_paths = changed_pathbufs.iter().map(PathBuf::borrow).collect_vec();
 
         let dehydrated_patch_commit =
@@ -1247,60 +1930,35 @@ impl Repo {
         let rebased_index =
             self.cherry_pick_commit(&dehydrated_patch_commit, &dehydrated_target_commit, 0)?;
         let rebased_tree = {
-            if rebased_index.has_conflicts() {
-                let conflicting_paths = {
-                    let mut result = HashSet::new();
-                    for conflict in rebased_index.inner.conflicts().map_err(|err| {
-                        CherryPickFastError::GetConflicts {
-                            source: err,
-                            commit: patch_commit.get_oid(),
-                            onto: target_commit.get_oid(),
-                        }
-                    })? {
-                        let conflict =
-                            conflict.map_err(|err| CherryPickFastError::GetConflicts {
-                                source: err,
-                                commit: patch_commit.get_oid(),
-                                onto: target_commit.get_oid(),
-                            })?;
-                        if let Some(ancestor) = conflict.ancestor {
-                            result.insert(ancestor.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "ancestor",
-                                }
-                            })?);
-                        }
-                        if let Some(our) = conflict.our {
-                            result.insert(our.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "our",
-                                }
-                            })?);
-                        }
-                        if let Some(their) = conflict.their {
-                            result.insert(their.path.into_path_buf().map_err(|err| {
-                                CherryPickFastError::DecodePath {
-                                    source: err,
-                                    item: "their",
-                                }
-                            })?);
-                        }
-                    }
-                    result
-                };
+            let submodule_resolutions = if rebased_index.has_conflicts() {
+                let (conflicts, submodule_resolutions) = self.get_unresolved_index_conflicts(
+                    &rebased_index,
+                    patch_commit,
+                    target_commit,
+                )?;
+                let conflicting_paths: HashSet<PathBuf> = conflicts
+                    .into_iter()
+                    .map(|conflict| conflict.path)
+                    .collect();
 
-                if conflicting_paths.is_empty() {
+                if conflicting_paths.is_empty() && submodule_resolutions.is_empty() {
                     warn!("BUG: A merge conflict was detected, but there were no entries in `conflicting_paths`. Maybe the wrong index entry was used?")
                 }
 
-                return Err(CherryPickFastError::MergeConflict { conflicting_paths });
-            }
+                if !conflicting_paths.is_empty() || submodule_resolutions.is_empty() {
+                    return Err(CherryPickFastError::MergeConflict { conflicting_paths });
+                }
+                submodule_resolutions
+            } else {
+                HashMap::new()
+            };
             let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> =
                 changed_pathbufs
                     .into_iter()
                     .map(|changed_path| {
+                        if let Some(resolution) = submodule_resolutions.get(&changed_path) {
+                            return (changed_path, Some(*resolution));
+                        }
                         let value = match rebased_index.get_entry(&changed_path) {
                             Some(IndexEntry {
                                 oid: MaybeZeroOid::Zero,
@@ -1324,14 +1982,290 @@ impl Repo {
                         (changed_path, value)
                     })
                     .collect();
-            let rebased_tree_oid =
-                hydrate_tree(self, Some(&target_commit.get_tree()?), rebased_entries)
-                    .map_err(CherryPickFastError::HydrateTree)?;
+            let rebased_tree_oid = hydrate_tree(self, Some(&target_tree), rebased_entries)
+                .map_err(CherryPickFastError::HydrateTree)?;
+            self.cherry_pick_fast_cache
+                .borrow_mut()
+                .insert(cache_key, rebased_tree_oid);
             self.find_tree_or_fail(rebased_tree_oid)?
         };
         Ok(rebased_tree)
     }
 
+    /// Get the conflicting entries in an index produced by cherry-picking
+    /// `patch_commit` onto `target_commit`.
+    fn get_index_conflicts(
+        &self,
+        index: &Index,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+    ) -> std::result::Result<Vec<MergeConflictEntry>, CherryPickFastError> {
+        let map_err = |err| CherryPickFastError::GetConflicts {
+            source: err,
+            commit: patch_commit.get_oid(),
+            onto: target_commit.get_oid(),
+        };
+        // The path of the entry, and its OID and file mode, if valid.
+        type ConflictSide = Option<(PathBuf, Option<(NonZeroOid, FileMode)>)>;
+        let convert_entry = |entry: Option<git2::IndexEntry>,
+                             item: &'static str|
+         -> std::result::Result<ConflictSide, CherryPickFastError> {
+            let entry = match entry {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            let oid = match MaybeZeroOid::from(entry.id) {
+                MaybeZeroOid::NonZero(oid) => Some(oid),
+                MaybeZeroOid::Zero => None,
+            };
+            // `libgit2` uses u32 for file modes in index entries, but i32 for
+            // file modes in tree entries for some reason.
+            let file_mode = i32::try_from(entry.mode).ok().map(FileMode::from);
+            let path = entry
+                .path
+                .into_path_buf()
+                .map_err(|err| CherryPickFastError::DecodePath { source: err, item })?;
+            Ok(Some((path, oid.zip(file_mode))))
+        };
+
+        let mut result = Vec::new();
+        for conflict in index.inner.conflicts().map_err(map_err)? {
+            let conflict = conflict.map_err(map_err)?;
+            let ancestor = convert_entry(conflict.ancestor, "ancestor")?;
+            let ours = convert_entry(conflict.our, "our")?;
+            let theirs = convert_entry(conflict.their, "their")?;
+            let path = match [&ancestor, &ours, &theirs]
+                .into_iter()
+                .find_map(|entry| entry.as_ref().map(|(path, _)| path.clone()))
+            {
+                Some(path) => path,
+                None => continue,
+            };
+            result.push(MergeConflictEntry {
+                path,
+                ancestor: ancestor.and_then(|(_path, entry)| entry),
+                ours: ours.and_then(|(_path, entry)| entry),
+                theirs: theirs.and_then(|(_path, entry)| entry),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Like `get_index_conflicts`, but merge conflicting submodules the same
+    /// way as Git, by fast-forwarding them when possible. Returns the
+    /// conflicts which remain and the entries for the merged submodules.
+    fn get_unresolved_index_conflicts(
+        &self,
+        index: &Index,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+    ) -> std::result::Result<(Vec<MergeConflictEntry>, SubmoduleResolutions), CherryPickFastError>
+    {
+        let mut unresolved_conflicts = Vec::new();
+        let mut submodule_resolutions = SubmoduleResolutions::new();
+        for conflict in self.get_index_conflicts(index, patch_commit, target_commit)? {
+            match self.merge_submodule(&conflict)? {
+                Some(oid) => {
+                    submodule_resolutions.insert(conflict.path, (oid, FileMode::Commit));
+                }
+                None => unresolved_conflicts.push(conflict),
+            }
+        }
+        Ok((unresolved_conflicts, submodule_resolutions))
+    }
+
+    /// If both sides of the conflict moved a submodule forward from the
+    /// common ancestor, and one side's commit contains the other's, then
+    /// return that commit, as Git does when merging submodules. Returns
+    /// `None` if the conflict isn't for a submodule, or if it can't be merged
+    /// this way (such as when the submodule isn't checked out, so its commits
+    /// can't be compared).
+    fn merge_submodule(&self, conflict: &MergeConflictEntry) -> Result<Option<NonZeroOid>> {
+        let (ancestor_oid, our_oid, their_oid) =
+            match (conflict.ancestor, conflict.ours, conflict.theirs) {
+                (
+                    Some((ancestor_oid, FileMode::Commit)),
+                    Some((our_oid, FileMode::Commit)),
+                    Some((their_oid, FileMode::Commit)),
+                ) => (ancestor_oid, our_oid, their_oid),
+                _ => return Ok(None),
+            };
+        let submodule_repo = match self.open_submodule(&conflict.path)? {
+            Some(submodule_repo) => submodule_repo,
+            None => return Ok(None),
+        };
+        let contains = |descendant: NonZeroOid, ancestor: NonZeroOid| -> Result<bool> {
+            if descendant == ancestor {
+                return Ok(true);
+            }
+            match submodule_repo.graph_descendant_of(descendant.inner, ancestor.inner) {
+                Ok(is_descendant) => Ok(is_descendant),
+                // One of the commits hasn't been fetched into the submodule.
+                Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+                Err(err) => Err(Error::MergeSubmodule {
+                    source: err,
+                    path: conflict.path.clone(),
+                }),
+            }
+        };
+
+        if !contains(our_oid, ancestor_oid)? || !contains(their_oid, ancestor_oid)? {
+            Ok(None)
+        } else if contains(their_oid, our_oid)? {
+            Ok(Some(their_oid))
+        } else if contains(our_oid, their_oid)? {
+            Ok(Some(our_oid))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Cherry-pick `patch_commit` onto `target_commit` in memory, as with
+    /// [`Repo::cherry_pick_fast`], and return the entries for any paths which
+    /// couldn't be merged automatically. Returns an empty list if the patch
+    /// applies cleanly.
+    #[instrument]
+    pub fn get_cherry_pick_conflicts(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+    ) -> std::result::Result<Vec<MergeConflictEntry>, CherryPickFastError> {
+        let (rebased_index, _changed_paths) =
+            self.cherry_pick_dehydrated(patch_commit, target_commit)?;
+        if !rebased_index.has_conflicts() {
+            return Ok(Vec::new());
+        }
+        let (conflicts, _submodule_resolutions) =
+            self.get_unresolved_index_conflicts(&rebased_index, patch_commit, target_commit)?;
+        Ok(conflicts)
+    }
+
+    /// Cherry-pick `patch_commit` onto `target_commit` in memory, as with
+    /// [`Repo::cherry_pick_fast`], but use the given entries for paths which
+    /// couldn't be merged automatically. Fails with
+    /// [`CherryPickFastError::MergeConflict`] if any conflicting path doesn't
+    /// have an entry in `resolutions`. A path is deleted if its entry is
+    /// `None`.
+    #[instrument]
+    pub fn cherry_pick_with_resolutions<'repo>(
+        &'repo self,
+        patch_commit: &'repo Commit,
+        target_commit: &'repo Commit,
+        resolutions: &HashMap<PathBuf, Option<(NonZeroOid, FileMode)>>,
+    ) -> std::result::Result<Tree<'repo>, CherryPickFastError> {
+        let (rebased_index, changed_pathbufs) =
+            self.cherry_pick_dehydrated(patch_commit, target_commit)?;
+        let (conflicts, submodule_resolutions) =
+            self.get_unresolved_index_conflicts(&rebased_index, patch_commit, target_commit)?;
+        let unresolved_paths: HashSet<PathBuf> = conflicts
+            .into_iter()
+            .map(|conflict| conflict.path)
+            .filter(|path| !resolutions.contains_key(path))
+            .collect();
+        if !unresolved_paths.is_empty() {
+            return Err(CherryPickFastError::MergeConflict {
+                conflicting_paths: unresolved_paths,
+            });
+        }
+
+        let resolutions: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = submodule_resolutions
+            .into_iter()
+            .map(|(path, entry)| (path, Some(entry)))
+            .chain(resolutions.clone())
+            .collect();
+        let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = changed_pathbufs
+            .into_iter()
+            .map(|changed_path| {
+                let value = match resolutions.get(&changed_path) {
+                    Some(resolution) => *resolution,
+                    None => match rebased_index.get_entry(&changed_path) {
+                        Some(IndexEntry {
+                            oid: MaybeZeroOid::NonZero(oid),
+                            file_mode,
+                        }) => Some((oid, file_mode)),
+                        Some(IndexEntry {
+                            oid: MaybeZeroOid::Zero,
+                            file_mode: _,
+                        })
+                        | None => None,
+                    },
+                };
+                (changed_path, value)
+            })
+            .collect();
+        let target_tree = target_commit.get_tree()?;
+        let rebased_tree_oid = hydrate_tree(self, Some(&target_tree), rebased_entries)
+            .map_err(CherryPickFastError::HydrateTree)?;
+        Ok(self.find_tree_or_fail(rebased_tree_oid)?)
+    }
+
+    /// Cherry-pick `patch_commit` onto `target_commit` using only the paths
+    /// touched by the patch. Returns the resulting index, which may contain
+    /// conflicts, and the touched paths.
+    fn cherry_pick_dehydrated(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+    ) -> std::result::Result<(Index, Vec<PathBuf>), CherryPickFastError> {
+        let changed_pathbufs = self
+            .get_paths_touched_by_commit(patch_commit)?
+            .ok_or_else(|| CherryPickFastError::GetPatch {
+                commit: patch_commit.get_oid(),
+            })?
+            .into_iter()
+            .collect_vec();
+        let changed_paths = changed_pathbufs.iter().map(PathBuf::borrow).collect_vec();
+        let dehydrated_patch_commit =
+            self.dehydrate_commit(patch_commit, changed_paths.as_slice(), true)?;
+        let dehydrated_target_commit =
+            self.dehydrate_commit(target_commit, changed_paths.as_slice(), false)?;
+        let rebased_index =
+            self.cherry_pick_commit(&dehydrated_patch_commit, &dehydrated_target_commit, 0)?;
+        Ok((rebased_index, changed_pathbufs))
+    }
+
+    /// Determine whether a patch touching `changed_paths` can be applied to
+    /// `target_tree` by just copying its entries, because none of those paths
+    /// have changed since `patch_parent_tree`. If the patch adds a path, then
+    /// the target tree also must not have a file where the patch expects a
+    /// directory, since that would be a conflict.
+    fn can_apply_without_merging(
+        &self,
+        patch_parent_tree: Option<&Tree>,
+        target_tree: &Tree,
+        changed_paths: &[PathBuf],
+    ) -> Result<bool> {
+        let get_entry = |tree: &Tree, path: &Path| -> Result<Option<(NonZeroOid, FileMode)>> {
+            let entry = tree.get_path(path).map_err(Error::ReadTreeEntry)?;
+            Ok(entry.map(|entry| (entry.get_oid(), entry.get_filemode())))
+        };
+
+        for changed_path in changed_paths {
+            let parent_entry = match patch_parent_tree {
+                Some(patch_parent_tree) => get_entry(patch_parent_tree, changed_path)?,
+                None => None,
+            };
+            let target_entry = get_entry(target_tree, changed_path)?;
+            if parent_entry != target_entry {
+                return Ok(false);
+            }
+
+            if target_entry.is_none() {
+                for ancestor in changed_path.ancestors().skip(1) {
+                    if ancestor.as_os_str().is_empty() {
+                        break;
+                    }
+                    match get_entry(target_tree, ancestor)? {
+                        None => {}
+                        Some((_oid, FileMode::Tree)) => break,
+                        Some(_) => return Ok(false),
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
     #[instrument]
     fn dehydrate_commit(
         &self,
@@ -1452,22 +2386,36 @@ impl Repo {
         let repo_path = self
             .get_working_copy_path()
             .ok_or(Error::NoWorkingCopyPath)?;
+        // The path of a changed entry, and its new OID and file mode, if it
+        // wasn't deleted.
+        type NewTreeEntry = (PathBuf, Option<(NonZeroOid, FileMode)>);
         let new_tree_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = match opts {
             AmendFastOptions::FromWorkingCopy { status_entries } => status_entries
                 .iter()
                 .flat_map(|entry| {
-                    entry.paths().into_iter().map(
-                        move |path| -> Result<(PathBuf, Option<(NonZeroOid, FileMode)>)> {
+                    entry
+                        .paths()
+                        .into_iter()
+                        .map(move |path| -> Result<Option<NewTreeEntry>> {
+                            if entry.working_copy_file_mode == FileMode::Commit {
+                                // Submodules are recorded as the commit checked
+                                // out in them. If the submodule isn't checked
+                                // out, then keep its entry in the parent tree.
+                                return Ok(self
+                                    .get_submodule_head_oid(&path)?
+                                    .map(|oid| (path, Some((oid, FileMode::Commit)))));
+                            }
+
                             let file_path = &repo_path.join(&path);
                             // Try to create a new blob OID based on the current on-disk
                             // contents of the file in the working copy.
                             let entry = self
                                 .create_blob_from_path(file_path)?
                                 .map(|oid| (oid, entry.working_copy_file_mode));
-                            Ok((path, entry))
-                        },
-                    )
+                            Ok(Some((path, entry)))
+                        })
                 })
+                .filter_map(Result::transpose)
                 .collect::<Result<HashMap<_, _>>>()?,
             AmendFastOptions::FromIndex { paths } => {
                 let index = self.get_index()?;
@@ -1606,6 +2554,21 @@ pub struct PatchId {
     patch_id: git2::Oid,
 }
 
+impl std::fmt::Display for PatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.patch_id)
+    }
+}
+
+impl FromStr for PatchId {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let patch_id = git2::Oid::from_str(s)?;
+        Ok(PatchId { patch_id })
+    }
+}
+
 /// A timestamp as used in a [`Signature`].
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Time {
@@ -1707,6 +2670,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_cherry_pick_fast_unchanged_paths() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.run(&["checkout", "-b", "foo"])?;
+        let test1_oid = git.commit_file_with_contents("test1", 1, "test1 contents")?;
+        let initial_update_oid =
+            git.commit_file_with_contents("initial", 2, "foo initial contents")?;
+        git.run(&["checkout", "master"])?;
+        let initial2_oid =
+            git.commit_file_with_contents("initial", 2, "master initial contents")?;
+
+        let repo = git.get_repo()?;
+        let options = CherryPickFastOptions {
+            reuse_parent_tree_if_possible: false,
+        };
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let initial2_commit = repo.find_commit_or_fail(initial2_oid)?;
+        let tree = repo.cherry_pick_fast(&test1_commit, &initial2_commit, &options)?;
+        let initial2_tree = initial2_commit.get_tree()?;
+        let test1_tree = test1_commit.get_tree()?;
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("initial.txt"))?,
+            initial2_tree.get_oid_for_path(Path::new("initial.txt"))?,
+        );
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test1.txt"))?,
+            test1_tree.get_oid_for_path(Path::new("test1.txt"))?,
+        );
+
+        // The same result is returned when applying the patch again.
+        let tree_again = repo.cherry_pick_fast(&test1_commit, &initial2_commit, &options)?;
+        assert_eq!(tree_again.get_oid(), tree.get_oid());
+
+        // Paths changed on both sides are still merged (and conflict).
+        let initial_update_commit = repo.find_commit_or_fail(initial_update_oid)?;
+        match repo.cherry_pick_fast(&initial_update_commit, &initial2_commit, &options) {
+            Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+                assert_eq!(
+                    conflicting_paths,
+                    [PathBuf::from("initial.txt")].into_iter().collect()
+                );
+            }
+            other => panic!("Expected merge conflict, got: {:?}", other.map(|_| ())),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_amend_fast_from_index() -> eyre::Result<()> {
         let git = make_git()?;
@@ -1896,4 +2909,18 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_hash_blob_contents() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let repo = git.get_repo()?;
+        let contents = b"hash me\n";
+        let oid = repo.hash_blob_contents(contents)?;
+        assert!(repo.find_blob(oid)?.is_none());
+        assert_eq!(repo.create_blob_from_contents(contents)?, oid);
+
+        Ok(())
+    }
 }
@@ -15,9 +15,11 @@ use tracing::instrument;
 
 use crate::core::config::get_hooks_dir;
 use crate::core::effects::{Effects, OperationType};
-use crate::core::eventlog::{EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use crate::core::eventlog::{
+    EventTransactionId, BRANCHLESS_TRANSACTION_DB_ENV_VAR, BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
 use crate::git::repo::Repo;
-use crate::util::{get_sh, ExitCode};
+use crate::util::{get_sh, get_sh_path, ExitCode};
 
 /// Path to the `git` executable on disk to be executed.
 #[derive(Clone)]
@@ -142,6 +144,7 @@ impl GitRunInfo {
         command.envs(env.iter());
         if let Some(event_tx_id) = event_tx_id {
             command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+            command.env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR);
         }
         command.stdout(Stdio::piped());
         command.stderr(Stdio::piped());
@@ -217,6 +220,7 @@ impl GitRunInfo {
         command.envs(env.iter());
         if let Some(event_tx_id) = event_tx_id {
             command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+            command.env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR);
         }
 
         let mut child = command.spawn().wrap_err("Spawning Git subprocess")?;
@@ -279,6 +283,7 @@ impl GitRunInfo {
         command.envs(env.iter());
         if let Some(event_tx_id) = event_tx_id {
             command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+            command.env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR);
         }
 
         command.stdin(match stdin {
@@ -327,6 +332,7 @@ impl GitRunInfo {
     /// considerably more lightweight and reliable.
     ///
     /// Returns the stdout of the Git invocation.
+    #[instrument(skip(opts))]
     pub fn run_silent<S: AsRef<str> + std::fmt::Debug>(
         &self,
         repo: &Repo,
@@ -364,37 +370,35 @@ impl GitRunInfo {
             working_directory: _,
             env,
         } = self;
-        let path = {
-            let mut path_components: Vec<PathBuf> =
-                vec![std::fs::canonicalize(&hook_dir).wrap_err("Canonicalizing hook dir")?];
-            if let Some(path) = env.get(OsStr::new("PATH")) {
-                path_components.extend(std::env::split_paths(path));
-            }
-            std::env::join_paths(path_components).wrap_err("Joining path components")?
-        };
-
-        if hook_dir.join(hook_name).exists() {
+        // From `githooks(5)`: Before Git invokes a hook, it changes its working
+        // directory to either $GIT_DIR in a bare repository or the root of the
+        // working tree in a non-bare repository. A relative `core.hooksPath` is
+        // also resolved relative to that directory.
+        let hook_working_directory = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path());
+        let hook_path = hook_working_directory.join(&hook_dir).join(hook_name);
+        if hook_path.exists() {
+            let hook_path =
+                std::fs::canonicalize(&hook_path).wrap_err("Canonicalizing hook path")?;
             let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
-                // From `githooks(5)`: Before Git invokes a hook, it changes its
-                // working directory to either $GIT_DIR in a bare repository or the
-                // root of the working tree in a non-bare repository.
-                .current_dir(
-                    repo.get_working_copy_path()
-                        .unwrap_or_else(|| repo.get_path()),
-                )
+                .current_dir(hook_working_directory)
+                // Pass the hook path as `$0` rather than interpolating it into
+                // the script, so that it doesn't need to be quoted, even if it
+                // contains spaces (as on Windows, under `C:\Program Files`).
                 .arg("-c")
-                .arg(format!("{hook_name} \"$@\""))
-                .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
+                .arg(r#""$0" "$@""#)
+                .arg(get_sh_path(&hook_path))
                 .args(args)
                 .env_clear()
                 .envs(env.iter())
                 .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
-                .env("PATH", &path)
+                .env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR)
                 .stdin(Stdio::piped())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
                 .spawn()
-                .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
+                .wrap_err_with(|| format!("Invoking {} hook at {:?}", &hook_name, &hook_path))?;
 
             if let Some(stdin) = stdin {
                 child
@@ -440,6 +444,65 @@ impl GitRunInfo {
             stdin,
         )
     }
+
+    /// Run a user-provided shell command, such as a script configured with
+    /// `branchless.hooks.<event>`, in the root of the working copy, passing
+    /// `stdin` to it.
+    ///
+    /// The command doesn't join the current event transaction, since it runs
+    /// after the operation which triggered it has finished. The variables in
+    /// `extra_env` are set in its environment.
+    #[instrument]
+    pub fn run_user_command(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        command: &str,
+        extra_env: &[(&str, &str)],
+        stdin: BString,
+    ) -> eyre::Result<ExitCode> {
+        let GitRunInfo {
+            path_to_git: _,
+            working_directory: _,
+            env,
+        } = self;
+        let working_directory = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path());
+        let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
+            .current_dir(working_directory)
+            .arg("-c")
+            .arg(command)
+            .env_clear()
+            .envs(env.iter())
+            .envs(extra_env.iter().copied())
+            .env_remove(BRANCHLESS_TRANSACTION_ID_ENV_VAR)
+            .env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Invoking user command {command:?}"))?;
+
+        // The command may exit without reading all of its input, so ignore
+        // errors from writing to it.
+        let _ignored: std::io::Result<()> = child.stdin.take().unwrap().write_all(&stdin);
+
+        let stdout = child.stdout.take();
+        let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
+        let stderr = child.stderr.take();
+        let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+
+        let exit_status = child.wait().wrap_err("Waiting for user command to exit")?;
+        stdout_thread.join().unwrap();
+        stderr_thread.join().unwrap();
+
+        let exit_code: i32 = exit_status.code().unwrap_or(1);
+        let exit_code: isize = exit_code
+            .try_into()
+            .wrap_err("Converting exit code from i32 to isize")?;
+        Ok(ExitCode(exit_code))
+    }
 }
 
 #[cfg(test)]
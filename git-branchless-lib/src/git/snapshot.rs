@@ -342,6 +342,14 @@ branchless: automated working copy snapshot
                     // existence on disk because it's no longer being tracked by
                     // the index.
                     None
+                } else if file_mode == FileMode::Commit {
+                    // Submodules are recorded as the commit checked out in
+                    // them, rather than as a blob. If the submodule isn't
+                    // checked out, then keep its entry from `HEAD`.
+                    match repo.get_submodule_head_oid(&path)? {
+                        Some(oid) => Some((oid, file_mode)),
+                        None => continue,
+                    }
                 } else {
                     repo.create_blob_from_path(&path)?
                         .map(|blob_oid| (blob_oid, file_mode))
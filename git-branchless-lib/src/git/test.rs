@@ -77,7 +77,7 @@ pub struct SerializedTestResult {
 
 /// Get the directory where the results of running tests are stored.
 fn get_test_dir(repo: &Repo) -> PathBuf {
-    repo.get_path().join("branchless").join("test")
+    repo.get_branchless_dir().join("test")
 }
 
 /// Get the directory where the result of tests for a particular commit are
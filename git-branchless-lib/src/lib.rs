@@ -1,4 +1,64 @@
 //! Core functionality for git-branchless.
+//!
+//! The `git-branchless` commands are built on this library, and other tools
+//! can use it to work with branchless repositories directly rather than
+//! running the `git-branchless` executable and parsing its output. The main
+//! entry points are:
+//!
+//! - [`git`]: a wrapper around the Git repository, its objects and its
+//!   references. Start with [`git::Repo`].
+//! - [`core::eventlog`]: the event log, which records every change to the
+//!   commit graph so that it can be replayed and undone. It can also be opened
+//!   read-only, to inspect which commits are visible or obsolete.
+//! - [`core::dag`]: the commit graph, including which commits are visible,
+//!   public (on the main branch) or draft.
+//! - [`core::rewrite`]: planning and executing rebases, either in memory or on
+//!   disk.
+//! - [`core::formatting`] and [`core::effects`]: rendering output, and
+//!   reporting progress to the user or, as structured events, to the observers
+//!   registered with [`core::effects::Effects::with_observer`].
+//!
+//! With the `testing` feature, the `testing` module provides fixtures for
+//! creating temporary repositories, making commits and running `git-branchless`
+//! commands in them, for writing integration tests.
+//!
+//! The API isn't stable yet: it changes as the `git-branchless` commands need,
+//! and any release may include breaking changes, so depend on an exact
+//! version of this crate.
+//!
+//! For example, to list the draft commits (the commits which are visible but
+//! not yet on the main branch) of the repository in the current directory:
+//!
+//! ```no_run
+//! use branchless::core::dag::{commit_set_to_vec, Dag};
+//! use branchless::core::effects::Effects;
+//! use branchless::core::eventlog::{EventLogDb, EventReplayer};
+//! use branchless::core::formatting::Glyphs;
+//! use branchless::core::repo_ext::RepoExt;
+//! use branchless::git::Repo;
+//!
+//! # fn main() -> eyre::Result<()> {
+//! let effects = Effects::new(Glyphs::text());
+//! let repo = Repo::from_current_dir()?;
+//! let references_snapshot = repo.get_references_snapshot()?;
+//! let conn = repo.get_db_conn()?;
+//! let event_log_db = EventLogDb::new(&conn)?;
+//! let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+//! let event_cursor = event_replayer.make_default_cursor();
+//! let dag = Dag::open_and_sync(
+//!     &effects,
+//!     &repo,
+//!     &event_replayer,
+//!     event_cursor,
+//!     &references_snapshot,
+//! )?;
+//! for commit_oid in commit_set_to_vec(dag.query_draft_commits()?)? {
+//!     let commit = repo.find_commit_or_fail(commit_oid)?;
+//!     println!("{}", commit.get_summary()?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
 #![warn(missing_docs)]
 #![warn(
@@ -11,5 +71,6 @@
 
 pub mod core;
 pub mod git;
+#[cfg(any(test, feature = "testing"))]
 pub mod testing;
 pub mod util;
@@ -1,7 +1,36 @@
 //! Testing utilities.
 //!
 //! This is inside `src` rather than `tests` since we use this code in some unit
-//! tests.
+//! tests. It's only compiled with the `testing` feature, so that other crates
+//! which integrate with `git-branchless` can write their own tests against
+//! temporary repositories:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! git-branchless-lib = { version = "...", features = ["testing"] }
+//! ```
+//!
+//! Then create a repository with [`make_git`], make commits with
+//! [`Git::commit_file`], and run `git-branchless` commands with
+//! [`Git::branchless`]:
+//!
+//! ```no_run
+//! use branchless::testing::make_git;
+//!
+//! # fn main() -> eyre::Result<()> {
+//! let git = make_git()?;
+//! git.init_repo()?;
+//! git.commit_file("test1", 1)?;
+//! let (stdout, _stderr) = git.branchless("smartlog", &[])?;
+//! assert!(stdout.contains("create test1.txt"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The `TEST_GIT` and `TEST_GIT_EXEC_PATH` environment variables must be set to
+//! the Git executable and its exec path (see [`get_path_to_git`] and
+//! [`get_git_exec_path`]). The `git-branchless` executable is taken from the
+//! Cargo target directory if it was built there, and otherwise from `PATH`.
 
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
@@ -102,14 +131,15 @@ impl Git {
             .ok_or_else(|| eyre::eyre!("Could not convert path to Git to string"))?;
         let output = stdout.replace(path_to_git, "<git-executable>");
 
-        // NB: tests which run on Windows are unlikely to succeed due to this
-        // `canonicalize` call.
         let repo_path = std::fs::canonicalize(&self.repo_path)?;
-
         let repo_path = repo_path
             .to_str()
             .ok_or_else(|| eyre::eyre!("Could not convert repo path to string"))?;
+        // On Windows, the canonicalized path is a verbatim path, which isn't
+        // what gets printed. Git also prints paths with forward slashes.
+        let repo_path = repo_path.strip_prefix(r"\\?\").unwrap_or(repo_path);
         let output = output.replace(repo_path, "<repo-path>");
+        let output = output.replace(&repo_path.replace('\\', "/"), "<repo-path>");
 
         lazy_static! {
             // Simulate clearing the terminal line by searching for the
@@ -136,6 +166,20 @@ impl Git {
     /// Get the `PATH` environment variable to use for testing.
     pub fn get_path_for_env(&self) -> OsString {
         let cargo_bin_path = assert_cmd::cargo::cargo_bin("git-branchless");
+        // Crates other than `git-branchless` itself won't have built the
+        // executable into their target directory, so fall back to the one on
+        // `PATH`.
+        let cargo_bin_path = if cargo_bin_path.exists() {
+            cargo_bin_path
+        } else {
+            std::env::var_os("PATH")
+                .and_then(|path| {
+                    std::env::split_paths(&path)
+                        .map(|dir| dir.join(cargo_bin_path.file_name().unwrap()))
+                        .find(|path| path.exists())
+                })
+                .unwrap_or(cargo_bin_path)
+        };
         let branchless_path = cargo_bin_path
             .parent()
             .expect("Unable to find git-branchless path parent");
@@ -591,6 +635,191 @@ then you can only run tests in the main `git-branchless` and \
         std::fs::remove_file(event_log_path)?;
         Ok(())
     }
+
+    /// Populate the repository with a synthetic history of the provided shape,
+    /// for benchmarking purposes. The repository must have been initialized
+    /// without an initial commit (see `GitInitOptions::make_initial_commit`).
+    ///
+    /// The commits are written directly with `git fast-import`, so large
+    /// histories can be generated quickly. Afterwards, `master` is checked out
+    /// and configured as the main branch.
+    #[instrument]
+    pub fn generate_synthetic_history(
+        &self,
+        options: &SyntheticHistoryOptions,
+    ) -> eyre::Result<SyntheticHistory> {
+        let SyntheticHistoryOptions {
+            num_main_commits,
+            num_stacks,
+            stack_depth,
+            num_extra_refs,
+        } = *options;
+        if num_main_commits == 0 {
+            eyre::bail!("A synthetic history needs at least one main branch commit");
+        }
+
+        let mut stream = String::new();
+        let mut next_mark = 1;
+        let mut write_commit =
+            |stream: &mut String, ref_name: &str, from_mark: Option<usize>, name: &str| -> usize {
+                let mark = next_mark;
+                next_mark += 1;
+                let message = format!("create {name}.txt\n");
+                let contents = format!("{name} contents\n");
+                stream.push_str(&format!("commit {ref_name}\n"));
+                stream.push_str(&format!("mark :{mark}\n"));
+                stream.push_str(&format!(
+                    "committer {DUMMY_NAME} <{DUMMY_EMAIL}> {} +0000\n",
+                    SYNTHETIC_HISTORY_START_TIME + mark
+                ));
+                stream.push_str(&format!("data {}\n{message}", message.len()));
+                if let Some(from_mark) = from_mark {
+                    stream.push_str(&format!("from :{from_mark}\n"));
+                }
+                stream.push_str(&format!("M 100644 inline {name}.txt\n"));
+                stream.push_str(&format!("data {}\n{contents}\n", contents.len()));
+                mark
+            };
+
+        let main_marks = (0..num_main_commits)
+            .map(|i| write_commit(&mut stream, "refs/heads/master", None, &format!("main{i}")))
+            .collect_vec();
+        let stack_marks = (0..num_stacks)
+            .map(|stack_idx| {
+                // Spread the stacks out evenly over the main branch.
+                let base_mark = main_marks[stack_idx * num_main_commits / num_stacks];
+                let ref_name = format!("refs/heads/stack{stack_idx}");
+                let mut from_mark = base_mark;
+                (0..stack_depth)
+                    .map(|commit_idx| {
+                        from_mark = write_commit(
+                            &mut stream,
+                            &ref_name,
+                            Some(from_mark),
+                            &format!("stack{stack_idx}-{commit_idx}"),
+                        );
+                        from_mark
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        // Distribute the extra references over all of the stack commits, or
+        // over the main branch commits if there are no stack commits.
+        let ref_target_marks = stack_marks.iter().flatten().copied().collect_vec();
+        let ref_target_marks = if ref_target_marks.is_empty() {
+            &main_marks
+        } else {
+            &ref_target_marks
+        };
+        for ref_idx in 0..num_extra_refs {
+            let mark = ref_target_marks[ref_idx % ref_target_marks.len()];
+            stream.push_str(&format!("reset refs/heads/ref{ref_idx}\nfrom :{mark}\n\n"));
+        }
+
+        let marks_path = self.repo_path.join(".git").join("synthetic-history-marks");
+        self.run_with_options(
+            &[
+                "fast-import",
+                "--quiet",
+                &format!("--export-marks={}", marks_path.to_str().unwrap()),
+            ],
+            &GitRunOptions {
+                input: Some(stream),
+                ..Default::default()
+            },
+        )?;
+        let mark_oids: HashMap<usize, NonZeroOid> = fs::read_to_string(&marks_path)?
+            .lines()
+            .map(|line| -> eyre::Result<(usize, NonZeroOid)> {
+                let (mark, oid) = line
+                    .strip_prefix(':')
+                    .and_then(|line| line.split_once(' '))
+                    .ok_or_else(|| eyre::eyre!("Could not parse mark line: {line:?}"))?;
+                Ok((mark.parse()?, oid.parse()?))
+            })
+            .try_collect()?;
+        fs::remove_file(&marks_path)?;
+
+        self.run(&["config", "branchless.core.mainBranch", "master"])?;
+        self.run(&["symbolic-ref", "HEAD", "refs/heads/master"])?;
+        self.run(&["reset", "--hard", "master"])?;
+
+        let main_oids = main_marks.iter().map(|mark| mark_oids[mark]).collect();
+        let stack_oids = stack_marks
+            .iter()
+            .map(|marks| marks.iter().map(|mark| mark_oids[mark]).collect())
+            .collect();
+        Ok(SyntheticHistory {
+            main_oids,
+            stack_oids,
+        })
+    }
+}
+
+/// The commit timestamp of the first commit in a synthetic history. Each
+/// subsequent commit is one second later.
+const SYNTHETIC_HISTORY_START_TIME: usize = 1_600_000_000;
+
+/// Options for `Git::generate_synthetic_history`.
+#[derive(Clone, Copy, Debug)]
+pub struct SyntheticHistoryOptions {
+    /// The number of commits on the main branch. Must be at least 1.
+    pub num_main_commits: usize,
+
+    /// The number of stacks of draft commits. The stacks are based on commits
+    /// spread evenly over the main branch, and each stack has a branch pointing
+    /// to its last commit.
+    pub num_stacks: usize,
+
+    /// The number of commits in each stack.
+    pub stack_depth: usize,
+
+    /// The number of additional branches to create. These are distributed over
+    /// the stack commits.
+    pub num_extra_refs: usize,
+}
+
+/// Representative shapes of synthetic histories, named for use as benchmark
+/// parameters.
+pub const SYNTHETIC_HISTORIES: &[(&str, SyntheticHistoryOptions)] = &[
+    (
+        "wide-stacks",
+        SyntheticHistoryOptions {
+            num_main_commits: 1000,
+            num_stacks: 200,
+            stack_depth: 3,
+            num_extra_refs: 0,
+        },
+    ),
+    (
+        "deep-stacks",
+        SyntheticHistoryOptions {
+            num_main_commits: 1000,
+            num_stacks: 3,
+            stack_depth: 200,
+            num_extra_refs: 0,
+        },
+    ),
+    (
+        "many-refs",
+        SyntheticHistoryOptions {
+            num_main_commits: 1000,
+            num_stacks: 20,
+            stack_depth: 10,
+            num_extra_refs: 2000,
+        },
+    ),
+];
+
+/// The commits created by `Git::generate_synthetic_history`.
+#[derive(Clone, Debug)]
+pub struct SyntheticHistory {
+    /// The main branch commits, from oldest to newest.
+    pub main_oids: Vec<NonZeroOid>,
+
+    /// The commits of each stack, from oldest to newest.
+    pub stack_oids: Vec<Vec<NonZeroOid>>,
 }
 
 /// Wrapper around a `Git` instance which cleans up the repository once dropped.
@@ -1,13 +1,46 @@
 //! Utility functions.
 
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 /// Represents the code to exit the process with.
+///
+/// Commands exit with one of the codes defined as associated constants, so
+/// that scripts can distinguish between the classes of outcome:
+///
+/// | Code | Meaning                                                 |
+/// |------|---------------------------------------------------------|
+/// | 0    | [`ExitCode::SUCCESS`]: the command succeeded.           |
+/// | 1    | [`ExitCode::FAILURE`]: a generic error occurred.        |
+/// | 2    | [`ExitCode::USAGE`]: the command was invoked wrongly.   |
+/// | 3    | [`ExitCode::MERGE_CONFLICT`]: a merge conflict stopped the command. |
+/// | 4    | [`ExitCode::NOTHING_TO_DO`]: there were no changes to make. |
+///
+/// Exit codes of `git` subprocesses may be passed through unchanged, in which
+/// case they can take other values.
 #[must_use]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct ExitCode(pub isize);
 
 impl ExitCode {
+    /// The command succeeded.
+    pub const SUCCESS: Self = Self(0);
+
+    /// The command failed for a reason not covered by a more specific exit
+    /// code.
+    pub const FAILURE: Self = Self(1);
+
+    /// The command was invoked with invalid arguments or configuration. This
+    /// is the same exit code that `clap` uses for argument parsing errors.
+    pub const USAGE: Self = Self(2);
+
+    /// The command stopped because it would have caused a merge conflict, or
+    /// because it encountered one.
+    pub const MERGE_CONFLICT: Self = Self(3);
+
+    /// The command had no changes to make.
+    pub const NOTHING_TO_DO: Self = Self(4);
+
     /// Determine whether or not this exit code represents a successful
     /// termination.
     pub fn is_success(&self) -> bool {
@@ -53,3 +86,18 @@ pub fn get_sh() -> Option<PathBuf> {
     }
     get_from_path(exe_name)
 }
+
+/// Convert the given path into a form which the shell returned by [`get_sh`]
+/// can execute. Git Bash on Windows doesn't understand verbatim paths (those
+/// starting with `\\?\`, as returned by [`std::fs::canonicalize`]), and
+/// treats backslashes as escape characters in some contexts, so this strips
+/// the verbatim prefix and uses forward slashes instead.
+pub fn get_sh_path(path: &Path) -> OsString {
+    if cfg!(target_os = "windows") {
+        let path = path.to_string_lossy();
+        let path = path.strip_prefix(r"\\?\").unwrap_or(&path);
+        OsString::from(path.replace('\\', "/"))
+    } else {
+        path.as_os_str().to_owned()
+    }
+}
@@ -26,12 +26,14 @@ use tracing::instrument;
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::resolve_commits;
 use lib::core::config::{
-    get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
-    print_hint_suppression_notice, Hint,
+    get_hint_enabled, get_hint_string, get_move_default_in_memory, get_restack_preserve_timestamps,
+    print_hint_suppression_notice, Hint, PROTECTED_BRANCHES_CONFIG_KEY,
 };
 use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
-use lib::core::effects::Effects;
+use lib::core::effects::{Effects, OperationIcon, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::protected_branches::ProtectedBranches;
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
     MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
@@ -227,7 +229,7 @@ pub fn r#move(
     let source_oids = source_oids.union(&base_oids);
 
     if let Some(head_oid) = head_oid {
-        if get_hint_enabled(&repo, Hint::MoveImplicitHeadArgument)? {
+        if get_hint_enabled(effects, &repo, Hint::MoveImplicitHeadArgument)? {
             let should_warn_base = !sources_provided
                 && bases_provided
                 && base_oids.contains(&head_oid.into())?
@@ -236,7 +238,7 @@ pub fn r#move(
                 writeln!(
                     effects.get_output_stream(),
                     "{}: you can omit the --base flag in this case, as it defaults to HEAD",
-                    effects.get_glyphs().render(get_hint_string())?,
+                    effects.get_glyphs().render(get_hint_string(effects))?,
                 )?;
             }
 
@@ -245,7 +247,7 @@ pub fn r#move(
                 writeln!(
                     effects.get_output_stream(),
                     "{}: you can omit the --dest flag in this case, as it defaults to HEAD",
-                    effects.get_glyphs().render(get_hint_string())?,
+                    effects.get_glyphs().render(get_hint_string(effects))?,
                 )?;
             }
 
@@ -265,6 +267,8 @@ pub fn r#move(
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
+    let force_in_memory = force_in_memory
+        || (!force_on_disk && !resolve_merge_conflicts && get_move_default_in_memory(&repo)?);
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
     let pool = ThreadPoolBuilder::new().build()?;
@@ -287,14 +291,42 @@ pub fn r#move(
                 commits_to_move
             };
 
-            match RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commits_to_move)?
-            {
+            let permissions = match RebasePlanPermissions::verify_rewrite_set(
+                &dag,
+                build_options,
+                &commits_to_move,
+            )? {
                 Ok(permissions) => permissions,
                 Err(err) => {
                     err.describe(effects, &repo)?;
                     return Ok(ExitCode(1));
                 }
+            };
+
+            if !force_rewrite_public_commits {
+                let protected_branch_names = ProtectedBranches::new(&repo)?
+                    .find_protected_branches(
+                        &references_snapshot,
+                        &dag.query().descendants(commits_to_move.clone())?,
+                    )?;
+                if !protected_branch_names.is_empty() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "\
+You are trying to move {}: {}
+Protected branches are the main branch and the branches matching {PROTECTED_BRANCHES_CONFIG_KEY}.
+Retry with -f/--force-rewrite to proceed anyways.",
+                        Pluralize {
+                            determiner: None,
+                            amount: protected_branch_names.len(),
+                            unit: ("protected branch", "protected branches"),
+                        },
+                        protected_branch_names.join(", "),
+                    )?;
+                    return Ok(ExitCode(1));
+                }
             }
+            permissions
         };
         let mut builder = RebasePlanBuilder::new(&dag, permissions);
 
@@ -475,12 +507,12 @@ pub fn r#move(
                 builder.move_subtree(dest_child, vec![source_head])?;
             }
         }
-        builder.build(effects, &pool, &repo_pool)?
+        builder.build(effects, git_run_info, &pool, &repo_pool)?
     };
     let result = match rebase_plan {
         Ok(None) => {
             writeln!(effects.get_output_stream(), "Nothing to do.")?;
-            return Ok(ExitCode(0));
+            return Ok(ExitCode::NOTHING_TO_DO);
         }
         Ok(Some(rebase_plan)) => {
             let options = ExecuteRebasePlanOptions {
@@ -492,8 +524,18 @@ pub fn r#move(
                 resolve_merge_conflicts,
                 check_out_commit_options: Default::default(),
             };
+            let (effects, progress) = effects.start_operation(OperationType::MoveCommits);
+            progress.notify_status(
+                OperationIcon::InProgress,
+                format!(
+                    "Moving commits onto: {}",
+                    effects.get_glyphs().render(
+                        repo.friendly_describe_commit_from_oid(effects.get_glyphs(), dest_oid)?
+                    )?
+                ),
+            );
             execute_rebase_plan(
-                effects,
+                &effects,
                 git_run_info,
                 &repo,
                 &event_log_db,
@@ -512,7 +554,7 @@ pub fn r#move(
 
         ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
             failed_merge_info.describe(effects, &repo, MergeConflictRemediation::Retry)?;
-            Ok(ExitCode(1))
+            Ok(ExitCode::MERGE_CONFLICT)
         }
 
         ExecuteRebasePlanResult::Failed { exit_code } => Ok(exit_code),
@@ -28,7 +28,11 @@ use tracing::{instrument, warn};
 use git_branchless_opts::{SwitchOptions, TraverseCommitsOptions};
 use git_branchless_revset::resolve_default_smartlog_commits;
 use git_branchless_smartlog::make_smartlog_graph;
-use lib::core::config::get_next_interactive;
+use lib::core::child_index::ChildIndexDb;
+use lib::core::config::{
+    get_hint_enabled, get_hint_string, get_next_default_newest, get_next_interactive,
+    print_hint_suppression_notice, Hint,
+};
 use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
@@ -37,7 +41,7 @@ use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, NodeDescriptor, Redactor, RelativeTimeDescriptor,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{CategorizedReferenceName, GitRunInfo, NonZeroOid, Repo, ResolvedReferenceInfo};
 
 use crate::prompt::prompt_select_commit;
 
@@ -91,6 +95,7 @@ fn advance(
     effects: &Effects,
     repo: &Repo,
     dag: &Dag,
+    child_index_db: &ChildIndexDb,
     commit_descriptors: &mut [&mut dyn NodeDescriptor],
     current_oid: NonZeroOid,
     command: Command,
@@ -102,6 +107,8 @@ fn advance(
         None => {
             if get_next_interactive(repo)? {
                 Some(Towards::Interactive)
+            } else if matches!(command, Command::Next) && get_next_default_newest(repo)? {
+                Some(Towards::Newest)
             } else {
                 None
             }
@@ -117,9 +124,7 @@ fn advance(
         let candidate_commits = match command {
             Command::Next => {
                 let child_commits = || -> eyre::Result<CommitSet> {
-                    let result = dag.query().children(CommitSet::from(current_oid))?;
-                    let result = dag.filter_visible_commits(result)?;
-                    Ok(result)
+                    child_index_db.query_visible_children(dag, &CommitSet::from(current_oid))
                 };
 
                 let descendant_branches = || -> eyre::Result<CommitSet> {
@@ -368,13 +373,25 @@ pub fn traverse_commits(
     )?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
-    let dag = Dag::open_and_sync(
-        effects,
-        &repo,
-        &event_replayer,
-        event_cursor,
-        &references_snapshot,
-    )?;
+    // `git prev` only needs the ancestors of `HEAD`, which are synced along
+    // with it, so skip syncing the rest of the observed commits.
+    let dag = match command {
+        Command::Next => Dag::open_and_sync(
+            effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?,
+        Command::Prev => Dag::open_and_sync_heads(
+            effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+            CommitSet::empty(),
+        )?,
+    };
 
     let head_oid = match references_snapshot.head_oid {
         Some(head_oid) => head_oid,
@@ -383,10 +400,12 @@ pub fn traverse_commits(
         }
     };
 
+    let child_index_db = ChildIndexDb::new(&conn)?;
     let current_oid = advance(
         effects,
         &repo,
         &dag,
+        &child_index_db,
         &mut [
             &mut CommitOidDescriptor::new(true)?,
             &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
@@ -454,7 +473,7 @@ pub fn traverse_commits(
         }
         args
     };
-    check_out_commit(
+    let exit_code = check_out_commit(
         effects,
         git_run_info,
         &repo,
@@ -465,7 +484,11 @@ pub fn traverse_commits(
             additional_args,
             ..Default::default()
         },
-    )
+    )?;
+    if exit_code.is_success() {
+        print_detached_head_hint(effects, &repo, &head_info)?;
+    }
+    Ok(exit_code)
 }
 
 /// Interactively switch to a commit from the smartlog.
@@ -534,7 +557,7 @@ pub fn switch(
             match prompt_select_commit(
                 None,
                 &initial_query,
-                graph.get_commits(),
+                graph.get_commits()?,
                 &mut [
                     &mut CommitOidDescriptor::new(true)?,
                     &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
@@ -585,5 +608,42 @@ pub fn switch(
             render_smartlog: true,
         },
     )?;
+    if exit_code.is_success() && !*detach {
+        print_detached_head_hint(effects, &repo, &head_info)?;
+    }
     Ok(exit_code)
 }
+
+/// If `HEAD` was attached to a branch before checking out another commit, and
+/// is now detached, point out that new commits won't update the branch. This
+/// isn't done for the main branch, since draft commits are usually made on a
+/// detached `HEAD` anyways.
+fn print_detached_head_hint(
+    effects: &Effects,
+    repo: &Repo,
+    previous_head_info: &ResolvedReferenceInfo,
+) -> eyre::Result<()> {
+    let previous_reference_name = match &previous_head_info.reference_name {
+        Some(reference_name) => reference_name,
+        None => return Ok(()),
+    };
+    if previous_reference_name == &repo.get_main_branch()?.get_reference_name()?
+        || repo.get_head_info()?.reference_name.is_some()
+        || !get_hint_enabled(effects, repo, Hint::DetachedHead)?
+    {
+        return Ok(());
+    }
+
+    let branch_name = CategorizedReferenceName::new(previous_reference_name).render_suffix();
+    let hint = effects.get_glyphs().render(get_hint_string(effects))?;
+    writeln!(
+        effects.get_output_stream(),
+        "{hint}: HEAD is now detached from branch {branch_name}, so new commits won't update it"
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "{hint}: to check out the branch again, run: git switch {branch_name}"
+    )?;
+    print_hint_suppression_notice(effects, Hint::DetachedHead)?;
+    Ok(())
+}
@@ -14,6 +14,7 @@ use lib::git::NonZeroOid;
 
 use std::ffi::OsString;
 use std::fmt::Display;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
@@ -197,6 +198,16 @@ pub enum HookSubcommand {
     /// Internal use.
     PreAutoGc,
     /// Internal use.
+    PrePush {
+        /// The name of the remote being pushed to.
+        #[clap(value_parser)]
+        remote_name: String,
+
+        /// The URL of the remote being pushed to.
+        #[clap(value_parser)]
+        remote_url: String,
+    },
+    /// Internal use.
     PostApplypatch,
     /// Internal use.
     PostCheckout {
@@ -257,12 +268,75 @@ pub struct InitArgs {
     #[clap(action, long = "uninstall")]
     pub uninstall: bool,
 
+    /// When uninstalling, also delete the event log, the cached commit graph,
+    /// and the references which keep commits alive.
+    ///
+    /// By default, these are preserved, so that your commit history is still
+    /// available if you run `git branchless init` again later.
+    #[clap(action, long = "purge", requires = "uninstall")]
+    pub purge: bool,
+
     /// Use the provided name as the name of the main branch.
     ///
     /// If not set, it will be auto-detected. If it can't be auto-detected,
     /// then you will be prompted to enter a value for the main branch name.
     #[clap(value_parser, long = "main-branch", conflicts_with = "uninstall")]
     pub main_branch_name: Option<String>,
+
+    /// Only install the `git` alias with the given name (such as `sl` for `git
+    /// sl`), rather than all of them. May be passed multiple times.
+    ///
+    /// If not set, the aliases listed in the `branchless.init.aliases`
+    /// configuration value are installed, or all of them if that isn't set
+    /// either.
+    #[clap(
+        value_parser,
+        long = "alias",
+        conflicts_with_all(&["uninstall", "no_aliases"])
+    )]
+    pub aliases: Vec<String>,
+
+    /// Don't install any `git` aliases.
+    #[clap(action, long = "no-aliases", conflicts_with = "uninstall")]
+    pub no_aliases: bool,
+
+    /// Don't install the `reference-transaction` hook, which runs on every
+    /// reference update. Branch updates will instead be detected when one of
+    /// the other hooks runs, which is less precise but faster for repositories
+    /// with a large number of reference updates.
+    ///
+    /// This sets the `branchless.core.referenceTransactionHook` configuration
+    /// value to `false`.
+    #[clap(
+        action,
+        long = "no-reference-transaction-hook",
+        conflicts_with = "uninstall"
+    )]
+    pub no_reference_transaction_hook: bool,
+
+    /// Install the `pre-push` hook, which warns when pushing commits which
+    /// have been rewritten or hidden locally, such as an old version of a
+    /// stack.
+    ///
+    /// This sets the `branchless.core.prePushHook` configuration value to
+    /// `true`. Set `branchless.prePush.obsoleteCommits` to `block` to abort
+    /// such pushes instead of only warning about them.
+    #[clap(action, long = "pre-push-hook", conflicts_with = "uninstall")]
+    pub pre_push_hook: bool,
+
+    /// Also install aliases named after Sapling commands, for users who are
+    /// used to Sapling: `git goto` (for `switch`), `git fold` (for `fold`),
+    /// `git metaedit` (for `reword`), and `git histedit` (for an interactive
+    /// rebase).
+    ///
+    /// This sets the `branchless.init.saplingAliases` configuration value to
+    /// `true`.
+    #[clap(
+        action,
+        long = "sapling-aliases",
+        conflicts_with_all(&["uninstall", "no_aliases"])
+    )]
+    pub sapling_aliases: bool,
 }
 
 /// Query the commit graph using the "revset" language and print matching
@@ -357,6 +431,12 @@ pub struct SubmitArgs {
     #[clap(action, short = 'c', long = "create")]
     pub create: bool,
 
+    /// Where to submit the commits for review. By default, branches are only
+    /// pushed to the remote. This can also be set with the
+    /// `branchless.submit.forge` config setting.
+    #[clap(value_parser, long = "forge")]
+    pub forge: Option<SubmitForge>,
+
     /// The commits to push. All branches attached to those commits will be
     /// pushed.
     #[clap(value_parser, default_value = "stack()")]
@@ -375,7 +455,9 @@ pub struct TestArgs {
     pub subcommand: TestSubcommand,
 }
 
-/// FIXME: write man-page text
+/// The `git-branchless` subcommands. The doc comments of each variant are
+/// also used to generate its man-page, where the paragraphs after the first
+/// make up the extended description.
 #[derive(Debug, Parser)]
 pub enum Command {
     /// Amend the current HEAD commit.
@@ -391,14 +473,124 @@ pub enum Command {
         reparent: bool,
     },
 
+    /// Hide a group of stacks under the given name, so that they can be
+    /// restored later with `unarchive`.
+    Archive {
+        /// The name to archive the commits under.
+        #[clap(value_parser, required_unless_present("list"))]
+        name: Option<String>,
+
+        /// The commits to archive. All visible draft descendants of these
+        /// commits are archived as well.
+        #[clap(value_parser, default_value = "stack()")]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// List the existing archives instead of creating a new one.
+        #[clap(action, short = 'l', long = "list", conflicts_with("name"))]
+        list: bool,
+    },
+
     /// Gather information about recent operations to upload as part of a bug
     /// report.
     BugReport,
 
+    /// Print a shell script which sets up completions for the `git-branchless`
+    /// subcommands and their options. Arguments which take commits are
+    /// completed with the names of the branches and the hashes of the commits
+    /// shown in the smartlog.
+    ///
+    /// For example, add `source <(git branchless completions bash)` to your
+    /// `~/.bashrc`.
+    Completions {
+        /// The shell to generate completions for.
+        #[clap(value_enum, required_unless_present = "list_candidates")]
+        shell: Option<CompletionShell>,
+
+        /// Print the branches and commits to complete arguments which take
+        /// commits with, one per line, each followed by a tab and a
+        /// description. Used by the completion scripts.
+        #[clap(
+            action,
+            long = "list-candidates",
+            hide = true,
+            conflicts_with = "shell"
+        )]
+        list_candidates: bool,
+    },
+
+    /// Change `git-branchless` settings for this repository.
+    Config {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: ConfigSubcommand,
+    },
+
+    /// Combine a linear range of commits into a single commit, whose message
+    /// is the concatenation of their messages. Any descendants of the commits
+    /// are moved onto the combined commit.
+    Fold {
+        /// Fold the commits from the given commit up to the current commit,
+        /// inclusive.
+        #[clap(value_parser, long = "from", conflicts_with = "revsets")]
+        from: Option<Revset>,
+
+        /// The commits to fold, if `--from` isn't given.
+        #[clap(value_parser, required_unless_present("from"))]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Force folding public commits, even though other people may have
+        /// access to those commits.
+        #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
+        force_rewrite_public_commits: bool,
+    },
+
     /// Run internal garbage collection.
-    Gc,
+    Gc {
+        /// After deleting references to hidden commits, also repack the
+        /// repository and prune unreachable objects (subject to Git's usual
+        /// expiry settings, such as `gc.pruneExpire`), and report how much
+        /// space was reclaimed.
+        #[clap(action, long = "aggressive")]
+        aggressive: bool,
+
+        /// Don't change anything. Instead, list the commits which would be
+        /// hidden and those which would no longer be kept from Git's garbage
+        /// collection, along with an estimate of the disk space which could be
+        /// reclaimed.
+        #[clap(action, short = 'n', long = "dry-run", conflicts_with = "aggressive")]
+        dry_run: bool,
+
+        /// Instead of collecting garbage, check that the event log database is
+        /// intact, that every visible commit exists in the object database, and
+        /// that every reference kept by branchless points to a commit.
+        #[clap(
+            action,
+            long = "check",
+            conflicts_with_all(&["aggressive", "dry_run"])
+        )]
+        check: bool,
+
+        /// With `--check`, also repair the problems found, where possible.
+        #[clap(action, long = "repair", requires = "check")]
+        repair: bool,
+    },
 
     /// Hide the provided commits from the smartlog.
+    ///
+    /// Hidden commits are not deleted. They can be restored with `unhide`, or
+    /// by returning to an earlier state of the repository with `undo`.
+    ///
+    /// By default, the children of the provided commits are left behind, and
+    /// are rendered as abandoned in the smartlog until they're hidden or
+    /// restacked. Pass `--recursive` to hide them as well.
     Hide {
         /// Zero or more commits to hide.
         #[clap(value_parser)]
@@ -416,6 +608,29 @@ pub enum Command {
         /// commits.
         #[clap(action, short = 'r', long = "recursive")]
         recursive: bool,
+
+        /// Don't hide the children of the provided commits, even if
+        /// `branchless.hide.defaultRecursive` is set.
+        #[clap(action, long = "no-recursive", conflicts_with = "recursive")]
+        no_recursive: bool,
+
+        /// A note explaining why the commits were hidden. It's displayed in
+        /// the smartlog and in the undo browser.
+        #[clap(value_parser, short = 'm', long = "message")]
+        message: Option<String>,
+
+        /// Hide the commits even if they're protected. Without this flag,
+        /// `hide` refuses to hide the currently checked-out commit, commits
+        /// with branches (unless `--delete-branches` is passed), and public
+        /// commits on the main branch.
+        #[clap(action, short = 'f', long = "force")]
+        force: bool,
+
+        /// Also hide all visible commits which descend from a manually-hidden
+        /// commit. Such commits are left behind when hiding a commit without
+        /// `--recursive`.
+        #[clap(action, long = "orphans")]
+        orphans: bool,
     },
 
     /// Internal use.
@@ -425,6 +640,17 @@ pub enum Command {
     /// Initialize the branchless workflow for this repository.
     Init(InitArgs),
 
+    /// Write the man-pages for `git-branchless` and its subcommands into the
+    /// given directory, such as `/usr/share/man`. This is meant to be run
+    /// when packaging `git-branchless`.
+    #[clap(hide = true)]
+    InstallManPages {
+        /// The `man` directory to write into. The pages are written into its
+        /// `man1` subdirectory.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+
     /// Move a subtree of commits from one location to another.
     ///
     /// By default, `git move` tries to move the entire current stack if you
@@ -488,6 +714,25 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Export or import the commits which have been rewritten or hidden, as
+    /// Mercurial obsolescence markers. The markers refer to commits by their
+    /// Git hashes, so `hg-evolve` can't apply them to a Mercurial repository
+    /// as-is.
+    Obsmarkers {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: ObsmarkersSubcommand,
+    },
+
+    /// Commands for scripts, whose output is versioned and won't change
+    /// within a version, unlike the output of the other commands, which is
+    /// meant to be read by humans.
+    Plumbing {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: PlumbingSubcommand,
+    },
+
     /// Move to an earlier commit in the current stack.
     Prev {
         /// Options for traversing commits.
@@ -514,6 +759,12 @@ pub enum Command {
     },
 
     /// Fix up commits abandoned by a previous rewrite operation.
+    ///
+    /// A commit is abandoned when one of its ancestors is rewritten, such as
+    /// with `git commit --amend`, without the commit itself being moved onto
+    /// the rewritten ancestor. `restack` moves each abandoned commit onto the
+    /// latest version of its parent, and moves any branches pointing to
+    /// rewritten commits to their latest versions.
     Restack {
         /// The IDs of the abandoned commits whose descendants should be
         /// restacked. If not provided, all abandoned commits are restacked.
@@ -570,7 +821,77 @@ pub enum Command {
         commit_to_fixup: Option<Revset>,
     },
 
-    /// `smartlog` command.
+    /// Generate a series of patches for the given commits with `git
+    /// format-patch`, for an email-based review workflow. The series is
+    /// threaded under a cover letter, whose subject and description are taken
+    /// from the `branch.<name>.description` setting of the branch at the top of
+    /// the series, if any.
+    Send {
+        /// The commits to send. They must form a linear range.
+        #[clap(value_parser, default_value = "stack()")]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// The directory to write the patches to. Defaults to the current
+        /// directory.
+        #[clap(value_parser, short = 'o', long = "output-directory")]
+        output_directory: Option<PathBuf>,
+
+        /// Mark the series as the given version of the patches (such as `[PATCH
+        /// v2]`).
+        #[clap(value_parser, long = "reroll-count")]
+        reroll_count: Option<usize>,
+
+        /// Use the given prefix instead of `PATCH` in the subject lines.
+        #[clap(value_parser, long = "subject-prefix")]
+        subject_prefix: Option<String>,
+
+        /// After generating the patches, send them with `git send-email`.
+        #[clap(action, long = "send")]
+        send: bool,
+
+        /// The recipients of the patches, when sending them.
+        #[clap(value_parser, long = "to", requires = "send")]
+        to: Vec<String>,
+
+        /// The recipients to copy on the patches, when sending them.
+        #[clap(value_parser, long = "cc", requires = "send")]
+        cc: Vec<String>,
+    },
+
+    /// Serve the smartlog and commands to editor integrations over a local
+    /// socket, so that they don't need to run and parse the output of other
+    /// commands.
+    ///
+    /// Requests and responses are JSON-RPC 2.0 messages, one per line. The
+    /// supported methods are `smartlog`, `subscribe` (to receive an
+    /// `eventsChanged` notification whenever the repository changes), `hide`,
+    /// `move`, `checkout`, and `shutdown`.
+    Serve {
+        /// Use the JSON-RPC protocol. This is currently the only protocol
+        /// available.
+        #[clap(action, long = "json-rpc", required = true)]
+        json_rpc: bool,
+
+        /// The path of the socket to listen on. Defaults to
+        /// `.git/branchless/serve.sock`.
+        #[clap(value_parser, long = "socket")]
+        socket: Option<PathBuf>,
+    },
+
+    /// Display a nice graph of the commits you've recently worked on.
+    ///
+    /// By default, the smartlog shows your draft commits, the commits with
+    /// branches, and the current commit, along with the main branch commits
+    /// needed to connect them. Pass a revset to show a different set of
+    /// commits.
+    ///
+    /// Commits which have been rewritten or hidden, but which still have
+    /// visible descendants, are rendered with a note explaining what happened
+    /// to them.
     Smartlog(SmartlogArgs),
 
     #[clap(hide = true)]
@@ -584,6 +905,20 @@ pub enum Command {
     /// Push commits to a remote.
     Submit(SubmitArgs),
 
+    /// Suggest how to split the given commits so that each resulting commit
+    /// only touches the files of a single ownership area, as assigned by the
+    /// repository's `CODEOWNERS` file. This makes it easier to route large
+    /// changes to their reviewers.
+    SuggestSplit {
+        /// The commits to analyze.
+        #[clap(value_parser, default_value = ".")]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
     /// Switch to the provided branch or commit.
     Switch {
         /// Options for switching.
@@ -592,6 +927,10 @@ pub enum Command {
     },
 
     /// Move any local commit stacks on top of the main branch.
+    ///
+    /// Stacks which can't be moved without merge conflicts are skipped, and
+    /// listed at the end, so that they can be moved manually with `move
+    /// --merge`.
     Sync {
         /// Run `git fetch` to update remote references before carrying out the
         /// sync.
@@ -622,15 +961,27 @@ pub enum Command {
     Test(TestArgs),
 
     /// Browse or return to a previous state of the repository.
+    ///
+    /// Without `--interactive`, returns to the state before the last
+    /// operation. The changes that would be made to commits, branches, and
+    /// the checked-out commit are printed for confirmation before they're
+    /// applied.
+    ///
+    /// Changes to the working copy that haven't been committed are not
+    /// tracked, and can't be undone.
     Undo {
         /// Interactively browse through previous states of the repository
         /// before selecting one to return to.
         #[clap(action, short = 'i', long = "interactive")]
         interactive: bool,
+    },
 
-        /// Skip confirmation and apply changes immediately.
-        #[clap(action, short = 'y', long = "yes")]
-        yes: bool,
+    /// Restore the commits and branches from an archive created with
+    /// `archive`.
+    Unarchive {
+        /// The name of the archive to restore.
+        #[clap(value_parser)]
+        name: String,
     },
 
     /// Unhide previously-hidden commits from the smartlog.
@@ -654,7 +1005,20 @@ pub enum Command {
         #[clap(value_parser, long = "git-executable")]
         git_executable: Option<PathBuf>,
 
-        /// The arguments to pass to `git`.
+        /// Run an arbitrary program (such as a script which runs several Git
+        /// commands) instead of `git`. The first argument is the program to
+        /// run. All of the events recorded while it runs are grouped into one
+        /// transaction, so that they can be undone together.
+        #[clap(action, short = 'x', long = "exec", conflicts_with = "git_executable")]
+        exec: bool,
+
+        /// The label of the transaction, as shown in `git undo`. Defaults to
+        /// the name of the wrapped command.
+        #[clap(value_parser, short = 'm', long = "message")]
+        message: Option<String>,
+
+        /// The arguments to pass to `git` (or the program to run, if `--exec`
+        /// is passed).
         #[clap(subcommand)]
         command: WrappedCommand,
     },
@@ -672,6 +1036,75 @@ pub enum ColorSetting {
     Never,
 }
 
+/// A shell to generate completions for with `git branchless completions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    /// Bash. The completions also apply to `git branchless` and the aliases
+    /// installed by `git branchless init` when Git's own completions are
+    /// loaded.
+    Bash,
+
+    /// Zsh.
+    Zsh,
+
+    /// Fish.
+    Fish,
+}
+
+/// How to format the result of a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text. This is the default behavior.
+    Human,
+    /// A JSON object describing the commits and branches changed by the
+    /// command, instead of the usual output.
+    Json,
+    /// A stream of JSON objects, one per line, describing each step of the
+    /// command as it happens, instead of the usual output. The last object
+    /// contains the exit code of the command.
+    Jsonl,
+}
+
+/// Where to submit commits for review.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SubmitForge {
+    /// Default. Push branches to the remote, without creating code reviews.
+    Branch,
+
+    /// Push branches to the remote and create or update a GitHub pull
+    /// request for each branch. Stacked branches are submitted as stacked
+    /// pull requests. Requires the GitHub CLI (`gh`) to be installed and
+    /// authenticated.
+    Github,
+
+    /// Push branches to the remote and create or update a GitLab merge
+    /// request for each branch, targeting the branch below it in the stack.
+    /// Uses the project set in `branchless.submit.gitlab.project` and the
+    /// access token in `$GITLAB_TOKEN`.
+    Gitlab,
+
+    /// Push the commits to Gerrit as one change per commit, adding a
+    /// `Change-Id` trailer to any commits which don't have one. Branches are
+    /// not pushed.
+    Gerrit,
+
+    /// Create or update a Phabricator revision for each commit with `arc
+    /// diff`, adding a `Differential Revision` trailer to commits for new
+    /// revisions. Branches are not pushed. Requires Arcanist (`arc`) to be
+    /// installed and configured.
+    Phabricator,
+}
+
+/// The output format of the `plumbing` commands. Each format is versioned, and
+/// its schema only changes in backwards-compatible ways, such as by adding
+/// fields. Incompatible changes are made in a new version instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PlumbingFormat {
+    /// Version 1 of the JSON output.
+    #[clap(name = "json-v1")]
+    JsonV1,
+}
+
 /// How to execute tests.
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum TestExecutionStrategy {
@@ -710,9 +1143,124 @@ pub struct GlobalArgs {
     #[clap(value_parser, short = 'C', global = true)]
     pub working_directory: Option<PathBuf>,
 
+    /// Path to the repository (such as a `.git` directory) to use, rather than
+    /// discovering it from the working directory. This is equivalent to
+    /// setting `$GIT_DIR`, as with `git --git-dir`.
+    #[clap(value_parser, long = "git-dir", global = true)]
+    pub git_dir: Option<PathBuf>,
+
+    /// Path to the working tree to use for the repository. This is equivalent
+    /// to setting `$GIT_WORK_TREE`, as with `git --work-tree`.
+    #[clap(value_parser, long = "work-tree", global = true)]
+    pub work_tree: Option<PathBuf>,
+
     /// Flag to force enable or disable terminal colors.
     #[clap(value_parser, long = "color", value_enum, global = true)]
     pub color: Option<ColorSetting>,
+
+    /// How to format the result of the command. With `json`, the mutating
+    /// commands (`hide`, `unhide`, `move`, `restack`, `record` and `sync`)
+    /// print the commits they created, rewrote or hid, the branches they
+    /// updated, and any merge conflicts, instead of their usual output. With
+    /// `jsonl`, the long-running commands (`sync`, `test` and `submit`) print
+    /// each step as it starts and finishes.
+    #[clap(value_parser, long = "output", value_enum, global = true)]
+    pub output: Option<OutputFormat>,
+
+    /// Send the output of `smartlog`, `query` and `test show` to a pager if
+    /// stdout is a terminal, even if `pager.<command>` is set to `false`, as
+    /// with `git --paginate`. The pager is chosen in the same way as Git:
+    /// `$GIT_PAGER`, `core.pager`, `$PAGER`, then `less`.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(
+        action = clap::ArgAction::SetTrue,
+        long = "paginate",
+        conflicts_with = "no_pager",
+        global = true
+    )]
+    pub paginate: Option<bool>,
+
+    /// Don't send output to a pager, even if one is configured. This is
+    /// equivalent to setting `$GIT_PAGER` to `cat`, as with `git --no-pager`.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(action = clap::ArgAction::SetTrue, long = "no-pager", global = true)]
+    pub no_pager: Option<bool>,
+
+    /// Produce plain output for screen readers, dumb terminals, and logs:
+    /// draw graphs with ASCII characters, disable colors, and don't show
+    /// progress indicators. This can also be enabled with the
+    /// `branchless.core.plain` config setting.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(action = clap::ArgAction::SetTrue, long = "plain", global = true)]
+    pub plain: Option<bool>,
+
+    /// Don't ask for confirmation before destructive operations, such as
+    /// deleting branches with `hide -D`, pruning objects with `gc
+    /// --aggressive`, or applying `undo`. Confirmation is also skipped when not
+    /// running in a terminal.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(
+        action = clap::ArgAction::SetTrue,
+        short = 'y',
+        long = "yes",
+        visible_alias = "no-confirm",
+        global = true
+    )]
+    pub yes: Option<bool>,
+
+    /// Produce less output: don't print the smartlog after commands such as
+    /// `next`, `prev`, `switch` and `restack`, and don't print hints.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(
+        action = clap::ArgAction::SetTrue,
+        short = 'q',
+        long = "quiet",
+        conflicts_with = "verbose",
+        global = true
+    )]
+    pub quiet: Option<bool>,
+
+    /// Produce more output. Pass once to print the rebase plan before
+    /// executing it, or twice to also print the constraints used to build it.
+    /// For `git test`, pass once to show the abbreviated test output, or twice
+    /// to show the full test output.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(
+        action = clap::ArgAction::Count,
+        short = 'v',
+        long = "verbose",
+        global = true
+    )]
+    pub verbose: Option<u8>,
+
+    /// Produce output which is the same across runs and machines, such as for
+    /// snapshot tests: don't show the ages of commits or progress
+    /// indicators, and use a fixed terminal width for Git subprocesses.
+    //
+    // See `trace` below for why this is an `Option`.
+    #[clap(action = clap::ArgAction::SetTrue, long = "deterministic", global = true)]
+    pub deterministic: Option<bool>,
+
+    /// Print a summary of the time spent in each phase of the command (such
+    /// as loading the event log, building the commit graph, and running Git
+    /// subprocesses) when it exits.
+    //
+    // This is an `Option` because the global arguments are first parsed on
+    // their own with errors ignored, in which case they may not receive their
+    // default values.
+    #[clap(action = clap::ArgAction::SetTrue, long = "trace", global = true)]
+    pub trace: Option<bool>,
+
+    /// Write a trace of the command's execution to the given file, which can
+    /// be viewed with `chrome://tracing` or <https://ui.perfetto.dev>.
+    #[clap(value_parser, long = "profile", global = true)]
+    pub profile: Option<PathBuf>,
 }
 
 /// Branchless workflow for Git.
@@ -730,6 +1278,120 @@ pub struct Opts {
     pub command: Command,
 }
 
+/// `config` subcommands.
+#[derive(Debug, Parser)]
+pub enum ConfigSubcommand {
+    /// Print the value of the given setting.
+    Get {
+        /// The setting to print, such as `core.mainBranch`. The `branchless.`
+        /// prefix may be omitted.
+        #[clap(value_parser)]
+        key: String,
+    },
+
+    /// Set the given setting to the provided value, after checking that the
+    /// value is valid for that setting.
+    Set {
+        /// The setting to change, such as `core.mainBranch` (or `main-branch`).
+        /// The `branchless.` prefix may be omitted.
+        #[clap(value_parser)]
+        key: String,
+
+        /// The new value for the setting.
+        #[clap(value_parser)]
+        value: String,
+    },
+
+    /// List all `git-branchless` settings which have been set, and warn about
+    /// any which aren't recognized.
+    List,
+}
+
+/// `obsmarkers` subcommands.
+#[derive(Debug, Parser)]
+pub enum ObsmarkersSubcommand {
+    /// Print the obsolescence markers for all currently-obsolete commits, in
+    /// the JSON format produced by `hg debugobsolete -Tjson`.
+    Export,
+
+    /// Read obsolescence markers in the JSON format produced by `hg
+    /// debugobsolete -Tjson`, and mark their predecessor commits as rewritten
+    /// or hidden. Markers which refer to commits which aren't in this
+    /// repository are skipped.
+    Import {
+        /// The file to read the markers from.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+}
+
+/// `plumbing` subcommands.
+#[derive(Debug, Parser)]
+pub enum PlumbingSubcommand {
+    /// Print the commits in the commit graph, with their parents, branches
+    /// and visibility.
+    Graph {
+        /// The commits to print. Defaults to the commits in the smartlog.
+        #[clap(value_parser)]
+        revset: Option<Revset>,
+
+        /// The output format.
+        #[clap(value_enum, long = "format", required = true)]
+        format: PlumbingFormat,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
+    /// Print whether the given commits are obsolete, and the latest commits
+    /// that they were rewritten into.
+    Successors {
+        /// The commits to look up.
+        #[clap(value_parser, required = true)]
+        revsets: Vec<Revset>,
+
+        /// The output format.
+        #[clap(value_enum, long = "format", required = true)]
+        format: PlumbingFormat,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
+    /// Print the rebase plan for moving the given commits and their
+    /// descendants onto the destination commit, without carrying it out.
+    PlanMove {
+        /// The source commits to move. These commits, and all of their
+        /// descendants, will be moved.
+        #[clap(
+            action(clap::ArgAction::Append),
+            short = 's',
+            long = "source",
+            required = true
+        )]
+        source: Vec<Revset>,
+
+        /// The destination commit to move the source commits onto.
+        #[clap(value_parser, short = 'd', long = "dest")]
+        dest: Revset,
+
+        /// Plan to move public commits, even though other people may have
+        /// access to those commits.
+        #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
+        force_rewrite_public_commits: bool,
+
+        /// The output format.
+        #[clap(value_enum, long = "format", required = true)]
+        format: PlumbingFormat,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+}
+
 /// `snapshot` subcommands.
 #[derive(Debug, Parser)]
 pub enum SnapshotSubcommand {
@@ -780,10 +1442,6 @@ pub enum TestSubcommand {
         #[clap(flatten)]
         resolve_revset_options: ResolveRevsetOptions,
 
-        /// Show the test output as well.
-        #[clap(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
-        verbosity: u8,
-
         /// How to execute the tests.
         #[clap(short = 's', long = "strategy")]
         strategy: Option<TestExecutionStrategy>,
@@ -829,10 +1487,6 @@ pub enum TestSubcommand {
         /// Options for resolving revset expressions.
         #[clap(flatten)]
         resolve_revset_options: ResolveRevsetOptions,
-
-        /// Show the test output as well.
-        #[clap(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
-        verbosity: u8,
     },
 
     /// Run a given command on a set of commits and present the successes and failures.
@@ -858,10 +1512,6 @@ pub enum TestSubcommand {
         #[clap(flatten)]
         resolve_revset_options: ResolveRevsetOptions,
 
-        /// Show the test output as well.
-        #[clap(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
-        verbosity: u8,
-
         /// How to execute the tests.
         #[clap(short = 's', long = "strategy")]
         strategy: Option<TestExecutionStrategy>,
@@ -884,27 +1534,87 @@ pub enum TestSubcommand {
 ///
 /// The generated files are named things like `man1/git-branchless-smartlog.1`,
 /// so this directory should be of the form `path/to/man`, to ensure that these
-/// files get generated into the correct man-page section.
+/// files get generated into the correct man-page section. Nested subcommands
+/// get their own pages, such as `man1/git-branchless-test-run.1`. Hidden
+/// subcommands are skipped.
 pub fn write_man_pages(man_dir: &Path) -> std::io::Result<()> {
     let man1_dir = man_dir.join("man1");
     std::fs::create_dir_all(&man1_dir)?;
 
     let app = Opts::command();
-    generate_man_page(&man1_dir, "git-branchless", &app)?;
-    for subcommand in app.get_subcommands() {
-        let subcommand_exe_name = format!("git-branchless-{}", subcommand.get_name());
-        generate_man_page(&man1_dir, &subcommand_exe_name, subcommand)?;
+    generate_man_pages(&man1_dir, &["git-branchless"], &app, "git")
+}
+
+fn generate_man_pages(
+    man1_dir: &Path,
+    command_path: &[&str],
+    command: &ClapCommand,
+    parent_page_name: &str,
+) -> std::io::Result<()> {
+    let page_name = command_path.join("-");
+    generate_man_page(man1_dir, command_path, command, parent_page_name)?;
+    for subcommand in command.get_subcommands() {
+        if subcommand.is_hide_set() {
+            continue;
+        }
+        let mut subcommand_path = command_path.to_vec();
+        subcommand_path.push(subcommand.get_name());
+        generate_man_pages(man1_dir, &subcommand_path, subcommand, &page_name)?;
     }
     Ok(())
 }
 
-fn generate_man_page(man1_dir: &Path, name: &str, command: &ClapCommand) -> std::io::Result<()> {
+fn generate_man_page(
+    man1_dir: &Path,
+    command_path: &[&str],
+    command: &ClapCommand,
+    parent_page_name: &str,
+) -> std::io::Result<()> {
+    let page_name = command_path.join("-");
+    let source = format!("git-branchless {}", env!("CARGO_PKG_VERSION"));
     let rendered_man_page = {
         let mut buffer = Vec::new();
-        clap_mangen::Man::new(command.clone()).render(&mut buffer)?;
+
+        // The title and `NAME` section use the name of the page, like
+        // `git-branchless-test-run`, while the rest of the page uses the
+        // command as it's invoked, like `git branchless test run`.
+        let page = clap_mangen::Man::new(command.clone().name(page_name.clone()))
+            .title(page_name.to_uppercase())
+            .source(source.clone());
+        page.render_title(&mut buffer)?;
+        page.render_name_section(&mut buffer)?;
+
+        let command = command
+            .clone()
+            .name(command_path.join(" "))
+            .display_name(page_name.clone());
+        let man = clap_mangen::Man::new(command.clone()).source(source);
+        man.render_synopsis_section(&mut buffer)?;
+        man.render_description_section(&mut buffer)?;
+        if command.get_arguments().any(|arg| !arg.is_hide_set()) {
+            man.render_options_section(&mut buffer)?;
+        }
+        if command
+            .get_subcommands()
+            .any(|subcommand| !subcommand.is_hide_set())
+        {
+            man.render_subcommands_section(&mut buffer)?;
+        }
+        if command.get_after_help().is_some() || command.get_after_long_help().is_some() {
+            man.render_extra_section(&mut buffer)?;
+        }
+        if command.get_version().is_some() {
+            man.render_version_section(&mut buffer)?;
+        }
+
+        writeln!(
+            buffer,
+            ".SH \"SEE ALSO\"\n\\fB{}\\fR(1)",
+            parent_page_name.replace('-', "\\-")
+        )?;
         buffer
     };
-    let output_path = man1_dir.join(format!("{name}.1"));
+    let output_path = man1_dir.join(format!("{page_name}.1"));
     std::fs::write(output_path, rendered_man_page)?;
     Ok(())
 }
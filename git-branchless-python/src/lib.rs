@@ -0,0 +1,318 @@
+//! Python bindings for `git-branchless`, published as the `pybranchless`
+//! module.
+//!
+//! Queries like the smartlog and the event log are answered directly from the
+//! repository, with the same library code as the `git branchless` commands.
+//! The smartlog graph and successor queries are shared with the C bindings
+//! (see the `git-branchless-ffi` crate).
+//! Commands which modify the repository (`hide` and `move`) are run as
+//! `git branchless` subprocesses instead, so that they behave exactly as they
+//! do on the command line, including running hooks and recording events.
+
+#![warn(missing_docs)]
+#![warn(
+    clippy::all,
+    clippy::as_conversions,
+    clippy::clone_on_ref_ptr,
+    clippy::dbg_macro
+)]
+#![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use branchless_ffi::{get_graph_commits, get_successor, GraphCommit, Successor};
+use bstr::ByteSlice;
+use git_branchless_opts::Revset;
+use itertools::Itertools;
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::{MaybeZeroOid, NonZeroOid};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Convert an error from the library into a Python `RuntimeError`.
+fn to_py_err(err: eyre::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{err:#}"))
+}
+
+fn parse_oid(oid: &str) -> PyResult<NonZeroOid> {
+    oid.parse()
+        .map_err(|_| PyValueError::new_err(format!("Not a commit hash: {oid}")))
+}
+
+fn maybe_zero_oid_to_py(oid: MaybeZeroOid) -> Option<String> {
+    match oid {
+        MaybeZeroOid::NonZero(oid) => Some(oid.to_string()),
+        MaybeZeroOid::Zero => None,
+    }
+}
+
+/// Convert an event into a dictionary with its `type` and fields. Missing OIDs
+/// are represented as `None`.
+fn event_to_py(py: Python<'_>, event: &Event) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    let timestamp = event
+        .get_timestamp()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default();
+    dict.set_item("timestamp", timestamp)?;
+    dict.set_item("event_tx_id", event.get_event_tx_id().to_string())?;
+    match event {
+        Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid,
+            new_commit_oid,
+        } => {
+            dict.set_item("type", "rewrite")?;
+            dict.set_item("old_commit_oid", maybe_zero_oid_to_py(*old_commit_oid))?;
+            dict.set_item("new_commit_oid", maybe_zero_oid_to_py(*new_commit_oid))?;
+        }
+        Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid,
+            new_oid,
+            message,
+        } => {
+            dict.set_item("type", "ref_update")?;
+            dict.set_item("ref_name", ref_name.as_str())?;
+            dict.set_item("old_oid", maybe_zero_oid_to_py(*old_oid))?;
+            dict.set_item("new_oid", maybe_zero_oid_to_py(*new_oid))?;
+            dict.set_item("message", message.as_ref().map(|message| message.as_str()))?;
+        }
+        Event::CommitEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            dict.set_item("type", "commit")?;
+            dict.set_item("commit_oid", commit_oid.to_string())?;
+        }
+        Event::ObsoleteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+            message,
+        } => {
+            dict.set_item("type", "obsolete")?;
+            dict.set_item("commit_oid", commit_oid.to_string())?;
+            dict.set_item("message", message)?;
+        }
+        Event::UnobsoleteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            dict.set_item("type", "unobsolete")?;
+            dict.set_item("commit_oid", commit_oid.to_string())?;
+        }
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            original_commit_oid,
+            new_commit_oid,
+            kind,
+        } => {
+            dict.set_item("type", "copy")?;
+            dict.set_item("original_commit_oid", original_commit_oid.to_string())?;
+            dict.set_item("new_commit_oid", new_commit_oid.to_string())?;
+            dict.set_item("kind", format!("{kind:?}").to_lowercase())?;
+        }
+        Event::WorkingCopySnapshot {
+            timestamp: _,
+            event_tx_id: _,
+            head_oid,
+            commit_oid,
+            ref_name,
+        } => {
+            dict.set_item("type", "working_copy_snapshot")?;
+            dict.set_item("head_oid", maybe_zero_oid_to_py(*head_oid))?;
+            dict.set_item("commit_oid", commit_oid.to_string())?;
+            dict.set_item(
+                "ref_name",
+                ref_name.as_ref().map(|ref_name| ref_name.as_str()),
+            )?;
+        }
+    }
+    Ok(dict.to_object(py))
+}
+
+/// A repository managed by `git-branchless`.
+#[pyclass]
+struct Repo {
+    path: PathBuf,
+}
+
+impl Repo {
+    fn open_repo(&self) -> eyre::Result<lib::git::Repo> {
+        Ok(lib::git::Repo::from_dir(&self.path)?)
+    }
+
+    fn get_successor(&self, oid: &str) -> PyResult<Successor> {
+        let oid = parse_oid(oid)?;
+        let repo = self.open_repo().map_err(to_py_err)?;
+        get_successor(&repo, oid).map_err(to_py_err)
+    }
+
+    /// Run `git branchless` with the given arguments in the repository, and
+    /// return its exit code and output.
+    fn run_command(&self, py: Python<'_>, args: &[String]) -> PyResult<PyObject> {
+        let output = py
+            .allow_threads(|| {
+                Command::new("git")
+                    .arg("branchless")
+                    .args(args)
+                    .current_dir(&self.path)
+                    .stdin(Stdio::null())
+                    .output()
+            })
+            .map_err(|err| to_py_err(err.into()))?;
+        let dict = PyDict::new(py);
+        dict.set_item("exit_code", output.status.code().unwrap_or(1))?;
+        dict.set_item("stdout", output.stdout.to_str_lossy())?;
+        dict.set_item("stderr", output.stderr.to_str_lossy())?;
+        Ok(dict.to_object(py))
+    }
+}
+
+#[pymethods]
+impl Repo {
+    /// Open the repository containing the given path.
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let repo = lib::git::Repo::from_dir(&path).map_err(|err| to_py_err(err.into()))?;
+        let path = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path())
+            .to_path_buf();
+        Ok(Self { path })
+    }
+
+    /// Get the commits shown in the smartlog for the given revset (by default,
+    /// the same commits as `git smartlog`), in topological order. Each commit
+    /// is a dictionary with its `oid`, `parents`, `summary` and `branches`,
+    /// and whether it `is_head`, `is_main` or `is_obsolete`.
+    #[pyo3(signature = (revset = None))]
+    fn smartlog(&self, py: Python<'_>, revset: Option<String>) -> PyResult<Vec<PyObject>> {
+        let repo = self.open_repo().map_err(to_py_err)?;
+        let revset = match revset {
+            Some(revset) => Revset(revset),
+            None => Revset::default_smartlog_revset(),
+        };
+        let graph_commits = match get_graph_commits(&repo, revset).map_err(to_py_err)? {
+            Ok(graph_commits) => graph_commits,
+            Err(err) => return Err(PyValueError::new_err(err)),
+        };
+
+        let mut nodes = Vec::new();
+        for graph_commit in graph_commits {
+            let GraphCommit {
+                oid,
+                parents,
+                summary,
+                branches,
+                is_head,
+                is_main,
+                is_obsolete,
+                num_omitted_descendants: _,
+            } = graph_commit;
+            let dict = PyDict::new(py);
+            dict.set_item("oid", oid.to_string())?;
+            dict.set_item(
+                "parents",
+                parents.iter().map(|oid| oid.to_string()).collect_vec(),
+            )?;
+            dict.set_item("summary", summary)?;
+            dict.set_item("branches", branches)?;
+            dict.set_item("is_head", is_head)?;
+            dict.set_item("is_main", is_main)?;
+            dict.set_item("is_obsolete", is_obsolete)?;
+            nodes.push(dict.to_object(py));
+        }
+        Ok(nodes)
+    }
+
+    /// Get the events recorded in the event log, from oldest to newest. Each
+    /// event is a dictionary with its `type`, `timestamp` and `event_tx_id`,
+    /// along with the fields for that type of event.
+    fn events(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let repo = self.open_repo().map_err(to_py_err)?;
+        let conn = repo
+            .get_db_conn_read_only()
+            .map_err(|err| to_py_err(err.into()))?;
+        let event_log_db = EventLogDb::new_read_only(&conn).map_err(to_py_err)?;
+        let events = event_log_db.get_events().map_err(to_py_err)?;
+        events.iter().map(|event| event_to_py(py, event)).collect()
+    }
+
+    /// Whether the given commit is obsolete, such as because it was rewritten
+    /// or hidden.
+    fn is_obsolete(&self, oid: &str) -> PyResult<bool> {
+        Ok(self.get_successor(oid)?.is_obsolete)
+    }
+
+    /// Get the latest commit that the given commit was rewritten into, if any.
+    /// Returns `None` if the commit wasn't rewritten, and an empty string if
+    /// it was deleted rather than rewritten into another commit.
+    fn find_rewrite_target(&self, oid: &str) -> PyResult<Option<String>> {
+        let Successor {
+            is_obsolete: _,
+            rewrite_target,
+        } = self.get_successor(oid)?;
+        Ok(rewrite_target.map(|oid| match oid {
+            MaybeZeroOid::NonZero(oid) => oid.to_string(),
+            MaybeZeroOid::Zero => String::new(),
+        }))
+    }
+
+    /// Hide the commits in the given revsets, as with `git branchless hide`.
+    #[pyo3(signature = (revsets, recursive = false))]
+    fn hide(&self, py: Python<'_>, revsets: Vec<String>, recursive: bool) -> PyResult<PyObject> {
+        let mut args = vec!["hide".to_string()];
+        args.extend(revsets);
+        if recursive {
+            args.push("--recursive".to_string());
+        }
+        self.run_command(py, &args)
+    }
+
+    /// Move commits, as with `git branchless move`.
+    #[pyo3(signature = (source = Vec::new(), base = Vec::new(), exact = Vec::new(), dest = None, insert = false))]
+    fn r#move(
+        &self,
+        py: Python<'_>,
+        source: Vec<String>,
+        base: Vec<String>,
+        exact: Vec<String>,
+        dest: Option<String>,
+        insert: bool,
+    ) -> PyResult<PyObject> {
+        let mut args = vec!["move".to_string()];
+        for (flag, revsets) in [("--source", source), ("--base", base), ("--exact", exact)] {
+            for revset in revsets {
+                args.push(flag.to_string());
+                args.push(revset);
+            }
+        }
+        if let Some(dest) = dest {
+            args.push("--dest".to_string());
+            args.push(dest);
+        }
+        if insert {
+            args.push("--insert".to_string());
+        }
+        self.run_command(py, &args)
+    }
+}
+
+/// Python bindings for `git-branchless`.
+#[pymodule]
+pub fn pybranchless(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Repo>()?;
+    Ok(())
+}
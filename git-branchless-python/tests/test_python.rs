@@ -0,0 +1,66 @@
+use lib::testing::make_git;
+use pybranchless::pybranchless;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+#[test]
+fn test_python_module() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    let amended_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+
+    // The module is only registered here, since the interpreter can only be
+    // initialized once per process.
+    pyo3::append_to_inittab!(pybranchless);
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| -> PyResult<()> {
+        let locals = PyDict::new(py);
+        locals.set_item("path", git.repo_path.to_str().unwrap())?;
+        locals.set_item("test1_oid", test1_oid.to_string())?;
+        locals.set_item("amended_oid", amended_oid.to_string())?;
+        py.run(
+            r#"
+import pybranchless
+
+repo = pybranchless.Repo(path)
+
+nodes = {node["summary"]: node for node in repo.smartlog()}
+assert sorted(nodes) == [
+    "amended test2",
+    "create initial.txt",
+    "create test1.txt",
+    "create test2.txt",
+], nodes
+assert nodes["create initial.txt"]["is_main"]
+assert nodes["create test1.txt"]["oid"] == test1_oid
+assert nodes["create test1.txt"]["parents"] == [nodes["create initial.txt"]["oid"]]
+assert nodes["create test2.txt"]["branches"] == ["foo"]
+assert nodes["create test2.txt"]["is_obsolete"]
+assert nodes["amended test2"]["is_head"]
+
+obsolete_oid = nodes["create test2.txt"]["oid"]
+assert repo.is_obsolete(obsolete_oid)
+assert repo.find_rewrite_target(obsolete_oid) == amended_oid
+assert not repo.is_obsolete(amended_oid)
+assert repo.find_rewrite_target(amended_oid) is None
+
+assert any(event["type"] == "rewrite" for event in repo.events())
+
+try:
+    repo.smartlog("foo(")
+    assert False, "expected an invalid revset to raise an error"
+except ValueError as err:
+    assert "parse error" in str(err), err
+"#,
+            None,
+            Some(locals),
+        )
+    })?;
+
+    Ok(())
+}
@@ -6,6 +6,7 @@ use itertools::Itertools;
 use lib::core::dag::{commit_set_to_vec, Dag};
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::pager::start_pager;
 use lib::core::repo_ext::RepoExt;
 use lib::git::{CategorizedReferenceName, GitRunInfo, Repo};
 use lib::util::ExitCode;
@@ -27,6 +28,8 @@ pub fn command_main(ctx: CommandContext, args: QueryArgs) -> eyre::Result<ExitCo
         show_branches,
         raw,
     } = args;
+    let repo = Repo::from_current_dir()?;
+    let _pager = start_pager(&git_run_info, &repo, "query")?;
     query(
         &effects,
         &git_run_info,
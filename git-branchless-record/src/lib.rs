@@ -13,6 +13,7 @@ use std::collections::HashSet;
 use std::ffi::OsString;
 use std::fmt::Write;
 use std::io;
+use std::path::PathBuf;
 use std::time::SystemTime;
 
 use cursive::backends::crossterm;
@@ -23,7 +24,7 @@ use eden_dag::DagAlgorithm;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::RecordArgs;
 use git_record::Recorder;
-use git_record::{RecordError, RecordState};
+use git_record::{FileState, RecordError, RecordState};
 use itertools::Itertools;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions};
 use lib::core::config::get_restack_preserve_timestamps;
@@ -39,8 +40,8 @@ use lib::core::rewrite::{
 };
 use lib::git::{
     process_diff_for_record, update_index, CategorizedReferenceName, FileMode, GitRunInfo,
-    NonZeroOid, Repo, ResolvedReferenceInfo, Stage, UpdateIndexCommand, WorkingCopyChangesType,
-    WorkingCopySnapshot,
+    NonZeroOid, Repo, ResolvedReferenceInfo, Stage, Tree, UpdateIndexCommand,
+    WorkingCopyChangesType, WorkingCopySnapshot,
 };
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
@@ -82,7 +83,7 @@ fn record(
     insert: bool,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "record")?;
@@ -235,14 +236,46 @@ fn record_interactive(
     event_tx_id: EventTransactionId,
     message: Option<&str>,
 ) -> eyre::Result<ExitCode> {
+    let old_tree = snapshot.commit_stage0.get_tree()?;
+    let new_tree = snapshot.commit_unstaged.get_tree()?;
+    let file_states = match select_changes_interactively(effects, repo, &old_tree, &new_tree)? {
+        Some(file_states) => file_states,
+        None => {
+            println!("Aborted.");
+            return Ok(ExitCode(1));
+        }
+    };
+    stage_selected_changes(git_run_info, repo, event_tx_id, file_states)?;
+
+    let args = {
+        let mut args = vec!["commit"];
+        if let Some(message) = message {
+            args.extend(["--message", message]);
+        }
+        args
+    };
+    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+}
+
+/// Have the user interactively select which of the changes between
+/// `old_tree` and `new_tree` to keep, by file, hunk, or line, or by editing
+/// hunks directly.
+///
+/// Returns the resulting file states, or `None` if the user cancelled. Use
+/// [`stage_selected_changes`] to apply the selection to the index.
+#[instrument]
+pub fn select_changes_interactively(
+    effects: &Effects,
+    repo: &Repo,
+    old_tree: &Tree,
+    new_tree: &Tree,
+) -> eyre::Result<Option<Vec<(PathBuf, FileState<'static>)>>> {
     let file_states = {
         let (effects, _progress) = effects.start_operation(OperationType::CalculateDiff);
-        let old_tree = snapshot.commit_stage0.get_tree()?;
-        let new_tree = snapshot.commit_unstaged.get_tree()?;
         let diff = repo.get_diff_between_trees(
             &effects,
-            Some(&old_tree),
-            &new_tree,
+            Some(old_tree),
+            new_tree,
             // We manually add context to the git-record output, so suppress the context lines here.
             0,
         )?;
@@ -258,18 +291,22 @@ fn record_interactive(
     let siv = siv.into_runner();
 
     let recorder = Recorder::new(record_state);
-    let result = recorder.run(siv);
-    let RecordState {
-        file_states: result,
-    } = match result {
-        Ok(result) => result,
-        Err(RecordError::Cancelled) => {
-            println!("Aborted.");
-            return Ok(ExitCode(1));
-        }
-    };
+    match recorder.run(siv) {
+        Ok(RecordState { file_states }) => Ok(Some(file_states)),
+        Err(RecordError::Cancelled) => Ok(None),
+    }
+}
 
-    let update_index_script: Vec<UpdateIndexCommand> = result
+/// Update the index so that each of the given files contains only its
+/// selected changes, as returned by [`select_changes_interactively`].
+#[instrument]
+pub fn stage_selected_changes(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    file_states: Vec<(PathBuf, FileState)>,
+) -> eyre::Result<()> {
+    let update_index_script: Vec<UpdateIndexCommand> = file_states
         .into_iter()
         .map(|(path, file_state)| -> eyre::Result<UpdateIndexCommand> {
             let (selected, _unselected) = file_state.get_selected_contents();
@@ -292,15 +329,7 @@ fn record_interactive(
         event_tx_id,
         &update_index_script,
     )?;
-
-    let args = {
-        let mut args = vec!["commit"];
-        if let Some(message) = message {
-            args.extend(["--message", message]);
-        }
-        args
-    };
-    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+    Ok(())
 }
 
 #[instrument]
@@ -311,7 +340,7 @@ fn insert_before_siblings(
     event_tx_id: EventTransactionId,
 ) -> eyre::Result<ExitCode> {
     // Reopen the repository since references may have changed.
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let references_snapshot = repo.get_references_snapshot()?;
@@ -375,7 +404,7 @@ fn insert_before_siblings(
                 }
                 let thread_pool = ThreadPoolBuilder::new().build()?;
                 let repo_pool = RepoResource::new_pool(&repo)?;
-                builder.build(effects, &thread_pool, &repo_pool)?
+                builder.build(effects, git_run_info, &thread_pool, &repo_pool)?
             }
         };
 
@@ -396,15 +396,18 @@ fn test_record_insert_merge_conflict() -> eyre::Result<()> {
 
     git.write_file_txt("test1", "new contents 2\n")?;
     {
-        let (stdout, _stderr) =
-            git.branchless("record", &["-m", "update test1.txt", "--insert"])?;
+        let (stdout, stderr) = git.branchless("record", &["-m", "update test1.txt", "--insert"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: c36bf7c update test1.txt
+        This operation would cause a merge conflict:
+        - (1 conflicting file) ae32734 create test1.txt
+        To resolve merge conflicts, run: git move -m -s 'siblings(.)'
+        "###);
         insta::assert_snapshot!(stdout, @r###"
         [detached HEAD c36bf7c] update test1.txt
          1 file changed, 1 insertion(+), 1 deletion(-)
         Attempting rebase in-memory...
-        This operation would cause a merge conflict:
-        - (1 conflicting file) ae32734 create test1.txt
-        To resolve merge conflicts, run: git move -m -s 'siblings(.)'
         "###);
     }
 
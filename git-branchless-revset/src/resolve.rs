@@ -1,9 +1,8 @@
-use std::fmt::Write;
-
 use eyre::WrapErr;
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
+use lib::core::user_error::{UserError, UserErrorCode};
 use lib::git::Repo;
 use thiserror::Error;
 use tracing::instrument;
@@ -17,39 +16,58 @@ use crate::{eval, parse};
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum ResolveError {
+    /// The revset expression couldn't be parsed.
     #[error("parse error in {expr:?}: {source}")]
-    ParseError { expr: String, source: ParseError },
+    ParseError {
+        /// The revset expression.
+        expr: String,
+
+        /// The underlying error.
+        source: ParseError,
+    },
 
+    /// The revset expression couldn't be evaluated.
     #[error("evaluation error in {expr:?}: {source}")]
-    EvalError { expr: String, source: EvalError },
+    EvalError {
+        /// The revset expression.
+        expr: String,
 
+        /// The underlying error.
+        source: EvalError,
+    },
+
+    /// Querying the commit graph failed.
     #[error("DAG query error: {source}")]
-    DagError { source: eden_dag::Error },
+    DagError {
+        /// The underlying error.
+        source: eden_dag::Error,
+    },
 
+    /// Some other error occurred.
     #[error(transparent)]
-    OtherError { source: eyre::Error },
+    OtherError {
+        /// The underlying error.
+        source: eyre::Error,
+    },
 }
 
 impl ResolveError {
+    /// Report the error to the user. Errors which aren't the user's fault are
+    /// returned instead.
     pub fn describe(self, effects: &Effects) -> eyre::Result<()> {
-        match self {
+        let message = match self {
             ResolveError::ParseError { expr, source } => {
-                writeln!(
-                    effects.get_error_stream(),
-                    "Parse error for expression '{expr}': {source}"
-                )?;
-                Ok(())
+                format!("Parse error for expression '{expr}': {source}")
             }
             ResolveError::EvalError { expr, source } => {
-                writeln!(
-                    effects.get_error_stream(),
-                    "Evaluation error for expression '{expr}': {source}"
-                )?;
-                Ok(())
+                format!("Evaluation error for expression '{expr}': {source}")
             }
-            ResolveError::DagError { source } => Err(source.into()),
-            ResolveError::OtherError { source } => Err(source),
-        }
+            ResolveError::DagError { source } => return Err(source.into()),
+            ResolveError::OtherError { source } => return Err(source),
+        };
+        // The callers return their own exit codes.
+        let _exit_code = UserError::new(UserErrorCode::InvalidRevset, message).report(effects)?;
+        Ok(())
     }
 }
 
@@ -29,12 +29,14 @@ use eyre::Context;
 use tracing::{instrument, warn};
 
 use lib::core::config::{
-    get_comment_char, get_commit_template, get_editor, get_restack_preserve_timestamps,
+    get_comment_char, get_commit_template, get_editor, get_hint_enabled, get_hint_string,
+    get_restack_preserve_timestamps, print_hint_suppression_notice, Hint,
 };
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::gerrit::preserve_change_id;
 use lib::core::node_descriptors::{render_node_descriptors, CommitOidDescriptor, NodeObject};
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
@@ -180,7 +182,7 @@ pub fn reword(
                 effects.get_output_stream(),
                 "Aborting. The message was not edited; nothing to do."
             )?;
-            return Ok(ExitCode(1));
+            return Ok(ExitCode::NOTHING_TO_DO);
         }
         PrepareMessagesResult::EmptyMessage => {
             writeln!(
@@ -256,6 +258,9 @@ pub fn reword(
 
         for commit in commits.iter() {
             let message = messages.get(&commit.get_oid()).unwrap();
+            // Keep the commit in the same Gerrit change, even if the trailer
+            // was removed while editing.
+            let message = preserve_change_id(&commit.get_message_raw()?.to_str_lossy(), message);
             // This looks funny, but just means "leave everything but the message as is"
             let replacement_oid =
                 commit.amend_commit(None, None, None, Some(message.as_str()), None)?;
@@ -263,7 +268,7 @@ pub fn reword(
             builder.replace_commit(commit.get_oid(), replacement_oid)?;
         }
 
-        match builder.build(effects, &pool, &repo_pool)? {
+        match builder.build(effects, git_run_info, &pool, &repo_pool)? {
             Ok(Some(rebase_plan)) => rebase_plan,
             Ok(None) => {
                 eyre::bail!(
@@ -691,11 +696,13 @@ fn render_status_report(
         )?;
     }
 
-    if num_commits != 1 {
+    if num_commits != 1 && get_hint_enabled(effects, repo, Hint::RewordUndo)? {
         writeln!(
             effects.get_output_stream(),
-            "Reworded {num_commits} commits. If this was unintentional, run: git undo",
+            "{}: reworded {num_commits} commits. If this was unintentional, run: git undo",
+            effects.get_glyphs().render(get_hint_string(effects))?,
         )?;
+        print_hint_suppression_notice(effects, Hint::RewordUndo)?;
     }
 
     Ok(())
@@ -0,0 +1,92 @@
+use git_branchless_revset::resolve_default_smartlog_commits;
+use git_branchless_smartlog::{make_smartlog_graph, write_graph};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::core::node_descriptors::{
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor, Redactor,
+};
+use lib::core::repo_ext::RepoExt;
+use lib::testing::{make_git, GitInitOptions, SYNTHETIC_HISTORIES};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Build and render the smartlog for synthetic repositories. This requires
+/// the `TEST_GIT` and `TEST_GIT_EXEC_PATH` environment variables to be set, as
+/// for the integration tests.
+fn bench_smartlog(c: &mut Criterion) {
+    let mut group = c.benchmark_group("smartlog");
+    group.sample_size(10);
+    for (name, options) in SYNTHETIC_HISTORIES {
+        group.bench_function(BenchmarkId::new("smartlog", name), |b| {
+            let git = make_git().unwrap();
+            git.init_repo_with_options(&GitInitOptions {
+                make_initial_commit: false,
+                run_branchless_init: false,
+            })
+            .unwrap();
+            git.generate_synthetic_history(options).unwrap();
+
+            let repo = git.get_repo().unwrap();
+            let effects = Effects::new_suppress_for_test(Glyphs::text());
+            let head_info = repo.get_head_info().unwrap();
+            let references_snapshot = repo.get_references_snapshot().unwrap();
+            let conn = repo.get_db_conn().unwrap();
+            let event_log_db = EventLogDb::new(&conn).unwrap();
+            let event_replayer =
+                EventReplayer::from_event_log_db(&effects, &repo, &event_log_db).unwrap();
+            let event_cursor = event_replayer.make_default_cursor();
+            let mut dag = Dag::open_and_sync(
+                &effects,
+                &repo,
+                &event_replayer,
+                event_cursor,
+                &references_snapshot,
+            )
+            .unwrap();
+            let commits = resolve_default_smartlog_commits(&effects, &repo, &mut dag).unwrap();
+
+            b.iter(|| {
+                let graph = make_smartlog_graph(
+                    &effects,
+                    &repo,
+                    &dag,
+                    &event_replayer,
+                    event_cursor,
+                    &commits,
+                )
+                .unwrap();
+                let mut num_lines = 0;
+                write_graph(
+                    &effects,
+                    &repo,
+                    &dag,
+                    &graph,
+                    references_snapshot.head_oid,
+                    &mut [
+                        &mut CommitOidDescriptor::new(false).unwrap(),
+                        &mut BranchesDescriptor::new(
+                            &repo,
+                            &head_info,
+                            &references_snapshot,
+                            &Redactor::Disabled,
+                        )
+                        .unwrap(),
+                        &mut CommitMessageDescriptor::new(&Redactor::Disabled).unwrap(),
+                    ],
+                    &mut |line| {
+                        effects.get_glyphs().render(line)?;
+                        num_lines += 1;
+                        Ok(())
+                    },
+                )
+                .unwrap();
+                num_lines
+            });
+        });
+    }
+}
+
+criterion_group!(benches, bench_smartlog);
+criterion_main!(benches);
@@ -16,10 +16,13 @@ use std::cmp::Ordering;
 use std::fmt::Write;
 use std::time::SystemTime;
 
-use eden_dag::DagAlgorithm;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::{Revset, SmartlogArgs};
-use lib::core::config::{get_hint_enabled, get_hint_string, print_hint_suppression_notice, Hint};
+use lib::core::child_index::ChildIndexDb;
+use lib::core::config::{
+    get_hint_enabled, get_hint_string, get_smartlog_highlight_changes,
+    print_hint_suppression_notice, Hint,
+};
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::find_rewrite_target;
 use lib::util::ExitCode;
@@ -30,19 +33,22 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::Pluralize;
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
-    DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    BranchesDescriptor, CiStatusDescriptor, CodeReviewDescriptor, CommitMessageDescriptor,
+    CommitOidDescriptor, CopiedCommitDescriptor, DifferentialRevisionDescriptor,
+    IssueTrailersDescriptor, ObsolescenceExplanationDescriptor, Redactor, RelativeTimeDescriptor,
+    WorktreesDescriptor,
 };
+use lib::core::pager::start_pager;
 use lib::git::{GitRunInfo, Repo};
 
-pub use graph::{make_smartlog_graph, SmartlogGraph};
-pub use render::{render_graph, SmartlogOptions};
+pub use graph::{highlight_transaction_changes, make_smartlog_graph, CommitChange, SmartlogGraph};
+pub use query::{get_graph_commits, get_successor, GraphCommit, Successor};
+pub use render::{render_graph, write_graph, SmartlogOptions};
 
 use git_branchless_revset::resolve_commits;
 
 mod graph {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
     use std::convert::TryFrom;
 
     use eden_dag::DagAlgorithm;
@@ -51,9 +57,10 @@ mod graph {
 
     use lib::core::dag::{commit_set_to_vec, CommitSet, CommitVertex, Dag};
     use lib::core::effects::{Effects, OperationType};
-    use lib::core::eventlog::{EventCursor, EventReplayer};
+    use lib::core::eventlog::{Event, EventCursor, EventReplayer, EventTransactionId};
+    use lib::core::merge_base::MergeBaseDb;
     use lib::core::node_descriptors::NodeObject;
-    use lib::git::{Commit, Time};
+    use lib::git::{CategorizedReferenceName, Commit, MaybeZeroOid, Time};
     use lib::git::{NonZeroOid, Repo};
 
     #[derive(Debug)]
@@ -67,11 +74,36 @@ mod graph {
         pub oid: NonZeroOid,
         pub is_merge_child: bool,
     }
+
+    /// How a commit was changed by the transaction being highlighted in the
+    /// smartlog. If a commit was changed in several ways, the greatest one
+    /// applies.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum CommitChange {
+        /// A branch was created at or moved to the commit.
+        BranchMoved,
+
+        /// The commit was created by rewriting another commit.
+        Rewritten,
+
+        /// The commit was created (or copied or unhidden).
+        Created,
+
+        /// The commit was hidden.
+        Hidden,
+    }
+
     /// Node contained in the smartlog commit graph.
+    ///
+    /// Only the commit's OID is stored, rather than the commit object itself,
+    /// so that building the graph for a large set of commits (such as with
+    /// `git smartlog --hidden`) doesn't need to load every commit. The commit
+    /// is looked up when it's rendered, or when its timestamp is needed to
+    /// order it among its siblings.
     #[derive(Debug)]
-    pub struct Node<'repo> {
-        /// The underlying commit object.
-        pub object: NodeObject<'repo>,
+    pub struct Node {
+        /// The OID of the underlying commit.
+        pub oid: NonZeroOid,
 
         /// The OIDs of the parent nodes in the smartlog commit graph.
         ///
@@ -117,29 +149,49 @@ mod graph {
         /// This allows us to indicate a "false head" to the user. Otherwise,
         /// this commit would look like a normal, descendant-less head.
         pub num_omitted_descendants: usize,
+
+        /// How this commit was changed by the transaction being highlighted,
+        /// if at all. See `highlight_transaction_changes`.
+        pub change: Option<CommitChange>,
     }
 
     /// Graph of commits that the user is working on.
     pub struct SmartlogGraph<'repo> {
+        repo: &'repo Repo,
+
         /// The nodes in the graph for use in rendering the smartlog.
-        pub nodes: HashMap<NonZeroOid, Node<'repo>>,
+        pub nodes: HashMap<NonZeroOid, Node>,
     }
 
     impl<'repo> SmartlogGraph<'repo> {
+        /// Look up the object for the node with the given OID, for rendering.
+        pub fn get_node_object(&self, oid: NonZeroOid) -> eyre::Result<NodeObject<'repo>> {
+            let object = match self.repo.find_commit(oid)? {
+                Some(commit) => NodeObject::Commit { commit },
+                None => NodeObject::GarbageCollected { oid },
+            };
+            Ok(object)
+        }
+
+        /// Look up the commit time of the node with the given OID, or `None`
+        /// if the commit has been garbage collected.
+        pub fn get_commit_time(&self, oid: NonZeroOid) -> eyre::Result<Option<Time>> {
+            let commit_time = self.repo.find_commit(oid)?.map(|commit| commit.get_time());
+            Ok(commit_time)
+        }
+
         /// Get a list of commits stored in the graph.
         /// Returns commits in descending commit time order.
-        pub fn get_commits(&self) -> Vec<Commit<'repo>> {
-            let mut commits = self
-                .nodes
-                .values()
-                .filter_map(|node| match &node.object {
-                    NodeObject::Commit { commit } => Some(commit.clone()),
-                    NodeObject::GarbageCollected { oid: _ } => None,
-                })
-                .collect::<Vec<Commit<'repo>>>();
+        pub fn get_commits(&self) -> eyre::Result<Vec<Commit<'repo>>> {
+            let mut commits = Vec::new();
+            for oid in self.nodes.keys() {
+                if let Some(commit) = self.repo.find_commit(*oid)? {
+                    commits.push(commit);
+                }
+            }
             commits.sort_by_key(|commit| (commit.get_committer().get_time(), commit.get_oid()));
             commits.reverse();
-            commits
+            Ok(commits)
         }
     }
 
@@ -160,38 +212,56 @@ mod graph {
         effects: &Effects,
         repo: &'repo Repo,
         dag: &Dag,
+        merge_base_db: &MergeBaseDb,
         commits: &CommitSet,
     ) -> eyre::Result<SmartlogGraph<'repo>> {
+        // The merge-bases with the main branch can be cached as long as it
+        // consists of a single commit, which is almost always the case.
+        let main_branch_oid = match commit_set_to_vec(&dag.main_branch_commit)?.as_slice() {
+            [main_branch_oid] => Some(*main_branch_oid),
+            _ => None,
+        };
+
+        // Determine which commits will be in the graph using only the DAG
+        // first, so that each commit object is loaded just once below, rather
+        // than once for each commit which shares its merge-base with the main
+        // branch.
+        let vertex_oids: HashSet<NonZeroOid> = {
+            let mut result = HashSet::new();
+            for vertex_oid in commit_set_to_vec(commits)? {
+                let merge_base_oids = match main_branch_oid {
+                    Some(main_branch_oid) => {
+                        merge_base_db.get_merge_bases(dag, main_branch_oid, vertex_oid)?
+                    }
+                    None => commit_set_to_vec(
+                        &dag.query()
+                            .gca_all(dag.main_branch_commit.union(&CommitSet::from(vertex_oid)))?,
+                    )?,
+                };
+                result.insert(vertex_oid);
+                result.extend(merge_base_oids);
+            }
+            result
+        };
+
+        let obsolete_commits = dag.query_obsolete_commits();
         let mut graph: HashMap<NonZeroOid, Node> = {
             let mut result = HashMap::new();
-            for vertex in commit_set_to_vec(commits)? {
-                let vertex = CommitSet::from(vertex);
-                let merge_bases = dag.query().gca_all(dag.main_branch_commit.union(&vertex))?;
-                let vertices = vertex.union(&merge_bases);
-
-                for oid in commit_set_to_vec(&vertices)? {
-                    let object = match repo.find_commit(oid)? {
-                        Some(commit) => NodeObject::Commit { commit },
-                        None => {
-                            // Assume that this commit was garbage collected.
-                            NodeObject::GarbageCollected { oid }
-                        }
-                    };
-
-                    result.insert(
+            for oid in vertex_oids {
+                result.insert(
+                    oid,
+                    Node {
                         oid,
-                        Node {
-                            object,
-                            parents: Vec::new(),  // populated below
-                            children: Vec::new(), // populated below
-                            ancestor_info: None,
-                            descendants: Vec::new(), // populated below
-                            is_main: dag.is_public_commit(oid)?,
-                            is_obsolete: dag.query_obsolete_commits().contains(&oid.into())?,
-                            num_omitted_descendants: 0, // populated below
-                        },
-                    );
-                }
+                        parents: Vec::new(),  // populated below
+                        children: Vec::new(), // populated below
+                        ancestor_info: None,
+                        descendants: Vec::new(), // populated below
+                        is_main: dag.is_public_commit(oid)?,
+                        is_obsolete: obsolete_commits.contains(&oid.into())?,
+                        num_omitted_descendants: 0, // populated below
+                        change: None,
+                    },
+                );
             }
             result
         };
@@ -235,10 +305,18 @@ mod graph {
                 // Find the nearest ancestor that is included in the graph and
                 // also on the same branch.
 
-                let parent_set = CommitSet::from(excluded_parent_vertex);
-                let merge_base = dag
-                    .query()
-                    .gca_one(dag.main_branch_commit.union(&parent_set))?;
+                let parent_set = CommitSet::from(excluded_parent_vertex.clone());
+                let merge_base = match main_branch_oid {
+                    Some(main_branch_oid) => {
+                        let excluded_parent_oid = NonZeroOid::try_from(excluded_parent_vertex)?;
+                        merge_base_db
+                            .get_merge_base(dag, main_branch_oid, excluded_parent_oid)?
+                            .map(CommitVertex::from)
+                    }
+                    None => dag
+                        .query()
+                        .gca_one(dag.main_branch_commit.union(&parent_set))?,
+                };
 
                 let path_to_main_branch = match merge_base {
                     Some(merge_base) => {
@@ -310,26 +388,25 @@ mod graph {
             node.num_omitted_descendants = descendants_not_in_graph.count()?;
         }
 
-        Ok(SmartlogGraph { nodes: graph })
+        Ok(SmartlogGraph { repo, nodes: graph })
     }
 
     /// Sort children nodes of the commit graph in a standard order, for determinism
     /// in output.
-    fn sort_children(graph: &mut SmartlogGraph) {
-        let commit_times: HashMap<NonZeroOid, Option<Time>> = graph
-            .nodes
-            .iter()
-            .map(|(oid, node)| {
-                (
-                    *oid,
-                    match &node.object {
-                        NodeObject::Commit { commit } => Some(commit.get_time()),
-                        NodeObject::GarbageCollected { oid: _ } => None,
-                    },
-                )
-            })
-            .collect();
+    fn sort_children(graph: &mut SmartlogGraph) -> eyre::Result<()> {
+        // Only the commits with siblings need to be looked up to be ordered.
+        let mut commit_times: HashMap<NonZeroOid, Option<Time>> = HashMap::new();
+        for node in graph.nodes.values() {
+            if node.children.len() > 1 {
+                for ChildInfo { oid, .. } in node.children.iter() {
+                    commit_times.insert(*oid, graph.get_commit_time(*oid)?);
+                }
+            }
+        }
         for node in graph.nodes.values_mut() {
+            if node.children.len() <= 1 {
+                continue;
+            }
             node.children.sort_by_key(
                 |ChildInfo {
                      oid,
@@ -337,6 +414,96 @@ mod graph {
                  }| (&commit_times[oid], *is_merge_child, oid.to_string()),
             );
         }
+        Ok(())
+    }
+
+    /// Mark the nodes for the commits which were changed by the given
+    /// transaction, so that they're highlighted when the graph is rendered.
+    /// This is used to show the effect of a command in the smartlog printed
+    /// at the end of it.
+    pub fn highlight_transaction_changes(
+        graph: &mut SmartlogGraph,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        event_tx_id: EventTransactionId,
+    ) {
+        let mut mark = |oid: NonZeroOid, change: CommitChange| {
+            if let Some(node) = graph.nodes.get_mut(&oid) {
+                node.change = node.change.max(Some(change));
+            }
+        };
+        for event in event_replayer.get_tx_events(event_tx_id, event_cursor) {
+            match event {
+                Event::CommitEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    commit_oid,
+                }
+                | Event::UnobsoleteEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    commit_oid,
+                }
+                | Event::CopyEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    original_commit_oid: _,
+                    new_commit_oid: commit_oid,
+                    kind: _,
+                } => mark(*commit_oid, CommitChange::Created),
+
+                Event::ObsoleteEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    commit_oid,
+                    message: _,
+                } => mark(*commit_oid, CommitChange::Hidden),
+
+                Event::RewriteEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    old_commit_oid,
+                    new_commit_oid,
+                } => match (old_commit_oid, new_commit_oid) {
+                    (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::NonZero(new_oid)) => {
+                        mark(*old_oid, CommitChange::Hidden);
+                        mark(*new_oid, CommitChange::Rewritten);
+                    }
+                    (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::Zero) => {
+                        mark(*old_oid, CommitChange::Hidden);
+                    }
+                    (MaybeZeroOid::Zero, MaybeZeroOid::NonZero(new_oid)) => {
+                        mark(*new_oid, CommitChange::Created);
+                    }
+                    (MaybeZeroOid::Zero, MaybeZeroOid::Zero) => {}
+                },
+
+                Event::RefUpdateEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    ref_name,
+                    old_oid: _,
+                    new_oid: MaybeZeroOid::NonZero(new_oid),
+                    message: _,
+                } => match CategorizedReferenceName::new(ref_name) {
+                    CategorizedReferenceName::LocalBranch { .. } => {
+                        mark(*new_oid, CommitChange::BranchMoved)
+                    }
+                    CategorizedReferenceName::RemoteBranch { .. }
+                    | CategorizedReferenceName::OtherRef { .. } => {}
+                },
+
+                Event::RefUpdateEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    ref_name: _,
+                    old_oid: _,
+                    new_oid: MaybeZeroOid::Zero,
+                    message: _,
+                }
+                | Event::WorkingCopySnapshot { .. } => {}
+            }
+        }
     }
 
     /// Construct the smartlog graph for the repo.
@@ -363,19 +530,162 @@ mod graph {
                 mark_commit_reachable(repo, oid)?;
             }
 
-            build_graph(&effects, repo, dag, &commits)?
+            let conn = repo.get_db_conn()?;
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+            build_graph(&effects, repo, dag, &merge_base_db, &commits)?
         };
-        sort_children(&mut graph);
+        sort_children(&mut graph)?;
         Ok(graph)
     }
 }
 
+mod query {
+    use std::collections::HashSet;
+
+    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use git_branchless_revset::{resolve_commits, ResolveError};
+    use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
+    use lib::core::effects::Effects;
+    use lib::core::eventlog::{CommitActivityStatus, EventCursor, EventLogDb, EventReplayer};
+    use lib::core::repo_ext::RepoExt;
+    use lib::core::rewrite::find_rewrite_target;
+    use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, Repo};
+    use tracing::instrument;
+
+    use super::make_smartlog_graph;
+
+    /// A commit in the smartlog graph, as returned by [`get_graph_commits`].
+    /// This is the representation of the smartlog shared by the plumbing
+    /// commands, `git branchless serve`, and the language bindings.
+    #[derive(Clone, Debug)]
+    pub struct GraphCommit {
+        /// The OID of the commit.
+        pub oid: NonZeroOid,
+
+        /// The parents of the commit in the graph, which may be ancestors
+        /// rather than parents of the underlying commit, since uninteresting
+        /// commits are omitted.
+        pub parents: Vec<NonZeroOid>,
+
+        /// The first line of the commit message.
+        pub summary: String,
+
+        /// The names of the local branches pointing to the commit, in sorted
+        /// order.
+        pub branches: Vec<String>,
+
+        /// Whether the commit is checked out.
+        pub is_head: bool,
+
+        /// Whether the commit is on the main branch.
+        pub is_main: bool,
+
+        /// Whether the commit is obsolete, such as because it was rewritten or
+        /// hidden.
+        pub is_obsolete: bool,
+
+        /// The number of descendants of the commit which aren't shown in the
+        /// graph.
+        pub num_omitted_descendants: usize,
+    }
+
+    /// Get the commits in the smartlog graph for the given revset, in
+    /// topological order. If the revset couldn't be resolved, returns the
+    /// error in the inner `Result`.
+    #[instrument]
+    pub fn get_graph_commits(
+        effects: &Effects,
+        repo: &Repo,
+        revset: Revset,
+        resolve_revset_options: &ResolveRevsetOptions,
+    ) -> eyre::Result<Result<Vec<GraphCommit>, ResolveError>> {
+        let references_snapshot = repo.get_references_snapshot()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let mut dag = Dag::open_and_sync(
+            effects,
+            repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        let commits =
+            match resolve_commits(effects, repo, &mut dag, &[revset], resolve_revset_options) {
+                Ok(commit_sets) => union_all(&commit_sets),
+                Err(err) => return Ok(Err(err)),
+            };
+        let graph =
+            make_smartlog_graph(effects, repo, &dag, &event_replayer, event_cursor, &commits)?;
+
+        let node_oids: CommitSet = graph.nodes.keys().copied().collect();
+        let mut graph_commits = Vec::new();
+        for commit in sorted_commit_set(repo, &dag, &node_oids)? {
+            let node = &graph.nodes[&commit.get_oid()];
+            let mut branches: Vec<String> = references_snapshot
+                .branch_oid_to_names
+                .get(&node.oid)
+                .unwrap_or(&HashSet::new())
+                .iter()
+                .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                .collect();
+            branches.sort_unstable();
+            graph_commits.push(GraphCommit {
+                oid: node.oid,
+                parents: node.parents.clone(),
+                summary: String::from_utf8_lossy(&commit.get_summary()?).into_owned(),
+                branches,
+                is_head: references_snapshot.head_oid == Some(node.oid),
+                is_main: node.is_main,
+                is_obsolete: node.is_obsolete,
+                num_omitted_descendants: node.num_omitted_descendants,
+            });
+        }
+        Ok(Ok(graph_commits))
+    }
+
+    /// Whether a commit is obsolete, and what it was rewritten into, as
+    /// returned by [`get_successor`].
+    #[derive(Clone, Debug)]
+    pub struct Successor {
+        /// Whether the commit is obsolete, such as because it was rewritten or
+        /// hidden.
+        pub is_obsolete: bool,
+
+        /// The latest commit that the commit was rewritten into, if any. This
+        /// is [`MaybeZeroOid::Zero`] if the commit was deleted while rewriting
+        /// other commits, and `None` if it wasn't rewritten (for example, if it
+        /// was only hidden).
+        pub rewrite_target: Option<MaybeZeroOid>,
+    }
+
+    /// Get whether the given commit is obsolete as of the given event cursor,
+    /// and what it was rewritten into.
+    pub fn get_successor(
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        oid: NonZeroOid,
+    ) -> Successor {
+        let is_obsolete = matches!(
+            event_replayer.get_cursor_commit_activity_status(event_cursor, oid),
+            CommitActivityStatus::Obsolete
+        );
+        let rewrite_target = find_rewrite_target(event_replayer, event_cursor, oid);
+        Successor {
+            is_obsolete,
+            rewrite_target,
+        }
+    }
+}
+
 mod render {
     use std::cmp::Ordering;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
     use std::convert::TryFrom;
 
-    use cursive_core::theme::{BaseColor, Effect};
+    use cursive_core::theme::Effect;
     use cursive_core::utils::markup::StyledString;
     use eden_dag::DagAlgorithm;
     use tracing::instrument;
@@ -385,11 +695,12 @@ mod render {
     use lib::core::formatting::{set_effect, Pluralize};
     use lib::core::formatting::{Glyphs, StyledStringBuilder};
     use lib::core::node_descriptors::{render_node_descriptors, NodeDescriptor};
-    use lib::git::{NonZeroOid, Repo};
+    use lib::git::{NonZeroOid, Repo, Time};
 
     use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use lib::core::eventlog::EventTransactionId;
 
-    use super::graph::{AncestorInfo, ChildInfo, SmartlogGraph};
+    use super::graph::{AncestorInfo, ChildInfo, CommitChange, SmartlogGraph};
 
     /// Split fully-independent subgraphs into multiple graphs.
     ///
@@ -399,10 +710,9 @@ mod render {
     /// Returns the list such that the topologically-earlier subgraphs are first in
     /// the list (i.e. those that would be rendered at the bottom of the smartlog).
     fn split_commit_graph_by_roots(
-        repo: &Repo,
         dag: &Dag,
         graph: &SmartlogGraph,
-    ) -> Vec<NonZeroOid> {
+    ) -> eyre::Result<Vec<NonZeroOid>> {
         let mut root_commit_oids: Vec<NonZeroOid> = graph
             .nodes
             .iter()
@@ -411,12 +721,18 @@ mod render {
             .copied()
             .collect();
 
+        // Look up each root's commit time once, rather than for each
+        // comparison. There's nothing to order if there's only one root.
+        let mut commit_times: HashMap<NonZeroOid, Option<Time>> = HashMap::new();
+        if root_commit_oids.len() > 1 {
+            for oid in root_commit_oids.iter() {
+                commit_times.insert(*oid, graph.get_commit_time(*oid)?);
+            }
+        }
+        let get_commit_time = |oid: &NonZeroOid| commit_times.get(oid).and_then(Option::as_ref);
         let compare = |lhs_oid: &NonZeroOid, rhs_oid: &NonZeroOid| -> Ordering {
-            let lhs_commit = repo.find_commit(*lhs_oid);
-            let rhs_commit = repo.find_commit(*rhs_oid);
-
-            let (lhs_commit, rhs_commit) = match (lhs_commit, rhs_commit) {
-                (Ok(Some(lhs_commit)), Ok(Some(rhs_commit))) => (lhs_commit, rhs_commit),
+            let (lhs_time, rhs_time) = match (get_commit_time(lhs_oid), get_commit_time(rhs_oid)) {
+                (Some(lhs_time), Some(rhs_time)) => (lhs_time, rhs_time),
                 _ => return lhs_oid.cmp(rhs_oid),
             };
 
@@ -437,7 +753,7 @@ mod render {
                 // The commits were not orderable (pathlogical situation). Let's
                 // just order them by timestamp in that case to produce a consistent
                 // and reasonable guess at the intended topological ordering.
-                Some(_) | None => match lhs_commit.get_time().cmp(&rhs_commit.get_time()) {
+                Some(_) | None => match lhs_time.cmp(rhs_time) {
                     result @ Ordering::Less | result @ Ordering::Greater => result,
                     Ordering::Equal => lhs_oid.cmp(rhs_oid),
                 },
@@ -445,11 +761,31 @@ mod render {
         };
 
         root_commit_oids.sort_by(compare);
-        root_commit_oids
+        Ok(root_commit_oids)
     }
 
-    #[instrument(skip(commit_descriptors, graph))]
-    fn get_child_output(
+    /// Callback which receives each line of the smartlog as it's rendered.
+    type EmitLine<'a> = dyn FnMut(StyledString) -> eyre::Result<()> + 'a;
+
+    fn emit_line(emit: &mut EmitLine, prefix: &str, line: StyledString) -> eyre::Result<()> {
+        if prefix.is_empty() {
+            emit(line)
+        } else {
+            emit(
+                StyledStringBuilder::new()
+                    .append_plain(prefix)
+                    .append(line)
+                    .build(),
+            )
+        }
+    }
+
+    /// Render the node with the given OID and its descendants. Each line is
+    /// passed to `emit` as soon as it's rendered, preceded by `prefix`, rather
+    /// than being collected, so that the rendered smartlog doesn't need to be
+    /// kept in memory.
+    #[instrument(skip(commit_descriptors, graph, emit))]
+    fn write_child_output(
         glyphs: &Glyphs,
         graph: &SmartlogGraph,
         root_oids: &[NonZeroOid],
@@ -457,14 +793,16 @@ mod render {
         head_oid: Option<NonZeroOid>,
         current_oid: NonZeroOid,
         last_child_line_char: Option<&str>,
-    ) -> eyre::Result<Vec<StyledString>> {
+        prefix: &str,
+        emit: &mut EmitLine,
+    ) -> eyre::Result<()> {
         let current_node = &graph.nodes[&current_oid];
         let is_head = Some(current_oid) == head_oid;
 
-        let mut lines = vec![];
-
         if let Some(AncestorInfo { oid: _, distance }) = current_node.ancestor_info {
-            lines.push(
+            emit_line(
+                emit,
+                prefix,
                 StyledStringBuilder::new()
                     .append_plain(glyphs.commit_omitted)
                     .append_plain(" ")
@@ -478,38 +816,41 @@ mod render {
                         Effect::Dim,
                     )
                     .build(),
-            );
-            lines.push(StyledString::plain(glyphs.vertical_ellipsis));
+            )?;
+            emit_line(emit, prefix, StyledString::plain(glyphs.vertical_ellipsis))?;
         };
 
         if let [_, merge_parents @ ..] = current_node.parents.as_slice() {
             if !merge_parents.is_empty() {
                 for merge_parent_oid in merge_parents {
-                    let merge_parent_node = &graph.nodes[merge_parent_oid];
-                    lines.push(
+                    let merge_parent_object = graph.get_node_object(*merge_parent_oid)?;
+                    emit_line(
+                        emit,
+                        prefix,
                         StyledStringBuilder::new()
                             .append_plain(last_child_line_char.unwrap_or(glyphs.line))
                             .append_plain(" ")
                             .append_styled(
                                 format!("{} (merge) ", glyphs.commit_merge),
-                                BaseColor::Blue.dark(),
+                                glyphs.theme.merge,
                             )
                             .append(render_node_descriptors(
                                 glyphs,
-                                &merge_parent_node.object,
+                                &merge_parent_object,
                                 commit_descriptors,
                             )?)
                             .build(),
-                    );
+                    )?;
                 }
-                lines.push(StyledString::plain(format!(
-                    "{}{}",
-                    glyphs.line_with_offshoot, glyphs.merge,
-                )));
+                emit_line(
+                    emit,
+                    prefix,
+                    StyledString::plain(format!("{}{}", glyphs.line_with_offshoot, glyphs.merge,)),
+                )?;
             }
         }
 
-        lines.push({
+        emit_line(emit, prefix, {
             let cursor = match (current_node.is_main, current_node.is_obsolete, is_head) {
                 (false, false, false) => glyphs.commit_visible,
                 (false, false, true) => glyphs.commit_visible_head,
@@ -520,22 +861,40 @@ mod render {
                 (true, true, false) => glyphs.commit_main_obsolete,
                 (true, true, true) => glyphs.commit_main_obsolete_head,
             };
-            let text = render_node_descriptors(glyphs, &current_node.object, commit_descriptors)?;
+            let cursor = match current_node.change {
+                None => StyledString::plain(cursor),
+                Some(CommitChange::BranchMoved) => {
+                    StyledString::styled(cursor, glyphs.theme.change_branch_moved)
+                }
+                Some(CommitChange::Rewritten) => {
+                    StyledString::styled(cursor, glyphs.theme.change_rewritten)
+                }
+                Some(CommitChange::Created) => {
+                    StyledString::styled(cursor, glyphs.theme.change_created)
+                }
+                Some(CommitChange::Hidden) => {
+                    StyledString::styled(cursor, glyphs.theme.change_hidden)
+                }
+            };
+            let current_object = graph.get_node_object(current_oid)?;
+            let text = render_node_descriptors(glyphs, &current_object, commit_descriptors)?;
             let first_line = StyledStringBuilder::new()
-                .append_plain(cursor)
+                .append(cursor)
                 .append_plain(" ")
                 .append(text)
                 .build();
-            if is_head {
+            if is_head || current_node.change.is_some() {
                 set_effect(first_line, Effect::Bold)
             } else {
                 first_line
             }
-        });
+        })?;
 
         if current_node.num_omitted_descendants > 0 {
-            lines.push(StyledString::plain(glyphs.vertical_ellipsis));
-            lines.push(
+            emit_line(emit, prefix, StyledString::plain(glyphs.vertical_ellipsis))?;
+            emit_line(
+                emit,
+                prefix,
                 StyledStringBuilder::new()
                     .append_plain(glyphs.commit_omitted)
                     .append_plain(" ")
@@ -549,7 +908,7 @@ mod render {
                         Effect::Dim,
                     )
                     .build(),
-            );
+            )?;
         };
 
         let children: Vec<ChildInfo> = current_node
@@ -588,36 +947,49 @@ mod render {
                 //     "{}{}",
                 //     glyphs.line_with_offshoot, glyphs.split
                 // )));
-                lines.push(
+                let child_object = graph.get_node_object(*child_oid)?;
+                emit_line(
+                    emit,
+                    prefix,
                     StyledStringBuilder::new()
                         // .append_plain(last_child_line_char.unwrap_or(glyphs.line))
                         // .append_plain(" ")
                         .append_styled(
                             format!("{} (merge) ", glyphs.commit_merge),
-                            BaseColor::Blue.dark(),
+                            glyphs.theme.merge,
                         )
                         .append(render_node_descriptors(
                             glyphs,
-                            &graph.nodes[child_oid].object,
+                            &child_object,
                             commit_descriptors,
                         )?)
                         .build(),
-                );
+                )?;
                 continue;
             }
 
             let is_last_child = child_idx == (children.len() + descendants.len()) - 1;
-            lines.push(StyledString::plain(
-                if !is_last_child || last_child_line_char.is_some() {
+            emit_line(
+                emit,
+                prefix,
+                StyledString::plain(if !is_last_child || last_child_line_char.is_some() {
                     format!("{}{}", glyphs.line_with_offshoot, glyphs.split)
                 } else if current_node.descendants.is_empty() {
                     glyphs.line.to_string()
                 } else {
                     glyphs.vertical_ellipsis.to_string()
-                },
-            ));
+                }),
+            )?;
 
-            let child_output = get_child_output(
+            let child_prefix = if is_last_child {
+                match last_child_line_char {
+                    Some(last_child_line_char) => format!("{prefix}{last_child_line_char} "),
+                    None => prefix.to_string(),
+                }
+            } else {
+                format!("{prefix}{} ", glyphs.line)
+            };
+            write_child_output(
                 glyphs,
                 graph,
                 root_oids,
@@ -625,40 +997,24 @@ mod render {
                 head_oid,
                 *child_oid,
                 None,
+                &child_prefix,
+                emit,
             )?;
-            for child_line in child_output {
-                let line = if is_last_child {
-                    match last_child_line_char {
-                        Some(last_child_line_char) => StyledStringBuilder::new()
-                            .append_plain(format!("{last_child_line_char} "))
-                            .append(child_line)
-                            .build(),
-                        None => child_line,
-                    }
-                } else {
-                    StyledStringBuilder::new()
-                        .append_plain(format!("{} ", glyphs.line))
-                        .append(child_line)
-                        .build()
-                };
-                lines.push(line)
-            }
         }
-        Ok(lines)
+        Ok(())
     }
 
     /// Render a pretty graph starting from the given root OIDs in the given graph.
-    #[instrument(skip(commit_descriptors, graph))]
-    fn get_output(
+    #[instrument(skip(commit_descriptors, graph, emit))]
+    fn write_output(
         glyphs: &Glyphs,
         dag: &Dag,
         graph: &SmartlogGraph,
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
         head_oid: Option<NonZeroOid>,
         root_oids: &[NonZeroOid],
-    ) -> eyre::Result<Vec<StyledString>> {
-        let mut lines = Vec::new();
-
+        emit: &mut EmitLine,
+    ) -> eyre::Result<()> {
         // Determine if the provided OID has the provided parent OID as a parent.
         //
         // This returns `true` in strictly more cases than checking `graph`,
@@ -681,11 +1037,11 @@ mod render {
                 } else {
                     StyledString::plain(glyphs.vertical_ellipsis.to_owned())
                 };
-                lines.push(line);
+                emit(line)?;
             } else if root_idx > 0 {
                 // Pathological case: multiple topologically-unrelated roots.
                 // Separate them with a newline.
-                lines.push(StyledString::new());
+                emit(StyledString::new())?;
             }
 
             let last_child_line_char = {
@@ -698,7 +1054,7 @@ mod render {
                 }
             };
 
-            let child_output = get_child_output(
+            write_child_output(
                 glyphs,
                 graph,
                 root_oids,
@@ -706,31 +1062,62 @@ mod render {
                 head_oid,
                 *root_oid,
                 last_child_line_char,
+                "",
+                emit,
             )?;
-            lines.extend(child_output.into_iter());
         }
 
-        Ok(lines)
+        Ok(())
     }
 
-    /// Render the smartlog graph and write it to the provided stream.
-    #[instrument(skip(commit_descriptors, graph))]
-    pub fn render_graph(
+    /// Render the smartlog graph, passing each line to `emit` as soon as it's
+    /// rendered. The lines are produced from top to bottom (or from bottom to
+    /// top if `effects` is in reverse order).
+    #[instrument(skip(commit_descriptors, graph, emit))]
+    pub fn write_graph(
         effects: &Effects,
         repo: &Repo,
         dag: &Dag,
         graph: &SmartlogGraph,
         head_oid: Option<NonZeroOid>,
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
-    ) -> eyre::Result<Vec<StyledString>> {
-        let root_oids = split_commit_graph_by_roots(repo, dag, graph);
-        let lines = get_output(
+        emit: &mut dyn FnMut(StyledString) -> eyre::Result<()>,
+    ) -> eyre::Result<()> {
+        let root_oids = split_commit_graph_by_roots(dag, graph)?;
+        write_output(
             effects.get_glyphs(),
             dag,
             graph,
             commit_descriptors,
             head_oid,
             &root_oids,
+            emit,
+        )
+    }
+
+    /// Render the smartlog graph into a list of lines. Prefer [`write_graph`]
+    /// when the lines can be written out as they're rendered.
+    #[instrument(skip(commit_descriptors, graph))]
+    pub fn render_graph(
+        effects: &Effects,
+        repo: &Repo,
+        dag: &Dag,
+        graph: &SmartlogGraph,
+        head_oid: Option<NonZeroOid>,
+        commit_descriptors: &mut [&mut dyn NodeDescriptor],
+    ) -> eyre::Result<Vec<StyledString>> {
+        let mut lines = Vec::new();
+        write_graph(
+            effects,
+            repo,
+            dag,
+            graph,
+            head_oid,
+            commit_descriptors,
+            &mut |line| {
+                lines.push(line);
+                Ok(())
+            },
         )?;
         Ok(lines)
     }
@@ -753,6 +1140,11 @@ mod render {
         /// Reverse the ordering of items in the smartlog output, list the most
         /// recent commits first.
         pub reverse: bool,
+
+        /// The transaction whose changes to highlight. If not provided, the
+        /// changes made by the enclosing transaction are highlighted, such as
+        /// when the smartlog is rendered at the end of a `git move`.
+        pub highlight_event_tx_id: Option<EventTransactionId>,
     }
 
     impl Default for SmartlogOptions {
@@ -762,6 +1154,7 @@ mod render {
                 revset: Revset::default_smartlog_revset(),
                 resolve_revset_options: Default::default(),
                 reverse: false,
+                highlight_event_tx_id: None,
             }
         }
     }
@@ -779,9 +1172,10 @@ pub fn smartlog(
         ref revset,
         ref resolve_revset_options,
         reverse,
+        highlight_event_tx_id,
     } = *options;
 
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let head_info = repo.get_head_info()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
@@ -826,7 +1220,7 @@ pub fn smartlog(
         }
     };
 
-    let graph = make_smartlog_graph(
+    let mut graph = make_smartlog_graph(
         effects,
         &repo,
         &dag,
@@ -834,8 +1228,20 @@ pub fn smartlog(
         event_cursor,
         &commits,
     )?;
+    if get_smartlog_highlight_changes(&repo)? {
+        if let Some(event_tx_id) =
+            highlight_event_tx_id.or_else(|| event_log_db.get_enclosing_transaction_id())
+        {
+            highlight_transaction_changes(&mut graph, &event_replayer, event_cursor, event_tx_id);
+        }
+    }
 
-    let mut lines = render_graph(
+    // Write each line out as soon as it's rendered, rather than rendering the
+    // whole smartlog first, which may be very large (such as with `--hidden`).
+    // When the order is reversed, the lines have to be buffered, since the
+    // last line needs to be written first.
+    let mut reversed_lines = Vec::new();
+    write_graph(
         &effects.reverse_order(reverse),
         &repo,
         &dag,
@@ -848,22 +1254,37 @@ pub fn smartlog(
                 &event_replayer,
                 event_replayer.make_default_cursor(),
             )?,
+            &mut CopiedCommitDescriptor::new(
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+            )?,
             &mut BranchesDescriptor::new(
                 &repo,
                 &head_info,
                 &references_snapshot,
                 &Redactor::Disabled,
             )?,
+            &mut WorktreesDescriptor::new(&repo)?,
             &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
+            &mut CodeReviewDescriptor::new(&repo, &Redactor::Disabled)?,
+            &mut CiStatusDescriptor::new(&repo, SystemTime::now(), &Redactor::Disabled)?,
+            &mut IssueTrailersDescriptor::new(&repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
-    )?
-    .into_iter();
-    while let Some(line) = if reverse {
-        lines.next_back()
-    } else {
-        lines.next()
-    } {
+        &mut |line| {
+            if reverse {
+                reversed_lines.push(line);
+            } else {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    effects.get_glyphs().render(line)?
+                )?;
+            }
+            Ok(())
+        },
+    )?;
+    for line in reversed_lines.into_iter().rev() {
         writeln!(
             effects.get_output_stream(),
             "{}",
@@ -872,7 +1293,7 @@ pub fn smartlog(
     }
 
     if !resolve_revset_options.show_hidden_commits
-        && get_hint_enabled(&repo, Hint::SmartlogFixAbandoned)?
+        && get_hint_enabled(effects, &repo, Hint::SmartlogFixAbandoned)?
     {
         let commits_with_abandoned_children: CommitSet = graph
             .nodes
@@ -887,13 +1308,14 @@ pub fn smartlog(
                 }
             })
             .collect();
-        let children = dag.query().children(commits_with_abandoned_children)?;
+        let children =
+            ChildIndexDb::new(&conn)?.query_children(&dag, &commits_with_abandoned_children)?;
         let num_abandoned_children = children.difference(&dag.query_obsolete_commits()).count()?;
         if num_abandoned_children > 0 {
             writeln!(
                 effects.get_output_stream(),
                 "{}: there {} in your commit graph",
-                effects.get_glyphs().render(get_hint_string())?,
+                effects.get_glyphs().render(get_hint_string(effects))?,
                 Pluralize {
                     determiner: Some(("is", "are")),
                     amount: num_abandoned_children,
@@ -903,7 +1325,7 @@ pub fn smartlog(
             writeln!(
                 effects.get_output_stream(),
                 "{}: to fix this, run: git restack",
-                effects.get_glyphs().render(get_hint_string())?,
+                effects.get_glyphs().render(get_hint_string(effects))?,
             )?;
             print_hint_suppression_notice(effects, Hint::SmartlogFixAbandoned)?;
         }
@@ -926,6 +1348,8 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> eyre::Result<Exi
         reverse,
     } = args;
 
+    let repo = Repo::from_current_dir()?;
+    let _pager = start_pager(&git_run_info, &repo, "smartlog")?;
     smartlog(
         &effects,
         &git_run_info,
@@ -934,6 +1358,7 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> eyre::Result<Exi
             revset: revset.unwrap_or_else(Revset::default_smartlog_revset),
             resolve_revset_options,
             reverse,
+            highlight_event_tx_id: None,
         },
     )
 }
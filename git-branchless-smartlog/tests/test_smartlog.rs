@@ -1,4 +1,6 @@
-use lib::testing::{extract_hint_command, make_git, GitRunOptions};
+use lib::testing::{
+    extract_hint_command, make_git, make_git_worktree, GitRunOptions, GitWorktreeWrapper,
+};
 
 #[test]
 fn test_init_smartlog() -> eyre::Result<()> {
@@ -60,6 +62,38 @@ fn test_tree() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_smartlog_wide_character_summaries() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["branch", "initial"])?;
+    git.write_file_txt("test1", "test1 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "添加测试文件 🎉"])?;
+    git.run(&["checkout", "initial"])?;
+    git.write_file_txt("test2", "test2 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "👨‍👩‍👧 family café"])?;
+
+    // Summaries are never truncated, and the graph doesn't depend on their
+    // width, so wide characters and multi-codepoint graphemes don't misalign
+    // it.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 625d4dc 添加测试文件 🎉
+        |
+        @ 84c587f (> initial) 👨‍👩‍👧 family café
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_rebase() -> eyre::Result<()> {
     let git = make_git()?;
@@ -703,3 +737,404 @@ fn test_smartlog_sparse_vertical_ellipsis_sibling_commits() -> eyre::Result<()>
 
     Ok(())
 }
+
+#[test]
+fn test_smartlog_worktrees() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let GitWorktreeWrapper {
+        temp_dir: _temp_dir,
+        worktree,
+    } = make_git_worktree(&git, "new-worktree")?;
+    worktree.run(&["checkout", "--detach", "master"])?;
+    worktree.commit_file("test2", 2)?;
+
+    // The commit made in the worktree is recorded in the shared event log, and
+    // the worktree's checkout doesn't move `HEAD` in the main worktree.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master, new-worktree) create test1.txt
+        |
+        o 96d1c37 [worktree: new-worktree] create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.commitDescriptors.worktrees", "false"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master, new-worktree) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_get_pager_command() -> eyre::Result<()> {
+    use lib::core::pager::get_pager_command;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let git_run_info = git.get_git_run_info();
+
+    // The default pager depends on how Git was built.
+    assert!(get_pager_command(&git_run_info, &repo, "smartlog")?.is_some());
+
+    git.run(&["config", "core.pager", "my-pager --flag"])?;
+    assert_eq!(
+        get_pager_command(&git_run_info, &repo, "smartlog")?,
+        Some("my-pager --flag".to_string())
+    );
+
+    git.run(&["config", "pager.smartlog", "smartlog-pager"])?;
+    assert_eq!(
+        get_pager_command(&git_run_info, &repo, "smartlog")?,
+        Some("smartlog-pager".to_string())
+    );
+    assert_eq!(
+        get_pager_command(&git_run_info, &repo, "query")?,
+        Some("my-pager --flag".to_string())
+    );
+
+    git.run(&["config", "pager.smartlog", "false"])?;
+    assert_eq!(get_pager_command(&git_run_info, &repo, "smartlog")?, None);
+
+    {
+        // `--paginate` sets `$GIT_BRANCHLESS_PAGINATE`, which overrides
+        // `pager.<command>`.
+        let mut git_run_info = git_run_info.clone();
+        git_run_info
+            .env
+            .insert("GIT_BRANCHLESS_PAGINATE".into(), "1".into());
+        assert_eq!(
+            get_pager_command(&git_run_info, &repo, "smartlog")?,
+            Some("my-pager --flag".to_string())
+        );
+    }
+
+    {
+        let mut git_run_info = git_run_info.clone();
+        git_run_info
+            .env
+            .insert("GIT_PAGER".into(), "env-pager".into());
+        assert_eq!(get_pager_command(&git_run_info, &repo, "smartlog")?, None);
+        assert_eq!(
+            get_pager_command(&git_run_info, &repo, "query")?,
+            Some("env-pager".to_string())
+        );
+
+        // `--no-pager` sets `$GIT_PAGER` to `cat`.
+        git_run_info.env.insert("GIT_PAGER".into(), "cat".into());
+        assert_eq!(get_pager_command(&git_run_info, &repo, "query")?, None);
+    }
+
+    {
+        let mut git_run_info = git_run_info.clone();
+        git_run_info
+            .env
+            .insert("GIT_PAGER_IN_USE".into(), "true".into());
+        assert_eq!(get_pager_command(&git_run_info, &repo, "query")?, None);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_smartlog_pager() -> eyre::Result<()> {
+    use lib::testing::pty::{run_in_pty, PtyAction};
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "core.pager", "sed -e 's/^/paged: /'"])?;
+
+    run_in_pty(
+        &git,
+        "smartlog",
+        &[],
+        &[PtyAction::WaitUntilContains("paged: ◆ 62fc20d")],
+    )?;
+
+    Ok(())
+}
+
+/// A stand-in for the GitHub CLI which serves the check runs and commit
+/// statuses of each commit from the files in `$FAKE_GH_STATE`. If the file
+/// `fail` exists there, every call fails, as if GitHub couldn't be reached.
+#[cfg(unix)]
+const FAKE_GH_SCRIPT: &str = r#"#!/bin/sh
+state="$FAKE_GH_STATE"
+echo "gh $*" >>"$state/log"
+if [ -e "$state/fail" ]; then
+    echo "error connecting to api.github.com" >&2
+    exit 1
+fi
+commit=$(echo "$2" | cut -d/ -f5)
+case "$2" in
+*/check-runs)
+    cat "$state/$commit-check-runs.json" 2>/dev/null || echo '{"total_count":0,"check_runs":[]}'
+    ;;
+*/status)
+    cat "$state/$commit-status.json" 2>/dev/null || echo '{"state":"pending","total_count":0}'
+    ;;
+*)
+    echo "unexpected arguments: $*" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_smartlog_ci_status() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/foo",
+        &test2_oid.to_string(),
+    ])?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/bar",
+        &test3_oid.to_string(),
+    ])?;
+
+    let bin_dir = git.repo_path.join(".git").join("bin");
+    let state_dir = git.repo_path.join(".git").join("gh-state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+    let gh_path = bin_dir.join("gh");
+    std::fs::write(&gh_path, FAKE_GH_SCRIPT)?;
+    std::fs::set_permissions(&gh_path, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(
+        state_dir.join(format!("{test2_oid}-check-runs.json")),
+        r#"{"total_count":2,"check_runs":[{"status":"completed","conclusion":"success"},{"status":"completed","conclusion":"skipped"}]}"#,
+    )?;
+    std::fs::write(
+        state_dir.join(format!("{test3_oid}-check-runs.json")),
+        r#"{"total_count":1,"check_runs":[{"status":"in_progress","conclusion":null}]}"#,
+    )?;
+    let options = GitRunOptions {
+        env: HashMap::from([
+            (
+                "PATH".to_string(),
+                format!(
+                    "{}:{}",
+                    bin_dir.display(),
+                    git.get_path_for_env().to_string_lossy()
+                ),
+            ),
+            (
+                "FAKE_GH_STATE".to_string(),
+                state_dir.to_string_lossy().into_owned(),
+            ),
+        ]),
+        ..Default::default()
+    };
+    let read_gh_log = || -> eyre::Result<String> {
+        let log = std::fs::read_to_string(state_dir.join("log")).unwrap_or_default();
+        let _ = std::fs::remove_file(state_dir.join("log"));
+        Ok(log)
+    };
+
+    // Statuses aren't shown unless a provider is configured.
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @"");
+    }
+
+    git.run(&["config", "branchless.ci.provider", "github"])?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 + create test2.txt
+        |
+        o 70deb1e ~ create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @r###"
+        gh api repos/{owner}/{repo}/commits/96d1c37a3d4363611c49f7e52186e189a04c531f/check-runs
+        gh api repos/{owner}/{repo}/commits/96d1c37a3d4363611c49f7e52186e189a04c531f/status
+        gh api repos/{owner}/{repo}/commits/70deb1e28791d8e7dd5a1f0c871a51b91282562f/check-runs
+        gh api repos/{owner}/{repo}/commits/70deb1e28791d8e7dd5a1f0c871a51b91282562f/status
+        "###);
+    }
+
+    // The statuses are cached, so they aren't queried again until the refresh
+    // interval has passed.
+    std::fs::write(
+        state_dir.join(format!("{test3_oid}-status.json")),
+        r#"{"state":"failure","total_count":1}"#,
+    )?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 + create test2.txt
+        |
+        o 70deb1e ~ create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @"");
+    }
+
+    git.run(&["config", "branchless.ci.refreshInterval", "0s"])?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 + create test2.txt
+        |
+        o 70deb1e x create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @r###"
+        gh api repos/{owner}/{repo}/commits/96d1c37a3d4363611c49f7e52186e189a04c531f/check-runs
+        gh api repos/{owner}/{repo}/commits/96d1c37a3d4363611c49f7e52186e189a04c531f/status
+        gh api repos/{owner}/{repo}/commits/70deb1e28791d8e7dd5a1f0c871a51b91282562f/check-runs
+        gh api repos/{owner}/{repo}/commits/70deb1e28791d8e7dd5a1f0c871a51b91282562f/status
+        "###);
+    }
+
+    // Failures to reach the provider are cached too, so that the smartlog
+    // doesn't query it again for every invocation while offline.
+    git.run(&["config", "--unset", "branchless.ci.refreshInterval"])?;
+    git.run(&["update-ref", "refs/remotes/origin/baz", "HEAD"])?;
+    std::fs::write(state_dir.join("fail"), "")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 + create test2.txt
+        |
+        o 70deb1e x create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @r###"
+        gh api repos/{owner}/{repo}/commits/355e173bf9c5d2efac2e451da0cdad3fb82b869a/check-runs
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 + create test2.txt
+        |
+        o 70deb1e x create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_issue_trailers() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&[
+        "commit",
+        "--allow-empty",
+        "-m",
+        "Fix the frobnicator\n\nFixes: #123, #456\nJira: ABC-1\nSee-also: #789",
+    ])?;
+    git.run(&[
+        "commit",
+        "--allow-empty",
+        "-m",
+        "Refactor the frobnicator\n\nfixes: #123",
+    ])?;
+
+    // Trailers aren't shown unless they're configured.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 3956629 Fix the frobnicator
+        |
+        @ e86ac2d Refactor the frobnicator
+        "###);
+    }
+
+    git.run(&["config", "branchless.issues.trailers", "Fixes Jira"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 3956629 #123, #456, ABC-1 Fix the frobnicator
+        |
+        @ e86ac2d #123 Refactor the frobnicator
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.issues.statusCommand",
+        r##"case "$BRANCHLESS_ISSUE_ID" in "#123") echo closed ;; ABC-1) echo "in progress" ;; esac"##,
+    ])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 3956629 #123 [closed], #456, ABC-1 [in progress] Fix the frobnicator
+        |
+        @ e86ac2d #123 [closed] Refactor the frobnicator
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,327 @@
+//! Submit commits as Gerrit changes.
+//!
+//! Unlike forges which review branches, Gerrit reviews individual commits: a
+//! stack is pushed to the magic `refs/for/<branch>` ref, and Gerrit creates or
+//! updates one change per commit, identified by the `Change-Id` trailer in its
+//! message. Commits without a `Change-Id` are reworded to add one before
+//! pushing. The change numbers which Gerrit reports are matched back to the
+//! commits by `Change-Id`, via the patch set refs which Gerrit keeps under
+//! `refs/changes/`, and recorded in the database so that they can be shown in
+//! the smartlog.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use bstr::ByteSlice;
+use eden_dag::DagAlgorithm;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::forge::{CiStatus, CodeReview, CodeReviewDb};
+use lib::core::formatting::Pluralize;
+use lib::core::gerrit::{add_change_id, get_change_id, make_change_id};
+use lib::git::{GitRunInfo, GitRunOpts, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use regex::Regex;
+use tracing::instrument;
+
+use crate::reviews::get_main_branch_name;
+use crate::{get_default_remote, reword_commits};
+
+/// The name under which Gerrit changes are recorded in the database.
+const FORGE_NAME: &str = "gerrit";
+
+lazy_static! {
+    /// Matches the lines which Gerrit prints for each change it receives, such
+    /// as `remote:   https://gerrit.example.com/c/project/+/123 Summary [NEW]`.
+    static ref CHANGE_LINE_RE: Regex = Regex::new(
+        r"^remote:\s+(?P<url>https?://\S+/(?P<number>[0-9]+))\s+(?P<summary>.*?)(\s+\[[A-Z ]+\])*\s*$"
+    )
+    .expect("Failed to compile `CHANGE_LINE_RE` regex");
+}
+
+/// Push the draft commits in `commit_set` to Gerrit for review, adding
+/// `Change-Id` trailers to any commits which don't have one.
+#[instrument]
+pub fn submit_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    commit_set: &CommitSet,
+) -> eyre::Result<ExitCode> {
+    let commit_set = dag.query_draft_commits()?.intersection(commit_set);
+    if commit_set.is_empty()? {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no draft commits to submit."
+        )?;
+        return Ok(ExitCode(0));
+    }
+    let remote_name = match get_default_remote(repo)? {
+        Some(remote_name) => remote_name,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "\
+No upstream repository was associated with the main branch and no value was
+specified for `remote.pushDefault`, so cannot push to Gerrit.
+Configure a value with: git config remote.pushDefault <remote>"
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let target_branch = get_main_branch_name(repo)?;
+
+    let rewritten_oids = match add_missing_change_ids(
+        effects,
+        git_run_info,
+        repo,
+        dag,
+        event_log_db,
+        event_tx_id,
+        &commit_set,
+    )? {
+        Ok(rewritten_oids) => rewritten_oids,
+        Err(exit_code) => return Ok(exit_code),
+    };
+    let get_new_oid = |oid: NonZeroOid| -> Option<NonZeroOid> {
+        match rewritten_oids.get(&oid) {
+            None => Some(oid),
+            Some(MaybeZeroOid::NonZero(new_oid)) => Some(*new_oid),
+            Some(MaybeZeroOid::Zero) => None,
+        }
+    };
+
+    // Gerrit creates a change for each commit reachable from a pushed commit,
+    // so only the heads of each stack need to be pushed.
+    let head_oids = commit_set_to_vec(&dag.query().heads(commit_set.clone())?)?
+        .into_iter()
+        .filter_map(get_new_oid)
+        .collect_vec();
+    let mut changes: Vec<(u64, String)> = Vec::new();
+    for head_oid in head_oids {
+        let refspec = format!("{head_oid}:refs/for/{target_branch}");
+        let args = ["push", remote_name.as_str(), refspec.as_str()];
+        // Gerrit reports the changes it received in the remote output, so
+        // capture it rather than using `git_run_info.run`.
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: running command: {} {}",
+            git_run_info.path_to_git.to_string_lossy(),
+            args.join(" ")
+        )?;
+        let result = git_run_info.run_silent(
+            repo,
+            Some(event_tx_id),
+            &args,
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )?;
+        write!(
+            effects.get_error_stream(),
+            "{}",
+            result.stderr.to_str_lossy()
+        )?;
+        if !result.exit_code.is_success() {
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to push to {remote_name}: refs/for/{target_branch}"
+            )?;
+            return Ok(result.exit_code);
+        }
+        for line in result.stderr.to_str_lossy().lines() {
+            if let Some(captures) = CHANGE_LINE_RE.captures(line) {
+                if let Ok(number) = captures["number"].parse() {
+                    changes.push((number, captures["url"].to_owned()));
+                }
+            }
+        }
+    }
+
+    // Gerrit only reports the number and summary of each change, so look up
+    // the `Change-Id` of each change to match it to its commit.
+    let change_ids = get_change_ids(
+        git_run_info,
+        repo,
+        event_tx_id,
+        &remote_name,
+        changes.iter().map(|(number, _url)| *number),
+    )?;
+    let mut changes: HashMap<String, (u64, String)> = changes
+        .into_iter()
+        .filter_map(|(number, url)| {
+            change_ids
+                .get(&number)
+                .map(|change_id| (change_id.clone(), (number, url)))
+        })
+        .collect();
+    let conn = repo.get_db_conn()?;
+    let code_review_db = CodeReviewDb::new(&conn)?;
+    let mut submitted = Vec::new();
+    for commit in sorted_commit_set(repo, dag, &commit_set)? {
+        let commit = match get_new_oid(commit.get_oid()) {
+            Some(commit_oid) => repo.find_commit_or_fail(commit_oid)?,
+            None => continue,
+        };
+        let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+        let change_id = match get_change_id(&message) {
+            Some(change_id) => change_id.to_owned(),
+            None => continue,
+        };
+        let (number, url) = match changes.remove(&change_id) {
+            Some(change) => change,
+            None => continue,
+        };
+        let summary = commit.get_summary()?.to_str_lossy().into_owned();
+        let review = CodeReview {
+            id: format!("c/{number}"),
+            url,
+            head: change_id,
+            base: target_branch.clone(),
+            title: summary,
+            body: Default::default(),
+            ci_status: CiStatus::Unknown,
+            state: None,
+        };
+        code_review_db.set_review(FORGE_NAME, commit.get_oid(), &review)?;
+        submitted.push(review);
+    }
+
+    if !submitted.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Submitted {} (into {target_branch}):",
+            Pluralize {
+                determiner: None,
+                amount: submitted.len(),
+                unit: ("change", "changes"),
+            }
+        )?;
+        for review in submitted {
+            writeln!(
+                effects.get_output_stream(),
+                "  {} {}: {}",
+                review.id,
+                review.title,
+                review.url
+            )?;
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// Look up the `Change-Id` of the latest patch set of each of the given
+/// changes. Gerrit stores patch set `P` of change `N` under the ref
+/// `refs/changes/<last two digits of N>/N/P`.
+#[instrument(skip(change_numbers))]
+fn get_change_ids(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    remote_name: &str,
+    change_numbers: impl IntoIterator<Item = u64>,
+) -> eyre::Result<HashMap<u64, String>> {
+    let patterns = change_numbers
+        .into_iter()
+        .map(|number| format!("refs/changes/{:02}/{number}/*", number % 100))
+        .collect_vec();
+    if patterns.is_empty() {
+        return Ok(Default::default());
+    }
+
+    let mut args = vec!["ls-remote", remote_name];
+    args.extend(patterns.iter().map(|pattern| pattern.as_str()));
+    let result = git_run_info.run_silent(repo, Some(event_tx_id), &args, Default::default())?;
+
+    let mut latest_patch_sets: HashMap<u64, (u64, NonZeroOid)> = HashMap::new();
+    for line in result.stdout.to_str_lossy().lines() {
+        let (oid, ref_name) = match line.split_once('\t') {
+            Some(fields) => fields,
+            None => continue,
+        };
+        let (number, patch_set) = match ref_name.split('/').collect_vec().as_slice() {
+            ["refs", "changes", _, number, patch_set] => {
+                match (number.parse(), patch_set.parse()) {
+                    (Ok(number), Ok(patch_set)) => (number, patch_set),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        let oid: NonZeroOid = match oid.parse() {
+            Ok(oid) => oid,
+            Err(_) => continue,
+        };
+        match latest_patch_sets.get(&number) {
+            Some((latest_patch_set, _oid)) if *latest_patch_set >= patch_set => {}
+            _ => {
+                latest_patch_sets.insert(number, (patch_set, oid));
+            }
+        }
+    }
+
+    let mut change_ids = HashMap::new();
+    for (number, (_patch_set, oid)) in latest_patch_sets {
+        // The patch set was just pushed from this repository, so its commit
+        // should be available locally.
+        let commit = match repo.find_commit(oid)? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+        if let Some(change_id) = get_change_id(&message) {
+            change_ids.insert(number, change_id.to_owned());
+        }
+    }
+    Ok(change_ids)
+}
+
+/// Reword the commits in `commit_set` which don't have a `Change-Id` trailer
+/// to add one. Returns the rewritten commits.
+fn add_missing_change_ids(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    commit_set: &CommitSet,
+) -> eyre::Result<Result<HashMap<NonZeroOid, MaybeZeroOid>, ExitCode>> {
+    let mut rewordings = Vec::new();
+    for commit in sorted_commit_set(repo, dag, commit_set)? {
+        let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+        if get_change_id(&message).is_none() {
+            let change_id = make_change_id(repo, &commit)?;
+            rewordings.push((commit, add_change_id(&message, &change_id)));
+        }
+    }
+    if rewordings.is_empty() {
+        return Ok(Ok(Default::default()));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Adding Change-Id to {}.",
+        Pluralize {
+            determiner: None,
+            amount: rewordings.len(),
+            unit: ("commit", "commits"),
+        }
+    )?;
+    reword_commits(
+        effects,
+        git_run_info,
+        repo,
+        dag,
+        event_log_db,
+        event_tx_id,
+        &rewordings,
+    )
+}
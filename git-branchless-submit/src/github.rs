@@ -0,0 +1,309 @@
+//! Submit branches as GitHub pull requests.
+//!
+//! GitHub is accessed via the [GitHub CLI](https://cli.github.com/) (`gh`),
+//! which must be installed and authenticated, either with `gh auth login` or
+//! by setting `$GH_TOKEN` to a personal access token.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use itertools::Itertools;
+use lib::core::effects::Effects;
+use lib::core::forge::{CiStatus, CodeReview, CodeReviewSpec, Forge};
+use lib::git::GitRunInfo;
+use lib::util::ExitCode;
+use serde::Deserialize;
+
+/// The name of the GitHub CLI executable.
+const GH_EXECUTABLE: &str = "gh";
+
+/// The fields of a pull request which we query with `gh pr list`.
+const PULL_REQUEST_FIELDS: &str = "number,url,headRefName,baseRefName,title,body,statusCheckRollup";
+
+/// An open pull request, as reported by `gh pr list --json`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestInfo {
+    number: u64,
+    url: String,
+    head_ref_name: String,
+    base_ref_name: String,
+    title: String,
+    body: String,
+    #[serde(default)]
+    status_check_rollup: Vec<StatusCheck>,
+}
+
+/// A single check run or commit status of a pull request. Check runs have a
+/// `status` and (once completed) a `conclusion`, while commit statuses only
+/// have a `state`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct StatusCheck {
+    status: Option<String>,
+    conclusion: Option<String>,
+    state: Option<String>,
+}
+
+impl StatusCheck {
+    fn get_ci_status(&self) -> CiStatus {
+        let Self {
+            status,
+            conclusion,
+            state,
+        } = self;
+        let result = state
+            .as_deref()
+            .or(conclusion
+                .as_deref()
+                .filter(|conclusion| !conclusion.is_empty()))
+            .or(status.as_deref());
+        match result {
+            Some("SUCCESS" | "NEUTRAL" | "SKIPPED") => CiStatus::Passed,
+            Some("PENDING" | "EXPECTED" | "QUEUED" | "IN_PROGRESS" | "WAITING" | "REQUESTED") => {
+                CiStatus::Pending
+            }
+            Some(
+                "FAILURE" | "ERROR" | "CANCELLED" | "TIMED_OUT" | "ACTION_REQUIRED"
+                | "STARTUP_FAILURE" | "STALE",
+            ) => CiStatus::Failed,
+            _ => CiStatus::Unknown,
+        }
+    }
+}
+
+impl From<PullRequestInfo> for CodeReview {
+    fn from(pull_request: PullRequestInfo) -> Self {
+        let PullRequestInfo {
+            number,
+            url,
+            head_ref_name,
+            base_ref_name,
+            title,
+            body,
+            status_check_rollup,
+        } = pull_request;
+        let statuses = status_check_rollup
+            .iter()
+            .map(|check| check.get_ci_status())
+            .collect_vec();
+        let ci_status = if statuses.is_empty() {
+            CiStatus::Unknown
+        } else if statuses.contains(&CiStatus::Failed) {
+            CiStatus::Failed
+        } else if statuses
+            .iter()
+            .any(|status| matches!(status, CiStatus::Pending | CiStatus::Unknown))
+        {
+            CiStatus::Pending
+        } else {
+            CiStatus::Passed
+        };
+        CodeReview {
+            id: format!("#{number}"),
+            url,
+            head: head_ref_name,
+            base: base_ref_name,
+            title,
+            body,
+            ci_status,
+            state: None,
+        }
+    }
+}
+
+/// Creates and updates pull requests on GitHub.
+#[derive(Debug)]
+pub struct GithubForge<'a> {
+    git_run_info: &'a GitRunInfo,
+}
+
+impl<'a> GithubForge<'a> {
+    /// Constructor.
+    pub fn new(git_run_info: &'a GitRunInfo) -> Self {
+        Self { git_run_info }
+    }
+
+    /// Run the GitHub CLI with the given arguments and return its standard
+    /// output. If it fails, its error output is shown and its exit code is
+    /// returned.
+    fn run_gh(&self, effects: &Effects, args: &[&str]) -> eyre::Result<Result<String, ExitCode>> {
+        let GitRunInfo {
+            path_to_git: _,
+            working_directory,
+            env,
+        } = self.git_run_info;
+        let output = match Command::new(GH_EXECUTABLE)
+            .args(args)
+            .current_dir(working_directory)
+            .env_clear()
+            .envs(env.iter())
+            .stdin(Stdio::null())
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+Could not find the GitHub CLI ({GH_EXECUTABLE}), which is needed to submit pull requests.
+Install it from https://cli.github.com/ and authenticate with: gh auth login"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("Running {GH_EXECUTABLE} {}", args.join(" ")))
+            }
+        };
+
+        if output.status.success() {
+            Ok(Ok(String::from_utf8_lossy(&output.stdout).into_owned()))
+        } else {
+            write!(
+                effects.get_error_stream(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to run: {GH_EXECUTABLE} {}",
+                args.iter().take(2).join(" ")
+            )?;
+            let exit_code = output.status.code().unwrap_or(1);
+            Ok(Err(ExitCode(isize::try_from(exit_code).unwrap_or(1))))
+        }
+    }
+}
+
+impl Forge for GithubForge<'_> {
+    fn get_name(&self) -> &'static str {
+        "github"
+    }
+
+    fn get_review_unit(&self) -> (&'static str, &'static str) {
+        ("pull request", "pull requests")
+    }
+
+    fn query_reviews(
+        &mut self,
+        effects: &Effects,
+        heads: &[&str],
+    ) -> eyre::Result<Result<HashMap<String, CodeReview>, ExitCode>> {
+        let mut result = HashMap::new();
+        for head in heads {
+            let output = match self.run_gh(
+                effects,
+                &[
+                    "pr",
+                    "list",
+                    "--head",
+                    head,
+                    "--state",
+                    "open",
+                    "--json",
+                    PULL_REQUEST_FIELDS,
+                ],
+            )? {
+                Ok(output) => output,
+                Err(exit_code) => return Ok(Err(exit_code)),
+            };
+            let pull_requests: Vec<PullRequestInfo> = serde_json::from_str(&output)
+                .wrap_err_with(|| format!("Parsing pull requests for branch {head}: {output}"))?;
+            if let Some(pull_request) = pull_requests.into_iter().next() {
+                result.insert(head.to_string(), pull_request.into());
+            }
+        }
+        Ok(Ok(result))
+    }
+
+    fn query_protected_branches(
+        &mut self,
+        effects: &Effects,
+    ) -> eyre::Result<Result<Vec<String>, ExitCode>> {
+        // `gh api` fills in the `{owner}` and `{repo}` placeholders from the
+        // current repository.
+        let output = match self.run_gh(
+            effects,
+            &[
+                "api",
+                "--paginate",
+                "repos/{owner}/{repo}/branches?protected=true",
+                "--jq",
+                ".[].name",
+            ],
+        )? {
+            Ok(output) => output,
+            Err(exit_code) => return Ok(Err(exit_code)),
+        };
+        Ok(Ok(output.lines().map(|line| line.to_owned()).collect()))
+    }
+
+    fn create_review(
+        &mut self,
+        effects: &Effects,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>> {
+        let CodeReviewSpec {
+            commit_oid: _,
+            head,
+            base,
+            title,
+            body,
+        } = spec;
+        let url = match self.run_gh(
+            effects,
+            &[
+                "pr", "create", "--head", head, "--base", base, "--title", title, "--body", body,
+            ],
+        )? {
+            Ok(output) => output.trim().to_owned(),
+            Err(exit_code) => return Ok(Err(exit_code)),
+        };
+        // `gh pr create` only prints the URL of the new pull request, which
+        // ends with its number.
+        let number = url.rsplit('/').next().unwrap_or_default();
+        Ok(Ok(CodeReview {
+            id: format!("#{number}"),
+            url,
+            head: head.clone(),
+            base: base.clone(),
+            title: title.clone(),
+            body: body.clone(),
+            ci_status: CiStatus::Unknown,
+            state: None,
+        }))
+    }
+
+    fn update_review(
+        &mut self,
+        effects: &Effects,
+        review: &CodeReview,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>> {
+        let CodeReviewSpec {
+            commit_oid: _,
+            head: _,
+            base,
+            title,
+            body,
+        } = spec;
+        let number = review.id.trim_start_matches('#');
+        if let Err(exit_code) = self.run_gh(
+            effects,
+            &[
+                "pr", "edit", number, "--base", base, "--title", title, "--body", body,
+            ],
+        )? {
+            return Ok(Err(exit_code));
+        }
+        Ok(Ok(CodeReview {
+            base: base.clone(),
+            title: title.clone(),
+            body: body.clone(),
+            ..review.clone()
+        }))
+    }
+}
@@ -0,0 +1,371 @@
+//! Submit branches as GitLab merge requests.
+//!
+//! GitLab is accessed via its [REST API](https://docs.gitlab.com/ee/api/merge_requests.html),
+//! authenticated with a personal or project access token in `$GITLAB_TOKEN`.
+//! The project is given by the `branchless.submit.gitlab.project` config
+//! setting (such as `group/project`), and the API of a self-hosted instance can
+//! be set with `branchless.submit.gitlab.url`.
+//!
+//! Requests are made with `curl`, which must be installed. The token is passed
+//! to `curl` on standard input, so that it doesn't appear in the process list.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use lib::core::effects::Effects;
+use lib::core::forge::{CiStatus, CodeReview, CodeReviewSpec, Forge};
+use lib::git::{ConfigRead, GitRunInfo, Repo};
+use lib::util::ExitCode;
+use serde::Deserialize;
+use url::form_urlencoded;
+
+/// The name of the `curl` executable.
+const CURL_EXECUTABLE: &str = "curl";
+
+/// The environment variable which holds the GitLab access token.
+const GITLAB_TOKEN_ENV_VAR: &str = "GITLAB_TOKEN";
+
+/// The API URL used if `branchless.submit.gitlab.url` isn't set.
+const DEFAULT_GITLAB_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// A protected branch, as returned by the GitLab API.
+#[derive(Clone, Debug, Deserialize)]
+struct ProtectedBranchInfo {
+    name: String,
+}
+
+/// A merge request, as returned by the GitLab API.
+#[derive(Clone, Debug, Deserialize)]
+struct MergeRequestInfo {
+    iid: u64,
+    web_url: String,
+    source_branch: String,
+    target_branch: String,
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+impl From<MergeRequestInfo> for CodeReview {
+    fn from(merge_request: MergeRequestInfo) -> Self {
+        let MergeRequestInfo {
+            iid,
+            web_url,
+            source_branch,
+            target_branch,
+            title,
+            description,
+        } = merge_request;
+        CodeReview {
+            id: format!("!{iid}"),
+            url: web_url,
+            head: source_branch,
+            base: target_branch,
+            title,
+            body: description.unwrap_or_default(),
+            ci_status: CiStatus::Unknown,
+            state: None,
+        }
+    }
+}
+
+/// Creates and updates merge requests on GitLab.
+pub struct GitlabForge<'a> {
+    git_run_info: &'a GitRunInfo,
+    api_url: String,
+    project: String,
+    token: String,
+}
+
+impl std::fmt::Debug for GitlabForge<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<GitlabForge api_url={:?} project={:?}>",
+            self.api_url, self.project
+        )
+    }
+}
+
+impl<'a> GitlabForge<'a> {
+    /// Constructor. Fails (after printing an explanation) if the project or
+    /// access token aren't configured.
+    pub fn new(
+        effects: &Effects,
+        git_run_info: &'a GitRunInfo,
+        repo: &Repo,
+    ) -> eyre::Result<Result<Self, ExitCode>> {
+        let config = repo.get_readonly_config()?;
+        let api_url: String = config.get_or_else("branchless.submit.gitlab.url", || {
+            DEFAULT_GITLAB_API_URL.to_owned()
+        })?;
+        let project: Option<String> = config.get("branchless.submit.gitlab.project")?;
+        let project = match project {
+            Some(project) => project,
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+No GitLab project was configured, so cannot submit merge requests.
+Configure a value with: git config branchless.submit.gitlab.project <group>/<project>"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        let token = match git_run_info.env.get(OsStr::new(GITLAB_TOKEN_ENV_VAR)) {
+            Some(token) if !token.is_empty() => token.to_string_lossy().into_owned(),
+            _ => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+No GitLab access token was found, so cannot submit merge requests.
+Create a token with the `api` scope and set it in: ${GITLAB_TOKEN_ENV_VAR}"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        Ok(Ok(Self {
+            git_run_info,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            project,
+            token,
+        }))
+    }
+
+    /// Get the API URL of the project, with the given suffix.
+    fn project_url(&self, suffix: &str) -> String {
+        let project: String = form_urlencoded::byte_serialize(self.project.as_bytes()).collect();
+        format!("{}/projects/{project}{suffix}", self.api_url)
+    }
+
+    /// Get the API URL of the project's merge requests, with the given
+    /// suffix.
+    fn merge_requests_url(&self, suffix: &str) -> String {
+        self.project_url(&format!("/merge_requests{suffix}"))
+    }
+
+    /// Make a request to the GitLab API and return the response body. If the
+    /// request fails, the error is shown and an exit code is returned.
+    fn request(
+        &self,
+        effects: &Effects,
+        method: &str,
+        url: &str,
+        body: Option<&serde_json::Value>,
+    ) -> eyre::Result<Result<String, ExitCode>> {
+        // Pass the request to `curl` as a config file on stdin, rather than as
+        // arguments, to keep the token out of the process list.
+        let mut curl_config = String::new();
+        for (key, value) in [
+            ("url", url),
+            ("request", method),
+            ("header", &format!("PRIVATE-TOKEN: {}", self.token)),
+            ("header", "Content-Type: application/json"),
+        ] {
+            writeln!(curl_config, "{key} = \"{}\"", escape_curl_config(value))?;
+        }
+        // Print the HTTP status on its own line after the response body.
+        writeln!(curl_config, "write-out = \"\\n%{{http_code}}\"")?;
+        if let Some(body) = body {
+            writeln!(
+                curl_config,
+                "data = \"{}\"",
+                escape_curl_config(&body.to_string())
+            )?;
+        }
+
+        let GitRunInfo {
+            path_to_git: _,
+            working_directory,
+            env,
+        } = self.git_run_info;
+        let mut child = match Command::new(CURL_EXECUTABLE)
+            .args(["--silent", "--show-error", "--config", "-"])
+            .current_dir(working_directory)
+            .env_clear()
+            .envs(env.iter())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Could not find {CURL_EXECUTABLE}, which is needed to submit merge requests."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+            Err(err) => return Err(err).wrap_err_with(|| format!("Running {CURL_EXECUTABLE}")),
+        };
+        {
+            use std::io::Write;
+            child
+                .stdin
+                .take()
+                .expect("Child process stdin was not piped")
+                .write_all(curl_config.as_bytes())
+                .wrap_err("Writing curl config")?;
+        }
+        let output = child
+            .wait_with_output()
+            .wrap_err_with(|| format!("Waiting for {CURL_EXECUTABLE}"))?;
+        if !output.status.success() {
+            write!(
+                effects.get_error_stream(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to connect to GitLab: {method} {url}"
+            )?;
+            let exit_code = output.status.code().unwrap_or(1);
+            return Ok(Err(ExitCode(isize::try_from(exit_code).unwrap_or(1))));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        match status.trim().parse::<u16>() {
+            Ok(status) if (200..300).contains(&status) => Ok(Ok(body.to_owned())),
+            _ => {
+                writeln!(effects.get_error_stream(), "{body}")?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "GitLab request failed with status {}: {method} {url}",
+                    status.trim()
+                )?;
+                Ok(Err(ExitCode(1)))
+            }
+        }
+    }
+}
+
+/// Escape a value for use in a double-quoted string in a `curl` config file.
+fn escape_curl_config(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+impl Forge for GitlabForge<'_> {
+    fn get_name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    fn get_review_unit(&self) -> (&'static str, &'static str) {
+        ("merge request", "merge requests")
+    }
+
+    fn query_reviews(
+        &mut self,
+        effects: &Effects,
+        heads: &[&str],
+    ) -> eyre::Result<Result<HashMap<String, CodeReview>, ExitCode>> {
+        let mut result = HashMap::new();
+        for head in heads {
+            let query: String = form_urlencoded::Serializer::new(String::new())
+                .append_pair("state", "opened")
+                .append_pair("source_branch", head)
+                .finish();
+            let url = self.merge_requests_url(&format!("?{query}"));
+            let output = match self.request(effects, "GET", &url, None)? {
+                Ok(output) => output,
+                Err(exit_code) => return Ok(Err(exit_code)),
+            };
+            let merge_requests: Vec<MergeRequestInfo> = serde_json::from_str(&output)
+                .wrap_err_with(|| format!("Parsing merge requests for branch {head}: {output}"))?;
+            if let Some(merge_request) = merge_requests.into_iter().next() {
+                result.insert(head.to_string(), merge_request.into());
+            }
+        }
+        Ok(Ok(result))
+    }
+
+    fn query_protected_branches(
+        &mut self,
+        effects: &Effects,
+    ) -> eyre::Result<Result<Vec<String>, ExitCode>> {
+        let url = self.project_url("/protected_branches?per_page=100");
+        let output = match self.request(effects, "GET", &url, None)? {
+            Ok(output) => output,
+            Err(exit_code) => return Ok(Err(exit_code)),
+        };
+        let protected_branches: Vec<ProtectedBranchInfo> = serde_json::from_str(&output)
+            .wrap_err_with(|| format!("Parsing protected branches: {output}"))?;
+        Ok(Ok(protected_branches
+            .into_iter()
+            .map(|protected_branch| protected_branch.name)
+            .collect()))
+    }
+
+    fn create_review(
+        &mut self,
+        effects: &Effects,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>> {
+        let CodeReviewSpec {
+            commit_oid: _,
+            head,
+            base,
+            title,
+            body,
+        } = spec;
+        let request = serde_json::json!({
+            "source_branch": head,
+            "target_branch": base,
+            "title": title,
+            "description": body,
+        });
+        let url = self.merge_requests_url("");
+        let output = match self.request(effects, "POST", &url, Some(&request))? {
+            Ok(output) => output,
+            Err(exit_code) => return Ok(Err(exit_code)),
+        };
+        let merge_request: MergeRequestInfo = serde_json::from_str(&output)
+            .wrap_err_with(|| format!("Parsing created merge request: {output}"))?;
+        Ok(Ok(merge_request.into()))
+    }
+
+    fn update_review(
+        &mut self,
+        effects: &Effects,
+        review: &CodeReview,
+        spec: &CodeReviewSpec,
+    ) -> eyre::Result<Result<CodeReview, ExitCode>> {
+        let CodeReviewSpec {
+            commit_oid: _,
+            head: _,
+            base,
+            title,
+            body,
+        } = spec;
+        let request = serde_json::json!({
+            "target_branch": base,
+            "title": title,
+            "description": body,
+        });
+        let iid = review.id.trim_start_matches('!');
+        let url = self.merge_requests_url(&format!("/{iid}"));
+        let output = match self.request(effects, "PUT", &url, Some(&request))? {
+            Ok(output) => output,
+            Err(exit_code) => return Ok(Err(exit_code)),
+        };
+        let merge_request: MergeRequestInfo = serde_json::from_str(&output)
+            .wrap_err_with(|| format!("Parsing updated merge request: {output}"))?;
+        Ok(Ok(merge_request.into()))
+    }
+}
@@ -1,19 +1,41 @@
+//! Push commits to a remote, and optionally submit them for code review.
+
+mod gerrit;
+mod github;
+mod gitlab;
+mod phabricator;
+mod reviews;
+
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::time::SystemTime;
 
+use clap::ValueEnum;
 use cursive_core::theme::{BaseColor, Effect, Style};
 use git_branchless_invoke::CommandContext;
 use itertools::{Either, Itertools};
 use lazy_static::lazy_static;
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::config::{get_restack_preserve_timestamps, PROTECTED_BRANCHES_CONFIG_KEY};
 use lib::core::dag::{commit_set_to_vec, Dag};
 use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::forge::Forge;
 use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::protected_branches::ProtectedBranches;
 use lib::core::repo_ext::RepoExt;
-use lib::git::{Branch, BranchType, CategorizedReferenceName, ConfigRead, GitRunInfo, Repo};
+use lib::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+};
+use lib::git::{
+    Branch, BranchType, CategorizedReferenceName, Commit, ConfigRead, GitRunInfo, MaybeZeroOid,
+    NonZeroOid, Repo,
+};
 use lib::util::ExitCode;
+use rayon::ThreadPoolBuilder;
 
-use git_branchless_opts::{ResolveRevsetOptions, Revset, SubmitArgs};
+use git_branchless_opts::{ResolveRevsetOptions, Revset, SubmitArgs, SubmitForge};
 use git_branchless_revset::resolve_commits;
 
 lazy_static! {
@@ -30,6 +52,7 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> eyre::Result<ExitC
     } = ctx;
     let SubmitArgs {
         create,
+        forge,
         revset,
         resolve_revset_options,
     } = args;
@@ -39,6 +62,7 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> eyre::Result<ExitC
         revset,
         &resolve_revset_options,
         create,
+        forge,
     )
 }
 
@@ -48,8 +72,29 @@ fn submit(
     revset: Revset,
     resolve_revset_options: &ResolveRevsetOptions,
     create: bool,
+    forge: Option<SubmitForge>,
 ) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
+    let forge = match forge {
+        Some(forge) => forge,
+        None => match get_configured_forge(effects, &repo)? {
+            Ok(forge) => forge,
+            Err(exit_code) => return Ok(exit_code),
+        },
+    };
+    // Set up the forge before pushing anything, so that a misconfigured forge
+    // is reported before any branches are created.
+    let mut review_forge: Option<Box<dyn Forge>> = match forge {
+        SubmitForge::Branch | SubmitForge::Gerrit | SubmitForge::Phabricator => None,
+        SubmitForge::Github => Some(Box::new(github::GithubForge::new(git_run_info))),
+        SubmitForge::Gitlab => match gitlab::GitlabForge::new(effects, git_run_info, &repo)? {
+            Ok(forge) => Some(Box::new(forge)),
+            Err(exit_code) => return Ok(exit_code),
+        },
+    };
+    // Branches have to be on the remote before reviews can be created for
+    // them.
+    let create = create || forge != SubmitForge::Branch;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let now = SystemTime::now();
@@ -73,6 +118,31 @@ fn submit(
                 return Ok(ExitCode(1));
             }
         };
+    match forge {
+        SubmitForge::Branch | SubmitForge::Github | SubmitForge::Gitlab => {}
+        SubmitForge::Gerrit => {
+            return gerrit::submit_changes(
+                effects,
+                git_run_info,
+                &repo,
+                &dag,
+                &event_log_db,
+                event_tx_id,
+                &commit_set,
+            );
+        }
+        SubmitForge::Phabricator => {
+            return phabricator::submit_revisions(
+                effects,
+                git_run_info,
+                &repo,
+                &dag,
+                &event_log_db,
+                event_tx_id,
+                &commit_set,
+            );
+        }
+    }
 
     let branches: Vec<Branch> = commit_set_to_vec(&commit_set)?
         .into_iter()
@@ -110,6 +180,46 @@ fn submit(
         .map(|(v, k)| (k, v))
         .into_group_map();
 
+    // The branches which are already on a remote are force-pushed below, so
+    // make sure that none of them are protected.
+    {
+        let branch_names: Vec<&str> = remotes_to_branches
+            .values()
+            .flatten()
+            .map(|branch| branch.get_name())
+            .collect::<Result<_, _>>()?;
+        if !branch_names.is_empty() {
+            let mut protected_branches = ProtectedBranches::new(&repo)?;
+            if let Some(review_forge) = review_forge.as_mut() {
+                match review_forge.query_protected_branches(effects)? {
+                    Ok(patterns) => protected_branches.add_patterns(patterns),
+                    Err(exit_code) => return Ok(exit_code),
+                }
+            }
+            let protected_branch_names = branch_names
+                .into_iter()
+                .filter(|branch_name| protected_branches.is_protected(branch_name))
+                .sorted()
+                .collect_vec();
+            if !protected_branch_names.is_empty() {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+Refusing to force-push {}: {}
+Protected branches are the main branch, the branches matching {PROTECTED_BRANCHES_CONFIG_KEY},
+and the branches protected on the forge. Push them with git push instead.",
+                    Pluralize {
+                        determiner: None,
+                        amount: protected_branch_names.len(),
+                        unit: ("protected branch", "protected branches"),
+                    },
+                    protected_branch_names.join(", "),
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    }
+
     let (created_branches, uncreated_branches) = {
         let mut branch_names: Vec<&str> = branches_without_remotes
             .iter()
@@ -326,7 +436,47 @@ create and push them, retry this operation with the --create option."
         )?;
     }
 
-    Ok(ExitCode(0))
+    match review_forge.as_mut() {
+        None => Ok(ExitCode(0)),
+        Some(review_forge) => reviews::sync_reviews(
+            effects,
+            review_forge.as_mut(),
+            &repo,
+            &dag,
+            &references_snapshot,
+            &commit_set,
+        ),
+    }
+}
+
+fn get_configured_forge(
+    effects: &Effects,
+    repo: &Repo,
+) -> eyre::Result<Result<SubmitForge, ExitCode>> {
+    let forge_config_key = "branchless.submit.forge";
+    let forge: Option<String> = repo.get_readonly_config()?.get(forge_config_key)?;
+    match forge {
+        None => Ok(Ok(SubmitForge::Branch)),
+        Some(forge) => match SubmitForge::from_str(&forge, true) {
+            Ok(forge) => Ok(Ok(forge)),
+            Err(_) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Invalid value for config value {forge_config_key}: {forge}"
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Expected one of: {}",
+                    SubmitForge::value_variants()
+                        .iter()
+                        .filter_map(|variant| variant.to_possible_value())
+                        .map(|value| value.get_name().to_owned())
+                        .join(", ")
+                )?;
+                Ok(Err(ExitCode(1)))
+            }
+        },
+    }
 }
 
 fn get_default_remote(repo: &Repo) -> eyre::Result<Option<String>> {
@@ -357,3 +507,95 @@ fn get_default_remote(repo: &Repo) -> eyre::Result<Option<String>> {
     let push_default_remote_opt = repo.get_readonly_config()?.get("remote.pushDefault")?;
     Ok(push_default_remote_opt)
 }
+
+/// Replace the messages of the given commits, restacking their descendants.
+/// Returns the rewritten commits.
+fn reword_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    rewordings: &[(Commit, String)],
+) -> eyre::Result<Result<HashMap<NonZeroOid, MaybeZeroOid>, ExitCode>> {
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: false,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    let permissions = match RebasePlanPermissions::verify_rewrite_set(
+        dag,
+        build_options,
+        &rewordings
+            .iter()
+            .map(|(commit, _message)| commit.get_oid())
+            .collect(),
+    )? {
+        Ok(permissions) => permissions,
+        Err(err) => {
+            err.describe(effects, repo)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let rebase_plan = {
+        let pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(repo)?;
+        let mut builder = RebasePlanBuilder::new(dag, permissions);
+        for (commit, message) in rewordings {
+            let replacement_oid = commit.amend_commit(None, None, None, Some(message), None)?;
+            builder.move_subtree(commit.get_oid(), commit.get_parent_oids())?;
+            builder.replace_commit(commit.get_oid(), replacement_oid)?;
+        }
+        match builder.build(effects, git_run_info, &pool, &repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => return Ok(Ok(Default::default())),
+            Err(err) => {
+                err.describe(effects, repo)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        }
+    };
+
+    let execute_options = ExecuteRebasePlanOptions {
+        now: SystemTime::now(),
+        event_tx_id,
+        preserve_timestamps: get_restack_preserve_timestamps(repo)?,
+        force_in_memory: true,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        check_out_commit_options: CheckOutCommitOptions {
+            additional_args: Default::default(),
+            reset: false,
+            render_smartlog: false,
+        },
+    };
+    let result = execute_rebase_plan(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        &rebase_plan,
+        &execute_options,
+    )?;
+    match result {
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: Some(rewritten_oids),
+        } => Ok(Ok(rewritten_oids)),
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: None,
+        } => Ok(Ok(Default::default())),
+        ExecuteRebasePlanResult::DeclinedToMerge {
+            failed_merge_info: _,
+        } => {
+            writeln!(
+                effects.get_error_stream(),
+                "BUG: Merge failed, but rewording shouldn't cause any merge failures."
+            )?;
+            Ok(Err(ExitCode(1)))
+        }
+        ExecuteRebasePlanResult::Failed { exit_code } => Ok(Err(exit_code)),
+    }
+}
@@ -0,0 +1,418 @@
+//! Submit commits as Phabricator revisions.
+//!
+//! Like Gerrit, Phabricator reviews individual commits. Each commit is
+//! submitted with `arc diff`, which creates a new revision or updates the one
+//! named by the `Differential Revision` trailer in the commit message.
+//! Commits for new revisions are reworded to add the trailer, so that later
+//! submissions (and rewrites of the commits) update the same revision. The
+//! status of each revision is then queried with `arc call-conduit` and
+//! recorded in the database so that it can be shown in the smartlog.
+//!
+//! Phabricator is accessed via [Arcanist](https://secure.phabricator.com/book/phabricator/article/arcanist/)
+//! (`arc`), which must be installed and configured for the repository.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::process::{Command, Stdio};
+
+use bstr::ByteSlice;
+use eyre::Context;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::{Effects, OperationType};
+use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::forge::{CiStatus, CodeReview, CodeReviewDb, PHABRICATOR_FORGE_NAME};
+use lib::core::formatting::Pluralize;
+use lib::core::node_descriptors::extract_diff_number;
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::instrument;
+
+/// The name of the Arcanist executable.
+const ARC_EXECUTABLE: &str = "arc";
+
+lazy_static! {
+    /// Matches the line which `arc diff` prints for the submitted revision,
+    /// such as `Revision URI: https://phabricator.example.com/D123`.
+    static ref REVISION_URI_RE: Regex =
+        Regex::new(r"(?m)^Revision URI:\s*(?P<uri>\S+/(?P<revision>D[0-9]+))\s*$")
+            .expect("Failed to compile `REVISION_URI_RE` regex");
+}
+
+/// The response to a `differential.revision.search` Conduit call.
+#[derive(Debug, Deserialize)]
+struct ConduitResponse {
+    response: Option<RevisionSearchResults>,
+    #[serde(rename = "errorMessage")]
+    error_message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevisionSearchResults {
+    data: Vec<RevisionInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevisionInfo {
+    id: u64,
+    fields: RevisionFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevisionFields {
+    status: RevisionStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevisionStatus {
+    name: String,
+}
+
+/// A revision which was submitted with `arc diff`.
+#[derive(Debug)]
+struct SubmittedRevision {
+    commit_oid: NonZeroOid,
+    revision: String,
+    uri: String,
+    title: String,
+    is_new: bool,
+}
+
+/// Submit the draft commits in `commit_set` to Phabricator with `arc diff`,
+/// adding `Differential Revision` trailers to the commits for any new
+/// revisions.
+#[instrument]
+pub fn submit_revisions(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    commit_set: &CommitSet,
+) -> eyre::Result<ExitCode> {
+    let commit_set = dag.query_draft_commits()?.intersection(commit_set);
+    let commits = sorted_commit_set(repo, dag, &commit_set)?;
+    if commits.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no draft commits to submit."
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    // If submitting a commit fails, the commits before it have still been
+    // submitted, so record their revisions before reporting the failure.
+    let mut failure = None;
+    let mut submitted = Vec::new();
+    {
+        let (effects, progress) = effects.start_operation(OperationType::SyncCodeReviews);
+        progress.notify_progress(0, commits.len());
+        for commit in &commits {
+            let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+            let existing_revision = extract_diff_number(&message);
+            let commit_oid = commit.get_oid().to_string();
+            let base_oid = match commit.get_parent_oids().first() {
+                Some(parent_oid) => parent_oid.to_string(),
+                None => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Cannot submit root commit {} to Phabricator.",
+                        effects
+                            .get_glyphs()
+                            .render(commit.friendly_describe(effects.get_glyphs())?)?
+                    )?;
+                    failure = Some(ExitCode(1));
+                    break;
+                }
+            };
+            let mut args = vec![
+                "diff",
+                "--no-amend",
+                "--allow-untracked",
+                "--verbatim",
+                "--head",
+                commit_oid.as_str(),
+            ];
+            match &existing_revision {
+                Some(revision) => args.extend(["--update", revision.as_str()]),
+                None => args.push("--create"),
+            }
+            args.push(base_oid.as_str());
+
+            let output = match run_arc(&effects, git_run_info, &args, None)? {
+                Ok(output) => output,
+                Err(exit_code) => {
+                    failure = Some(exit_code);
+                    break;
+                }
+            };
+            let captures = match REVISION_URI_RE.captures(&output) {
+                Some(captures) => captures,
+                None => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Could not determine the revision for commit {} from the output of: {ARC_EXECUTABLE} diff",
+                        effects.get_glyphs().render(commit.friendly_describe(effects.get_glyphs())?)?
+                    )?;
+                    failure = Some(ExitCode(1));
+                    break;
+                }
+            };
+            submitted.push(SubmittedRevision {
+                commit_oid: commit.get_oid(),
+                revision: captures["revision"].to_owned(),
+                uri: captures["uri"].to_owned(),
+                title: commit.get_summary()?.to_str_lossy().into_owned(),
+                is_new: existing_revision.is_none(),
+            });
+            progress.notify_progress_inc(1);
+        }
+    }
+
+    // Record the new revisions in the commit messages, so that they're
+    // updated rather than recreated the next time the commits are submitted.
+    let rewordings = commits
+        .iter()
+        .zip(submitted.iter())
+        .filter(|(_commit, submitted)| submitted.is_new)
+        .map(|(commit, submitted)| -> eyre::Result<_> {
+            let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+            let message = add_differential_revision(&message, &submitted.uri);
+            Ok((commit.clone(), message))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> = if rewordings.is_empty() {
+        Default::default()
+    } else {
+        writeln!(
+            effects.get_output_stream(),
+            "Adding Differential Revision to {}.",
+            Pluralize {
+                determiner: None,
+                amount: rewordings.len(),
+                unit: ("commit", "commits"),
+            }
+        )?;
+        match crate::reword_commits(
+            effects,
+            git_run_info,
+            repo,
+            dag,
+            event_log_db,
+            event_tx_id,
+            &rewordings,
+        )? {
+            Ok(rewritten_oids) => rewritten_oids,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    };
+
+    let statuses = if failure.is_some() {
+        Default::default()
+    } else {
+        match query_revision_statuses(
+            effects,
+            git_run_info,
+            submitted
+                .iter()
+                .map(|submitted| submitted.revision.as_str()),
+        )? {
+            Ok(statuses) => statuses,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    };
+
+    let conn = repo.get_db_conn()?;
+    let code_review_db = CodeReviewDb::new(&conn)?;
+    for submitted in &submitted {
+        let commit_oid = match rewritten_oids.get(&submitted.commit_oid) {
+            None => submitted.commit_oid,
+            Some(MaybeZeroOid::NonZero(new_oid)) => *new_oid,
+            Some(MaybeZeroOid::Zero) => continue,
+        };
+        let review = CodeReview {
+            id: submitted.revision.clone(),
+            url: submitted.uri.clone(),
+            head: submitted.revision.clone(),
+            base: Default::default(),
+            title: submitted.title.clone(),
+            body: Default::default(),
+            ci_status: CiStatus::Unknown,
+            state: statuses.get(&submitted.revision).cloned(),
+        };
+        code_review_db.set_review(PHABRICATOR_FORGE_NAME, commit_oid, &review)?;
+    }
+
+    let (created, updated): (Vec<_>, Vec<_>) =
+        submitted.iter().partition(|submitted| submitted.is_new);
+    for (verb, revisions) in [("Created", created), ("Updated", updated)] {
+        if revisions.is_empty() {
+            continue;
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "{verb} {}:",
+            Pluralize {
+                determiner: None,
+                amount: revisions.len(),
+                unit: ("revision", "revisions"),
+            }
+        )?;
+        for submitted in revisions {
+            let status = match statuses.get(&submitted.revision) {
+                Some(status) => format!(" [{status}]"),
+                None => String::new(),
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "  {} {}: {}{status}",
+                submitted.revision,
+                submitted.title,
+                submitted.uri,
+            )?;
+        }
+    }
+    Ok(failure.unwrap_or(ExitCode(0)))
+}
+
+/// Add a `Differential Revision` trailer for the given revision URI to the
+/// given commit message, in the same way as `arc diff` would when amending the
+/// commit.
+fn add_differential_revision(message: &str, uri: &str) -> String {
+    format!("{}\n\nDifferential Revision: {uri}\n", message.trim_end())
+}
+
+/// Look up the status (such as `accepted` or `needs review`) of each of the
+/// given revisions.
+fn query_revision_statuses<'a>(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revisions: impl IntoIterator<Item = &'a str>,
+) -> eyre::Result<Result<HashMap<String, String>, ExitCode>> {
+    let ids: Vec<u64> = revisions
+        .into_iter()
+        .filter_map(|revision| revision.trim_start_matches('D').parse().ok())
+        .collect();
+    if ids.is_empty() {
+        return Ok(Ok(Default::default()));
+    }
+
+    let request = serde_json::json!({ "constraints": { "ids": ids } }).to_string();
+    let output = match run_arc(
+        effects,
+        git_run_info,
+        &["call-conduit", "--", "differential.revision.search"],
+        Some(&request),
+    )? {
+        Ok(output) => output,
+        Err(exit_code) => return Ok(Err(exit_code)),
+    };
+    let response: ConduitResponse = serde_json::from_str(&output)
+        .wrap_err_with(|| format!("Parsing revision statuses: {output}"))?;
+    let results = match response {
+        ConduitResponse {
+            response: Some(results),
+            error_message: _,
+        } => results,
+        ConduitResponse {
+            response: None,
+            error_message,
+        } => {
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to query revision statuses: {}",
+                error_message.unwrap_or_default()
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    let statuses = results
+        .data
+        .into_iter()
+        .map(|revision| {
+            (
+                format!("D{}", revision.id),
+                revision.fields.status.name.to_lowercase(),
+            )
+        })
+        .collect();
+    Ok(Ok(statuses))
+}
+
+/// Run Arcanist with the given arguments and return its standard output. If
+/// it fails, its output is shown and its exit code is returned.
+fn run_arc(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> eyre::Result<Result<String, ExitCode>> {
+    let GitRunInfo {
+        path_to_git: _,
+        working_directory,
+        env,
+    } = git_run_info;
+    let child = Command::new(ARC_EXECUTABLE)
+        .args(args)
+        .current_dir(working_directory)
+        .env_clear()
+        .envs(env.iter())
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            writeln!(
+                effects.get_output_stream(),
+                "\
+Could not find Arcanist ({ARC_EXECUTABLE}), which is needed to submit Phabricator revisions.
+Install it from https://secure.phabricator.com/book/phabricator/article/arcanist/"
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        Err(err) => {
+            return Err(err)
+                .wrap_err_with(|| format!("Running {ARC_EXECUTABLE} {}", args.join(" ")))
+        }
+    };
+    if let Some(stdin) = stdin {
+        use std::io::Write;
+        child
+            .stdin
+            .take()
+            .expect("Child process stdin was not piped")
+            .write_all(stdin.as_bytes())
+            .wrap_err("Writing Arcanist stdin")?;
+    }
+    let output = child
+        .wait_with_output()
+        .wrap_err_with(|| format!("Waiting for {ARC_EXECUTABLE} {}", args.join(" ")))?;
+
+    if output.status.success() {
+        Ok(Ok(String::from_utf8_lossy(&output.stdout).into_owned()))
+    } else {
+        write!(
+            effects.get_error_stream(),
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "Failed to run: {ARC_EXECUTABLE} {}",
+            args.iter().take(1).join(" ")
+        )?;
+        let exit_code = output.status.code().unwrap_or(1);
+        Ok(Err(ExitCode(isize::try_from(exit_code).unwrap_or(1))))
+    }
+}
@@ -0,0 +1,248 @@
+//! Create and update code reviews for submitted branches on any [`Forge`].
+//!
+//! Each submitted branch gets its own code review. The base branch of each
+//! review is the nearest ancestor branch which was also submitted, or the main
+//! branch if there is none, so that a stack of branches is reviewed as a stack
+//! of code reviews. Since the bases are recomputed on every submit, reviews are
+//! rewired automatically after commits are moved.
+
+use std::fmt::Write;
+
+use eden_dag::DagAlgorithm;
+use itertools::Itertools;
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::{Effects, OperationType};
+use lib::core::forge::{CodeReviewDb, CodeReviewSpec, Forge};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::git::{CategorizedReferenceName, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// Create a code review for each branch in `commit_set` which doesn't already
+/// have one, and update the base branch, title, and body of existing reviews
+/// to match the current stack and commit messages. The resulting reviews are
+/// recorded in the database.
+///
+/// The branches must already have been pushed.
+#[instrument]
+pub fn sync_reviews(
+    effects: &Effects,
+    forge: &mut dyn Forge,
+    repo: &Repo,
+    dag: &Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_set: &CommitSet,
+) -> eyre::Result<ExitCode> {
+    let specs = make_review_specs(repo, dag, references_snapshot, commit_set)?;
+    if specs.is_empty() {
+        return Ok(ExitCode(0));
+    }
+
+    let conn = repo.get_db_conn()?;
+    let code_review_db = CodeReviewDb::new(&conn)?;
+    let (effects, progress) = effects.start_operation(OperationType::SyncCodeReviews);
+    progress.notify_progress(0, specs.len());
+    let mut existing_reviews = {
+        let heads = specs.iter().map(|spec| spec.head.as_str()).collect_vec();
+        match forge.query_reviews(&effects, &heads)? {
+            Ok(reviews) => reviews,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    };
+
+    let mut created = Vec::new();
+    let mut updated = Vec::new();
+    let mut up_to_date = Vec::new();
+    for spec in specs {
+        let review = match existing_reviews.remove(&spec.head) {
+            None => {
+                let review = match forge.create_review(&effects, &spec)? {
+                    Ok(review) => review,
+                    Err(exit_code) => return Ok(exit_code),
+                };
+                created.push(review.clone());
+                review
+            }
+
+            Some(review) if spec.is_up_to_date(&review) => {
+                up_to_date.push(review.clone());
+                review
+            }
+
+            Some(old_review) => {
+                let review = match forge.update_review(&effects, &old_review, &spec)? {
+                    Ok(review) => review,
+                    Err(exit_code) => return Ok(exit_code),
+                };
+                updated.push((old_review, review.clone()));
+                review
+            }
+        };
+        code_review_db.set_review(forge.get_name(), spec.commit_oid, &review)?;
+        progress.notify_progress_inc(1);
+    }
+    drop(progress);
+
+    let unit = forge.get_review_unit();
+    if !created.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Created {}:",
+            Pluralize {
+                determiner: None,
+                amount: created.len(),
+                unit,
+            }
+        )?;
+        for review in created {
+            writeln!(
+                effects.get_output_stream(),
+                "  {} {} (into {}): {}",
+                review.id,
+                review.head,
+                review.base,
+                review.url
+            )?;
+        }
+    }
+    if !updated.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Updated {}:",
+            Pluralize {
+                determiner: None,
+                amount: updated.len(),
+                unit,
+            }
+        )?;
+        for (old_review, review) in updated {
+            let base_description = if old_review.base == review.base {
+                format!("into {}", review.base)
+            } else {
+                format!("into {}, was {}", review.base, old_review.base)
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "  {} {} ({base_description}): {}",
+                review.id,
+                review.head,
+                review.url
+            )?;
+        }
+    }
+    if !up_to_date.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Skipped {} (already up-to-date): {}",
+            Pluralize {
+                determiner: None,
+                amount: up_to_date.len(),
+                unit,
+            },
+            up_to_date
+                .into_iter()
+                .map(|review| format!("{} {}", review.id, review.head))
+                .join(", ")
+        )?;
+    }
+
+    Ok(ExitCode(0))
+}
+
+/// Determine the desired code review for each local branch in `commit_set`,
+/// in topological order.
+fn make_review_specs(
+    repo: &Repo,
+    dag: &Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_set: &CommitSet,
+) -> eyre::Result<Vec<CodeReviewSpec>> {
+    let main_branch_name = get_main_branch_name(repo)?;
+    let submitted_branch_commits = dag.branch_commits.intersection(commit_set);
+
+    let mut result = Vec::new();
+    for commit in sorted_commit_set(repo, dag, &submitted_branch_commits)? {
+        let commit_oid = commit.get_oid();
+        let base = match get_base_branch_commit(dag, &submitted_branch_commits, commit_oid)? {
+            Some(base_oid) => get_local_branch_names(references_snapshot, base_oid)
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| main_branch_name.clone()),
+            None => main_branch_name.clone(),
+        };
+
+        let message = commit.get_message_pretty()?.to_string();
+        let (title, body) = match message.split_once('\n') {
+            Some((title, body)) => (title.trim().to_owned(), body.trim().to_owned()),
+            None => (message.trim().to_owned(), String::new()),
+        };
+
+        for head in get_local_branch_names(references_snapshot, commit_oid) {
+            result.push(CodeReviewSpec {
+                commit_oid,
+                head,
+                base: base.clone(),
+                title: title.clone(),
+                body: body.clone(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Find the nearest ancestor of the given commit which has a submitted branch
+/// attached to it.
+fn get_base_branch_commit(
+    dag: &Dag,
+    submitted_branch_commits: &CommitSet,
+    commit_oid: NonZeroOid,
+) -> eyre::Result<Option<NonZeroOid>> {
+    let parents = dag.query().parents(CommitSet::from(commit_oid))?;
+    let ancestors = dag.query().ancestors(parents)?;
+    let ancestor_branch_commits = submitted_branch_commits.intersection(&ancestors);
+    let nearest_ancestor_branch_commits = dag.query().heads_ancestors(ancestor_branch_commits)?;
+    let nearest_ancestor_branch_commit = commit_set_to_vec(&nearest_ancestor_branch_commits)?
+        .into_iter()
+        .next();
+    Ok(nearest_ancestor_branch_commit)
+}
+
+fn get_local_branch_names(
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_oid: NonZeroOid,
+) -> Vec<String> {
+    references_snapshot
+        .branch_oid_to_names
+        .get(&commit_oid)
+        .into_iter()
+        .flatten()
+        .filter_map(
+            |reference_name| match CategorizedReferenceName::new(reference_name) {
+                name @ CategorizedReferenceName::LocalBranch { .. } => name.remove_prefix().ok(),
+                CategorizedReferenceName::RemoteBranch { .. }
+                | CategorizedReferenceName::OtherRef { .. } => None,
+            },
+        )
+        .sorted()
+        .collect()
+}
+
+/// Get the name of the main branch as it's known on the remote, i.e. without
+/// any remote name prefix.
+pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
+    let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+    let name = CategorizedReferenceName::new(&main_branch_reference_name);
+    let name = match name {
+        CategorizedReferenceName::RemoteBranch { .. } => {
+            let name = name.remove_prefix()?;
+            match name.split_once('/') {
+                Some((_remote_name, branch_name)) => branch_name.to_owned(),
+                None => name,
+            }
+        }
+        CategorizedReferenceName::LocalBranch { .. }
+        | CategorizedReferenceName::OtherRef { .. } => name.remove_prefix()?,
+    };
+    Ok(name)
+}
@@ -1,6 +1,9 @@
 use lib::{
     git::GitVersion,
-    testing::{make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo},
+    testing::{
+        make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions,
+        GitWrapperWithRemoteRepo,
+    },
 };
 
 /// Minimum version due to changes in the output of `git push`.
@@ -294,3 +297,874 @@ fn test_submit_up_to_date_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_submit_protected_branch() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    cloned_repo.run(&["checkout", "-b", "release/1.0"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.run(&["submit", "--create"])?;
+
+    cloned_repo.run(&["config", "branchless.core.protectedBranches", "release/*"])?;
+    cloned_repo.run(&["checkout", "release/1.0"])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "updated message"])?;
+    {
+        let (stdout, stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["stack() | foo"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Refusing to force-push 1 protected branch: release/1.0
+        Protected branches are the main branch, the branches matching branchless.core.protectedBranches,
+        and the branches protected on the forge. Push them with git push instead.
+        "###);
+    }
+
+    Ok(())
+}
+
+/// A stand-in for the GitHub CLI, which records its invocations in
+/// `$FAKE_GH_STATE/log` and keeps the pull requests it has created in
+/// `$FAKE_GH_STATE`.
+#[cfg(unix)]
+const FAKE_GH_SCRIPT: &str = r#"#!/bin/sh
+state="$FAKE_GH_STATE"
+echo "gh $*" >>"$state/log"
+case "$1 $2" in
+"pr list")
+    cat "$state/$4.json" 2>/dev/null || echo '[]'
+    ;;
+"pr create")
+    number=$(($(cat "$state/count" 2>/dev/null || echo 0) + 1))
+    echo "$number" >"$state/count"
+    echo "$4" >"$state/pr-$number"
+    url="https://github.com/example/repo/pull/$number"
+    printf '[{"number":%s,"url":"%s","headRefName":"%s","baseRefName":"%s","title":"%s","body":"%s"}]' \
+        "$number" "$url" "$4" "$6" "$8" "${10}" >"$state/$4.json"
+    echo "$url"
+    ;;
+"api --paginate")
+    echo "master"
+    ;;
+"pr edit")
+    head=$(cat "$state/pr-$3")
+    url="https://github.com/example/repo/pull/$3"
+    printf '[{"number":%s,"url":"%s","headRefName":"%s","baseRefName":"%s","title":"%s","body":"%s","statusCheckRollup":[{"status":"COMPLETED","conclusion":"FAILURE"}]}]' \
+        "$3" "$url" "$head" "$5" "$7" "$9" >"$state/$head.json"
+    ;;
+*)
+    echo "unexpected arguments: $*" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_submit_github() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    let GitWrapperWithRemoteRepo {
+        temp_dir,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    let bin_dir = temp_dir.path().join("bin");
+    let state_dir = temp_dir.path().join("gh-state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+    let gh_path = bin_dir.join("gh");
+    std::fs::write(&gh_path, FAKE_GH_SCRIPT)?;
+    std::fs::set_permissions(&gh_path, std::fs::Permissions::from_mode(0o755))?;
+    let options = GitRunOptions {
+        env: HashMap::from([
+            (
+                "PATH".to_string(),
+                format!(
+                    "{}:{}",
+                    bin_dir.display(),
+                    cloned_repo.get_path_for_env().to_string_lossy()
+                ),
+            ),
+            (
+                "FAKE_GH_STATE".to_string(),
+                state_dir.to_string_lossy().into_owned(),
+            ),
+        ]),
+        ..Default::default()
+    };
+    let read_gh_log = || -> eyre::Result<String> {
+        let log = std::fs::read_to_string(state_dir.join("log"))?;
+        std::fs::remove_file(state_dir.join("log"))?;
+        Ok(log)
+    };
+
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["--forge", "github"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push --set-upstream origin bar foo
+        branch 'bar' set up to track 'origin/bar'.
+        branch 'foo' set up to track 'origin/foo'.
+        Created 2 branches: bar, foo
+        Created 2 pull requests:
+          #1 foo (into master): https://github.com/example/repo/pull/1
+          #2 bar (into foo): https://github.com/example/repo/pull/2
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @r###"
+        gh pr list --head foo --state open --json number,url,headRefName,baseRefName,title,body,statusCheckRollup
+        gh pr list --head bar --state open --json number,url,headRefName,baseRefName,title,body,statusCheckRollup
+        gh pr create --head foo --base master --title create test3.txt --body 
+        gh pr create --head bar --base foo --title create test4.txt --body 
+        "###);
+    }
+
+    // Moving `bar` onto the main branch should rewire its pull request.
+    cloned_repo.branchless("move", &["-s", "bar", "-d", "master"])?;
+    cloned_repo.run(&["config", "branchless.submit.forge", "github"])?;
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["draft()"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        branchless: running command: <git-executable> push --force-with-lease origin bar
+        Pushed 1 branch: bar
+        Skipped 1 branch (already up-to-date): foo
+        Updated 1 pull request:
+          #2 bar (into master, was foo): https://github.com/example/repo/pull/2
+        Skipped 1 pull request (already up-to-date): #1 foo
+        "###);
+        insta::assert_snapshot!(read_gh_log()?, @r###"
+        gh api --paginate repos/{owner}/{repo}/branches?protected=true --jq .[].name
+        gh pr list --head foo --state open --json number,url,headRefName,baseRefName,title,body,statusCheckRollup
+        gh pr list --head bar --state open --json number,url,headRefName,baseRefName,title,body,statusCheckRollup
+        gh pr edit 2 --base master --title create test4.txt --body 
+        "###);
+    }
+
+    // A third submit picks up the results of the checks which ran on the
+    // updated pull request.
+    {
+        cloned_repo.branchless_with_options("submit", &["draft()"], &options)?;
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 96d1c37 (master) create test2.txt
+        |\
+        | o 70deb1e (foo) #1 create test3.txt
+        |
+        @ f57e36f (> bar) #2 [CI failed] create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+/// A stand-in for `curl` which serves the GitLab merge request API from
+/// `$FAKE_GITLAB_STATE`. It reads the request from the config passed on stdin,
+/// and only accepts requests with the token `secret`.
+#[cfg(unix)]
+const FAKE_CURL_SCRIPT: &str = r#"#!/bin/sh
+state="$FAKE_GITLAB_STATE"
+config=$(cat)
+value() {
+    echo "$config" | sed -n "s/^$1 = \"\(.*\)\"\$/\1/p"
+}
+field() {
+    echo "$data" | sed -n "s/.*\\\\\"$1\\\\\":\\\\\"\([^\\\\]*\)\\\\\".*/\1/p"
+}
+url=$(value url)
+method=$(value request)
+data=$(value data)
+case "$url" in
+*/protected_branches*)
+    echo "curl $method protected_branches" >>"$state/log"
+    printf '[{"name":"master"}]\n200'
+    exit 0
+    ;;
+esac
+path=${url#https://gitlab.example.com/api/v4/projects/example%2Frepo/merge_requests}
+echo "curl $method $path $data" >>"$state/log"
+if ! echo "$config" | grep -q '^header = "PRIVATE-TOKEN: secret"$'; then
+    printf '{"message":"401 Unauthorized"}\n401'
+    exit 0
+fi
+mr() {
+    printf '{"iid":%s,"web_url":"https://gitlab.example.com/example/repo/-/merge_requests/%s","source_branch":"%s","target_branch":"%s","title":"%s","description":"%s"}' \
+        "$1" "$1" "$2" "$3" "$4" "$5"
+}
+case "$method $path" in
+"GET ?state=opened&source_branch="*)
+    cat "$state/${path#*source_branch=}.json" 2>/dev/null || printf '[]'
+    printf '\n200'
+    ;;
+"POST ")
+    number=$(($(cat "$state/count" 2>/dev/null || echo 0) + 1))
+    echo "$number" >"$state/count"
+    head=$(field source_branch)
+    echo "$head" >"$state/mr-$number"
+    mr "$number" "$head" "$(field target_branch)" "$(field title)" "$(field description)" >"$state/$head.tmp"
+    printf '[%s]' "$(cat "$state/$head.tmp")" >"$state/$head.json"
+    cat "$state/$head.tmp"
+    printf '\n201'
+    ;;
+"PUT /"*)
+    number=${path#/}
+    head=$(cat "$state/mr-$number")
+    mr "$number" "$head" "$(field target_branch)" "$(field title)" "$(field description)" >"$state/$head.tmp"
+    printf '[%s]' "$(cat "$state/$head.tmp")" >"$state/$head.json"
+    cat "$state/$head.tmp"
+    printf '\n200'
+    ;;
+*)
+    printf '{"message":"404 Not Found"}\n404'
+    ;;
+esac
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_submit_gitlab() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    let GitWrapperWithRemoteRepo {
+        temp_dir,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    let bin_dir = temp_dir.path().join("bin");
+    let state_dir = temp_dir.path().join("gitlab-state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+    let curl_path = bin_dir.join("curl");
+    std::fs::write(&curl_path, FAKE_CURL_SCRIPT)?;
+    std::fs::set_permissions(&curl_path, std::fs::Permissions::from_mode(0o755))?;
+    let make_options = |token: &str| GitRunOptions {
+        env: HashMap::from([
+            (
+                "PATH".to_string(),
+                format!(
+                    "{}:{}",
+                    bin_dir.display(),
+                    cloned_repo.get_path_for_env().to_string_lossy()
+                ),
+            ),
+            (
+                "FAKE_GITLAB_STATE".to_string(),
+                state_dir.to_string_lossy().into_owned(),
+            ),
+            ("GITLAB_TOKEN".to_string(), token.to_string()),
+        ]),
+        ..Default::default()
+    };
+    let options = make_options("secret");
+    let read_gitlab_log = || -> eyre::Result<String> {
+        let log = std::fs::read_to_string(state_dir.join("log"))?;
+        std::fs::remove_file(state_dir.join("log"))?;
+        Ok(log)
+    };
+
+    cloned_repo.run(&["config", "branchless.submit.forge", "gitlab"])?;
+    cloned_repo.run(&[
+        "config",
+        "branchless.submit.gitlab.url",
+        "https://gitlab.example.com/api/v4/",
+    ])?;
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..make_options("secret")
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No GitLab project was configured, so cannot submit merge requests.
+        Configure a value with: git config branchless.submit.gitlab.project <group>/<project>
+        "###);
+    }
+
+    cloned_repo.run(&["config", "branchless.submit.gitlab.project", "example/repo"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options("submit", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push --set-upstream origin bar foo
+        branch 'bar' set up to track 'origin/bar'.
+        branch 'foo' set up to track 'origin/foo'.
+        Created 2 branches: bar, foo
+        Created 2 merge requests:
+          !1 foo (into master): https://gitlab.example.com/example/repo/-/merge_requests/1
+          !2 bar (into foo): https://gitlab.example.com/example/repo/-/merge_requests/2
+        "###);
+        insta::assert_snapshot!(read_gitlab_log()?, @r###"
+        curl GET ?state=opened&source_branch=foo 
+        curl GET ?state=opened&source_branch=bar 
+        curl POST  {\"description\":\"\",\"source_branch\":\"foo\",\"target_branch\":\"master\",\"title\":\"create test3.txt\"}
+        curl POST  {\"description\":\"\",\"source_branch\":\"bar\",\"target_branch\":\"foo\",\"title\":\"create test4.txt\"}
+        "###);
+    }
+
+    // Moving `bar` onto the main branch should retarget its merge request.
+    cloned_repo.branchless("move", &["-s", "bar", "-d", "master"])?;
+    {
+        let (stdout, _stderr) =
+            cloned_repo.branchless_with_options("submit", &["draft()"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        branchless: running command: <git-executable> push --force-with-lease origin bar
+        Pushed 1 branch: bar
+        Skipped 1 branch (already up-to-date): foo
+        Updated 1 merge request:
+          !2 bar (into master, was foo): https://gitlab.example.com/example/repo/-/merge_requests/2
+        Skipped 1 merge request (already up-to-date): !1 foo
+        "###);
+        insta::assert_snapshot!(read_gitlab_log()?, @r###"
+        curl GET protected_branches
+        curl GET ?state=opened&source_branch=foo 
+        curl GET ?state=opened&source_branch=bar 
+        curl PUT /2 {\"description\":\"\",\"target_branch\":\"master\",\"title\":\"create test4.txt\"}
+        "###);
+    }
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 96d1c37 (master) create test2.txt
+        |\
+        | o 70deb1e (foo) !1 create test3.txt
+        |
+        @ f57e36f (> bar) !2 create test4.txt
+        "###);
+    }
+
+    // An invalid token is reported.
+    {
+        let (stdout, stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &["draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..make_options("wrong")
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        {"message":"401 Unauthorized"}
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin
+        Skipped 2 branches (already up-to-date): bar, foo
+        GitLab request failed with status 401: GET https://gitlab.example.com/api/v4/projects/example%2Frepo/merge_requests?state=opened&source_branch=foo
+        "###);
+    }
+
+    Ok(())
+}
+
+/// A stand-in for Gerrit's handling of pushes to `refs/for/<branch>`, to be
+/// installed as the remote's `post-receive` hook. It assigns a change number to
+/// each pushed commit by its `Change-Id`, stores each new patch set under
+/// `refs/changes/`, and reports the changes in the same format as Gerrit,
+/// which truncates long summaries.
+#[cfg(unix)]
+const FAKE_GERRIT_HOOK: &str = r#"#!/bin/sh
+state="$(git rev-parse --git-dir)/fake-gerrit"
+mkdir -p "$state"
+while read -r old new ref; do
+    case "$ref" in
+    refs/for/*) ;;
+    *) continue ;;
+    esac
+    echo
+    echo "SUCCESS"
+    echo
+    for commit in $(git rev-list --reverse "$new" "^refs/heads/${ref#refs/for/}" --); do
+        change_id=$(git log -1 --format='%(trailers:key=Change-Id,valueonly)' "$commit")
+        if [ -f "$state/$change_id" ]; then
+            number=$(cat "$state/$change_id")
+            suffix=""
+        else
+            number=$(($(cat "$state/count" 2>/dev/null || echo 0) + 1))
+            echo "$number" >"$state/count"
+            echo "$number" >"$state/$change_id"
+            suffix=" [NEW]"
+        fi
+        patch_set=$(($(cat "$state/$number.patch-sets" 2>/dev/null || echo 0) + 1))
+        echo "$patch_set" >"$state/$number.patch-sets"
+        git update-ref "refs/changes/$(printf %02d $((number % 100)))/$number/$patch_set" "$commit" >/dev/null 2>&1
+        summary=$(git log -1 --format=%s "$commit")
+        if [ ${#summary} -gt 80 ]; then
+            summary="$(printf '%s' "$summary" | cut -c1-77)..."
+        fi
+        echo "  https://gerrit.example.com/c/project/+/$number $summary$suffix"
+    done
+    git update-ref -d "$ref"
+done
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_submit_gerrit() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    let (hooks_dir, _stderr) = original_repo.run(&["rev-parse", "--git-path", "hooks"])?;
+    let hook_path = original_repo
+        .repo_path
+        .join(hooks_dir.trim())
+        .join("post-receive");
+    std::fs::write(&hook_path, FAKE_GERRIT_HOOK)?;
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+
+    cloned_repo.run(&["config", "branchless.submit.forge", "gerrit"])?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, stderr) = cloned_repo.branchless("submit", &[])?;
+        // Git pads the lines of remote output with spaces.
+        let stderr = redact_remotes(stderr)
+            .lines()
+            .map(|line| format!("{}\n", line.trim_end()))
+            .collect::<String>();
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
+        Previous HEAD position was 355e173 create test4.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 9d31bb2 create test4.txt
+        branchless: processing checkout
+        branchless: processing 1 update: ref refs/for/master
+        remote:
+        remote: SUCCESS
+        remote:
+        remote:   https://gerrit.example.com/c/project/+/1 create test3.txt [NEW]
+        remote:   https://gerrit.example.com/c/project/+/2 create test4.txt [NEW]
+        remote: branchless: processing 1 update: ref refs/for/master
+        To: file://<remote>
+         * [new reference]   9d31bb285030ce8b637611fe68f8202e90652ae7 -> refs/for/master
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Adding Change-Id to 2 commits.
+        Attempting rebase in-memory...
+        [1/2] Committed as: 1d4501e create test3.txt
+        [2/2] Committed as: 9d31bb2 create test4.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 9d31bb285030ce8b637611fe68f8202e90652ae7
+        In-memory rebase succeeded.
+        branchless: running command: <git-executable> push origin 9d31bb285030ce8b637611fe68f8202e90652ae7:refs/for/master
+        Submitted 2 changes (into master):
+          c/1 create test3.txt: https://gerrit.example.com/c/project/+/1
+          c/2 create test4.txt: https://gerrit.example.com/c/project/+/2
+        "###);
+    }
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test4.txt
+
+        Change-Id: I8543e796a388ce0a5ce374e5e40ea2c7083a3310
+        "###);
+    }
+
+    // Rewording a commit keeps its `Change-Id`, so resubmitting it updates the
+    // existing change.
+    cloned_repo.branchless("reword", &["-m", "reworded test4"])?;
+    {
+        let (stdout, stderr) = cloned_repo.branchless("submit", &[])?;
+        // Git pads the lines of remote output with spaces.
+        let stderr = redact_remotes(stderr)
+            .lines()
+            .map(|line| format!("{}\n", line.trim_end()))
+            .collect::<String>();
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref refs/for/master
+        remote:
+        remote: SUCCESS
+        remote:
+        remote:   https://gerrit.example.com/c/project/+/1 create test3.txt
+        remote:   https://gerrit.example.com/c/project/+/2 reworded test4
+        remote: branchless: processing 1 update: ref refs/for/master
+        To: file://<remote>
+         * [new reference]   50f8344bf4ffd40b18007f89d886ae6bd50dac12 -> refs/for/master
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push origin 50f8344bf4ffd40b18007f89d886ae6bd50dac12:refs/for/master
+        Submitted 2 changes (into master):
+          c/1 create test3.txt: https://gerrit.example.com/c/project/+/1
+          c/2 reworded test4: https://gerrit.example.com/c/project/+/2
+        "###);
+    }
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 96d1c37 (master) create test2.txt
+        |
+        o 1d4501e c/1 create test3.txt
+        |
+        @ 50f8344 c/2 reworded test4
+        "###);
+    }
+
+    // Changes are matched to commits by `Change-Id`, so they're still found
+    // when Gerrit truncates the summary.
+    cloned_repo.branchless(
+        "reword",
+        &[
+            "-m",
+            "reworded test4 with a summary which is long enough that Gerrit truncates it in its output",
+        ],
+    )?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> push origin 8c9ed1877570979a9df62594e2376a35dc8a6647:refs/for/master
+        Submitted 2 changes (into master):
+          c/1 create test3.txt: https://gerrit.example.com/c/project/+/1
+          c/2 reworded test4 with a summary which is long enough that Gerrit truncates it in its output: https://gerrit.example.com/c/project/+/2
+        "###);
+    }
+
+    Ok(())
+}
+
+/// A stand-in for Arcanist. `arc diff` assigns the next revision number to
+/// each new revision, and `arc call-conduit` reports `D1` as accepted and any
+/// other revisions as needing review. Its state is stored in
+/// `$FAKE_ARC_STATE`.
+#[cfg(unix)]
+const FAKE_ARC_SCRIPT: &str = r#"#!/bin/sh
+state="$FAKE_ARC_STATE"
+echo "arc $*" >>"$state/log"
+case "$1" in
+diff)
+    revision=""
+    prev=""
+    for arg in "$@"; do
+        if [ "$prev" = "--update" ]; then
+            revision="$arg"
+        fi
+        prev="$arg"
+    done
+    if [ -z "$revision" ]; then
+        number=$(($(cat "$state/count" 2>/dev/null || echo 0) + 1))
+        if [ "$number" = "$(cat "$state/fail_at" 2>/dev/null)" ]; then
+            echo "failed to create revision" >&2
+            exit 1
+        fi
+        echo "$number" >"$state/count"
+        revision="D$number"
+    fi
+    echo "Revision URI: https://phabricator.example.com/$revision"
+    ;;
+call-conduit)
+    cat >/dev/null
+    data=""
+    number=1
+    while [ "$number" -le "$(cat "$state/count")" ]; do
+        if [ "$number" = 1 ]; then status="Accepted"; else status="Needs Review"; fi
+        data="$data${data:+,}{\"id\":$number,\"fields\":{\"status\":{\"name\":\"$status\"}}}"
+        number=$((number + 1))
+    done
+    echo "{\"response\":{\"data\":[$data]},\"errorMessage\":null}"
+    ;;
+*)
+    echo "unexpected arguments: $*" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_submit_phabricator() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    let bin_dir = git.repo_path.join(".git").join("bin");
+    let state_dir = git.repo_path.join(".git").join("arc-state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+    let arc_path = bin_dir.join("arc");
+    std::fs::write(&arc_path, FAKE_ARC_SCRIPT)?;
+    std::fs::set_permissions(&arc_path, std::fs::Permissions::from_mode(0o755))?;
+    let options = GitRunOptions {
+        env: HashMap::from([
+            (
+                "PATH".to_string(),
+                format!(
+                    "{}:{}",
+                    bin_dir.display(),
+                    git.get_path_for_env().to_string_lossy()
+                ),
+            ),
+            (
+                "FAKE_ARC_STATE".to_string(),
+                state_dir.to_string_lossy().into_owned(),
+            ),
+        ]),
+        ..Default::default()
+    };
+    let read_arc_log = || -> eyre::Result<String> {
+        let log = std::fs::read_to_string(state_dir.join("log"))?;
+        std::fs::remove_file(state_dir.join("log"))?;
+        Ok(log)
+    };
+
+    {
+        let (stdout, _stderr) =
+            git.branchless_with_options("submit", &["--forge", "phabricator"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Adding Differential Revision to 2 commits.
+        Attempting rebase in-memory...
+        [1/2] Committed as: cf9dedf create test2.txt
+        [2/2] Committed as: b134a14 create test3.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout b134a149fe9a6d628cda5b5ccdf6224e78f01655
+        In-memory rebase succeeded.
+        Created 2 revisions:
+          D1 create test2.txt: https://phabricator.example.com/D1 [accepted]
+          D2 create test3.txt: https://phabricator.example.com/D2 [needs review]
+        "###);
+        insta::assert_snapshot!(read_arc_log()?, @r###"
+        arc diff --no-amend --allow-untracked --verbatim --head 96d1c37a3d4363611c49f7e52186e189a04c531f --create 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        arc diff --no-amend --allow-untracked --verbatim --head 70deb1e28791d8e7dd5a1f0c871a51b91282562f --create 96d1c37a3d4363611c49f7e52186e189a04c531f
+        arc call-conduit -- differential.revision.search
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt
+
+        Differential Revision: https://phabricator.example.com/D2
+        "###);
+    }
+
+    // Resubmitting updates the revisions named by the commit messages, and the
+    // trailers are kept when the commits are rewritten.
+    git.run(&["config", "branchless.submit.forge", "phabricator"])?;
+    git.branchless("move", &["-x", "HEAD", "-d", "master"])?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("submit", &["draft()"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Updated 2 revisions:
+          D1 create test2.txt: https://phabricator.example.com/D1 [accepted]
+          D2 create test3.txt: https://phabricator.example.com/D2 [needs review]
+        "###);
+        insta::assert_snapshot!(read_arc_log()?, @r###"
+        arc diff --no-amend --allow-untracked --verbatim --head cf9dedff5332b655a3f7690f485a1ff1d9cb14ca --update D1 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        arc diff --no-amend --allow-untracked --verbatim --head 1436f5648f5b7357a81460ddfe87e3120a015e76 --update D2 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        arc call-conduit -- differential.revision.search
+        "###);
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o cf9dedf D1 [accepted] create test2.txt
+        |
+        @ 1436f56 D2 [needs review] create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_submit_phabricator_partial_failure() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    let bin_dir = git.repo_path.join(".git").join("bin");
+    let state_dir = git.repo_path.join(".git").join("arc-state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+    let arc_path = bin_dir.join("arc");
+    std::fs::write(&arc_path, FAKE_ARC_SCRIPT)?;
+    std::fs::set_permissions(&arc_path, std::fs::Permissions::from_mode(0o755))?;
+    let env = HashMap::from([
+        (
+            "PATH".to_string(),
+            format!(
+                "{}:{}",
+                bin_dir.display(),
+                git.get_path_for_env().to_string_lossy()
+            ),
+        ),
+        (
+            "FAKE_ARC_STATE".to_string(),
+            state_dir.to_string_lossy().into_owned(),
+        ),
+    ]);
+
+    // Creating the second revision fails, but the first revision is still
+    // recorded in its commit message, so that it's not created again.
+    std::fs::write(state_dir.join("fail_at"), "2\n")?;
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "submit",
+            &["--forge", "phabricator"],
+            &GitRunOptions {
+                env: env.clone(),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        failed to create revision
+        branchless: creating working copy snapshot
+        Previous HEAD position was 70deb1e create test3.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 1f809c5 create test3.txt
+        branchless: processing checkout
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Failed to run: arc diff
+        Adding Differential Revision to 1 commit.
+        Attempting rebase in-memory...
+        [1/2] Committed as: cf9dedf create test2.txt
+        [2/2] Committed as: 1f809c5 create test3.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 1f809c52f84ec731f61013678b705ca18463e29f
+        In-memory rebase succeeded.
+        Created 1 revision:
+          D1 create test2.txt: https://phabricator.example.com/D1
+        "###);
+    }
+
+    std::fs::remove_file(state_dir.join("fail_at"))?;
+    std::fs::remove_file(state_dir.join("log"))?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "submit",
+            &["--forge", "phabricator"],
+            &GitRunOptions {
+                env,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Adding Differential Revision to 1 commit.
+        Attempting rebase in-memory...
+        [1/1] Committed as: b134a14 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout b134a149fe9a6d628cda5b5ccdf6224e78f01655
+        In-memory rebase succeeded.
+        Created 1 revision:
+          D2 create test3.txt: https://phabricator.example.com/D2 [needs review]
+        Updated 1 revision:
+          D1 create test2.txt: https://phabricator.example.com/D1 [accepted]
+        "###);
+        let log = std::fs::read_to_string(state_dir.join("log"))?;
+        insta::assert_snapshot!(log, @r###"
+        arc diff --no-amend --allow-untracked --verbatim --head cf9dedff5332b655a3f7690f485a1ff1d9cb14ca --update D1 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        arc diff --no-amend --allow-untracked --verbatim --head 1f809c52f84ec731f61013678b705ca18463e29f --create cf9dedff5332b655a3f7690f485a1ff1d9cb14ca
+        arc call-conduit -- differential.revision.search
+        "###);
+    }
+
+    Ok(())
+}
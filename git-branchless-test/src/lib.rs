@@ -13,7 +13,7 @@
 
 mod worker;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::fmt::Write as _;
 use std::fs::File;
@@ -36,18 +36,20 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::config::{
-    get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
+    self, get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
     print_hint_suppression_notice, Hint,
 };
 use lib::core::dag::{commit_set_to_vec, sorted_commit_set, CommitSet, Dag};
 use lib::core::effects::{icons, Effects, OperationIcon, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::{Glyphs, Pluralize, StyledStringBuilder};
+use lib::core::pager::start_pager;
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
     RebaseCommand, RebasePlan, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
+use lib::core::user_error::{UserError, UserErrorCode};
 use lib::git::{
     get_latest_test_command_path, get_test_locks_dir, get_test_tree_dir, get_test_worktrees_dir,
     make_test_command_slug, Commit, ConfigRead, GitRunInfo, GitRunResult, MaybeZeroOid, NonZeroOid,
@@ -91,12 +93,12 @@ enum Verbosity {
     FullOutput,
 }
 
-impl From<u8> for Verbosity {
-    fn from(value: u8) -> Self {
+impl From<config::Verbosity> for Verbosity {
+    fn from(value: config::Verbosity) -> Self {
         match value {
-            0 => Self::None,
-            1 => Self::PartialOutput,
-            _ => Self::FullOutput,
+            config::Verbosity::Quiet | config::Verbosity::Normal => Self::None,
+            config::Verbosity::Verbose => Self::PartialOutput,
+            config::Verbosity::VeryVerbose => Self::FullOutput,
         }
     }
 }
@@ -154,47 +156,47 @@ fn resolve_test_command_alias(
         return Ok(Ok(command));
     }
 
-    match alias {
-        Some(alias) => {
-            writeln!(
-                effects.get_output_stream(),
-                "\
-The test command alias {alias:?} was not defined.
-
-To create it, run: git config branchless.test.alias.{alias} <command>
-Or use the -x/--exec flag instead to run a test command without first creating an alias."
-            )?;
-        }
-        None => {
-            writeln!(
-                effects.get_output_stream(),
-                "\
+    let mut message = match alias {
+        Some(alias) => format!("The test command alias {alias:?} was not defined."),
+        None => "\
 Could not determine test command to run. No test command was provided with -c/--command or
--x/--exec, and the configuration value 'branchless.test.alias.default' was not set.
-
-To configure a default test command, run: git config branchless.test.alias.default <command>
-To run a specific test command, run: git test run -x <command>
-To run a specific command alias, run: git test run -c <alias>",
-            )?;
-        }
-    }
-
+-x/--exec, and the configuration value 'branchless.test.alias.default' was not set."
+            .to_string(),
+    };
     let aliases = config.list("branchless.test.alias.*")?;
     if !aliases.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "\nThese are the currently-configured command aliases:"
-        )?;
+        message.push_str("\n\nThese are the currently-configured command aliases:");
         for (name, command) in aliases {
-            writeln!(
-                effects.get_output_stream(),
-                "{} {name} = {command:?}",
-                effects.get_glyphs().bullet_point,
-            )?;
+            message.push_str(&format!(
+                "\n{} {name} = {command:?}",
+                effects.get_glyphs().bullet_point
+            ));
         }
     }
 
-    Ok(Err(ExitCode(1)))
+    let error = UserError::new(UserErrorCode::TestCommandNotFound, message);
+    let error = match alias {
+        Some(alias) => error
+            .with_command(
+                "To create it",
+                format!("git config branchless.test.alias.{alias} <command>"),
+            )
+            .with_command(
+                "To run a test command without creating an alias",
+                "git test run -x <command>",
+            ),
+        None => error
+            .with_command(
+                "To configure a default test command",
+                "git config branchless.test.alias.default <command>",
+            )
+            .with_command(
+                "To run a specific test command",
+                "git test run -x <command>",
+            )
+            .with_command("To run a specific command alias", "git test run -c <alias>"),
+    };
+    Ok(Err(error.report(effects)?))
 }
 
 #[derive(Debug)]
@@ -468,7 +470,6 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
             command: command_alias,
             revset,
             resolve_revset_options,
-            verbosity,
             strategy,
             search,
             bisect,
@@ -488,7 +489,7 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
                 no_cache,
                 interactive,
                 jobs,
-                verbosity: Verbosity::from(verbosity),
+                verbosity: Verbosity::from(effects.get_verbosity()),
                 apply_fixes: false,
             },
             revset,
@@ -501,9 +502,9 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
             command: command_alias,
             revset,
             resolve_revset_options,
-            verbosity,
         } => subcommand_show(
             &effects,
+            &git_run_info,
             &RawTestOptions {
                 exec: command,
                 command: command_alias,
@@ -514,7 +515,7 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
                 no_cache: false,
                 interactive: false,
                 jobs: None,
-                verbosity: Verbosity::from(verbosity),
+                verbosity: Verbosity::from(effects.get_verbosity()),
                 apply_fixes: false,
             },
             revset,
@@ -527,7 +528,6 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
             dry_run,
             revset,
             resolve_revset_options,
-            verbosity,
             strategy,
             no_cache,
             jobs,
@@ -545,7 +545,7 @@ pub fn command_main(ctx: CommandContext, args: TestArgs) -> eyre::Result<ExitCod
                 no_cache,
                 interactive: false,
                 jobs,
-                verbosity: Verbosity::from(verbosity),
+                verbosity: Verbosity::from(effects.get_verbosity()),
                 apply_fixes: true,
             },
             revset,
@@ -1305,6 +1305,17 @@ fn run_tests<'a>(
         let (effects, progress) =
             effects.start_operation(OperationType::RunTests(Arc::new(command.clone())));
         progress.notify_progress(0, commits.len());
+        progress.notify_status(
+            OperationIcon::InProgress,
+            format!(
+                "Running tests on {}: {command}",
+                Pluralize {
+                    determiner: None,
+                    amount: commits.len(),
+                    unit: ("commit", "commits"),
+                }
+            ),
+        );
         let commit_jobs = {
             let mut results = IndexMap::new();
             for commit in commits {
@@ -1476,6 +1487,16 @@ fn event_loop(
     let mut scheduled_jobs: HashMap<NonZeroOid, ScheduledJob> = Default::default();
     let mut testing_aborted_error = None;
 
+    // When testing all commits, results are processed in the order that the
+    // commits were provided, rather than the order in which the workers
+    // finished them, so that the same results are reported regardless of the
+    // number of jobs (such as when testing is aborted).
+    let mut unprocessed_commit_oids: VecDeque<NonZeroOid> = match search_strategy {
+        Some(_) => Default::default(),
+        None => commit_jobs.keys().copied().collect(),
+    };
+    let mut received_test_outputs: HashMap<NonZeroOid, TestOutput> = Default::default();
+
     if search_strategy.is_none() {
         let jobs_to_schedule = commit_jobs
             .keys()
@@ -1589,49 +1610,70 @@ fn event_loop(
             commit_oid,
             operation_type: _,
         } = job;
-        let (maybe_testing_aborted_error, search_status) = match &test_output.test_status {
-            TestStatus::CheckoutFailed
-            | TestStatus::SpawnTestFailed(_)
-            | TestStatus::TerminatedBySignal
-            | TestStatus::AlreadyInProgress
-            | TestStatus::ReadCacheFailed(_)
-            | TestStatus::Indeterminate { .. } => (None, search::Status::Indeterminate),
+        let test_outputs_to_process = match search_strategy {
+            Some(_) => vec![(commit_oid, test_output)],
+            None => {
+                received_test_outputs.insert(commit_oid, test_output);
+                let mut result = Vec::new();
+                while let Some(commit_oid) = unprocessed_commit_oids.front().copied() {
+                    match received_test_outputs.remove(&commit_oid) {
+                        Some(test_output) => {
+                            unprocessed_commit_oids.pop_front();
+                            result.push((commit_oid, test_output));
+                        }
+                        None => break,
+                    }
+                }
+                result
+            }
+        };
 
-            TestStatus::Abort { exit_code } => (
-                Some(TestingAbortedError {
-                    commit_oid,
-                    exit_code: *exit_code,
-                }),
-                search::Status::Indeterminate,
-            ),
+        for (commit_oid, test_output) in test_outputs_to_process {
+            let (maybe_testing_aborted_error, search_status) = match &test_output.test_status {
+                TestStatus::CheckoutFailed
+                | TestStatus::SpawnTestFailed(_)
+                | TestStatus::TerminatedBySignal
+                | TestStatus::AlreadyInProgress
+                | TestStatus::ReadCacheFailed(_)
+                | TestStatus::Indeterminate { .. } => (None, search::Status::Indeterminate),
+
+                TestStatus::Abort { exit_code } => (
+                    Some(TestingAbortedError {
+                        commit_oid,
+                        exit_code: *exit_code,
+                    }),
+                    search::Status::Indeterminate,
+                ),
 
-            TestStatus::Failed {
-                cached: _,
-                interactive: _,
-                exit_code: _,
-            } => (None, search::Status::Failure),
+                TestStatus::Failed {
+                    cached: _,
+                    interactive: _,
+                    exit_code: _,
+                } => (None, search::Status::Failure),
 
-            TestStatus::Passed {
-                cached: _,
-                interactive: _,
-                fixed_tree_oid: _,
-            } => (None, search::Status::Success),
-        };
-        if search_strategy.is_some() {
-            search.notify(commit_oid, search_status)?;
-        }
-        if scheduled_jobs
-            .insert(commit_oid, ScheduledJob::Complete(test_output))
-            .is_none()
-        {
-            warn!(
-                ?commit_oid,
-                "Received test result for commit that was not scheduled"
-            );
-        }
+                TestStatus::Passed {
+                    cached: _,
+                    interactive: _,
+                    fixed_tree_oid: _,
+                } => (None, search::Status::Success),
+            };
+            if search_strategy.is_some() {
+                search.notify(commit_oid, search_status)?;
+            }
+            if scheduled_jobs
+                .insert(commit_oid, ScheduledJob::Complete(test_output))
+                .is_none()
+            {
+                warn!(
+                    ?commit_oid,
+                    "Received test result for commit that was not scheduled"
+                );
+            }
 
-        if let Some(err) = maybe_testing_aborted_error {
-            testing_aborted_error = Some(err);
+            if let Some(err) = maybe_testing_aborted_error {
+                testing_aborted_error = Some(err);
+                break;
+            }
         }
     }
 
@@ -1805,11 +1847,11 @@ fn print_summary(
         }
     }
 
-    if num_cached_results > 0 && get_hint_enabled(repo, Hint::CleanCachedTestResults)? {
+    if num_cached_results > 0 && get_hint_enabled(effects, repo, Hint::CleanCachedTestResults)? {
         writeln!(
             effects.get_output_stream(),
             "{}: there {}",
-            effects.get_glyphs().render(get_hint_string())?,
+            effects.get_glyphs().render(get_hint_string(effects))?,
             Pluralize {
                 determiner: Some(("was", "were")),
                 amount: num_cached_results,
@@ -1819,7 +1861,7 @@ fn print_summary(
         writeln!(
             effects.get_output_stream(),
             "{}: to clear these cached results, run: git test clean {}",
-            effects.get_glyphs().render(get_hint_string())?,
+            effects.get_glyphs().render(get_hint_string(effects))?,
             shell_escape(revset.to_string()),
         )?;
         print_hint_suppression_notice(effects, Hint::CleanCachedTestResults)?;
@@ -1996,7 +2038,7 @@ fn apply_fixes(
 
         let thread_pool = ThreadPoolBuilder::new().build()?;
         let repo_pool = RepoResource::new_pool(repo)?;
-        builder.build(effects, &thread_pool, &repo_pool)?
+        builder.build(effects, git_run_info, &thread_pool, &repo_pool)?
     };
 
     let rebase_plan = match rebase_plan {
@@ -2742,12 +2784,14 @@ To abort testing entirely, run:      {exit127}",
 #[instrument]
 fn subcommand_show(
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     options: &RawTestOptions,
     revset: Revset,
     resolve_revset_options: &ResolveRevsetOptions,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
+    let _pager = start_pager(git_run_info, &repo, "test")?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "test show")?;
@@ -2815,13 +2859,13 @@ fn subcommand_show(
         }
     }
 
-    if get_hint_enabled(&repo, Hint::TestShowVerbose)? {
+    if get_hint_enabled(effects, &repo, Hint::TestShowVerbose)? {
         match options.verbosity {
             Verbosity::None => {
                 writeln!(
                     effects.get_output_stream(),
                     "{}: to see more detailed output, re-run with -v/--verbose",
-                    effects.get_glyphs().render(get_hint_string())?,
+                    effects.get_glyphs().render(get_hint_string(effects))?,
                 )?;
                 print_hint_suppression_notice(effects, Hint::TestShowVerbose)?;
             }
@@ -2829,7 +2873,7 @@ fn subcommand_show(
                 writeln!(
                     effects.get_output_stream(),
                     "{}: to see more detailed output, re-run with -vv/--verbose --verbose",
-                    effects.get_glyphs().render(get_hint_string())?,
+                    effects.get_glyphs().render(get_hint_string(effects))?,
                 )?;
                 print_hint_suppression_notice(effects, Hint::TestShowVerbose)?;
             }
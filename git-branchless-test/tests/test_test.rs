@@ -394,7 +394,7 @@ fn test_test_command_alias() -> eyre::Result<()> {
     git.init_repo()?;
 
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "test",
             &["run"],
             &GitRunOptions {
@@ -402,19 +402,19 @@ fn test_test_command_alias() -> eyre::Result<()> {
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
+        insta::assert_snapshot!(stderr, @r###"
         Could not determine test command to run. No test command was provided with -c/--command or
         -x/--exec, and the configuration value 'branchless.test.alias.default' was not set.
-
         To configure a default test command, run: git config branchless.test.alias.default <command>
         To run a specific test command, run: git test run -x <command>
         To run a specific command alias, run: git test run -c <alias>
         "###);
+        insta::assert_snapshot!(stdout, @"");
     }
 
     git.run(&["config", "branchless.test.alias.foo", "echo foo"])?;
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "test",
             &["run"],
             &GitRunOptions {
@@ -422,21 +422,21 @@ fn test_test_command_alias() -> eyre::Result<()> {
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
+        insta::assert_snapshot!(stderr, @r###"
         Could not determine test command to run. No test command was provided with -c/--command or
         -x/--exec, and the configuration value 'branchless.test.alias.default' was not set.
 
+        These are the currently-configured command aliases:
+        - branchless.test.alias.foo = "echo foo"
         To configure a default test command, run: git config branchless.test.alias.default <command>
         To run a specific test command, run: git test run -x <command>
         To run a specific command alias, run: git test run -c <alias>
-
-        These are the currently-configured command aliases:
-        - branchless.test.alias.foo = "echo foo"
         "###);
+        insta::assert_snapshot!(stdout, @"");
     }
 
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "test",
             &["run", "-c", "nonexistent"],
             &GitRunOptions {
@@ -444,15 +444,15 @@ fn test_test_command_alias() -> eyre::Result<()> {
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
+        insta::assert_snapshot!(stderr, @r###"
         The test command alias "nonexistent" was not defined.
 
-        To create it, run: git config branchless.test.alias.nonexistent <command>
-        Or use the -x/--exec flag instead to run a test command without first creating an alias.
-
         These are the currently-configured command aliases:
         - branchless.test.alias.foo = "echo foo"
+        To create it, run: git config branchless.test.alias.nonexistent <command>
+        To run a test command without creating an alias, run: git test run -x <command>
         "###);
+        insta::assert_snapshot!(stdout, @"");
     }
 
     git.run(&["config", "branchless.test.alias.default", "echo default"])?;
@@ -485,7 +485,7 @@ fn test_test_command_alias() -> eyre::Result<()> {
     }
 
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "test",
             &["run", "-c", "foo bar baz"],
             &GitRunOptions {
@@ -493,16 +493,16 @@ fn test_test_command_alias() -> eyre::Result<()> {
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
+        insta::assert_snapshot!(stderr, @r###"
         The test command alias "foo bar baz" was not defined.
 
-        To create it, run: git config branchless.test.alias.foo bar baz <command>
-        Or use the -x/--exec flag instead to run a test command without first creating an alias.
-
         These are the currently-configured command aliases:
         - branchless.test.alias.foo = "echo foo"
         - branchless.test.alias.default = "echo default"
+        To create it, run: git config branchless.test.alias.foo bar baz <command>
+        To run a test command without creating an alias, run: git test run -x <command>
         "###);
+        insta::assert_snapshot!(stdout, @"");
     }
 
     Ok(())
@@ -1562,6 +1562,48 @@ fn test_test_interactive() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_test_abort_parallel() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    // The later commits finish testing first, but the results should be
+    // reported as if the commits had been tested in order.
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "test",
+            &[
+                "run",
+                "--strategy",
+                "worktree",
+                "--jobs",
+                "3",
+                "-x",
+                "if [ -f test3.txt ]; then exit 0; elif [ -f test2.txt ]; then exit 127; else sleep 1; fi",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Using test execution strategy: worktree
+        ✓ Passed: 62fc20d create test1.txt
+        X Exit code indicated to abort testing (exit code 127): 96d1c37 create test2.txt
+        Tested 2 commits with if [ -f test3.txt ]; then exit 0; elif [ -f test2.txt ]; then exit 127; else sleep 1; fi:
+        1 passed, 1 failed, 0 skipped
+        Aborted testing with exit code 127 at commit: 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_test_search_abort() -> eyre::Result<()> {
     let git = make_git()?;
@@ -20,31 +20,33 @@ use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::SystemTime;
 
 use cursive_core::event::Key;
-use cursive_core::traits::Resizable;
 use cursive_core::utils::markup::StyledString;
-use cursive_core::views::{
-    Dialog, EditView, LinearLayout, OnEventView, Panel, ScrollView, TextView,
-};
+use cursive_core::views::{Dialog, EditView, OnEventView, Panel, ScrollView, TextView};
 use cursive_core::{Cursive, CursiveRunner};
 use eyre::Context;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
 use lib::core::repo_ext::RepoExt;
+use lib::core::user_hooks::{run_user_hook, UserHook};
 use lib::util::ExitCode;
 use tracing::instrument;
 
-use crate::tui::{with_siv, SingletonView};
+use crate::tui::{with_siv, SingletonView, SplitView};
 use git_branchless_revset::resolve_default_smartlog_commits;
 use git_branchless_smartlog::{make_smartlog_graph, render_graph};
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::eventlog::{
+    CopyKind, Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId,
+};
 use lib::core::formatting::{Glyphs, Pluralize, StyledStringBuilder};
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor, CopiedCommitDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
     RelativeTimeDescriptor,
 };
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo, ResolvedReferenceInfo,
+};
 
 fn render_cursor_smartlog(
     effects: &Effects,
@@ -87,6 +89,7 @@ fn render_cursor_smartlog(
             &mut CommitOidDescriptor::new(true)?,
             &mut RelativeTimeDescriptor::new(repo, SystemTime::now())?,
             &mut ObsolescenceExplanationDescriptor::new(event_replayer, event_cursor)?,
+            &mut CopiedCommitDescriptor::new(event_replayer, event_cursor)?,
             &mut BranchesDescriptor::new(
                 repo,
                 &head_info,
@@ -123,8 +126,24 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
             timestamp: _,
             event_tx_id: _,
             commit_oid,
+            message,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Hide commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+                match message {
+                    Some(message) => StyledStringBuilder::new()
+                        .append_plain("     reason: ")
+                        .append_plain(message)
+                        .build(),
+                    None => StyledString::new(),
+                },
+            ]
         }
-        | Event::RewriteEvent {
+
+        Event::RewriteEvent {
             timestamp: _,
             event_tx_id: _,
             old_commit_oid: MaybeZeroOid::NonZero(commit_oid),
@@ -297,6 +316,44 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
             ]
         }
 
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            original_commit_oid,
+            new_commit_oid,
+            kind: CopyKind::CherryPick,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Cherry-pick commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *original_commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("                as ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *new_commit_oid)?)
+                    .build(),
+            ]
+        }
+
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            original_commit_oid,
+            new_commit_oid,
+            kind: CopyKind::Revert,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Revert commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *original_commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("           by ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *new_commit_oid)?)
+                    .build(),
+            ]
+        }
+
         Event::RewriteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -417,6 +474,96 @@ fn describe_events_numbered(
     Ok(lines)
 }
 
+/// Get the OIDs of the commits before and after the event, if the event changed
+/// the contents of the repository in a way that can be shown as a diff, i.e.
+/// it rewrote a commit or moved a reference from one commit to another.
+fn get_event_diff_oids(event: &Event) -> Option<(NonZeroOid, NonZeroOid)> {
+    match event {
+        Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid: MaybeZeroOid::NonZero(old_oid),
+            new_commit_oid: MaybeZeroOid::NonZero(new_oid),
+        }
+        | Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name: _,
+            old_oid: MaybeZeroOid::NonZero(old_oid),
+            new_oid: MaybeZeroOid::NonZero(new_oid),
+            message: _,
+        } => Some((*old_oid, *new_oid)),
+
+        Event::RewriteEvent { .. }
+        | Event::RefUpdateEvent { .. }
+        | Event::CommitEvent { .. }
+        | Event::ObsoleteEvent { .. }
+        | Event::UnobsoleteEvent { .. }
+        | Event::CopyEvent { .. }
+        | Event::WorkingCopySnapshot { .. } => None,
+    }
+}
+
+/// Render the diffs between the old and new commits of each rewrite and
+/// reference move in the provided events, so that the user can see what the
+/// transaction changed before undoing it.
+fn describe_events_diffs(
+    effects: &Effects,
+    repo: &Repo,
+    events: &[Event],
+) -> eyre::Result<Vec<StyledString>> {
+    let glyphs = effects.get_glyphs();
+    let mut lines = Vec::new();
+    for (i, event) in (1..).zip(events) {
+        let (old_oid, new_oid) = match get_event_diff_oids(event) {
+            Some(oids) => oids,
+            None => continue,
+        };
+
+        let num_header = format!("{i}. ");
+        for (j, event_line) in (0..).zip(describe_event(glyphs, repo, event)?) {
+            let prefix = if j == 0 {
+                num_header.clone()
+            } else {
+                " ".repeat(num_header.len())
+            };
+            lines.push(
+                StyledStringBuilder::new()
+                    .append_plain(prefix)
+                    .append(event_line)
+                    .build(),
+            );
+        }
+
+        match (repo.find_commit(old_oid)?, repo.find_commit(new_oid)?) {
+            (Some(old_commit), Some(new_commit)) => {
+                let old_tree = old_commit.get_tree()?;
+                let new_tree = new_commit.get_tree()?;
+                let diff = repo.get_diff_between_trees(effects, Some(&old_tree), &new_tree, 3)?;
+                let diff_lines = diff.render_patch()?;
+                if diff_lines.is_empty() {
+                    lines.push(StyledString::plain("(no changes to file contents)"));
+                } else {
+                    lines.extend(diff_lines);
+                }
+            }
+            (None, _) | (_, None) => {
+                lines.push(StyledString::plain(
+                    "(diff not available: commit was garbage collected)",
+                ));
+            }
+        }
+        lines.push(StyledString::new());
+    }
+
+    if lines.is_empty() {
+        lines.push(StyledString::plain(
+            "There are no rewritten commits or moved references in this transaction.",
+        ));
+    }
+    Ok(lines)
+}
+
 #[instrument(skip(siv))]
 fn select_past_event(
     mut siv: CursiveRunner<Cursive>,
@@ -431,7 +578,9 @@ fn select_past_event(
         Next,
         Previous,
         GoToEvent,
+        ShowDiff,
         SetEventReplayerCursor { event_id: isize },
+        ResizeEventsPane { delta: isize },
         Help,
         Quit,
         SelectEventIdAndQuit,
@@ -450,6 +599,12 @@ fn select_past_event(
         ('?'.into(), Message::Help),
         ('g'.into(), Message::GoToEvent),
         ('G'.into(), Message::GoToEvent),
+        ('d'.into(), Message::ShowDiff),
+        ('D'.into(), Message::ShowDiff),
+        ('+'.into(), Message::ResizeEventsPane { delta: 10 }),
+        ('='.into(), Message::ResizeEventsPane { delta: 10 }),
+        ('-'.into(), Message::ResizeEventsPane { delta: -10 }),
+        ('_'.into(), Message::ResizeEventsPane { delta: -10 }),
         ('q'.into(), Message::Quit),
         ('Q'.into(), Message::Quit),
         (
@@ -482,6 +637,7 @@ fn select_past_event(
         declare_views! {
             SmartlogView => ScrollView<TextView>,
             InfoView => TextView,
+            PanesView => SplitView<Panel<SmartlogView>, Panel<ScrollView<InfoView>>>,
         }
 
         let redraw = |siv: &mut Cursive,
@@ -544,19 +700,19 @@ fn select_past_event(
             Ok(Message::Init) => {
                 let smartlog_view: SmartlogView = ScrollView::new(TextView::new("")).into();
                 let info_view: InfoView = TextView::new("").into();
-                siv.add_fullscreen_layer(
-                    LinearLayout::vertical()
-                        .child(
-                            Panel::new(smartlog_view)
-                                .title("Commit graph")
-                                .full_height(),
-                        )
-                        .child(Panel::new(ScrollView::new(info_view)).title("Events"))
-                        .full_width(),
-                );
+                let panes_view: PanesView = SplitView::new(
+                    Panel::new(smartlog_view).title("Commit graph"),
+                    Panel::new(ScrollView::new(info_view)).title("Events"),
+                )
+                .into();
+                siv.add_fullscreen_layer(panes_view);
                 redraw(&mut siv, event_replayer, cursor)?;
             }
 
+            Ok(Message::ResizeEventsPane { delta }) => {
+                PanesView::find(&mut siv).resize_bottom(delta);
+            }
+
             Ok(Message::Next) => {
                 cursor = event_replayer.advance_cursor_by_transaction(cursor, 1);
                 redraw(&mut siv, event_replayer, cursor)?;
@@ -601,6 +757,28 @@ fn select_past_event(
                 );
             }
 
+            Ok(Message::ShowDiff) => {
+                let diff_lines = match event_replayer.get_tx_events_before_cursor(cursor) {
+                    None => vec![StyledString::plain(
+                        "There are no previous available events.",
+                    )],
+                    Some((_event_id, events)) => describe_events_diffs(effects, repo, events)?,
+                };
+                siv.add_layer(
+                    OnEventView::new(
+                        Dialog::new()
+                            .title("Changes in this transaction")
+                            .content(ScrollView::new(TextView::new(
+                                StyledStringBuilder::from_lines(diff_lines),
+                            )))
+                            .dismiss_button("Close"),
+                    )
+                    .on_event(Key::Esc, |siv| {
+                        siv.pop_layer();
+                    }),
+                );
+            }
+
             Ok(Message::Help) => {
                 siv.add_layer(
                         Dialog::new()
@@ -612,6 +790,8 @@ h/?: Show this help.
 q: Quit.
 p/n or <left>/<right>: View next/previous state.
 g: Go to a provided event ID.
+d: Show the diffs of the commits rewritten and references moved by the current transaction.
++/-: Grow/shrink the events pane. (You can also drag its border with the mouse.)
 <enter>: Revert the repository to the given state (requires confirmation).
 
 You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it.
@@ -657,12 +837,14 @@ fn inverse_event(
             timestamp,
             event_tx_id,
             commit_oid,
+            message: None,
         },
 
         Event::ObsoleteEvent {
             timestamp: _,
             event_tx_id: _,
             commit_oid,
+            message: _,
         } => Event::UnobsoleteEvent {
             timestamp,
             event_tx_id,
@@ -705,6 +887,10 @@ fn inverse_event(
         // this event occurred, we want to check out the working copy as it
         // existed at that point in time.
         event @ Event::WorkingCopySnapshot { .. } => event,
+
+        // The copied commit is hidden by the inverse of its `CommitEvent`, so
+        // there's nothing else to undo.
+        event @ Event::CopyEvent { .. } => event,
     };
     Ok(inverse_event)
 }
@@ -784,10 +970,37 @@ fn undo_events(
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
     let head_info = repo.get_head_info()?;
+    let head_reference_name = repo.get_head_event_reference_name();
     let inverse_events: Vec<Event> = event_replayer
         .get_events_since_cursor(event_cursor)
         .iter()
         .rev()
+        .filter_map(|event| match event {
+            // Checkouts in this worktree are undone by checking out `HEAD`
+            // again, and checkouts in other worktrees aren't undone at all.
+            Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name,
+                old_oid,
+                new_oid,
+                message,
+            } if ref_name == &head_reference_name => Some(Event::RefUpdateEvent {
+                timestamp: *timestamp,
+                event_tx_id: *event_tx_id,
+                ref_name: "HEAD".into(),
+                old_oid: *old_oid,
+                new_oid: *new_oid,
+                message: message.clone(),
+            }),
+            Event::RefUpdateEvent { ref_name, .. }
+                if ref_name.as_str().starts_with("worktrees/")
+                    && ref_name.as_str().ends_with("/HEAD") =>
+            {
+                None
+            }
+            event => Some(event.clone()),
+        })
         .filter(|event| {
             !matches!(
                 event,
@@ -801,7 +1014,7 @@ fn undo_events(
                 } if ref_name.as_str() == "HEAD"
             )
         })
-        .map(|event| inverse_event(event.clone(), now, event_tx_id))
+        .map(|event| inverse_event(event, now, event_tx_id))
         .collect::<eyre::Result<Vec<Event>>>()?;
 
     if inverse_events.is_empty() {
@@ -836,7 +1049,11 @@ fn undo_events(
         }
     };
     if !confirmed {
-        writeln!(effects.get_output_stream(), "Aborted.")?;
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            effects.get_messages().format("confirm-aborted", &[])
+        )?;
         return Ok(ExitCode(1));
     }
 
@@ -906,6 +1123,10 @@ fn undo_events(
                 // Should be handled as the checkout target already.
             }
 
+            Event::CopyEvent { .. } => {
+                // Nothing to restore; see `inverse_event`.
+            }
+
             Event::CommitEvent { .. }
             | Event::ObsoleteEvent { .. }
             | Event::UnobsoleteEvent { .. }
@@ -932,6 +1153,16 @@ fn undo_events(
     }
 
     writeln!(effects.get_output_stream(), "Applied {num_inverse_events}.")?;
+
+    run_user_hook(
+        effects,
+        git_run_info,
+        repo,
+        UserHook::PostUndo,
+        event_tx_id,
+        &inverse_events,
+    )?;
+
     Ok(ExitCode(0))
 }
 
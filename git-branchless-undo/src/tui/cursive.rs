@@ -17,6 +17,7 @@ pub fn with_siv<T, F: FnOnce(Effects, CursiveRunner<Cursive>) -> eyre::Result<T>
     let backend = BufferedBackend::new(backend);
 
     let effects = effects.enable_tui_mode();
+    let tui_palette = effects.get_glyphs().theme.tui_palette.clone();
     let mut siv = Cursive::new().into_runner(Box::new(backend));
     siv.update_theme(|theme| {
         theme.shadow = false;
@@ -27,6 +28,7 @@ pub fn with_siv<T, F: FnOnce(Effects, CursiveRunner<Cursive>) -> eyre::Result<T>
             (PaletteColor::TitlePrimary, Color::TerminalDefault),
             (PaletteColor::TitleSecondary, Color::TerminalDefault),
         ]);
+        theme.palette.extend(tui_palette);
     });
     f(effects, siv)
 }
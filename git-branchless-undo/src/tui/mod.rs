@@ -1,6 +1,8 @@
 //! Utilities to control output and render to the terminal.
 
 mod cursive;
+mod split;
 
 pub use self::cursive::{with_siv, SingletonView};
+pub use self::split::SplitView;
 pub use git_record::testing;
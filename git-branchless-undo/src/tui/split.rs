@@ -0,0 +1,242 @@
+//! A resizable layout of two panes stacked vertically.
+
+use cursive_core::direction::Direction;
+use cursive_core::event::{AnyCb, Event, EventResult, Key, MouseButton, MouseEvent};
+use cursive_core::view::{CannotFocus, Selector, View, ViewNotFound};
+use cursive_core::{Printer, Rect, Vec2};
+
+/// The smallest and largest share of the screen height, in percent, which the
+/// bottom pane may be resized to.
+const MIN_BOTTOM_PERCENT: usize = 10;
+const MAX_BOTTOM_PERCENT: usize = 90;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pane {
+    Top,
+    Bottom,
+}
+
+/// Two views stacked vertically. The bottom pane takes up as much height as it
+/// requires, up to a configurable share of the available height, and the top
+/// pane takes up the rest.
+///
+/// Unlike `LinearLayout`, mouse events are sent to the pane under the pointer
+/// rather than to the focused pane, so that either pane can be scrolled with
+/// the mouse wheel. The split can be resized by dragging the first row of the
+/// bottom pane (such as the top border of a `Panel`) with the mouse, or with
+/// [`SplitView::resize_bottom`].
+pub struct SplitView<T, B> {
+    top: T,
+    bottom: B,
+    bottom_percent: usize,
+    focus: Pane,
+    is_dragging: bool,
+    size: Vec2,
+    top_height: usize,
+}
+
+impl<T: View, B: View> SplitView<T, B> {
+    /// Constructor. By default, the bottom pane may take up to half of the
+    /// available height.
+    pub fn new(top: T, bottom: B) -> Self {
+        Self {
+            top,
+            bottom,
+            bottom_percent: 50,
+            focus: Pane::Top,
+            is_dragging: false,
+            size: Vec2::zero(),
+            top_height: 0,
+        }
+    }
+
+    /// The largest share of the available height, in percent, which the bottom
+    /// pane may take up.
+    pub fn get_bottom_percent(&self) -> usize {
+        self.bottom_percent
+    }
+
+    /// Change the largest share of the available height which the bottom pane
+    /// may take up by `delta` percentage points.
+    pub fn resize_bottom(&mut self, delta: isize) {
+        let bottom_percent = if delta < 0 {
+            self.bottom_percent.saturating_sub(delta.unsigned_abs())
+        } else {
+            self.bottom_percent.saturating_add(delta.unsigned_abs())
+        };
+        self.bottom_percent = bottom_percent.clamp(MIN_BOTTOM_PERCENT, MAX_BOTTOM_PERCENT);
+    }
+
+    fn compute_top_height(&mut self, size: Vec2) -> usize {
+        let max_bottom_height = size.y * self.bottom_percent / 100;
+        let bottom_height = self
+            .bottom
+            .required_size(Vec2::new(size.x, max_bottom_height))
+            .y
+            .min(max_bottom_height);
+        size.y - bottom_height
+    }
+
+    fn focus_pane(&mut self, pane: Pane) -> EventResult {
+        if self.focus == pane {
+            return EventResult::Consumed(None);
+        }
+        let result = match pane {
+            Pane::Top => self.top.take_focus(Direction::none()),
+            Pane::Bottom => self.bottom.take_focus(Direction::none()),
+        };
+        match result {
+            Ok(result) => {
+                let old_focus_result = match self.focus {
+                    Pane::Top => self.top.on_event(Event::FocusLost),
+                    Pane::Bottom => self.bottom.on_event(Event::FocusLost),
+                };
+                self.focus = pane;
+                old_focus_result.and(result)
+            }
+            Err(CannotFocus) => EventResult::Ignored,
+        }
+    }
+
+    fn on_mouse_event(
+        &mut self,
+        event: Event,
+        position: Vec2,
+        mouse_event: MouseEvent,
+    ) -> EventResult {
+        match mouse_event {
+            MouseEvent::Press(MouseButton::Left) if position.y == self.top_height => {
+                self.is_dragging = true;
+                return EventResult::Consumed(None);
+            }
+            MouseEvent::Hold(MouseButton::Left) if self.is_dragging => {
+                if self.size.y > 0 {
+                    let bottom_height = self.size.y.saturating_sub(position.y);
+                    self.bottom_percent = (bottom_height * 100 / self.size.y)
+                        .clamp(MIN_BOTTOM_PERCENT, MAX_BOTTOM_PERCENT);
+                }
+                return EventResult::Consumed(None);
+            }
+            MouseEvent::Release(MouseButton::Left) if self.is_dragging => {
+                self.is_dragging = false;
+                return EventResult::Consumed(None);
+            }
+            _ => {}
+        }
+
+        let pane = if position.y < self.top_height {
+            Pane::Top
+        } else {
+            Pane::Bottom
+        };
+        let focus_result = if mouse_event.grabs_focus() {
+            self.focus_pane(pane)
+        } else {
+            EventResult::Ignored
+        };
+        let result = match pane {
+            Pane::Top => self.top.on_event(event),
+            Pane::Bottom => self
+                .bottom
+                .on_event(event.relativized((0, self.top_height))),
+        };
+        focus_result.and(result)
+    }
+}
+
+impl<T: View, B: View> View for SplitView<T, B> {
+    fn draw(&self, printer: &Printer) {
+        self.top.draw(
+            &printer
+                .cropped((self.size.x, self.top_height))
+                .focused(self.focus == Pane::Top),
+        );
+        self.bottom.draw(
+            &printer
+                .offset((0, self.top_height))
+                .focused(self.focus == Pane::Bottom),
+        );
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.size = size;
+        self.top_height = self.compute_top_height(size);
+        self.top.layout(Vec2::new(size.x, self.top_height));
+        self.bottom
+            .layout(Vec2::new(size.x, size.y - self.top_height));
+    }
+
+    fn needs_relayout(&self) -> bool {
+        true
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if let Event::Mouse {
+            offset,
+            position,
+            event: mouse_event,
+        } = event
+        {
+            return match position.checked_sub(offset) {
+                Some(position) => self.on_mouse_event(event, position, mouse_event),
+                None => EventResult::Ignored,
+            };
+        }
+
+        let result = match self.focus {
+            Pane::Top => self.top.on_event(event.clone()),
+            Pane::Bottom => self.bottom.on_event(event.clone()),
+        };
+        match (result, event) {
+            (EventResult::Ignored, Event::Key(Key::Tab)) if self.focus == Pane::Top => {
+                self.focus_pane(Pane::Bottom)
+            }
+            (EventResult::Ignored, Event::Shift(Key::Tab)) if self.focus == Pane::Bottom => {
+                self.focus_pane(Pane::Top)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn take_focus(&mut self, source: Direction) -> Result<EventResult, CannotFocus> {
+        if let Ok(result) = self.top.take_focus(source) {
+            self.focus = Pane::Top;
+            Ok(result)
+        } else {
+            let result = self.bottom.take_focus(source)?;
+            self.focus = Pane::Bottom;
+            Ok(result)
+        }
+    }
+
+    fn call_on_any(&mut self, selector: &Selector, callback: AnyCb) {
+        self.top.call_on_any(selector, callback);
+        self.bottom.call_on_any(selector, callback);
+    }
+
+    fn focus_view(&mut self, selector: &Selector) -> Result<EventResult, ViewNotFound> {
+        if let Ok(result) = self.top.focus_view(selector) {
+            self.focus = Pane::Top;
+            Ok(result)
+        } else {
+            let result = self.bottom.focus_view(selector)?;
+            self.focus = Pane::Bottom;
+            Ok(result)
+        }
+    }
+
+    fn important_area(&self, size: Vec2) -> Rect {
+        match self.focus {
+            Pane::Top => self.top.important_area(Vec2::new(size.x, self.top_height)),
+            Pane::Bottom => {
+                self.bottom
+                    .important_area(Vec2::new(size.x, size.y.saturating_sub(self.top_height)))
+                    + Vec2::new(0, self.top_height)
+            }
+        }
+    }
+}
@@ -14,7 +14,7 @@ use eyre::Context;
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions};
 use itertools::Itertools;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
-use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::config::{get_restack_preserve_timestamps, get_smartlog_show_after_command};
 use lib::core::dag::commit_set_to_vec;
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
@@ -142,7 +142,7 @@ pub fn amend(
             effects.get_output_stream(),
             "There are no uncommitted or staged changes. Nothing to amend."
         )?;
-        return Ok(ExitCode(0));
+        return Ok(ExitCode::NOTHING_TO_DO);
     }
 
     let amended_tree = repo.amend_fast(&head_commit, &opts)?;
@@ -276,7 +276,7 @@ pub fn amend(
 
         let thread_pool = ThreadPoolBuilder::new().build()?;
         let repo_pool = RepoResource::new_pool(&repo)?;
-        match builder.build(effects, &thread_pool, &repo_pool)? {
+        match builder.build(effects, git_run_info, &thread_pool, &repo_pool)? {
             Ok(rebase_plan) => rebase_plan,
             Err(err) => {
                 err.describe(effects, &repo)?;
@@ -373,5 +373,11 @@ pub fn amend(
         }
     }
 
+    if get_smartlog_show_after_command(&repo)? {
+        let exit_code =
+            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?;
+        return Ok(exit_code);
+    }
+
     Ok(ExitCode(0))
 }
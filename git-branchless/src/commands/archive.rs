@@ -0,0 +1,381 @@
+//! Park whole stacks of commits under a name, and restore them later.
+//!
+//! Archiving is like hiding the stacks with `git hide -D -r`, except that the
+//! commits and deleted branches are recorded under the given name, so that
+//! they can be recovered with `git branchless unarchive <name>` without having
+//! to dig through `git undo`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use bstr::BString;
+use eden_dag::DagAlgorithm;
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use lib::core::archive::{Archive, ArchiveDb};
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::move_branches;
+use lib::core::user_error::{UserError, UserErrorCode};
+use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use git_branchless_revset::resolve_commits;
+
+/// Archive the stacks containing the provided commits under the given name.
+#[instrument]
+pub fn archive(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    name: &str,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let glyphs = Glyphs::detect();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let archive_db = ArchiveDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    if archive_db.get_archive(name)?.is_some() {
+        return UserError::new(
+            UserErrorCode::ArchiveAlreadyExists,
+            format!("An archive named {name} already exists."),
+        )
+        .with_command("To restore it", format!("git branchless unarchive {name}"))
+        .report(effects);
+    }
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    // Public commits are never archived, since they would just remain visible
+    // as ancestors of the main branch anyways.
+    let commits = dag
+        .filter_visible_commits(dag.query().descendants(union_all(&commit_sets))?)?
+        .intersection(dag.query_draft_commits()?);
+    let commits = dag.query().sort(&commits)?;
+    if commits.is_empty()? {
+        writeln!(effects.get_output_stream(), "No commits to archive.")?;
+        return Ok(ExitCode(1));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "archive")?;
+
+    // Move off of the archived commits before hiding them, so that they don't
+    // remain visible as the current `HEAD`.
+    if let Some(head_oid) = references_snapshot.head_oid {
+        if commits.contains(&head_oid.into())? {
+            let parents = dag
+                .query()
+                .parents(dag.query().roots(commits.clone())?)?
+                .difference(&commits);
+            if let Some(target_oid) = commit_set_to_vec(&parents)?.into_iter().next() {
+                let exit_code = check_out_commit(
+                    effects,
+                    git_run_info,
+                    &repo,
+                    &event_log_db,
+                    event_tx_id,
+                    Some(CheckoutTarget::Oid(target_oid)),
+                    &CheckOutCommitOptions {
+                        render_smartlog: false,
+                        ..Default::default()
+                    },
+                )?;
+                if !exit_code.is_success() {
+                    return Ok(exit_code);
+                }
+            }
+        }
+    }
+
+    let commits = sorted_commit_set(&repo, &dag, &commits)?;
+    let mut branches: Vec<_> = commits
+        .iter()
+        .filter_map(|commit| {
+            references_snapshot
+                .branch_oid_to_names
+                .get(&commit.get_oid())
+                .map(|names| (commit.get_oid(), names))
+        })
+        .flat_map(|(commit_oid, names)| {
+            names
+                .iter()
+                .filter(|name| {
+                    matches!(
+                        CategorizedReferenceName::new(name),
+                        CategorizedReferenceName::LocalBranch { .. }
+                    )
+                })
+                .map(move |name| (name.clone(), commit_oid))
+        })
+        .collect();
+    branches.sort_unstable();
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    archive_db.add_archive(&Archive {
+        name: name.to_owned(),
+        timestamp,
+        commit_oids: commits.iter().map(|commit| commit.get_oid()).collect(),
+        branches: branches.clone(),
+    })?;
+
+    let message = format!("archived as {name}");
+    let events = commits
+        .iter()
+        .map(|commit| Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: commit.get_oid(),
+            message: Some(message.clone()),
+        })
+        .collect();
+    event_log_db.add_events(events)?;
+
+    let abandoned_branches: HashMap<NonZeroOid, MaybeZeroOid> = branches
+        .iter()
+        .map(|(_, commit_oid)| (*commit_oid, MaybeZeroOid::Zero))
+        .collect();
+    move_branches(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        &abandoned_branches,
+    )?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Archived {} as {name}:",
+        Pluralize {
+            determiner: None,
+            amount: commits.len(),
+            unit: ("commit", "commits"),
+        }
+    )?;
+    for commit in commits.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "- {}",
+            glyphs.render(commit.friendly_describe(&glyphs)?)?,
+        )?;
+    }
+    if !branches.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Deleted {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: branches.len(),
+                unit: ("branch", "branches"),
+            },
+            render_branch_names(&branches),
+        )?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "To restore {}, run: git branchless unarchive {name}",
+        match commits.len() {
+            1 => "it",
+            _ => "them",
+        }
+    )?;
+
+    Ok(ExitCode(0))
+}
+
+/// List the existing archives.
+#[instrument]
+pub fn list_archives(effects: &Effects) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let archive_db = ArchiveDb::new(&conn)?;
+
+    let archives = archive_db.get_archives()?;
+    if archives.is_empty() {
+        writeln!(effects.get_output_stream(), "There are no archives.")?;
+        return Ok(ExitCode(0));
+    }
+
+    for Archive {
+        name,
+        timestamp: _,
+        commit_oids,
+        branches,
+    } in archives
+    {
+        write!(
+            effects.get_output_stream(),
+            "{name}: {}",
+            Pluralize {
+                determiner: None,
+                amount: commit_oids.len(),
+                unit: ("commit", "commits"),
+            }
+        )?;
+        if !branches.is_empty() {
+            write!(
+                effects.get_output_stream(),
+                " ({})",
+                render_branch_names(&branches)
+            )?;
+        }
+        writeln!(effects.get_output_stream())?;
+    }
+
+    Ok(ExitCode(0))
+}
+
+/// Restore the commits and branches from the archive with the given name.
+#[instrument]
+pub fn unarchive(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    name: &str,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let glyphs = Glyphs::detect();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let archive_db = ArchiveDb::new(&conn)?;
+
+    let Archive {
+        name,
+        timestamp: _,
+        commit_oids,
+        branches,
+    } = match archive_db.get_archive(name)? {
+        Some(archive) => archive,
+        None => {
+            return UserError::new(
+                UserErrorCode::ArchiveNotFound,
+                format!("There is no archive named {name}."),
+            )
+            .with_command(
+                "To see the existing archives",
+                "git branchless archive --list",
+            )
+            .report(effects);
+        }
+    };
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = event_log_db.make_transaction_id(now, "unarchive")?;
+    let events = commit_oids
+        .iter()
+        .map(|commit_oid| Event::UnobsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *commit_oid,
+        })
+        .collect();
+    event_log_db.add_events(events)?;
+
+    let mut restored_branches = Vec::new();
+    let mut skipped_branches = Vec::new();
+    for (reference_name, commit_oid) in branches {
+        if repo.find_reference(&reference_name)?.is_some() {
+            skipped_branches.push((reference_name, commit_oid));
+        } else {
+            repo.create_reference(&reference_name, commit_oid, false, "unarchive")?;
+            restored_branches.push((reference_name, commit_oid));
+        }
+    }
+    if !restored_branches.is_empty() {
+        let branch_moves_stdin: String = restored_branches
+            .iter()
+            .map(|(reference_name, commit_oid)| {
+                format!(
+                    "{} {commit_oid} {}\n",
+                    MaybeZeroOid::Zero,
+                    reference_name.as_str()
+                )
+            })
+            .collect();
+        git_run_info.run_hook(
+            effects,
+            &repo,
+            "reference-transaction",
+            event_tx_id,
+            &["committed"],
+            Some(BString::from(branch_moves_stdin)),
+        )?;
+    }
+
+    archive_db.remove_archive(&name)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Unarchived {} from {name}:",
+        Pluralize {
+            determiner: None,
+            amount: commit_oids.len(),
+            unit: ("commit", "commits"),
+        }
+    )?;
+    for commit_oid in commit_oids {
+        let description = match repo.find_commit(commit_oid)? {
+            Some(commit) => glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            None => format!("{commit_oid} (no longer exists)"),
+        };
+        writeln!(effects.get_output_stream(), "- {description}")?;
+    }
+    if !restored_branches.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Restored {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: restored_branches.len(),
+                unit: ("branch", "branches"),
+            },
+            render_branch_names(&restored_branches),
+        )?;
+    }
+    if !skipped_branches.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Not restoring {} which already exist: {}",
+            Pluralize {
+                determiner: None,
+                amount: skipped_branches.len(),
+                unit: ("branch", "branches"),
+            },
+            render_branch_names(&skipped_branches),
+        )?;
+    }
+
+    Ok(ExitCode(0))
+}
+
+fn render_branch_names(branches: &[(lib::git::ReferenceName, NonZeroOid)]) -> String {
+    branches
+        .iter()
+        .map(|(reference_name, _)| CategorizedReferenceName::new(reference_name).render_suffix())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -19,7 +19,7 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor, CopiedCommitDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
     RelativeTimeDescriptor,
 };
@@ -41,17 +41,31 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
             event_tx_id: _,
             commit_oid: _,
         }
-        | Event::ObsoleteEvent {
+        | Event::UnobsoleteEvent {
             timestamp: _,
             event_tx_id: _,
             commit_oid: _,
         }
-        | Event::UnobsoleteEvent {
+        | Event::CopyEvent {
             timestamp: _,
             event_tx_id: _,
-            commit_oid: _,
+            original_commit_oid: _,
+            new_commit_oid: _,
+            kind: _,
         }) => event,
 
+        Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+            message,
+        } => Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+            message: message.map(|message| redactor.redact_commit_summary(message)),
+        },
+
         Event::RefUpdateEvent {
             timestamp,
             event_tx_id,
@@ -144,6 +158,7 @@ fn describe_event_cursor(
             &mut CommitOidDescriptor::new(true)?,
             &mut RelativeTimeDescriptor::new(repo, now)?,
             &mut ObsolescenceExplanationDescriptor::new(event_replayer, event_cursor)?,
+            &mut CopiedCommitDescriptor::new(event_replayer, event_cursor)?,
             &mut BranchesDescriptor::new(repo, head_info, references_snapshot, redactor)?,
             &mut DifferentialRevisionDescriptor::new(repo, redactor)?,
             &mut CommitMessageDescriptor::new(redactor)?,
@@ -163,9 +178,9 @@ fn describe_event_cursor(
     .concat())
 }
 
-fn collect_events(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ReportEntry> {
+fn collect_events(effects: &Effects) -> eyre::Result<ReportEntry> {
     let now = SystemTime::now();
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let head_info = repo.get_head_info()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -219,7 +234,6 @@ fn collect_events(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<
 
 struct EventCollector {
     effects: Effects,
-    git_run_info: GitRunInfo,
 }
 
 impl Collector for EventCollector {
@@ -231,7 +245,7 @@ impl Collector for EventCollector {
         &mut self,
         _crate_info: &bugreport::CrateInfo,
     ) -> Result<ReportEntry, CollectionError> {
-        collect_events(&self.effects, &self.git_run_info)
+        collect_events(&self.effects)
             .map_err(|e| CollectionError::CouldNotRetrieve(format!("Error: {e}")))
     }
 }
@@ -322,7 +336,6 @@ pub fn bug_report(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<
         })
         .info(EventCollector {
             effects: effects.clone(),
-            git_run_info: git_run_info.clone(),
         })
         .print::<Markdown>();
 
@@ -0,0 +1,510 @@
+//! Generate shell completions for `git-branchless`.
+//!
+//! The completion scripts are generated from the `clap` definitions of the
+//! subcommands. Arguments which take revsets are completed dynamically by
+//! calling `git branchless completions --list-candidates`, which prints the
+//! branches and the commits in the smartlog.
+
+use std::fmt::Write;
+
+use bstr::ByteSlice;
+use clap::builder::ValueParser;
+use clap::{Arg, Command as ClapCommand, CommandFactory};
+use git_branchless_opts::{CompletionShell, Opts, Revset};
+use git_branchless_revset::resolve_default_smartlog_commits;
+use itertools::Itertools;
+use lib::core::dag::{sorted_commit_set, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{CategorizedReferenceName, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// The kind of value that an option takes.
+#[derive(Debug)]
+enum ValueKind {
+    /// The option is a flag.
+    None,
+
+    /// The option takes an arbitrary value, such as a path.
+    Any,
+
+    /// The option takes a revset.
+    Revset,
+
+    /// The option takes one of the given values.
+    OneOf(Vec<String>),
+}
+
+#[derive(Debug)]
+struct OptionSpec {
+    short: Option<char>,
+    long: Option<String>,
+    about: String,
+    value: ValueKind,
+}
+
+impl OptionSpec {
+    /// The spellings of the option, such as `-d` and `--dest`.
+    fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Some(short) = self.short {
+            names.push(format!("-{short}"));
+        }
+        if let Some(long) = &self.long {
+            names.push(format!("--{long}"));
+        }
+        names
+    }
+}
+
+/// A subcommand (or the top-level command) and the arguments it accepts.
+#[derive(Debug)]
+struct CommandSpec {
+    /// The names of the subcommands leading to this one, separated by spaces,
+    /// such as `test run`. Empty for the top-level command.
+    path: String,
+
+    /// The names and descriptions of the subcommands of this command.
+    subcommands: Vec<(String, String)>,
+
+    options: Vec<OptionSpec>,
+
+    /// Whether the positional arguments of this command are revsets.
+    positional_revsets: bool,
+}
+
+fn describe(about: Option<&impl ToString>) -> String {
+    let about = match about {
+        Some(about) => about.to_string(),
+        None => return String::new(),
+    };
+    let about = about.lines().next().unwrap_or_default();
+    match about.find(". ") {
+        Some(index) => about[..index].to_owned(),
+        None => about.trim_end_matches('.').to_owned(),
+    }
+}
+
+fn is_revset_arg(arg: &Arg) -> bool {
+    let revset_value_parser = ValueParser::from(clap::value_parser!(Revset));
+    arg.get_value_parser().type_id() == revset_value_parser.type_id()
+}
+
+fn collect_command_specs(command: &ClapCommand, path: &str, specs: &mut Vec<CommandSpec>) {
+    let subcommands: Vec<(String, &ClapCommand)> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+        .flat_map(|subcommand| {
+            std::iter::once(subcommand.get_name())
+                .chain(subcommand.get_visible_aliases())
+                .map(move |name| (name.to_owned(), subcommand))
+        })
+        .collect();
+
+    let mut options = Vec::new();
+    let mut positional_revsets = false;
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        if arg.is_positional() {
+            positional_revsets |= is_revset_arg(arg);
+            continue;
+        }
+        let possible_values: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_owned())
+            .collect();
+        let value = if !arg.get_action().takes_values() {
+            ValueKind::None
+        } else if is_revset_arg(arg) {
+            ValueKind::Revset
+        } else if !possible_values.is_empty() {
+            ValueKind::OneOf(possible_values)
+        } else {
+            ValueKind::Any
+        };
+        options.push(OptionSpec {
+            short: arg.get_short(),
+            long: arg.get_long().map(|long| long.to_owned()),
+            about: describe(arg.get_help()),
+            value,
+        });
+    }
+
+    specs.push(CommandSpec {
+        path: path.to_owned(),
+        subcommands: subcommands
+            .iter()
+            .map(|(name, subcommand)| (name.clone(), describe(subcommand.get_about())))
+            .collect(),
+        options,
+        positional_revsets,
+    });
+    for (name, subcommand) in subcommands {
+        let path = if path.is_empty() {
+            name
+        } else {
+            format!("{path} {name}")
+        };
+        collect_command_specs(subcommand, &path, specs);
+    }
+}
+
+fn get_command_specs() -> Vec<CommandSpec> {
+    let mut command = Opts::command();
+    command.build();
+    let mut specs = Vec::new();
+    collect_command_specs(&command, "", &mut specs);
+    specs
+}
+
+/// Quote the given string for use as a single word in a POSIX shell script.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r#"'\''"#))
+}
+
+/// Quote the given string for use as a single word in a Fish script.
+fn quote_fish(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn render_bash(specs: &[CommandSpec]) -> eyre::Result<String> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "\
+# Bash completions for git-branchless, generated by `git branchless completions bash`.
+
+_git_branchless_candidates() {{
+    local candidates
+    candidates=\"$(git branchless completions --list-candidates 2>/dev/null | cut -f1)\"
+    COMPREPLY=($(compgen -W \"$candidates\" -- \"$1\"))
+}}
+
+_git_branchless() {{
+    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    local cmd_path=\"\" word i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word=\"${{COMP_WORDS[i]}}\"
+        case \"${{cmd_path:+$cmd_path }}$word\" in"
+    )?;
+    let paths = specs
+        .iter()
+        .filter(|spec| !spec.path.is_empty())
+        .map(|spec| quote(&spec.path))
+        .join("|");
+    writeln!(
+        out,
+        "            {paths})
+                cmd_path=\"${{cmd_path:+$cmd_path }}$word\" ;;
+        esac
+    done
+
+    local options=\"\" subcommands=\"\" positional_revsets=\"\"
+    case \"$cmd_path\" in"
+    )?;
+    for spec in specs {
+        writeln!(out, "        {})", quote(&spec.path))?;
+        let mut value_cases = Vec::new();
+        let revset_names = spec
+            .options
+            .iter()
+            .filter(|option| matches!(option.value, ValueKind::Revset))
+            .flat_map(|option| option.names())
+            .collect_vec();
+        if !revset_names.is_empty() {
+            value_cases.push(format!(
+                "{}) _git_branchless_candidates \"$cur\"; return ;;",
+                revset_names.join("|")
+            ));
+        }
+        for option in &spec.options {
+            match &option.value {
+                ValueKind::OneOf(values) => value_cases.push(format!(
+                    "{}) COMPREPLY=($(compgen -W {} -- \"$cur\")); return ;;",
+                    option.names().join("|"),
+                    quote(&values.join(" "))
+                )),
+                ValueKind::Any => {
+                    value_cases.push(format!("{}) return ;;", option.names().join("|")))
+                }
+                ValueKind::None | ValueKind::Revset => {}
+            }
+        }
+        if !value_cases.is_empty() {
+            writeln!(out, "            case \"$prev\" in")?;
+            for value_case in value_cases {
+                writeln!(out, "                {value_case}")?;
+            }
+            writeln!(out, "            esac")?;
+        }
+        let options = spec
+            .options
+            .iter()
+            .flat_map(|option| option.names())
+            .join(" ");
+        writeln!(out, "            options={}", quote(&options))?;
+        if !spec.subcommands.is_empty() {
+            let subcommands = spec.subcommands.iter().map(|(name, _)| name).join(" ");
+            writeln!(out, "            subcommands={}", quote(&subcommands))?;
+        }
+        if spec.positional_revsets {
+            writeln!(out, "            positional_revsets=1")?;
+        }
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(
+        out,
+        "    esac
+
+    if [[ \"$cur\" == -* ]]; then
+        COMPREPLY=($(compgen -W \"$options\" -- \"$cur\"))
+    elif [[ -n \"$subcommands\" ]]; then
+        COMPREPLY=($(compgen -W \"$subcommands\" -- \"$cur\"))
+    elif [[ -n \"$positional_revsets\" ]]; then
+        _git_branchless_candidates \"$cur\"
+    fi
+}}
+
+complete -o default -F _git_branchless git-branchless"
+    )?;
+    Ok(out)
+}
+
+fn render_zsh(specs: &[CommandSpec]) -> eyre::Result<String> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "\
+#compdef git-branchless
+# Zsh completions for git-branchless, generated by `git branchless completions zsh`.
+
+_git_branchless_candidates() {{
+    local -a candidates
+    candidates=(${{${{(f)\"$(git branchless completions --list-candidates 2>/dev/null)\"}}//$'\\t'/:}})
+    _describe -t commits 'commit or branch' candidates
+}}
+
+_git-branchless() {{
+    local cmd_path=\"\" word i
+    for ((i = 2; i < CURRENT; i++)); do
+        word=\"${{words[i]}}\"
+        case \"${{cmd_path:+$cmd_path }}$word\" in"
+    )?;
+    let paths = specs
+        .iter()
+        .filter(|spec| !spec.path.is_empty())
+        .map(|spec| quote(&spec.path))
+        .join("|");
+    writeln!(
+        out,
+        "            ({paths})
+                cmd_path=\"${{cmd_path:+$cmd_path }}$word\" ;;
+        esac
+    done
+
+    local prev=\"${{words[CURRENT-1]}}\"
+    local -a option_descriptions subcommand_descriptions
+    local positional_revsets=\"\"
+    case \"$cmd_path\" in"
+    )?;
+    for spec in specs {
+        writeln!(out, "        ({})", quote(&spec.path))?;
+        let mut value_cases = Vec::new();
+        for option in &spec.options {
+            let names = option.names().join("|");
+            match &option.value {
+                ValueKind::Revset => {
+                    value_cases.push(format!("({names}) _git_branchless_candidates; return ;;"))
+                }
+                ValueKind::OneOf(values) => value_cases.push(format!(
+                    "({names}) _values {} {}; return ;;",
+                    quote(&option.about),
+                    values.iter().map(|value| quote(value)).join(" ")
+                )),
+                ValueKind::Any => value_cases.push(format!("({names}) _files; return ;;")),
+                ValueKind::None => {}
+            }
+        }
+        if !value_cases.is_empty() {
+            writeln!(out, "            case \"$prev\" in")?;
+            for value_case in value_cases {
+                writeln!(out, "                {value_case}")?;
+            }
+            writeln!(out, "            esac")?;
+        }
+        writeln!(out, "            option_descriptions=(")?;
+        for option in &spec.options {
+            for name in option.names() {
+                writeln!(
+                    out,
+                    "                {}",
+                    quote(&format!("{name}:{}", option.about))
+                )?;
+            }
+        }
+        writeln!(out, "            )")?;
+        if !spec.subcommands.is_empty() {
+            writeln!(out, "            subcommand_descriptions=(")?;
+            for (name, about) in &spec.subcommands {
+                writeln!(out, "                {}", quote(&format!("{name}:{about}")))?;
+            }
+            writeln!(out, "            )")?;
+        }
+        if spec.positional_revsets {
+            writeln!(out, "            positional_revsets=1")?;
+        }
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(
+        out,
+        "    esac
+
+    if [[ \"$PREFIX\" == -* ]]; then
+        _describe -t options 'option' option_descriptions
+    elif (( ${{#subcommand_descriptions}} )); then
+        _describe -t commands 'subcommand' subcommand_descriptions
+    elif [[ -n \"$positional_revsets\" ]]; then
+        _git_branchless_candidates
+    else
+        _files
+    fi
+}}
+
+compdef _git-branchless git-branchless"
+    )?;
+    Ok(out)
+}
+
+fn render_fish(specs: &[CommandSpec]) -> eyre::Result<String> {
+    let mut out = String::new();
+    let paths = specs
+        .iter()
+        .filter(|spec| !spec.path.is_empty())
+        .map(|spec| quote_fish(&spec.path))
+        .join(" ");
+    writeln!(
+        out,
+        "\
+# Fish completions for git-branchless, generated by `git branchless completions fish`.
+
+function __git_branchless_at
+    set -l words (commandline -opc)
+    set -e words[1]
+    set -l cmd_path
+    for word in $words
+        set -l candidate (string join ' ' $cmd_path $word)
+        if contains -- $candidate {paths}
+            set cmd_path $cmd_path $word
+        end
+    end
+    set -l joined (string join ' ' $cmd_path)
+    test \"$joined\" = \"$argv\"
+end
+
+function __git_branchless_candidates
+    git branchless completions --list-candidates 2>/dev/null
+end
+
+complete -c git-branchless -f"
+    )?;
+    for spec in specs {
+        let condition = quote_fish(&format!("__git_branchless_at {}", spec.path));
+        for (name, about) in &spec.subcommands {
+            writeln!(
+                out,
+                "complete -c git-branchless -n {condition} -a {} -d {}",
+                quote_fish(name),
+                quote_fish(about)
+            )?;
+        }
+        for option in &spec.options {
+            let mut line = format!("complete -c git-branchless -n {condition}");
+            if let Some(short) = option.short {
+                write!(line, " -s {short}")?;
+            }
+            if let Some(long) = &option.long {
+                write!(line, " -l {long}")?;
+            }
+            match &option.value {
+                ValueKind::None => {}
+                ValueKind::Any => write!(line, " -r -F")?,
+                ValueKind::Revset => write!(line, " -x -a '(__git_branchless_candidates)'")?,
+                ValueKind::OneOf(values) => {
+                    write!(line, " -x -a {}", quote_fish(&values.join(" ")))?
+                }
+            }
+            write!(line, " -d {}", quote_fish(&option.about))?;
+            writeln!(out, "{line}")?;
+        }
+        if spec.positional_revsets {
+            writeln!(
+                out,
+                "complete -c git-branchless -n {condition} -a '(__git_branchless_candidates)'"
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+/// Print the completion script for the given shell.
+#[instrument]
+pub fn completions(effects: &Effects, shell: CompletionShell) -> eyre::Result<ExitCode> {
+    let specs = get_command_specs();
+    let script = match shell {
+        CompletionShell::Bash => render_bash(&specs)?,
+        CompletionShell::Zsh => render_zsh(&specs)?,
+        CompletionShell::Fish => render_fish(&specs)?,
+    };
+    write!(effects.get_output_stream(), "{script}")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the branches and the commits in the smartlog, which are the
+/// candidates for completing arguments which take revsets.
+#[instrument]
+pub fn list_candidates(effects: &Effects) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commits = resolve_default_smartlog_commits(effects, &repo, &mut dag)?;
+    let commits = sorted_commit_set(&repo, &dag, &commits)?;
+
+    let mut branches = Vec::new();
+    for (oid, names) in references_snapshot.branch_oid_to_names.iter() {
+        let summary = match repo.find_commit(*oid)? {
+            Some(commit) => commit.get_summary()?.to_str_lossy().into_owned(),
+            None => continue,
+        };
+        for name in names {
+            let name = CategorizedReferenceName::new(name).render_suffix();
+            branches.push((name, summary.clone()));
+        }
+    }
+    for (name, summary) in branches.into_iter().sorted().dedup() {
+        writeln!(effects.get_output_stream(), "{name}\t{summary}")?;
+    }
+    for commit in commits {
+        writeln!(
+            effects.get_output_stream(),
+            "{}\t{}",
+            commit.get_short_oid()?,
+            commit.get_summary()?.to_str_lossy()
+        )?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,148 @@
+//! Change `git-branchless` settings for the current repository.
+
+use std::fmt::Write;
+
+use lib::core::config::{find_known_config_key, suggest_known_config_key};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{Config, ConfigRead, ConfigWrite, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// Describe the given unrecognized config key, along with the known key which
+/// it's most likely a misspelling of, if any.
+fn describe_unknown_key(key: &str) -> String {
+    match suggest_known_config_key(key) {
+        Some(suggestion) => format!("Unknown setting: {key} (did you mean {suggestion}?)"),
+        None => format!("Unknown setting: {key}"),
+    }
+}
+
+/// Print the value of the given setting.
+#[instrument]
+pub fn get(effects: &Effects, key: &str) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let key = match find_known_config_key(key) {
+        Some((key, _known_key)) => key,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Warning: {}",
+                describe_unknown_key(key)
+            )?;
+            key.to_owned()
+        }
+    };
+
+    let value: Option<String> = repo.get_readonly_config()?.get(&key)?;
+    match value {
+        Some(value) => {
+            writeln!(effects.get_output_stream(), "{value}")?;
+            Ok(ExitCode(0))
+        }
+        None => Ok(ExitCode(1)),
+    }
+}
+
+/// Set the given setting to the provided value.
+#[instrument]
+pub fn set(effects: &Effects, key: &str, value: &str) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
+
+    // Settings are written to the same isolated config file that `git
+    // branchless init` creates, so that they take precedence over (and are
+    // uninstalled along with) the rest of our settings.
+    let config_path = repo.get_config_path();
+    if !config_path.exists() {
+        writeln!(
+            effects.get_output_stream(),
+            "git-branchless is not initialized in this repository. To initialize it, run: git branchless init"
+        )?;
+        return Ok(ExitCode(1));
+    }
+    let mut config = Config::open(&config_path)?;
+
+    let (key, known_key) = match find_known_config_key(key) {
+        Some(result) => result,
+        None => {
+            writeln!(effects.get_output_stream(), "{}", describe_unknown_key(key))?;
+            let key = if key.to_lowercase().starts_with("branchless.") {
+                key.to_owned()
+            } else {
+                format!("branchless.{key}")
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "To set it anyways, run: git config {key} <value>"
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    if let Err(err) = known_key.value_type.validate(&repo, value)? {
+        writeln!(
+            effects.get_output_stream(),
+            "Invalid value for {key}: {err}"
+        )?;
+        return Ok(ExitCode(1));
+    }
+    config.set(&key, value)?;
+
+    if key == "branchless.core.mainBranch" {
+        // Re-sync the commit graph so that it's consistent with the new main
+        // branch immediately, rather than at the next invocation.
+        let repo = Repo::from_dir(repo.get_path())?;
+        let references_snapshot = repo.get_references_snapshot()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        Dag::open_and_sync(
+            effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Set {key} to: {}",
+        console::style(value).bold()
+    )?;
+    Ok(ExitCode(0))
+}
+
+/// List all `git-branchless` settings which have been set, warning about any
+/// which aren't recognized.
+#[instrument]
+pub fn list(effects: &Effects) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let entries = repo.get_readonly_config()?.list(r"^branchless\.")?;
+
+    let mut unknown_keys = Vec::new();
+    for (key, value) in entries {
+        // Git reports keys in lowercase, so show the canonical spelling
+        // instead where possible.
+        let key = match find_known_config_key(&key) {
+            Some((key, _known_key)) => key,
+            None => {
+                unknown_keys.push(key.clone());
+                key
+            }
+        };
+        writeln!(effects.get_output_stream(), "{key}={value}")?;
+    }
+    for key in unknown_keys {
+        writeln!(
+            effects.get_error_stream(),
+            "Warning: {}",
+            describe_unknown_key(&key)
+        )?;
+    }
+
+    Ok(ExitCode(0))
+}
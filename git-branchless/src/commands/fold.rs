@@ -0,0 +1,288 @@
+//! Fold a range of commits into a single commit.
+//!
+//! The range must be linear: each commit except the first must be the only
+//! child of the previous commit within the range. The folded commit replaces
+//! the last commit in the range, and the other commits are recorded as having
+//! been rewritten into it, so that their branches move to the folded commit.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use bstr::ByteSlice;
+use eden_dag::DagAlgorithm;
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use itertools::Itertools;
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    execute_rebase_plan, move_branches, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+};
+use lib::git::{GitRunInfo, MaybeZeroOid, Repo};
+use lib::util::ExitCode;
+use rayon::ThreadPoolBuilder;
+use tracing::instrument;
+
+/// Fold the given commits, or the commits from `from` to `HEAD`, into a single
+/// commit.
+#[instrument]
+pub fn fold(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    from: Option<Revset>,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+    force_rewrite_public_commits: bool,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let revsets = match from {
+        Some(Revset(from)) => vec![Revset(format!("({from})::."))],
+        None => revsets,
+    };
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let commit_set = union_all(&commit_sets);
+    let commits = sorted_commit_set(&repo, &dag, &commit_set)?;
+    if commits.len() < 2 {
+        writeln!(
+            effects.get_output_stream(),
+            "At least 2 commits are needed to fold, but {} given.",
+            Pluralize {
+                determiner: None,
+                amount: commits.len(),
+                unit: ("commit was", "commits were"),
+            }
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let first_commit = &commits[0];
+    let last_commit = &commits[commits.len() - 1];
+    let base_commit = match first_commit.get_only_parent() {
+        Some(base_commit) => base_commit,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot fold commits starting at a merge or root commit: {}",
+                effects
+                    .get_glyphs()
+                    .render(first_commit.friendly_describe(effects.get_glyphs())?)?
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    for (parent, child) in commits.iter().tuple_windows() {
+        if child.get_parent_oids() != vec![parent.get_oid()] {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot fold commits which don't form a linear range: {} is not the only parent of {}",
+                effects
+                    .get_glyphs()
+                    .render(parent.friendly_describe(effects.get_glyphs())?)?,
+                effects
+                    .get_glyphs()
+                    .render(child.friendly_describe(effects.get_glyphs())?)?
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    let commits_to_verify = dag.query().descendants(commit_set.clone())?;
+    let commits_to_verify = dag.filter_visible_commits(commits_to_verify)?;
+    let permissions =
+        match RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commits_to_verify)? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    let mut messages = Vec::new();
+    for commit in commits.iter() {
+        messages.push(commit.get_message_raw()?.to_str_lossy().trim().to_owned());
+    }
+    let folded_oid = repo.create_commit(
+        None,
+        &first_commit.get_author(),
+        &last_commit.get_committer(),
+        &format!("{}\n", messages.join("\n\n")),
+        &last_commit.get_tree()?,
+        vec![&base_commit],
+    )?;
+
+    let rebase_plan = {
+        let pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(&repo)?;
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        builder.move_subtree(last_commit.get_oid(), vec![base_commit.get_oid()])?;
+        builder.replace_commit(last_commit.get_oid(), folded_oid)?;
+        // Move any other children of the folded commits onto the folded
+        // commit, since their parents are about to be hidden.
+        for (commit, next_commit) in commits.iter().tuple_windows() {
+            let children = dag
+                .query()
+                .children(CommitSet::from(commit.get_oid()))?
+                .difference(&CommitSet::from(next_commit.get_oid()));
+            let children = dag.filter_visible_commits(children)?;
+            for child_oid in commit_set_to_vec(&children)? {
+                builder.move_subtree(child_oid, vec![last_commit.get_oid()])?;
+            }
+        }
+        match builder.build(effects, git_run_info, &pool, &repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => {
+                eyre::bail!(
+                    "BUG: rebase plan indicates nothing to do, but folding should always do something."
+                );
+            }
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        }
+    };
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "fold")?;
+    let execute_options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id,
+        preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+        force_in_memory: true,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        check_out_commit_options: CheckOutCommitOptions {
+            additional_args: Default::default(),
+            reset: false,
+            render_smartlog: false,
+        },
+    };
+    let head_info = repo.get_head_info()?;
+    let result = execute_rebase_plan(
+        effects,
+        git_run_info,
+        &repo,
+        &event_log_db,
+        &rebase_plan,
+        &execute_options,
+    )?;
+    let rewritten_oids = match result {
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: Some(rewritten_oids),
+        } => rewritten_oids,
+        ExecuteRebasePlanResult::Succeeded {
+            rewritten_oids: None,
+        } => return Ok(ExitCode(0)),
+        ExecuteRebasePlanResult::DeclinedToMerge {
+            failed_merge_info: _,
+        } => {
+            writeln!(
+                effects.get_output_stream(),
+                "Folding would cause a merge conflict when moving the descendants of the folded commits. Move them elsewhere and try again."
+            )?;
+            return Ok(ExitCode::MERGE_CONFLICT);
+        }
+        ExecuteRebasePlanResult::Failed { exit_code } => return Ok(exit_code),
+    };
+    let folded_oid = match rewritten_oids.get(&last_commit.get_oid()) {
+        Some(MaybeZeroOid::NonZero(folded_oid)) => *folded_oid,
+        Some(MaybeZeroOid::Zero) | None => {
+            eyre::bail!("BUG: the folded commit was not rewritten")
+        }
+    };
+
+    // Record that the other commits were folded into the last one, so that
+    // they're hidden and their branches are moved.
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let folded_oids: HashMap<_, _> = commits[..commits.len() - 1]
+        .iter()
+        .map(|commit| (commit.get_oid(), MaybeZeroOid::NonZero(folded_oid)))
+        .collect();
+    event_log_db.add_events(
+        folded_oids
+            .keys()
+            .map(|commit_oid| Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(*commit_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(folded_oid),
+            })
+            .collect(),
+    )?;
+    let head_was_folded = match head_info.oid {
+        Some(head_oid) => folded_oids.contains_key(&head_oid),
+        None => false,
+    };
+    if head_was_folded {
+        // Detach `HEAD` before moving its branch, so that the working copy is
+        // updated when the folded commit is checked out below.
+        repo.detach_head(&head_info)?;
+    }
+    move_branches(effects, git_run_info, &repo, event_tx_id, &folded_oids)?;
+    if head_was_folded {
+        let target = match head_info.reference_name {
+            Some(reference_name) => CheckoutTarget::Reference(reference_name),
+            None => CheckoutTarget::Oid(folded_oid),
+        };
+        let exit_code = check_out_commit(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            event_tx_id,
+            Some(target),
+            &execute_options.check_out_commit_options,
+        )?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
+    let folded_commit = repo.find_commit_or_fail(folded_oid)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Folded {} into: {}",
+        Pluralize {
+            determiner: None,
+            amount: commits.len(),
+            unit: ("commit", "commits"),
+        },
+        effects
+            .get_glyphs()
+            .render(folded_commit.friendly_describe(effects.get_glyphs())?)?
+    )?;
+    Ok(ExitCode(0))
+}
@@ -7,13 +7,21 @@ use std::time::SystemTime;
 
 use eden_dag::DagAlgorithm;
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
-use lib::core::dag::{sorted_commit_set, union_all, Dag};
+use itertools::Itertools;
+use lib::core::child_index::ChildIndexDb;
+use lib::core::config::{
+    get_hide_default_recursive, get_hint_enabled, get_hint_string, get_smartlog_show_after_command,
+    print_hint_suppression_notice, Hint,
+};
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{CommitActivityStatus, Event};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
-use lib::core::repo_ext::RepoExt;
+use lib::core::prompt::confirm;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
 use lib::core::rewrite::move_branches;
+use lib::core::user_hooks::{run_user_hook, UserHook};
 use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 use lib::util::ExitCode;
 use tracing::instrument;
@@ -28,11 +36,18 @@ pub fn hide(
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     delete_branches: bool,
-    recursive: bool,
+    recursive: Option<bool>,
+    message: Option<String>,
+    force: bool,
+    orphans: bool,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
     let repo = Repo::from_current_dir()?;
+    let recursive = match recursive {
+        Some(recursive) => recursive,
+        None => get_hide_default_recursive(&repo)?,
+    };
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
@@ -56,14 +71,101 @@ pub fn hide(
         };
 
     let commits = union_all(&commit_sets);
+    let commits = if orphans {
+        commits.union(&find_orphaned_commits(&dag, &event_replayer, event_cursor)?)
+    } else {
+        commits
+    };
     let commits = if recursive {
         dag.filter_visible_commits(dag.query().descendants(commits)?)?
     } else {
         commits
     };
     let commits = dag.query().sort(&commits)?;
+    let dangling_children = if recursive {
+        CommitSet::empty()
+    } else {
+        ChildIndexDb::new(&conn)?
+            .query_visible_children(&dag, &commits)?
+            .difference(&commits)
+            .difference(&dag.query_obsolete_commits())
+    };
     let commits = sorted_commit_set(&repo, &dag, &commits)?;
 
+    if !force {
+        let mut protected_commits = Vec::new();
+        for commit in commits.iter() {
+            let reasons = get_hide_protection_reasons(
+                &dag,
+                &references_snapshot,
+                commit.get_oid(),
+                delete_branches,
+            )?;
+            if !reasons.is_empty() {
+                protected_commits.push((commit, reasons));
+            }
+        }
+
+        if !protected_commits.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "Refusing to hide {}:",
+                Pluralize {
+                    determiner: None,
+                    amount: protected_commits.len(),
+                    unit: ("protected commit", "protected commits"),
+                }
+            )?;
+            for (commit, reasons) in protected_commits {
+                writeln!(
+                    effects.get_output_stream(),
+                    "- {} ({})",
+                    glyphs.render(commit.friendly_describe(&glyphs)?)?,
+                    reasons.join(", "),
+                )?;
+            }
+            writeln!(
+                effects.get_output_stream(),
+                "Retry with -f/--force to hide them anyways."
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    let mut abandoned_branches: Vec<String> = commits
+        .iter()
+        .filter_map(|commit| {
+            references_snapshot
+                .branch_oid_to_names
+                .get(&commit.get_oid())
+        })
+        .flatten()
+        .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
+        .collect();
+    abandoned_branches.sort_unstable();
+    if delete_branches
+        && !abandoned_branches.is_empty()
+        && !confirm(
+            effects,
+            &format!(
+                "Delete {}: {}?",
+                Pluralize {
+                    determiner: None,
+                    amount: abandoned_branches.len(),
+                    unit: ("branch", "branches"),
+                },
+                abandoned_branches.join(", ")
+            ),
+        )?
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            effects.get_messages().format("confirm-aborted", &[])
+        )?;
+        return Ok(ExitCode(1));
+    }
+
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
     let events = commits
@@ -72,9 +174,10 @@ pub fn hide(
             timestamp,
             event_tx_id,
             commit_oid: commit.get_oid(),
+            message: message.clone(),
         })
-        .collect();
-    event_log_db.add_events(events)?;
+        .collect_vec();
+    event_log_db.add_events(events.clone())?;
 
     let cursor = event_replayer.make_default_cursor();
     let num_commits = commits.len();
@@ -118,18 +221,7 @@ pub fn hide(
         )?;
     }
 
-    let mut abandoned_branches: Vec<String> = commits
-        .iter()
-        .filter_map(|commit| {
-            references_snapshot
-                .branch_oid_to_names
-                .get(&commit.get_oid())
-        })
-        .flatten()
-        .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
-        .collect();
     if !abandoned_branches.is_empty() {
-        abandoned_branches.sort_unstable();
         // This message will look like either of these:
         // Abandoned X branches: <branches>
         // Deleted X branches: <branches>
@@ -150,38 +242,147 @@ pub fn hide(
         )?;
     }
 
-    // This message will look like either of these:
-    // To unhide these X commits, run: git undo
-    // To unhide these X commits and restore X branches, run: git undo
-    let delete_branches_message = match delete_branches {
-        true => format!(
-            " and restore {}",
+    let dangling_children = sorted_commit_set(&repo, &dag, &dangling_children)?;
+    if !dangling_children.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Warning: {} will appear to dangle in the smartlog:",
             Pluralize {
                 determiner: None,
-                amount: abandoned_branches.len(),
-                unit: ("branch", "branches"),
+                amount: dangling_children.len(),
+                unit: ("visible child commit", "visible child commits"),
             }
-        ),
-        false => String::new(),
-    };
-    writeln!(
-        effects.get_output_stream(),
-        "To unhide {}{}, run: git undo",
-        Pluralize {
-            determiner: Some(("this", "these")),
-            amount: num_commits,
-            unit: ("commit", "commits"),
-        },
-        delete_branches_message
+        )?;
+        for child in dangling_children.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "- {}",
+                glyphs.render(child.friendly_describe(&glyphs)?)?,
+            )?;
+        }
+        if get_hint_enabled(effects, &repo, Hint::HideOrphans)? {
+            writeln!(
+                effects.get_output_stream(),
+                "{}: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans",
+                effects.get_glyphs().render(get_hint_string(effects))?,
+            )?;
+            print_hint_suppression_notice(effects, Hint::HideOrphans)?;
+        }
+    }
+
+    if get_hint_enabled(effects, &repo, Hint::HideUndo)? {
+        // This message will look like either of these:
+        // hint: to unhide these X commits, run: git undo
+        // hint: to unhide these X commits and restore X branches, run: git undo
+        let delete_branches_message = match delete_branches {
+            true => format!(
+                " and restore {}",
+                Pluralize {
+                    determiner: None,
+                    amount: abandoned_branches.len(),
+                    unit: ("branch", "branches"),
+                }
+            ),
+            false => String::new(),
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "{}: to unhide {}{}, run: git undo",
+            effects.get_glyphs().render(get_hint_string(effects))?,
+            Pluralize {
+                determiner: Some(("this", "these")),
+                amount: num_commits,
+                unit: ("commit", "commits"),
+            },
+            delete_branches_message
+        )?;
+        print_hint_suppression_notice(effects, Hint::HideUndo)?;
+    }
+
+    run_user_hook(
+        effects,
+        git_run_info,
+        &repo,
+        UserHook::PostHide,
+        event_tx_id,
+        &events,
     )?;
 
+    if get_smartlog_show_after_command(&repo)? {
+        let exit_code =
+            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?;
+        return Ok(exit_code);
+    }
+
     Ok(ExitCode(0))
 }
 
+/// Find visible commits which descend from a manually-hidden commit. These are
+/// typically left behind when hiding a commit without `--recursive`.
+fn find_orphaned_commits(
+    dag: &Dag,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+) -> eyre::Result<CommitSet> {
+    let manually_hidden_commits: CommitSet = commit_set_to_vec(&dag.query_obsolete_commits())?
+        .into_iter()
+        .filter(|commit_oid| {
+            matches!(
+                event_replayer.get_cursor_commit_latest_event(event_cursor, *commit_oid),
+                Some(Event::ObsoleteEvent { .. })
+            )
+        })
+        .collect();
+    let orphaned_commits = dag
+        .query()
+        .descendants(manually_hidden_commits)?
+        .difference(&dag.query_obsolete_commits());
+    dag.filter_visible_commits(orphaned_commits)
+}
+
+/// Determine why the given commit shouldn't be hidden without `--force`, if
+/// at all. An empty result means that the commit isn't protected.
+fn get_hide_protection_reasons(
+    dag: &Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_oid: NonZeroOid,
+    delete_branches: bool,
+) -> eyre::Result<Vec<String>> {
+    let mut reasons = Vec::new();
+
+    if references_snapshot.head_oid == Some(commit_oid) {
+        reasons.push("currently checked out".to_string());
+    }
+
+    // If the user asked to delete branches, then they've already acknowledged
+    // that the branches will go away.
+    if !delete_branches {
+        if let Some(branch_names) = references_snapshot.branch_oid_to_names.get(&commit_oid) {
+            let mut branch_names: Vec<String> = branch_names
+                .iter()
+                .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
+                .collect();
+            branch_names.sort_unstable();
+            let label = match branch_names.len() {
+                1 => "has branch",
+                _ => "has branches",
+            };
+            reasons.push(format!("{label}: {}", branch_names.join(", ")));
+        }
+    }
+
+    if dag.is_public_commit(commit_oid)? {
+        reasons.push("public commit on the main branch".to_string());
+    }
+
+    Ok(reasons)
+}
+
 /// Unhide the hashes provided on the command-line.
 #[instrument]
 pub fn unhide(
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     recursive: bool,
@@ -231,8 +432,8 @@ pub fn unhide(
             event_tx_id,
             commit_oid: commit.get_oid(),
         })
-        .collect();
-    event_log_db.add_events(events)?;
+        .collect_vec();
+    event_log_db.add_events(events.clone())?;
 
     let cursor = event_replayer.make_default_cursor();
     let num_commits = commits.len();
@@ -252,15 +453,34 @@ pub fn unhide(
         }
     }
 
-    writeln!(
-        effects.get_output_stream(),
-        "To hide {}, run: git undo",
-        Pluralize {
-            determiner: Some(("this", "these")),
-            amount: num_commits,
-            unit: ("commit", "commits"),
-        },
+    if get_hint_enabled(effects, &repo, Hint::HideUndo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "{}: to hide {}, run: git undo",
+            effects.get_glyphs().render(get_hint_string(effects))?,
+            Pluralize {
+                determiner: Some(("this", "these")),
+                amount: num_commits,
+                unit: ("commit", "commits"),
+            },
+        )?;
+        print_hint_suppression_notice(effects, Hint::HideUndo)?;
+    }
+
+    run_user_hook(
+        effects,
+        git_run_info,
+        &repo,
+        UserHook::PostHide,
+        event_tx_id,
+        &events,
     )?;
 
+    if get_smartlog_show_after_command(&repo)? {
+        let exit_code =
+            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?;
+        return Ok(exit_code);
+    }
+
     Ok(ExitCode(0))
 }
@@ -0,0 +1,366 @@
+//! Print the result of a command as JSON, for `--output=json`.
+//!
+//! The human-oriented output of the command is suppressed. The commits and
+//! branches which the command changed are found in the event log entries
+//! added while it ran, so that the changes made by hooks in `git`
+//! subprocesses are included. Merge conflicts are reported to an observer
+//! while rebasing.
+//!
+//! Output written directly to stdout, such as by `git` subprocesses which
+//! inherit it, is sent to stderr instead while the command runs, so that
+//! stdout only contains the JSON.
+
+use std::fmt::Write;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use lib::core::effects::{Effects, OperationEvent, OperationObserver};
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::core::user_error::{Remediation, UserError};
+use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use serde::Serialize;
+
+/// The JSON output of a mutating command.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CommandResultV1 {
+    command: String,
+    exit_code: isize,
+
+    /// Commits which were created, such as with `record`.
+    created_commits: Vec<String>,
+
+    /// Commits which were rewritten, such as by `move` or `restack`.
+    rewritten_commits: Vec<RewrittenCommitV1>,
+
+    hidden_commits: Vec<String>,
+    unhidden_commits: Vec<String>,
+    updated_branches: Vec<UpdatedBranchV1>,
+
+    /// Merge conflicts which stopped a rebase.
+    conflicts: Vec<ConflictV1>,
+
+    /// Failures which were reported to the user.
+    errors: Vec<UserErrorV1>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RewrittenCommitV1 {
+    old_oid: String,
+
+    /// The rewritten commit, or `None` if the commit was deleted, such as
+    /// because its changes were already applied upstream.
+    new_oid: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatedBranchV1 {
+    name: String,
+
+    /// The previous target of the branch, or `None` if it was created.
+    old_oid: Option<String>,
+
+    /// The new target of the branch, or `None` if it was deleted.
+    new_oid: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConflictV1 {
+    commit_oid: String,
+    paths: Vec<PathBuf>,
+}
+
+/// A [`UserError`], as reported in the JSON output of any command.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserErrorV1 {
+    code: &'static str,
+    message: String,
+    remediations: Vec<RemediationV1>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum RemediationV1 {
+    Run {
+        purpose: String,
+        command: String,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    RetryWithOption {
+        purpose: String,
+        option: String,
+    },
+}
+
+impl From<&UserError> for UserErrorV1 {
+    fn from(error: &UserError) -> Self {
+        let UserError {
+            code,
+            message,
+            remediations,
+        } = error;
+        Self {
+            code: code.as_str(),
+            message: message.source().to_owned(),
+            remediations: remediations
+                .iter()
+                .map(|remediation| match remediation {
+                    Remediation::Run { purpose, command } => RemediationV1::Run {
+                        purpose: purpose.clone(),
+                        command: command.clone(),
+                    },
+                    Remediation::RetryWithOption { purpose, option } => {
+                        RemediationV1::RetryWithOption {
+                            purpose: purpose.clone(),
+                            option: option.clone(),
+                        }
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct FailureObserver {
+    conflicts: Mutex<Vec<(NonZeroOid, Vec<PathBuf>)>>,
+    errors: Mutex<Vec<UserError>>,
+}
+
+impl OperationObserver for FailureObserver {
+    fn on_event(&self, event: &OperationEvent) {
+        match event {
+            OperationEvent::RebaseDeclinedToMerge {
+                commit_oid,
+                conflicting_paths,
+            } => {
+                self.conflicts
+                    .lock()
+                    .unwrap()
+                    .push((*commit_oid, conflicting_paths.clone()));
+            }
+            OperationEvent::UserError(error) => {
+                self.errors.lock().unwrap().push(error.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Records the changes made by a command, to print them as JSON once it
+/// finishes.
+pub struct JsonOutput {
+    command: String,
+    num_events_before: usize,
+    observer: Arc<FailureObserver>,
+    stdout_redirect: StdoutRedirect,
+}
+
+impl JsonOutput {
+    /// Start recording the changes made by the given command.
+    pub fn start(command: &str) -> eyre::Result<Self> {
+        let num_events_before = match Repo::from_current_dir() {
+            Ok(repo) => {
+                let conn = repo.get_db_conn()?;
+                EventLogDb::new(&conn)?.count_events()?
+            }
+            Err(_) => 0,
+        };
+        Ok(Self {
+            command: command.to_owned(),
+            num_events_before,
+            observer: Default::default(),
+            stdout_redirect: StdoutRedirect::new()?,
+        })
+    }
+
+    /// Get the `Effects` to run the command with, which suppress its usual
+    /// output.
+    pub fn wrap_effects(&self, effects: &Effects) -> Effects {
+        effects
+            .suppress()
+            .with_observer(Arc::<FailureObserver>::clone(&self.observer))
+    }
+
+    /// Print the changes made by the command, which exited with the given
+    /// code.
+    pub fn finish(self, effects: &Effects, exit_code: ExitCode) -> eyre::Result<()> {
+        let Self {
+            command,
+            num_events_before,
+            observer,
+            stdout_redirect,
+        } = self;
+        drop(stdout_redirect);
+        let ExitCode(exit_code) = exit_code;
+
+        let events = match Repo::from_current_dir() {
+            Ok(repo) => {
+                let conn = repo.get_db_conn()?;
+                let event_log_db = EventLogDb::new(&conn)?;
+                let mut events = event_log_db.get_events()?;
+                events.split_off(num_events_before.min(events.len()))
+            }
+            Err(_) => Vec::new(),
+        };
+
+        let mut result = CommandResultV1 {
+            command,
+            exit_code,
+            created_commits: Default::default(),
+            rewritten_commits: Default::default(),
+            hidden_commits: Default::default(),
+            unhidden_commits: Default::default(),
+            updated_branches: Default::default(),
+            conflicts: Default::default(),
+            errors: Default::default(),
+        };
+        for event in events {
+            match event {
+                Event::CommitEvent { commit_oid, .. } => {
+                    result.created_commits.push(commit_oid.to_string());
+                }
+                Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                    new_commit_oid,
+                    ..
+                } => {
+                    result.rewritten_commits.push(RewrittenCommitV1 {
+                        old_oid: old_commit_oid.to_string(),
+                        new_oid: render_oid(new_commit_oid),
+                    });
+                }
+                Event::ObsoleteEvent { commit_oid, .. } => {
+                    result.hidden_commits.push(commit_oid.to_string());
+                }
+                Event::UnobsoleteEvent { commit_oid, .. } => {
+                    result.unhidden_commits.push(commit_oid.to_string());
+                }
+                Event::RefUpdateEvent {
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    ..
+                } => {
+                    if let name @ CategorizedReferenceName::LocalBranch { .. } =
+                        CategorizedReferenceName::new(&ref_name)
+                    {
+                        result.updated_branches.push(UpdatedBranchV1 {
+                            name: name.render_suffix(),
+                            old_oid: render_oid(old_oid),
+                            new_oid: render_oid(new_oid),
+                        });
+                    }
+                }
+                Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::Zero,
+                    ..
+                }
+                | Event::CopyEvent { .. }
+                | Event::WorkingCopySnapshot { .. } => {}
+            }
+        }
+        result.conflicts = observer
+            .conflicts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(commit_oid, paths)| ConflictV1 {
+                commit_oid: commit_oid.to_string(),
+                paths: paths.clone(),
+            })
+            .collect();
+        result.errors = observer
+            .errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(UserErrorV1::from)
+            .collect();
+
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            serde_json::to_string_pretty(&result)?
+        )?;
+        Ok(())
+    }
+}
+
+fn render_oid(oid: MaybeZeroOid) -> Option<String> {
+    match oid {
+        MaybeZeroOid::NonZero(oid) => Some(oid.to_string()),
+        MaybeZeroOid::Zero => None,
+    }
+}
+
+/// Sends the output written to the stdout file descriptor to stderr instead,
+/// until dropped.
+#[cfg(unix)]
+pub struct StdoutRedirect {
+    saved_stdout: libc::c_int,
+}
+
+#[cfg(unix)]
+impl StdoutRedirect {
+    pub fn new() -> io::Result<Self> {
+        io::Write::flush(&mut io::stdout())?;
+        // SAFETY: the file descriptors are only duplicated, and `saved_stdout`
+        // is closed exactly once, when this value is dropped.
+        let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if saved_stdout < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(saved_stdout) };
+            return Err(err);
+        }
+        Ok(Self { saved_stdout })
+    }
+
+    /// Get a writer for the stdout file descriptor as it was before being
+    /// redirected.
+    pub fn original_stdout(&self) -> io::Result<Box<dyn io::Write + Send>> {
+        use std::os::unix::io::FromRawFd;
+
+        let fd = unsafe { libc::dup(self.saved_stdout) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just duplicated, so it's owned by the new file.
+        Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StdoutRedirect {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(&mut io::stdout());
+        unsafe {
+            libc::dup2(self.saved_stdout, libc::STDOUT_FILENO);
+            libc::close(self.saved_stdout);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct StdoutRedirect;
+
+#[cfg(not(unix))]
+impl StdoutRedirect {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self)
+    }
+
+    pub fn original_stdout(&self) -> io::Result<Box<dyn io::Write + Send>> {
+        Ok(Box::new(io::stdout()))
+    }
+}
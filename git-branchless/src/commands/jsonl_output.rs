@@ -0,0 +1,246 @@
+//! Stream the steps of a command as JSON objects, one per line, for
+//! `--output=jsonl`.
+//!
+//! Each line is written as soon as the step is reported to an observer, so
+//! that the progress of long-running commands like `sync` and `test` can be
+//! followed by other programs. The last line reports the exit code of the
+//! command. As with `--output=json`, the human-oriented output is suppressed,
+//! and output written directly to stdout is sent to stderr instead.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use lib::core::effects::{
+    Effects, OperationEvent, OperationIcon, OperationObserver, OperationType,
+};
+use lib::git::MaybeZeroOid;
+use lib::util::ExitCode;
+use serde::Serialize;
+use tracing::warn;
+
+use super::json_output::{StdoutRedirect, UserErrorV1};
+
+/// A step of a command. Operations are identified by the path of nested
+/// operations which they were started under, ending with the operation
+/// itself.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum StepV1 {
+    Started {
+        operation: Vec<String>,
+    },
+
+    Progress {
+        operation: Vec<String>,
+        current: usize,
+        total: usize,
+    },
+
+    Status {
+        operation: Vec<String>,
+        status: StatusV1,
+        message: String,
+    },
+
+    Finished {
+        operation: Vec<String>,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    Rewritten {
+        old_oid: String,
+
+        /// The rewritten commit, or `None` if the commit was skipped.
+        new_oid: Option<String>,
+    },
+
+    /// A rebase stopped because of a merge conflict.
+    #[serde(rename_all = "camelCase")]
+    Conflicted {
+        commit_oid: String,
+        paths: Vec<PathBuf>,
+    },
+
+    /// A rebase failed for a reason other than a merge conflict.
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        exit_code: isize,
+    },
+
+    /// A failure was reported to the user.
+    Error {
+        #[serde(flatten)]
+        error: UserErrorV1,
+    },
+
+    /// The command exited. This is always the last step.
+    #[serde(rename_all = "camelCase")]
+    Exited {
+        exit_code: isize,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum StatusV1 {
+    InProgress,
+    Success,
+    Warning,
+    Failure,
+}
+
+struct StepObserver {
+    output: Mutex<Box<dyn Write + Send>>,
+
+    /// The current and total units of work of each operation, to report the
+    /// absolute progress when it's incremented.
+    progress: Mutex<HashMap<Vec<OperationType>, (usize, usize)>>,
+}
+
+impl StepObserver {
+    fn write_step(&self, step: &StepV1) {
+        let mut output = self.output.lock().unwrap();
+        let result = serde_json::to_string(step)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(output, "{line}"))
+            .and_then(|()| output.flush());
+        if let Err(err) = result {
+            warn!(?err, "Could not write step");
+        }
+    }
+}
+
+impl OperationObserver for StepObserver {
+    fn on_event(&self, event: &OperationEvent) {
+        let step = match event {
+            OperationEvent::Started { operation_key } => StepV1::Started {
+                operation: render_operation_key(operation_key),
+            },
+            OperationEvent::Progress {
+                operation_key,
+                current,
+                total,
+            } => {
+                self.progress
+                    .lock()
+                    .unwrap()
+                    .insert(operation_key.clone(), (*current, *total));
+                StepV1::Progress {
+                    operation: render_operation_key(operation_key),
+                    current: *current,
+                    total: *total,
+                }
+            }
+            OperationEvent::ProgressIncremented {
+                operation_key,
+                increment,
+            } => {
+                let (current, total) = {
+                    let mut progress = self.progress.lock().unwrap();
+                    let (current, total) = progress.entry(operation_key.clone()).or_default();
+                    *current += increment;
+                    (*current, *total)
+                };
+                StepV1::Progress {
+                    operation: render_operation_key(operation_key),
+                    current,
+                    total,
+                }
+            }
+            OperationEvent::Status {
+                operation_key,
+                icon,
+                message,
+            } => StepV1::Status {
+                operation: render_operation_key(operation_key),
+                status: match icon {
+                    OperationIcon::InProgress => StatusV1::InProgress,
+                    OperationIcon::Success => StatusV1::Success,
+                    OperationIcon::Warning => StatusV1::Warning,
+                    OperationIcon::Failure => StatusV1::Failure,
+                },
+                message: console::strip_ansi_codes(message).into_owned(),
+            },
+            OperationEvent::Finished { operation_key } => StepV1::Finished {
+                operation: render_operation_key(operation_key),
+            },
+            OperationEvent::CommitRewritten {
+                old_commit_oid,
+                new_commit_oid,
+            } => StepV1::Rewritten {
+                old_oid: old_commit_oid.to_string(),
+                new_oid: match new_commit_oid {
+                    MaybeZeroOid::NonZero(oid) => Some(oid.to_string()),
+                    MaybeZeroOid::Zero => None,
+                },
+            },
+            OperationEvent::RebaseSucceeded => return,
+            OperationEvent::RebaseDeclinedToMerge {
+                commit_oid,
+                conflicting_paths,
+            } => StepV1::Conflicted {
+                commit_oid: commit_oid.to_string(),
+                paths: conflicting_paths.clone(),
+            },
+            OperationEvent::RebaseFailed {
+                exit_code: ExitCode(exit_code),
+            } => StepV1::Failed {
+                exit_code: *exit_code,
+            },
+            OperationEvent::UserError(error) => StepV1::Error {
+                error: UserErrorV1::from(error),
+            },
+        };
+        self.write_step(&step);
+    }
+}
+
+fn render_operation_key(operation_key: &[OperationType]) -> Vec<String> {
+    operation_key
+        .iter()
+        .map(|operation_type| console::strip_ansi_codes(&operation_type.to_string()).into_owned())
+        .collect()
+}
+
+/// Streams the steps of a command while it runs.
+pub struct JsonlOutput {
+    observer: Arc<StepObserver>,
+    stdout_redirect: StdoutRedirect,
+}
+
+impl JsonlOutput {
+    /// Start streaming the steps of the command.
+    pub fn start() -> eyre::Result<Self> {
+        let stdout_redirect = StdoutRedirect::new()?;
+        let observer = StepObserver {
+            output: Mutex::new(stdout_redirect.original_stdout()?),
+            progress: Default::default(),
+        };
+        Ok(Self {
+            observer: Arc::new(observer),
+            stdout_redirect,
+        })
+    }
+
+    /// Get the `Effects` to run the command with, which suppress its usual
+    /// output.
+    pub fn wrap_effects(&self, effects: &Effects) -> Effects {
+        effects
+            .suppress()
+            .with_observer(Arc::<StepObserver>::clone(&self.observer))
+    }
+
+    /// Report that the command exited with the given code.
+    pub fn finish(self, exit_code: ExitCode) -> eyre::Result<()> {
+        let Self {
+            observer,
+            stdout_redirect,
+        } = self;
+        let ExitCode(exit_code) = exit_code;
+        observer.write_step(&StepV1::Exited { exit_code });
+        drop(stdout_redirect);
+        Ok(())
+    }
+}
@@ -1,23 +1,48 @@
 //! Sub-commands of `git-branchless`.
 
 mod amend;
+mod archive;
 mod bug_report;
+mod completions;
+mod config;
+mod fold;
 mod hide;
+mod json_output;
+mod jsonl_output;
+mod obsmarkers;
+mod plumbing;
 mod repair;
 mod restack;
+mod send;
+mod serve;
 mod snapshot;
+mod suggest_split;
 mod sync;
 mod wrap;
 
+use std::fmt::Write;
+
+use eyre::WrapErr;
+
 use git_branchless_invoke::CommandContext;
+use lib::core::check_out::check_has_working_copy;
 use lib::core::gc;
+use lib::core::prompt::should_assume_yes;
 use lib::core::rewrite::MergeConflictRemediation;
 use lib::util::ExitCode;
 
 use git_branchless_opts::{
-    rewrite_args, Command, Opts, ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
+    rewrite_args, write_man_pages, Command, ConfigSubcommand, ObsmarkersSubcommand, Opts,
+    OutputFormat, PlumbingSubcommand, ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
 };
-use lib::git::GitRunInfo;
+use lib::git::{GitRunInfo, Repo};
+use tracing::warn;
+
+/// The machine-readable output requested with `--output`.
+enum StructuredOutput {
+    Json(json_output::JsonOutput),
+    Jsonl(jsonl_output::JsonlOutput),
+}
 
 fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
     let CommandContext {
@@ -25,10 +50,105 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
         git_run_info,
     } = ctx.clone();
     let Opts {
-        global_args: _,
+        global_args,
         command,
     } = opts;
 
+    let structured_output = match global_args.output.unwrap_or(OutputFormat::Human) {
+        OutputFormat::Human => None,
+        OutputFormat::Json => {
+            let command_name = match &command {
+                Command::Hide { .. } => "hide",
+                Command::Move { .. } => "move",
+                Command::Record(_) => "record",
+                Command::Restack { .. } => "restack",
+                Command::Sync { .. } => "sync",
+                Command::Unhide { .. } => "unhide",
+                _ => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "The --output=json option is only supported by the hide, move, record, restack, sync and unhide commands."
+                    )?;
+                    return Ok(ExitCode::USAGE);
+                }
+            };
+            Some(StructuredOutput::Json(json_output::JsonOutput::start(
+                command_name,
+            )?))
+        }
+        OutputFormat::Jsonl => {
+            match &command {
+                Command::Submit(_) | Command::Sync { .. } | Command::Test(_) => {}
+                _ => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "The --output=jsonl option is only supported by the submit, sync and test commands."
+                    )?;
+                    return Ok(ExitCode::USAGE);
+                }
+            }
+            Some(StructuredOutput::Jsonl(jsonl_output::JsonlOutput::start()?))
+        }
+    };
+    let output_effects = effects.clone();
+    let (ctx, effects) = match &structured_output {
+        Some(structured_output) => {
+            let effects = match structured_output {
+                StructuredOutput::Json(json_output) => json_output.wrap_effects(&output_effects),
+                StructuredOutput::Jsonl(jsonl_output) => jsonl_output.wrap_effects(&output_effects),
+            };
+            let ctx = CommandContext {
+                effects: effects.clone(),
+                ..ctx
+            };
+            (ctx, effects)
+        }
+        None => (ctx, effects),
+    };
+
+    match command {
+        // Hooks are necessarily installed if we're running one, and `init`
+        // reports on (and fixes) the hook installation itself. Installing
+        // man-pages doesn't involve the repository at all.
+        Command::Hook(_) | Command::Init(_) | Command::InstallManPages { .. } => {}
+        _ => {
+            // This is only a diagnostic, so it shouldn't prevent the command
+            // from running if it fails (such as due to an invalid config).
+            if let Ok(repo) = Repo::from_current_dir() {
+                if let Err(err) =
+                    git_branchless_init::check_hooks_installed(&effects, &git_run_info, &repo)
+                {
+                    warn!(?err, "Could not check whether hooks are installed");
+                }
+            }
+        }
+    }
+
+    match command {
+        Command::Amend { .. }
+        | Command::Next { .. }
+        | Command::Prev { .. }
+        | Command::Record(_)
+        | Command::Snapshot { .. }
+        | Command::Switch { .. } => {
+            let repo = Repo::from_current_dir()?;
+            if let Err(exit_code) = check_has_working_copy(&effects, &repo)? {
+                return Ok(exit_code);
+            }
+        }
+        _ => {}
+    }
+
+    // Garbage is collected automatically after running the command, except
+    // for the commands skipped by the hooks check above, and `gc` itself.
+    let gc_if_needed_git_run_info = match command {
+        Command::Hook(_)
+        | Command::Init(_)
+        | Command::InstallManPages { .. }
+        | Command::Gc { .. } => None,
+        _ => Some(git_run_info.clone()),
+    };
+
     let exit_code = match command {
         Command::Amend {
             move_options,
@@ -41,15 +161,78 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             reparent,
         )?,
 
+        Command::Archive {
+            name,
+            revsets,
+            resolve_revset_options,
+            list,
+        } => match name {
+            Some(name) if !list => archive::archive(
+                &effects,
+                &git_run_info,
+                &name,
+                revsets,
+                &resolve_revset_options,
+            )?,
+            _ => archive::list_archives(&effects)?,
+        },
+
         Command::BugReport => bug_report::bug_report(&effects, &git_run_info)?,
 
+        Command::Completions {
+            shell,
+            list_candidates: _,
+        } => match shell {
+            Some(shell) => completions::completions(&effects, shell)?,
+            None => completions::list_candidates(&effects)?,
+        },
+
+        Command::Config { subcommand } => match subcommand {
+            ConfigSubcommand::Get { key } => config::get(&effects, &key)?,
+            ConfigSubcommand::Set { key, value } => config::set(&effects, &key, &value)?,
+            ConfigSubcommand::List => config::list(&effects)?,
+        },
+
         Command::Switch { switch_options } => {
             git_branchless_navigation::switch(&effects, &git_run_info, &switch_options)?
         }
 
-        Command::Gc => {
-            gc::gc(&effects)?;
-            ExitCode(0)
+        Command::Fold {
+            from,
+            revsets,
+            resolve_revset_options,
+            force_rewrite_public_commits,
+        } => fold::fold(
+            &effects,
+            &git_run_info,
+            from,
+            revsets,
+            &resolve_revset_options,
+            force_rewrite_public_commits,
+        )?,
+
+        Command::Gc {
+            aggressive: _,
+            dry_run: _,
+            check: true,
+            repair,
+        } => gc::check(&effects, repair)?,
+
+        Command::Gc {
+            aggressive,
+            dry_run,
+            check: false,
+            repair: _,
+        } => {
+            let exit_code = gc::gc(&effects, &git_run_info, dry_run)?;
+            if !exit_code.is_success() {
+                exit_code
+            } else if aggressive {
+                let repo = Repo::from_current_dir()?;
+                gc::repack_and_prune(&effects, &git_run_info, &repo)?
+            } else {
+                ExitCode(0)
+            }
         }
 
         Command::Hook(args) => git_branchless_hook::command_main(ctx, args)?,
@@ -59,17 +242,34 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             resolve_revset_options,
             delete_branches,
             recursive,
+            no_recursive,
+            message,
+            force,
+            orphans,
         } => hide::hide(
             &effects,
             &git_run_info,
             revsets,
             &resolve_revset_options,
             delete_branches,
-            recursive,
+            match (recursive, no_recursive) {
+                (true, _) => Some(true),
+                (false, true) => Some(false),
+                (false, false) => None,
+            },
+            message,
+            force,
+            orphans,
         )?,
 
         Command::Init(args) => git_branchless_init::command_main(ctx, args)?,
 
+        Command::InstallManPages { path } => {
+            write_man_pages(&path)
+                .wrap_err_with(|| format!("Writing man-pages to: {:?}", &path))?;
+            ExitCode::SUCCESS
+        }
+
         Command::Move {
             source,
             dest,
@@ -99,6 +299,39 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             &traverse_commits_options,
         )?,
 
+        Command::Obsmarkers { subcommand } => match subcommand {
+            ObsmarkersSubcommand::Export => obsmarkers::export(&effects)?,
+            ObsmarkersSubcommand::Import { path } => obsmarkers::import(&effects, &path)?,
+        },
+
+        Command::Plumbing { subcommand } => match subcommand {
+            PlumbingSubcommand::Graph {
+                revset,
+                format,
+                resolve_revset_options,
+            } => plumbing::graph(&effects, revset, format, &resolve_revset_options)?,
+            PlumbingSubcommand::Successors {
+                revsets,
+                format,
+                resolve_revset_options,
+            } => plumbing::successors(&effects, revsets, format, &resolve_revset_options)?,
+            PlumbingSubcommand::PlanMove {
+                source,
+                dest,
+                force_rewrite_public_commits,
+                format,
+                resolve_revset_options,
+            } => plumbing::plan_move(
+                &effects,
+                &git_run_info,
+                source,
+                dest,
+                force_rewrite_public_commits,
+                format,
+                &resolve_revset_options,
+            )?,
+        },
+
         Command::Prev {
             traverse_commits_options,
         } => git_branchless_navigation::traverse_commits(
@@ -152,6 +385,35 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
             )?
         }
 
+        Command::Send {
+            revsets,
+            resolve_revset_options,
+            output_directory,
+            reroll_count,
+            subject_prefix,
+            send,
+            to,
+            cc,
+        } => send::send(
+            &effects,
+            &git_run_info,
+            revsets,
+            &resolve_revset_options,
+            output_directory,
+            reroll_count,
+            subject_prefix,
+            send,
+            to,
+            cc,
+        )?,
+
+        Command::Serve {
+            // Only JSON-RPC is supported for now, but the flag is required so
+            // that other protocols can be added later.
+            json_rpc: _,
+            socket,
+        } => serve::serve(&effects, socket)?,
+
         Command::Smartlog(args) => git_branchless_smartlog::command_main(ctx, args)?,
 
         Command::Snapshot { subcommand } => match subcommand {
@@ -163,6 +425,11 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
 
         Command::Submit(args) => git_branchless_submit::command_main(ctx, args)?,
 
+        Command::SuggestSplit {
+            revsets,
+            resolve_revset_options,
+        } => suggest_split::suggest_split(&effects, revsets, &resolve_revset_options)?,
+
         Command::Sync {
             pull,
             move_options,
@@ -179,18 +446,28 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
 
         Command::Test(args) => git_branchless_test::command_main(ctx, args)?,
 
-        Command::Undo { interactive, yes } => {
-            git_branchless_undo::undo(&effects, &git_run_info, interactive, yes)?
+        Command::Undo { interactive } => {
+            git_branchless_undo::undo(&effects, &git_run_info, interactive, should_assume_yes())?
         }
 
+        Command::Unarchive { name } => archive::unarchive(&effects, &git_run_info, &name)?,
+
         Command::Unhide {
             revsets,
             resolve_revset_options,
             recursive,
-        } => hide::unhide(&effects, revsets, &resolve_revset_options, recursive)?,
+        } => hide::unhide(
+            &effects,
+            &git_run_info,
+            revsets,
+            &resolve_revset_options,
+            recursive,
+        )?,
 
         Command::Wrap {
             git_executable: explicit_git_executable,
+            exec,
+            message,
             command: WrappedCommand::WrappedCommand(args),
         } => {
             let git_run_info = match explicit_git_executable {
@@ -200,10 +477,33 @@ fn command_main(ctx: CommandContext, opts: Opts) -> eyre::Result<ExitCode> {
                 },
                 None => git_run_info,
             };
-            wrap::wrap(&git_run_info, args.as_slice())?
+            if exec {
+                wrap::wrap_program(&git_run_info, args.as_slice(), message.as_deref())?
+            } else {
+                wrap::wrap(&git_run_info, args.as_slice(), message.as_deref())?
+            }
         }
     };
 
+    if let Some(git_run_info) = gc_if_needed_git_run_info {
+        if let Ok(repo) = Repo::from_current_dir() {
+            let repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
+            if repo.get_config_path().exists() {
+                gc::gc_if_needed(&effects, &git_run_info, &repo)?;
+            }
+        }
+    }
+
+    match structured_output {
+        None => {}
+        Some(StructuredOutput::Json(json_output)) => {
+            json_output.finish(&output_effects, exit_code)?;
+        }
+        Some(StructuredOutput::Jsonl(jsonl_output)) => {
+            jsonl_output.finish(exit_code)?;
+        }
+    }
+
     Ok(exit_code)
 }
 
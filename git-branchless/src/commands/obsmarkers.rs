@@ -0,0 +1,251 @@
+//! Export and import commit rewrites as Mercurial obsolescence markers.
+//!
+//! An obsolescence marker records that a predecessor commit was replaced by
+//! zero or more successor commits: one for an amend or rebase, several for a
+//! split, or none if the commit was pruned (hidden). The markers are read and
+//! written in the JSON format of `hg debugobsolete -Tjson`, with Git commit
+//! hashes as the node IDs.
+//!
+//! This is only an interchange format. The node IDs aren't translated to the
+//! Mercurial node IDs of the corresponding commits (e.g. via `hg-git`'s
+//! mapping), so `hg-evolve` can't consume the exported markers directly, and
+//! imported markers must already refer to Git commit hashes. Other tools
+//! which track rewrites, such as Jujutsu, store them differently and can't
+//! read these markers either.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use eyre::Context;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{
+    CommitActivityStatus, Event, EventLogDb, EventReplayer, EventTransactionId,
+};
+use lib::core::formatting::Pluralize;
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use serde::{Deserialize, Serialize};
+
+/// An obsolescence marker, as formatted by `hg debugobsolete -Tjson`.
+#[derive(Debug, Deserialize, Serialize)]
+struct ObsMarker {
+    /// The commit which was rewritten or pruned.
+    prednode: String,
+
+    /// The commits which replaced the predecessor commit, if any.
+    succnodes: Vec<String>,
+
+    /// Mercurial-specific flags, which are ignored.
+    #[serde(default)]
+    flag: u64,
+
+    /// The timestamp of the marker, and its timezone offset in seconds.
+    date: (f64, i64),
+
+    /// Additional information about the marker, such as the operation which
+    /// created it.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+/// Print the obsolescence markers for the commits which are currently
+/// obsolete.
+pub fn export(effects: &Effects) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let is_obsolete = |commit_oid: NonZeroOid| {
+        matches!(
+            event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid),
+            CommitActivityStatus::Obsolete
+        )
+    };
+
+    // A commit which was split is rewritten into several commits in the same
+    // transaction, so group the successors into a single marker.
+    let mut markers: Vec<(NonZeroOid, f64, EventTransactionId, Vec<NonZeroOid>)> = Vec::new();
+    let mut marker_indices: HashMap<(NonZeroOid, EventTransactionId), usize> = HashMap::new();
+    for event in event_log_db.get_events()? {
+        let (commit_oid, timestamp, event_tx_id, successor_oid) = match event {
+            Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } => (
+                old_commit_oid,
+                timestamp,
+                event_tx_id,
+                match new_commit_oid {
+                    MaybeZeroOid::NonZero(new_commit_oid) => Some(new_commit_oid),
+                    MaybeZeroOid::Zero => None,
+                },
+            ),
+            Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+                message: _,
+            } => (commit_oid, timestamp, event_tx_id, None),
+            _ => continue,
+        };
+        // Markers can't be retracted, so don't export those for commits which
+        // have since been restored.
+        if !is_obsolete(commit_oid) {
+            continue;
+        }
+        let index = *marker_indices
+            .entry((commit_oid, event_tx_id))
+            .or_insert_with(|| {
+                markers.push((commit_oid, timestamp, event_tx_id, Vec::new()));
+                markers.len() - 1
+            });
+        if let Some(successor_oid) = successor_oid {
+            let (_, _, _, successor_oids) = &mut markers[index];
+            if !successor_oids.contains(&successor_oid) {
+                successor_oids.push(successor_oid);
+            }
+        }
+    }
+
+    let mut operations: HashMap<EventTransactionId, String> = HashMap::new();
+    let mut obs_markers = Vec::new();
+    for (commit_oid, timestamp, event_tx_id, successor_oids) in markers {
+        let operation = match operations.get(&event_tx_id) {
+            Some(operation) => operation.clone(),
+            None => {
+                let operation = event_log_db.get_transaction_message(event_tx_id)?;
+                operations.insert(event_tx_id, operation.clone());
+                operation
+            }
+        };
+        obs_markers.push(ObsMarker {
+            prednode: commit_oid.to_string(),
+            succnodes: successor_oids
+                .into_iter()
+                .map(|successor_oid| successor_oid.to_string())
+                .collect(),
+            flag: 0,
+            date: (timestamp, 0),
+            metadata: BTreeMap::from([("operation".to_string(), operation)]),
+        });
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        serde_json::to_string_pretty(&obs_markers)?
+    )?;
+    Ok(ExitCode(0))
+}
+
+/// Mark the predecessor commits of the obsolescence markers in the given file
+/// as rewritten or hidden.
+pub fn import(effects: &Effects, path: &Path) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Reading obsolescence markers from {path:?}"))?;
+    let obs_markers: Vec<ObsMarker> = match serde_json::from_str(&contents) {
+        Ok(obs_markers) => obs_markers,
+        Err(err) => {
+            writeln!(
+                effects.get_output_stream(),
+                "Could not parse obsolescence markers from {}: {err}",
+                path.display()
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let find_commit_oid = |oid: &str| -> eyre::Result<Option<NonZeroOid>> {
+        let oid: NonZeroOid = match oid.parse() {
+            Ok(oid) => oid,
+            Err(_) => return Ok(None),
+        };
+        Ok(repo.find_commit(oid)?.map(|commit| commit.get_oid()))
+    };
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "obsmarkers import")?;
+    let mut events = Vec::new();
+    let mut num_imported = 0;
+    let mut num_skipped = 0;
+    for obs_marker in obs_markers {
+        let ObsMarker {
+            prednode,
+            succnodes,
+            flag: _,
+            date: (timestamp, _timezone_offset),
+            metadata: _,
+        } = obs_marker;
+        let commit_oid = find_commit_oid(&prednode)?;
+        let successor_oids: Option<Vec<NonZeroOid>> = succnodes
+            .iter()
+            .map(|succnode| find_commit_oid(succnode))
+            .collect::<eyre::Result<Vec<_>>>()?
+            .into_iter()
+            .collect();
+        let (commit_oid, successor_oids) = match (commit_oid, successor_oids) {
+            (Some(commit_oid), Some(successor_oids)) => (commit_oid, successor_oids),
+            _ => {
+                num_skipped += 1;
+                continue;
+            }
+        };
+        // Skip markers which have already been applied, so that importing the
+        // same markers again has no effect.
+        if let CommitActivityStatus::Obsolete =
+            event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid)
+        {
+            continue;
+        }
+
+        num_imported += 1;
+        if successor_oids.is_empty() {
+            events.push(Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+                message: None,
+            });
+        } else {
+            events.extend(
+                successor_oids
+                    .into_iter()
+                    .map(|successor_oid| Event::RewriteEvent {
+                        timestamp,
+                        event_tx_id,
+                        old_commit_oid: MaybeZeroOid::NonZero(commit_oid),
+                        new_commit_oid: MaybeZeroOid::NonZero(successor_oid),
+                    }),
+            );
+        }
+    }
+    event_log_db.add_events(events)?;
+
+    write!(
+        effects.get_output_stream(),
+        "Imported {}",
+        Pluralize {
+            determiner: None,
+            amount: num_imported,
+            unit: ("obsolescence marker", "obsolescence markers"),
+        }
+    )?;
+    if num_skipped > 0 {
+        write!(
+            effects.get_output_stream(),
+            " (skipped {num_skipped} for commits not in this repository)"
+        )?;
+    }
+    writeln!(effects.get_output_stream(), ".")?;
+    Ok(ExitCode(0))
+}
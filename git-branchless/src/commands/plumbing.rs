@@ -0,0 +1,406 @@
+//! Commands for scripts, with versioned output.
+//!
+//! The output of the other commands is meant for humans, and may change
+//! between releases. Scripts should use these commands instead, which print
+//! JSON in a format chosen with `--format`. Within a format version, fields are
+//! only ever added, so existing scripts keep working.
+
+use std::fmt::Write;
+
+use eden_dag::DagAlgorithm;
+use git_branchless_opts::{PlumbingFormat, ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use git_branchless_smartlog::{get_graph_commits, get_successor, GraphCommit, Successor};
+use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    BuildRebasePlanOptions, OidOrLabel, RebaseCommand, RebasePlanBuilder, RebasePlanPermissions,
+    RepoResource,
+};
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use tracing::instrument;
+
+/// The `json-v1` output of `plumbing graph`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphV1 {
+    /// The commits in the graph, in topological order.
+    commits: Vec<GraphCommitV1>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphCommitV1 {
+    oid: String,
+
+    /// The parents of the commit in the graph, which may be ancestors rather
+    /// than parents of the underlying commit, since uninteresting commits are
+    /// omitted.
+    parents: Vec<String>,
+
+    summary: String,
+    branches: Vec<String>,
+    is_head: bool,
+    is_main: bool,
+    is_obsolete: bool,
+}
+
+/// The `json-v1` output of `plumbing successors`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SuccessorsV1 {
+    commits: Vec<SuccessorsCommitV1>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SuccessorsCommitV1 {
+    oid: String,
+    is_obsolete: bool,
+
+    /// The latest commit that this commit was rewritten into, or `None` if it
+    /// wasn't rewritten (for example, if it was only hidden).
+    successor: Option<String>,
+
+    /// Whether this commit was deleted while rewriting other commits, such as
+    /// when its changes were already applied upstream.
+    is_deleted: bool,
+}
+
+/// The `json-v1` output of `plumbing plan-move`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanV1 {
+    /// The planned commands, or `None` if there's nothing to do.
+    plan: Option<PlanCommandsV1>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanCommandsV1 {
+    first_dest_oid: String,
+    commands: Vec<PlanCommandV1>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum PlanCommandV1 {
+    #[serde(rename_all = "camelCase")]
+    CreateLabel { label_name: String },
+
+    #[serde(rename_all = "camelCase")]
+    Reset { target: String },
+
+    #[serde(rename_all = "camelCase")]
+    Pick {
+        original_commit_oid: String,
+        commit_to_apply_oid: String,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    Merge {
+        commit_oid: String,
+        commits_to_merge: Vec<String>,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    Replace {
+        commit_oid: String,
+        replacement_commit_oid: String,
+        parents: Vec<String>,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    Skip { commit_oid: String },
+}
+
+impl PlanCommandV1 {
+    /// Convert the given command, or return `None` if it's an implementation
+    /// detail of executing the plan.
+    fn new(command: &RebaseCommand) -> Option<Self> {
+        let command = match command {
+            RebaseCommand::CreateLabel { label_name } => PlanCommandV1::CreateLabel {
+                label_name: label_name.clone(),
+            },
+            RebaseCommand::Reset { target } => PlanCommandV1::Reset {
+                target: render_target(target),
+            },
+            RebaseCommand::Pick {
+                original_commit_oid,
+                commit_to_apply_oid,
+            } => PlanCommandV1::Pick {
+                original_commit_oid: original_commit_oid.to_string(),
+                commit_to_apply_oid: commit_to_apply_oid.to_string(),
+            },
+            RebaseCommand::Merge {
+                commit_oid,
+                commits_to_merge,
+            } => PlanCommandV1::Merge {
+                commit_oid: commit_oid.to_string(),
+                commits_to_merge: commits_to_merge.iter().map(render_target).collect(),
+            },
+            RebaseCommand::Replace {
+                commit_oid,
+                replacement_commit_oid,
+                parents,
+            } => PlanCommandV1::Replace {
+                commit_oid: commit_oid.to_string(),
+                replacement_commit_oid: replacement_commit_oid.to_string(),
+                parents: parents.iter().map(render_target).collect(),
+            },
+            RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+            | RebaseCommand::SkipFixedUpCommit {
+                commit_oid,
+                dest_oid: _,
+            } => PlanCommandV1::Skip {
+                commit_oid: commit_oid.to_string(),
+            },
+            RebaseCommand::Break
+            | RebaseCommand::RegisterExtraPostRewriteHook
+            | RebaseCommand::DetectEmptyCommit { .. } => return None,
+        };
+        Some(command)
+    }
+}
+
+fn render_target(target: &OidOrLabel) -> String {
+    match target {
+        OidOrLabel::Oid(oid) => oid.to_string(),
+        OidOrLabel::Label(label) => label.clone(),
+    }
+}
+
+fn write_output(
+    effects: &Effects,
+    format: PlumbingFormat,
+    output: &impl Serialize,
+) -> eyre::Result<()> {
+    match format {
+        PlumbingFormat::JsonV1 => {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                serde_json::to_string_pretty(output)?
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the commits in the commit graph.
+#[instrument]
+pub fn graph(
+    effects: &Effects,
+    revset: Option<Revset>,
+    format: PlumbingFormat,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let revset = revset.unwrap_or_else(Revset::default_smartlog_revset);
+    let graph_commits = match get_graph_commits(effects, &repo, revset, resolve_revset_options)? {
+        Ok(graph_commits) => graph_commits,
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let graph_commits = graph_commits
+        .into_iter()
+        .map(|graph_commit| {
+            let GraphCommit {
+                oid,
+                parents,
+                summary,
+                branches,
+                is_head,
+                is_main,
+                is_obsolete,
+                num_omitted_descendants: _,
+            } = graph_commit;
+            GraphCommitV1 {
+                oid: oid.to_string(),
+                parents: parents.iter().map(|oid| oid.to_string()).collect(),
+                summary,
+                branches,
+                is_head,
+                is_main,
+                is_obsolete,
+            }
+        })
+        .collect();
+
+    write_output(
+        effects,
+        format,
+        &GraphV1 {
+            commits: graph_commits,
+        },
+    )?;
+    Ok(ExitCode(0))
+}
+
+/// Print whether the given commits are obsolete, and what they were rewritten
+/// into.
+#[instrument]
+pub fn successors(
+    effects: &Effects,
+    revsets: Vec<Revset>,
+    format: PlumbingFormat,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    let mut commits = Vec::new();
+    for commit in sorted_commit_set(&repo, &dag, &union_all(&commit_sets))? {
+        let commit_oid = commit.get_oid();
+        let Successor {
+            is_obsolete,
+            rewrite_target,
+        } = get_successor(&event_replayer, event_cursor, commit_oid);
+        let (successor, is_deleted) = match rewrite_target {
+            Some(MaybeZeroOid::NonZero(successor_oid)) => (Some(successor_oid.to_string()), false),
+            Some(MaybeZeroOid::Zero) => (None, true),
+            None => (None, false),
+        };
+        commits.push(SuccessorsCommitV1 {
+            oid: commit_oid.to_string(),
+            is_obsolete,
+            successor,
+            is_deleted,
+        });
+    }
+
+    write_output(effects, format, &SuccessorsV1 { commits })?;
+    Ok(ExitCode(0))
+}
+
+/// Print the rebase plan for moving the given subtrees onto the destination
+/// commit.
+#[instrument]
+pub fn plan_move(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    sources: Vec<Revset>,
+    dest: Revset,
+    force_rewrite_public_commits: bool,
+    format: PlumbingFormat,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let source_oids =
+        match resolve_commits(effects, &repo, &mut dag, &sources, resolve_revset_options) {
+            Ok(commit_sets) => union_all(&commit_sets),
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let dest_oid: NonZeroOid = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        std::slice::from_ref(&dest),
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => match commit_set_to_vec(&commit_sets[0])?.as_slice() {
+            [only_commit_oid] => *only_commit_oid,
+            other => {
+                let Revset(expr) = dest;
+                writeln!(
+                    effects.get_error_stream(),
+                    "Expected revset to expand to exactly 1 commit (got {}): {}",
+                    other.len(),
+                    expr,
+                )?;
+                return Ok(ExitCode(1));
+            }
+        },
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: true,
+    };
+    let commits_to_move = dag.query().descendants(source_oids.clone())?;
+    let commits_to_move = dag.filter_visible_commits(commits_to_move)?;
+    let permissions =
+        match RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commits_to_move)? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    let rebase_plan = {
+        let pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(&repo)?;
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        for source_root in commit_set_to_vec(&dag.query().roots(source_oids)?)? {
+            builder.move_subtree(source_root, vec![dest_oid])?;
+        }
+        match builder.build(effects, git_run_info, &pool, &repo_pool)? {
+            Ok(rebase_plan) => rebase_plan,
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        }
+    };
+
+    let plan = rebase_plan.map(|rebase_plan| PlanCommandsV1 {
+        first_dest_oid: rebase_plan.first_dest_oid.to_string(),
+        commands: rebase_plan
+            .commands
+            .iter()
+            .filter_map(PlanCommandV1::new)
+            .collect(),
+    });
+    write_output(effects, format, &PlanV1 { plan })?;
+    Ok(ExitCode(0))
+}
@@ -70,6 +70,7 @@ pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
             timestamp,
             event_tx_id,
             commit_oid: *commit_oid,
+            message: None,
         })
         .collect_vec();
     let num_broken_branches = broken_branches.len();
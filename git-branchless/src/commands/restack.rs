@@ -67,11 +67,13 @@ use tracing::{instrument, warn};
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::resolve_commits;
-use git_branchless_smartlog::smartlog;
-use lib::core::config::get_restack_preserve_timestamps;
+use git_branchless_smartlog::{smartlog, SmartlogOptions};
+use lib::core::child_index::ChildIndexDb;
+use lib::core::config::{get_restack_preserve_timestamps, Verbosity};
 use lib::core::dag::{commit_set_to_vec, union_all, CommitSet, Dag};
-use lib::core::effects::Effects;
+use lib::core::effects::{Effects, OperationIcon, OperationType};
 use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
 use lib::core::rewrite::{
     execute_rebase_plan, find_abandoned_children, find_rewrite_target, move_branches,
     BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
@@ -108,10 +110,17 @@ fn restack_commits(
         abandoned_child_oids: Vec<NonZeroOid>,
     }
     let rebases: Vec<RebaseInfo> = {
+        let conn = repo.get_db_conn()?;
+        let child_index_db = ChildIndexDb::new(&conn)?;
         let mut result = Vec::new();
         for original_commit_oid in commits {
-            let abandoned_children =
-                find_abandoned_children(dag, event_replayer, event_cursor, original_commit_oid)?;
+            let abandoned_children = find_abandoned_children(
+                dag,
+                &child_index_db,
+                event_replayer,
+                event_cursor,
+                original_commit_oid,
+            )?;
             if let Some((rewritten_oid, abandoned_child_oids)) = abandoned_children {
                 result.push(RebaseInfo {
                     dest_oid: rewritten_oid,
@@ -122,6 +131,15 @@ fn restack_commits(
         result
     };
 
+    let num_stacks: usize = rebases
+        .iter()
+        .map(
+            |RebaseInfo {
+                 dest_oid: _,
+                 abandoned_child_oids,
+             }| abandoned_child_oids.len(),
+        )
+        .sum();
     let rebase_plan = {
         let permissions = match RebasePlanPermissions::verify_rewrite_set(
             dag,
@@ -152,7 +170,7 @@ fn restack_commits(
                 builder.move_subtree(child_oid, vec![dest_oid])?;
             }
         }
-        match builder.build(effects, thread_pool, repo_pool)? {
+        match builder.build(effects, git_run_info, thread_pool, repo_pool)? {
             Ok(Some(rebase_plan)) => rebase_plan,
             Ok(None) => {
                 writeln!(
@@ -168,14 +186,28 @@ fn restack_commits(
         }
     };
 
-    let execute_rebase_plan_result = execute_rebase_plan(
-        effects,
-        git_run_info,
-        &repo,
-        event_log_db,
-        &rebase_plan,
-        execute_options,
-    )?;
+    let execute_rebase_plan_result = {
+        let (effects, progress) = effects.start_operation(OperationType::RestackCommits);
+        progress.notify_status(
+            OperationIcon::InProgress,
+            format!(
+                "Restacking {}",
+                Pluralize {
+                    determiner: None,
+                    amount: num_stacks,
+                    unit: ("commit stack", "commit stacks"),
+                }
+            ),
+        );
+        execute_rebase_plan(
+            &effects,
+            git_run_info,
+            &repo,
+            event_log_db,
+            &rebase_plan,
+            execute_options,
+        )?
+    };
     match execute_rebase_plan_result {
         ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
             writeln!(effects.get_output_stream(), "Finished restacking commits.")?;
@@ -184,7 +216,7 @@ fn restack_commits(
 
         ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
             failed_merge_info.describe(effects, &repo, merge_conflict_remediation)?;
-            Ok(ExitCode(1))
+            Ok(ExitCode::MERGE_CONFLICT)
         }
 
         ExecuteRebasePlanResult::Failed { exit_code } => {
@@ -364,5 +396,15 @@ pub fn restack(
         return Ok(exit_code);
     }
 
-    smartlog(effects, git_run_info, &Default::default())
+    if effects.get_verbosity() == Verbosity::Quiet {
+        return Ok(ExitCode::SUCCESS);
+    }
+    smartlog(
+        effects,
+        git_run_info,
+        &SmartlogOptions {
+            highlight_event_tx_id: Some(event_tx_id),
+            ..Default::default()
+        },
+    )
 }
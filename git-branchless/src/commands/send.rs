@@ -0,0 +1,252 @@
+//! Generate a patch series for a stack, for email-based review workflows.
+//!
+//! The patches are generated with `git format-patch`, threaded under a cover
+//! letter. Git's cover letter template has placeholders for the subject and
+//! description of the series, which are filled in from the
+//! `branch.<name>.description` setting of the branch at the top of the series
+//! (as edited with `git branch --edit-description`), or otherwise from the
+//! name of the branch or the summary of the first commit.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use bstr::ByteSlice;
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use itertools::Itertools;
+use lib::core::dag::{sorted_commit_set, union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::git::{CategorizedReferenceName, Commit, ConfigRead, GitRunInfo, GitRunOpts, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// The placeholder for the subject in Git's cover letter template.
+const SUBJECT_PLACEHOLDER: &str = "*** SUBJECT HERE ***";
+
+/// The placeholder for the description in Git's cover letter template.
+const BLURB_PLACEHOLDER: &str = "*** BLURB HERE ***";
+
+/// Generate a patch series for the given commits, and send it if requested.
+#[instrument]
+pub fn send(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+    output_directory: Option<PathBuf>,
+    reroll_count: Option<usize>,
+    subject_prefix: Option<String>,
+    send: bool,
+    to: Vec<String>,
+    cc: Vec<String>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let commits = sorted_commit_set(&repo, &dag, &union_all(&commit_sets))?;
+    let (first_commit, last_commit) = match (commits.first(), commits.last()) {
+        (Some(first_commit), Some(last_commit)) => (first_commit, last_commit),
+        _ => {
+            writeln!(effects.get_output_stream(), "There are no commits to send.")?;
+            return Ok(ExitCode(0));
+        }
+    };
+    let base_oid = match first_commit.get_only_parent_oid() {
+        Some(base_oid) => base_oid,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot send commits starting at a merge or root commit: {}",
+                effects
+                    .get_glyphs()
+                    .render(first_commit.friendly_describe(effects.get_glyphs())?)?
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    for (parent, child) in commits.iter().tuple_windows() {
+        if child.get_parent_oids() != vec![parent.get_oid()] {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot send commits which don't form a linear range: {} is not the only parent of {}",
+                effects
+                    .get_glyphs()
+                    .render(parent.friendly_describe(effects.get_glyphs())?)?,
+                effects
+                    .get_glyphs()
+                    .render(child.friendly_describe(effects.get_glyphs())?)?
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    // `git format-patch` is run from the root of the working copy, so resolve
+    // the output directory relative to the current directory instead.
+    let output_directory = match output_directory {
+        Some(output_directory) => git_run_info.working_directory.join(output_directory),
+        None => git_run_info.working_directory.clone(),
+    };
+    let mut args = vec![
+        "format-patch".to_string(),
+        "--cover-letter".to_string(),
+        "--thread".to_string(),
+        "--output-directory".to_string(),
+        output_directory.to_string_lossy().into_owned(),
+    ];
+    if let Some(reroll_count) = reroll_count {
+        args.push(format!("--reroll-count={reroll_count}"));
+    }
+    if let Some(subject_prefix) = subject_prefix {
+        args.push(format!("--subject-prefix={subject_prefix}"));
+    }
+    args.push(format!("{}..{}", base_oid, last_commit.get_oid()));
+    let result = git_run_info.run_silent(
+        &repo,
+        None,
+        &args,
+        GitRunOpts {
+            treat_git_failure_as_error: false,
+            stdin: None,
+        },
+    )?;
+    if !result.exit_code.is_success() {
+        write!(
+            effects.get_error_stream(),
+            "{}",
+            result.stderr.to_str_lossy()
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "Failed to generate patches with git format-patch."
+        )?;
+        return Ok(result.exit_code);
+    }
+    // `git format-patch` prints the paths of the files it wrote, starting with
+    // the cover letter.
+    let patch_paths = result
+        .stdout
+        .to_str_lossy()
+        .lines()
+        .map(PathBuf::from)
+        .collect_vec();
+    if let Some(cover_letter_path) = patch_paths.first() {
+        let (subject, blurb) = get_cover_letter_text(&repo, &references_snapshot, &commits)?;
+        fill_cover_letter(cover_letter_path, &subject, &blurb)?;
+    }
+    let patch_paths = patch_paths
+        .into_iter()
+        .map(
+            |patch_path| match patch_path.strip_prefix(&git_run_info.working_directory) {
+                Ok(relative_path) => relative_path.to_path_buf(),
+                Err(_) => patch_path,
+            },
+        )
+        .collect_vec();
+
+    writeln!(
+        effects.get_output_stream(),
+        "Generated {} with a cover letter:",
+        Pluralize {
+            determiner: None,
+            amount: commits.len(),
+            unit: ("patch", "patches"),
+        }
+    )?;
+    for patch_path in patch_paths.iter() {
+        writeln!(effects.get_output_stream(), "  {}", patch_path.display())?;
+    }
+
+    if send {
+        let mut args = vec!["send-email".to_string()];
+        for to in to {
+            args.push(format!("--to={to}"));
+        }
+        for cc in cc {
+            args.push(format!("--cc={cc}"));
+        }
+        args.push("--".to_string());
+        args.extend(
+            patch_paths
+                .iter()
+                .map(|patch_path| patch_path.to_string_lossy().into_owned()),
+        );
+        return git_run_info.run(effects, None, &args);
+    }
+    Ok(ExitCode(0))
+}
+
+/// Determine the subject and description of the cover letter for the given
+/// commits.
+fn get_cover_letter_text(
+    repo: &Repo,
+    references_snapshot: &RepoReferencesSnapshot,
+    commits: &[Commit],
+) -> eyre::Result<(String, String)> {
+    let branch_name = commits.last().and_then(|last_commit| {
+        references_snapshot
+            .branch_oid_to_names
+            .get(&last_commit.get_oid())
+            .and_then(|branch_names| branch_names.iter().sorted().next())
+            .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
+    });
+
+    if let Some(branch_name) = &branch_name {
+        let description: Option<String> = repo
+            .get_readonly_config()?
+            .get(format!("branch.{branch_name}.description"))?;
+        if let Some(description) = description {
+            let description = description.trim();
+            if !description.is_empty() {
+                let (subject, blurb) = description.split_once('\n').unwrap_or((description, ""));
+                return Ok((subject.trim().to_owned(), blurb.trim().to_owned()));
+            }
+        }
+    }
+
+    let subject = match branch_name {
+        Some(branch_name) => branch_name,
+        None => match commits.first() {
+            Some(first_commit) => first_commit.get_summary()?.to_str_lossy().into_owned(),
+            None => String::new(),
+        },
+    };
+    Ok((subject, String::new()))
+}
+
+/// Replace the placeholders in the cover letter generated by `git format-patch`.
+fn fill_cover_letter(path: &Path, subject: &str, blurb: &str) -> eyre::Result<()> {
+    let contents = std::fs::read_to_string(path)?;
+    let contents = contents.replacen(SUBJECT_PLACEHOLDER, subject, 1);
+    let contents = if blurb.is_empty() {
+        // Remove the placeholder along with the blank line after it.
+        contents
+            .replacen(&format!("{BLURB_PLACEHOLDER}\n\n"), "", 1)
+            .replacen(BLURB_PLACEHOLDER, "", 1)
+    } else {
+        contents.replacen(BLURB_PLACEHOLDER, blurb, 1)
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,470 @@
+//! Serve the smartlog and commands to editor integrations over a local socket.
+//!
+//! Each connection carries JSON-RPC 2.0 messages, one per line. Queries like
+//! `smartlog` are answered directly from the repository and event log, while
+//! commands which modify the repository (`hide`, `move`, and `checkout`) are
+//! run as `git-branchless` subprocesses, so that they behave exactly as they
+//! do on the command line. Clients which call `subscribe` are sent an
+//! `eventsChanged` notification whenever new events are recorded in the event
+//! log, such as when commits are made or branches are moved.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use lib::core::effects::Effects;
+use lib::util::ExitCode;
+
+/// Listen for JSON-RPC requests on the given socket until a client calls
+/// `shutdown`.
+#[cfg(unix)]
+pub fn serve(effects: &Effects, socket: Option<PathBuf>) -> eyre::Result<ExitCode> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use eyre::Context;
+    use lib::git::Repo;
+    use tracing::warn;
+
+    let repo = Repo::from_current_dir()?;
+    let repo_path = repo
+        .get_working_copy_path()
+        .unwrap_or_else(|| repo.get_path())
+        .to_path_buf();
+    let socket_path = match socket {
+        Some(socket) => socket,
+        None => repo.get_branchless_dir().join("serve.sock"),
+    };
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            writeln!(
+                effects.get_output_stream(),
+                "Another server is already listening on {}",
+                socket_path.display()
+            )?;
+            return Ok(ExitCode(1));
+        }
+        // The socket was left behind by a server which didn't shut down
+        // cleanly.
+        std::fs::remove_file(&socket_path)
+            .wrap_err_with(|| format!("Removing stale socket {socket_path:?}"))?;
+    }
+    let listener = UnixListener::bind(&socket_path)
+        .wrap_err_with(|| format!("Listening on socket {socket_path:?}"))?;
+    writeln!(
+        effects.get_output_stream(),
+        "Listening on {}",
+        socket_path.display()
+    )?;
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!(?err, "Could not accept connection");
+                continue;
+            }
+        };
+        let connection = json_rpc::Connection {
+            effects: effects.clone(),
+            repo_path: repo_path.clone(),
+            socket_path: socket_path.clone(),
+            shutdown: Arc::clone(&shutdown),
+        };
+        std::thread::spawn(move || {
+            if let Err(err) = connection.handle(stream) {
+                warn!(?err, "Connection failed");
+            }
+        });
+    }
+
+    std::fs::remove_file(&socket_path)
+        .wrap_err_with(|| format!("Removing socket {socket_path:?}"))?;
+    Ok(ExitCode(0))
+}
+
+/// Report that serving isn't supported on this platform.
+#[cfg(not(unix))]
+pub fn serve(effects: &Effects, socket: Option<PathBuf>) -> eyre::Result<ExitCode> {
+    let _ = socket;
+    writeln!(
+        effects.get_output_stream(),
+        "Serving is only supported on platforms with Unix domain sockets."
+    )?;
+    Ok(ExitCode(1))
+}
+
+#[cfg(unix)]
+mod json_rpc {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use bstr::ByteSlice;
+    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use git_branchless_smartlog::{get_graph_commits, GraphCommit};
+    use itertools::Itertools;
+    use lazy_static::lazy_static;
+    use lib::core::effects::Effects;
+    use lib::core::eventlog::EventLogDb;
+    use lib::git::{Repo, RepoError};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use tracing::warn;
+
+    /// How often the event log is checked for changes on behalf of
+    /// subscribed clients.
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+    lazy_static! {
+        /// Commands which modify the repository are run one at a time, even
+        /// if they were requested by different clients.
+        static ref MUTATION_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// A JSON-RPC error, as returned to the client.
+    struct RpcError {
+        code: i64,
+        message: String,
+    }
+
+    impl RpcError {
+        const PARSE_ERROR: i64 = -32700;
+        const INVALID_REQUEST: i64 = -32600;
+        const METHOD_NOT_FOUND: i64 = -32601;
+        const INVALID_PARAMS: i64 = -32602;
+        const INTERNAL_ERROR: i64 = -32603;
+
+        fn new(code: i64, message: impl Into<String>) -> Self {
+            RpcError {
+                code,
+                message: message.into(),
+            }
+        }
+    }
+
+    impl From<RepoError> for RpcError {
+        fn from(err: RepoError) -> Self {
+            RpcError::from(eyre::Error::from(err))
+        }
+    }
+
+    impl From<eyre::Error> for RpcError {
+        fn from(err: eyre::Error) -> Self {
+            RpcError::new(RpcError::INTERNAL_ERROR, format!("{err:#}"))
+        }
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    struct SmartlogParams {
+        revset: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct HideParams {
+        revsets: Vec<String>,
+        #[serde(default)]
+        recursive: bool,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    struct MoveParams {
+        source: Vec<String>,
+        base: Vec<String>,
+        exact: Vec<String>,
+        dest: Option<String>,
+        insert: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct CheckoutParams {
+        target: String,
+    }
+
+    /// A connection from a single client.
+    pub struct Connection {
+        pub effects: Effects,
+        pub repo_path: PathBuf,
+        pub socket_path: PathBuf,
+        pub shutdown: Arc<AtomicBool>,
+    }
+
+    impl Connection {
+        /// Answer requests from the client until it disconnects.
+        pub fn handle(self, stream: UnixStream) -> eyre::Result<()> {
+            let reader = BufReader::new(stream.try_clone()?);
+            let writer = Arc::new(Mutex::new(stream));
+            let is_connected = Arc::new(AtomicBool::new(true));
+            let mut is_subscribed = false;
+
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (id, result) = match serde_json::from_str::<Value>(&line) {
+                    Ok(request) => {
+                        let id = request.get("id").cloned();
+                        let result = match request.get("method").and_then(Value::as_str) {
+                            Some("subscribe") if !is_subscribed => {
+                                is_subscribed = true;
+                                self.subscribe(Arc::clone(&writer), Arc::clone(&is_connected))
+                                    .map(|event_id| json!({ "eventId": event_id }))
+                            }
+                            Some("subscribe") => self
+                                .get_event_id()
+                                .map(|event_id| json!({ "eventId": event_id })),
+                            Some(method) => self.dispatch(
+                                method,
+                                request.get("params").cloned().unwrap_or(Value::Null),
+                            ),
+                            None => Err(RpcError::new(
+                                RpcError::INVALID_REQUEST,
+                                "Request has no method",
+                            )),
+                        };
+                        (id, result)
+                    }
+                    Err(err) => (
+                        Some(Value::Null),
+                        Err(RpcError::new(RpcError::PARSE_ERROR, err.to_string())),
+                    ),
+                };
+
+                // Requests without an ID are notifications, which don't get a
+                // response.
+                if let Some(id) = id {
+                    let response = match result {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err(RpcError { code, message }) => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": code, "message": message },
+                        }),
+                    };
+                    let mut writer = writer.lock().unwrap();
+                    writeln!(writer, "{response}")?;
+                    writer.flush()?;
+                }
+
+                if self.shutdown.load(Ordering::SeqCst) {
+                    // Wake up the listener so that it notices the shutdown.
+                    let _ = UnixStream::connect(&self.socket_path);
+                    break;
+                }
+            }
+
+            is_connected.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+            match method {
+                "smartlog" => {
+                    let SmartlogParams { revset } = parse_params(params)?;
+                    self.smartlog(revset)
+                }
+                "hide" => {
+                    let HideParams { revsets, recursive } = parse_params(params)?;
+                    let mut args = vec!["hide".to_string()];
+                    if recursive {
+                        args.push("--recursive".to_string());
+                    }
+                    // Don't interpret revsets provided by the client as
+                    // options.
+                    args.push("--".to_string());
+                    args.extend(revsets);
+                    self.run_command(&args)
+                }
+                "move" => {
+                    let MoveParams {
+                        source,
+                        base,
+                        exact,
+                        dest,
+                        insert,
+                    } = parse_params(params)?;
+                    let mut args = vec!["move".to_string()];
+                    for (flag, revsets) in
+                        [("--source", source), ("--base", base), ("--exact", exact)]
+                    {
+                        for revset in revsets {
+                            args.push(format!("{flag}={revset}"));
+                        }
+                    }
+                    if let Some(dest) = dest {
+                        args.push(format!("--dest={dest}"));
+                    }
+                    if insert {
+                        args.push("--insert".to_string());
+                    }
+                    self.run_command(&args)
+                }
+                "checkout" => {
+                    let CheckoutParams { target } = parse_params(params)?;
+                    let repo = self.open_repo()?;
+                    if target.starts_with('-') || repo.revparse_single_commit(&target)?.is_none() {
+                        return Err(RpcError::new(
+                            RpcError::INVALID_PARAMS,
+                            format!("Invalid checkout target: {target:?}"),
+                        ));
+                    }
+                    self.run_command(&["switch".to_string(), "--".to_string(), target])
+                }
+                "shutdown" => {
+                    self.shutdown.store(true, Ordering::SeqCst);
+                    Ok(Value::Null)
+                }
+                other => Err(RpcError::new(
+                    RpcError::METHOD_NOT_FOUND,
+                    format!("Unknown method: {other}"),
+                )),
+            }
+        }
+
+        fn open_repo(&self) -> eyre::Result<Repo> {
+            Ok(Repo::from_dir(&self.repo_path)?)
+        }
+
+        fn get_event_id(&self) -> Result<usize, RpcError> {
+            let repo = self.open_repo()?;
+            let conn = repo.get_db_conn()?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            Ok(event_log_db.count_events()?)
+        }
+
+        fn smartlog(&self, revset: Option<String>) -> Result<Value, RpcError> {
+            let effects = &self.effects;
+            let repo = self.open_repo()?;
+            let revset = match revset {
+                Some(revset) => Revset(revset),
+                None => Revset::default_smartlog_revset(),
+            };
+            let graph_commits = match get_graph_commits(
+                effects,
+                &repo,
+                revset,
+                &ResolveRevsetOptions::default(),
+            )? {
+                Ok(graph_commits) => graph_commits,
+                Err(err) => {
+                    return Err(RpcError::new(RpcError::INVALID_PARAMS, err.to_string()));
+                }
+            };
+
+            let nodes = graph_commits
+                .into_iter()
+                .map(|graph_commit| {
+                    let GraphCommit {
+                        oid,
+                        parents,
+                        summary,
+                        branches,
+                        is_head,
+                        is_main,
+                        is_obsolete,
+                        num_omitted_descendants,
+                    } = graph_commit;
+                    json!({
+                        "oid": oid.to_string(),
+                        "parents": parents.iter().map(|oid| oid.to_string()).collect_vec(),
+                        "summary": summary,
+                        "branches": branches,
+                        "isHead": is_head,
+                        "isMain": is_main,
+                        "isObsolete": is_obsolete,
+                        "numOmittedDescendants": num_omitted_descendants,
+                    })
+                })
+                .collect_vec();
+            let conn = repo.get_db_conn()?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            Ok(json!({
+                "eventId": event_log_db.count_events()?,
+                "nodes": nodes,
+            }))
+        }
+
+        fn run_command(&self, args: &[String]) -> Result<Value, RpcError> {
+            let _lock = MUTATION_LOCK.lock().unwrap();
+            let output = Command::new(std::env::current_exe().map_err(eyre::Error::from)?)
+                .current_dir(&self.repo_path)
+                .args(args)
+                .stdin(Stdio::null())
+                .output()
+                .map_err(eyre::Error::from)?;
+            Ok(json!({
+                "exitCode": output.status.code().unwrap_or(1),
+                "stdout": output.stdout.to_str_lossy(),
+                "stderr": output.stderr.to_str_lossy(),
+            }))
+        }
+
+        /// Send an `eventsChanged` notification to the client whenever the
+        /// number of events in the event log changes, until it disconnects.
+        /// Returns the current event ID.
+        fn subscribe(
+            &self,
+            writer: Arc<Mutex<UnixStream>>,
+            is_connected: Arc<AtomicBool>,
+        ) -> Result<usize, RpcError> {
+            let event_id = self.get_event_id()?;
+            let mut last_event_id = event_id;
+            let repo_path = self.repo_path.clone();
+            std::thread::spawn(move || {
+                let get_event_id = || -> eyre::Result<usize> {
+                    let repo = Repo::from_dir(&repo_path)?;
+                    let conn = repo.get_db_conn()?;
+                    EventLogDb::new(&conn)?.count_events()
+                };
+                while is_connected.load(Ordering::SeqCst) {
+                    std::thread::sleep(POLL_INTERVAL);
+                    let event_id = match get_event_id() {
+                        Ok(event_id) => event_id,
+                        Err(err) => {
+                            warn!(?err, "Could not read event log");
+                            continue;
+                        }
+                    };
+                    if event_id == last_event_id {
+                        continue;
+                    }
+                    last_event_id = event_id;
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": "eventsChanged",
+                        "params": { "eventId": event_id },
+                    });
+                    let mut writer = writer.lock().unwrap();
+                    if writeln!(writer, "{notification}").is_err() || writer.flush().is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok(event_id)
+        }
+    }
+
+    fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+        // Omitted parameters are treated like an empty object, so that methods
+        // whose parameters are all optional can be called without any.
+        let params = match params {
+            Value::Null => json!({}),
+            params => params,
+        };
+        serde_json::from_value(params)
+            .map_err(|err| RpcError::new(RpcError::INVALID_PARAMS, err.to_string()))
+    }
+}
@@ -14,7 +14,7 @@ use lib::git::{GitRunInfo, GitRunResult, NonZeroOid, Repo, WorkingCopySnapshot};
 use lib::util::ExitCode;
 
 pub fn create(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "snapshot create")?;
@@ -57,7 +57,7 @@ pub fn restore(
     git_run_info: &GitRunInfo,
     snapshot_oid: NonZeroOid,
 ) -> eyre::Result<ExitCode> {
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "snapshot restore")?;
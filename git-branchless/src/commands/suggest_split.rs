@@ -0,0 +1,130 @@
+//! Suggest how to split commits along the ownership areas assigned by the
+//! repository's `CODEOWNERS` file.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use itertools::Itertools;
+use lib::core::codeowners::{CodeOwners, CODEOWNERS_PATHS};
+use lib::core::dag::{sorted_commit_set, union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// Suggest how to split the given commits so that each resulting commit only
+/// touches a single ownership area.
+#[instrument]
+pub fn suggest_split(
+    effects: &Effects,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let commits = sorted_commit_set(&repo, &dag, &union_all(&commit_sets))?;
+
+    let glyphs = effects.get_glyphs();
+    let mut found_code_owners = false;
+    for commit in commits {
+        // Use the ownership areas as of the commit itself, since a stack may
+        // change the `CODEOWNERS` file.
+        let code_owners = match CodeOwners::from_tree(&repo, &commit.get_tree()?)? {
+            Some(code_owners) => code_owners,
+            None => continue,
+        };
+        found_code_owners = true;
+
+        let changed_paths = repo
+            .get_paths_touched_by_commit(&commit)?
+            .unwrap_or_default();
+        let mut areas: BTreeMap<&[String], Vec<PathBuf>> = BTreeMap::new();
+        for path in changed_paths.iter().sorted() {
+            areas
+                .entry(code_owners.get_owners(path))
+                .or_default()
+                .push(path.clone());
+        }
+
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            glyphs.render(commit.friendly_describe(glyphs)?)?
+        )?;
+        match areas.len() {
+            0 => writeln!(effects.get_output_stream(), "  Doesn't touch any files")?,
+            1 => {
+                let owners = areas.keys().next().unwrap();
+                writeln!(
+                    effects.get_output_stream(),
+                    "  Touches 1 ownership area: {}",
+                    render_owners(owners)
+                )?;
+            }
+            num_areas => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "  Touches {}, so it could be split into:",
+                    Pluralize {
+                        determiner: None,
+                        amount: num_areas,
+                        unit: ("ownership area", "ownership areas"),
+                    }
+                )?;
+                for (i, (owners, paths)) in areas.iter().enumerate() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "    {}. {}: {}",
+                        i + 1,
+                        render_owners(owners),
+                        paths.iter().map(|path| path.display()).join(", ")
+                    )?;
+                }
+            }
+        }
+    }
+
+    if !found_code_owners {
+        writeln!(
+            effects.get_output_stream(),
+            "No CODEOWNERS file was found. It can be at any of these paths: {}",
+            CODEOWNERS_PATHS.join(", ")
+        )?;
+        return Ok(ExitCode(1));
+    }
+    Ok(ExitCode(0))
+}
+
+fn render_owners(owners: &[String]) -> String {
+    if owners.is_empty() {
+        "(no owners)".to_string()
+    } else {
+        owners.join(" ")
+    }
+}
@@ -1,31 +1,40 @@
 //! Implements the `git sync` command.
 
+use bstr::ByteSlice;
 use cursive_core::theme::BaseColor;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
 use eden_dag::DagAlgorithm;
 use eyre::Report;
 use itertools::Itertools;
-use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::gerrit::get_change_id;
+use lib::core::node_descriptors::extract_diff_number;
+use lib::core::patch_id::PatchIdDb;
 use lib::core::repo_ext::RepoExt;
 use lib::util::ExitCode;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::{check_revset_syntax, resolve_commits};
-use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::config::{
+    get_hint_enabled, get_hint_string, get_restack_preserve_timestamps,
+    print_hint_suppression_notice, Hint,
+};
 use lib::core::dag::{commit_set_to_vec, sorted_commit_set, union_all, CommitSet, Dag};
-use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::effects::{Effects, OperationIcon, OperationType};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::prompt::confirm;
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
-    ExecuteRebasePlanResult, FailedMergeInfo, RebasePlan, RebasePlanBuilder, RebasePlanPermissions,
-    RepoPool, RepoResource,
+    execute_rebase_plan, move_branches, BuildRebasePlanError, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, FailedMergeInfo, RebasePlan,
+    RebasePlanBuilder, RebasePlanPermissions, RepoPool, RepoResource,
 };
 use lib::core::task::ResourcePool;
-use lib::git::{CategorizedReferenceName, Commit, GitRunInfo, NonZeroOid, Repo};
+use lib::git::{CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
 fn get_stack_roots(dag: &Dag) -> eyre::Result<CommitSet> {
     let draft_commits = dag.query_draft_commits()?;
@@ -72,6 +81,12 @@ pub fn sync(
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
+    if detect_duplicate_commits_via_patch_id && !repo.get_shallow_commit_oids()?.is_empty() {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: this repository is a shallow clone, so commits which were applied to the main branch before the shallow boundary can't be detected"
+        )?;
+    }
     let build_options = BuildRebasePlanOptions {
         force_rewrite_public_commits,
         detect_duplicate_commits_via_patch_id,
@@ -114,7 +129,7 @@ pub fn sync(
 
     // The main branch might have changed since we synced with `master`, so read its information again.
 
-    execute_sync_plans(
+    let exit_code = execute_sync_plans(
         effects,
         git_run_info,
         &repo,
@@ -125,7 +140,53 @@ pub fn sync(
         &repo_pool,
         revsets,
         resolve_revset_options,
-    )
+    )?;
+    if !pull {
+        print_main_branch_behind_hint(effects, &repo)?;
+    }
+    Ok(exit_code)
+}
+
+/// If the main branch is behind its upstream branch, as of the last fetch,
+/// suggest `git sync --pull` to bring it up to date before syncing onto it.
+fn print_main_branch_behind_hint(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+    if !get_hint_enabled(effects, repo, Hint::SyncMainBranchBehind)? {
+        return Ok(());
+    }
+
+    let main_branch = repo.get_main_branch()?;
+    let main_branch_oid = match main_branch.get_oid()? {
+        Some(main_branch_oid) => main_branch_oid,
+        None => return Ok(()),
+    };
+    let upstream_main_branch = match main_branch.get_upstream_branch()? {
+        Some(upstream_main_branch) => upstream_main_branch,
+        None => return Ok(()),
+    };
+    let upstream_main_branch_oid = match upstream_main_branch.get_oid()? {
+        Some(upstream_main_branch_oid) => upstream_main_branch_oid,
+        None => return Ok(()),
+    };
+    let is_behind = main_branch_oid != upstream_main_branch_oid
+        && repo.find_merge_base(main_branch_oid, upstream_main_branch_oid)?
+            == Some(main_branch_oid);
+    if !is_behind {
+        return Ok(());
+    }
+
+    let hint = effects.get_glyphs().render(get_hint_string(effects))?;
+    writeln!(
+        effects.get_output_stream(),
+        "{hint}: {} is behind {}, as of the last fetch",
+        main_branch.get_name()?,
+        upstream_main_branch.get_name()?,
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "{hint}: to update it and sync your commits onto it, run: git sync --pull"
+    )?;
+    print_hint_suppression_notice(effects, Hint::SyncMainBranchBehind)?;
+    Ok(())
 }
 
 fn execute_main_branch_sync_plan(
@@ -215,12 +276,30 @@ fn execute_main_branch_sync_plan(
             "sync",
         )?;
         return Ok(ExitCode(0));
-    } else {
+    }
+
+    if !confirm(
+        effects,
+        &format!(
+            "{local_main_branch_description} has diverged from its upstream branch. Rebase {} onto it?",
+            Pluralize {
+                determiner: None,
+                amount: local_main_branch_commits.count()?,
+                unit: ("local commit", "local commits"),
+            }
+        ),
+    )? {
         writeln!(
             effects.get_output_stream(),
-            "Syncing {local_main_branch_description}"
+            "{}",
+            effects.get_messages().format("confirm-aborted", &[])
         )?;
+        return Ok(ExitCode(1));
     }
+    writeln!(
+        effects.get_output_stream(),
+        "Syncing {local_main_branch_description}"
+    )?;
 
     let build_options = BuildRebasePlanOptions {
         // Since we're syncing the main branch, by definition, any commits on it would be public, so
@@ -249,7 +328,7 @@ fn execute_main_branch_sync_plan(
         Err(_) => return Ok(ExitCode(0)),
     };
     builder.move_subtree(root_commit_oid, vec![upstream_main_branch_oid])?;
-    let rebase_plan = match builder.build(effects, thread_pool, repo_pool)? {
+    let rebase_plan = match builder.build(effects, git_run_info, thread_pool, repo_pool)? {
         Ok(rebase_plan) => rebase_plan,
         Err(err) => {
             err.describe(effects, repo)?;
@@ -307,6 +386,22 @@ fn execute_sync_plans(
     } else {
         dag.query().roots(union_all(&commit_sets))?
     };
+    let root_commit_oids = if build_options.detect_duplicate_commits_via_patch_id {
+        match hide_merged_commits(
+            effects,
+            git_run_info,
+            repo,
+            &dag,
+            event_log_db,
+            execute_options,
+            &root_commit_oids,
+        )? {
+            Ok(root_commit_oids) => root_commit_oids,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    } else {
+        root_commit_oids
+    };
 
     let root_commits = sorted_commit_set(repo, &dag, &root_commit_oids)?;
     let permissions =
@@ -344,7 +439,8 @@ fn execute_sync_plans(
                     }
 
                     builder.move_subtree(root_commit.get_oid(), vec![main_branch_oid])?;
-                    let rebase_plan = builder.build(effects, thread_pool, repo_pool)?;
+                    let rebase_plan =
+                        builder.build(effects, git_run_info, thread_pool, repo_pool)?;
                     Ok(rebase_plan.map(|rebase_plan| (root_commit_oid, rebase_plan)))
                 },
             )
@@ -371,6 +467,216 @@ fn execute_sync_plans(
     )
 }
 
+/// Get the trailers of the given commit which identify its code review, so
+/// that it can be matched with the commit which landed it, even if the commit
+/// was modified during landing.
+fn get_review_trailers(commit: &Commit) -> eyre::Result<Vec<String>> {
+    let message = commit.get_message_raw()?.to_str_lossy().into_owned();
+    let mut result = Vec::new();
+    if let Some(change_id) = get_change_id(&message) {
+        result.push(format!("Change-Id: {change_id}"));
+    }
+    if let Some(diff_number) = extract_diff_number(&message) {
+        result.push(format!("Differential Revision: {diff_number}"));
+    }
+    Ok(result)
+}
+
+/// Find the commits in the stacks rooted at `root_commit_oids` which have
+/// already landed in the main branch, hide them, and delete their branches
+/// after asking the user to confirm. Returns the roots of the remaining
+/// commits, which still need to be synced. If the user declines, nothing is
+/// hidden and all of the commits are synced as usual.
+///
+/// Commits which were landed individually are already skipped when the rebase
+/// plan is built, but when a branch is squash-merged, its commits are landed as
+/// a single commit, so the combined changes of each branch are compared
+/// against the main branch commits instead. Commits are also considered landed
+/// if a main branch commit has the same `Change-Id` or `Differential Revision`
+/// trailer.
+fn hide_merged_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    execute_options: &ExecuteRebasePlanOptions,
+    root_commit_oids: &CommitSet,
+) -> eyre::Result<Result<CommitSet, ExitCode>> {
+    let main_branch = repo.get_main_branch()?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let patch_id_db = PatchIdDb::new(&conn)?;
+
+    let stack_commits = dag
+        .query()
+        .descendants(root_commit_oids.clone())?
+        .intersection(dag.query_draft_commits()?);
+    let mut merged_commits = CommitSet::empty();
+    for root_commit in sorted_commit_set(repo, dag, root_commit_oids)? {
+        let base_commit = match root_commit.get_only_parent() {
+            Some(base_commit) => base_commit,
+            None => continue,
+        };
+        let main_commits = dag.query().only(
+            CommitSet::from(main_branch_oid),
+            CommitSet::from(base_commit.get_oid()),
+        )?;
+        if main_commits.is_empty()? {
+            continue;
+        }
+
+        let mut main_patch_ids = HashSet::new();
+        let mut main_review_trailers = HashSet::new();
+        for main_commit in sorted_commit_set(repo, dag, &main_commits)? {
+            if let Some(patch_id) = patch_id_db.get_patch_id(effects, repo, &main_commit)? {
+                main_patch_ids.insert(patch_id);
+            }
+            main_review_trailers.extend(get_review_trailers(&main_commit)?);
+        }
+
+        let root_stack_commits = dag
+            .query()
+            .descendants(CommitSet::from(root_commit.get_oid()))?
+            .intersection(&stack_commits);
+        for commit in sorted_commit_set(repo, dag, &root_stack_commits)? {
+            let commit_oid = commit.get_oid();
+            let landed_commits = dag
+                .query()
+                .ancestors(CommitSet::from(commit_oid))?
+                .intersection(&root_stack_commits);
+            let is_merged = get_review_trailers(&commit)?
+                .iter()
+                .any(|trailer| main_review_trailers.contains(trailer))
+                // A single commit would have been detected by the rebase plan
+                // anyways. Only branches are checked, since a squash-merge lands
+                // an entire branch at once.
+                || (landed_commits.count()? > 1
+                    && references_snapshot
+                        .branch_oid_to_names
+                        .contains_key(&commit_oid)
+                    && main_patch_ids.contains(&repo.get_patch_id_between_commits(
+                        effects,
+                        &base_commit,
+                        &commit,
+                    )?));
+            if is_merged {
+                merged_commits = merged_commits.union(&landed_commits);
+            }
+        }
+    }
+    if merged_commits.is_empty()? {
+        return Ok(Ok(root_commit_oids.clone()));
+    }
+
+    let ExecuteRebasePlanOptions {
+        now, event_tx_id, ..
+    } = *execute_options;
+    let merged_commits_vec = sorted_commit_set(repo, dag, &merged_commits)?;
+
+    let mut merged_branches: Vec<String> = merged_commits_vec
+        .iter()
+        .filter_map(|commit| {
+            references_snapshot
+                .branch_oid_to_names
+                .get(&commit.get_oid())
+        })
+        .flatten()
+        .map(|branch_name| CategorizedReferenceName::new(branch_name).render_suffix())
+        .collect();
+    merged_branches.sort_unstable();
+    if !merged_branches.is_empty()
+        && !confirm(
+            effects,
+            &format!(
+                "Delete {}: {}?",
+                Pluralize {
+                    determiner: None,
+                    amount: merged_branches.len(),
+                    unit: ("branch", "branches"),
+                },
+                merged_branches.join(", ")
+            ),
+        )?
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "Not hiding the merged commits."
+        )?;
+        return Ok(Ok(root_commit_oids.clone()));
+    }
+
+    // Move `HEAD` off of the commits before they're hidden, since otherwise
+    // they would remain visible.
+    let head_info = repo.get_head_info()?;
+    if let Some(head_oid) = head_info.oid {
+        if merged_commits.contains(&head_oid.into())? {
+            let exit_code = check_out_commit(
+                effects,
+                git_run_info,
+                repo,
+                event_log_db,
+                event_tx_id,
+                Some(CheckoutTarget::Reference(main_branch.get_reference_name()?)),
+                &CheckOutCommitOptions {
+                    additional_args: Default::default(),
+                    reset: false,
+                    render_smartlog: false,
+                },
+            )?;
+            if !exit_code.is_success() {
+                return Ok(Err(exit_code));
+            }
+        }
+    }
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    event_log_db.add_events(
+        merged_commits_vec
+            .iter()
+            .map(|commit| Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: commit.get_oid(),
+                message: None,
+            })
+            .collect(),
+    )?;
+    for commit in merged_commits_vec.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "Hid merged commit: {}",
+            effects
+                .get_glyphs()
+                .render(commit.friendly_describe(effects.get_glyphs())?)?
+        )?;
+    }
+
+    if !merged_branches.is_empty() {
+        let deleted_branches: HashMap<NonZeroOid, MaybeZeroOid> = merged_commits_vec
+            .iter()
+            .map(|commit| (commit.get_oid(), MaybeZeroOid::Zero))
+            .collect();
+        move_branches(effects, git_run_info, repo, event_tx_id, &deleted_branches)?;
+        writeln!(
+            effects.get_output_stream(),
+            "Deleted {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: merged_branches.len(),
+                unit: ("branch", "branches"),
+            },
+            merged_branches.join(", ")
+        )?;
+    }
+
+    let remaining_roots = dag
+        .query()
+        .roots(stack_commits.difference(&merged_commits))?;
+    Ok(Ok(remaining_roots))
+}
+
 fn execute_plans(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -393,9 +699,19 @@ fn execute_plans(
                 Some(rebase_plan) => rebase_plan,
                 None => {
                     skipped_commits.push(root_commit);
+                    progress.notify_progress_inc(1);
                     continue;
                 }
             };
+            progress.notify_status(
+                OperationIcon::InProgress,
+                format!(
+                    "Syncing commit stack: {}",
+                    effects
+                        .get_glyphs()
+                        .render(root_commit.friendly_describe(effects.get_glyphs())?)?
+                ),
+            );
 
             let result = execute_rebase_plan(
                 &effects,
@@ -435,6 +751,11 @@ fn execute_plans(
         )?;
     }
 
+    let exit_code = if failed_merge_commits.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::MERGE_CONFLICT
+    };
     for (failed_merge_commit, failed_merge_info) in failed_merge_commits {
         match failed_merge_info {
             FailedMergeInfo::Conflict {
@@ -480,5 +801,5 @@ fn execute_plans(
         )?;
     }
 
-    Ok(ExitCode(0))
+    Ok(exit_code)
 }
@@ -1,76 +1,248 @@
-//! Wrap a user-provided Git command, so that `git-branchless` can do special
-//! processing.
+//! Wrap a user-provided Git command (or another program), so that
+//! `git-branchless` can do special processing.
 
 use std::convert::TryInto;
+use std::ffi::OsStr;
+use std::path::PathBuf;
 use std::process::Command;
 use std::time::SystemTime;
 
 use eyre::Context;
 use itertools::Itertools;
 
-use lib::core::eventlog::{EventLogDb, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use lib::core::eventlog::{
+    EventLogDb, EventTransactionId, BRANCHLESS_TRANSACTION_DB_ENV_VAR,
+    BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
 use lib::git::{GitRunInfo, Repo};
 use lib::util::ExitCode;
 
-fn pass_through_git_command_inner(
+fn pass_through_command_inner(
     git_run_info: &GitRunInfo,
+    program: &OsStr,
     args: &[&str],
-    event_tx_id: Option<EventTransactionId>,
+    transaction: Option<WrapTransaction>,
 ) -> eyre::Result<ExitCode> {
     let GitRunInfo {
-        path_to_git,
+        path_to_git: _,
         working_directory,
         env,
     } = git_run_info;
-    let mut command = Command::new(path_to_git);
+    let mut command = Command::new(program);
     command.current_dir(working_directory);
     command.args(args);
     command.env_clear();
     command.envs(env.iter());
-    if let Some(event_tx_id) = event_tx_id {
+    if let Some(WrapTransaction {
+        event_tx_id,
+        db_path,
+    }) = transaction
+    {
         command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+        match db_path {
+            Some(db_path) => command.env(BRANCHLESS_TRANSACTION_DB_ENV_VAR, db_path),
+            None => command.env_remove(BRANCHLESS_TRANSACTION_DB_ENV_VAR),
+        };
+    }
+
+    // The child inherits our standard streams, so its output isn't buffered,
+    // and it can detect a terminal (to show progress bars or start a pager).
+    let mut child = command
+        .spawn()
+        .wrap_err_with(|| format!("Running command: {program:?}"))?;
+    let exit_status = {
+        let _forward_signals = signals::ForwardSignals::new(child.id());
+        child
+            .wait()
+            .wrap_err_with(|| format!("Waiting for command: {program:?}"))?
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = exit_status.signal() {
+            // Terminate with the same signal, so that the caller (such as a
+            // shell) can tell that the command was interrupted.
+            signals::raise(signal);
+            let exit_code: isize = (128 + signal).try_into()?;
+            return Ok(ExitCode(exit_code));
+        }
     }
-    let exit_status = command.status().wrap_err("Running Git command")?;
+
     let exit_code: isize = exit_status.code().unwrap_or(1).try_into()?;
     let exit_code = ExitCode(exit_code);
     Ok(exit_code)
 }
 
-fn pass_through_git_command<S: AsRef<str> + std::fmt::Debug>(
+#[cfg(unix)]
+mod signals {
+    use std::convert::TryInto;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// The process ID of the running child, or 0 if there is none.
+    static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+    /// Signals which are sent to the whole foreground process group when
+    /// typed at the terminal, so the child receives them as well. We ignore
+    /// them while the child is running and let it decide how to handle them.
+    const IGNORED_SIGNALS: &[libc::c_int] = &[libc::SIGINT, libc::SIGQUIT];
+
+    /// Signals which are typically sent only to our process (such as by a
+    /// process supervisor), and so have to be forwarded to the child.
+    const FORWARDED_SIGNALS: &[libc::c_int] = &[libc::SIGTERM, libc::SIGHUP];
+
+    extern "C" fn forward_signal(signal: libc::c_int) {
+        let pid = CHILD_PID.load(Ordering::SeqCst);
+        if pid > 0 {
+            // SAFETY: `kill` is async-signal-safe.
+            unsafe {
+                libc::kill(pid, signal);
+            }
+        }
+    }
+
+    /// Handles signals on behalf of the child process until dropped, at which
+    /// point the previous signal handlers are restored.
+    pub struct ForwardSignals {
+        previous_handlers: Vec<(libc::c_int, libc::sighandler_t)>,
+    }
+
+    impl ForwardSignals {
+        pub fn new(child_pid: u32) -> Self {
+            CHILD_PID.store(child_pid.try_into().unwrap_or(0), Ordering::SeqCst);
+            // `libc::signal` takes the handler as an address.
+            #[allow(clippy::as_conversions)]
+            let forward_signal = forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            let mut previous_handlers = Vec::new();
+            for (signal, handler) in IGNORED_SIGNALS
+                .iter()
+                .map(|signal| (*signal, libc::SIG_IGN))
+                .chain(
+                    FORWARDED_SIGNALS
+                        .iter()
+                        .map(|signal| (*signal, forward_signal)),
+                )
+            {
+                // SAFETY: the handlers are either `SIG_IGN` or
+                // `forward_signal`, which is async-signal-safe.
+                let previous_handler = unsafe { libc::signal(signal, handler) };
+                previous_handlers.push((signal, previous_handler));
+            }
+            Self { previous_handlers }
+        }
+    }
+
+    impl Drop for ForwardSignals {
+        fn drop(&mut self) {
+            for (signal, previous_handler) in self.previous_handlers.drain(..) {
+                // SAFETY: this restores the handler which was previously
+                // installed.
+                unsafe {
+                    libc::signal(signal, previous_handler);
+                }
+            }
+            CHILD_PID.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Terminate the current process with the given signal, using its default
+    /// disposition. Returns if the signal doesn't terminate the process.
+    pub fn raise(signal: libc::c_int) {
+        // SAFETY: resetting to the default handler and raising a signal have
+        // no memory-safety implications.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub struct ForwardSignals;
+
+    impl ForwardSignals {
+        pub fn new(_child_pid: u32) -> Self {
+            Self
+        }
+    }
+}
+
+fn pass_through_command<S: AsRef<str> + std::fmt::Debug>(
     git_run_info: &GitRunInfo,
+    program: &OsStr,
     args: &[S],
-    event_tx_id: Option<EventTransactionId>,
+    transaction: Option<WrapTransaction>,
 ) -> eyre::Result<ExitCode> {
-    pass_through_git_command_inner(
+    pass_through_command_inner(
         git_run_info,
+        program,
         args.iter().map(AsRef::as_ref).collect_vec().as_slice(),
-        event_tx_id,
+        transaction,
     )
 }
 
-fn make_event_tx_id<S: AsRef<str> + std::fmt::Debug>(
-    args: &[S],
-) -> eyre::Result<EventTransactionId> {
+/// The transaction which the wrapped command's events should be recorded in,
+/// along with the database which it belongs to.
+struct WrapTransaction {
+    event_tx_id: EventTransactionId,
+    db_path: Option<PathBuf>,
+}
+
+/// Start a transaction for the wrapped command. If this is itself running under
+/// `git branchless wrap` in the same repository, the enclosing transaction is
+/// reused, so that the events of the nested commands aren't split up.
+fn make_transaction(message: &str) -> eyre::Result<WrapTransaction> {
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = {
-        let message = args.first().map(|s| s.as_ref()).unwrap_or("wrap");
-        event_log_db.make_transaction_id(now, message)?
-    };
-    Ok(event_tx_id)
+    let event_tx_id = event_log_db.make_transaction_id(now, message)?;
+    Ok(WrapTransaction {
+        event_tx_id,
+        db_path: event_log_db.get_db_path(),
+    })
 }
 
-/// Run the provided Git command, but wrapped in an event transaction.
+/// Run the provided Git command, but wrapped in an event transaction. The
+/// transaction is labeled with `message`, or the name of the Git subcommand if
+/// not provided.
 pub fn wrap<S: AsRef<str> + std::fmt::Debug>(
     git_run_info: &GitRunInfo,
     args: &[S],
+    message: Option<&str>,
 ) -> eyre::Result<ExitCode> {
+    let message = message.unwrap_or_else(|| args.first().map(|s| s.as_ref()).unwrap_or("wrap"));
+
     // We may not be able to make an event transaction ID (such as if there is
     // no repository in the current directory). Ignore the error in that case.
-    let event_tx_id = make_event_tx_id(args).ok();
+    let transaction = make_transaction(message).ok();
+
+    let exit_code = pass_through_command(
+        git_run_info,
+        git_run_info.path_to_git.as_os_str(),
+        args,
+        transaction,
+    )?;
+    Ok(exit_code)
+}
 
-    let exit_code = pass_through_git_command(git_run_info, args, event_tx_id)?;
+/// Run the provided program (the first element of `args`) wrapped in an event
+/// transaction. Since the transaction is passed down to any `git` commands
+/// which the program runs, all of their events are grouped together, and can
+/// be undone at once. The transaction is labeled with `message`, or the name of
+/// the program if not provided.
+pub fn wrap_program<S: AsRef<str> + std::fmt::Debug>(
+    git_run_info: &GitRunInfo,
+    args: &[S],
+    message: Option<&str>,
+) -> eyre::Result<ExitCode> {
+    let (program, args) = match args.split_first() {
+        Some((program, args)) => (program.as_ref(), args),
+        None => eyre::bail!("No program to run was provided"),
+    };
+    let message = message.unwrap_or(program);
+    let transaction = make_transaction(message).ok();
+    let exit_code = pass_through_command(git_run_info, OsStr::new(program), args, transaction)?;
     Ok(exit_code)
 }
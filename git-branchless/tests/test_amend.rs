@@ -57,14 +57,21 @@ fn test_amend_with_children() -> eyre::Result<()> {
     {
         let git = git.duplicate_repo()?;
         {
-            let (stdout, _stderr) = git.branchless("amend", &[])?;
+            let (stdout, stderr) = git.branchless("amend", &[])?;
+            insta::assert_snapshot!(stderr, @r###"
+            branchless: creating working copy snapshot
+            branchless: processing 1 update: ref HEAD
+            branchless: processing 1 update: ref HEAD
+            HEAD is now at 7c5e857 create test2.txt
+            branchless: processing checkout
+            This operation would cause a merge conflict:
+            - (1 conflicting file) b51f01b create test3.txt
+            To resolve merge conflicts, run: git restack --merge
+            "###);
             insta::assert_snapshot!(stdout, @r###"
             branchless: running command: <git-executable> reset 7c5e8578f402b6b77afa143283b65fcdc9614233
             branchless: running command: <git-executable> checkout 7c5e8578f402b6b77afa143283b65fcdc9614233
             Attempting rebase in-memory...
-            This operation would cause a merge conflict:
-            - (1 conflicting file) b51f01b create test3.txt
-            To resolve merge conflicts, run: git restack --merge
             Amending without restacking descendant commits: 7ac317b create test2.txt
             Amended with 1 staged change.
             "###);
@@ -388,7 +395,14 @@ fn test_amend_head() -> eyre::Result<()> {
     // Amend should only update tracked files.
     git.write_file_txt("newfile", "some new file")?;
     {
-        let (stdout, _stderr) = git.branchless("amend", &[])?;
+        let (stdout, _stderr) = git.branchless_with_options(
+            "amend",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 4,
+                ..Default::default()
+            },
+        )?;
         insta::assert_snapshot!(stdout, @"There are no uncommitted or staged changes. Nothing to amend.
 ");
     }
@@ -0,0 +1,138 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_archive_and_unarchive() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "experiment"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("archive", &["my-experiment", "stack(experiment)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch experiment
+        Archived 2 commits as my-experiment:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        Deleted 1 branch: experiment
+        To restore them, run: git branchless unarchive my-experiment
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 98b9119 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("archive", &["--list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        my-experiment: 2 commits (experiment)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("unarchive", &["my-experiment"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: branch experiment
+        Unarchived 2 commits from my-experiment:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        Restored 1 branch: experiment
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        | |
+        | o 96d1c37 (experiment) create test2.txt
+        |
+        @ 98b9119 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("archive", &["--list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        There are no archives.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_checked_out_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("archive", &["wip"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout master
+        Archived 2 commits as wip:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        To restore them, run: git branchless unarchive wip
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "archive",
+            &["wip"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        An archive named wip already exists.
+        To restore it, run: git branchless unarchive wip
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "unarchive",
+            &["does-not-exist"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        There is no archive named does-not-exist.
+        To see the existing archives, run: git branchless archive --list
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use itertools::Itertools;
-use lib::testing::{make_git, GitRunOptions};
+use lib::testing::{make_git, Git, GitRunOptions};
 
 #[test]
 fn test_commands() -> eyre::Result<()> {
@@ -19,11 +19,12 @@ fn test_commands() -> eyre::Result<()> {
     }
 
     {
-        let (stdout, _stderr) = git.branchless("hide", &["3df4b935"])?;
+        let (stdout, _stderr) = git.branchless("hide", &["--force", "3df4b935"])?;
         insta::assert_snapshot!(stdout, @r###"
         Hid commit: 3df4b93 create test.txt
         Abandoned 1 branch: master
-        To unhide this 1 commit, run: git undo
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -31,7 +32,8 @@ fn test_commands() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("unhide", &["3df4b935"])?;
         insta::assert_snapshot!(stdout, @r###"
         Unhid commit: 3df4b93 create test.txt
-        To hide this 1 commit, run: git undo
+        hint: to hide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -130,3 +132,551 @@ fn test_sparse_checkout() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_toml_config_files() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let xdg_config_home = git.repo_path.join(".git").join("xdg-config");
+    std::fs::create_dir_all(xdg_config_home.join("git-branchless"))?;
+    std::fs::write(
+        xdg_config_home.join("git-branchless").join("config.toml"),
+        "[commitDescriptors]\nbranches = false\n",
+    )?;
+    let options = GitRunOptions {
+        env: HashMap::from([(
+            "XDG_CONFIG_HOME".to_string(),
+            xdg_config_home.to_str().unwrap().to_string(),
+        )]),
+        ..Default::default()
+    };
+
+    // The per-user config file is used when the Git config doesn't set the key.
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    // The per-repository config file takes precedence over the per-user
+    // config file.
+    std::fs::write(
+        git.repo_path
+            .join(".git")
+            .join("branchless")
+            .join("config.toml"),
+        "[commitDescriptors]\nBranches = true\n",
+    )?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    // The Git config takes precedence over both config files.
+    git.run(&["config", "branchless.commitDescriptors.branches", "false"])?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_env_var_overrides() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "develop"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    let db_path = git.repo_path.join(".git").join("custom-db.sqlite3");
+    let options = GitRunOptions {
+        env: HashMap::from([
+            (
+                "GIT_BRANCHLESS_MAIN_BRANCH".to_string(),
+                "develop".to_string(),
+            ),
+            (
+                "GIT_BRANCHLESS_DB_PATH".to_string(),
+                db_path.to_str().unwrap().to_string(),
+            ),
+        ]),
+        ..Default::default()
+    };
+    {
+        let (stdout, _stderr) = git.branchless_with_options("smartlog", &[], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |
+        O 96d1c37 (develop) create test2.txt
+        "###);
+    }
+    assert!(db_path.exists());
+
+    // The override doesn't persist to future invocations.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |
+        o 96d1c37 (develop) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "smartlog",
+            &[],
+            &GitRunOptions {
+                env: HashMap::from([("GIT_BRANCHLESS_COLOR".to_string(), "always".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        assert!(stdout.contains('\u{1b}'));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_theme_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "branchless.theme.commitOid", "bold #ff8000"])?;
+    git.run(&["config", "branchless.theme.branch", "magenta"])?;
+    git.run(&["config", "branchless.theme.glyphs", "ascii"])?;
+
+    let run_smartlog = |env: &[(&str, &str)]| -> eyre::Result<(String, String)> {
+        let mut env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        env.insert("GIT_BRANCHLESS_COLOR".to_string(), "always".to_string());
+        git.branchless_with_options(
+            "smartlog",
+            &[],
+            &GitRunOptions {
+                env,
+                ..Default::default()
+            },
+        )
+    };
+
+    {
+        let (stdout, _stderr) = run_smartlog(&[("COLORTERM", "truecolor")])?;
+        insta::assert_snapshot!(format!("{stdout:?}"), @r###"
+        ":\n\u{1b}[1m@\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[38;2;255;128;0m\u{1b}[1m62fc20d\u{1b}[0m\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[35m\u{1b}[1m(> master)\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[1mcreate test1.txt\u{1b}[0m\n"
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = run_smartlog(&[("TERM", "xterm-256color")])?;
+        insta::assert_snapshot!(format!("{stdout:?}"), @r###"
+        ":\n\u{1b}[1m@\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[38;5;214m\u{1b}[1m62fc20d\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[35m\u{1b}[1m(> master)\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[1mcreate test1.txt\u{1b}[0m\n"
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = run_smartlog(&[])?;
+        insta::assert_snapshot!(format!("{stdout:?}"), @r###"
+        ":\n\u{1b}[1m@\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[33m\u{1b}[1m62fc20d\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[35m\u{1b}[1m(> master)\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[1mcreate test1.txt\u{1b}[0m\n"
+        "###);
+    }
+
+    git.run(&["config", "branchless.theme.branch", "not-a-color"])?;
+    {
+        let (stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: Invalid value for branchless.theme.branch: unknown color or attribute: not-a-color
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_plain_output() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["config", "branchless.theme.glyphs", "unicode"])?;
+
+    let run_smartlog = |args: &[&str], env: &[(&str, &str)]| -> eyre::Result<String> {
+        let mut env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        env.insert("GIT_BRANCHLESS_COLOR".to_string(), "always".to_string());
+        let (stdout, _stderr) = git.branchless_with_options(
+            "smartlog",
+            args,
+            &GitRunOptions {
+                env,
+                ..Default::default()
+            },
+        )?;
+        Ok(stdout)
+    };
+
+    {
+        let stdout = run_smartlog(&[], &[])?;
+        assert!(stdout.contains('\u{1b}'));
+        assert!(stdout.contains('┃'));
+    }
+
+    {
+        let stdout = run_smartlog(&["--plain"], &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.core.plain", "true"])?;
+    {
+        let stdout = run_smartlog(&[], &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let stdout = run_smartlog(&[], &[("GIT_BRANCHLESS_PLAIN", "0")])?;
+        assert!(stdout.contains('\u{1b}'));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_git_dir_and_work_tree_options() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    // Run from a separate repository, so that the repository can't be
+    // discovered from the working directory.
+    let outside = Git {
+        repo_path: git.repo_path.join(".git").join("outside"),
+        ..(*git).clone()
+    };
+    std::fs::create_dir(&outside.repo_path)?;
+    outside.init_repo()?;
+
+    let git_dir = git.repo_path.join(".git");
+    {
+        let (stdout, _stderr) = outside.branchless(
+            "smartlog",
+            &[
+                "--git-dir",
+                git_dir.to_str().unwrap(),
+                "--work-tree",
+                git.repo_path.to_str().unwrap(),
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    // Relative paths are resolved against the working directory.
+    {
+        let (stdout, _stderr) =
+            outside.branchless("smartlog", &["--git-dir", "..", "--work-tree", "../.."])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_trace_and_profile_options() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, stderr) = git.branchless("smartlog", &["--trace"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+        assert!(stderr.starts_with("branchless: time spent in each span"));
+        assert!(stderr.contains("git_branchless_smartlog::smartlog"));
+        assert!(stderr.contains("branchless::core::dag::open_and_sync"));
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        assert!(!stderr.contains("time spent"));
+    }
+
+    {
+        let profile_path = git.repo_path.join("profile.json");
+        let (_stdout, stderr) =
+            git.branchless("smartlog", &["--profile", profile_path.to_str().unwrap()])?;
+        assert!(!stderr.contains("time spent"));
+        let profile = std::fs::read_to_string(&profile_path)?;
+        assert!(profile.contains("\"name\":\"smartlog\""));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_quiet_and_verbose_options() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("prev", &["-q"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["--verbose", "-x", "HEAD", "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Rebase plan: Some(
+            RebasePlan {
+                first_dest_oid: NonZeroOid(f777ecc9b0db5ed372b2615695191a8a17f79f24),
+                commands: [
+                    Reset {
+                        target: Oid(
+                            NonZeroOid(f777ecc9b0db5ed372b2615695191a8a17f79f24),
+                        ),
+                    },
+                    Pick {
+                        original_commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+                        commit_to_apply_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+                    },
+                    DetectEmptyCommit {
+                        commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+                    },
+                    Reset {
+                        target: Oid(
+                            NonZeroOid(f777ecc9b0db5ed372b2615695191a8a17f79f24),
+                        ),
+                    },
+                    Pick {
+                        original_commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+                        commit_to_apply_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+                    },
+                    DetectEmptyCommit {
+                        commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+                    },
+                    RegisterExtraPostRewriteHook,
+                ],
+            },
+        )
+        Attempting rebase in-memory...
+        [1/2] Committed as: 62fc20d create test1.txt
+        [2/2] Committed as: fe65c1f create test2.txt
+        branchless: processing 2 rewritten commits
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("move", &["-x", "HEAD", "-d", "master", "-vv"])?;
+        assert!(stdout.contains("Rebase constraints before adding descendants"));
+        assert!(stdout.contains("Rebase plan"));
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "smartlog",
+            &["--quiet", "--verbose"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("cannot be used with"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_subcommand_aliases() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    git.run(&["config", "branchless.alias.up", "next --all"])?;
+    git.run(&["config", "branchless.alias.top", "up"])?;
+    {
+        let (stdout, _stderr) = git.branchless("top", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    // Built-in subcommands can't be overridden.
+    git.run(&["config", "branchless.alias.smartlog", "prev"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.alias.loop", "loop"])?;
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "loop",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The alias loop expands to itself: loop -> loop
+        "###);
+    }
+
+    // `init` installs a `git` alias for each subcommand alias.
+    git.branchless("init", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["config", "alias.up"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless up
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_install_man_pages() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let man_dir = git.repo_path.join("man");
+    git.branchless("install-man-pages", &[man_dir.to_str().unwrap()])?;
+
+    let man1_dir = man_dir.join("man1");
+    assert!(man1_dir.join("git-branchless.1").exists());
+    assert!(man1_dir.join("git-branchless-smartlog.1").exists());
+    assert!(!man1_dir.join("git-branchless-hook.1").exists());
+
+    let page = std::fs::read_to_string(man1_dir.join("git-branchless-test-run.1"))?;
+    assert!(page.contains(".TH GIT-BRANCHLESS-TEST-RUN 1"));
+    assert!(page.contains("\\fBgit\\-branchless test run\\fR"));
+    assert!(page.contains(".SH \"SEE ALSO\"\n\\fBgit\\-branchless\\-test\\fR(1)"));
+
+    let page = std::fs::read_to_string(man1_dir.join("git-branchless-undo.1"))?;
+    assert!(page.contains("Changes to the working copy that haven\\*(Aqt been committed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_deterministic_option() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors.relativeTime",
+        "true",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--deterministic"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+
+        // The ages of the commits are shown otherwise.
+        let (relative_time_stdout, _stderr) = git.branchless("smartlog", &[])?;
+        assert_ne!(stdout, relative_time_stdout);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "smartlog",
+            &[],
+            &GitRunOptions {
+                env: [("GIT_BRANCHLESS_DETERMINISTIC", "1")]
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
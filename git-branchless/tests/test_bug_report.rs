@@ -37,7 +37,7 @@ fn test_bug_report() -> eyre::Result<()> {
 
         insta::assert_snapshot!(stdout, @r###"
         <details>
-        <summary>Show 7 hooks</summary>
+        <summary>Show 8 hooks</summary>
 
         ##### Hook `post-applypatch`
 
@@ -99,6 +99,12 @@ fn test_bug_report() -> eyre::Result<()> {
 
         ## END BRANCHLESS CONFIG
         ```
+        ##### Hook `pre-push`
+
+        ```
+        <not found>
+        <missing newline>
+        ```
         ##### Hook `reference-transaction`
 
         ```
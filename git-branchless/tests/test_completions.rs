@@ -0,0 +1,87 @@
+use std::process::Command;
+
+use lib::testing::make_git;
+
+#[test]
+fn test_completions_list_candidates() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["--list-candidates"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        foo	create test2.txt
+        master	create initial.txt
+        f777ecc	create initial.txt
+        62fc20d	create test1.txt
+        96d1c37	create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_completions_bash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let (script, _stderr) = git.branchless("completions", &["bash"])?;
+    let complete = |words: &str| -> eyre::Result<String> {
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                r#"{script}
+COMP_WORDS=({words})
+COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))
+_git_branchless
+echo "${{COMPREPLY[@]}}""#
+            ))
+            .output()?;
+        assert!(output.status.success(), "{:?}", output);
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    insta::assert_snapshot!(complete("git-branchless mo")?, @r###"
+    move
+    "###);
+    insta::assert_snapshot!(complete("git branchless test r")?, @r###"
+    run
+    "###);
+    insta::assert_snapshot!(complete("git move --in")?, @r###"
+    --in-memory --insert
+    "###);
+    insta::assert_snapshot!(complete("git-branchless smartlog --color a")?, @r###"
+    auto always
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_completions_zsh_and_fish() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["zsh"])?;
+        assert!(stdout.starts_with("#compdef git-branchless\n"));
+        assert!(stdout.contains("(-d|--dest) _git_branchless_candidates; return ;;"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["fish"])?;
+        assert!(stdout.contains(
+            "complete -c git-branchless -n '__git_branchless_at move' -s d -l dest -x -a '(__git_branchless_candidates)'"
+        ));
+    }
+
+    Ok(())
+}
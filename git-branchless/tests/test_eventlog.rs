@@ -1,7 +1,9 @@
 use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
-use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::eventlog::{CommitActivityStatus, Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
+use lib::core::rewrite::find_rewrite_target;
+use lib::git::MaybeZeroOid;
 use lib::testing::make_git;
 
 #[test]
@@ -17,7 +19,7 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
     git.commit_file("test1", 1)?;
     git.run(&["checkout", "HEAD^"])?;
     git.commit_file("test2", 2)?;
-    git.branchless("hide", &["test1"])?;
+    git.branchless("hide", &["--force", "test1"])?;
     git.run(&["branch", "-D", "test1"])?;
 
     let effects = Effects::new_suppress_for_test(Glyphs::text());
@@ -136,6 +138,7 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
                 9,
             ),
             commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+            message: None,
         },
         RefUpdateEvent {
             timestamp: 0.0,
@@ -154,3 +157,47 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_read_only_event_log() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    let amended_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn_read_only()?;
+    let event_log_db = EventLogDb::new_read_only(&conn)?;
+    assert!(!event_log_db.get_events()?.is_empty());
+
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    assert!(matches!(
+        event_replayer.get_cursor_commit_activity_status(event_cursor, test1_oid),
+        CommitActivityStatus::Obsolete
+    ));
+    assert!(matches!(
+        event_replayer.get_cursor_commit_activity_status(event_cursor, amended_oid),
+        CommitActivityStatus::Active
+    ));
+    assert_eq!(
+        find_rewrite_target(&event_replayer, event_cursor, test1_oid),
+        Some(MaybeZeroOid::NonZero(amended_oid))
+    );
+
+    // The database can't be modified through a read-only connection.
+    let event_tx_id = event_log_db.get_events()?[0].get_event_tx_id();
+    let result = event_log_db.add_events(vec![Event::ObsoleteEvent {
+        timestamp: 0.0,
+        event_tx_id,
+        commit_oid: amended_oid,
+        message: None,
+    }]);
+    assert!(result.is_err());
+
+    Ok(())
+}
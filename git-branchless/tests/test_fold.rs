@@ -0,0 +1,133 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_fold_from() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", "foo"])?;
+    git.commit_file("test5", 5)?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        | |\
+        | | o 70deb1e create test3.txt
+        | |
+        | @ d2e18e3 (> foo) create test5.txt
+        |
+        o bf0d52a create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("fold", &["--from", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/3] Committed as: a184736 create test1.txt
+        [2/3] Committed as: f421c55 create test3.txt
+        [3/3] Committed as: a22f137 create test4.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout foo
+        In-memory rebase succeeded.
+        Folded 3 commits into: a184736 create test1.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ a184736 (> foo) create test1.txt
+        |\
+        | o f421c55 create test3.txt
+        |
+        o a22f137 create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "--no-patch", "--format=%B", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test1.txt
+
+        create test2.txt
+
+        create test5.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fold_head_in_range() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("fold", &["HEAD^", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: b8cd6af create test1.txt
+        [2/2] Committed as: 85a589d create test3.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout b8cd6afd462b1dbee59edb869fa35f7e51a69abd
+        In-memory rebase succeeded.
+        Folded 2 commits into: b8cd6af create test1.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ b8cd6af create test1.txt
+        |
+        o 85a589d create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "fold",
+            &["HEAD"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        At least 2 commits are needed to fold, but 1 commit was given.
+        "###);
+    }
+
+    Ok(())
+}
@@ -2,7 +2,37 @@ use itertools::Itertools;
 use lib::core::eventlog::testing::redact_event_timestamp;
 use lib::core::eventlog::EventLogDb;
 use lib::git::GitVersion;
-use lib::testing::{make_git, GitInitOptions};
+use lib::testing::{make_git, GitInitOptions, GitRunOptions};
+
+/// Replace disk sizes (such as `1.5 KiB`) in the output, since they depend on
+/// the filesystem and compression library.
+fn redact_sizes(output: &str) -> String {
+    output
+        .lines()
+        .map(|line| {
+            let words = line.split(' ').collect_vec();
+            let mut result = Vec::new();
+            let mut i = 0;
+            while i < words.len() {
+                let number = words[i].trim_start_matches('(');
+                let unit = words
+                    .get(i + 1)
+                    .map(|word| word.trim_end_matches(')'))
+                    .unwrap_or_default();
+                if number.parse::<f64>().is_ok() && ["B", "KiB", "MiB", "GiB"].contains(&unit) {
+                    let prefix = &words[i][..words[i].len() - number.len()];
+                    let suffix = &words[i + 1][unit.len()..];
+                    result.push(format!("{prefix}<size>{suffix}"));
+                    i += 2;
+                } else {
+                    result.push(words[i].to_string());
+                    i += 1;
+                }
+            }
+            result.join(" ")
+        })
+        .join("\n")
+}
 
 #[test]
 fn test_gc() -> eyre::Result<()> {
@@ -33,7 +63,8 @@ fn test_gc() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("gc", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         branchless: collecting garbage
-        branchless: 1 dangling reference deleted
+        branchless: 1 dangling reference deleted:
+        - 62fc20d create test1.txt
         "###);
     }
 
@@ -173,6 +204,7 @@ fn test_gc_reference_transaction() -> eyre::Result<()> {
                 7,
             ),
             commit_oid: NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f),
+            message: None,
         },
         RefUpdateEvent {
             timestamp: 0.0,
@@ -289,3 +321,525 @@ fn test_gc_no_init() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_gc_auto_hide() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test4", 4)?;
+
+    // The test commits are dated 2020, so they're not stale yet as of this
+    // duration.
+    git.run(&["config", "branchless.autoHide.after", "1000w"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        "###);
+    }
+
+    git.run(&["config", "branchless.autoHide.after", "90d"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: hid 2 stale commits not touched in 90d:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        branchless: to unhide them, run: git undo
+        branchless: 0 dangling references deleted
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 98b9119 (foo) create test3.txt
+        |
+        @ 8f7aef5 create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["-y"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Unhide commit 96d1c37 create test2.txt
+           
+        2. Unhide commit 62fc20d create test1.txt
+           
+        Applied 2 inverse events.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_aggressive() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    // Disable the expiry settings which would otherwise keep the hidden commit
+    // around, so that it can be pruned immediately.
+    git.run(&["config", "gc.pruneExpire", "now"])?;
+    git.run(&["config", "gc.reflogExpire", "now"])?;
+    git.run(&["config", "gc.reflogExpireUnreachable", "now"])?;
+    git.run(&["reflog", "expire", "--all"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--aggressive"])?;
+        let stdout = redact_sizes(&stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling reference deleted:
+        - 62fc20d create test1.txt
+        branchless: running command: <git-executable> gc --quiet
+        branchless: reclaimed 3 objects (<size>)
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_none())
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_retention() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    git.run(&["config", "branchless.gc.retention", "30d"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: 1 reference to a recently-hidden commit kept (hidden less than 30d ago)
+        "###);
+    }
+    {
+        let repo = git.get_repo()?;
+        assert!(repo
+            .find_reference(
+                &"refs/namespaces/branchless/refs/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+                    .into()
+            )?
+            .is_some());
+    }
+
+    git.run(&["config", "branchless.gc.retention", "0s"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling reference deleted:
+        - 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["-r", "62fc20d2"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--dry-run"])?;
+        let stdout = redact_sizes(&stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage (dry run)
+        branchless: 2 dangling references would be deleted:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        branchless: up to <size> could then be reclaimed by Git's garbage collection
+        "###);
+    }
+    {
+        let repo = git.get_repo()?;
+        assert!(repo
+            .find_reference(
+                &"refs/namespaces/branchless/refs/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+                    .into()
+            )?
+            .is_some());
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 2 dangling references deleted:
+        - 62fc20d create test1.txt
+        - 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_if_needed() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.gc.autoEvents", "0"])?;
+    git.run(&["config", "branchless.gc.autoHiddenCommits", "2"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, stderr) = git.branchless("hide", &["62fc20d2"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 96d1c37 create test2.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+    {
+        let (stdout, stderr) = git.branchless("hide", &["96d1c37a"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: collecting garbage automatically (2 hidden commits since the last collection)
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+    {
+        let repo = git.get_repo()?;
+        assert!(repo
+            .find_reference(
+                &"refs/namespaces/branchless/refs/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+                    .into()
+            )?
+            .is_none());
+    }
+
+    // The count is reset once garbage has been collected.
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_check() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--check"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: checking consistency
+        branchless: no problems found
+        "###);
+    }
+
+    let repo = git.get_repo()?;
+    repo.find_reference(&format!("refs/namespaces/branchless/refs/keep/{test2_oid}").into())?
+        .unwrap()
+        .delete()?;
+    git.run(&["gc", "--prune=now"])?;
+    git.write_file(
+        ".git/refs/namespaces/branchless/refs/keep/1234567890123456789012345678901234567890",
+        "1234567890123456789012345678901234567890\n",
+    )?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "gc",
+            &["--check"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: checking consistency
+        branchless: 1 visible commit missing from the object database:
+        - 96d1c37a3d4363611c49f7e52186e189a04c531f
+        branchless: 1 reference not pointing to a commit:
+        - refs/namespaces/branchless/refs/keep/1234567890123456789012345678901234567890
+        branchless: to repair, run: git branchless gc --check --repair
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--check", "--repair"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: checking consistency
+        branchless: 1 visible commit missing from the object database (now hidden):
+        - 96d1c37a3d4363611c49f7e52186e189a04c531f
+        branchless: 1 reference not pointing to a commit (now deleted):
+        - refs/namespaces/branchless/refs/keep/1234567890123456789012345678901234567890
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--check"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: checking consistency
+        branchless: no problems found
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_locked() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    git.write_file(".git/branchless/gc.lock", "12345\n")?;
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "gc",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: another garbage collection is already running; skipping (if not, remove: <repo-path>/.git/branchless/gc.lock)
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    std::fs::remove_file(git.repo_path.join(".git/branchless/gc.lock"))?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling reference deleted:
+        - 62fc20d create test1.txt
+        "###);
+    }
+    assert!(!git.repo_path.join(".git/branchless/gc.lock").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_stale_state() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.gc.staleStateExpiry", "0s"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.branchless("test", &["run", "--exec", "true", "--strategy", "worktree"])?;
+    git.write_file(".git/branchless/tmp/leftover", "")?;
+    git.write_file(
+        ".git/rebase-merge/branchless_original_head_oid",
+        "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+    )?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage (dry run)
+        branchless: 0 dangling references would be deleted
+        branchless: would remove 3 stale paths from interrupted operations (not modified in 0s):
+        - <repo-path>/.git/branchless/tmp/leftover
+        - <repo-path>/.git/branchless/test/worktrees/testing-worktree-1
+        - <repo-path>/.git/rebase-merge
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: removed 3 stale paths from interrupted operations (not modified in 0s):
+        - <repo-path>/.git/branchless/tmp/leftover
+        - <repo-path>/.git/branchless/test/worktrees/testing-worktree-1
+        - <repo-path>/.git/rebase-merge
+        "###);
+    }
+    assert!(!git.repo_path.join(".git/rebase-merge").exists());
+    {
+        let (stdout, _stderr) = git.run(&["worktree", "list"])?;
+        assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_stale_state_keeps_started_rebase() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.gc.staleStateExpiry", "0s"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.write_file_txt("test1", "conflicting contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "conflicting test1"])?;
+
+    git.branchless_with_options(
+        "move",
+        &["--on-disk", "-s", &test1_oid.to_string(), "-d", "master"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    assert!(git.repo_path.join(".git/rebase-merge").exists());
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        "###);
+    }
+    assert!(git.repo_path.join(".git/rebase-merge").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_migrate_legacy_references() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    // Simulate references created by an older version.
+    for oid in [test1_oid, test2_oid] {
+        git.run(&[
+            "update-ref",
+            "-d",
+            &format!("refs/namespaces/branchless/refs/keep/{oid}"),
+        ])?;
+        git.run(&[
+            "update-ref",
+            &format!("refs/branchless/{oid}"),
+            &oid.to_string(),
+        ])?;
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["for-each-ref", "--format=%(refname)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        refs/heads/master
+        refs/namespaces/branchless/refs/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        refs/namespaces/branchless/refs/keep/96d1c37a3d4363611c49f7e52186e189a04c531f
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["config", "transfer.hideRefs"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        refs/namespaces/branchless/refs/keep/
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_write_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let commit_graph_path = git
+        .repo_path
+        .join(".git")
+        .join("objects")
+        .join("info")
+        .join("commit-graph");
+    git.branchless("gc", &[])?;
+    assert!(!commit_graph_path.exists());
+
+    git.run(&["config", "branchless.gc.writeCommitGraph", "true"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: wrote commit-graph
+        "###);
+    }
+    assert!(commit_graph_path.exists());
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use eyre::WrapErr;
-use lib::testing::{make_git, GitRunOptions};
+use lib::testing::{make_git, GitInitOptions, GitRunOptions, SyntheticHistoryOptions};
 
 #[test]
 fn test_git_is_not_a_wrapper() -> eyre::Result<()> {
@@ -24,3 +24,49 @@ Check that the Git executable is not being wrapped in a shell script.",
     }
     Ok(())
 }
+
+#[test]
+fn test_generate_synthetic_history() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        run_branchless_init: false,
+    })?;
+    let history = git.generate_synthetic_history(&SyntheticHistoryOptions {
+        num_main_commits: 4,
+        num_stacks: 2,
+        stack_depth: 2,
+        num_extra_refs: 1,
+    })?;
+    assert_eq!(history.main_oids.len(), 4);
+    assert_eq!(
+        history
+            .stack_oids
+            .iter()
+            .map(|oids| oids.len())
+            .collect::<Vec<_>>(),
+        vec![2, 2]
+    );
+
+    git.branchless("init", &[])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O 7f4c39c create main0.txt
+        |\
+        : o b5ec917 (ref0) create stack0-0.txt
+        : |
+        : o 6ed44ec (stack0) create stack0-1.txt
+        :
+        O e090b78 create main2.txt
+        |\
+        | o cda282f create stack1-0.txt
+        | |
+        | o 728f112 (stack1) create stack1-1.txt
+        |
+        @ 4d99d30 (> master) create main3.txt
+        "###);
+    }
+
+    Ok(())
+}
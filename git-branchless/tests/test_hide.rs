@@ -1,3 +1,4 @@
+use lib::testing::pty::{run_in_pty, PtyAction};
 use lib::testing::{
     make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
 };
@@ -28,7 +29,8 @@ fn test_hide_commit() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
         insta::assert_snapshot!(stdout, @r###"
         Hid commit: 62fc20d create test1.txt
-        To unhide this 1 commit, run: git undo
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -44,6 +46,43 @@ fn test_hide_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_with_message() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "hide",
+            &[&test1_oid.to_string(), "-m", "superseded by test2"],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--hidden", "all()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | x 62fc20d (manually hidden: superseded by test2) create test1.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_bad_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -75,13 +114,14 @@ fn test_hide_already_hidden_commit() -> eyre::Result<()> {
     git.detach_head()?;
     let test1_oid = git.commit_file("test1", 1)?;
 
-    git.branchless("hide", &[&test1_oid.to_string()])?;
+    git.branchless("hide", &["--force", &test1_oid.to_string()])?;
     {
-        let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
+        let (stdout, _stderr) = git.branchless("hide", &["--force", &test1_oid.to_string()])?;
         insta::assert_snapshot!(stdout, @r###"
         Hid commit: 62fc20d create test1.txt
         (It was already hidden, so this operation had no effect.)
-        To unhide this 1 commit, run: git undo
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -95,7 +135,7 @@ fn test_hide_current_commit() -> eyre::Result<()> {
     git.init_repo()?;
     git.detach_head()?;
     git.commit_file("test", 1)?;
-    git.branchless("hide", &["HEAD"])?;
+    git.branchless("hide", &["--force", "HEAD"])?;
 
     {
         let stdout = git.smartlog()?;
@@ -162,6 +202,67 @@ fn test_hide_master_commit_with_hidden_children() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_protected_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "hide",
+            &["-r", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Refusing to hide 3 protected commits:
+        - f777ecc create initial.txt (has branch: master, public commit on the main branch)
+        - 62fc20d create test1.txt (has branch: foo)
+        - 96d1c37 create test2.txt (currently checked out)
+        Retry with -f/--force to hide them anyways.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["-D", "foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        branchless: processing 1 update: branch foo
+        Deleted 1 branch: foo
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 96d1c37 create test2.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit and restore 1 branch, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["--force", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 70deb1e create test3.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_branches_always_visible() -> eyre::Result<()> {
     let git = make_git()?;
@@ -173,12 +274,13 @@ fn test_branches_always_visible() -> eyre::Result<()> {
     git.run(&["branch", "test"])?;
     git.run(&["checkout", "master"])?;
 
-    let (stdout, _stderr) = git.branchless("hide", &["test", "test^"])?;
+    let (stdout, _stderr) = git.branchless("hide", &["--force", "test", "test^"])?;
     insta::assert_snapshot!(stdout, @r###"
     Hid commit: 62fc20d create test1.txt
     Hid commit: 96d1c37 create test2.txt
     Abandoned 1 branch: test
-    To unhide these 2 commits, run: git undo
+    hint: to unhide these 2 commits, run: git undo
+    hint: disable this hint by running: git config --global branchless.hint.hideUndo false
     "###);
 
     {
@@ -219,7 +321,8 @@ fn test_hide_delete_branches() -> eyre::Result<()> {
     Hid commit: 96d1c37 create test2.txt
     branchless: processing 1 update: branch test
     Deleted 1 branch: test
-    To unhide these 2 commits and restore 1 branch, run: git undo
+    hint: to unhide these 2 commits and restore 1 branch, run: git undo
+    hint: disable this hint by running: git config --global branchless.hint.hideUndo false
     "###);
 
     {
@@ -252,6 +355,53 @@ fn test_hide_delete_branches() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_delete_branches_confirm() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "test"])?;
+    git.run(&["checkout", "master"])?;
+
+    run_in_pty(
+        &git,
+        "hide",
+        &["-D", "test"],
+        &[
+            PtyAction::WaitUntilContains("Delete 1 branch: test? [yN]"),
+            PtyAction::Write("n\r"),
+            PtyAction::WaitUntilContains("Aborted."),
+        ],
+    )?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d (test) create test1.txt
+        "###);
+    }
+
+    run_in_pty(
+        &git,
+        "hide",
+        &["--yes", "-D", "test"],
+        &[PtyAction::WaitUntilContains("Deleted 1 branch: test")],
+    )?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_delete_multiple_branches() -> eyre::Result<()> {
     let git = make_git()?;
@@ -291,7 +441,8 @@ fn test_hide_delete_multiple_branches() -> eyre::Result<()> {
     Hid commit: fe65c1f create test2.txt
     branchless: processing 2 updates: branch test-abc, branch test-def
     Deleted 2 branches: test-abc, test-def
-    To unhide these 2 commits and restore 2 branches, run: git undo
+    hint: to unhide these 2 commits and restore 2 branches, run: git undo
+    hint: disable this hint by running: git config --global branchless.hint.hideUndo false
     "###);
 
     {
@@ -313,12 +464,13 @@ fn test_hide_delete_checked_out_branch() -> eyre::Result<()> {
     git.commit_file("test1", 1)?;
     git.commit_file("test2", 2)?;
 
-    let (stdout, _stderr) = git.branchless("hide", &["--delete-branches", "test"])?;
+    let (stdout, _stderr) = git.branchless("hide", &["--delete-branches", "--force", "test"])?;
     insta::assert_snapshot!(stdout, @r###"
     Hid commit: 96d1c37 create test2.txt
     branchless: processing 1 update: branch test
     Deleted 1 branch: test
-    To unhide this 1 commit and restore 1 branch, run: git undo
+    hint: to unhide this 1 commit and restore 1 branch, run: git undo
+    hint: disable this hint by running: git config --global branchless.hint.hideUndo false
     "###);
 
     {
@@ -350,7 +502,8 @@ fn test_unhide() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         Unhid commit: 96d1c37 create test2.txt
         (It was not hidden, so this operation had no effect.)
-        To hide this 1 commit, run: git undo
+        hint: to hide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -368,7 +521,8 @@ fn test_unhide() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("unhide", &[&test2_oid.to_string()])?;
         insta::assert_snapshot!(stdout, @r###"
         Unhid commit: 96d1c37 create test2.txt
-        To hide this 1 commit, run: git undo
+        hint: to hide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -415,7 +569,8 @@ fn test_hide_recursive() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         Hid commit: 96d1c37 create test2.txt
         Hid commit: 70deb1e create test3.txt
-        To unhide these 2 commits, run: git undo
+        hint: to unhide these 2 commits, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -433,7 +588,8 @@ fn test_hide_recursive() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         Unhid commit: 96d1c37 create test2.txt
         Unhid commit: 70deb1e create test3.txt
-        To hide these 2 commits, run: git undo
+        hint: to hide these 2 commits, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
         "###);
     }
 
@@ -450,6 +606,118 @@ fn test_hide_recursive() -> eyre::Result<()> {
         "###);
     }
 
+    git.run(&["config", "branchless.hide.defaultRecursive", "true"])?;
+    {
+        let (stdout, _stderr) = git.branchless("hide", &[&test2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        Hid commit: 70deb1e create test3.txt
+        hint: to unhide these 2 commits, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+    git.branchless("unhide", &["-r", &test2_oid.to_string()])?;
+    {
+        let (stdout, _stderr) =
+            git.branchless("hide", &["--no-recursive", &test2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 70deb1e create test3.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hide_orphans() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 96d1c37 create test2.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        x 62fc20d (manually hidden) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["--orphans"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        Hid commit: 70deb1e create test3.txt
+        hint: to unhide these 2 commits, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hide_hints_disabled() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["config", "branchless.hint.hideOrphans", "false"])?;
+    git.run(&["config", "branchless.hint.hideUndo", "false"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("unhide", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unhid commit: 62fc20d create test1.txt
+        "###);
+    }
+
     Ok(())
 }
 
@@ -501,7 +769,7 @@ fn test_smartlog_show_hidden_commits() -> eyre::Result<()> {
     let test2_oid = git.commit_file("test2", 2)?;
     git.run(&["commit", "--amend", "-m", "amended test2"])?;
     let test2_oid_amended = git.get_repo()?.get_head_info()?.oid.unwrap();
-    git.branchless("hide", &["HEAD"])?;
+    git.branchless("hide", &["--force", "HEAD"])?;
     git.run(&["checkout", "HEAD^"])?;
 
     {
@@ -551,7 +819,7 @@ fn test_smartlog_show_only_branches() -> eyre::Result<()> {
 
     git.run(&["branch", "branch-2", &test2_oid.to_string()])?;
     git.run(&["branch", "branch-4", &test4_oid.to_string()])?;
-    git.branchless("hide", &[&test4_oid.to_string()])?;
+    git.branchless("hide", &["--force", &test4_oid.to_string()])?;
     git.branchless("hide", &[&test6_oid.to_string()])?;
 
     // confirm our baseline:
@@ -595,3 +863,48 @@ fn test_smartlog_show_only_branches() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hide_show_smartlog_after_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["config", "branchless.smartlog.showAfterCommand", "true"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        Warning: 1 visible child commit will appear to dangle in the smartlog:
+        - 96d1c37 create test2.txt
+        hint: to hide descendant commits as well, pass -r/--recursive, or run: git hide --orphans
+        hint: disable this hint by running: git config --global branchless.hint.hideOrphans false
+        hint: to unhide this 1 commit, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.hideUndo false
+        O f777ecc (master) create initial.txt
+        |
+        x 62fc20d (manually hidden) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "unhide",
+            &[&test1_oid.to_string()],
+            &GitRunOptions {
+                env: [("GIT_BRANCHLESS_COLOR".to_string(), "always".to_string())].into(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(format!("{stdout:?}"), @r###"
+        "Unhid commit: 62fc20d create test1.txt\n\u{1b}[34m\u{1b}[1mhint\u{1b}[0m: to hide this 1 commit, run: git undo\n\u{1b}[34m\u{1b}[1mhint\u{1b}[0m: disable this hint by running: git config --global branchless.hint.hideUndo false\n◇ \u{1b}[33mf777ecc\u{1b}[0m \u{1b}[38;5;10m(master)\u{1b}[0m create initial.txt\n┃\n\u{1b}[38;5;10m\u{1b}[1m◯\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[33m\u{1b}[1m62fc20d\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[1mcreate test1.txt\u{1b}[0m\n┃\n\u{1b}[1m●\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[33m\u{1b}[1m96d1c37\u{1b}[0m\u{1b}[1m \u{1b}[0m\u{1b}[1mcreate test2.txt\u{1b}[0m\n"
+        "###);
+    }
+
+    Ok(())
+}
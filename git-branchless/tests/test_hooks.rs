@@ -1,10 +1,14 @@
+use std::collections::HashMap;
+
 use eyre::{eyre, Context};
 use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
-use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::eventlog::{get_ref_update_spool_dir_path, Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::git::GitVersion;
-use lib::testing::make_git;
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
 use lib::util::get_sh;
 use std::process::Command;
 
@@ -39,6 +43,8 @@ fn test_abandoned_commit_message() -> eyre::Result<()> {
         branchless: processed commit: c1e22fd amend test1 again
         branchless: processing 1 rewritten commit
         branchless: This operation abandoned 1 commit and 1 branch (master)!
+        branchless: The abandoned commits are:
+        branchless:   7ffa962 create test2.txt
         branchless: Consider running one of the following:
         branchless:   - git restack: re-apply the abandoned commits/branches
         branchless:     (this is most likely what you want to do)
@@ -52,6 +58,97 @@ fn test_abandoned_commit_message() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_abandoned_commit_prompt_without_terminal() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.promptAfterAmend", "true"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        // There's no terminal to prompt in, so the commits are left as-is.
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe9 amend test1
+        branchless: processing 1 rewritten commit
+        branchless: This operation abandoned 1 commit!
+        branchless: The abandoned commits are:
+        branchless:   96d1c37 create test2.txt
+        branchless: Consider running one of the following:
+        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:     (this is most likely what you want to do)
+        branchless:   - git smartlog: assess the situation
+        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
+        branchless:   - git undo: undo the operation
+        hint: disable this hint by running: git config --global branchless.hint.restackWarnAbandoned false
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abandoned_commit_message_translated() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    let locale_dir = git.repo_path.join(".git").join("locale");
+    std::fs::create_dir_all(&locale_dir)?;
+    std::fs::write(
+        locale_dir.join("de.ftl"),
+        "\
+# Only some messages are translated; the rest fall back to English.
+abandoned-warning = Dieser Vorgang hat { $items } verwaist!
+abandoned-commits-list = Die verwaisten Commits sind:
+hint = Hinweis
+",
+    )?;
+    git.run(&["config", "branchless.core.localeDir", ".git/locale"])?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["commit", "--amend", "-m", "amend test1"],
+            &GitRunOptions {
+                env: HashMap::from([("LANG".to_string(), "de_DE.UTF-8".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe9 amend test1
+        branchless: processing 1 rewritten commit
+        branchless: Dieser Vorgang hat 1 commit verwaist!
+        branchless: Die verwaisten Commits sind:
+        branchless:   96d1c37 create test2.txt
+        branchless: Consider running one of the following:
+        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:     (this is most likely what you want to do)
+        branchless:   - git smartlog: assess the situation
+        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
+        branchless:   - git undo: undo the operation
+        Hinweis: disable this hint by running: git config --global branchless.hint.restackWarnAbandoned false
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_abandoned_branch_message() -> eyre::Result<()> {
     let git = make_git()?;
@@ -324,7 +421,7 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
         RefUpdateEvent {
             timestamp: 0.0,
             event_tx_id: EventTransactionId(
-                8,
+                7,
             ),
             ref_name: ReferenceName(
                 "HEAD",
@@ -336,7 +433,7 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
         CommitEvent {
             timestamp: 0.0,
             event_tx_id: EventTransactionId(
-                9,
+                8,
             ),
             commit_oid: NonZeroOid(91a5ccb4feefba38b0ffa4911c5c3f6c225f662e),
         },
@@ -346,6 +443,76 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cherry_pick_and_revert_recorded() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, stderr) = git.run(&["cherry-pick", &test2_oid.to_string()])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 2 updates: branch master, ref HEAD
+        branchless: processed commit: de7112a create test2.txt
+        hint: the original commit 96d1c37 create test2.txt is still visible; to hide it, run: git hide 96d1c37
+        hint: disable this hint by running: git config --global branchless.hint.hideCherryPickedCommit false
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        [master de7112a] create test2.txt
+         Date: Thu Oct 29 12:34:56 2020 -0200
+         1 file changed, 1 insertion(+)
+         create mode 100644 test2.txt
+        "###);
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        | |
+        | o 96d1c37 (cherry-picked as de7112a5) create test2.txt
+        |
+        @ de7112a (> master) create test2.txt
+        "###);
+    }
+
+    git.run(&["checkout", &test2_oid.to_string()])?;
+    {
+        let (stdout, stderr) = git.run(&["revert", "--no-edit", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 406d56c Revert "create test1.txt"
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        [detached HEAD 406d56c] Revert "create test1.txt"
+         Date: Thu Oct 29 12:34:56 2020 +0000
+         1 file changed, 1 deletion(-)
+         delete mode 100644 test1.txt
+        "###);
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d (reverted by 406d56c9) create test1.txt
+        | |
+        | o 96d1c37 (cherry-picked as de7112a5) create test2.txt
+        | |
+        | @ 406d56c Revert "create test1.txt"
+        |
+        O de7112a (master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_git_am_recorded() -> eyre::Result<()> {
     let git = make_git()?;
@@ -388,3 +555,441 @@ fn test_git_am_recorded() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_git_am_series_single_transaction() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["format-patch", "HEAD~2"])?;
+    git.run(&["reset", "--hard", "HEAD~2"])?;
+    git.run(&["branch", "-f", "master"])?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["hide", "-r", "62fc20d"])?;
+
+    git.run(&[
+        "am",
+        "0001-create-test1.txt.patch",
+        "0002-create-test2.txt.patch",
+    ])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 91c5ce6 (> master) create test2.txt
+        "###);
+    }
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    let last_event_tx_id = events.last().unwrap().get_event_tx_id();
+    let am_events: Vec<Event> = events
+        .iter()
+        .filter(|event| event.get_event_tx_id() == last_event_tx_id)
+        .map(|event| redact_event_timestamp(event.clone()))
+        .collect();
+    insta::assert_debug_snapshot!(am_events, @r###"
+    [
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            ref_name: ReferenceName(
+                "HEAD",
+            ),
+            old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            new_oid: 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a,
+            message: None,
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/master",
+            ),
+            old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            new_oid: 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a,
+            message: None,
+        },
+        CommitEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            commit_oid: NonZeroOid(047b7ad7790bd443d78ea38854cecb9d9cc7fb7a),
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            ref_name: ReferenceName(
+                "HEAD",
+            ),
+            old_oid: 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a,
+            new_oid: 91c5ce63686889388daec1120bf57bea8a744bc2,
+            message: None,
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/master",
+            ),
+            old_oid: 047b7ad7790bd443d78ea38854cecb9d9cc7fb7a,
+            new_oid: 91c5ce63686889388daec1120bf57bea8a744bc2,
+            message: None,
+        },
+        CommitEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                11,
+            ),
+            commit_oid: NonZeroOid(91c5ce63686889388daec1120bf57bea8a744bc2),
+        },
+    ]
+    "###);
+    insta::assert_snapshot!(event_log_db.get_transaction_message(last_event_tx_id)?, @r###"
+    am
+    "###);
+
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["-y"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Hide commit 91c5ce6 create test2.txt
+           
+        2. Move branch master from 91c5ce6 create test2.txt
+                                to 047b7ad create test1.txt
+        3. Check out from 91c5ce6 create test2.txt
+                       to 047b7ad create test1.txt
+        4. Hide commit 047b7ad create test1.txt
+           
+        5. Move branch master from 047b7ad create test1.txt
+                                to f777ecc create initial.txt
+        6. Check out from 047b7ad create test1.txt
+                       to f777ecc create initial.txt
+        branchless: running command: <git-executable> checkout master --detach
+        @ f777ecc (master) create initial.txt
+        Applied 6 inverse events.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reference_transaction_ignored_refs() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "branchless.core.ignoredRefs",
+        "refs/notes refs/heads/bot/*",
+    ])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["notes", "add", "-m", "note"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["branch", "bot/update-deps"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["branch", "feature"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: branch feature
+        "###);
+    }
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let ref_names: Vec<String> = get_event_replayer_events(&event_replayer)
+        .iter()
+        .filter_map(|event| match event {
+            Event::RefUpdateEvent { ref_name, .. } => Some(ref_name.as_str().to_owned()),
+            _ => None,
+        })
+        .collect();
+    insta::assert_debug_snapshot!(ref_names, @r###"
+    [
+        "refs/heads/feature",
+    ]
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_reference_transaction_spooled() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let spool_dir = get_ref_update_spool_dir_path(&repo.get_db_path());
+    let num_spooled_files = || -> eyre::Result<usize> {
+        let entries = match std::fs::read_dir(&spool_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(entries
+            .filter(|entry| match entry {
+                Ok(entry) => !entry.file_name().to_string_lossy().starts_with('.'),
+                Err(_) => true,
+            })
+            .count())
+    };
+    EventLogDb::new(&conn)?;
+    assert_eq!(num_spooled_files()?, 0);
+
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["branch", "baz"])?;
+    assert_eq!(num_spooled_files()?, 3);
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let event_log_db = EventLogDb::new(&conn)?;
+    assert_eq!(num_spooled_files()?, 0);
+
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events: Vec<Event> = get_event_replayer_events(&event_replayer)
+        .iter()
+        .rev()
+        .take(3)
+        .rev()
+        .map(|event| redact_event_timestamp(event.clone()))
+        .collect();
+    insta::assert_debug_snapshot!(events, @r###"
+    [
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/foo",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            message: None,
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                2,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/bar",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            message: None,
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                3,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/baz",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            message: None,
+        },
+    ]
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_run_hook_in_hooks_path_with_spaces() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    git.run(&["config", "core.hooksPath", ".git/my hooks"])?;
+    let hooks_dir = git.repo_path.join(".git").join("my hooks");
+    std::fs::create_dir_all(&hooks_dir)?;
+    let hook_path = hooks_dir.join("post-rewrite");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh
+echo \"Existing hook saw: $1\" >&2
+",
+    )?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    git.branchless("init", &[])?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        // The in-memory rebase invokes the `post-rewrite` hook itself, rather
+        // than Git invoking it.
+        let (_stdout, stderr) = git.branchless("move", &["-x", "HEAD", "-d", "master"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Existing hook saw: rebase
+        branchless: creating working copy snapshot
+        Previous HEAD position was 96d1c37 create test2.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at fe65c1f create test2.txt
+        branchless: processing checkout
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pre_push_obsolete_commits() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.branchless("init", &["--pre-push-hook"])?;
+
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    let old_oid = cloned_repo.commit_file("test2", 2)?;
+    cloned_repo.run(&["commit", "--amend", "-m", "amended test2"])?;
+
+    let get_branchless_lines = |stderr: String| -> String {
+        stderr
+            .lines()
+            .filter(|line| line.starts_with("branchless:") && !line.contains("processing"))
+            .map(|line| format!("{line}\n"))
+            .collect()
+    };
+
+    {
+        let (_stdout, stderr) = cloned_repo.run(&["push", "origin", "HEAD:refs/heads/foo"])?;
+        insta::assert_snapshot!(get_branchless_lines(stderr), @"");
+    }
+
+    {
+        let (_stdout, stderr) = cloned_repo.run(&[
+            "push",
+            "--force",
+            "origin",
+            &format!("{old_oid}:refs/heads/foo"),
+        ])?;
+        insta::assert_snapshot!(get_branchless_lines(stderr), @r###"
+        branchless: warning: pushing 1 commit to origin which has been rewritten or hidden locally:
+        branchless:   96d1c37 create test2.txt (rewritten as cb8137a amended test2)
+        "###);
+    }
+
+    cloned_repo.run(&["config", "branchless.prePush.obsoleteCommits", "block"])?;
+    cloned_repo.run(&["push", "--force", "origin", "HEAD:refs/heads/foo"])?;
+    {
+        let (_stdout, stderr) = cloned_repo.run_with_options(
+            &[
+                "push",
+                "--force",
+                "origin",
+                &format!("{old_oid}:refs/heads/foo"),
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(get_branchless_lines(stderr), @r###"
+        branchless: error: pushing 1 commit to origin which has been rewritten or hidden locally:
+        branchless:   96d1c37 create test2.txt (rewritten as cb8137a amended test2)
+        branchless: aborting push (branchless.prePush.obsoleteCommits is set to block); to push anyways, run: git push --no-verify
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_user_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    for key in [
+        "branchless.hooks.postRewrite",
+        "branchless.hooks.postHide",
+        "branchless.hooks.postUndo",
+    ] {
+        git.run(&["config", key, "cat >> .git/user-hook-payloads"])?;
+    }
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    let (amended_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &[amended_oid.trim()])?;
+    git.branchless("undo", &["-y"])?;
+
+    let payloads = std::fs::read_to_string(git.repo_path.join(".git").join("user-hook-payloads"))?;
+    insta::assert_snapshot!(payloads, @r###"
+    {"eventTxId":7,"hiddenCommits":[],"hook":"postRewrite","refUpdates":[],"rewrittenCommits":[{"newCommit":"ae94dc2a748bc0965c88fcf3edac2e30074ff7e2","oldCommit":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e"}],"unhiddenCommits":[]}
+    {"eventTxId":9,"hiddenCommits":["ae94dc2a748bc0965c88fcf3edac2e30074ff7e2"],"hook":"postHide","refUpdates":[],"rewrittenCommits":[],"unhiddenCommits":[]}
+    {"eventTxId":10,"hiddenCommits":[],"hook":"postUndo","refUpdates":[],"rewrittenCommits":[],"unhiddenCommits":["ae94dc2a748bc0965c88fcf3edac2e30074ff7e2"]}
+    "###);
+
+    Ok(())
+}
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use eyre::Context;
 use lib::git::GitVersion;
 use lib::testing::{
-    make_git, make_git_worktree, GitInitOptions, GitRunOptions, GitWorktreeWrapper,
+    make_git, make_git_worktree, Git, GitInitOptions, GitRunOptions, GitWorktreeWrapper,
 };
 use regex::Regex;
 
@@ -60,6 +60,113 @@ echo Hello, world
     Ok(())
 }
 
+#[test]
+fn test_hook_chained_to_existing_hook() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+
+    // Simulate a hook installed by a hook manager, which exits explicitly, so
+    // that any contents appended to it would never run.
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    std::fs::create_dir_all(&hooks_dir)?;
+    let original_hook_contents = "#!/bin/sh
+echo \"Existing hook saw: $(cat | wc -l | tr -d ' ') line(s)\" >&2
+exit 0
+";
+    let hook_path = hooks_dir.join("reference-transaction");
+    std::fs::write(&hook_path, original_hook_contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Moved existing reference-transaction hook to: <repo-path>/.git/hooks/reference-transaction.pre-branchless
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run(&["branch", "foo"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Existing hook saw: 1 line(s)
+        Existing hook saw: 1 line(s)
+        branchless: processing 1 update: branch foo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &["--uninstall"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Removing config file: <repo-path>/.git/branchless/config
+        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, pre-push, reference-transaction
+        Restored original reference-transaction hook
+        Hooks: removed 6 hooks, restored 1 hook
+        Kept event log: <repo-path>/.git/branchless/db.sqlite3
+        To delete it as well, run: git branchless init --uninstall --purge
+        Successfully uninstalled git-branchless.
+        "###);
+    }
+    assert_eq!(std::fs::read_to_string(&hook_path)?, original_hook_contents);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_chained_to_non_executable_hook() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+
+    // Git doesn't run hooks which aren't executable, so the chained hook
+    // shouldn't either.
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    std::fs::create_dir_all(&hooks_dir)?;
+    let hook_path = hooks_dir.join("reference-transaction");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh
+echo \"Existing hook ran\" >&2
+exit 1
+",
+    )?;
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o644))?;
+
+    git.branchless("init", &[])?;
+    {
+        let (_stdout, stderr) = git.run(&["branch", "foo"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: branch foo
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_alias_installed() -> eyre::Result<()> {
     let git = make_git()?;
@@ -315,7 +422,7 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
        0: branchless::core::eventlog::from_event_log_db with effects=<Output fancy=false> repo=<Git repository at: "<repo-path>/.git/"> event_log_db=<EventLogDb>
           at some/file/path.rs:123
-       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, revset: Revset("((draft() | branches() | @) % main()) | branches() | @"), resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false }
+       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, revset: Revset("((draft() | branches() | @) % main()) | branches() | @"), resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false, highlight_event_tx_id: None }
           at some/file/path.rs:123
        2: git_branchless_smartlog::command_main with ctx=CommandContext { effects: <Output fancy=false>, git_run_info: <GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> } args=SmartlogArgs { event_id: None, revset: None, reverse: false, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false } }
           at some/file/path.rs:123
@@ -348,17 +455,113 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 fn test_init_uninstall() -> eyre::Result<()> {
     let git = make_git()?;
 
-    git.init_repo()?;
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    let config_path = git.repo_path.join(".git").join("config");
+    let original_config = std::fs::read_to_string(&config_path)?;
+    git.branchless("init", &[])?;
 
     {
         let (stdout, stderr) = git.branchless("init", &["--uninstall"])?;
         insta::assert_snapshot!(stderr, @"");
         insta::assert_snapshot!(stdout, @r###"
         Removing config file: <repo-path>/.git/branchless/config
-        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, pre-push, reference-transaction
+        Hooks: removed 7 hooks
+        Kept event log: <repo-path>/.git/branchless/db.sqlite3
+        To delete it as well, run: git branchless init --uninstall --purge
+        Successfully uninstalled git-branchless.
         "###);
     }
 
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let remaining_hooks: Vec<String> = std::fs::read_dir(&hooks_dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .filter(|name: &std::io::Result<String>| match name {
+            Ok(name) => !name.ends_with(".sample"),
+            Err(_) => true,
+        })
+        .collect::<std::io::Result<_>>()?;
+    assert_eq!(remaining_hooks, Vec::<String>::new());
+
+    assert_eq!(std::fs::read_to_string(&config_path)?, original_config);
+    assert!(git
+        .repo_path
+        .join(".git")
+        .join("branchless")
+        .join("db.sqlite3")
+        .exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_init_uninstall_preserves_user_hook_contents() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let hook_path = git.repo_path.join(".git").join("hooks").join("post-commit");
+    let hook_contents = std::fs::read_to_string(&hook_path)?;
+    std::fs::write(&hook_path, format!("{hook_contents}echo 'user hook'\n"))?;
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &["--uninstall"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Removing config file: <repo-path>/.git/branchless/config
+        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, pre-push, reference-transaction
+        Removed git-branchless section from existing post-commit hook
+        Hooks: removed 6 hooks, updated 1 hook
+        Kept event log: <repo-path>/.git/branchless/db.sqlite3
+        To delete it as well, run: git branchless init --uninstall --purge
+        Successfully uninstalled git-branchless.
+        "###);
+    }
+    insta::assert_snapshot!(std::fs::read_to_string(&hook_path)?, @r###"
+    #!/bin/sh
+    echo 'user hook'
+    "###);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_init_uninstall_purge() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["for-each-ref", "refs/namespaces/branchless/"])?;
+        assert!(!stdout.is_empty());
+    }
+
+    {
+        let (stdout, stderr) = git.branchless("init", &["--uninstall", "--purge"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Removing config file: <repo-path>/.git/branchless/config
+        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, pre-push, reference-transaction
+        Hooks: removed 7 hooks
+        Deleted 2 references which kept commits alive
+        Deleted event log: <repo-path>/.git/branchless/db.sqlite3
+        Successfully uninstalled git-branchless.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["for-each-ref", "refs/namespaces/branchless/"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+    assert!(!git.repo_path.join(".git").join("branchless").exists());
+
     Ok(())
 }
 
@@ -463,6 +666,174 @@ fn test_init_explicit_main_branch_name() -> eyre::Result<()> {
     Ok(())
 }
 
+#[cfg(unix)]
+#[test]
+fn test_init_detect_main_branch_from_remote_head() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    git.run(&["branch", "bespoke"])?;
+    git.run(&["update-ref", "refs/remotes/origin/bespoke", "bespoke"])?;
+    git.run(&[
+        "symbolic-ref",
+        "refs/remotes/origin/HEAD",
+        "refs/remotes/origin/bespoke",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: bespoke
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_config_set_main_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "bespoke", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("config", &["set", "main-branch", "bespoke"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Set branchless.core.mainBranch to: bespoke
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (bespoke) create initial.txt
+        |
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "config",
+            &["set", "main-branch", "nonexistent"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Invalid value for branchless.core.mainBranch: the branch nonexistent does not exist (expected the name of a local branch)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_config_get_set_list() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("config", &["set", "autoHide.after", "90d"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Set branchless.autoHide.after to: 90d
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("config", &["get", "branchless.autohide.after"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        90d
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "config",
+            &["set", "autoHide.after", "90 days"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Invalid value for branchless.autoHide.after: invalid duration "90 days": invalid digit found in string
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "config",
+            &["set", "autoHide.after", "99999999999999999w"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Invalid value for branchless.autoHide.after: duration "99999999999999999w" is too large
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "config",
+            &["set", "test.strategy", "sideways"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Invalid value for branchless.test.strategy: expected one of: working-copy, worktree, but got: sideways
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "config",
+            &["set", "commitDescriptors.relativeTim", "true"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unknown setting: commitDescriptors.relativeTim (did you mean branchless.commitDescriptors.relativeTime?)
+        To set it anyways, run: git config branchless.commitDescriptors.relativeTim <value>
+        "###);
+    }
+
+    git.run(&["config", "branchless.navigation.autoSwitchBranch", "false"])?;
+    {
+        let (stdout, stderr) = git.branchless("config", &["list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless.commitDescriptors.relativeTime=false
+        branchless.restack.preserveTimestamps=true
+        branchless.core.mainBranch=master
+        branchless.autoHide.after=90d
+        branchless.navigation.autoswitchbranch=false
+        "###);
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: Unknown setting: branchless.navigation.autoswitchbranch (did you mean branchless.navigation.autoSwitchBranches?)
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_init_repo_default_branch() -> eyre::Result<()> {
     let git = make_git()?;
@@ -547,6 +918,7 @@ fn test_init_core_hooks_path_warning() -> eyre::Result<()> {
         Warning: the configuration value core.hooksPath was set to: my-hooks,
         which is not the expected default value of: <repo-path>/.git/hooks
         The Git hooks above may have been installed to an unexpected global location.
+        If core.hooksPath is managed by another tool, configure it to run: git branchless hook <hook-type> "$@"
         Successfully installed git-branchless.
         To uninstall, run: git branchless init --uninstall
         "###);
@@ -555,6 +927,193 @@ fn test_init_core_hooks_path_warning() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_init_select_aliases() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let list_aliases = || -> eyre::Result<String> {
+        let (stdout, _stderr) = git.run(&["config", "--list"])?;
+        Ok(stdout
+            .lines()
+            .filter(|line| line.starts_with("alias."))
+            .map(|line| format!("{line}\n"))
+            .collect())
+    };
+
+    git.branchless("init", &["--alias", "sl", "--alias", "next"])?;
+    insta::assert_snapshot!(list_aliases()?, @r###"
+    alias.next=branchless next
+    alias.sl=branchless smartlog
+    "###);
+
+    // Aliases installed by a previous `init` which are no longer selected are
+    // removed.
+    git.run(&["config", "branchless.init.aliases", "prev, sw"])?;
+    git.branchless("init", &[])?;
+    insta::assert_snapshot!(list_aliases()?, @r###"
+    alias.prev=branchless prev
+    alias.sw=branchless switch
+    "###);
+
+    git.branchless("init", &["--no-aliases"])?;
+    insta::assert_snapshot!(list_aliases()?, @"");
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "init",
+            &["--alias", "foo"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unknown alias: foo
+        The available aliases are: amend, hide, move, next, prev, query, record, restack, reword, sl, smartlog, submit, sw, sync, test, undo, unhide
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_init_sapling_aliases() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let list_aliases = || -> eyre::Result<String> {
+        let (stdout, _stderr) = git.run(&["config", "--list"])?;
+        Ok(stdout
+            .lines()
+            .filter(|line| line.starts_with("alias."))
+            .map(|line| format!("{line}\n"))
+            .collect())
+    };
+
+    git.branchless("init", &["--alias", "sl", "--sapling-aliases"])?;
+    insta::assert_snapshot!(list_aliases()?, @r###"
+    alias.sl=branchless smartlog
+    alias.fold=branchless fold
+    alias.goto=branchless switch
+    alias.histedit=branchless wrap -- rebase --interactive
+    alias.metaedit=branchless reword
+    "###);
+
+    {
+        let (stdout, _stderr) = git.run(&["goto", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        "###);
+    }
+
+    // The setting is remembered by later invocations.
+    git.branchless("init", &["--alias", "sl"])?;
+    insta::assert_snapshot!(list_aliases()?, @r###"
+    alias.sl=branchless smartlog
+    alias.fold=branchless fold
+    alias.goto=branchless switch
+    alias.histedit=branchless wrap -- rebase --interactive
+    alias.metaedit=branchless reword
+    "###);
+
+    git.run(&["config", "branchless.init.saplingAliases", "false"])?;
+    git.branchless("init", &["--alias", "sl"])?;
+    insta::assert_snapshot!(list_aliases()?, @r###"
+    alias.sl=branchless smartlog
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_init_keeps_user_sapling_named_aliases() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    // Aliases in the config file managed by `git branchless init` are only
+    // removed if they were installed by it.
+    let config_path = git.get_repo()?.get_config_path();
+    let config_path = config_path.to_str().unwrap();
+    git.run(&["config", "--file", config_path, "alias.goto", "checkout"])?;
+    git.run(&[
+        "config",
+        "--file",
+        config_path,
+        "alias.fold",
+        "branchless fold",
+    ])?;
+    git.run(&["config", "alias.metaedit", "commit --amend"])?;
+    git.branchless("init", &["--alias", "sl"])?;
+    {
+        let (stdout, _stderr) = git.run(&["config", "--get-regexp", "^alias\\."])?;
+        insta::assert_snapshot!(stdout, @r###"
+        alias.goto checkout
+        alias.sl branchless smartlog
+        alias.metaedit commit --amend
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_core_hooks_path_not_installed_warning() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    // Simulate a hook manager taking over the hooks directory after `git
+    // branchless init` was run.
+    git.run(&["config", "core.hooksPath", "my-hooks"])?;
+
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: the following git-branchless hooks are not installed in <repo-path>/my-hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Commits, checkouts, and branch updates will not be recorded until they are installed.
+        hint: to install them, run: git branchless init
+        hint: core.hooksPath is set to <repo-path>/my-hooks; if it is managed by a hook manager, configure it to run `git branchless hook <hook-type> "$@"` instead
+        hint: disable this hint by running: git config --global branchless.hint.hooksNotInstalled false
+        "###);
+    }
+
+    {
+        let (_stdout, _stderr) = git.branchless("init", &[])?;
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    git.run(&["config", "core.hooksPath", "other-hooks"])?;
+    git.run(&["config", "branchless.hint.hooksNotInstalled", "false"])?;
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    // Failing to check the hooks doesn't prevent the command from running.
+    git.run(&["config", "branchless.hint.hooksNotInstalled", "invalid"])?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[cfg(unix)]
 #[test]
 
@@ -600,6 +1159,7 @@ hooksPath = my-hooks
         Warning: the configuration value core.hooksPath was set to: my-hooks,
         which is not the expected default value of: <repo-path>/.git/hooks
         The Git hooks above may have been installed to an unexpected global location.
+        If core.hooksPath is managed by another tool, configure it to run: git branchless hook <hook-type> "$@"
         Successfully installed git-branchless.
         To uninstall, run: git branchless init --uninstall
         "###);
@@ -623,6 +1183,8 @@ fn test_init_worktree() -> eyre::Result<()> {
         worktree,
     } = make_git_worktree(&git, "new-worktree")?;
     worktree.branchless("init", &[])?;
+    // The main worktree is named after its (temporary) directory.
+    git.run(&["config", "branchless.commitDescriptors.worktrees", "false"])?;
     {
         let stdout = worktree.smartlog()?;
         insta::assert_snapshot!(stdout, @r###"
@@ -633,3 +1195,175 @@ fn test_init_worktree() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_init_bare_repo() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let bare_path = git.repo_path.join("bare.git");
+    git.run(&["clone", "--bare", ".", bare_path.to_str().unwrap()])?;
+    let bare = Git {
+        repo_path: bare_path,
+        ..(*git).clone()
+    };
+    bare.run(&[
+        "config",
+        "branchless.commitDescriptors.relativeTime",
+        "false",
+    ])?;
+
+    {
+        let (stdout, _stderr) = bare.branchless("init", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: pre-auto-gc, reference-transaction
+        Skipping hooks which require a working copy (this repository is bare): post-applypatch, post-checkout, post-commit, post-merge, post-rewrite
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    {
+        let stdout = bare.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 96d1c37 (> master) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = bare.branchless_with_options(
+            "prev",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This operation requires a working copy, but this repository is bare.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_init_no_reference_transaction_hook() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &["--no-reference-transaction-hook"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc
+        Skipping hook reference-transaction (branchless.core.referenceTransactionHook is false); branch updates will be recorded by the other hooks instead
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+    assert!(!git
+        .repo_path
+        .join(".git")
+        .join("hooks")
+        .join("reference-transaction")
+        .exists());
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("config", &["get", "core.referenceTransactionHook"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        false
+        "###);
+    }
+
+    git.run(&["branch", "foo"])?;
+    {
+        let (_stdout, stderr) = git.run(&["checkout", "foo"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Switched to branch 'foo'
+        branchless: processing checkout
+        branchless: processing 1 update: branch foo
+        "###);
+    }
+    {
+        git.write_file_txt("test2", "test2 contents")?;
+        git.run(&["add", "."])?;
+        let (_stdout, stderr) = git.run(&["commit", "-m", "create test2.txt"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processed commit: a10e3eb create test2.txt
+        branchless: processing 2 updates: branch foo, ref HEAD
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_init_records_existing_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "-b", "bar", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "--detach"])?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("init", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Recorded 3 commits from existing branches as visible
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    git.run(&["checkout", "master"])?;
+    git.run(&["branch", "-D", "foo"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        o 4838e49 (bar) create test3.txt
+        |
+        o a248207 create test4.txt
+        "###);
+    }
+
+    // Running `init` again shouldn't record the commits again.
+    {
+        let (stdout, _stderr) = git.branchless("init", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git branchless init --main-branch <branch>
+        Installing hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    Ok(())
+}
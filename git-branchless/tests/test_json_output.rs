@@ -0,0 +1,377 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_json_output_move() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["--output=json", "-x", "HEAD", "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "command": "move",
+          "exitCode": 0,
+          "createdCommits": [],
+          "rewrittenCommits": [
+            {
+              "oldOid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "newOid": "fe65c1fe15584744e649b2c79d4cf9b0d878f92e"
+            }
+          ],
+          "hiddenCommits": [],
+          "unhiddenCommits": [],
+          "updatedBranches": [
+            {
+              "name": "foo",
+              "oldOid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "newOid": "fe65c1fe15584744e649b2c79d4cf9b0d878f92e"
+            }
+          ],
+          "conflicts": [],
+          "errors": []
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_merge_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--output", "json", "--source", &other_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 3,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "command": "move",
+          "exitCode": 3,
+          "createdCommits": [],
+          "rewrittenCommits": [],
+          "hiddenCommits": [],
+          "unhiddenCommits": [],
+          "updatedBranches": [],
+          "conflicts": [
+            {
+              "commitOid": "e85d25c772a05b5c73ea8ec43881c12bbf588848",
+              "paths": [
+                "conflict.txt"
+              ]
+            }
+          ],
+          "errors": [
+            {
+              "code": "merge-conflict",
+              "message": "This operation would cause a merge conflict:\n- (1 conflicting file) e85d25c create conflict.txt",
+              "remediations": [
+                {
+                  "kind": "retryWithOption",
+                  "purpose": "To resolve merge conflicts",
+                  "option": "--merge"
+                }
+              ]
+            }
+          ]
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_hide_and_unhide() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["--output=json", "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "command": "hide",
+          "exitCode": 0,
+          "createdCommits": [],
+          "rewrittenCommits": [],
+          "hiddenCommits": [
+            "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+            "96d1c37a3d4363611c49f7e52186e189a04c531f"
+          ],
+          "unhiddenCommits": [],
+          "updatedBranches": [],
+          "conflicts": [],
+          "errors": []
+        }
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("unhide", &["--output=json", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "command": "unhide",
+          "exitCode": 0,
+          "createdCommits": [],
+          "rewrittenCommits": [],
+          "hiddenCommits": [],
+          "unhiddenCommits": [
+            "62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+          ],
+          "updatedBranches": [],
+          "conflicts": [],
+          "errors": []
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_record() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.write_file_txt("test1", "contents\n")?;
+    git.run(&["add", "."])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("record", &["--output=json", "-m", "create test1.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "command": "record",
+          "exitCode": 0,
+          "createdCommits": [
+            "6391e7f11cfb9b686f2a41ed02aaebbd6699d3db"
+          ],
+          "rewrittenCommits": [],
+          "hiddenCommits": [],
+          "unhiddenCommits": [],
+          "updatedBranches": [
+            {
+              "name": "master",
+              "oldOid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "newOid": "6391e7f11cfb9b686f2a41ed02aaebbd6699d3db"
+            }
+          ],
+          "conflicts": [],
+          "errors": []
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output_unsupported_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "smartlog",
+            &["--output=json"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The --output=json option is only supported by the hide, move, record, restack, sync and unhide commands.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_output_sync() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("sync", &["--output=jsonl"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {"event":"started","operation":["Processing events"]}
+        {"event":"finished","operation":["Processing events"]}
+        {"event":"started","operation":["Updating commit graph"]}
+        {"event":"finished","operation":["Updating commit graph"]}
+        {"event":"started","operation":["Computing diffs"]}
+        {"event":"finished","operation":["Computing diffs"]}
+        {"event":"started","operation":["Hashing commit contents"]}
+        {"event":"finished","operation":["Hashing commit contents"]}
+        {"event":"started","operation":["Building rebase plan"]}
+        {"event":"started","operation":["Building rebase plan","Creating commit constraints"]}
+        {"event":"progress","operation":["Building rebase plan","Creating commit constraints"],"current":0,"total":1}
+        {"event":"progress","operation":["Building rebase plan","Creating commit constraints"],"current":1,"total":1}
+        {"event":"finished","operation":["Building rebase plan","Creating commit constraints"]}
+        {"event":"started","operation":["Building rebase plan","Checking for cycles"]}
+        {"event":"finished","operation":["Building rebase plan","Checking for cycles"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits","Walking commits"]}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits","Walking commits"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Reading from cache"]}
+        {"event":"progress","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Reading from cache"],"current":0,"total":2}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Reading from cache"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Filtering upstream commits by touched paths"]}
+        {"event":"progress","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Filtering upstream commits by touched paths"],"current":0,"total":2}
+        {"event":"progress","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Filtering upstream commits by touched paths"],"current":1,"total":2}
+        {"event":"progress","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Filtering upstream commits by touched paths"],"current":2,"total":2}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits","Filtering upstream commits by touched paths"]}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits","Filtering commits"]}
+        {"event":"started","operation":["Building rebase plan","Checking for duplicate commits","Enumerating patch IDs"]}
+        {"event":"progress","operation":["Building rebase plan","Checking for duplicate commits","Enumerating patch IDs"],"current":0,"total":0}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits","Enumerating patch IDs"]}
+        {"event":"finished","operation":["Building rebase plan","Checking for duplicate commits"]}
+        {"event":"finished","operation":["Building rebase plan"]}
+        {"event":"started","operation":["Syncing commit stacks"]}
+        {"event":"progress","operation":["Syncing commit stacks"],"current":0,"total":1}
+        {"event":"status","operation":["Syncing commit stacks"],"status":"inProgress","message":"Syncing commit stack: 62fc20d create test1.txt"}
+        {"event":"started","operation":["Syncing commit stacks","Rebasing commits"]}
+        {"event":"progress","operation":["Syncing commit stacks","Rebasing commits"],"current":1,"total":1}
+        {"event":"status","operation":["Syncing commit stacks","Rebasing commits"],"status":"inProgress","message":"Applying patch for commit: 62fc20d create test1.txt"}
+        {"event":"status","operation":["Syncing commit stacks","Rebasing commits"],"status":"inProgress","message":"Committing to repository: 62fc20d create test1.txt"}
+        {"event":"finished","operation":["Syncing commit stacks","Rebasing commits"]}
+        {"event":"started","operation":["Syncing commit stacks","Querying the working copy"]}
+        {"event":"finished","operation":["Syncing commit stacks","Querying the working copy"]}
+        {"event":"started","operation":["Syncing commit stacks","Running Git command: git checkout master"]}
+        {"event":"finished","operation":["Syncing commit stacks","Running Git command: git checkout master"]}
+        {"event":"rewritten","oldOid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","newOid":"07709435a8f6d1566e0091896d130c78acd429dd"}
+        {"event":"progress","operation":["Syncing commit stacks"],"current":1,"total":1}
+        {"event":"finished","operation":["Syncing commit stacks"]}
+        {"event":"exited","exitCode":0}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_output_test() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "test",
+            &[
+                "run",
+                "--output=jsonl",
+                "-x",
+                "! test -f test2.txt",
+                "draft()",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        {"event":"started","operation":["Processing events"]}
+        {"event":"finished","operation":["Processing events"]}
+        {"event":"started","operation":["Updating commit graph"]}
+        {"event":"finished","operation":["Updating commit graph"]}
+        {"event":"started","operation":["Evaluating revset: draft()"]}
+        {"event":"finished","operation":["Evaluating revset: draft()"]}
+        {"event":"started","operation":["Initializing rebase"]}
+        {"event":"started","operation":["Initializing rebase","Running Git command: git diff --quiet"]}
+        {"event":"finished","operation":["Initializing rebase","Running Git command: git diff --quiet"]}
+        {"event":"finished","operation":["Initializing rebase"]}
+        {"event":"started","operation":["Running Git command: git rebase --continue"]}
+        {"event":"finished","operation":["Running Git command: git rebase --continue"]}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt"]}
+        {"event":"progress","operation":["Running tests: ! test -f test2.txt"],"current":0,"total":2}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt"],"status":"inProgress","message":"Running tests on 2 commits: ! test -f test2.txt"}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"]}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"],"status":"inProgress","message":"Waiting to test 62fc20d create test1.txt"}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"]}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"]}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"],"status":"inProgress","message":"Waiting to test 96d1c37 create test2.txt"}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"]}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"]}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"],"status":"inProgress","message":"Preparing 62fc20d create test1.txt"}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"],"status":"inProgress","message":"Testing 62fc20d create test1.txt"}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt","Querying the working copy"]}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt","Querying the working copy"]}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"],"status":"success","message":"Passed: 62fc20d create test1.txt"}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt","Waiting to test 62fc20d create test1.txt"]}
+        {"event":"progress","operation":["Running tests: ! test -f test2.txt"],"current":1,"total":2}
+        {"event":"started","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"]}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"],"status":"inProgress","message":"Preparing 96d1c37 create test2.txt"}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"],"status":"inProgress","message":"Testing 96d1c37 create test2.txt"}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"],"status":"failure","message":"Failed (exit code 1): 96d1c37 create test2.txt"}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt","Waiting to test 96d1c37 create test2.txt"]}
+        {"event":"progress","operation":["Running tests: ! test -f test2.txt"],"current":2,"total":2}
+        {"event":"status","operation":["Running tests: ! test -f test2.txt"],"status":"inProgress","message":"Waiting for workers"}
+        {"event":"finished","operation":["Running tests: ! test -f test2.txt"]}
+        {"event":"started","operation":["Running Git command: git rebase --abort"]}
+        {"event":"finished","operation":["Running Git command: git rebase --abort"]}
+        {"event":"exited","exitCode":1}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_output_unsupported_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "move",
+            &["--output=jsonl"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The --output=jsonl option is only supported by the submit, sync and test commands.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
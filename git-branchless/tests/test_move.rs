@@ -1,6 +1,8 @@
+use lib::git::NonZeroOid;
+use lib::testing::pty::{run_in_pty, PtyAction};
 use lib::testing::{
-    extract_hint_command, make_git, make_git_with_remote_repo, remove_rebase_lines, GitInitOptions,
-    GitRunOptions, GitWrapperWithRemoteRepo,
+    extract_hint_command, make_git, make_git_with_remote_repo, remove_rebase_lines, Git,
+    GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
 };
 
 #[test]
@@ -2371,20 +2373,22 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
     git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
 
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "move",
             &["--source", &other_oid.to_string()],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
-        Attempting rebase in-memory...
+        insta::assert_snapshot!(stderr, @r###"
         This operation would cause a merge conflict:
         - (1 conflicting file) e85d25c create conflict.txt
         To resolve merge conflicts, retry this operation with the --merge option.
         "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        "###);
     }
 
     {
@@ -2457,6 +2461,233 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_merge_conflict_resolve_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    run_in_pty(
+        &git,
+        "move",
+        &["--merge", "--source", &other_oid.to_string()],
+        &[
+            PtyAction::WaitUntilContains("conflict 1/1"),
+            PtyAction::Write("t"),
+            PtyAction::Write("c"),
+        ],
+    )?;
+
+    {
+        // The conflict was resolved without starting an on-disk rebase.
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 202143f create conflict.txt
+        |
+        o c7c2ea2 create conflict.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["--raw", "children(.)"])?;
+        let (stdout, _stderr) = git.run(&["show", &format!("{}:conflict.txt", stdout.trim())])?;
+        insta::assert_snapshot!(stdout, @r###"
+        conflict 1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_merge_conflict_rerere() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "rerere.enabled", "true"])?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other1_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let other2_oid = git.commit_file_with_contents("conflict", 3, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 4, "conflict 2\n")?;
+
+    // Git records the resolution of the conflict from the on-disk rebase.
+    git.branchless_with_options(
+        "move",
+        &["--merge", "--source", &other1_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    git.resolve_file("conflict", "resolved\n")?;
+    git.run(&["rebase", "--continue"])?;
+
+    // The recorded resolution isn't committed without review unless
+    // `rerere.autoUpdate` is set.
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "move",
+            &["--source", &other2_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 3,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 5c309e9 create conflict.txt
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        "###);
+    }
+
+    git.run(&["config", "rerere.autoUpdate", "true"])?;
+    {
+        let (stdout, _stderr) = git.branchless("move", &["--source", &other2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Reused recorded conflict resolution for: conflict.txt
+        [1/1] Committed as: a67c96d create conflict.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["--raw", "children(.)"])?;
+        for commit_oid in stdout.lines() {
+            let (stdout, _stderr) = git.run(&["show", &format!("{commit_oid}:conflict.txt")])?;
+            assert_eq!(stdout, "resolved\n");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_merge_conflict_rerere_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "rerere.enabled", "true"])?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other1_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let other2_oid = git.commit_file_with_contents("conflict", 3, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 4, "conflict 2\n")?;
+
+    run_in_pty(
+        &git,
+        "move",
+        &["--merge", "--source", &other1_oid.to_string()],
+        &[
+            PtyAction::WaitUntilContains("conflict 1/1"),
+            PtyAction::Write("t"),
+            PtyAction::Write("c"),
+        ],
+    )?;
+
+    {
+        // The resolution made in memory is reused without prompting again.
+        let (stdout, _stderr) =
+            git.branchless("move", &["--merge", "--source", &other2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Reused recorded conflict resolution for: conflict.txt
+        [1/1] Committed as: 7b8b97a create conflict.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_protected_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "release/1.0"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+    git.run(&["config", "branchless.core.protectedBranches", "release/*"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--source", &test2_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to move 1 protected branch: release/1.0
+        Protected branches are the main branch and the branches matching branchless.core.protectedBranches.
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["-f", "--source", &test2_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 44352d0 create test2.txt
+        [2/2] Committed as: cf5eb24 create test3.txt
+        branchless: processing 1 update: branch release/1.0
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        :
+        @ bf0d52a (> master) create test4.txt
+        |
+        o 44352d0 (release/1.0) create test2.txt
+        |
+        o cf5eb24 create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_base() -> eyre::Result<()> {
     let git = make_git()?;
@@ -2801,17 +3032,18 @@ fn test_move_force_in_memory() -> eyre::Result<()> {
             "move",
             &["-d", "master", "--in-memory"],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stderr, @"");
-        insta::assert_snapshot!(stdout, @r###"
-        Attempting rebase in-memory...
+        insta::assert_snapshot!(stderr, @r###"
         This operation would cause a merge conflict:
         - (1 conflicting file) 081b474 conflicting test2
         To resolve merge conflicts, retry this operation with the --merge option.
         "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        "###);
     }
 
     {
@@ -3491,9 +3723,9 @@ fn test_move_delete_checked_out_branch() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         O f777ecc create initial.txt
         |\
-        : o 62fc20d create test1.txt
+        : o 62fc20d (cherry-picked as 047b7ad7) create test1.txt
         : |
-        : o 96d1c37 (work) create test2.txt
+        : o 96d1c37 (cherry-picked as 91c5ce63) (work) create test2.txt
         : |
         : o ffcba55 (more-work) create test3.txt
         :
@@ -3830,17 +4062,18 @@ fn test_move_merge_commit() -> eyre::Result<()> {
                 "move",
                 &["-s", &test2_oid.to_string(), "-d", "master"],
                 &GitRunOptions {
-                    expected_exit_code: 1,
+                    expected_exit_code: 3,
                     ..Default::default()
                 },
             )?;
             insta::assert_snapshot!(stdout, @r###"
             Attempting rebase in-memory...
+            "###);
+            insta::assert_snapshot!(stderr, @r###"
             Merge commits currently can't be rebased in-memory.
             The merge commit was: 28790c7 Merge commit 'fe65c1fe15584744e649b2c79d4cf9b0d878f92e' into HEAD
             To resolve merge conflicts, retry this operation with the --merge option.
             "###);
-            insta::assert_snapshot!(stderr, @"");
         }
 
         {
@@ -4345,9 +4578,9 @@ fn test_move_standalone_create_gc_refs() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["show-ref"])?;
         insta::assert_snapshot!(stdout, @r###"
-        62fc20d2a290daea0d52bdc2ed2ad4be6491010e refs/branchless/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
-        96d1c37a3d4363611c49f7e52186e189a04c531f refs/branchless/96d1c37a3d4363611c49f7e52186e189a04c531f
         62fc20d2a290daea0d52bdc2ed2ad4be6491010e refs/heads/master
+        62fc20d2a290daea0d52bdc2ed2ad4be6491010e refs/namespaces/branchless/refs/keep/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        96d1c37a3d4363611c49f7e52186e189a04c531f refs/namespaces/branchless/refs/keep/96d1c37a3d4363611c49f7e52186e189a04c531f
         "###);
     }
 
@@ -4702,3 +4935,232 @@ fn test_move_delete_branch_config_entry() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_move_in_memory_partial_clone() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    {
+        original_repo.init_repo()?;
+        original_repo.run(&["config", "uploadpack.allowFilter", "true"])?;
+        original_repo.write_file_txt("test", "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n")?;
+        original_repo.run(&["add", "."])?;
+        original_repo.run(&["commit", "-m", "create test.txt"])?;
+        original_repo.run(&["checkout", "-b", "other"])?;
+        original_repo.write_file_txt("test", "1\n2\n3\n4\n5\n6\n7\n8\n9\nten\n")?;
+        original_repo.run(&["commit", "-a", "-m", "update line 10"])?;
+        original_repo.run(&["checkout", "master"])?;
+        original_repo.write_file_txt("test", "one\n2\n3\n4\n5\n6\n7\n8\n9\n10\n")?;
+        original_repo.run(&["commit", "-a", "-m", "update line 1"])?;
+
+        original_repo.clone_repo_into(&cloned_repo, &["--filter=blob:none"])?;
+    }
+
+    {
+        cloned_repo.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            run_branchless_init: false,
+        })?;
+        cloned_repo.branchless("init", &["--main-branch", "master"])?;
+        cloned_repo.run(&["branch", "other", "origin/other"])?;
+
+        let (stdout, _stderr) = cloned_repo.branchless("move", &["-s", "other", "--in-memory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 5c7730e update line 10
+        branchless: processing 1 update: branch other
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        Your branch is up to date with 'origin/master'.
+        :
+        @ 6326e95 (> master) update line 1
+        |
+        o 5c7730e (other) update line 10
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["show", "other:test.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        one
+        2
+        3
+        4
+        5
+        6
+        7
+        8
+        9
+        ten
+        "###);
+    }
+
+    Ok(())
+}
+
+/// Set up a repository whose `sub` directory is a submodule of
+/// `submodule_git`, checked out at `submodule_oid`.
+fn add_submodule(git: &Git, submodule_git: &Git, submodule_oid: NonZeroOid) -> eyre::Result<()> {
+    git.run(&[
+        "-c",
+        "protocol.file.allow=always",
+        "submodule",
+        "add",
+        submodule_git.repo_path.to_str().unwrap(),
+        "sub",
+    ])?;
+    // The URL is an absolute path, which would make commit hashes vary
+    // between test runs.
+    git.run(&["config", "-f", ".gitmodules", "submodule.sub.url", "../sub"])?;
+    git.run(&["add", ".gitmodules"])?;
+    set_submodule_head(git, submodule_oid)?;
+    git.run(&["commit", "-m", "add submodule"])?;
+    Ok(())
+}
+
+/// Check out the provided commit in the `sub` submodule and stage it.
+fn set_submodule_head(git: &Git, submodule_oid: NonZeroOid) -> eyre::Result<()> {
+    git.run(&["-C", "sub", "checkout", "-q", &submodule_oid.to_string()])?;
+    git.run(&["add", "sub"])?;
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_submodule_fast_forward() -> eyre::Result<()> {
+    let git = make_git()?;
+    let submodule_git = make_git()?;
+
+    submodule_git.init_repo()?;
+    let sub1_oid = submodule_git.commit_file("sub1", 1)?;
+    let sub2_oid = submodule_git.commit_file("sub2", 2)?;
+    let sub3_oid = submodule_git.commit_file("sub3", 3)?;
+
+    git.init_repo()?;
+    add_submodule(&git, &submodule_git, sub1_oid)?;
+    git.detach_head()?;
+    set_submodule_head(&git, sub2_oid)?;
+    git.write_file_txt("test1", "test1 contents\n")?;
+    git.run(&["add", "test1.txt"])?;
+    git.run(&["commit", "-m", "update submodule and create test1.txt"])?;
+    git.run(&["checkout", "master"])?;
+    set_submodule_head(&git, sub3_oid)?;
+    git.run(&["commit", "-m", "update submodule again"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("move", &["-s", "draft()", "--in-memory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: a556e01 update submodule and create test1.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        :
+        @ e170f82 (> master) update submodule again
+        |
+        o a556e01 update submodule and create test1.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (moved_oid, _stderr) = git.branchless("query", &["--raw", "draft()"])?;
+        let (stdout, _stderr) = git.run(&["rev-parse", &format!("{}:sub", moved_oid.trim())])?;
+        assert_eq!(stdout.trim(), sub3_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_dirty_submodule() -> eyre::Result<()> {
+    let git = make_git()?;
+    let submodule_git = make_git()?;
+
+    submodule_git.init_repo()?;
+    let sub1_oid = submodule_git.commit_file("sub1", 1)?;
+    let sub2_oid = submodule_git.commit_file("sub2", 2)?;
+
+    git.init_repo()?;
+    add_submodule(&git, &submodule_git, sub1_oid)?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test2_oid.to_string()])?;
+    git.run(&["-C", "sub", "checkout", "-q", &sub2_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["-s", "draft()", "-d", "master", "--in-memory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: a3eea3c create test1.txt
+        [2/2] Committed as: 84e4630 create test2.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 84e46300d01457a69f50e410f92706801d1bfd15
+        M	sub
+        :
+        O 1a6936b (master) create test3.txt
+        |
+        o a3eea3c create test1.txt
+        |
+        @ 84e4630 create test2.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        insta::assert_snapshot!(stdout, @" M sub");
+        let (stdout, _stderr) = git.run(&["-C", "sub", "rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), sub2_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_in_memory_submodule_out_of_sync_warning() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let initial_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.detach_head()?;
+    git.run(&["config", "-f", ".gitmodules", "submodule.sub.path", "sub"])?;
+    git.run(&["config", "-f", ".gitmodules", "submodule.sub.url", "./sub"])?;
+    git.run(&["add", ".gitmodules"])?;
+    git.run(&["commit", "-m", "create .gitmodules"])?;
+    git.run(&[
+        "update-index",
+        "--add",
+        "--cacheinfo",
+        &format!("160000,{initial_oid},sub"),
+    ])?;
+    git.run(&["commit", "-m", "add submodule"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("move", &["-x", "draft() - roots(draft())", "--in-memory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 3f80fb8 add submodule
+        [1/1] Warning: the submodule at sub is now out of sync with .gitmodules
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        @ f777ecc (> master) create initial.txt
+        |\
+        | o 3f80fb8 add submodule
+        |
+        o 4a90bae create .gitmodules
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
@@ -153,6 +153,22 @@ fn test_next_ambiguous() -> eyre::Result<()> {
         "###);
     }
 
+    git.run(&["checkout", "master"])?;
+    git.run(&["config", "branchless.next.defaultNewest", "true"])?;
+    {
+        let (stdout, _stderr) = git.branchless("next", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 98b9119d16974f372e76cb64a3b77c528fc0b18b
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |\
+        | o fe65c1f create test2.txt
+        |
+        @ 98b9119 create test3.txt
+        "###);
+    }
+
     Ok(())
 }
 
@@ -578,6 +594,121 @@ fn test_navigation_failed_to_check_out_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_navigation_detached_head_hint() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("prev", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        |
+        o 70deb1e (foo) create test3.txt
+        hint: HEAD is now detached from branch foo, so new commits won't update it
+        hint: to check out the branch again, run: git switch foo
+        hint: disable this hint by running: git config --global branchless.hint.detachedHead false
+        "###);
+    }
+
+    git.run(&["checkout", "foo"])?;
+    git.run(&["config", "branchless.hint.detachedHead", "false"])?;
+    {
+        let (stdout, _stderr) = git.branchless("prev", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        |
+        o 70deb1e (foo) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_check_out_in_process() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.checkout.inProcess", "true"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("prev", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing checkout
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        |
+        o 70deb1e (foo) create test3.txt
+        hint: HEAD is now detached from branch foo, so new commits won't update it
+        hint: to check out the branch again, run: git switch foo
+        hint: disable this hint by running: git config --global branchless.hint.detachedHead false
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+    {
+        let (stdout, _stderr) = git.branchless("switch", &["foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing checkout
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e (> foo) create test3.txt
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["reflog", "-n", "2", "--format=%gs"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        checkout: moving from 96d1c37a3d4363611c49f7e52186e189a04c531f to foo
+        checkout: moving from foo to 96d1c37a3d4363611c49f7e52186e189a04c531f
+        "###);
+    }
+
+    // Checkouts which would overwrite local changes fall back to `git
+    // checkout`, so that it can report the problem.
+    git.write_file_txt("test3", "conflicting contents")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "prev",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        Failed to check out commit: 96d1c37a3d4363611c49f7e52186e189a04c531f
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 #[cfg(unix)]
 fn test_switch_pty_branch() -> eyre::Result<()> {
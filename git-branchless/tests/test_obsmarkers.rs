@@ -0,0 +1,104 @@
+use lib::testing::make_git;
+use regex::Regex;
+
+#[test]
+fn test_obsmarkers_export_import() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ b867646 amended test2
+        "###);
+    }
+
+    let markers = {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["export"])?;
+        let timestamp_re = Regex::new(r"[0-9]+\.[0-9]+")?;
+        insta::assert_snapshot!(timestamp_re.replace_all(&stdout, "<timestamp>"), @r###"
+        [
+          {
+            "prednode": "fe65c1fe15584744e649b2c79d4cf9b0d878f92e",
+            "succnodes": [
+              "b867646a8fefdca9e23ba395feb9e97a8fe153a6"
+            ],
+            "flag": 0,
+            "date": [
+              <timestamp>,
+              0
+            ],
+            "metadata": {
+              "operation": "hook-post-rewrite"
+            }
+          },
+          {
+            "prednode": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+            "succnodes": [],
+            "flag": 0,
+            "date": [
+              <timestamp>,
+              0
+            ],
+            "metadata": {
+              "operation": "hide"
+            }
+          }
+        ]
+        "###);
+        stdout
+    };
+
+    git.branchless("unhide", &[&test1_oid.to_string(), &test2_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["export"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        []
+        "###);
+    }
+
+    git.write_file("markers.json", &markers)?;
+    {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["import", "markers.json"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 2 obsolescence markers.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ b867646 amended test2
+        "###);
+    }
+
+    git.write_file(
+        "markers.json",
+        &format!(
+            r#"[
+  {{"prednode": "{test1_oid}", "succnodes": [], "date": [0.0, 0]}},
+  {{"prednode": "1234567890123456789012345678901234567890", "succnodes": [], "date": [0.0, 0]}}
+]"#
+        ),
+    )?;
+    {
+        let (stdout, _stderr) = git.branchless("obsmarkers", &["import", "markers.json"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 0 obsolescence markers (skipped 1 for commits not in this repository).
+        "###);
+    }
+
+    Ok(())
+}
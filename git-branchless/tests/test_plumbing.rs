@@ -0,0 +1,191 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_plumbing_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("plumbing", &["graph", "--format", "json-v1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "commits": [
+            {
+              "oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "parents": [],
+              "summary": "create initial.txt",
+              "branches": [
+                "master"
+              ],
+              "isHead": false,
+              "isMain": true,
+              "isObsolete": false
+            },
+            {
+              "oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "parents": [
+                "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+              ],
+              "summary": "create test1.txt",
+              "branches": [
+                "foo"
+              ],
+              "isHead": false,
+              "isMain": false,
+              "isObsolete": false
+            },
+            {
+              "oid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "parents": [
+                "62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+              ],
+              "summary": "create test2.txt",
+              "branches": [],
+              "isHead": true,
+              "isMain": false,
+              "isObsolete": false
+            }
+          ]
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_plumbing_successors() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &[&test2_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "plumbing",
+            &[
+                "successors",
+                "--format",
+                "json-v1",
+                &test1_oid.to_string(),
+                &test2_oid.to_string(),
+                "HEAD",
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "commits": [
+            {
+              "oid": "ae94dc2a748bc0965c88fcf3edac2e30074ff7e2",
+              "isObsolete": false,
+              "successor": null,
+              "isDeleted": false
+            },
+            {
+              "oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "isObsolete": true,
+              "successor": "ae94dc2a748bc0965c88fcf3edac2e30074ff7e2",
+              "isDeleted": false
+            },
+            {
+              "oid": "51452b55e09488387e59770a9f44d999eba27864",
+              "isObsolete": true,
+              "successor": null,
+              "isDeleted": false
+            }
+          ]
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_plumbing_plan_move() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "plumbing",
+            &[
+                "plan-move",
+                "--format",
+                "json-v1",
+                "-s",
+                "HEAD~",
+                "-d",
+                "master",
+            ],
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        {
+          "plan": {
+            "firstDestOid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+            "commands": [
+              {
+                "type": "reset",
+                "target": "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+              },
+              {
+                "type": "pick",
+                "originalCommitOid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+                "commitToApplyOid": "96d1c37a3d4363611c49f7e52186e189a04c531f"
+              },
+              {
+                "type": "pick",
+                "originalCommitOid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+                "commitToApplyOid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f"
+              }
+            ]
+          }
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_plumbing_requires_format() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "plumbing",
+            &["graph"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The following required arguments were not provided:
+          --format <FORMAT>
+
+        Usage: git-branchless plumbing graph --format <FORMAT> [REVSET]
+
+        For more information try '--help'
+        "###);
+    }
+
+    Ok(())
+}
@@ -15,9 +15,11 @@ fn test_repair_broken_commit() -> eyre::Result<()> {
     git.run(&["checkout", "HEAD^"])?;
 
     let repo = git.get_repo()?;
-    repo.find_reference(&ReferenceName::from(format!("refs/branchless/{test3_oid}")))?
-        .unwrap()
-        .delete()?;
+    repo.find_reference(&ReferenceName::from(format!(
+        "refs/namespaces/branchless/refs/keep/{test3_oid}"
+    )))?
+    .unwrap()
+    .delete()?;
     git.run(&["gc", "--prune=now"])?;
 
     {
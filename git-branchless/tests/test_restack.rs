@@ -218,20 +218,22 @@ fn test_restack_aborts_during_rebase_conflict() -> eyre::Result<()> {
     git.run(&["commit", "--amend", "-m", "amend test1 with test2 conflict"])?;
 
     {
-        let (stdout, _stderr) = git.branchless_with_options(
+        let (stdout, stderr) = git.branchless_with_options(
             "restack",
             &["-f"],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
-        insta::assert_snapshot!(stdout, @r###"
-        Attempting rebase in-memory...
+        insta::assert_snapshot!(stderr, @r###"
         This operation would cause a merge conflict:
         - (1 conflicting file) 96d1c37 create test2.txt
         To resolve merge conflicts, retry this operation with the --merge option.
         "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        "###);
     }
 
     {
@@ -438,7 +440,8 @@ fn test_restack_unobserved_commit() -> eyre::Result<()> {
         "###);
     }
 
-    // Only abandon `test3` now that it's been observed by the DAG.
+    // `test3` was checked out when `init` was run, so it was recorded as
+    // visible, and is abandoned by amending its parent.
     git.run(&["checkout", "foo"])?;
 
     git.run(&["commit", "--amend", "-m", "Updated test2"])?;
@@ -447,20 +450,34 @@ fn test_restack_unobserved_commit() -> eyre::Result<()> {
         insta::assert_snapshot!(stdout, @r###"
         :
         O 62fc20d (master) create test1.txt
+        |\
+        | @ f4229de (> foo) Updated test2
         |
-        @ f4229de (> foo) Updated test2
+        x 96d1c37 (rewritten as f4229de3) create test2.txt
+        |
+        o 70deb1e create test3.txt
+        hint: there is 1 abandoned commit in your commit graph
+        hint: to fix this, run: git restack
+        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
         "###);
     }
 
     {
         let (stdout, _stderr) = git.run(&["restack"])?;
         insta::assert_snapshot!(stdout, @r###"
-        No abandoned commits to restack.
+        Attempting rebase in-memory...
+        [1/1] Committed as: a33d87d create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout foo
+        In-memory rebase succeeded.
+        Finished restacking commits.
         No abandoned branches to restack.
         :
         O 62fc20d (master) create test1.txt
         |
         @ f4229de (> foo) Updated test2
+        |
+        o a33d87d create test3.txt
         "###);
     }
 
@@ -617,7 +617,8 @@ fn test_reword_merge_commit() -> eyre::Result<()> {
         Reworded commit 96d1c37 as 800dd6c new message 2
         Reworded commit 4838e49 as 11f31c5 new message 2
         Reworded commit 2fc54bd as 930244c new message 2
-        Reworded 3 commits. If this was unintentional, run: git undo
+        hint: reworded 3 commits. If this was unintentional, run: git undo
+        hint: disable this hint by running: git config --global branchless.hint.rewordUndo false
         "###);
     }
 
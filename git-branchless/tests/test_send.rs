@@ -0,0 +1,140 @@
+use lib::testing::{make_git, GitRunOptions};
+
+/// Render the cover letter without the headers and signature which vary
+/// between runs and Git versions.
+fn read_cover_letter(path: &std::path::Path) -> eyre::Result<String> {
+    let contents = std::fs::read_to_string(path)?;
+    let lines: Vec<&str> = contents
+        .lines()
+        .take_while(|line| *line != "-- ")
+        .filter(|line| !line.starts_with("Date:") && !line.starts_with("Message-Id:"))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+#[test]
+fn test_send_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "config",
+        "branch.foo.description",
+        "Add test files\n\nThis series adds some test files.",
+    ])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("send", &["-o", "patches", "--reroll-count", "2"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Generated 2 patches with a cover letter:
+          patches/v2-0000-cover-letter.patch
+          patches/v2-0001-create-test1.txt.patch
+          patches/v2-0002-create-test2.txt.patch
+        "###);
+    }
+
+    {
+        let cover_letter = read_cover_letter(
+            &git.repo_path
+                .join("patches")
+                .join("v2-0000-cover-letter.patch"),
+        )?;
+        insta::assert_snapshot!(cover_letter, @r###"
+        From 96d1c37a3d4363611c49f7e52186e189a04c531f Mon Sep 17 00:00:00 2001
+        From: Testy McTestface <test@example.com>
+        Subject: [PATCH v2 0/2] Add test files
+
+        This series adds some test files.
+
+        Testy McTestface (2):
+          create test1.txt
+          create test2.txt
+
+         test1.txt | 1 +
+         test2.txt | 1 +
+         2 files changed, 2 insertions(+)
+         create mode 100644 test1.txt
+         create mode 100644 test2.txt
+        "###);
+    }
+
+    {
+        let patch = std::fs::read_to_string(
+            git.repo_path
+                .join("patches")
+                .join("v2-0002-create-test2.txt.patch"),
+        )?;
+        assert!(patch.contains("In-Reply-To: "));
+        assert!(patch.contains("Subject: [PATCH v2 2/2] create test2.txt"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_send_without_description() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("send", &["--subject-prefix", "RFC PATCH"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Generated 1 patch with a cover letter:
+          0000-cover-letter.patch
+          0001-create-test1.txt.patch
+        "###);
+    }
+
+    {
+        let cover_letter = read_cover_letter(&git.repo_path.join("0000-cover-letter.patch"))?;
+        insta::assert_snapshot!(cover_letter, @r###"
+        From 62fc20d2a290daea0d52bdc2ed2ad4be6491010e Mon Sep 17 00:00:00 2001
+        From: Testy McTestface <test@example.com>
+        Subject: [RFC PATCH 0/1] create test1.txt
+
+        Testy McTestface (1):
+          create test1.txt
+
+         test1.txt | 1 +
+         1 file changed, 1 insertion(+)
+         create mode 100644 test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_send_nonlinear() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "send",
+            &["draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Cannot send commits which don't form a linear range: 96d1c37 create test2.txt is not the only parent of 4838e49 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
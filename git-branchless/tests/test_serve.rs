@@ -0,0 +1,209 @@
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use lib::testing::{make_git, Git};
+use serde_json::{json, Value};
+
+/// A client connected to a running `git branchless serve` process.
+struct Client {
+    server: Child,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+    notifications: Vec<Value>,
+}
+
+impl Client {
+    fn start(git: &Git) -> eyre::Result<Self> {
+        let mut server = Command::new(&git.path_to_git)
+            .current_dir(&git.repo_path)
+            .args(["branchless", "serve", "--json-rpc"])
+            .env_clear()
+            .envs(git.get_base_env(0))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut line = String::new();
+        BufReader::new(server.stdout.take().unwrap()).read_line(&mut line)?;
+        let socket_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("serve.sock");
+        assert_eq!(
+            line.trim(),
+            format!("Listening on {}", socket_path.display())
+        );
+
+        let writer = UnixStream::connect(&socket_path)?;
+        writer.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Client {
+            server,
+            reader,
+            writer,
+            next_id: 0,
+            notifications: Vec::new(),
+        })
+    }
+
+    fn read_message(&mut self) -> eyre::Result<Value> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> eyre::Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        writeln!(self.writer, "{request}")?;
+        loop {
+            let message = self.read_message()?;
+            if message.get("id") == Some(&json!(id)) {
+                return Ok(message);
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn wait_for_notification(&mut self) -> eyre::Result<Value> {
+        if !self.notifications.is_empty() {
+            return Ok(self.notifications.remove(0));
+        }
+        self.read_message()
+    }
+}
+
+#[test]
+fn test_serve_json_rpc() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    let mut client = Client::start(&git)?;
+
+    {
+        let response = client.call("smartlog", Value::Null)?;
+        insta::assert_snapshot!(serde_json::to_string_pretty(&response["result"])?, @r###"
+        {
+          "eventId": 7,
+          "nodes": [
+            {
+              "branches": [
+                "master"
+              ],
+              "isHead": false,
+              "isMain": true,
+              "isObsolete": false,
+              "numOmittedDescendants": 0,
+              "oid": "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+              "parents": [],
+              "summary": "create initial.txt"
+            },
+            {
+              "branches": [
+                "foo"
+              ],
+              "isHead": false,
+              "isMain": false,
+              "isObsolete": false,
+              "numOmittedDescendants": 0,
+              "oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "parents": [
+                "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+              ],
+              "summary": "create test1.txt"
+            },
+            {
+              "branches": [],
+              "isHead": true,
+              "isMain": false,
+              "isObsolete": false,
+              "numOmittedDescendants": 0,
+              "oid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "parents": [
+                "62fc20d2a290daea0d52bdc2ed2ad4be6491010e"
+              ],
+              "summary": "create test2.txt"
+            }
+          ]
+        }
+        "###);
+    }
+
+    {
+        let response = client.call("subscribe", Value::Null)?;
+        let event_id = response["result"]["eventId"].as_u64().unwrap();
+        let test3_oid = git.commit_file("test3", 3)?;
+        let notification = client.wait_for_notification()?;
+        assert_eq!(notification["method"], "eventsChanged");
+        assert!(notification["params"]["eventId"].as_u64().unwrap() > event_id);
+
+        let response = client.call("checkout", json!({ "target": "foo" }))?;
+        assert_eq!(response["result"]["exitCode"], 0);
+        let response = client.call("hide", json!({ "revsets": [test3_oid.to_string()] }))?;
+        assert_eq!(response["result"]["exitCode"], 0);
+        let response = client.call("smartlog", json!({ "revset": "draft()" }))?;
+        let nodes: Vec<String> = response["result"]["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| format!("{} (head: {})", node["summary"], node["isHead"]))
+            .collect();
+        insta::assert_snapshot!(nodes.join("\n"), @r###"
+        "create initial.txt" (head: false)
+        "create test1.txt" (head: true)
+        "create test2.txt" (head: false)
+        "###);
+    }
+
+    {
+        let response = client.call("frobnicate", Value::Null)?;
+        insta::assert_snapshot!(response["error"].to_string(), @r###"
+        {"code":-32601,"message":"Unknown method: frobnicate"}
+        "###);
+        let response = client.call("checkout", json!({ "branch": "foo" }))?;
+        insta::assert_snapshot!(response["error"].to_string(), @r###"
+        {"code":-32602,"message":"unknown field `branch`, expected `target`"}
+        "###);
+        let response = client.call("checkout", json!({ "target": "--detach" }))?;
+        insta::assert_snapshot!(response["error"].to_string(), @r###"
+        {"code":-32602,"message":"Invalid checkout target: \"--detach\""}
+        "###);
+
+        // Revsets which look like options aren't interpreted as options.
+        let response = client.call("hide", json!({ "revsets": ["--help"] }))?;
+        insta::assert_snapshot!(response["result"]["exitCode"].to_string(), @r###"
+        1
+        "###);
+        let response = client.call("move", json!({ "source": ["--help"], "dest": "--help" }))?;
+        insta::assert_snapshot!(response["result"]["exitCode"].to_string(), @r###"
+        1
+        "###);
+    }
+
+    {
+        let response = client.call("shutdown", Value::Null)?;
+        assert_eq!(response["result"], Value::Null);
+        let exit_status = client.server.wait()?;
+        assert!(exit_status.success());
+        assert!(!git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("serve.sock")
+            .exists());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_suggest_split() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.write_file(
+        ".github/CODEOWNERS",
+        "\
+* @everyone
+/docs/ @docs-team
+*.rs @rust-team @alice
+",
+    )?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "add CODEOWNERS"])?;
+    git.detach_head()?;
+    git.write_file("docs/guide.md", "guide\n")?;
+    git.write_file("src/lib.rs", "fn main() {}\n")?;
+    git.write_file("README.md", "readme\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "add everything"])?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("suggest-split", &["stack()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        e671ad5 add everything
+          Touches 3 ownership areas, so it could be split into:
+            1. @docs-team: docs/guide.md
+            2. @everyone: README.md
+            3. @rust-team @alice: src/lib.rs
+        58ada79 create test1.txt
+          Touches 1 ownership area: @everyone
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_suggest_split_no_codeowners() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "suggest-split",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No CODEOWNERS file was found. It can be at any of these paths: .github/CODEOWNERS, .gitlab/CODEOWNERS, CODEOWNERS, docs/CODEOWNERS
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,4 +1,7 @@
-use lib::testing::{make_git, make_git_with_remote_repo, GitInitOptions, GitWrapperWithRemoteRepo};
+use lib::testing::pty::{run_in_pty, PtyAction};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
 
 fn remove_nondeterministic_lines(output: String) -> String {
     output
@@ -206,6 +209,52 @@ fn test_sync_pull() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_sync_main_branch_behind_hint() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+
+    original_repo.commit_file("test2", 2)?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["fetch"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Not moving up-to-date stack at 4838e49 create test3.txt
+        hint: master is behind origin/master, as of the last fetch
+        hint: to update it and sync your commits onto it, run: git sync --pull
+        hint: disable this hint by running: git config --global branchless.hint.syncMainBranchBehind false
+        "###);
+    }
+
+    cloned_repo.run(&["config", "branchless.hint.syncMainBranchBehind", "false"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Not moving up-to-date stack at 4838e49 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_sync_specific_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -439,7 +488,14 @@ fn test_sync_merge_commit() -> eyre::Result<()> {
     }
 
     {
-        let (stdout, _stderr) = git.branchless("sync", &[])?;
+        let (stdout, _stderr) = git.branchless_with_options(
+            "sync",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 3,
+                ..Default::default()
+            },
+        )?;
         insta::assert_snapshot!(stdout, @r###"
         Attempting rebase in-memory...
         Attempting rebase in-memory...
@@ -450,3 +506,191 @@ fn test_sync_merge_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_sync_shallow_clone() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master", "--depth", "1"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+
+    original_repo.commit_file("test3", 3)?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("query", &["ancestors(HEAD)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        f57e36f create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = cloned_repo.run(&["sync", "-p"])?;
+        let stdout = remove_nondeterministic_lines(stdout);
+        assert!(
+            stderr.contains("branchless: this repository is a shallow clone"),
+            "{}",
+            stderr
+        );
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch --all
+        Fast-forwarding branch master to 70deb1e create test3.txt
+        Attempting rebase in-memory...
+        [1/1] Committed as: 355e173 create test4.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 355e173bf9c5d2efac2e451da0cdad3fb82b869a
+        In-memory rebase succeeded.
+        Synced f57e36f create test4.txt
+        "###);
+    }
+
+    // The commit graph is rebuilt once the boundary commits' parents are
+    // available.
+    cloned_repo.run(&["fetch", "--unshallow"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("query", &["ancestors(HEAD)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        f777ecc create initial.txt
+        62fc20d create test1.txt
+        96d1c37 create test2.txt
+        70deb1e create test3.txt
+        355e173 create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_squash_merged_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "bar"])?;
+
+    // Simulate squash-merging `foo` into the main branch.
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+    git.write_file_txt("test1", "test1 contents\n")?;
+    git.write_file_txt("test2", "test2 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "squashed foo"])?;
+    git.run(&["checkout", "foo"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        : o 62fc20d create test1.txt
+        : |
+        : @ 96d1c37 (> foo) create test2.txt
+        : |
+        : o 70deb1e (bar) create test3.txt
+        :
+        O fcf7c56 (master) squashed foo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["sync"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout master
+        Hid merged commit: 62fc20d create test1.txt
+        Hid merged commit: 96d1c37 create test2.txt
+        branchless: processing 1 update: branch foo
+        Deleted 1 branch: foo
+        Attempting rebase in-memory...
+        [1/1] Committed as: c54fd22 create test3.txt
+        branchless: processing 1 update: branch bar
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Synced 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ fcf7c56 (> master) squashed foo
+        |
+        o c54fd22 (bar) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_squash_merged_branch_confirm() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "bar"])?;
+
+    // Simulate squash-merging `foo` into the main branch.
+    git.run(&["checkout", "master"])?;
+    git.write_file_txt("test1", "test1 contents\n")?;
+    git.write_file_txt("test2", "test2 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "squashed foo"])?;
+
+    run_in_pty(
+        &git,
+        "sync",
+        &[],
+        &[
+            PtyAction::WaitUntilContains("Delete 1 branch: foo? [yN]"),
+            PtyAction::Write("n\r"),
+            PtyAction::WaitUntilContains("Not hiding the merged commits."),
+        ],
+    )?;
+
+    // The rest of the commits are still synced.
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 5e54828 (> master) squashed foo
+        |
+        o 3e2dc2a (bar) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
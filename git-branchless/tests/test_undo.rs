@@ -12,7 +12,8 @@ use lib::core::repo_ext::RepoExt;
 use lib::git::{GitRunInfo, GitVersion, Repo};
 use lib::testing::{make_git, trim_lines, Git, GitInitOptions};
 
-use cursive_core::event::Key;
+use cursive_core::event::{Event, Key, MouseButton, MouseEvent};
+use cursive_core::Vec2;
 use cursive_core::{Cursive, CursiveRunner};
 use lib::util::ExitCode;
 
@@ -101,7 +102,6 @@ fn test_undo_help() -> eyre::Result<()> {
         │O f777ecc (master) create initial.txt                                                                                 │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         │        ┌───────────────────────────────────────────┤ How to use ├───────────────────────────────────────────┐        │
         │        │ Use `git undo` to view and revert to previous states of the repository.                            │        │
         │        │                                                                                                    │        │
@@ -109,6 +109,8 @@ fn test_undo_help() -> eyre::Result<()> {
         │        │ q: Quit.                                                                                           │        │
         │        │ p/n or <left>/<right>: View next/previous state.                                                   │        │
         │        │ g: Go to a provided event ID.                                                                      │        │
+        │        │ d: Show the diffs of the commits rewritten and references moved by the current transaction.        │        │
+        │        │ +/-: Grow/shrink the events pane. (You can also drag its border with the mouse.)                   │        │
         │        │ <enter>: Revert the repository to the given state (requires confirmation).                         │        │
         │        │                                                                                                    │        │
         │        │ You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it. │        │
@@ -116,7 +118,6 @@ fn test_undo_help() -> eyre::Result<()> {
         │        │                                                                                            <Close> │        │
         │        └────────────────────────────────────────────────────────────────────────────────────────────────────┘        │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │There are no previous available events.                                                                               │
@@ -216,6 +217,191 @@ fn test_undo_navigate() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_resize_events_pane() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    for i in 2..=10 {
+        git.commit_file(&format!("test{i}"), i)?;
+    }
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["-r", &test1_oid.to_string()])?;
+
+    {
+        let screenshot1 = Default::default();
+        let screenshot2 = Default::default();
+        let screenshot3 = Default::default();
+        run_select_past_event(
+            &git.get_repo()?,
+            vec![
+                CursiveTestingEvent::Event('-'.into()),
+                CursiveTestingEvent::Event('-'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event(Event::Mouse {
+                    offset: Vec2::zero(),
+                    position: Vec2::new(5, 20),
+                    event: MouseEvent::WheelDown,
+                }),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                CursiveTestingEvent::Event(Event::Mouse {
+                    offset: Vec2::zero(),
+                    position: Vec2::new(5, 17),
+                    event: MouseEvent::Press(MouseButton::Left),
+                }),
+                CursiveTestingEvent::Event(Event::Mouse {
+                    offset: Vec2::zero(),
+                    position: Vec2::new(5, 5),
+                    event: MouseEvent::Hold(MouseButton::Left),
+                }),
+                CursiveTestingEvent::Event(Event::Mouse {
+                    offset: Vec2::zero(),
+                    position: Vec2::new(5, 5),
+                    event: MouseEvent::Release(MouseButton::Left),
+                }),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot3)),
+                CursiveTestingEvent::Event('q'.into()),
+            ],
+        )?;
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+        │@ f777ecc (master) create initial.txt                                                                                 │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+        │Repo after transaction 24 (event 24). Press 'h' for help, 'q' to quit.                                               ▒│
+        │1. Hide commit 62fc20d create test1.txt                                                                              |│
+        │                                                                                                                     |│
+        │2. Hide commit 96d1c37 create test2.txt                                                                              |│
+        │                                                                                                                     |│
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+        │@ f777ecc (master) create initial.txt                                                                                 │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+        │2. Hide commit 96d1c37 create test2.txt                                                                              ▒│
+        │                                                                                                                     |│
+        │3. Hide commit 70deb1e create test3.txt                                                                              |│
+        │                                                                                                                     |│
+        │4. Hide commit 355e173 create test4.txt                                                                              |│
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot3), @r###"
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+        │@ f777ecc (master) create initial.txt                                                                                 │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        │                                                                                                                      │
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
+        │2. Hide commit 96d1c37 create test2.txt                                                                              |│
+        │                                                                                                                     |│
+        │3. Hide commit 70deb1e create test3.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │4. Hide commit 355e173 create test4.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │5. Hide commit f81d55c create test5.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │6. Hide commit 2831fb5 create test6.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │7. Hide commit c8933b3 create test7.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │8. Hide commit 1edbaa1 create test8.txt                                                                              ▒│
+        │                                                                                                                     ▒│
+        │9. Hide commit 384010f create test9.txt                                                                              |│
+        │                                                                                                                     |│
+        └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_show_diff() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let screenshot1 = Default::default();
+        run_select_past_event(
+            &git.get_repo()?,
+            vec![
+                CursiveTestingEvent::Event('p'.into()),
+                CursiveTestingEvent::Event('d'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event('q'.into()),
+            ],
+        )?;
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        ┌───────────────────────────────────────────────────┤ Commit graph ├───────────────────────────────────────────────────┐
+        │:                             ┌────────────┤ Changes in this transaction ├────────────┐                               │
+        │@ 96d1c37 (master) create test│ 1. Check out from 62fc20d create test1.txt          ▒ │                               │
+        │                              │                to 96d1c37 create test2.txt          ▒ │                               │
+        │                              │ diff --git a/test2.txt b/test2.txt                  ▒ │                               │
+        │                              │ new file mode 100644                                ▒ │                               │
+        │                              │ index 0000000..4e512d2                              ▒ │                               │
+        │                              │ --- /dev/null                                       ▒ │                               │
+        │                              │ +++ b/test2.txt                                     ▒ │                               │
+        │                              │ @@ -0,0 +1 @@                                       ▒ │                               │
+        │                              │ +test2 contents                                     ▒ │                               │
+        │                              │                                                     ▒ │                               │
+        │                              │ 2. Move branch master from 62fc20d create test1.txt ▒ │                               │
+        │                              │                         to 96d1c37 create test2.txt ▒ │                               │
+        │                              │ diff --git a/test2.txt b/test2.txt                  ▒ │                               │
+        │                              │ new file mode 100644                                ▒ │                               │
+        └──────────────────────────────│ index 0000000..4e512d2                              ▒ │ ──────────────────────────────┘
+        ┌──────────────────────────────│ --- /dev/null                                       ▒ │ ──────────────────────────────┐
+        │Repo after transaction 3 (even│ +++ b/test2.txt                                     | │                               │
+        │1. Check out from 62fc20d crea│ @@ -0,0 +1 @@                                       | │                               │
+        │               to 96d1c37 crea│                                                       │                               │
+        │2. Move branch master from 62f│                                               <Close> │                               │
+        │                        to 96d└───────────────────────────────────────────────────────┘                               │
+        └───────────────────────────────                                                         ──────────────────────────────┘
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_go_to_event() -> eyre::Result<()> {
     let git = make_git()?;
@@ -311,7 +497,7 @@ fn test_undo_hide() -> eyre::Result<()> {
     git.commit_file("test1", 1)?;
     git.run(&["checkout", "HEAD^"])?;
     git.commit_file("test2", 2)?;
-    git.branchless("hide", &["test1"])?;
+    git.branchless("hide", &["--force", "test1"])?;
     git.run(&["branch", "-D", "test1"])?;
 
     {
@@ -432,7 +618,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
 
     git.init_repo()?;
     git.commit_file("test1", 1)?;
-    git.branchless("hide", &["HEAD"])?;
+    git.branchless("hide", &["--force", "HEAD"])?;
 
     let screenshot1 = Default::default();
     let screenshot2 = Default::default();
@@ -722,14 +908,15 @@ fn test_undo_garbage_collected_commit() -> eyre::Result<()> {
     git.commit_file("test1", 1)?;
     git.detach_head()?;
     git.commit_file("test2", 2)?;
-    git.branchless("hide", &["HEAD"])?;
+    git.branchless("hide", &["--force", "HEAD"])?;
     git.run(&["checkout", "HEAD^"])?;
 
     {
         let (stdout, _stderr) = git.branchless("gc", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         branchless: collecting garbage
-        branchless: 1 dangling reference deleted
+        branchless: 1 dangling reference deleted:
+        - 96d1c37 create test2.txt
         "###);
     }
     git.run(&["gc", "--prune=now"])?;
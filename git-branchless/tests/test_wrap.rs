@@ -1,3 +1,4 @@
+use itertools::Itertools;
 use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
@@ -374,3 +375,264 @@ fn test_wrap_exit_code() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn test_wrap_propagates_signal() -> eyre::Result<()> {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.branchless_with_options(
+        "wrap",
+        &["--git-executable", "sh", "--", "-c", "exit 42"],
+        &GitRunOptions {
+            expected_exit_code: 42,
+            ..Default::default()
+        },
+    )?;
+
+    // Invoke `git-branchless` directly, since `git` would report the signal
+    // as an exit code.
+    let status = Command::new("git-branchless")
+        .current_dir(&git.repo_path)
+        .env_clear()
+        .envs(git.get_base_env(0))
+        .args([
+            "wrap",
+            "--git-executable",
+            "sh",
+            "--",
+            "-c",
+            "kill -TERM $$",
+        ])
+        .status()?;
+    assert_eq!(status.signal(), Some(15 /* SIGTERM */), "{status:?}");
+
+    Ok(())
+}
+
+#[test]
+fn test_wrap_program() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.write_file_txt("test1", "contents\n")?;
+    git.branchless(
+        "wrap",
+        &[
+            "--exec",
+            "-m",
+            "my script",
+            "--",
+            "sh",
+            "-c",
+            "git add test1.txt && git commit -q -m 'create test1.txt' && git branch foo",
+        ],
+    )?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    let last_event_tx_id = events.last().unwrap().get_event_tx_id();
+    let wrapped_events: Vec<Event> = events
+        .iter()
+        .filter(|event| event.get_event_tx_id() == last_event_tx_id)
+        .map(|event| redact_event_timestamp(event.clone()))
+        .collect();
+    insta::assert_debug_snapshot!(wrapped_events, @r###"
+    [
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "HEAD",
+            ),
+            old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            new_oid: 6391e7f11cfb9b686f2a41ed02aaebbd6699d3db,
+            message: None,
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/master",
+            ),
+            old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            new_oid: 6391e7f11cfb9b686f2a41ed02aaebbd6699d3db,
+            message: None,
+        },
+        CommitEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            commit_oid: NonZeroOid(6391e7f11cfb9b686f2a41ed02aaebbd6699d3db),
+        },
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/foo",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: 6391e7f11cfb9b686f2a41ed02aaebbd6699d3db,
+            message: None,
+        },
+    ]
+    "###);
+    insta::assert_snapshot!(event_log_db.get_transaction_message(last_event_tx_id)?, @r###"
+    my script
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_wrap_nested() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.write_file_txt("test1", "contents\n")?;
+    git.branchless(
+        "wrap",
+        &[
+            "--exec",
+            "-m",
+            "outer",
+            "--",
+            "sh",
+            "-c",
+            "git add test1.txt && git branchless wrap -m inner -- commit -q -m 'create test1.txt' && git branch foo",
+        ],
+    )?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    let event_tx_ids: Vec<_> = events
+        .iter()
+        .map(|event| event.get_event_tx_id())
+        .dedup()
+        .collect();
+    insta::assert_debug_snapshot!(event_tx_ids, @r###"
+    [
+        EventTransactionId(
+            1,
+        ),
+    ]
+    "###);
+    insta::assert_snapshot!(event_log_db.get_transaction_message(event_tx_ids[0])?, @r###"
+    outer
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_wrap_program_other_repo() -> eyre::Result<()> {
+    let git = make_git()?;
+    let other_git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    other_git.init_repo()?;
+    git.branchless(
+        "wrap",
+        &[
+            "--exec",
+            "-m",
+            "my script",
+            "--",
+            "sh",
+            "-c",
+            &format!(
+                "git -C '{}' branch bar && git branch foo",
+                other_git.repo_path.display()
+            ),
+        ],
+    )?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events: Vec<Event> = get_event_replayer_events(&event_replayer)
+        .iter()
+        .map(|event| redact_event_timestamp(event.clone()))
+        .collect();
+    insta::assert_debug_snapshot!(events, @r###"
+    [
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/foo",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            message: None,
+        },
+    ]
+    "###);
+
+    let other_repo = other_git.get_repo()?;
+    let other_conn = other_repo.get_db_conn()?;
+    let other_event_log_db = EventLogDb::new(&other_conn)?;
+    let other_event_replayer =
+        EventReplayer::from_event_log_db(&effects, &other_repo, &other_event_log_db)?;
+    let other_events: Vec<Event> = get_event_replayer_events(&other_event_replayer)
+        .iter()
+        .map(|event| redact_event_timestamp(event.clone()))
+        .collect();
+    insta::assert_debug_snapshot!(other_events, @r###"
+    [
+        RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id: EventTransactionId(
+                1,
+            ),
+            ref_name: ReferenceName(
+                "refs/heads/bar",
+            ),
+            old_oid: 0000000000000000000000000000000000000000,
+            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
+            message: None,
+        },
+    ]
+    "###);
+    let other_event_tx_id = other_events.last().unwrap().get_event_tx_id();
+    insta::assert_snapshot!(other_event_log_db.get_transaction_message(other_event_tx_id)?, @r###"
+    reference-transaction
+    "###);
+
+    Ok(())
+}
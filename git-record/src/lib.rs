@@ -8,6 +8,9 @@
 //! reimplementation of `hg crecord`. Given a set of changes made by the user,
 //! this component presents them to the user and lets them select which of those
 //! changes should be staged for commit.
+//!
+//! It also provides a component to resolve merge conflicts by picking which
+//! side of each conflicted section to keep.
 
 #![warn(missing_docs)]
 #![warn(
@@ -19,10 +22,15 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 mod cursive_utils;
+mod resolve;
 mod tristate;
 mod types;
 mod ui;
 
 pub use cursive_utils::testing;
-pub use types::{FileState, RecordError, RecordState, Section, SectionChangedLine};
+pub use resolve::ConflictResolver;
+pub use types::{
+    ConflictFileState, ConflictResolution, ConflictSection, FileState, RecordError, RecordState,
+    ResolveState, Section, SectionChangedLine,
+};
 pub use ui::Recorder;
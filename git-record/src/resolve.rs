@@ -0,0 +1,530 @@
+use std::borrow::Cow;
+use std::cmp::min;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+use cursive::event::Event;
+use cursive::theme::Effect;
+use cursive::traits::{Nameable, Resizable};
+use cursive::utils::markup::StyledString;
+use cursive::views::{Dialog, LinearLayout, OnEventView, Panel, ScrollView, SelectView, TextView};
+use cursive::{CursiveRunnable, CursiveRunner, View};
+
+use crate::cursive_utils::{EventDrivenCursiveApp, EventDrivenCursiveAppExt};
+use crate::{ConflictFileState, ConflictResolution, ConflictSection, RecordError, ResolveState};
+
+/// The resolutions which the user can pick for a conflict, in the order that
+/// they're displayed.
+const RESOLUTION_CHOICES: [(&str, Option<ConflictResolution>); 5] = [
+    ("unresolved", None),
+    ("ours", Some(ConflictResolution::Ours)),
+    ("theirs", Some(ConflictResolution::Theirs)),
+    (
+        "ours, then theirs",
+        Some(ConflictResolution::OursThenTheirs),
+    ),
+    ("base", Some(ConflictResolution::Base)),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConflictKey {
+    file_num: usize,
+    section_num: usize,
+}
+
+impl ConflictKey {
+    fn view_id(&self) -> String {
+        let Self {
+            file_num,
+            section_num,
+        } = self;
+        format!("ConflictKey({file_num},{section_num})")
+    }
+}
+
+/// UI component to resolve merge conflicts by picking a side for each
+/// conflicted section.
+pub struct ConflictResolver<'a> {
+    did_user_confirm_exit: bool,
+    header: StyledString,
+    state: ResolveState<'a>,
+}
+
+impl<'a> ConflictResolver<'a> {
+    /// Constructor. The `header` is displayed above the conflicts, and should
+    /// explain what is being merged.
+    pub fn new(header: impl Into<StyledString>, state: ResolveState<'a>) -> Self {
+        Self {
+            did_user_confirm_exit: false,
+            header: header.into(),
+            state,
+        }
+    }
+
+    /// Run the terminal user interface and have the user interactively
+    /// resolve the conflicts.
+    pub fn run(self, siv: CursiveRunner<CursiveRunnable>) -> Result<ResolveState<'a>, RecordError> {
+        EventDrivenCursiveAppExt::run(self, siv)
+    }
+
+    fn make_main_view(&self, main_tx: Sender<Message>) -> impl View {
+        let mut view = LinearLayout::vertical();
+        view.add_child(TextView::new(self.header.clone()));
+        view.add_child(TextView::new(
+            "o: ours, t: theirs, b: both, a: base, c: continue, q: resolve on disk instead",
+        ));
+        view.add_child(TextView::new(" "));
+
+        let ResolveState { file_states } = &self.state;
+        let global_num_conflicts: usize = file_states
+            .iter()
+            .map(|(_path, file_state)| file_state.count_conflicts())
+            .sum();
+        let mut global_conflict_num = 0;
+
+        for (file_num, (path, file_state)) in file_states.iter().enumerate() {
+            view.add_child(self.make_file_view(
+                main_tx.clone(),
+                path,
+                file_num,
+                file_state,
+                &mut global_conflict_num,
+                global_num_conflicts,
+            ));
+            if file_num + 1 < file_states.len() {
+                // Render a spacer line. Note that an empty string won't render an
+                // empty line.
+                view.add_child(TextView::new(" "));
+            }
+        }
+
+        view
+    }
+
+    fn make_file_view(
+        &self,
+        main_tx: Sender<Message>,
+        path: &Path,
+        file_num: usize,
+        file_state: &ConflictFileState,
+        global_conflict_num: &mut usize,
+        global_num_conflicts: usize,
+    ) -> impl View {
+        let mut file_view = LinearLayout::vertical();
+        let mut line_num: usize = 1;
+        let local_num_conflicts = file_state.count_conflicts();
+        let mut local_conflict_num = 0;
+
+        file_view.add_child(TextView::new({
+            let mut s = StyledString::new();
+            s.append_styled(path.to_string_lossy(), Effect::Bold);
+            s
+        }));
+
+        let ConflictFileState { sections } = file_state;
+        for (section_num, section) in sections.iter().enumerate() {
+            match section {
+                ConflictSection::Unchanged { contents } => {
+                    const CONTEXT: usize = 2;
+
+                    // Add the trailing context for the previous section (if any).
+                    if section_num > 0 {
+                        let end_index = min(CONTEXT, contents.len());
+                        for (i, line) in contents[..end_index].iter().enumerate() {
+                            file_view.add_child(TextView::new(format!(
+                                "  {} {}",
+                                line_num + i,
+                                line
+                            )));
+                        }
+                    }
+
+                    // Add vertical ellipsis between sections.
+                    if section_num > 0 && section_num + 1 < sections.len() {
+                        file_view.add_child(TextView::new(":"));
+                    }
+
+                    // Add the leading context for the next section (if any).
+                    if section_num + 1 < sections.len() {
+                        let start_index = contents.len().saturating_sub(CONTEXT);
+                        for (i, line) in contents[start_index..].iter().enumerate() {
+                            file_view.add_child(TextView::new(format!(
+                                "  {} {}",
+                                line_num + start_index + i,
+                                line
+                            )));
+                        }
+                    }
+
+                    line_num += contents.len();
+                }
+
+                ConflictSection::Conflicted {
+                    base,
+                    ours,
+                    theirs,
+                    resolution,
+                } => {
+                    local_conflict_num += 1;
+                    *global_conflict_num += 1;
+                    let description = format!(
+                        "conflict {local_conflict_num}/{local_num_conflicts} in current file, {global_conflict_num}/{global_num_conflicts} total"
+                    );
+                    let conflict_key = ConflictKey {
+                        file_num,
+                        section_num,
+                    };
+                    file_view.add_child(self.make_conflict_view(
+                        main_tx.clone(),
+                        conflict_key,
+                        description,
+                        base,
+                        ours,
+                        theirs,
+                        *resolution,
+                    ));
+
+                    // Number lines according to our side of the merge.
+                    line_num += ours.len();
+                }
+            }
+        }
+
+        file_view
+    }
+
+    fn make_conflict_view(
+        &self,
+        main_tx: Sender<Message>,
+        conflict_key: ConflictKey,
+        description: String,
+        base: &[Cow<str>],
+        ours: &[Cow<str>],
+        theirs: &[Cow<str>],
+        resolution: Option<ConflictResolution>,
+    ) -> impl View {
+        let resolution_view = {
+            let mut select_view = SelectView::new().popup();
+            for (label, value) in RESOLUTION_CHOICES {
+                select_view.add_item(label, value);
+            }
+            select_view.set_selection(get_resolution_index(resolution));
+            select_view
+                .on_submit({
+                    let main_tx = main_tx.clone();
+                    move |_, resolution: &Option<ConflictResolution>| {
+                        if main_tx
+                            .send(Message::SetResolution(conflict_key, *resolution))
+                            .is_err()
+                        {
+                            // Do nothing.
+                        }
+                    }
+                })
+                .with_name(conflict_key.view_id())
+        };
+
+        let header_view = LinearLayout::horizontal()
+            .child(TextView::new("  "))
+            .child(resolution_view)
+            .child(TextView::new(format!(" {description}")));
+
+        let make_side_view = |title: &str, lines: &[Cow<str>]| {
+            let contents = lines
+                .iter()
+                .map(|line| line.trim_end_matches(['\r', '\n']))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Panel::new(TextView::new(contents))
+                .title(title)
+                .full_width()
+        };
+        let sides_view = LinearLayout::horizontal()
+            .child(TextView::new("  "))
+            .child(make_side_view("ours", ours))
+            .child(make_side_view("base", base))
+            .child(make_side_view("theirs", theirs));
+
+        let mut view = OnEventView::new(
+            LinearLayout::vertical()
+                .child(header_view)
+                .child(sides_view),
+        );
+        for (key, resolution) in [
+            ('o', ConflictResolution::Ours),
+            ('t', ConflictResolution::Theirs),
+            ('b', ConflictResolution::OursThenTheirs),
+            ('a', ConflictResolution::Base),
+        ] {
+            let main_tx = main_tx.clone();
+            view.set_on_event(key, move |_| {
+                if main_tx
+                    .send(Message::SetResolution(conflict_key, Some(resolution)))
+                    .is_err()
+                {
+                    // Do nothing.
+                }
+            });
+        }
+        view
+    }
+
+    fn set_resolution(
+        &mut self,
+        siv: &mut CursiveRunner<CursiveRunnable>,
+        conflict_key: ConflictKey,
+        new_resolution: Option<ConflictResolution>,
+    ) {
+        let ConflictKey {
+            file_num,
+            section_num,
+        } = conflict_key;
+        let (_path, ConflictFileState { sections }) = &mut self.state.file_states[file_num];
+        match &mut sections[section_num] {
+            ConflictSection::Unchanged { .. } => {
+                panic!("Invalid section num to resolve");
+            }
+            ConflictSection::Conflicted { resolution, .. } => {
+                *resolution = new_resolution;
+            }
+        }
+
+        siv.call_on_name(
+            &conflict_key.view_id(),
+            |select_view: &mut SelectView<Option<ConflictResolution>>| {
+                // Ignore the returned callback, since it would only notify us
+                // of the change we just made.
+                let _callback = select_view.set_selection(get_resolution_index(new_resolution));
+            },
+        );
+    }
+
+    fn count_unresolved_conflicts(&self) -> usize {
+        let ResolveState { file_states } = &self.state;
+        file_states
+            .iter()
+            .map(|(_path, file_state)| file_state.count_unresolved_conflicts())
+            .sum()
+    }
+}
+
+fn get_resolution_index(resolution: Option<ConflictResolution>) -> usize {
+    RESOLUTION_CHOICES
+        .iter()
+        .position(|(_label, value)| *value == resolution)
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Debug)]
+pub enum Message {
+    Init,
+    SetResolution(ConflictKey, Option<ConflictResolution>),
+    Confirm,
+    Quit,
+}
+
+impl<'a> EventDrivenCursiveApp for ConflictResolver<'a> {
+    type Message = Message;
+
+    type Return = Result<ResolveState<'a>, RecordError>;
+
+    fn get_init_message(&self) -> Self::Message {
+        Message::Init
+    }
+
+    fn get_key_bindings(&self) -> Vec<(Event, Self::Message)> {
+        vec![
+            ('c'.into(), Message::Confirm),
+            ('C'.into(), Message::Confirm),
+            ('q'.into(), Message::Quit),
+            ('Q'.into(), Message::Quit),
+        ]
+    }
+
+    fn handle_message(
+        &mut self,
+        siv: &mut CursiveRunner<CursiveRunnable>,
+        main_tx: Sender<Self::Message>,
+        message: Self::Message,
+    ) {
+        match message {
+            Message::Init => {
+                let main_view = self.make_main_view(main_tx);
+                siv.add_layer(ScrollView::new(
+                    // NB: you can't add `min_width` to the `ScrollView` itself,
+                    // or else the scrollbar stops responding to clicks and
+                    // drags.
+                    main_view.min_width(80),
+                ));
+            }
+
+            Message::SetResolution(conflict_key, resolution) => {
+                self.set_resolution(siv, conflict_key, resolution);
+            }
+
+            Message::Confirm => match self.count_unresolved_conflicts() {
+                0 => {
+                    self.did_user_confirm_exit = true;
+                    siv.quit();
+                }
+                1 => {
+                    siv.add_layer(Dialog::info("1 conflict is still unresolved."));
+                }
+                num_unresolved => {
+                    siv.add_layer(Dialog::info(format!(
+                        "{num_unresolved} conflicts are still unresolved."
+                    )));
+                }
+            },
+
+            Message::Quit => {
+                siv.quit();
+            }
+        }
+    }
+
+    fn finish(self) -> Self::Return {
+        if self.did_user_confirm_exit {
+            Ok(self.state)
+        } else {
+            Err(RecordError::Cancelled)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    use cursive::event::Key;
+
+    use crate::cursive_utils::testing::{
+        screen_to_string, CursiveTestingBackend, CursiveTestingEvent,
+    };
+
+    use super::*;
+
+    fn run_test(
+        state: ResolveState,
+        events: Vec<CursiveTestingEvent>,
+    ) -> Result<ResolveState, RecordError> {
+        let siv = CursiveRunnable::new::<Infallible, _>(move || {
+            Ok(CursiveTestingBackend::init(events.clone()))
+        });
+        let resolver = ConflictResolver::new("Resolving conflicts", state);
+        resolver.run(siv.into_runner())
+    }
+
+    fn example_resolve_state() -> ResolveState<'static> {
+        ResolveState {
+            file_states: vec![(
+                PathBuf::from("foo"),
+                ConflictFileState {
+                    sections: vec![
+                        ConflictSection::Unchanged {
+                            contents: vec![
+                                Cow::Borrowed("unchanged 1\n"),
+                                Cow::Borrowed("unchanged 2\n"),
+                            ],
+                        },
+                        ConflictSection::Conflicted {
+                            base: vec![Cow::Borrowed("base\n")],
+                            ours: vec![Cow::Borrowed("ours 1\n"), Cow::Borrowed("ours 2\n")],
+                            theirs: vec![Cow::Borrowed("theirs\n")],
+                            resolution: None,
+                        },
+                        ConflictSection::Unchanged {
+                            contents: vec![Cow::Borrowed("unchanged 3\n")],
+                        },
+                    ],
+                },
+            )],
+        }
+    }
+
+    #[test]
+    fn test_resolve_conflict() {
+        let screenshot1 = Default::default();
+        let screenshot2 = Default::default();
+        let screenshot3 = Default::default();
+        let result = run_test(
+            example_resolve_state(),
+            vec![
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event('c'.into()), // try to confirm
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                CursiveTestingEvent::Event(Key::Enter.into()), // dismiss dialog
+                CursiveTestingEvent::Event('t'.into()),        // pick theirs
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot3)),
+                CursiveTestingEvent::Event('c'.into()),
+            ],
+        );
+
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        Resolving conflicts
+         o: ours, t: theirs, b: both, a: base, c: continue, q: resolve on disk instead
+
+         foo
+           1 unchanged 1
+           2 unchanged 2
+           <   unresolved    > conflict 1/1 in current file, 1/1 total
+           ┌──────────────┤ ours ├──────────────┐┌──────────────┤ base ├──────────────┐┌──────────────┤ theirs ├──────────────┐
+           │ours 1                              ││base                                ││theirs                                │
+           │ours 2                              ││                                    ││                                      │
+           └────────────────────────────────────┘└────────────────────────────────────┘└──────────────────────────────────────┘
+           5 unchanged 3
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+        Resolving conflicts
+         o: ours, t: theirs, b: both, a: base, c: continue, q: resolve on disk instead
+
+         foo                                      ┌─────────────────────────────────┐
+           1 unchanged 1                          │ 1 conflict is still unresolved. │
+           2 unchanged 2                          │                                 │
+           <   unresolved    > conflict 1/1 in cur│                            <Ok> │
+           ┌──────────────┤ ours ├──────────────┐┌└─────────────────────────────────┘ ┐┌──────────────┤ theirs ├──────────────┐
+           │ours 1                              ││b                                   ││theirs                                │
+           │ours 2                              ││                                    ││                                      │
+           └────────────────────────────────────┘└────────────────────────────────────┘└──────────────────────────────────────┘
+           5 unchanged 3
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot3), @r###"
+        Resolving conflicts
+         o: ours, t: theirs, b: both, a: base, c: continue, q: resolve on disk instead
+
+         foo
+           1 unchanged 1
+           2 unchanged 2
+           <     theirs      > conflict 1/1 in current file, 1/1 total
+           ┌──────────────┤ ours ├──────────────┐┌──────────────┤ base ├──────────────┐┌──────────────┤ theirs ├──────────────┐
+           │ours 1                              ││base                                ││theirs                                │
+           │ours 2                              ││                                    ││                                      │
+           └────────────────────────────────────┘└────────────────────────────────────┘└──────────────────────────────────────┘
+           5 unchanged 3
+        "###);
+        let (_path, file_state) = &result.unwrap().file_states[0];
+        insta::assert_debug_snapshot!(file_state.get_resolved_contents(), @r###"
+        Some(
+            "unchanged 1\nunchanged 2\ntheirs\nunchanged 3\n",
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_resolve_conflict_quit() {
+        let result = run_test(
+            example_resolve_state(),
+            vec![
+                CursiveTestingEvent::Event('o'.into()),
+                CursiveTestingEvent::Event('q'.into()),
+            ],
+        );
+        insta::assert_debug_snapshot!(result.map(|_| ()), @r###"
+        Err(
+            Cancelled,
+        )
+        "###);
+    }
+}
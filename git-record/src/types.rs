@@ -197,3 +197,126 @@ impl<'a> SectionChangedLine<'a> {
         }
     }
 }
+
+/// The state used to render merge conflicts. This is passed into
+/// [`ConflictResolver::new`] and then updated and returned with
+/// [`ConflictResolver::run`].
+#[derive(Clone, Debug)]
+pub struct ResolveState<'a> {
+    /// The conflicts in each file. This is rendered in order, so you may want
+    /// to sort this list by path before providing it.
+    pub file_states: Vec<(PathBuf, ConflictFileState<'a>)>,
+}
+
+/// The state of a file with merge conflicts.
+#[derive(Clone, Debug)]
+pub struct ConflictFileState<'a> {
+    /// The set of [`ConflictSection`]s inside the file.
+    pub sections: Vec<ConflictSection<'a>>,
+}
+
+impl ConflictFileState<'_> {
+    /// Count the number of conflicted sections in this file.
+    pub fn count_conflicts(&self) -> usize {
+        let Self { sections } = self;
+        sections
+            .iter()
+            .filter(|section| match section {
+                ConflictSection::Unchanged { .. } => false,
+                ConflictSection::Conflicted { .. } => true,
+            })
+            .count()
+    }
+
+    /// Count the number of conflicted sections in this file which the user
+    /// hasn't resolved yet.
+    pub fn count_unresolved_conflicts(&self) -> usize {
+        let Self { sections } = self;
+        sections
+            .iter()
+            .filter(|section| match section {
+                ConflictSection::Unchanged { .. } => false,
+                ConflictSection::Conflicted { resolution, .. } => resolution.is_none(),
+            })
+            .count()
+    }
+
+    /// Calculate the contents of the file according to the user's chosen
+    /// resolutions, or `None` if any conflicts are still unresolved.
+    pub fn get_resolved_contents(&self) -> Option<String> {
+        let mut acc = String::new();
+        let Self { sections } = self;
+        for section in sections {
+            match section {
+                ConflictSection::Unchanged { contents } => {
+                    for line in contents {
+                        acc.push_str(line);
+                    }
+                }
+                ConflictSection::Conflicted {
+                    base,
+                    ours,
+                    theirs,
+                    resolution,
+                } => {
+                    let lines: Box<dyn Iterator<Item = &Cow<str>>> = match resolution {
+                        None => return None,
+                        Some(ConflictResolution::Ours) => Box::new(ours.iter()),
+                        Some(ConflictResolution::Theirs) => Box::new(theirs.iter()),
+                        Some(ConflictResolution::OursThenTheirs) => {
+                            Box::new(ours.iter().chain(theirs.iter()))
+                        }
+                        Some(ConflictResolution::Base) => Box::new(base.iter()),
+                    };
+                    for line in lines {
+                        acc.push_str(line);
+                    }
+                }
+            }
+        }
+        Some(acc)
+    }
+}
+
+/// A section of a file with merge conflicts.
+#[derive(Clone, Debug)]
+pub enum ConflictSection<'a> {
+    /// This section of the file merged cleanly and is just used for context.
+    Unchanged {
+        /// The contents of the lines in this section. Each line includes its
+        /// trailing newline character(s), if any.
+        contents: Vec<Cow<'a, str>>,
+    },
+
+    /// This section of the file couldn't be merged, and the user needs to
+    /// pick which version to keep.
+    Conflicted {
+        /// The contents of the lines at the common ancestor of both sides.
+        base: Vec<Cow<'a, str>>,
+
+        /// The contents of the lines on our side of the merge.
+        ours: Vec<Cow<'a, str>>,
+
+        /// The contents of the lines on their side of the merge.
+        theirs: Vec<Cow<'a, str>>,
+
+        /// How the user chose to resolve the conflict, if they have.
+        resolution: Option<ConflictResolution>,
+    },
+}
+
+/// How to resolve a conflicted section.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ConflictResolution {
+    /// Keep our version of the lines.
+    Ours,
+
+    /// Keep their version of the lines.
+    Theirs,
+
+    /// Keep both versions of the lines, with ours first.
+    OursThenTheirs,
+
+    /// Keep the lines from the common ancestor, discarding both changes.
+    Base,
+}
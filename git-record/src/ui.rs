@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::min;
 use std::path::Path;
 use std::sync::mpsc::Sender;
@@ -6,7 +7,9 @@ use cursive::event::Event;
 use cursive::theme::{BaseColor, Effect};
 use cursive::traits::{Nameable, Resizable};
 use cursive::utils::markup::StyledString;
-use cursive::views::{Checkbox, Dialog, HideableView, LinearLayout, ScrollView, TextView};
+use cursive::views::{
+    Checkbox, Dialog, HideableView, LinearLayout, OnEventView, ScrollView, TextArea, TextView,
+};
 use cursive::{CursiveRunnable, CursiveRunner, View};
 use tracing::error;
 
@@ -289,45 +292,57 @@ impl<'a> Recorder<'a> {
             ));
         }
 
-        view.add_child(
-            LinearLayout::horizontal()
-                .child(TextView::new("  "))
-                .child(
-                    TristateBox::new()
-                        .with_state(
-                            all_are_same_value(before.iter().chain(after.iter()).map(
-                                |SectionChangedLine {
-                                     is_selected,
-                                     line: _,
-                                 }| { *is_selected },
-                            ))
-                            .into_tristate(),
-                        )
-                        .on_change({
-                            let section_key = SectionKey {
-                                file_num,
-                                section_num,
-                            };
-                            let main_tx = main_tx;
-                            move |_, new_value| {
-                                if main_tx
-                                    .send(Message::ToggleHunk(section_key, new_value))
-                                    .is_err()
-                                {
-                                    // Do nothing.
-                                }
+        let section_header_view = LinearLayout::horizontal()
+            .child(TextView::new("  "))
+            .child(
+                TristateBox::new()
+                    .with_state(
+                        all_are_same_value(before.iter().chain(after.iter()).map(
+                            |SectionChangedLine {
+                                 is_selected,
+                                 line: _,
+                             }| { *is_selected },
+                        ))
+                        .into_tristate(),
+                    )
+                    .on_change({
+                        let section_key = SectionKey {
+                            file_num,
+                            section_num,
+                        };
+                        let main_tx = main_tx.clone();
+                        move |_, new_value| {
+                            if main_tx
+                                .send(Message::ToggleHunk(section_key, new_value))
+                                .is_err()
+                            {
+                                // Do nothing.
                             }
-                        })
-                        .with_name(section_key.view_id()),
-                )
-                .child(TextView::new({
-                    let mut s = StyledString::new();
-                    s.append_plain(" ");
-                    s.append_plain(section_description);
-                    s
-                })),
+                        }
+                    })
+                    .with_name(section_key.view_id()),
+            )
+            .child(TextView::new({
+                let mut s = StyledString::new();
+                s.append_plain(" ");
+                s.append_plain(section_description);
+                s
+            }));
+
+        // Pressing `e` anywhere in the section opens the editor for the whole
+        // section.
+        view.add_child(
+            OnEventView::new(
+                LinearLayout::vertical()
+                    .child(section_header_view)
+                    .child(HideableView::new(section_view)),
+            )
+            .on_event('e', move |_| {
+                if main_tx.send(Message::EditHunk(section_key)).is_err() {
+                    // Do nothing.
+                }
+            }),
         );
-        view.add_child(HideableView::new(section_view));
     }
 
     fn make_changed_line_view(
@@ -629,8 +644,141 @@ impl<'a> Recorder<'a> {
             tristate_box.set_state(file_new_value);
         });
     }
+
+    fn get_changed_section_mut(
+        &mut self,
+        section_key: SectionKey,
+    ) -> (
+        &mut Vec<SectionChangedLine<'a>>,
+        &mut Vec<SectionChangedLine<'a>>,
+    ) {
+        let SectionKey {
+            file_num,
+            section_num,
+        } = section_key;
+        let (
+            path,
+            FileState {
+                file_mode: _,
+                sections,
+            },
+        ) = &mut self.state.file_states[file_num];
+        match &mut sections[section_num] {
+            Section::Unchanged { contents } => {
+                error!(
+                    ?section_num,
+                    ?path,
+                    ?contents,
+                    "Invalid section num to edit"
+                );
+                panic!("Invalid section num to edit");
+            }
+            Section::Changed { before, after } => (before, after),
+            Section::FileMode {
+                is_selected: _,
+                before: _,
+                after: _,
+            } => {
+                unimplemented!("edit_section for Section::FileMode");
+            }
+        }
+    }
+
+    fn edit_section(
+        &mut self,
+        siv: &mut CursiveRunner<CursiveRunnable>,
+        main_tx: Sender<Message>,
+        section_key: SectionKey,
+    ) {
+        // Start from the contents that would currently be recorded for this
+        // section.
+        let contents: String = {
+            let (before, after) = self.get_changed_section_mut(section_key);
+            before
+                .iter()
+                .filter(|changed_line| !changed_line.is_selected)
+                .chain(after.iter().filter(|changed_line| changed_line.is_selected))
+                .map(|changed_line| changed_line.line.as_ref())
+                .collect()
+        };
+
+        siv.add_layer(
+            Dialog::around(
+                TextArea::new()
+                    .content(contents)
+                    .with_name(EDIT_SECTION_VIEW_ID)
+                    .min_size((80, 10)),
+            )
+            .title("Edit section")
+            .button("Save", move |siv| {
+                let contents = siv
+                    .call_on_name(EDIT_SECTION_VIEW_ID, |text_area: &mut TextArea| {
+                        text_area.get_content().to_owned()
+                    })
+                    .unwrap_or_default();
+                siv.pop_layer();
+                if main_tx
+                    .send(Message::SetHunkContents(section_key, contents))
+                    .is_err()
+                {
+                    // Do nothing.
+                }
+            })
+            .dismiss_button("Cancel"),
+        );
+    }
+
+    /// Replace the contents to be recorded for the given section. All of the
+    /// original lines are removed, and the edited lines are added in their
+    /// place. The final line keeps its trailing newline even if it was removed
+    /// in the editor, unless the original final line didn't have one either.
+    fn set_section_contents(
+        &mut self,
+        siv: &mut CursiveRunner<CursiveRunnable>,
+        main_tx: Sender<Message>,
+        section_key: SectionKey,
+        mut contents: String,
+    ) {
+        let (before, after) = self.get_changed_section_mut(section_key);
+        let original_has_trailing_newline = before
+            .iter()
+            .filter(|changed_line| !changed_line.is_selected)
+            .chain(after.iter().filter(|changed_line| changed_line.is_selected))
+            .last()
+            .map_or(true, |changed_line| changed_line.line.ends_with('\n'));
+        if original_has_trailing_newline && !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        for changed_line in before.iter_mut() {
+            changed_line.is_selected = true;
+        }
+        *after = contents
+            .split_inclusive('\n')
+            .map(|line| SectionChangedLine {
+                is_selected: true,
+                line: Cow::Owned(line.to_owned()),
+            })
+            .collect();
+
+        // The number of lines in the section may have changed, so rebuild the
+        // whole view rather than updating the existing checkboxes.
+        siv.pop_layer();
+        self.add_main_view(siv, main_tx);
+    }
+
+    fn add_main_view(&self, siv: &mut CursiveRunner<CursiveRunnable>, main_tx: Sender<Message>) {
+        let main_view = self.make_main_view(main_tx);
+        siv.add_layer(ScrollView::new(
+            // NB: you can't add `min_width` to the `ScrollView` itself,
+            // or else the scrollbar stops responding to clicks and
+            // drags.
+            main_view.min_width(80),
+        ));
+    }
 }
 
+const EDIT_SECTION_VIEW_ID: &str = "EditSection";
+
 fn iter_file_selections<'a>(file_state: &'a FileState) -> impl Iterator<Item = bool> + 'a {
     let FileState {
         file_mode: _,
@@ -663,6 +811,8 @@ pub enum Message {
     ToggleFile(FileKey, Tristate),
     ToggleHunk(SectionKey, Tristate),
     ToggleHunkLine(SectionLineKey, bool),
+    EditHunk(SectionKey),
+    SetHunkContents(SectionKey, String),
     Confirm,
     Quit,
 }
@@ -693,13 +843,7 @@ impl<'a> EventDrivenCursiveApp for Recorder<'a> {
     ) {
         match message {
             Message::Init => {
-                let main_view = self.make_main_view(main_tx);
-                siv.add_layer(ScrollView::new(
-                    // NB: you can't add `min_width` to the `ScrollView` itself,
-                    // or else the scrollbar stops responding to clicks and
-                    // drags.
-                    main_view.min_width(80),
-                ));
+                self.add_main_view(siv, main_tx);
             }
 
             Message::ToggleFile(file_key, new_value) => {
@@ -714,6 +858,14 @@ impl<'a> EventDrivenCursiveApp for Recorder<'a> {
                 self.toggle_section_line(siv, section_line_key, new_value);
             }
 
+            Message::EditHunk(section_key) => {
+                self.edit_section(siv, main_tx, section_key);
+            }
+
+            Message::SetHunkContents(section_key, contents) => {
+                self.set_section_contents(siv, main_tx, section_key, contents);
+            }
+
             Message::Confirm => {
                 self.did_user_confirm_exit = true;
                 siv.quit();
@@ -1310,4 +1462,145 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_edit_section() {
+        let screenshot1 = Default::default();
+        let screenshot2 = Default::default();
+        let result = run_test(
+            example_record_state(),
+            vec![
+                CursiveTestingEvent::Event(Key::Down.into()), // move to section
+                CursiveTestingEvent::Event('e'.into()),       // edit section
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event('x'.into()),
+                CursiveTestingEvent::Event(Key::Tab.into()), // move to "Save"
+                CursiveTestingEvent::Event(Key::Enter.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                CursiveTestingEvent::Event('c'.into()),
+            ],
+        );
+
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        ┌─────────────────────────────────┤ Edit section ├─────────────────────────────────┐
+                          │ after 1                                                                          │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                                  │
+                          │                                                                  <Save> <Cancel> │
+                          └──────────────────────────────────────────────────────────────────────────────────┘
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+        [X] foo
+                              1 unchanged 1
+                              2 unchanged 2
+                              [X] section 1/1 in current file, 1/1 total
+                                [X] -before 1
+                                [X] -before 2
+                                [X] +xafter 1
+        "###);
+        insta::assert_debug_snapshot!(result, @r###"
+        Ok(
+            RecordState {
+                file_states: [
+                    (
+                        "foo",
+                        FileState {
+                            file_mode: None,
+                            sections: [
+                                Unchanged {
+                                    contents: [
+                                        "unchanged 1\n",
+                                        "unchanged 2\n",
+                                    ],
+                                },
+                                Changed {
+                                    before: [
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "before 1\n",
+                                        },
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "before 2\n",
+                                        },
+                                    ],
+                                    after: [
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "xafter 1\n",
+                                        },
+                                    ],
+                                },
+                            ],
+                        },
+                    ),
+                ],
+            },
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_edit_section_trailing_newline() {
+        let result = run_test(
+            example_record_state(),
+            vec![
+                CursiveTestingEvent::Event(Key::Down.into()), // move to section
+                CursiveTestingEvent::Event('e'.into()),       // edit section
+                CursiveTestingEvent::Event(Key::End.into()),
+                CursiveTestingEvent::Event(Key::Del.into()), // delete trailing newline
+                CursiveTestingEvent::Event(Key::Tab.into()), // move to "Save"
+                CursiveTestingEvent::Event(Key::Enter.into()),
+                CursiveTestingEvent::Event('c'.into()),
+            ],
+        );
+        insta::assert_debug_snapshot!(result, @r###"
+        Ok(
+            RecordState {
+                file_states: [
+                    (
+                        "foo",
+                        FileState {
+                            file_mode: None,
+                            sections: [
+                                Unchanged {
+                                    contents: [
+                                        "unchanged 1\n",
+                                        "unchanged 2\n",
+                                    ],
+                                },
+                                Changed {
+                                    before: [
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "before 1\n",
+                                        },
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "before 2\n",
+                                        },
+                                    ],
+                                    after: [
+                                        SectionChangedLine {
+                                            is_selected: true,
+                                            line: "after 1\n",
+                                        },
+                                    ],
+                                },
+                            ],
+                        },
+                    ),
+                ],
+            },
+        )
+        "###);
+    }
 }